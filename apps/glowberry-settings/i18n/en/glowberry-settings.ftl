@@ -19,6 +19,10 @@ frame-rate = Frame Rate
 # Fit options
 fit-fill = Fill
 fit-fit = Fit
+fit-stretch = Stretch
+fit-tile = Tile
+fit-center = Center
+fit-color = Fill Color
 
 # Frame rate options
 fps-15 = 15 FPS
@@ -38,6 +42,7 @@ shader-license = License
 show-details = Show Details
 hide-details = Hide Details
 reset-to-defaults = Reset to Defaults
+shader-speed = Speed
 shader-resource-usage = Resource Usage
 resource-low = Low
 resource-medium = Medium
@@ -48,10 +53,14 @@ settings = Settings
 background-service = Background Service
 use-glowberry = Use GlowBerry as default
 path-order-warning = Warning: ~/.local/bin must come before /usr/bin in PATH for this to work
+daemon-not-running = The GlowBerry background service isn't running, so changes won't be shown.
+daemon-no-outputs = The GlowBerry background service is running but hasn't picked up any displays yet.
+start-service = Start service
 appearance = Appearance
 window-opacity = Window Opacity
 performance = Performance
 prefer-low-power = Prefer low power GPU
+audio-reactive = Audio-reactive shaders
 
 # Power saving section
 power-saving = Power Saving
@@ -64,6 +73,23 @@ action-reduce-5 = Reduce to 5 FPS
 pause-low-battery = Pause on low battery
 low-battery-threshold = Battery threshold
 pause-lid-closed = Pause when lid closed
+pause-idle = Pause when idle
+idle-never = Never
+idle-1-minute = After 1 minute
+idle-5-minutes = After 5 minutes
+idle-10-minutes = After 10 minutes
+idle-30-minutes = After 30 minutes
+pause-fullscreen = Pause behind fullscreen windows
+pause-covered = Pause when covered by windows
+coverage-threshold = Covered area
+on-screen-capture = While sharing the screen
+capture-keep-animating = Keep animating
+capture-pause = Pause animation
+capture-keep-animating-on-ac = Keep animating on AC power
+
+# Presentation section
+presentation = Presentation
+pause-rotation-sharing = Pause slideshow while sharing the screen
 
 # About
 repository = Repository
@@ -90,6 +116,7 @@ unlock-layer = Unlock
 tip-layer-up = Move layer up
 tip-layer-down = Move layer down
 tip-center = Center layer on the displays
+tip-identify = Identify: show each display's name on it
 tip-lock = Lock to a display
 tip-unlock = Unlock layer
 tip-delete = Delete layer
@@ -115,3 +142,23 @@ wp-span-all = Span across all screens
 wp-show-on = Show on
 wp-remove-source = Remove from grid
 apply-all = Apply on all screens
+
+# Online wallpapers
+online-wallpapers = Online Wallpapers
+online-search = Search
+online-provider = Provider
+online-access-key = Access key
+online-access-key-hint = Unsplash access key
+online-query = Search terms
+online-query-hint = mountains, night sky…
+online-search-button = Search
+online-no-results = No images match these search terms.
+online-results-hint = Click an image to use it as the wallpaper.
+online-photo-by = Photo by { $author }
+online-rotate = Rotate
+online-refresh = New image
+online-every-15-minutes = Every 15 minutes
+online-every-hour = Every hour
+online-every-6-hours = Every 6 hours
+online-every-day = Every day
+online-rotate-button = Show new images of this search
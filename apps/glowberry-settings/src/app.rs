@@ -16,10 +16,15 @@ use cosmic::widget::{
 };
 use cosmic::{ApplicationExt, Element};
 use cosmic_config::{ConfigGet, ConfigSet, CosmicConfigEntry};
+use glowberry_config::etiquette::EtiquetteConfig;
 use glowberry_config::extend::ExtendConfig;
-use glowberry_config::power_saving::{OnBatteryAction, PowerSavingConfig};
+use glowberry_config::power_saving::{OnBatteryAction, PowerSavingConfig, ScreenCaptureAction};
 use glowberry_config::state::State;
-use glowberry_config::{Color, Config, Context as ConfigContext, Entry, Gradient, Source};
+use glowberry_config::{
+    Color, Config, Context as ConfigContext, Entry, Gradient, OnlineProvider, OnlineSource,
+    ScalingMode, Source,
+};
+use glowberry_lib::online::OnlineImage;
 use image::{ImageBuffer, Rgba};
 use slotmap::{DefaultKey, SecondaryMap, SlotMap};
 use std::borrow::Cow;
@@ -39,12 +44,17 @@ pub const APP_ID: &str = "io.github.hojjatabdollahi.glowberry-settings";
 const SIMULATED_WIDTH: u16 = 300;
 const SIMULATED_HEIGHT: u16 = 169;
 
+/// Minutes between images offered for online sources.
+const ONLINE_REFRESH_MINUTES: [u32; 4] = [15, 60, 360, 1440];
+
 /// Context page for the settings drawer
 #[derive(Clone, Debug, Default, PartialEq)]
 pub enum ContextPage {
     #[default]
     Settings,
     About,
+    /// Search for wallpapers on Wallhaven and Unsplash
+    Online,
 }
 
 /// Main application state
@@ -80,11 +90,12 @@ pub struct GlowBerrySettings {
     /// Frame rate options
     frame_rate_options: Vec<String>,
 
-    /// Fit options (Zoom, Fit) — used by color/shader modes
-    #[allow(dead_code)]
+    /// Fit options (Fill, Fit, Stretch, Tile, Center) — how wallpapers are
+    /// scaled
     fit_options: Vec<String>,
-    #[allow(dead_code)]
     selected_fit: usize,
+    /// Color around fitted and centered images
+    fit_color: [f32; 3],
 
     /// Cached display preview image
     cached_display_handle: Option<ImageHandle>,
@@ -95,21 +106,47 @@ pub struct GlowBerrySettings {
     /// the grid in addition to the default folder.
     wallpaper_sources: Vec<PathBuf>,
 
+    /// Online search: provider, search terms, refresh interval, and access key
+    online_provider_options: Vec<String>,
+    online_provider: usize,
+    online_query: String,
+    online_refresh_options: Vec<String>,
+    online_refresh: usize,
+    online_api_key: String,
+    /// Results of the last online search, with thumbnails once downloaded
+    online_results: Vec<(OnlineImage, Option<ImageHandle>)>,
+    /// Whether a search or download is running
+    online_busy: bool,
+    /// Why the last search or download failed
+    online_error: Option<String>,
+
     /// Prefer low power GPU for shader rendering
     prefer_low_power: bool,
 
+    /// Capture system audio for shaders that use `iAudio`
+    audio_reactive: bool,
+
     /// Whether GlowBerry is currently set as the default background service
     glowberry_is_default: bool,
 
+    /// Whether the daemon is running and managing outputs
+    daemon_status: DaemonStatus,
+
     /// Current shader parameter values (shader_index -> param_name -> value)
     shader_param_values: HashMap<usize, HashMap<String, ParamValue>>,
 
+    /// Custom `iTime` speed factors (shader_index -> factor)
+    shader_time_scales: HashMap<usize, f32>,
+
     /// Whether shader details section is expanded
     shader_details_expanded: bool,
 
     /// Power saving configuration
     power_saving: PowerSavingConfig,
 
+    /// Presentation etiquette configuration
+    etiquette: EtiquetteConfig,
+
     /// On battery action options for dropdown
     on_battery_action_options: Vec<String>,
     /// Selected on battery action index
@@ -120,6 +157,21 @@ pub struct GlowBerrySettings {
     /// Selected low battery threshold index
     selected_low_battery_threshold: usize,
 
+    /// Idle time before pausing options for dropdown
+    pause_when_idle_options: Vec<String>,
+    /// Selected idle time index
+    selected_pause_when_idle: usize,
+
+    /// Window coverage threshold options for dropdown
+    coverage_threshold_options: Vec<String>,
+    /// Selected window coverage threshold index
+    selected_coverage_threshold: usize,
+
+    /// Screen capture action options for dropdown
+    on_screen_capture_options: Vec<String>,
+    /// Selected screen capture action index
+    selected_on_screen_capture: usize,
+
     /// Window background opacity (0.0 = transparent, 1.0 = opaque)
     window_opacity: f32,
 
@@ -224,6 +276,8 @@ pub enum Message {
     ShaderFrameRate(usize),
     /// Fit mode changed
     Fit(usize),
+    /// Color around fitted and centered images changed
+    FitColor([f32; 3]),
     /// Wallpaper event from subscription
     WallpaperEvent(WallpaperEvent),
     /// Open a file picker to add image files to the grid
@@ -238,20 +292,52 @@ pub enum Message {
     ToggleContextPage(ContextPage),
     /// Open URL (for about page links)
     OpenUrl(String),
+    /// Online provider changed (index into the provider dropdown)
+    OnlineProvider(usize),
+    /// Online search terms edited
+    OnlineQuery(String),
+    /// Online refresh interval changed (index into the interval dropdown)
+    OnlineRefresh(usize),
+    /// Unsplash access key edited
+    OnlineApiKey(String),
+    /// Search the selected provider
+    OnlineSearch,
+    /// Search finished
+    OnlineResults(Result<Vec<OnlineImage>, String>),
+    /// Thumbnail of a result downloaded (image id)
+    OnlineThumbnail(String, Option<ImageHandle>),
+    /// Use a result as the wallpaper (index into the results)
+    OnlineUse(usize),
+    /// A result was downloaded for use as the wallpaper (its URL)
+    OnlineDownloaded(String, Result<(), String>),
+    /// Show a new result of the search every refresh interval
+    OnlineRotate,
     /// Same wallpaper on all displays (used by colors/shaders)
     SameWallpaper(bool),
     /// Display output changed (for per-display mode)
     OutputChanged(segmented_button::Entity),
     /// Prefer low power GPU toggle
     PreferLowPower(bool),
+    /// Toggle audio capture for audio-reactive shaders
+    AudioReactive(bool),
     /// Config or state changed externally (from daemon or another instance)
     ConfigOrStateChanged(Option<Config>),
     /// Toggle GlowBerry as the default background service
     SetGlowBerryDefault(bool),
     /// Result of setting GlowBerry as default
     SetGlowBerryDefaultResult(Result<bool, String>),
+    /// Re-check whether the daemon is running
+    CheckDaemon,
+    /// Result of checking the daemon
+    DaemonChecked(DaemonStatus),
+    /// Start the daemon from the health banner
+    StartDaemon,
+    /// Result of starting the daemon
+    StartDaemonResult(Result<(), String>),
     /// Shader parameter changed (shader_index, param_name, value) - updates UI only
     ShaderParamChanged(usize, String, ParamValue),
+    /// Shader speed changed (shader_index, factor) - updates UI only
+    ShaderTimeScaleChanged(usize, f32),
     /// Shader parameter slider released - applies to config
     ShaderParamReleased,
     /// Toggle shader details section
@@ -268,6 +354,18 @@ pub enum Message {
     SetLowBatteryThreshold(usize),
     /// Toggle pause when lid closed
     SetPauseOnLidClosed(bool),
+    /// Change the idle time before pausing
+    SetPauseWhenIdle(usize),
+    /// Toggle pause behind fullscreen windows
+    SetPauseOnFullscreen(bool),
+    /// Toggle pause when covered by windows
+    SetPauseOnCovered(bool),
+    /// Change window coverage threshold
+    SetCoverageThreshold(usize),
+    /// Change screen capture action
+    SetOnScreenCapture(usize),
+    /// Toggle pausing slideshow rotation while the screen is shared
+    SetPauseRotationWhileSharing(bool),
 
     /// Window opacity slider changed (live preview)
     SetWindowOpacity(f32),
@@ -330,6 +428,8 @@ pub enum Message {
     LayerShowOn(DefaultKey, String),
     /// Reset canvas camera to fit all content
     ExtendFitView,
+    /// Ask the daemon to show each display's name on it
+    IdentifyOutputs,
     /// Export wallpaper config to cosmic-bg (for lock screen)
     ExportToCosmicBg,
     /// Export completed
@@ -519,16 +619,42 @@ impl cosmic::Application for GlowBerrySettings {
             shader_thumbnails,
             selected_shader_frame_rate: 1, // 30 FPS default
             frame_rate_options: vec![fl!("fps-15"), fl!("fps-30"), fl!("fps-60")],
-            fit_options: vec![fl!("fit-fill"), fl!("fit-fit")],
+            fit_options: vec![
+                fl!("fit-fill"),
+                fl!("fit-fit"),
+                fl!("fit-stretch"),
+                fl!("fit-tile"),
+                fl!("fit-center"),
+            ],
             selected_fit: 0,
+            fit_color: [0.0; 3],
             cached_display_handle: None,
             current_folder,
             wallpaper_sources: Vec::new(), // Will be set below from config
-            prefer_low_power: true,        // Will be set below
+            online_provider_options: vec![String::from("Wallhaven"), String::from("Unsplash")],
+            online_provider: 0,
+            online_query: String::new(),
+            online_refresh_options: vec![
+                fl!("online-every-15-minutes"),
+                fl!("online-every-hour"),
+                fl!("online-every-6-hours"),
+                fl!("online-every-day"),
+            ],
+            online_refresh: 1, // Hourly default
+            online_api_key: String::new(),
+            online_results: Vec::new(),
+            online_busy: false,
+            online_error: None,
+            prefer_low_power: true, // Will be set below
+            audio_reactive: false,  // Will be set below
             glowberry_is_default: is_glowberry_default(),
+            // Assumed until the first check, so no banner flashes at startup
+            daemon_status: DaemonStatus::Running,
             shader_param_values: HashMap::new(),
+            shader_time_scales: HashMap::new(),
             shader_details_expanded: false,
             power_saving: PowerSavingConfig::default(),
+            etiquette: EtiquetteConfig::default(),
             on_battery_action_options: vec![
                 fl!("action-nothing"),
                 fl!("action-pause"),
@@ -545,6 +671,26 @@ impl cosmic::Application for GlowBerrySettings {
             ],
             selected_low_battery_threshold: 1, // 20% default
             window_opacity: 1.0,               // Will be set below from config
+            pause_when_idle_options: vec![
+                fl!("idle-never"),
+                fl!("idle-1-minute"),
+                fl!("idle-5-minutes"),
+                fl!("idle-10-minutes"),
+                fl!("idle-30-minutes"),
+            ],
+            selected_pause_when_idle: 0, // Never default
+            coverage_threshold_options: vec![
+                "75%".to_string(),
+                "90%".to_string(),
+                "100%".to_string(),
+            ],
+            selected_coverage_threshold: 1, // 90% default
+            on_screen_capture_options: vec![
+                fl!("capture-keep-animating"),
+                fl!("capture-pause"),
+                fl!("capture-keep-animating-on-ac"),
+            ],
+            selected_on_screen_capture: 2, // Keep animating on AC default
             extend_config: ExtendConfig::default(),
             monitor_geometry: Vec::new(),
 
@@ -560,11 +706,13 @@ impl cosmic::Application for GlowBerrySettings {
         // Load prefer_low_power, power saving, extend config, and window opacity from config
         if let Some(ctx) = &app.config_context {
             app.prefer_low_power = ctx.prefer_low_power();
+            app.audio_reactive = ctx.audio_reactive();
             app.wallpaper_sources = ctx
                 .0
                 .get::<Vec<PathBuf>>("wallpaper-sources")
                 .unwrap_or_default();
             app.power_saving = ctx.power_saving_config();
+            app.etiquette = ctx.etiquette_config();
             app.window_opacity = ctx.window_opacity();
             app.extend_config = ctx.extend_config();
 
@@ -583,6 +731,25 @@ impl cosmic::Application for GlowBerrySettings {
                 50 => 3,
                 _ => 1, // Default to 20%
             };
+            app.selected_pause_when_idle = match app.power_saving.pause_when_idle_minutes {
+                0 => 0,
+                1 => 1,
+                5 => 2,
+                10 => 3,
+                30 => 4,
+                _ => 0, // Custom values show as Never
+            };
+            app.selected_coverage_threshold = match app.power_saving.coverage_threshold {
+                75 => 0,
+                90 => 1,
+                100 => 2,
+                _ => 1, // Default to 90%
+            };
+            app.selected_on_screen_capture = match app.power_saving.on_screen_capture {
+                ScreenCaptureAction::KeepAnimating => 0,
+                ScreenCaptureAction::Pause => 1,
+                ScreenCaptureAction::KeepAnimatingOnAc => 2,
+            };
         }
 
         // Populate outputs from config first - these are the outputs that have been configured
@@ -610,7 +777,10 @@ impl cosmic::Application for GlowBerrySettings {
             cosmic::Action::App(Message::MonitorsLoaded(result.unwrap_or_default()))
         });
 
-        (app, Task::batch([title_task, shader_task, monitor_task]))
+        (
+            app,
+            Task::batch([title_task, shader_task, monitor_task, check_daemon()]),
+        )
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
@@ -646,6 +816,12 @@ impl cosmic::Application for GlowBerrySettings {
             );
         }
 
+        // The daemon can exit without touching the state, so poll for it
+        subscriptions.push(
+            cosmic::iced::time::every(std::time::Duration::from_secs(5))
+                .map(|_| Message::CheckDaemon),
+        );
+
         Subscription::batch(subscriptions)
     }
 
@@ -827,6 +1003,11 @@ impl cosmic::Application for GlowBerrySettings {
                 self.apply_selection();
             }
 
+            Message::FitColor(color) => {
+                self.fit_color = color;
+                self.apply_selection();
+            }
+
             Message::WallpaperEvent(event) => match event {
                 WallpaperEvent::Loading => {
                     // Only reset the wallpaper-related data, preserve the active selection
@@ -966,6 +1147,110 @@ impl cosmic::Application for GlowBerrySettings {
                 let _ = open::that_detached(&url);
             }
 
+            Message::OnlineProvider(idx) => {
+                self.online_provider = idx;
+                self.online_results.clear();
+                self.online_error = None;
+            }
+
+            Message::OnlineQuery(query) => {
+                self.online_query = query;
+            }
+
+            Message::OnlineRefresh(idx) => {
+                self.online_refresh = idx;
+            }
+
+            Message::OnlineApiKey(key) => {
+                self.online_api_key = key;
+            }
+
+            Message::OnlineSearch => {
+                self.online_busy = true;
+                self.online_error = None;
+                let source = self.online_source();
+                return Task::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || {
+                            glowberry_lib::online::search(&source).map_err(|why| why.to_string())
+                        })
+                        .await
+                        .unwrap_or_else(|why| Err(why.to_string()))
+                    },
+                    |result| cosmic::Action::App(Message::OnlineResults(result)),
+                );
+            }
+
+            Message::OnlineResults(result) => {
+                self.online_busy = false;
+                match result {
+                    Ok(images) => {
+                        let thumbnails: Vec<_> = images
+                            .iter()
+                            .map(|image| (image.id.clone(), image.thumbnail.clone()))
+                            .collect();
+                        self.online_results =
+                            images.into_iter().map(|image| (image, None)).collect();
+                        if self.online_results.is_empty() {
+                            self.online_error = Some(fl!("online-no-results"));
+                        }
+                        return load_online_thumbnails(thumbnails);
+                    }
+                    Err(why) => {
+                        self.online_results.clear();
+                        self.online_error = Some(why);
+                    }
+                }
+            }
+
+            Message::OnlineThumbnail(id, handle) => {
+                if let Some((_, thumbnail)) = self
+                    .online_results
+                    .iter_mut()
+                    .find(|(image, _)| image.id == id)
+                {
+                    *thumbnail = handle;
+                }
+            }
+
+            Message::OnlineUse(idx) => {
+                let Some((image, _)) = self.online_results.get(idx).cloned() else {
+                    return Task::none();
+                };
+                self.online_busy = true;
+                self.online_error = None;
+                let source = self.online_source();
+                let url = image.url.clone();
+                // Downloaded here first, so the daemon finds it cached
+                return Task::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || {
+                            glowberry_lib::online::use_image(&source, &image)
+                                .map(|_| ())
+                                .map_err(|why| why.to_string())
+                        })
+                        .await
+                        .unwrap_or_else(|why| Err(why.to_string()))
+                    },
+                    move |result| {
+                        cosmic::Action::App(Message::OnlineDownloaded(url.clone(), result))
+                    },
+                );
+            }
+
+            Message::OnlineDownloaded(url, result) => {
+                self.online_busy = false;
+                match result {
+                    Ok(()) => self.apply_source(Source::Path(PathBuf::from(url))),
+                    Err(why) => self.online_error = Some(why),
+                }
+            }
+
+            Message::OnlineRotate => {
+                let source = self.online_source();
+                self.apply_source(Source::Online(source));
+            }
+
             Message::SameWallpaper(value) => {
                 self.config.same_on_all = value;
                 if let Some(ctx) = &self.config_context {
@@ -986,6 +1271,7 @@ impl cosmic::Application for GlowBerrySettings {
 
                     // Load the wallpaper for this specific output if it exists
                     if let Some(entry) = self.config.entry(&name.0) {
+                        self.show_scaling_mode(&entry.scaling_mode.clone());
                         self.select_entry_source(&entry.source.clone());
                     }
                 }
@@ -999,6 +1285,13 @@ impl cosmic::Application for GlowBerrySettings {
                 }
             }
 
+            Message::AudioReactive(value) => {
+                self.audio_reactive = value;
+                if let Some(ctx) = &self.config_context {
+                    let _ = ctx.set_audio_reactive(value);
+                }
+            }
+
             Message::ConfigOrStateChanged(maybe_config) => {
                 // Update config if provided and different
                 if let Some(config) = maybe_config
@@ -1015,6 +1308,7 @@ impl cosmic::Application for GlowBerrySettings {
                     // Update prefer_low_power from config
                     if let Some(ctx) = &self.config_context {
                         self.prefer_low_power = ctx.prefer_low_power();
+                        self.audio_reactive = ctx.audio_reactive();
                     }
 
                     // Re-cache display image if needed
@@ -1025,6 +1319,7 @@ impl cosmic::Application for GlowBerrySettings {
 
                 // Always refresh connected outputs (state may have changed)
                 self.populate_outputs_from_config();
+                return check_daemon();
             }
 
             Message::SetGlowBerryDefault(enable) => {
@@ -1052,6 +1347,32 @@ impl cosmic::Application for GlowBerrySettings {
                 }
             }
 
+            Message::CheckDaemon => {
+                return check_daemon();
+            }
+
+            Message::DaemonChecked(status) => {
+                self.daemon_status = status;
+            }
+
+            Message::StartDaemon => {
+                // Without the override cosmic-session keeps running cosmic-bg,
+                // so enabling GlowBerry is what starts it
+                if !self.glowberry_is_default {
+                    return self.update(Message::SetGlowBerryDefault(true));
+                }
+                return Task::perform(async { start_daemon() }, |result| {
+                    cosmic::Action::App(Message::StartDaemonResult(result))
+                });
+            }
+
+            Message::StartDaemonResult(result) => {
+                if let Err(e) = result {
+                    tracing::error!("Failed to start GlowBerry: {}", e);
+                }
+                return check_daemon();
+            }
+
             Message::ShaderParamChanged(shader_idx, param_name, value) => {
                 // Store the new value in memory only (don't write to config yet)
                 self.shader_param_values
@@ -1061,6 +1382,10 @@ impl cosmic::Application for GlowBerrySettings {
                 // UI will update to show new value, but config is not written
             }
 
+            Message::ShaderTimeScaleChanged(shader_idx, time_scale) => {
+                self.shader_time_scales.insert(shader_idx, time_scale);
+            }
+
             Message::ShaderParamReleased => {
                 // Apply the shader with current parameters when slider is released
                 if matches!(self.selection.active, Choice::Shader(_)) {
@@ -1075,6 +1400,7 @@ impl cosmic::Application for GlowBerrySettings {
             Message::ResetShaderParams(shader_idx) => {
                 // Remove all custom parameter values for this shader
                 self.shader_param_values.remove(&shader_idx);
+                self.shader_time_scales.remove(&shader_idx);
 
                 // Re-apply the shader with default parameters
                 if let Choice::Shader(idx) = self.selection.active
@@ -1130,6 +1456,69 @@ impl cosmic::Application for GlowBerrySettings {
                 }
             }
 
+            Message::SetPauseWhenIdle(idx) => {
+                self.selected_pause_when_idle = idx;
+                let minutes = match idx {
+                    1 => 1,
+                    2 => 5,
+                    3 => 10,
+                    4 => 30,
+                    _ => 0,
+                };
+                self.power_saving.pause_when_idle_minutes = minutes;
+                if let Some(ctx) = &self.config_context {
+                    let _ = ctx.set_pause_when_idle_minutes(minutes);
+                }
+            }
+
+            Message::SetPauseOnFullscreen(value) => {
+                self.power_saving.pause_on_fullscreen = value;
+                if let Some(ctx) = &self.config_context {
+                    let _ = ctx.set_pause_on_fullscreen(value);
+                }
+            }
+
+            Message::SetPauseOnCovered(value) => {
+                self.power_saving.pause_on_covered = value;
+                if let Some(ctx) = &self.config_context {
+                    let _ = ctx.set_pause_on_covered(value);
+                }
+            }
+
+            Message::SetCoverageThreshold(idx) => {
+                self.selected_coverage_threshold = idx;
+                let threshold = match idx {
+                    0 => 75,
+                    1 => 90,
+                    2 => 100,
+                    _ => 90,
+                };
+                self.power_saving.coverage_threshold = threshold;
+                if let Some(ctx) = &self.config_context {
+                    let _ = ctx.set_coverage_threshold(threshold);
+                }
+            }
+
+            Message::SetOnScreenCapture(idx) => {
+                self.selected_on_screen_capture = idx;
+                let action = match idx {
+                    0 => ScreenCaptureAction::KeepAnimating,
+                    1 => ScreenCaptureAction::Pause,
+                    _ => ScreenCaptureAction::KeepAnimatingOnAc,
+                };
+                self.power_saving.on_screen_capture = action;
+                if let Some(ctx) = &self.config_context {
+                    let _ = ctx.set_on_screen_capture(action);
+                }
+            }
+
+            Message::SetPauseRotationWhileSharing(value) => {
+                self.etiquette.pause_rotation_while_sharing = value;
+                if let Some(ctx) = &self.config_context {
+                    let _ = ctx.set_pause_rotation_while_sharing(value);
+                }
+            }
+
             Message::SetWindowOpacity(value) => {
                 // Update the opacity value for live preview
                 self.window_opacity = value.clamp(0.0, 1.0);
@@ -1748,6 +2137,14 @@ impl cosmic::Application for GlowBerrySettings {
                 self.extend_fit_view_requested = true;
             }
 
+            Message::IdentifyOutputs => {
+                if let Some(ctx) = &self.config_context
+                    && let Err(err) = ctx.request_identify()
+                {
+                    tracing::error!("Failed to request output identification: {err}");
+                }
+            }
+
             Message::ExportToCosmicBg => {
                 if self.extend_layers.is_empty() {
                     return Task::none();
@@ -1993,6 +2390,11 @@ impl cosmic::Application for GlowBerrySettings {
 
         let is_wallpaper_mode = matches!(self.categories.selected, Some(Category::Wallpapers));
 
+        // Changes go nowhere while the daemon isn't running
+        if let Some(banner) = self.view_daemon_banner() {
+            children.push(banner);
+        }
+
         // 1. Preview area (always slot 1) — the multi-monitor canvas in every
         // mode (wallpaper, color, live).
         children.push(self.view_multi_monitor_canvas());
@@ -2079,6 +2481,9 @@ impl cosmic::Application for GlowBerrySettings {
 
     fn header_end(&self) -> Vec<Element<'_, Self::Message>> {
         vec![
+            widget::button::icon(widget::icon::from_name("system-search-symbolic"))
+                .on_press(Message::ToggleContextPage(ContextPage::Online))
+                .into(),
             widget::button::icon(widget::icon::from_name("preferences-system-symbolic"))
                 .on_press(Message::ToggleContextPage(ContextPage::Settings))
                 .into(),
@@ -2104,6 +2509,11 @@ impl cosmic::Application for GlowBerrySettings {
                 Message::ToggleContextPage(ContextPage::Settings),
             )
             .title(fl!("settings")),
+            ContextPage::Online => context_drawer::context_drawer(
+                self.online_drawer_view(),
+                Message::ToggleContextPage(ContextPage::Online),
+            )
+            .title(fl!("online-wallpapers")),
         })
     }
 
@@ -2170,6 +2580,67 @@ impl GlowBerrySettings {
             toggler(self.power_saving.pause_on_lid_closed).on_toggle(Message::SetPauseOnLidClosed),
         ));
 
+        // Pause after a while without input
+        power_saving_section = power_saving_section.add(settings::item(
+            fl!("pause-idle"),
+            dropdown(
+                &self.pause_when_idle_options,
+                Some(self.selected_pause_when_idle),
+                Message::SetPauseWhenIdle,
+            ),
+        ));
+
+        // Pause behind fullscreen windows
+        power_saving_section = power_saving_section.add(settings::item(
+            fl!("pause-fullscreen"),
+            toggler(self.power_saving.pause_on_fullscreen).on_toggle(Message::SetPauseOnFullscreen),
+        ));
+
+        // Pause when covered by windows (with conditional threshold dropdown)
+        {
+            let toggle_row = settings::item(
+                fl!("pause-covered"),
+                toggler(self.power_saving.pause_on_covered).on_toggle(Message::SetPauseOnCovered),
+            );
+
+            if self.power_saving.pause_on_covered {
+                let dropdown_row = settings::item(
+                    fl!("coverage-threshold"),
+                    dropdown(
+                        &self.coverage_threshold_options,
+                        Some(self.selected_coverage_threshold),
+                        Message::SetCoverageThreshold,
+                    ),
+                );
+
+                power_saving_section = power_saving_section.add(
+                    widget::column::with_children(vec![toggle_row.into(), dropdown_row.into()])
+                        .spacing(8),
+                );
+            } else {
+                power_saving_section = power_saving_section.add(toggle_row);
+            }
+        }
+
+        // While the screen is recorded or shared
+        power_saving_section = power_saving_section.add(settings::item(
+            fl!("on-screen-capture"),
+            dropdown(
+                &self.on_screen_capture_options,
+                Some(self.selected_on_screen_capture),
+                Message::SetOnScreenCapture,
+            ),
+        ));
+
+        let presentation_section =
+            widget::settings::section()
+                .title(fl!("presentation"))
+                .add(settings::item(
+                    fl!("pause-rotation-sharing"),
+                    toggler(self.etiquette.pause_rotation_while_sharing)
+                        .on_toggle(Message::SetPauseRotationWhileSharing),
+                ));
+
         // Build background service section with optional PATH warning
         let mut bg_service_section = widget::settings::section()
             .title(fl!("background-service"))
@@ -2273,15 +2744,139 @@ impl GlowBerrySettings {
                     fl!("prefer-low-power"),
                     toggler(self.prefer_low_power).on_toggle(Message::PreferLowPower),
                 ))
+                .add(settings::item(
+                    fl!("audio-reactive"),
+                    toggler(self.audio_reactive).on_toggle(Message::AudioReactive),
+                ))
                 .into(),
             // Power saving section
             power_saving_section.into(),
+            // Presentation etiquette section
+            presentation_section.into(),
             // Bezel section
             bezel_section.into(),
         ])
         .into()
     }
 
+    /// The online source described by the search page.
+    fn online_source(&self) -> OnlineSource {
+        let api_key = self.online_api_key.trim();
+        OnlineSource {
+            provider: if self.online_provider == 1 {
+                OnlineProvider::Unsplash
+            } else {
+                OnlineProvider::Wallhaven
+            },
+            query: self.online_query.trim().to_string(),
+            refresh_minutes: ONLINE_REFRESH_MINUTES[self.online_refresh],
+            api_key: (!api_key.is_empty()).then(|| api_key.to_string()),
+        }
+    }
+
+    /// Build the online search page: provider and query, and a grid of
+    /// results that are set as the wallpaper when clicked.
+    fn online_drawer_view(&self) -> Element<'_, Message> {
+        let mut search_section =
+            widget::settings::section()
+                .title(fl!("online-search"))
+                .add(settings::item(
+                    fl!("online-provider"),
+                    dropdown(
+                        &self.online_provider_options,
+                        Some(self.online_provider),
+                        Message::OnlineProvider,
+                    ),
+                ));
+
+        if self.online_provider == 1 {
+            search_section = search_section.add(settings::item(
+                fl!("online-access-key"),
+                widget::text_input(fl!("online-access-key-hint"), &self.online_api_key)
+                    .on_input(Message::OnlineApiKey)
+                    .width(Length::Fixed(200.0)),
+            ));
+        }
+
+        let mut search_button = button::suggested(fl!("online-search-button"));
+        if !self.online_busy {
+            search_button = search_button.on_press(Message::OnlineSearch);
+        }
+        search_section = search_section
+            .add(settings::item(
+                fl!("online-query"),
+                widget::text_input(fl!("online-query-hint"), &self.online_query)
+                    .on_input(Message::OnlineQuery)
+                    .on_submit(|_| Message::OnlineSearch)
+                    .width(Length::Fixed(200.0)),
+            ))
+            .add(search_button);
+
+        let rotate_section = widget::settings::section()
+            .title(fl!("online-rotate"))
+            .add(settings::item(
+                fl!("online-refresh"),
+                dropdown(
+                    &self.online_refresh_options,
+                    Some(self.online_refresh),
+                    Message::OnlineRefresh,
+                ),
+            ))
+            .add(button::standard(fl!("online-rotate-button")).on_press(Message::OnlineRotate));
+
+        let mut children: Vec<Element<'_, Message>> =
+            vec![search_section.into(), rotate_section.into()];
+
+        if let Some(error) = &self.online_error {
+            children.push(
+                widget::text(error.clone())
+                    .class(cosmic::theme::Text::Color(cosmic::iced::Color::from_rgb(
+                        0.9, 0.6, 0.2,
+                    )))
+                    .into(),
+            );
+        }
+
+        if !self.online_results.is_empty() {
+            let results: Vec<Element<'_, Message>> = self
+                .online_results
+                .iter()
+                .enumerate()
+                .map(|(idx, (image, thumbnail))| {
+                    let content: Element<'_, Message> = match thumbnail {
+                        Some(handle) => widget::image(handle.clone())
+                            .width(Length::Fixed(140.0))
+                            .height(Length::Fixed(79.0))
+                            .content_fit(cosmic::iced::ContentFit::Cover)
+                            .into(),
+                        None => shader_placeholder(140, 79),
+                    };
+                    let mut result = button::custom_image_button(content, None::<Message>)
+                        .padding(0)
+                        .class(button::ButtonClass::Image);
+                    if !self.online_busy {
+                        result = result.on_press(Message::OnlineUse(idx));
+                    }
+                    let tip = match &image.author {
+                        Some(author) => fl!("online-photo-by", author = author.as_str()),
+                        None => image.page.clone(),
+                    };
+                    with_tip(result, tip)
+                })
+                .collect();
+
+            children.push(widget::text::caption(fl!("online-results-hint")).into());
+            children.push(
+                widget::flex_row(results)
+                    .column_spacing(8)
+                    .row_spacing(8)
+                    .into(),
+            );
+        }
+
+        widget::settings::view_column(children).into()
+    }
+
     fn init_from_config(&mut self) {
         // Determine which entry reflects the applied wallpaper, so the window
         // opens on the matching page (wallpaper / color / live).
@@ -2300,9 +2895,18 @@ impl GlowBerrySettings {
             &self.config.default_background
         };
 
+        self.show_scaling_mode(&entry.scaling_mode.clone());
         self.select_entry_source(&entry.source.clone());
     }
 
+    /// Show `mode` in the Fit dropdown and its fill color in the color row.
+    fn show_scaling_mode(&mut self, mode: &ScalingMode) {
+        self.selected_fit = fit_index(mode);
+        if let ScalingMode::Fit(color) | ScalingMode::Center(color) = mode {
+            self.fit_color = *color;
+        }
+    }
+
     fn cache_display_image(&mut self) {
         self.cached_display_handle = None;
 
@@ -2385,8 +2989,12 @@ impl GlowBerrySettings {
                         source_path,
                         params,
                         background_image: None,
+                        depth_image: None,
+                        background_from_wallpaper: false,
+                        background_scaling: glowberry_config::ScalingMode::default(),
                         language: glowberry_config::ShaderLanguage::Wgsl,
                         frame_rate,
+                        time_scale: self.shader_time_scales.get(idx).copied().unwrap_or(1.0),
                     })
                 } else {
                     return None;
@@ -2397,10 +3005,15 @@ impl GlowBerrySettings {
     }
 
     fn apply_selection(&mut self) {
-        let Some(ctx) = &self.config_context else {
+        let Some(source) = self.build_active_source() else {
             return;
         };
-        let Some(source) = self.build_active_source() else {
+        self.apply_source(source);
+    }
+
+    /// Set `source` as the wallpaper of the active display, or of all of them.
+    fn apply_source(&mut self, source: Source) {
+        let Some(ctx) = &self.config_context else {
             return;
         };
 
@@ -2413,7 +3026,18 @@ impl GlowBerrySettings {
             "all".to_string()
         };
 
-        let entry = Entry::new(output, source);
+        // Keep spanning while the dropdown shows Fill, which stands for it
+        let current = &self
+            .config
+            .entry(&output)
+            .unwrap_or(&self.config.default_background)
+            .scaling_mode;
+        let scaling_mode = if *current == ScalingMode::Span && self.selected_fit == 0 {
+            ScalingMode::Span
+        } else {
+            fit_mode(self.selected_fit, self.fit_color)
+        };
+        let entry = Entry::new(output, source).scaling_mode(scaling_mode);
         if let Err(e) = self.config.set_entry(ctx, entry) {
             tracing::error!("Failed to set wallpaper: {}", e);
         }
@@ -2507,6 +3131,11 @@ impl GlowBerrySettings {
                     }
                 }
 
+                if let Some(idx) = matched_idx {
+                    self.shader_time_scales
+                        .insert(idx, shader_source.time_scale);
+                }
+
                 self.selected_shader_frame_rate = match shader_source.frame_rate {
                     0..=22 => 0,
                     23..=45 => 1,
@@ -2514,6 +3143,29 @@ impl GlowBerrySettings {
                 };
                 self.categories.selected = Some(Category::Shaders);
             }
+            // Videos, playlists, merged folders, composites, procedural
+            // backgrounds, parallax images, and day and night pairs are set
+            // in the config file and have no category yet
+            Source::Video(_)
+            | Source::Playlist(_)
+            | Source::Folders(_)
+            | Source::Composite(_)
+            | Source::Procedural(_)
+            | Source::Parallax(_)
+            | Source::DayNight(_) => {}
+            // Online sources are edited on the search page
+            Source::Online(online) => {
+                self.online_provider = match online.provider {
+                    OnlineProvider::Wallhaven => 0,
+                    OnlineProvider::Unsplash => 1,
+                };
+                self.online_query = online.query.clone();
+                self.online_refresh = ONLINE_REFRESH_MINUTES
+                    .iter()
+                    .position(|&minutes| minutes >= online.refresh_minutes)
+                    .unwrap_or(ONLINE_REFRESH_MINUTES.len() - 1);
+                self.online_api_key = online.api_key.clone().unwrap_or_default();
+            }
         }
         self.cache_display_image();
     }
@@ -2940,18 +3592,20 @@ impl GlowBerrySettings {
                 .map(|(_, img)| ImageHandle::from_rgba(img.width(), img.height(), img.to_vec()));
             // Try to load the image handle from the path directly
             let image_handle = image_handle.or_else(|| {
-                image::open(&config_layer.source_path).ok().map(|img| {
-                    let rgba = img.to_rgba8();
-                    let (w, h) = (rgba.width(), rgba.height());
-                    // Resize to a reasonable thumbnail size
-                    let thumb = image::imageops::resize(
-                        &rgba,
-                        w.min(400),
-                        h.min(300),
-                        image::imageops::FilterType::Triangle,
-                    );
-                    ImageHandle::from_rgba(thumb.width(), thumb.height(), thumb.into_vec())
-                })
+                glowberry_lib::image_formats::open_upright(&config_layer.source_path)
+                    .ok()
+                    .map(|img| {
+                        let rgba = img.to_rgba8();
+                        let (w, h) = (rgba.width(), rgba.height());
+                        // Resize to a reasonable thumbnail size
+                        let thumb = image::imageops::resize(
+                            &rgba,
+                            w.min(400),
+                            h.min(300),
+                            image::imageops::FilterType::Triangle,
+                        );
+                        ImageHandle::from_rgba(thumb.width(), thumb.height(), thumb.into_vec())
+                    })
             });
             let image_size =
                 image::image_dimensions(&config_layer.source_path).unwrap_or((800, 600));
@@ -3015,6 +3669,53 @@ impl GlowBerrySettings {
         )
     }
 
+    /// Banner shown while the daemon isn't running or managing any output.
+    fn view_daemon_banner(&self) -> Option<Element<'_, Message>> {
+        let message = match self.daemon_status {
+            DaemonStatus::Running => return None,
+            DaemonStatus::NoOutputs => fl!("daemon-no-outputs"),
+            DaemonStatus::Stopped => fl!("daemon-not-running"),
+        };
+
+        let mut row = widget::row::with_children(vec![
+            widget::icon::from_name("dialog-warning-symbolic")
+                .size(20)
+                .icon()
+                .into(),
+            widget::text(message).width(Length::Fill).into(),
+        ])
+        .spacing(12)
+        .align_y(Alignment::Center);
+        if self.daemon_status == DaemonStatus::Stopped {
+            row = row.push(
+                widget::button::suggested(fl!("start-service")).on_press(Message::StartDaemon),
+            );
+        }
+
+        Some(
+            container(row)
+                .padding(12)
+                .width(Length::Fill)
+                .class(cosmic::theme::Container::custom(|theme| {
+                    let cosmic = theme.cosmic();
+                    cosmic::widget::container::Style {
+                        icon_color: Some(cosmic.warning.on.into()),
+                        text_color: Some(cosmic.warning.on.into()),
+                        background: Some(cosmic::iced::Background::Color(
+                            cosmic.warning.base.into(),
+                        )),
+                        border: cosmic::iced::Border {
+                            radius: cosmic.corner_radii.radius_s.into(),
+                            ..Default::default()
+                        },
+                        shadow: cosmic::iced::Shadow::default(),
+                        snap: false,
+                    }
+                }))
+                .into(),
+        )
+    }
+
     #[allow(dead_code)]
     fn view_display_preview(&self) -> Element<'_, Message> {
         let content: Element<'_, Message> = match &self.selection.active {
@@ -3084,6 +3785,19 @@ impl GlowBerrySettings {
     fn view_settings_list(&self) -> Element<'_, Message> {
         let mut list = widget::list_column();
 
+        // How images are scaled (only for wallpapers)
+        if let Choice::Wallpaper(_) = self.selection.active {
+            list = list.add(settings::item(
+                fl!("fit"),
+                dropdown(&self.fit_options, Some(self.selected_fit), Message::Fit),
+            ));
+
+            // Fitted and centered images are surrounded by a color
+            if matches!(self.selected_fit, 1 | 4) {
+                list = list.add(settings::item(fl!("fit-color"), self.view_fit_colors()));
+            }
+        }
+
         // Frame rate dropdown and shader parameters (only for shaders)
         if let Choice::Shader(shader_idx) = self.selection.active {
             // Frame rate is always visible
@@ -3175,6 +3889,32 @@ impl GlowBerrySettings {
                     widget::text(usage_label),
                 ));
 
+                // Animation speed, applied to `iTime`
+                let time_scale = self
+                    .shader_time_scales
+                    .get(&shader_idx)
+                    .copied()
+                    .unwrap_or(1.0);
+                list = list.add(settings::item(
+                    fl!("shader-speed"),
+                    widget::row::with_children(vec![
+                        slider(
+                            glowberry_config::MIN_TIME_SCALE..=glowberry_config::MAX_TIME_SCALE,
+                            time_scale,
+                            move |v| Message::ShaderTimeScaleChanged(shader_idx, v),
+                        )
+                        .on_release(Message::ShaderParamReleased)
+                        .step(0.1)
+                        .width(Length::Fixed(150.0))
+                        .into(),
+                        widget::text(format!("{:.1}×", time_scale))
+                            .width(Length::Fixed(50.0))
+                            .into(),
+                    ])
+                    .spacing(8)
+                    .align_y(Alignment::Center),
+                ));
+
                 // Shader parameters
                 for param in &parsed.params {
                     let current_values = self.shader_param_values.get(&shader_idx);
@@ -3391,6 +4131,16 @@ impl GlowBerrySettings {
             fl!("tip-fit"),
         ));
 
+        // Flashes connector names on the physical displays; pointless while
+        // the daemon isn't running
+        if self.daemon_status != DaemonStatus::Stopped {
+            overlay_buttons.push(with_tip(
+                widget::button::icon(widget::icon::from_name("video-display-symbolic"))
+                    .on_press(Message::IdentifyOutputs),
+                fl!("tip-identify"),
+            ));
+        }
+
         let tool_col = widget::column::with_children(overlay_buttons).spacing(4);
 
         let tool_overlay = container(tool_col)
@@ -3683,6 +4433,33 @@ impl GlowBerrySettings {
             .into()
     }
 
+    /// Swatches picking the color around fitted and centered images.
+    fn view_fit_colors(&self) -> Element<'_, Message> {
+        let swatches: Vec<Element<'_, Message>> = [[0.0; 3], [1.0; 3]]
+            .into_iter()
+            .chain(DEFAULT_COLORS.iter().filter_map(|color| match color {
+                Color::Single(rgb) => Some(*rgb),
+                Color::Gradient(_) => None,
+            }))
+            .map(|rgb| {
+                button::custom_image_button(
+                    color_image(Color::Single(rgb), 24, 24),
+                    None::<Message>,
+                )
+                .padding(0)
+                .selected(self.fit_color == rgb)
+                .class(button::ButtonClass::Image)
+                .on_press(Message::FitColor(rgb))
+                .into()
+            })
+            .collect();
+
+        widget::flex_row(swatches)
+            .column_spacing(8)
+            .row_spacing(8)
+            .into()
+    }
+
     fn view_shader_grid(&self) -> Element<'_, Message> {
         let selected = if let Choice::Shader(idx) = self.selection.active {
             Some(idx)
@@ -3801,6 +4578,27 @@ fn shader_placeholder<'a, M: 'a>(width: u16, height: u16) -> Element<'a, M> {
         .into()
 }
 
+/// Download the thumbnails of online search results, given as (id, URL).
+fn load_online_thumbnails(thumbnails: Vec<(String, String)>) -> Task<Message> {
+    Task::batch(thumbnails.into_iter().map(|(id, url)| {
+        Task::perform(
+            async move {
+                let handle = tokio::task::spawn_blocking(move || {
+                    glowberry_lib::remote::download(&url)
+                        .inspect_err(|why| tracing::debug!(?why, url, "Failed to load thumbnail"))
+                        .ok()
+                        .map(ImageHandle::from_path)
+                })
+                .await
+                .ok()
+                .flatten();
+                (id, handle)
+            },
+            |(id, handle)| cosmic::Action::App(Message::OnlineThumbnail(id, handle)),
+        )
+    }))
+}
+
 fn create_shader_placeholder(width: u32, height: u32) -> ImageHandle {
     let mut data = Vec::with_capacity((width * height * 4) as usize);
     for y in 0..height {
@@ -3942,6 +4740,29 @@ fn check_path_order() -> Result<bool, &'static str> {
     }
 }
 
+/// Scaling mode of the Fit dropdown's option `index`, filling around
+/// letterboxed and centered images with `color`.
+fn fit_mode(index: usize, color: [f32; 3]) -> ScalingMode {
+    match index {
+        1 => ScalingMode::Fit(color),
+        2 => ScalingMode::Stretch,
+        3 => ScalingMode::Tile,
+        4 => ScalingMode::Center(color),
+        _ => ScalingMode::Zoom,
+    }
+}
+
+/// Option of the Fit dropdown showing `mode`.
+fn fit_index(mode: &ScalingMode) -> usize {
+    match mode {
+        ScalingMode::Zoom | ScalingMode::Span => 0,
+        ScalingMode::Fit(_) => 1,
+        ScalingMode::Stretch => 2,
+        ScalingMode::Tile => 3,
+        ScalingMode::Center(_) => 4,
+    }
+}
+
 /// Check if GlowBerry is currently enabled as the default background service.
 ///
 /// This works by checking if ~/.local/bin/cosmic-bg exists and is a symlink
@@ -3957,6 +4778,106 @@ fn is_glowberry_default() -> bool {
     }
 }
 
+/// Whether the GlowBerry daemon is running and drawing on outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DaemonStatus {
+    Running,
+    /// Running, but no outputs recorded in the state yet
+    NoOutputs,
+    Stopped,
+}
+
+/// Check whether the daemon is running for this user and managing outputs.
+///
+/// The daemon has no D-Bus interface, so this looks for its process and for
+/// the connected outputs it records in the shared state.
+fn daemon_status() -> DaemonStatus {
+    if !is_daemon_running() {
+        return DaemonStatus::Stopped;
+    }
+
+    let connected_outputs = State::state()
+        .ok()
+        .and_then(|state_helper| State::get_entry(&state_helper).ok())
+        .map(|state| state.connected_outputs)
+        .unwrap_or_default();
+    if connected_outputs.is_empty() {
+        DaemonStatus::NoOutputs
+    } else {
+        DaemonStatus::Running
+    }
+}
+
+/// Check the daemon's status off the UI thread, since it scans `/proc`.
+fn check_daemon() -> Task<Message> {
+    Task::perform(
+        async {
+            tokio::task::spawn_blocking(daemon_status)
+                .await
+                .unwrap_or(DaemonStatus::Stopped)
+        },
+        |status| cosmic::Action::App(Message::DaemonChecked(status)),
+    )
+}
+
+/// Look for a process of this user running the `glowberry` executable, which
+/// is also what runs when cosmic-session starts it through the `cosmic-bg`
+/// symlink. Its subcommands, such as `stress` and `soak`, aren't the daemon.
+fn is_daemon_running() -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(uid) = std::fs::metadata("/proc/self").map(|m| m.uid()) else {
+        return false;
+    };
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.metadata().is_ok_and(|m| m.uid() == uid))
+        .filter(|entry| {
+            std::fs::read_link(entry.path().join("exe")).is_ok_and(|exe| {
+                exe.file_name().is_some_and(|name| {
+                    // The binary may have been replaced by a reinstall
+                    name.to_string_lossy().trim_end_matches(" (deleted)") == "glowberry"
+                })
+            })
+        })
+        .any(|entry| {
+            std::fs::read(entry.path().join("cmdline"))
+                .is_ok_and(|cmdline| is_daemon_argv(&cmdline))
+        })
+}
+
+/// Whether `cmdline`, the NUL-separated arguments of a `glowberry` process,
+/// runs the daemon: the program alone, without a subcommand.
+fn is_daemon_argv(cmdline: &[u8]) -> bool {
+    cmdline
+        .split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .count()
+        == 1
+}
+
+/// Start the daemon, preferring the user-local install.
+fn start_daemon() -> Result<(), String> {
+    use std::process::{Command, Stdio};
+
+    let program = dirs::home_dir()
+        .map(|h| h.join(".local/bin/glowberry"))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from("glowberry"));
+
+    Command::new(&program)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to start {}: {}", program.display(), e))
+}
+
 /// Check if the PATH is configured correctly for GlowBerry override to work.
 fn is_path_order_correct() -> bool {
     check_path_order().unwrap_or(false)
@@ -4,6 +4,11 @@
 
 use cosmic::iced::Subscription;
 use cosmic::iced::futures::{Stream, StreamExt as _};
+use glowberry_lib::{
+    image_formats,
+    scaler::{self, Strategy},
+    wallpaper,
+};
 use image::{ImageBuffer, Rgba, RgbaImage};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
@@ -112,7 +117,7 @@ async fn load_wallpapers_from_sources(
     }
 
     let stream = futures_lite::stream::iter(candidate_paths).filter_map(|path| async move {
-        if is_image_file(&path) {
+        if image_formats::is_supported(&path) {
             load_image_with_thumbnail(path).await
         } else {
             None
@@ -122,18 +127,6 @@ async fn load_wallpapers_from_sources(
     Box::pin(stream)
 }
 
-fn is_image_file(path: &Path) -> bool {
-    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
-        return false;
-    };
-
-    let ext_lower = ext.to_lowercase();
-    matches!(
-        ext_lower.as_str(),
-        "jpg" | "jpeg" | "png" | "webp" | "gif" | "bmp" | "jxl" | "avif"
-    )
-}
-
 async fn load_image_with_thumbnail(path: PathBuf) -> Option<(PathBuf, RgbaImage, RgbaImage)> {
     tokio::task::spawn_blocking(move || load_image_with_thumbnail_sync(&path))
         .await
@@ -149,11 +142,7 @@ type ImageTuple = (
 
 fn load_image_with_thumbnail_sync(path: &Path) -> Option<ImageTuple> {
     // Try to load the image
-    let image = if path.extension().is_some_and(|e| e == "jxl") {
-        decode_jpegxl(path).ok()?
-    } else {
-        image::open(path).ok()?
-    };
+    let image = wallpaper::decode(path).ok()?;
 
     // Canvas preview: the FULL image, aspect-preserving (fit within 600x400).
     // The multi-monitor canvas stretches this to the image's real size, so it
@@ -169,37 +158,7 @@ fn load_image_with_thumbnail_sync(path: &Path) -> Option<ImageTuple> {
 }
 
 fn resize_thumbnail(img: &image::DynamicImage, new_width: u32, new_height: u32) -> RgbaImage {
-    use fast_image_resize::{ResizeAlg, ResizeOptions, Resizer, SrcCropping};
-
-    let mut resizer = Resizer::new();
-    let options = ResizeOptions {
-        algorithm: ResizeAlg::Convolution(fast_image_resize::FilterType::Lanczos3),
-        cropping: SrcCropping::FitIntoDestination((new_width as f64, new_height as f64)),
-        ..Default::default()
-    };
-
-    let mut new_image = image::DynamicImage::new(new_width, new_height, img.color());
-
-    if resizer.resize(img, &mut new_image, &options).is_err() {
-        // Fallback to standard resize
-        return image::imageops::resize(
-            img,
-            new_width,
-            new_height,
-            image::imageops::FilterType::Lanczos3,
-        );
-    }
-
-    new_image.to_rgba8()
-}
-
-fn decode_jpegxl(path: &Path) -> eyre::Result<image::DynamicImage> {
-    use jxl_oxide::integration::JxlDecoder;
-    use std::fs::File;
-
-    let file = File::open(path)?;
-    let decoder = JxlDecoder::new(file)?;
-    Ok(image::DynamicImage::from_decoder(decoder)?)
+    scaler::scale(img, (new_width, new_height), Strategy::Zoom).to_rgba8()
 }
 
 // Rounded corner implementation from cosmic-settings-wallpaper
@@ -59,6 +59,9 @@ impl ParamValue {
 pub struct ParsedShader {
     pub metadata: ShaderMetadata,
     pub params: Vec<ShaderParam>,
+    /// Header lines the daemon reads: the `output:` color space and the
+    /// `[PARAMS]` block with its automation curves
+    pub header: String,
     /// The shader source after the header (without comments)
     pub source_body: String,
 }
@@ -76,6 +79,7 @@ impl ParsedShader {
         let mut params = Vec::new();
         let mut in_params_section = false;
         let mut source_lines = Vec::new();
+        let mut header_lines = Vec::new();
         let mut header_ended = false;
 
         for line in content.lines() {
@@ -87,11 +91,13 @@ impl ParsedShader {
             }
             if trimmed == "// [PARAMS]" {
                 in_params_section = true;
+                header_lines.push(line.to_string());
                 continue;
             }
             if trimmed == "// [/PARAMS]" {
                 in_params_section = false;
                 header_ended = true;
+                header_lines.push(line.to_string());
                 continue;
             }
 
@@ -104,6 +110,7 @@ impl ParsedShader {
                         "author" => metadata.author = value.to_string(),
                         "source" => metadata.source = value.to_string(),
                         "license" => metadata.license = value.to_string(),
+                        "output" => header_lines.push(line.to_string()),
                         _ => {}
                     }
                 }
@@ -115,6 +122,7 @@ impl ParsedShader {
                 if let Some(param) = parse_param_line(rest) {
                     params.push(param);
                 }
+                header_lines.push(line.to_string());
             }
 
             // Collect source body (after header ends)
@@ -141,6 +149,7 @@ impl ParsedShader {
         Some(Self {
             metadata,
             params,
+            header: header_lines.join("\n"),
             source_body: source_lines.join("\n"),
         })
     }
@@ -149,6 +158,12 @@ impl ParsedShader {
     pub fn generate_source(&self, values: &HashMap<String, ParamValue>) -> String {
         let mut result = String::new();
 
+        // Keep the header so the color space and automation curves survive customization
+        if !self.header.is_empty() {
+            result.push_str(&self.header);
+            result.push('\n');
+        }
+
         // Add const declarations for parameters with custom values
         for param in &self.params {
             let value = values.get(&param.name).unwrap_or(&param.default);
@@ -273,4 +288,27 @@ mod tests {
         assert_eq!(param.param_type, ParamType::F32);
         assert_eq!(param.label, "Speed");
     }
+
+    #[test]
+    fn generated_source_keeps_params_header() {
+        let content = "// name: Drift\n// [PARAMS]\n// speed: f32 = 0.5 | min: 0.1 | max: 2.0 | lfo: 2h\n// [/PARAMS]\nconst speed: f32 = 0.5;\nfn main() {}\n";
+        let parsed = ParsedShader::parse_content(content).unwrap();
+        let values = HashMap::from([("speed".to_string(), ParamValue::F32(1.5))]);
+        let source = parsed.generate_source(&values);
+
+        assert!(source.starts_with(
+            "// [PARAMS]\n// speed: f32 = 0.5 | min: 0.1 | max: 2.0 | lfo: 2h\n// [/PARAMS]\n"
+        ));
+        assert!(source.contains("const speed: f32 = 1.500000;"));
+        assert!(!source.contains("const speed: f32 = 0.5;"));
+    }
+
+    #[test]
+    fn generated_source_keeps_output_color_space() {
+        let content = "// [SHADER]\n// name: Glow\n// output: linear\n// [PARAMS]\n// speed: f32 = 0.5 | min: 0.1 | max: 2.0\n// [/PARAMS]\nconst speed: f32 = 0.5;\nfn main() {}\n";
+        let parsed = ParsedShader::parse_content(content).unwrap();
+        let source = parsed.generate_source(&HashMap::new());
+
+        assert!(source.starts_with("// output: linear\n// [PARAMS]\n"));
+    }
 }
@@ -9,7 +9,10 @@ use std::borrow::Cow;
 use std::path::Path;
 use std::time::Instant;
 
-use glowberry_lib::shader_defs::{VERTEX_SHADER, WGSL_PREAMBLE, aligned_bytes_per_row};
+use glowberry_lib::shader_defs::{
+    VERTEX_SHADER, WGSL_PREAMBLE, aligned_bytes_per_row, outputs_hdr, outputs_linear,
+};
+use glowberry_lib::shader_include::resolve_includes;
 use pollster::FutureExt;
 
 /// Error type for shader preview rendering.
@@ -65,8 +68,10 @@ impl ShaderPreviewRenderer {
     /// * `width` - Preview width in pixels
     /// * `height` - Preview height in pixels
     pub fn new(shader_path: &Path, width: u32, height: u32) -> Result<Self, PreviewError> {
-        // Read shader code
+        // Read shader code and expand shared snippets
         let shader_code = std::fs::read_to_string(shader_path)?;
+        let shader_code = resolve_includes(&shader_code, shader_path.parent())
+            .map_err(|e| PreviewError::ShaderCompilation(e.to_string()))?;
 
         // Check if shader requires texture resources (which we don't provide in preview)
         if shader_code.contains("iTexture") || shader_code.contains("iTextureSampler") {
@@ -176,7 +181,14 @@ impl ShaderPreviewRenderer {
         });
 
         // Create render pipeline
-        let format = wgpu::TextureFormat::Rgba8Unorm;
+        // Read back sRGB-encoded pixels, as the daemon presents them: linear
+        // output is encoded by an sRGB target, sRGB output is stored as-is.
+        // HDR output is previewed like linear output, clipped to SDR
+        let format = if outputs_linear(&shader_code) || outputs_hdr(&shader_code) {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("shader-preview: render pipeline"),
             layout: Some(&pipeline_layout),
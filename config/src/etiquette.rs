@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Presentation etiquette: keeping the desktop calm while others can see it.
+//!
+//! Unlike [`crate::power_saving`], these settings don't touch shader
+//! animation; they only hold back changes that would distract an audience.
+
+use cosmic_config::{ConfigGet, ConfigSet};
+
+use crate::Context;
+
+// Config keys
+pub const PAUSE_ROTATION_WHILE_SHARING: &str = "pause-rotation-while-sharing";
+
+/// Presentation etiquette configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EtiquetteConfig {
+    /// Freeze slideshow rotation while the screen is being shared
+    pub pause_rotation_while_sharing: bool,
+}
+
+impl EtiquetteConfig {
+    /// Load etiquette config from cosmic-config.
+    pub fn load(context: &Context) -> Self {
+        Self {
+            pause_rotation_while_sharing: context.pause_rotation_while_sharing(),
+        }
+    }
+
+    /// Save etiquette config to cosmic-config.
+    pub fn save(&self, context: &Context) -> Result<(), cosmic_config::Error> {
+        context.set_pause_rotation_while_sharing(self.pause_rotation_while_sharing)
+    }
+}
+
+impl Context {
+    /// Get the pause rotation while sharing setting.
+    #[must_use]
+    pub fn pause_rotation_while_sharing(&self) -> bool {
+        self.0
+            .get::<bool>(PAUSE_ROTATION_WHILE_SHARING)
+            .unwrap_or(false)
+    }
+
+    /// Set the pause rotation while sharing setting.
+    pub fn set_pause_rotation_while_sharing(
+        &self,
+        value: bool,
+    ) -> Result<(), cosmic_config::Error> {
+        self.0.set(PAUSE_ROTATION_WHILE_SHARING, value)
+    }
+
+    /// Load the full etiquette config.
+    #[must_use]
+    pub fn etiquette_config(&self) -> EtiquetteConfig {
+        EtiquetteConfig::load(self)
+    }
+}
@@ -1,13 +1,22 @@
 // SPDX-License-Identifier: MPL-2.0
 
+pub mod etiquette;
 pub mod extend;
+pub mod night_light;
+pub mod now_playing;
+pub mod output_group;
 pub mod power_saving;
 pub mod state;
+pub mod weather;
 
 use cosmic_config::{Config as CosmicConfig, ConfigGet, ConfigSet};
 use derive_setters::Setters;
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, collections::HashSet, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
 /// Package version from Cargo.toml.
@@ -32,7 +41,49 @@ pub const BACKGROUNDS: &str = "backgrounds";
 pub const DEFAULT_BACKGROUND: &str = "all";
 pub const SAME_ON_ALL: &str = "same-on-all";
 pub const PREFER_LOW_POWER: &str = "prefer-low-power";
+pub const GPU_ADAPTER: &str = "gpu-adapter";
+pub const GPU_BACKEND: &str = "gpu-backend";
+pub const IDENTIFY_OUTPUTS: &str = "identify-outputs";
+pub const QUEUE_EDIT: &str = "queue-edit";
 pub const WINDOW_OPACITY: &str = "window-opacity";
+pub const AUDIO_REACTIVE: &str = "audio-reactive";
+pub const FALLBACK_RESOLUTION: &str = "fallback-resolution";
+pub const TIMELAPSE_ENABLED: &str = "timelapse-enabled";
+pub const TIMELAPSE_INTERVAL: &str = "timelapse-interval-minutes";
+pub const SHADER_MAX_TEXTURE_SIZE: &str = "shader-max-texture-size";
+pub const SHADER_VRAM_BUDGET: &str = "shader-vram-budget-mib";
+pub const SHADER_MIN_FRAME_RATE: &str = "shader-min-frame-rate";
+pub const ANIMATION_CACHE_SIZE: &str = "animation-cache-mib";
+pub const REMOTE_REFRESH: &str = "remote-refresh-minutes";
+pub const SCALED_CACHE_SIZE: &str = "scaled-cache-mib";
+pub const MEMORY_BUDGET: &str = "memory-budget-mib";
+
+/// Resolution assumed for outputs that report neither a size nor any modes.
+pub const DEFAULT_FALLBACK_RESOLUTION: (u32, u32) = (1920, 1080);
+
+/// Minutes between time-lapse snapshots when none is configured.
+pub const DEFAULT_TIMELAPSE_INTERVAL: u32 = 15;
+
+/// Longest edge, in pixels, of textures uploaded for a shader when none is configured.
+pub const DEFAULT_SHADER_MAX_TEXTURE_SIZE: u32 = 4096;
+
+/// GPU memory, in MiB, a single shader canvas may use when none is configured.
+pub const DEFAULT_SHADER_VRAM_BUDGET: u32 = 128;
+
+/// Lowest frame rate a slow shader is stepped down to when none is configured.
+pub const DEFAULT_SHADER_MIN_FRAME_RATE: u8 = 10;
+
+/// Memory, in MiB, the decoded loop of one animated image may use when none is configured.
+pub const DEFAULT_ANIMATION_CACHE_SIZE: u32 = 256;
+
+/// Minutes between checks of a URL wallpaper for a new image when none is configured.
+pub const DEFAULT_REMOTE_REFRESH: u32 = 60;
+
+/// Disk space, in MiB, scaled wallpapers may be cached in when none is configured.
+pub const DEFAULT_SCALED_CACHE_SIZE: u32 = 512;
+
+/// Memory, in MiB, wallpapers' images, frames, and SHM pools may hold when none is configured.
+pub const DEFAULT_MEMORY_BUDGET: u32 = 512;
 
 /// Errors that can occur during config operations
 #[derive(Debug, Error)]
@@ -144,6 +195,256 @@ impl Context {
         Ok(())
     }
 
+    /// Get the GPU adapter shaders are pinned to: an adapter index, or part
+    /// of an adapter or vendor name. `None` selects one automatically.
+    #[must_use]
+    pub fn gpu_adapter(&self) -> Option<String> {
+        self.0
+            .get::<Option<String>>(GPU_ADAPTER)
+            .ok()
+            .flatten()
+            .filter(|adapter| !adapter.trim().is_empty())
+    }
+
+    /// Pin shaders to a GPU adapter, or pass `None` to select automatically.
+    pub fn set_gpu_adapter(&self, value: Option<&str>) -> Result<(), cosmic_config::Error> {
+        if self.gpu_adapter().as_deref() != value {
+            return self.0.set(GPU_ADAPTER, value);
+        }
+        Ok(())
+    }
+
+    /// Get the graphics API shaders render with.
+    #[must_use]
+    pub fn gpu_backend(&self) -> GpuBackend {
+        self.0.get::<GpuBackend>(GPU_BACKEND).unwrap_or_default()
+    }
+
+    /// Set the graphics API shaders render with.
+    pub fn set_gpu_backend(&self, value: GpuBackend) -> Result<(), cosmic_config::Error> {
+        if self.gpu_backend() != value {
+            return self.0.set(GPU_BACKEND, value);
+        }
+        Ok(())
+    }
+
+    /// Ask the daemon to briefly show each output's name on it.
+    ///
+    /// Stores the request time, so every request changes the key.
+    pub fn request_identify(&self) -> Result<(), cosmic_config::Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.0.set(IDENTIFY_OUTPUTS, now)
+    }
+
+    /// Ask the daemon to change a slideshow's queue.
+    ///
+    /// Stores the request time along with the edit, so repeating an edit
+    /// still changes the key.
+    pub fn request_queue_edit(&self, edit: QueueEdit) -> Result<(), cosmic_config::Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.0.set(QUEUE_EDIT, (now, edit))
+    }
+
+    /// The last requested slideshow queue edit.
+    #[must_use]
+    pub fn queue_edit(&self) -> Option<QueueEdit> {
+        self.0
+            .get::<(u64, QueueEdit)>(QUEUE_EDIT)
+            .ok()
+            .map(|(_, edit)| edit)
+    }
+
+    /// Get the audio-reactive setting.
+    /// When enabled, the daemon captures system audio and exposes it to shaders as `iAudio`.
+    #[must_use]
+    pub fn audio_reactive(&self) -> bool {
+        self.0.get::<bool>(AUDIO_REACTIVE).unwrap_or(false)
+    }
+
+    /// Set the audio-reactive setting.
+    pub fn set_audio_reactive(&self, value: bool) -> Result<(), cosmic_config::Error> {
+        if self.audio_reactive() != value {
+            return self.0.set(AUDIO_REACTIVE, value);
+        }
+        Ok(())
+    }
+
+    /// Get the resolution used for outputs that advertise no usable size or mode.
+    /// Virtual or misconfigured outputs may need this raised to render sharply.
+    #[must_use]
+    pub fn fallback_resolution(&self) -> (u32, u32) {
+        self.0
+            .get::<(u32, u32)>(FALLBACK_RESOLUTION)
+            .ok()
+            .filter(|(w, h)| *w > 0 && *h > 0)
+            .unwrap_or(DEFAULT_FALLBACK_RESOLUTION)
+    }
+
+    /// Set the fallback resolution.
+    pub fn set_fallback_resolution(&self, value: (u32, u32)) -> Result<(), cosmic_config::Error> {
+        if self.fallback_resolution() != value {
+            return self.0.set(FALLBACK_RESOLUTION, value);
+        }
+        Ok(())
+    }
+
+    /// Get the time-lapse setting.
+    /// When enabled, the daemon periodically saves a small snapshot of each output's wallpaper.
+    #[must_use]
+    pub fn timelapse_enabled(&self) -> bool {
+        self.0.get::<bool>(TIMELAPSE_ENABLED).unwrap_or(false)
+    }
+
+    /// Set the time-lapse setting.
+    pub fn set_timelapse_enabled(&self, value: bool) -> Result<(), cosmic_config::Error> {
+        if self.timelapse_enabled() != value {
+            return self.0.set(TIMELAPSE_ENABLED, value);
+        }
+        Ok(())
+    }
+
+    /// Get the minutes between time-lapse snapshots (at least 1).
+    #[must_use]
+    pub fn timelapse_interval(&self) -> u32 {
+        self.0
+            .get::<u32>(TIMELAPSE_INTERVAL)
+            .unwrap_or(DEFAULT_TIMELAPSE_INTERVAL)
+            .max(1)
+    }
+
+    /// Set the minutes between time-lapse snapshots.
+    pub fn set_timelapse_interval(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        if self.timelapse_interval() != value {
+            return self.0.set(TIMELAPSE_INTERVAL, value);
+        }
+        Ok(())
+    }
+
+    /// Get the longest edge, in pixels, of textures uploaded for a shader (at least 256).
+    /// Larger background images are downscaled before upload.
+    #[must_use]
+    pub fn shader_max_texture_size(&self) -> u32 {
+        self.0
+            .get::<u32>(SHADER_MAX_TEXTURE_SIZE)
+            .unwrap_or(DEFAULT_SHADER_MAX_TEXTURE_SIZE)
+            .max(256)
+    }
+
+    /// Set the maximum shader texture size.
+    pub fn set_shader_max_texture_size(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        if self.shader_max_texture_size() != value {
+            return self.0.set(SHADER_MAX_TEXTURE_SIZE, value);
+        }
+        Ok(())
+    }
+
+    /// Get the GPU memory, in MiB, a single shader canvas may use (at least 8).
+    #[must_use]
+    pub fn shader_vram_budget(&self) -> u32 {
+        self.0
+            .get::<u32>(SHADER_VRAM_BUDGET)
+            .unwrap_or(DEFAULT_SHADER_VRAM_BUDGET)
+            .max(8)
+    }
+
+    /// Set the per-shader GPU memory budget.
+    pub fn set_shader_vram_budget(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        if self.shader_vram_budget() != value {
+            return self.0.set(SHADER_VRAM_BUDGET, value);
+        }
+        Ok(())
+    }
+
+    /// Get the lowest frame rate (1-60) that slow shaders are stepped down to.
+    #[must_use]
+    pub fn shader_min_frame_rate(&self) -> u8 {
+        self.0
+            .get::<u8>(SHADER_MIN_FRAME_RATE)
+            .unwrap_or(DEFAULT_SHADER_MIN_FRAME_RATE)
+            .clamp(1, 60)
+    }
+
+    /// Set the lowest frame rate that slow shaders are stepped down to.
+    pub fn set_shader_min_frame_rate(&self, value: u8) -> Result<(), cosmic_config::Error> {
+        if self.shader_min_frame_rate() != value {
+            return self.0.set(SHADER_MIN_FRAME_RATE, value);
+        }
+        Ok(())
+    }
+
+    /// Get the memory, in MiB, the decoded loop of one animated image may
+    /// keep. Longer loops are decoded again on every pass.
+    #[must_use]
+    pub fn animation_cache_size(&self) -> u32 {
+        self.0
+            .get::<u32>(ANIMATION_CACHE_SIZE)
+            .unwrap_or(DEFAULT_ANIMATION_CACHE_SIZE)
+    }
+
+    /// Set the per-animation frame cache size.
+    pub fn set_animation_cache_size(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        if self.animation_cache_size() != value {
+            return self.0.set(ANIMATION_CACHE_SIZE, value);
+        }
+        Ok(())
+    }
+
+    /// Get the minutes between checks of URL wallpapers for a new image.
+    #[must_use]
+    pub fn remote_refresh_minutes(&self) -> u32 {
+        self.0
+            .get::<u32>(REMOTE_REFRESH)
+            .unwrap_or(DEFAULT_REMOTE_REFRESH)
+            .max(1)
+    }
+
+    /// Set the minutes between checks of URL wallpapers for a new image.
+    pub fn set_remote_refresh_minutes(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        if self.remote_refresh_minutes() != value {
+            return self.0.set(REMOTE_REFRESH, value);
+        }
+        Ok(())
+    }
+
+    /// Get the disk space, in MiB, scaled wallpapers may be cached in. With
+    /// 0, nothing is cached.
+    #[must_use]
+    pub fn scaled_cache_size(&self) -> u32 {
+        self.0
+            .get::<u32>(SCALED_CACHE_SIZE)
+            .unwrap_or(DEFAULT_SCALED_CACHE_SIZE)
+    }
+
+    /// Set the disk space scaled wallpapers may be cached in.
+    pub fn set_scaled_cache_size(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        if self.scaled_cache_size() != value {
+            return self.0.set(SCALED_CACHE_SIZE, value);
+        }
+        Ok(())
+    }
+
+    /// Get the memory, in MiB, the wallpapers' decoded images, scaled frames,
+    /// and SHM pools may hold before the least recently drawn are freed.
+    /// With 0, nothing is freed.
+    #[must_use]
+    pub fn memory_budget(&self) -> u32 {
+        self.0
+            .get::<u32>(MEMORY_BUDGET)
+            .unwrap_or(DEFAULT_MEMORY_BUDGET)
+    }
+
+    /// Set the memory the wallpapers may hold.
+    pub fn set_memory_budget(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        if self.memory_budget() != value {
+            return self.0.set(MEMORY_BUDGET, value);
+        }
+        Ok(())
+    }
+
     /// Get the window opacity setting for the settings app.
     /// Returns a value between 0.0 (fully transparent) and 1.0 (fully opaque).
     /// Default is 1.0 (fully opaque).
@@ -175,6 +476,17 @@ pub struct Entry {
     /// the configured image source
     #[setters(skip)]
     pub source: Source,
+    /// source shown instead while the COSMIC theme is dark
+    #[serde(default)]
+    pub dark_source: Option<Source>,
+    /// sources shown instead while these workspaces are active, on entries
+    /// shown on a single output
+    #[serde(default)]
+    pub workspaces: Vec<WorkspaceSource>,
+    /// sources shown instead on some days of the year, the first rule
+    /// applying today winning
+    #[serde(default)]
+    pub schedule: Vec<ScheduleRule>,
     /// whether the images should be filtered by the active theme
     pub filter_by_theme: bool,
     /// frequency at which the wallpaper is rotated in seconds
@@ -185,8 +497,95 @@ pub struct Entry {
     /// mode used to scale images,
     #[serde(default)]
     pub scaling_mode: ScalingMode,
+    /// images scaled with another mode than the entry's
+    #[serde(default)]
+    pub scaling_overrides: Vec<ScalingOverride>,
     #[serde(default)]
     pub sampling_method: SamplingMethod,
+    /// images a slideshow skips for being too small or the wrong shape
+    #[serde(default)]
+    pub image_filter: Option<ImageFilter>,
+    /// show files with the same contents once in a slideshow
+    #[serde(default)]
+    pub skip_duplicates: bool,
+    /// part of the image to show, or the point to keep in view
+    #[serde(default)]
+    pub crop: Option<Crop>,
+    /// slow pan and zoom over still images
+    #[serde(default)]
+    pub ken_burns: Option<KenBurns>,
+    /// dimming and blurring while windows are open on the output
+    #[serde(default)]
+    pub window_effect: Option<WindowEffect>,
+    /// animated switch between images as the slideshow rotates
+    #[serde(default)]
+    pub transition: Option<Transition>,
+    /// brightness of the wallpaper, from 0.0 (black) to 1.0 (unchanged)
+    #[serde(default = "default_brightness")]
+    pub brightness: f32,
+    /// color temperature of the wallpaper in kelvin, from 1000 (warmest) to
+    /// 6500 (unchanged)
+    #[serde(default = "default_temperature")]
+    pub temperature: u32,
+}
+
+/// Source an entry shows while a workspace is active on its output.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct WorkspaceSource {
+    /// Name of the workspace, as the compositor reports it, e.g. `"1"`.
+    pub workspace: String,
+    pub source: Source,
+}
+
+/// Scaling mode of one image of an entry, in place of the entry's own.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ScalingOverride {
+    pub image: PathBuf,
+    /// Any mode but `Span`, which only applies to a whole entry
+    pub scaling_mode: ScalingMode,
+}
+
+/// Source an entry shows on the days of the year `when` picks.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ScheduleRule {
+    pub when: DateRule,
+    pub source: Source,
+}
+
+/// Days of the year a schedule rule applies on. Ranges include both ends
+/// and may wrap around the new year.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum DateRule {
+    /// The first to the last month, 1-12: `Months(12, 2)` is December to
+    /// February.
+    Months(u8, u8),
+    /// Days from the first to the last, each as `(month, day)`.
+    Days((u8, u8), (u8, u8)),
+    /// A season of the meteorological calendar, in the hemisphere of the
+    /// computer's location.
+    Season(Season),
+    Holiday(Holiday),
+}
+
+/// Seasons of the meteorological calendar, three whole months each.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+/// Holidays a schedule rule can name.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Holiday {
+    /// New Year's Eve and Day.
+    NewYear,
+    /// Good Friday to Easter Monday, by the Western calendar.
+    Easter,
+    Halloween,
+    /// Christmas Eve to Boxing Day.
+    Christmas,
 }
 
 /// A background image which is colored.
@@ -203,15 +602,354 @@ pub struct Gradient {
     pub radius: f32,
 }
 
+/// A generated background: a pattern colored with a palette. The same
+/// seed always draws the same background.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Procedural {
+    pub pattern: Pattern,
+    /// Varies the pattern drawn (default 0).
+    #[serde(default)]
+    pub seed: u64,
+    /// Colors the pattern is mapped to, from its low to its high values, in
+    /// linear RGB. The pattern's own palette is used when empty.
+    #[serde(default)]
+    pub palette: Vec<[f32; 3]>,
+}
+
+/// Patterns of procedural backgrounds.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Default)]
+pub enum Pattern {
+    /// Smooth waves of interfering sines.
+    #[default]
+    Plasma,
+    /// Soft clouds of layered simplex noise.
+    Clouds,
+    /// Curtains of light over a night sky.
+    Aurora,
+    /// Stars of varied brightness on a dark sky.
+    Starfield,
+}
+
 /// The source of a background image.
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub enum Source {
     /// Background image(s) from a path.
     Path(PathBuf),
+    /// Images of several folders, or files, merged into one slideshow.
+    Folders(Vec<PathBuf>),
     /// A background color or gradient.
     Color(Color),
     /// A GPU-rendered shader for live wallpapers.
     Shader(ShaderSource),
+    /// A looping video, decoded with GStreamer.
+    Video(VideoSource),
+    /// Images found by searching an online wallpaper provider.
+    Online(OnlineSource),
+    /// Sources shown in turn, switching on the entry's rotation timer.
+    Playlist(Vec<Source>),
+    /// An image with a shader composited over it.
+    Composite(CompositeSource),
+    /// A pattern generated on the CPU, like a color.
+    Procedural(Procedural),
+    /// An image shifted in depth as the pointer moves.
+    Parallax(ParallaxSource),
+    /// A day and a night image, switching at sunrise and sunset.
+    DayNight(DayNightSource),
+}
+
+impl Source {
+    /// Sources shown by this one in turn: a playlist's items, with nested
+    /// playlists flattened, or this source alone.
+    pub fn items(&self) -> Vec<&Source> {
+        match self {
+            Source::Playlist(items) => items.iter().flat_map(Source::items).collect(),
+            source => vec![source],
+        }
+    }
+
+    /// Paths a slideshow shows the images of: a path source's path, or the
+    /// folders merged by a folders source.
+    pub fn folders(&self) -> &[PathBuf] {
+        match self {
+            Source::Path(path) => std::slice::from_ref(path),
+            Source::Folders(folders) => folders,
+            _ => &[],
+        }
+    }
+
+    /// Whether this source is drawn by a shader on the GPU: shaders, and
+    /// sources shown by a built-in one.
+    pub fn is_shader(&self) -> bool {
+        matches!(
+            self,
+            Source::Shader(_) | Source::Video(_) | Source::Composite(_) | Source::Parallax(_)
+        )
+    }
+}
+
+/// Configuration for images searched on an online provider. A new matching
+/// image is downloaded and shown every `refresh_minutes`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct OnlineSource {
+    pub provider: OnlineProvider,
+    /// Search terms, or tags, matched by the provider.
+    pub query: String,
+    /// Minutes between images (default 60).
+    #[serde(default = "default_online_refresh")]
+    pub refresh_minutes: u32,
+    /// Access key of the provider's API, required by Unsplash.
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+/// Online wallpaper providers.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnlineProvider {
+    /// [Wallhaven](https://wallhaven.cc), limited to images safe for work.
+    #[default]
+    Wallhaven,
+    /// [Unsplash](https://unsplash.com), with an access key.
+    Unsplash,
+}
+
+fn default_online_refresh() -> u32 {
+    60
+}
+
+/// Configuration for an image with a shader drawn over it, e.g. rain or fog
+/// over a photo. The image is scaled with the entry's `scaling_mode`, and
+/// the shader's output is blended over it where its alpha covers it.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CompositeSource {
+    /// The image drawn under the shader.
+    pub image: PathBuf,
+    /// The shader drawn over the image. It samples the image through
+    /// `iTexture`, in place of a background image of its own.
+    pub overlay: ShaderSource,
+    /// How the shader's colors are combined with the image's.
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// Opacity of the shader over the image (0.0-1.0, default 1.0).
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+}
+
+/// Configuration for a depth-parallax wallpaper: an image and its depth map,
+/// shifted in depth towards the pointer while it is over the desktop, or in
+/// a slow drift.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ParallaxSource {
+    /// The image shown.
+    pub image: PathBuf,
+    /// Grayscale depth map of the image, white for near and black for far.
+    pub depth: PathBuf,
+    /// Largest shift, as a fraction of the output size (0.0-0.1, default 0.02).
+    #[serde(default = "default_parallax_strength")]
+    pub strength: f32,
+    /// Follow the pointer while it is over the desktop, rather than only
+    /// drifting (default true).
+    #[serde(default = "default_follow_pointer")]
+    pub follow_pointer: bool,
+    /// Highest frame rate shown (1-60, default 30).
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: u8,
+}
+
+/// Configuration for a wallpaper following the sun: the day image from
+/// sunrise to sunset, and the night image in between.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DayNightSource {
+    /// The image shown while the sun is up.
+    pub day: PathBuf,
+    /// The image shown while it is down.
+    pub night: PathBuf,
+}
+
+/// Ken Burns motion of an entry's still images: a slow pan and zoom between
+/// two framings picked at random for each image, and back.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct KenBurns {
+    /// How far the closer framing zooms in (1.0-1.5, default 1.2).
+    #[serde(default = "default_ken_burns_zoom")]
+    pub zoom: f32,
+    /// Seconds one pan takes, or the entry's rotation frequency with 0
+    /// (default).
+    #[serde(default)]
+    pub duration: u64,
+    /// Highest frame rate shown (1-60, default 15).
+    #[serde(default = "default_ken_burns_frame_rate")]
+    pub frame_rate: u8,
+}
+
+impl Default for KenBurns {
+    fn default() -> Self {
+        Self {
+            zoom: default_ken_burns_zoom(),
+            duration: 0,
+            frame_rate: default_ken_burns_frame_rate(),
+        }
+    }
+}
+
+fn default_brightness() -> f32 {
+    1.0
+}
+
+fn default_temperature() -> u32 {
+    night_light::NEUTRAL_TEMPERATURE
+}
+
+/// Dimming and blurring of an entry's wallpaper while windows are open on
+/// its output, faded out again once the desktop is empty.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct WindowEffect {
+    /// How much darker the wallpaper gets (0.0-0.9, default 0.3).
+    #[serde(default = "default_window_dim")]
+    pub dim: f32,
+    /// Blur radius in pixels (0-64, default 0).
+    #[serde(default)]
+    pub blur: f32,
+    /// Seconds the fade in and out takes (default 0.5).
+    #[serde(default = "default_window_fade")]
+    pub fade: f32,
+}
+
+impl Default for WindowEffect {
+    fn default() -> Self {
+        Self {
+            dim: default_window_dim(),
+            blur: 0.0,
+            fade: default_window_fade(),
+        }
+    }
+}
+
+/// Images a slideshow leaves out. Images whose size can't be read without
+/// decoding them, like JPEG XL, HEIF, and RAW files, are always kept.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct ImageFilter {
+    /// Smallest width and height, in pixels, of an image shown.
+    #[serde(default)]
+    pub min_size: Option<(u32, u32)>,
+    /// How far an image's aspect ratio may be from that of each output it
+    /// is shown on, as a fraction of the output's: 0.15 keeps 16:10 images
+    /// on 16:9 outputs but not 21:9 ones.
+    #[serde(default)]
+    pub aspect_tolerance: Option<f32>,
+}
+
+/// Transition of an entry's wallpaper from one image to the next, rather
+/// than switching at once.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Transition {
+    /// How the new image replaces the old one (default crossfade).
+    #[serde(default)]
+    pub style: TransitionStyle,
+    /// How the transition speeds up and slows down (default ease in-out).
+    #[serde(default)]
+    pub easing: Easing,
+    /// Seconds the transition takes (default 1.0).
+    #[serde(default = "default_transition_duration")]
+    pub duration: f32,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            style: TransitionStyle::default(),
+            easing: Easing::default(),
+            duration: default_transition_duration(),
+        }
+    }
+}
+
+/// Ways a transition replaces the old image with the new one.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransitionStyle {
+    /// The old image fades into the new one.
+    #[default]
+    Crossfade,
+    /// The new image slides in from the right, pushing the old one out.
+    Slide,
+    /// The new image is uncovered from left to right.
+    Wipe,
+    /// The old image breaks into blocks, which come together as the new one.
+    Pixelate,
+    /// The old image grows and fades out over the new one.
+    Zoom,
+}
+
+/// Curves of a transition's progress over its duration.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// At a constant speed.
+    Linear,
+    /// Starting slowly.
+    EaseIn,
+    /// Ending slowly.
+    EaseOut,
+    /// Starting and ending slowly.
+    #[default]
+    EaseInOut,
+}
+
+fn default_transition_duration() -> f32 {
+    1.0
+}
+
+fn default_window_dim() -> f32 {
+    0.3
+}
+
+fn default_window_fade() -> f32 {
+    0.5
+}
+
+fn default_ken_burns_zoom() -> f32 {
+    1.2
+}
+
+fn default_ken_burns_frame_rate() -> u8 {
+    15
+}
+
+fn default_parallax_strength() -> f32 {
+    0.02
+}
+
+fn default_follow_pointer() -> bool {
+    true
+}
+
+/// Ways to combine a composite's shader with its image.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// The shader is drawn over the image.
+    #[default]
+    Normal,
+    /// Colors are added, brightening the image.
+    Add,
+    /// Inverted colors are multiplied, brightening the image more gently.
+    Screen,
+    /// Colors are multiplied, darkening the image.
+    Multiply,
+    /// Multiplies dark parts of the image and screens light ones.
+    Overlay,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+/// Configuration for a video wallpaper. It is scaled to each output with the
+/// entry's `scaling_mode`, like a static image.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct VideoSource {
+    /// The video file.
+    pub path: PathBuf,
+    /// Highest frame rate shown (1-60, default 30).
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: u8,
 }
 
 /// Configuration for a shader-based live wallpaper.
@@ -231,18 +969,43 @@ pub struct ShaderSource {
     /// Optional background image the shader can sample.
     #[serde(default)]
     pub background_image: Option<PathBuf>,
+    /// Optional depth map the shader can sample through `iDepth`, scaled
+    /// like the background image.
+    #[serde(default)]
+    pub depth_image: Option<PathBuf>,
+    /// Use the current image of the default static wallpaper as the background
+    /// image instead, following its slideshow. `background_image` is used
+    /// while no static image is available.
+    #[serde(default)]
+    pub background_from_wallpaper: bool,
+    /// How the background image is scaled to each output, as for static wallpapers.
+    #[serde(default)]
+    pub background_scaling: ScalingMode,
     /// Shader language (auto-detected from file extension if path).
     #[serde(default)]
     pub language: ShaderLanguage,
     /// Target frame rate (1-60, default 30).
     #[serde(default = "default_frame_rate")]
     pub frame_rate: u8,
+    /// Speed factor applied to `iTime`
+    /// ([`MIN_TIME_SCALE`]-[`MAX_TIME_SCALE`], default 1).
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f32,
 }
 
 fn default_frame_rate() -> u8 {
     30
 }
 
+/// Slowest shader time speed factor.
+pub const MIN_TIME_SCALE: f32 = 0.1;
+/// Fastest shader time speed factor.
+pub const MAX_TIME_SCALE: f32 = 4.0;
+
+fn default_time_scale() -> f32 {
+    1.0
+}
+
 /// Where the shader code comes from.
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub enum ShaderContent {
@@ -266,38 +1029,117 @@ impl Entry {
         Self {
             output,
             source,
+            dark_source: None,
+            workspaces: Vec::new(),
+            schedule: Vec::new(),
             filter_by_theme: false,
             rotation_frequency: 900,
             filter_method: FilterMethod::default(),
             scaling_mode: ScalingMode::default(),
+            scaling_overrides: Vec::new(),
             sampling_method: SamplingMethod::default(),
+            image_filter: None,
+            skip_duplicates: false,
+            crop: None,
+            ken_burns: None,
+            window_effect: None,
+            transition: None,
+            brightness: default_brightness(),
+            temperature: default_temperature(),
         }
     }
 
-    /// Fallback in case config and default schema can't be loaded.
-    /// Searches XDG data directories for the default cosmic wallpaper.
+    /// Fallback in case config and default schema can't be loaded: a
+    /// slideshow of the [`default_folders`], or the default cosmic
+    /// wallpaper without any.
     pub fn fallback() -> Self {
-        let wallpaper = "backgrounds/cosmic/orion_nebula_nasa_heic0601a.jpg";
+        let source = match default_folders().as_slice() {
+            [] => {
+                let wallpaper = "backgrounds/cosmic/orion_nebula_nasa_heic0601a.jpg";
 
-        // Use xdg crate to search all XDG data directories
-        // (searches ~/.local/share, then XDG_DATA_DIRS / defaults)
-        let xdg = xdg::BaseDirectories::new();
-        let source_path = xdg
-            .find_data_file(wallpaper)
-            .unwrap_or_else(|| PathBuf::from("/usr/share").join(wallpaper));
+                // Use xdg crate to search all XDG data directories
+                // (searches ~/.local/share, then XDG_DATA_DIRS / defaults)
+                let xdg = xdg::BaseDirectories::new();
+                Source::Path(
+                    xdg.find_data_file(wallpaper)
+                        .unwrap_or_else(|| PathBuf::from("/usr/share").join(wallpaper)),
+                )
+            }
+            [folder] => Source::Path(folder.clone()),
+            folders => Source::Folders(folders.to_vec()),
+        };
 
         Self {
             output: String::from("all"),
-            source: Source::Path(source_path),
+            source,
+            dark_source: None,
+            workspaces: Vec::new(),
+            schedule: Vec::new(),
             filter_by_theme: true,
             rotation_frequency: 3600,
             filter_method: FilterMethod::default(),
             scaling_mode: ScalingMode::default(),
+            scaling_overrides: Vec::new(),
             sampling_method: SamplingMethod::default(),
+            image_filter: None,
+            skip_duplicates: false,
+            crop: None,
+            ken_burns: None,
+            window_effect: None,
+            transition: None,
+            brightness: default_brightness(),
+            temperature: default_temperature(),
         }
     }
 }
 
+/// Folders shown when nothing usable is configured: `Wallpapers` in the
+/// user's pictures folder if there is one, or else the `backgrounds` of the
+/// XDG data directories.
+#[must_use]
+pub fn default_folders() -> Vec<PathBuf> {
+    let xdg = xdg::BaseDirectories::new();
+    if let Some(wallpapers) = pictures_dir(&xdg)
+        .map(|pictures| pictures.join("Wallpapers"))
+        .filter(|wallpapers| wallpapers.is_dir())
+    {
+        return vec![wallpapers];
+    }
+    xdg.find_data_files("backgrounds")
+        .filter(|backgrounds| backgrounds.is_dir())
+        .collect()
+}
+
+/// The user's pictures folder: `XDG_PICTURES_DIR` from `user-dirs.dirs`, or
+/// else `~/Pictures`.
+fn pictures_dir(xdg: &xdg::BaseDirectories) -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    let configured = xdg
+        .find_config_file("user-dirs.dirs")
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|dirs| user_dir(&dirs, "XDG_PICTURES_DIR", &home));
+    Some(configured.unwrap_or_else(|| home.join("Pictures")))
+}
+
+/// The folder `key` is set to in the `user-dirs.dirs` file `dirs`, with
+/// `$HOME` expanded to `home`. Like `xdg-user-dir`, the last setting wins,
+/// and values are absolute or relative to `$HOME`.
+fn user_dir(dirs: &str, key: &str, home: &Path) -> Option<PathBuf> {
+    dirs.lines()
+        .filter_map(|line| {
+            let (name, value) = line.trim().split_once('=')?;
+            (name.trim() == key).then(|| value.trim().trim_matches('"'))
+        })
+        .next_back()
+        .and_then(|value| match value.strip_prefix("$HOME") {
+            Some(relative) if relative.is_empty() || relative.starts_with('/') => {
+                Some(home.join(relative.trim_start_matches('/')))
+            }
+            Some(_) => None,
+            None => Some(PathBuf::from(value)).filter(|path| path.is_absolute()),
+        })
+}
+
 /// Image filtering method
 #[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
 pub enum FilterMethod {
@@ -328,6 +1170,55 @@ pub enum SamplingMethod {
     Alphanumeric,
     // Rotate through images in Random order
     Random,
+    // Rotate through a seeded shuffle that carries on after a restart and is
+    // reshuffled after each pass, never showing an image again within
+    // `no_repeat` images of when it was last shown
+    Shuffle {
+        #[serde(default = "default_no_repeat")]
+        no_repeat: u32,
+    },
+}
+
+fn default_no_repeat() -> u32 {
+    1
+}
+
+/// Graphics API for the shader renderer
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GpuBackend {
+    /// Vulkan where available, OpenGL ES otherwise
+    #[default]
+    Auto,
+    Vulkan,
+    /// OpenGL ES, for drivers whose Vulkan presentation is broken on Wayland
+    Gl,
+}
+
+/// A change to a slideshow's upcoming images, requested through
+/// [`Context::request_queue_edit`].
+///
+/// `output` is the entry's output, as listed in [`state::State::slideshow_queues`].
+/// Positions count from 0, the image shown next.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub enum QueueEdit {
+    /// Move the image at `from` to `to`.
+    Move {
+        output: String,
+        from: usize,
+        to: usize,
+    },
+    /// Show `path` next, moving it up if it is already queued.
+    PlayNext { output: String, path: PathBuf },
+}
+
+impl QueueEdit {
+    /// The entry output whose queue is edited.
+    #[must_use]
+    pub fn output(&self) -> &str {
+        match self {
+            Self::Move { output, .. } | Self::PlayNext { output, .. } => output,
+        }
+    }
 }
 
 /// Image scaling mode
@@ -340,6 +1231,29 @@ pub enum ScalingMode {
     /// Zoom the image so that it fill the whole area
     #[default]
     Zoom,
+    /// Zoom one image over the compositor's layout of all the entry's
+    /// outputs, each showing its slice of it
+    Span,
+    /// Repeat the image at its own size from the top-left corner
+    Tile,
+    /// Show the image at its own size in the middle, filling the rest of the
+    /// area with the given RGB color
+    Center([f32; 3]),
+}
+
+/// Part of an entry's images shown on its outputs, in fractions of the
+/// image's width and height from its top-left corner.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum Crop {
+    /// Show only this region, scaled with the entry's scaling mode
+    Region {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    /// Zoom around this point instead of the image's center
+    Focus { x: f32, y: f32 },
 }
 
 impl Entry {
@@ -347,6 +1261,16 @@ impl Entry {
     pub fn key(&self) -> String {
         self.output.to_string()
     }
+
+    /// Scaling mode of the image at `image`: its override, if it has one
+    /// other than `Span`, or else the entry's.
+    #[must_use]
+    pub fn scaling_mode_for(&self, image: &Path) -> &ScalingMode {
+        self.scaling_overrides
+            .iter()
+            .find(|o| o.image == image && o.scaling_mode != ScalingMode::Span)
+            .map_or(&self.scaling_mode, |o| &o.scaling_mode)
+    }
 }
 
 #[must_use]
@@ -414,6 +1338,43 @@ impl Config {
         self.backgrounds.iter().find(|entry| entry.output == output)
     }
 
+    /// Get the entry that applies to a connected output.
+    ///
+    /// Entry outputs may be connector names, pseudo-outputs, or globs (see
+    /// [`output_group`]); the most specific match wins, ties going to the
+    /// alphabetically first pattern.
+    #[must_use]
+    pub fn entry_for_output(&self, output: &str) -> Option<&Entry> {
+        self.backgrounds
+            .iter()
+            .filter_map(|entry| {
+                output_group::specificity(&entry.output, output).map(|spec| (spec, entry))
+            })
+            .min_by(|(a_spec, a), (b_spec, b)| {
+                b_spec.cmp(a_spec).then_with(|| a.output.cmp(&b.output))
+            })
+            .map(|(_, entry)| entry)
+    }
+
+    /// Get the entry that applies to a connected output and why it was chosen.
+    #[must_use]
+    pub fn explain_output(&self, output: &str) -> (&Entry, output_group::MatchReason) {
+        use output_group::MatchReason;
+
+        if self.same_on_all {
+            return (&self.default_background, MatchReason::SameOnAll);
+        }
+
+        match self.entry_for_output(output) {
+            Some(entry) => {
+                let spec = output_group::specificity(&entry.output, output)
+                    .unwrap_or(output_group::Specificity::Exact);
+                (entry, MatchReason::Matched(spec))
+            }
+            None => (&self.default_background, MatchReason::Default),
+        }
+    }
+
     /// get a mutable entry for a given output.
     #[must_use]
     pub fn entry_mut(&mut self, output: &str) -> Option<&mut Entry> {
@@ -464,3 +1425,59 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattens_nested_playlists() {
+        let color = |value: f32| Source::Color(Color::Single([value; 3]));
+        let playlist = Source::Playlist(vec![
+            color(0.1),
+            Source::Playlist(vec![color(0.2), Source::Playlist(Vec::new())]),
+            color(0.3),
+        ]);
+
+        assert_eq!(
+            playlist.items(),
+            vec![&color(0.1), &color(0.2), &color(0.3)]
+        );
+        assert_eq!(color(0.1).items(), vec![&color(0.1)]);
+    }
+
+    #[test]
+    fn reads_user_dirs() {
+        let home = Path::new("/home/user");
+        let dirs = "# This file is written by xdg-user-dirs-update\n\
+                    XDG_DESKTOP_DIR=\"$HOME/Desktop\"\n\
+                    XDG_PICTURES_DIR=\"$HOME/Bilder\"\n";
+        assert_eq!(
+            user_dir(dirs, "XDG_PICTURES_DIR", home),
+            Some(PathBuf::from("/home/user/Bilder"))
+        );
+        assert_eq!(
+            user_dir(
+                "XDG_PICTURES_DIR=\"/media/photos\"",
+                "XDG_PICTURES_DIR",
+                home
+            ),
+            Some(PathBuf::from("/media/photos"))
+        );
+
+        // Commented out, unset, or not a path
+        assert_eq!(
+            user_dir("#XDG_PICTURES_DIR=\"$HOME/P\"", "XDG_PICTURES_DIR", home),
+            None
+        );
+        assert_eq!(user_dir(dirs, "XDG_MUSIC_DIR", home), None);
+        assert_eq!(
+            user_dir("XDG_PICTURES_DIR=\"$HOMEx\"", "XDG_PICTURES_DIR", home),
+            None
+        );
+        assert_eq!(
+            user_dir("XDG_PICTURES_DIR=\"Pictures\"", "XDG_PICTURES_DIR", home),
+            None
+        );
+    }
+}
@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Configuration for warming the wallpaper's colors along with the desktop's
+//! night light.
+
+use cosmic_config::{ConfigGet, ConfigSet};
+
+use crate::Context;
+
+// Config keys
+pub const NIGHT_LIGHT_ENABLED: &str = "night-light-enabled";
+pub const NIGHT_LIGHT_TEMPERATURE: &str = "night-light-temperature";
+
+/// Color temperature of unchanged colors, in kelvin.
+pub const NEUTRAL_TEMPERATURE: u32 = 6500;
+/// Warmest color temperature, in kelvin.
+pub const MIN_TEMPERATURE: u32 = 1000;
+
+/// Night light configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NightLightConfig {
+    /// Tint wallpapers while the night light is on
+    pub enabled: bool,
+    /// Color temperature at night, in kelvin, when the desktop doesn't
+    /// report its own
+    pub temperature: u32,
+}
+
+impl Default for NightLightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            temperature: 4000,
+        }
+    }
+}
+
+impl NightLightConfig {
+    /// Load night light config from cosmic-config.
+    pub fn load(context: &Context) -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: context.0.get::<bool>(NIGHT_LIGHT_ENABLED).unwrap_or(false),
+            temperature: context
+                .0
+                .get::<u32>(NIGHT_LIGHT_TEMPERATURE)
+                .unwrap_or(defaults.temperature)
+                .clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE),
+        }
+    }
+
+    /// Save night light config to cosmic-config.
+    pub fn save(&self, context: &Context) -> Result<(), cosmic_config::Error> {
+        context.0.set(NIGHT_LIGHT_ENABLED, self.enabled)?;
+        context.0.set(NIGHT_LIGHT_TEMPERATURE, self.temperature)?;
+        Ok(())
+    }
+}
+
+impl Context {
+    /// Load the full night light config.
+    #[must_use]
+    pub fn night_light_config(&self) -> NightLightConfig {
+        NightLightConfig::load(self)
+    }
+}
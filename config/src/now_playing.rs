@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Configuration for showing the album art of the playing track as the
+//! wallpaper.
+
+use cosmic_config::ConfigGet;
+
+use crate::Context;
+
+// Config keys
+pub const NOW_PLAYING_ENABLED: &str = "now-playing-enabled";
+pub const NOW_PLAYING_BLUR: &str = "now-playing-blur";
+pub const NOW_PLAYING_DIM: &str = "now-playing-dim";
+
+/// Strongest blur, as a standard deviation in pixels of the art.
+pub const MAX_BLUR: f32 = 64.0;
+/// Strongest dimming; fully black art would hide the track.
+pub const MAX_DIM: f32 = 0.9;
+
+/// Album art wallpaper configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NowPlayingConfig {
+    /// Show the playing track's album art on all outputs
+    pub enabled: bool,
+    /// Blur applied to the art, as a standard deviation in pixels
+    pub blur: f32,
+    /// Fraction by which the art is darkened
+    pub dim: f32,
+}
+
+impl Default for NowPlayingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            blur: 12.0,
+            dim: 0.3,
+        }
+    }
+}
+
+impl NowPlayingConfig {
+    /// Load album art config from cosmic-config.
+    pub fn load(context: &Context) -> Self {
+        let defaults = Self::default();
+        let clamp = |value: f32, default: f32, max: f32| {
+            if value.is_finite() {
+                value.clamp(0.0, max)
+            } else {
+                default
+            }
+        };
+        Self {
+            enabled: context.0.get::<bool>(NOW_PLAYING_ENABLED).unwrap_or(false),
+            blur: clamp(
+                context
+                    .0
+                    .get::<f32>(NOW_PLAYING_BLUR)
+                    .unwrap_or(defaults.blur),
+                defaults.blur,
+                MAX_BLUR,
+            ),
+            dim: clamp(
+                context
+                    .0
+                    .get::<f32>(NOW_PLAYING_DIM)
+                    .unwrap_or(defaults.dim),
+                defaults.dim,
+                MAX_DIM,
+            ),
+        }
+    }
+}
+
+impl Context {
+    /// Load the full album art config.
+    #[must_use]
+    pub fn now_playing_config(&self) -> NowPlayingConfig {
+        NowPlayingConfig::load(self)
+    }
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Matching of entry output names against connected outputs.
+//!
+//! Besides connector names like `DP-1`, an entry's output may be one of the
+//! pseudo-outputs [`INTERNAL`] or [`EXTERNAL`], or a glob pattern such as
+//! `DP-*` or `HDMI-A-?`. When several entries match an output, the most
+//! specific one wins: exact names, then globs, then groups.
+
+/// Pseudo-output matching built-in panels (eDP, LVDS, DSI).
+pub const INTERNAL: &str = "internal";
+/// Pseudo-output matching every output that is not a built-in panel.
+pub const EXTERNAL: &str = "external";
+
+/// Connector name prefixes used by built-in laptop and tablet panels.
+const INTERNAL_PREFIXES: &[&str] = &["eDP", "LVDS", "DSI"];
+
+/// How specifically an entry's output pattern matches an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Specificity {
+    /// Matched through [`INTERNAL`] or [`EXTERNAL`].
+    Group,
+    /// Matched a glob pattern.
+    Glob,
+    /// Matched the connector name exactly.
+    Exact,
+}
+
+/// Why a particular entry applies to an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchReason {
+    /// `same-on-all` is enabled, so every output uses the default entry.
+    SameOnAll,
+    /// An entry matched the output through a name, glob, or group.
+    Matched(Specificity),
+    /// No per-output entry matched; the default entry is used.
+    Default,
+}
+
+impl std::fmt::Display for MatchReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::SameOnAll => "same-on-all is enabled",
+            Self::Matched(Specificity::Exact) => "exact output name",
+            Self::Matched(Specificity::Glob) => "glob pattern",
+            Self::Matched(Specificity::Group) => "internal/external group",
+            Self::Default => "no entry matched, using the default",
+        })
+    }
+}
+
+/// Whether the connector name belongs to a built-in panel.
+#[must_use]
+pub fn is_internal(output: &str) -> bool {
+    INTERNAL_PREFIXES
+        .iter()
+        .any(|prefix| output.starts_with(prefix))
+}
+
+/// Whether `pattern` is a pseudo-output or glob rather than a connector name.
+#[must_use]
+pub fn is_pattern(pattern: &str) -> bool {
+    pattern == INTERNAL || pattern == EXTERNAL || pattern.contains(['*', '?'])
+}
+
+/// How `pattern` matches the output named `output`, if at all.
+#[must_use]
+pub fn specificity(pattern: &str, output: &str) -> Option<Specificity> {
+    match pattern {
+        _ if pattern == output => Some(Specificity::Exact),
+        INTERNAL => is_internal(output).then_some(Specificity::Group),
+        EXTERNAL => (!is_internal(output)).then_some(Specificity::Group),
+        _ if pattern.contains(['*', '?']) => {
+            glob_match(pattern.as_bytes(), output.as_bytes()).then_some(Specificity::Glob)
+        }
+        _ => None,
+    }
+}
+
+/// Whether `pattern` matches the output named `output`.
+#[must_use]
+pub fn matches(pattern: &str, output: &str) -> bool {
+    specificity(pattern, output).is_some()
+}
+
+/// Match `*` (any run of characters) and `?` (one character).
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_split_internal_and_external() {
+        assert_eq!(specificity(INTERNAL, "eDP-1"), Some(Specificity::Group));
+        assert_eq!(specificity(INTERNAL, "DP-2"), None);
+        assert_eq!(specificity(EXTERNAL, "HDMI-A-1"), Some(Specificity::Group));
+        assert_eq!(specificity(EXTERNAL, "eDP-1"), None);
+    }
+
+    #[test]
+    fn globs_match_connector_names() {
+        assert!(matches("DP-*", "DP-3"));
+        assert!(!matches("DP-*", "eDP-1"));
+        assert!(matches("HDMI-A-?", "HDMI-A-1"));
+        assert!(!matches("HDMI-A-?", "HDMI-A-10"));
+        assert!(matches("*-1", "eDP-1"));
+    }
+
+    #[test]
+    fn exact_names_are_most_specific() {
+        assert!(specificity("DP-1", "DP-1") > specificity("DP-*", "DP-1"));
+        assert!(specificity("DP-*", "DP-1") > specificity(EXTERNAL, "DP-1"));
+    }
+}
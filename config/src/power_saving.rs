@@ -13,6 +13,21 @@ pub const ON_BATTERY_ACTION: &str = "on-battery-action";
 pub const PAUSE_ON_LOW_BATTERY: &str = "pause-on-low-battery";
 pub const LOW_BATTERY_THRESHOLD: &str = "low-battery-threshold";
 pub const PAUSE_ON_LID_CLOSED: &str = "pause-on-lid-closed";
+pub const RELEASE_GPU_AFTER: &str = "release-gpu-after-minutes";
+pub const PAUSE_WHEN_IDLE: &str = "pause-when-idle-minutes";
+pub const PAUSE_ON_FULLSCREEN: &str = "pause-on-fullscreen";
+pub const PAUSE_ON_COVERED: &str = "pause-on-covered";
+pub const COVERAGE_THRESHOLD: &str = "coverage-threshold";
+pub const ON_SCREEN_CAPTURE: &str = "on-screen-capture";
+
+/// Default minutes of paused animation before GPU state is released.
+pub const DEFAULT_RELEASE_GPU_AFTER: u32 = 10;
+
+/// Default minutes without input before animation pauses (0 = never).
+pub const DEFAULT_PAUSE_WHEN_IDLE: u32 = 0;
+
+/// Default percentage of an output covered by windows before it pauses.
+pub const DEFAULT_COVERAGE_THRESHOLD: u8 = 90;
 
 /// Action to take when on battery power.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -56,6 +71,30 @@ impl OnBatteryAction {
     }
 }
 
+/// What shaders do while the screen is being recorded or shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ScreenCaptureAction {
+    /// Keep animating, so recordings show the live wallpaper
+    KeepAnimating,
+    /// Pause animation to save power
+    Pause,
+    /// Keep animating on AC power, pause on battery
+    #[default]
+    KeepAnimatingOnAc,
+}
+
+impl ScreenCaptureAction {
+    /// Returns true if this action pauses animation in the given power state.
+    #[must_use]
+    pub fn should_pause(&self, on_battery: bool) -> bool {
+        match self {
+            Self::KeepAnimating => false,
+            Self::Pause => true,
+            Self::KeepAnimatingOnAc => on_battery,
+        }
+    }
+}
+
 /// Power saving configuration.
 #[derive(Debug, Clone, PartialEq)]
 pub struct PowerSavingConfig {
@@ -69,6 +108,18 @@ pub struct PowerSavingConfig {
     pub low_battery_threshold: u8,
     /// Pause internal display when lid is closed
     pub pause_on_lid_closed: bool,
+    /// Minutes of paused animation before GPU surfaces are released (0 = never)
+    pub release_gpu_after_minutes: u32,
+    /// Minutes without input before animation pauses (0 = never)
+    pub pause_when_idle_minutes: u32,
+    /// Pause outputs showing a fullscreen window
+    pub pause_on_fullscreen: bool,
+    /// Pause outputs mostly covered by windows
+    pub pause_on_covered: bool,
+    /// Percentage of the output covered by windows (75, 90, 100)
+    pub coverage_threshold: u8,
+    /// What to do while the screen is being captured
+    pub on_screen_capture: ScreenCaptureAction,
 }
 
 impl Default for PowerSavingConfig {
@@ -79,6 +130,12 @@ impl Default for PowerSavingConfig {
             pause_on_low_battery: true, // On by default
             low_battery_threshold: 20,
             pause_on_lid_closed: true, // On by default
+            release_gpu_after_minutes: DEFAULT_RELEASE_GPU_AFTER,
+            pause_when_idle_minutes: DEFAULT_PAUSE_WHEN_IDLE,
+            pause_on_fullscreen: true, // On by default
+            pause_on_covered: false,   // Opt-in
+            coverage_threshold: DEFAULT_COVERAGE_THRESHOLD,
+            on_screen_capture: ScreenCaptureAction::default(),
         }
     }
 }
@@ -95,6 +152,12 @@ impl PowerSavingConfig {
             pause_on_low_battery: context.0.get::<bool>(PAUSE_ON_LOW_BATTERY).unwrap_or(true),
             low_battery_threshold: context.0.get::<u8>(LOW_BATTERY_THRESHOLD).unwrap_or(20),
             pause_on_lid_closed: context.0.get::<bool>(PAUSE_ON_LID_CLOSED).unwrap_or(true),
+            release_gpu_after_minutes: context.release_gpu_after_minutes(),
+            pause_when_idle_minutes: context.pause_when_idle_minutes(),
+            pause_on_fullscreen: context.pause_on_fullscreen(),
+            pause_on_covered: context.pause_on_covered(),
+            coverage_threshold: context.coverage_threshold(),
+            on_screen_capture: context.on_screen_capture(),
         }
     }
 
@@ -111,6 +174,18 @@ impl PowerSavingConfig {
         context
             .0
             .set(PAUSE_ON_LID_CLOSED, self.pause_on_lid_closed)?;
+        context
+            .0
+            .set(RELEASE_GPU_AFTER, self.release_gpu_after_minutes)?;
+        context
+            .0
+            .set(PAUSE_WHEN_IDLE, self.pause_when_idle_minutes)?;
+        context
+            .0
+            .set(PAUSE_ON_FULLSCREEN, self.pause_on_fullscreen)?;
+        context.0.set(PAUSE_ON_COVERED, self.pause_on_covered)?;
+        context.0.set(COVERAGE_THRESHOLD, self.coverage_threshold)?;
+        context.0.set(ON_SCREEN_CAPTURE, self.on_screen_capture)?;
         Ok(())
     }
 }
@@ -176,6 +251,85 @@ impl Context {
         self.0.set(PAUSE_ON_LID_CLOSED, value)
     }
 
+    /// Get the minutes of paused animation before GPU state is released.
+    /// Zero keeps it for as long as the pause lasts.
+    #[must_use]
+    pub fn release_gpu_after_minutes(&self) -> u32 {
+        self.0
+            .get::<u32>(RELEASE_GPU_AFTER)
+            .unwrap_or(DEFAULT_RELEASE_GPU_AFTER)
+    }
+
+    /// Set the minutes of paused animation before GPU state is released.
+    pub fn set_release_gpu_after_minutes(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        self.0.set(RELEASE_GPU_AFTER, value)
+    }
+
+    /// Get the minutes without input before animation pauses.
+    /// Zero never pauses for idleness.
+    #[must_use]
+    pub fn pause_when_idle_minutes(&self) -> u32 {
+        self.0
+            .get::<u32>(PAUSE_WHEN_IDLE)
+            .unwrap_or(DEFAULT_PAUSE_WHEN_IDLE)
+    }
+
+    /// Set the minutes without input before animation pauses.
+    pub fn set_pause_when_idle_minutes(&self, value: u32) -> Result<(), cosmic_config::Error> {
+        self.0.set(PAUSE_WHEN_IDLE, value)
+    }
+
+    /// Get the pause on fullscreen setting.
+    #[must_use]
+    pub fn pause_on_fullscreen(&self) -> bool {
+        self.0.get::<bool>(PAUSE_ON_FULLSCREEN).unwrap_or(true)
+    }
+
+    /// Set the pause on fullscreen setting.
+    pub fn set_pause_on_fullscreen(&self, value: bool) -> Result<(), cosmic_config::Error> {
+        self.0.set(PAUSE_ON_FULLSCREEN, value)
+    }
+
+    /// Get the pause on covered setting.
+    #[must_use]
+    pub fn pause_on_covered(&self) -> bool {
+        self.0.get::<bool>(PAUSE_ON_COVERED).unwrap_or(false)
+    }
+
+    /// Set the pause on covered setting.
+    pub fn set_pause_on_covered(&self, value: bool) -> Result<(), cosmic_config::Error> {
+        self.0.set(PAUSE_ON_COVERED, value)
+    }
+
+    /// Get the percentage of an output covered by windows before it pauses.
+    #[must_use]
+    pub fn coverage_threshold(&self) -> u8 {
+        self.0
+            .get::<u8>(COVERAGE_THRESHOLD)
+            .unwrap_or(DEFAULT_COVERAGE_THRESHOLD)
+    }
+
+    /// Set the percentage of an output covered by windows before it pauses.
+    pub fn set_coverage_threshold(&self, value: u8) -> Result<(), cosmic_config::Error> {
+        self.0.set(COVERAGE_THRESHOLD, value)
+    }
+
+    /// Get the screen capture action setting.
+    #[must_use]
+    pub fn on_screen_capture(&self) -> ScreenCaptureAction {
+        self.0
+            .get::<ScreenCaptureAction>(ON_SCREEN_CAPTURE)
+            .unwrap_or_default()
+    }
+
+    /// Set the screen capture action setting.
+    pub fn set_on_screen_capture(
+        &self,
+        value: ScreenCaptureAction,
+    ) -> Result<(), cosmic_config::Error> {
+        self.0.set(ON_SCREEN_CAPTURE, value)
+    }
+
     /// Load the full power saving config.
     #[must_use]
     pub fn power_saving_config(&self) -> PowerSavingConfig {
@@ -1,6 +1,7 @@
 use cosmic_config::{Config, CosmicConfigEntry, cosmic_config_derive::CosmicConfigEntry};
 use derive_setters::Setters;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 use crate::{NAME, Source};
 
@@ -13,6 +14,40 @@ pub struct State {
     pub wallpapers: Vec<(String, Source)>,
     /// Currently connected outputs (updated by daemon)
     pub connected_outputs: Vec<String>,
+    /// Configured outputs whose wallpaper source can't be shown
+    /// (output_name, reason), updated by daemon
+    pub source_errors: Vec<(String, String)>,
+    /// Upcoming images of each slideshow, next first
+    /// (entry output, images), updated by daemon
+    pub slideshow_queues: Vec<(String, Vec<PathBuf>)>,
+    /// Last source each output showed successfully, used when its configured
+    /// source fails (output_name, source), updated by daemon
+    pub last_known_good: Vec<(String, Source)>,
+    /// Place of each shuffled slideshow in its sequence
+    /// (entry output, shuffle), updated by daemon
+    pub shuffles: Vec<(String, Shuffle)>,
+    /// Image each slideshow shows, to resume with after a restart
+    /// (entry output, position), updated by daemon
+    pub slideshow_positions: Vec<(String, SlideshowPosition)>,
+}
+
+/// Where a slideshow is in its folder.
+#[derive(Default, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SlideshowPosition {
+    /// Image shown now
+    pub image: PathBuf,
+    /// Index of the image in the slideshow's order, to carry on from should
+    /// the image be gone
+    pub cursor: usize,
+}
+
+/// Where a shuffled slideshow is in its sequence of passes.
+#[derive(Default, Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct Shuffle {
+    /// Seed every pass is shuffled from
+    pub seed: u64,
+    /// Pass through the images shown now, counting from 0
+    pub pass: u64,
 }
 
 impl State {
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Weather provider configuration for the `iWeather` shader uniform.
+
+use cosmic_config::{ConfigGet, ConfigSet};
+
+use crate::Context;
+
+// Config keys
+pub const WEATHER_ENABLED: &str = "weather-enabled";
+pub const WEATHER_LATITUDE: &str = "weather-latitude";
+pub const WEATHER_LONGITUDE: &str = "weather-longitude";
+pub const WEATHER_REFRESH_MINUTES: &str = "weather-refresh-minutes";
+
+/// Minimum refresh interval, keeping well within Open-Meteo's fair use limits.
+pub const MIN_REFRESH_MINUTES: u32 = 10;
+
+/// Weather provider configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeatherConfig {
+    /// Fetch weather data for shaders
+    pub enabled: bool,
+    /// Latitude of the location to report, in degrees
+    pub latitude: Option<f64>,
+    /// Longitude of the location to report, in degrees
+    pub longitude: Option<f64>,
+    /// Minutes between fetches
+    pub refresh_minutes: u32,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false, // Opt-in, since it contacts a remote service
+            latitude: None,
+            longitude: None,
+            refresh_minutes: 30,
+        }
+    }
+}
+
+impl WeatherConfig {
+    /// Load weather config from cosmic-config.
+    pub fn load(context: &Context) -> Self {
+        Self {
+            enabled: context.0.get::<bool>(WEATHER_ENABLED).unwrap_or(false),
+            latitude: context.0.get::<f64>(WEATHER_LATITUDE).ok(),
+            longitude: context.0.get::<f64>(WEATHER_LONGITUDE).ok(),
+            refresh_minutes: context
+                .0
+                .get::<u32>(WEATHER_REFRESH_MINUTES)
+                .unwrap_or(30)
+                .max(MIN_REFRESH_MINUTES),
+        }
+    }
+
+    /// Save weather config to cosmic-config.
+    pub fn save(&self, context: &Context) -> Result<(), cosmic_config::Error> {
+        context.0.set(WEATHER_ENABLED, self.enabled)?;
+        if let Some(latitude) = self.latitude {
+            context.0.set(WEATHER_LATITUDE, latitude)?;
+        }
+        if let Some(longitude) = self.longitude {
+            context.0.set(WEATHER_LONGITUDE, longitude)?;
+        }
+        context
+            .0
+            .set(WEATHER_REFRESH_MINUTES, self.refresh_minutes)?;
+        Ok(())
+    }
+
+    /// The configured location, if both coordinates are set and in range.
+    #[must_use]
+    pub fn location(&self) -> Option<(f64, f64)> {
+        let (lat, lon) = (self.latitude?, self.longitude?);
+        ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+    }
+}
+
+impl Context {
+    /// Load the full weather config.
+    #[must_use]
+    pub fn weather_config(&self) -> WeatherConfig {
+        WeatherConfig::load(self)
+    }
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Matching configured entries to connected outputs, as the daemon does each
+//! time the config changes or an output is hotplugged.
+//!
+//! ```sh
+//! cargo bench -p glowberry-lib --bench config
+//! ```
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use glowberry_config::{Color, Config, Entry, Source, output_group};
+use std::collections::HashSet;
+use std::hint::black_box;
+
+fn entry(output: &str) -> Entry {
+    Entry::new(
+        output.to_owned(),
+        Source::Color(Color::Single([0.1, 0.2, 0.3])),
+    )
+}
+
+/// A config with exact, glob, and group entries for `outputs` connectors.
+fn config(outputs: usize) -> Config {
+    let mut backgrounds: Vec<Entry> = (0..outputs)
+        .step_by(2)
+        .map(|i| entry(&format!("DP-{i}")))
+        .collect();
+    backgrounds.extend(
+        [
+            "DP-*",
+            "HDMI-A-?",
+            output_group::INTERNAL,
+            output_group::EXTERNAL,
+        ]
+        .into_iter()
+        .map(entry),
+    );
+
+    Config {
+        same_on_all: false,
+        outputs: backgrounds
+            .iter()
+            .map(|e| e.output.clone())
+            .collect::<HashSet<_>>(),
+        backgrounds,
+        default_background: entry("all"),
+    }
+}
+
+fn connectors(outputs: usize) -> Vec<String> {
+    let mut names = vec!["eDP-1".to_owned()];
+    names.extend((0..outputs).map(|i| match i % 3 {
+        0 => format!("DP-{i}"),
+        1 => format!("HDMI-A-{}", i % 10),
+        _ => format!("DVI-I-{i}"),
+    }));
+    names
+}
+
+fn bench_apply(c: &mut Criterion) {
+    let mut group = c.benchmark_group("config-apply");
+
+    for outputs in [3, 16, 64] {
+        let config = config(outputs);
+        let names = connectors(outputs);
+        group.bench_with_input(
+            BenchmarkId::new("entry-for-output", outputs),
+            &names,
+            |b, names| {
+                b.iter(|| {
+                    names
+                        .iter()
+                        .filter_map(|name| config.entry_for_output(black_box(name)))
+                        .count()
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("explain-output", outputs),
+            &names,
+            |b, names| {
+                b.iter(|| {
+                    for name in names {
+                        black_box(config.explain_output(black_box(name)));
+                    }
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_apply);
+criterion_main!(benches);
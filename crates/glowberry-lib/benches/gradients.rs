@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Color and gradient background generation at common output sizes.
+//!
+//! ```sh
+//! cargo bench -p glowberry-lib --bench gradients
+//! ```
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use glowberry_config::Gradient;
+use glowberry_lib::colored;
+use std::hint::black_box;
+
+const OUTPUTS: &[(u32, u32)] = &[(1920, 1080), (3840, 2160)];
+
+fn bench_gradients(c: &mut Criterion) {
+    let two_stop = Gradient {
+        colors: vec![[0.1, 0.2, 0.6], [0.9, 0.4, 0.3]].into(),
+        radius: 270.0,
+    };
+    let five_stop = Gradient {
+        colors: vec![
+            [0.0, 0.0, 0.0],
+            [0.2, 0.1, 0.5],
+            [0.8, 0.2, 0.4],
+            [1.0, 0.7, 0.2],
+            [1.0, 1.0, 1.0],
+        ]
+        .into(),
+        radius: 45.0,
+    };
+
+    let mut group = c.benchmark_group("colored");
+    group.sample_size(10);
+
+    for &(w, h) in OUTPUTS {
+        let id = format!("{w}x{h}");
+        group.throughput(Throughput::Elements(u64::from(w) * u64::from(h)));
+
+        group.bench_with_input(BenchmarkId::new("single", &id), &(w, h), |b, &(w, h)| {
+            b.iter(|| colored::single(black_box([0.2, 0.3, 0.4]), w, h));
+        });
+        group.bench_with_input(
+            BenchmarkId::new("placeholder", &id),
+            &(w, h),
+            |b, &(w, h)| {
+                b.iter(|| colored::placeholder(black_box(w), h));
+            },
+        );
+        for (name, gradient) in [("gradient-2", &two_stop), ("gradient-5", &five_stop)] {
+            group.bench_with_input(BenchmarkId::new(name, &id), &(w, h), |b, &(w, h)| {
+                b.iter(|| colored::gradient(black_box(gradient), w, h));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_gradients);
+criterion_main!(benches);
@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Scaling benchmarks for 4K-class sources.
+//!
+//! Compare single- and multi-threaded runs with:
+//!
+//! ```sh
+//! cargo bench -p glowberry-lib --bench scaling
+//! cargo bench -p glowberry-lib --bench scaling --no-default-features
+//! ```
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use glowberry_lib::scaler::{self, Strategy};
+use image::{DynamicImage, RgbImage};
+use std::hint::black_box;
+
+const OUTPUTS: &[(u32, u32)] = &[(1920, 1080), (2560, 1440), (3840, 2160)];
+
+fn source_image() -> DynamicImage {
+    let (w, h) = (5120, 2880);
+    DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x ^ y) % 256) as u8])
+    }))
+}
+
+fn bench_scaling(c: &mut Criterion) {
+    let img = source_image();
+    let mut group = c.benchmark_group("scaler");
+    group.sample_size(10);
+
+    for &(w, h) in OUTPUTS {
+        let id = format!("{w}x{h}");
+        group.throughput(Throughput::Elements(u64::from(w) * u64::from(h)));
+
+        for (name, strategy) in [
+            ("zoom", Strategy::Zoom),
+            ("fit", Strategy::Fit([0.0, 0.0, 0.0])),
+            ("stretch", Strategy::Stretch),
+        ] {
+            group.bench_with_input(BenchmarkId::new(name, &id), &(w, h), |b, &target| {
+                b.iter(|| scaler::scale(black_box(&img), target, strategy));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_scaling);
+criterion_main!(benches);
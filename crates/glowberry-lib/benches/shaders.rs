@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! CPU-side shader setup: preamble assembly and texture row padding.
+//!
+//! ```sh
+//! cargo bench -p glowberry-lib --bench shaders
+//! ```
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use glowberry_lib::{param_automation, shader_defs};
+use std::hint::black_box;
+
+/// An example shader with automated parameters that reads most optional uniforms.
+const SHADER: &str = r#"// [SHADER]
+// name: Bench
+//
+// [PARAMS]
+// speed: f32 = 0.5 | min: 0.2 | max: 1.0 | lfo: 2h
+// hue: f32 = 0.0 | min: 0.0 | max: 1.0 | pingpong: 45m
+// glow: f32 = 0.5 | keyframes: 06:00=0.2, 13:00=1.0, 21:00=0.1
+// [/PARAMS]
+
+const speed: f32 = 0.5;
+const hue: f32 = 0.0;
+const glow: f32 = 0.5;
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = fragCoord.xy / iResolution;
+    let t = iTime * speed + iLocalTime.w / 86400.0;
+    let warm = mix(0.0, 1.0, iWeather.x / 30.0) * iBattery.x;
+    let busy = iSystem.x;
+    return vec4<f32>(uv * glow, fract(hue + t + warm + busy), 1.0);
+}
+"#;
+
+/// Texture sizes; 1366 px rows need padding to wgpu's row alignment.
+const TEXTURES: &[(u32, u32)] = &[(1366, 768), (1920, 1080), (3840, 2160)];
+
+fn bench_preamble(c: &mut Criterion) {
+    let mut group = c.benchmark_group("shader-preamble");
+
+    group.bench_function("assemble", |b| {
+        b.iter(|| {
+            let code = black_box(SHADER);
+            let params = param_automation::parse(code);
+            let code = param_automation::apply(code, &params);
+            let preamble = shader_defs::fragment_preamble(&code, true, false, !params.is_empty());
+            format!("{preamble}\n{code}")
+        });
+    });
+
+    group.finish();
+}
+
+fn bench_texture_upload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("texture-upload");
+    group.sample_size(10);
+
+    for &(w, h) in TEXTURES {
+        let rgba = vec![0x80u8; w as usize * h as usize * 4];
+        group.throughput(Throughput::Bytes(rgba.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{w}x{h}")),
+            &rgba,
+            |b, rgba| {
+                b.iter(|| shader_defs::texture_upload_data(black_box(rgba), w, h));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_preamble, bench_texture_upload);
+criterion_main!(benches);
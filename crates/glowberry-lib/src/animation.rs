@@ -0,0 +1,285 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Playback of animated GIF, WebP, and PNG (APNG) wallpapers.
+//!
+//! Frames are decoded as they are first shown. The wallpaper shows one frame
+//! at a time as its image and a calloop timer moves to the next one once the
+//! frame's delay has passed, so playback goes through the same SHM and dmabuf
+//! drawing as any static image. Each frame is scaled to the outputs on the
+//! decoding workers of [`crate::predecode`] and drawn into the dmabuf the
+//! frame before last was, once the compositor released it. The decoded loop
+//! is kept in memory while it fits the cache size, after which the file is
+//! decoded again on every pass.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    iter,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use image::{
+    AnimationDecoder, DynamicImage, Frame, ImageFormat, ImageReader, ImageResult,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+};
+
+/// Delay used for frames that ask for less than [`MIN_DELAY`], like browsers
+/// do, since such files were made expecting it.
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Shortest frame delay honored as is.
+const MIN_DELAY: Duration = Duration::from_millis(20);
+
+type FrameIter = Box<dyn Iterator<Item = ImageResult<Frame>>>;
+
+#[derive(Clone)]
+pub struct AnimationFrame {
+    pub image: DynamicImage,
+    pub delay: Duration,
+}
+
+impl From<Frame> for AnimationFrame {
+    fn from(frame: Frame) -> Self {
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        Self {
+            delay: frame_delay(numer, denom),
+            image: DynamicImage::from(frame.into_buffer()),
+        }
+    }
+}
+
+/// An animated image being played, and as much of its loop as was decoded
+/// and fits the cache.
+pub struct Animation {
+    path: PathBuf,
+    // Frames not decoded yet in this pass, `None` once the loop is cached
+    decoder: Option<FrameIter>,
+    current: AnimationFrame,
+    // Position of `current` in the loop
+    index: usize,
+    cache: Vec<AnimationFrame>,
+    cache_bytes: u64,
+    cache_limit: u64,
+    // Cleared once the loop is found not to fit
+    caching: bool,
+}
+
+impl Animation {
+    /// Start playing `path` if it is an animated GIF, WebP, or PNG, keeping
+    /// up to `cache_limit` bytes of decoded frames. Returns `None` for other
+    /// files and for animations with a single frame, which are decoded like
+    /// any still image.
+    pub fn open(path: &Path, cache_limit: u64) -> Option<Self> {
+        let mut frames = decode_frames(path)?;
+        let first = next_frame(&mut frames, path)?;
+        // Only the second frame tells a GIF apart from a still one
+        let second = frames.next()?;
+        let frames: FrameIter = Box::new(iter::once(second).chain(frames));
+
+        Some(Self::new(path.to_path_buf(), frames, first, cache_limit))
+    }
+
+    fn new(path: PathBuf, decoder: FrameIter, first: AnimationFrame, cache_limit: u64) -> Self {
+        let mut animation = Self {
+            path,
+            decoder: Some(decoder),
+            current: first.clone(),
+            index: 0,
+            cache: Vec::new(),
+            cache_bytes: 0,
+            cache_limit,
+            caching: true,
+        };
+        animation.remember(first);
+        animation
+    }
+
+    /// The frame shown.
+    pub fn current(&self) -> &AnimationFrame {
+        &self.current
+    }
+
+    /// Bytes of decoded frames kept for the next pass.
+    pub fn cache_bytes(&self) -> u64 {
+        self.cache_bytes
+    }
+
+    /// Move to the next frame, starting over after the last one.
+    pub fn advance(&mut self) -> &AnimationFrame {
+        let Some(decoder) = self.decoder.as_mut() else {
+            self.index = (self.index + 1) % self.cache.len();
+            self.current = self.cache[self.index].clone();
+            return &self.current;
+        };
+
+        if let Some(frame) = next_frame(decoder, &self.path) {
+            self.index += 1;
+            self.current = frame.clone();
+            self.remember(frame);
+            return &self.current;
+        }
+
+        // End of the loop, which is fully cached unless it didn't fit
+        self.index = 0;
+        if self.caching {
+            self.decoder = None;
+            self.current = self.cache[0].clone();
+        } else {
+            match decode_frames(&self.path).and_then(|mut frames| {
+                let first = next_frame(&mut frames, &self.path)?;
+                Some((frames, first))
+            }) {
+                Some((frames, first)) => {
+                    self.decoder = Some(frames);
+                    self.current = first;
+                }
+                // Gone or broken since: keep showing the last frame
+                None => self.decoder = Some(Box::new(iter::empty())),
+            }
+        }
+        &self.current
+    }
+
+    /// Keep a newly decoded frame of the first pass, unless the loop
+    /// outgrows the cache, which is then dropped.
+    fn remember(&mut self, frame: AnimationFrame) {
+        if !self.caching || self.index != self.cache.len() {
+            return;
+        }
+        let bytes = frame.image.as_bytes().len() as u64;
+        if self.cache_bytes + bytes > self.cache_limit {
+            tracing::debug!(
+                path = %self.path.display(),
+                limit = self.cache_limit,
+                "animation exceeds its cache, decoding it on every loop"
+            );
+            self.caching = false;
+            self.cache = Vec::new();
+            self.cache_bytes = 0;
+            return;
+        }
+        self.cache_bytes += bytes;
+        self.cache.push(frame);
+    }
+}
+
+/// Frames of `path` if it is a GIF, an animated WebP, or an APNG.
+fn decode_frames(path: &Path) -> Option<FrameIter> {
+    let reader = ImageReader::open(path).ok()?.with_guessed_format().ok()?;
+    let format = reader.format()?;
+    let reader: BufReader<File> = reader.into_inner();
+
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader).ok()?.into_frames(),
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader).ok()?;
+            if !decoder.has_animation() {
+                return None;
+            }
+            decoder.into_frames()
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader).ok()?;
+            if !decoder.is_apng().ok()? {
+                return None;
+            }
+            decoder.apng().ok()?.into_frames()
+        }
+        _ => return None,
+    };
+    Some(Box::new(frames))
+}
+
+fn next_frame(frames: &mut FrameIter, path: &Path) -> Option<AnimationFrame> {
+    frames
+        .next()?
+        .inspect_err(|why| tracing::warn!(?why, "could not decode animation: {}", path.display()))
+        .ok()
+        .map(AnimationFrame::from)
+}
+
+/// How long to show a frame whose delay is `numer / denom` milliseconds.
+fn frame_delay(numer: u32, denom: u32) -> Duration {
+    let delay = Duration::from_secs_f64(f64::from(numer) / f64::from(denom.max(1)) / 1000.0);
+    if delay < MIN_DELAY {
+        DEFAULT_DELAY
+    } else {
+        delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Animation, AnimationFrame, DEFAULT_DELAY, FrameIter, frame_delay};
+    use image::{Delay, Frame, RgbaImage};
+    use std::{path::PathBuf, time::Duration};
+
+    fn frames(delays: &[u32]) -> FrameIter {
+        let frames = delays
+            .iter()
+            .map(|&ms| {
+                Ok(Frame::from_parts(
+                    RgbaImage::new(2, 2),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(ms, 1),
+                ))
+            })
+            .collect::<Vec<_>>();
+        Box::new(frames.into_iter())
+    }
+
+    fn animation(delays: &[u32], cache_limit: u64) -> Animation {
+        let mut decoder = frames(delays);
+        let first = AnimationFrame::from(decoder.next().unwrap().unwrap());
+        // Reopening this path fails, so only the cache can restart the loop
+        Animation::new(
+            PathBuf::from("/nonexistent.gif"),
+            decoder,
+            first,
+            cache_limit,
+        )
+    }
+
+    fn delay(frame: &AnimationFrame) -> u128 {
+        frame.delay.as_millis()
+    }
+
+    #[test]
+    fn honors_frame_delays_but_not_near_zero_ones() {
+        assert_eq!(frame_delay(40, 1), Duration::from_millis(40));
+        assert_eq!(frame_delay(100, 3), Duration::from_secs_f64(0.1 / 3.0));
+        assert_eq!(frame_delay(0, 1), DEFAULT_DELAY);
+        assert_eq!(frame_delay(10, 1), DEFAULT_DELAY);
+        assert_eq!(frame_delay(50, 0), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn loops_from_the_cache_once_decoded() {
+        let mut animation = animation(&[30, 60, 90], 1024);
+
+        assert_eq!(delay(animation.current()), 30);
+        assert_eq!(delay(animation.advance()), 60);
+        assert_eq!(delay(animation.advance()), 90);
+        assert_eq!(delay(animation.advance()), 30);
+        assert!(animation.decoder.is_none());
+        assert_eq!(animation.cache.len(), 3);
+        assert_eq!(delay(animation.advance()), 60);
+    }
+
+    #[test]
+    fn drops_a_loop_that_outgrows_the_cache() {
+        // Each 2x2 RGBA frame takes 16 bytes
+        let mut animation = animation(&[30, 60, 90], 40);
+
+        assert_eq!(delay(animation.advance()), 60);
+        assert_eq!(animation.cache.len(), 2);
+        assert_eq!(delay(animation.advance()), 90);
+        assert!(animation.cache.is_empty());
+
+        // The file can't be decoded again, so the last frame stays up
+        assert_eq!(delay(animation.advance()), 90);
+        assert_eq!(delay(animation.advance()), 90);
+    }
+}
@@ -0,0 +1,357 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Audio capture for music-reactive shader wallpapers.
+//!
+//! The default sink's monitor is captured through PipeWire on a dedicated
+//! thread. A second worker thread periodically runs an FFT over the most
+//! recent samples and publishes an [`AudioFrame`], which the renderer uploads
+//! to shaders as the `iAudio` texture:
+//! - row 0: log-scaled, smoothed spectrum magnitudes (0.0-1.0)
+//! - row 1: the latest waveform samples (-1.0-1.0)
+//!
+//! Capture is only available with the `audio` cargo feature; without it
+//! [`AudioMonitor::start`] returns `None` and shaders see silence.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of spectrum bins and waveform samples exposed to shaders.
+pub const AUDIO_BINS: usize = 512;
+
+/// A snapshot of the analysed audio signal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioFrame {
+    pub spectrum: [f32; AUDIO_BINS],
+    pub waveform: [f32; AUDIO_BINS],
+}
+
+impl Default for AudioFrame {
+    fn default() -> Self {
+        Self {
+            spectrum: [0.0; AUDIO_BINS],
+            waveform: [0.0; AUDIO_BINS],
+        }
+    }
+}
+
+/// Handle to the latest analysed audio frame.
+#[derive(Clone, Debug, Default)]
+pub struct AudioHandle {
+    frame: Arc<Mutex<AudioFrame>>,
+}
+
+impl AudioHandle {
+    /// Get the most recent audio frame.
+    pub fn current(&self) -> AudioFrame {
+        self.frame.lock().map(|f| f.clone()).unwrap_or_default()
+    }
+}
+
+/// Running audio capture. Capture and analysis stop when this is dropped.
+pub struct AudioMonitor {
+    handle: AudioHandle,
+    running: Arc<AtomicBool>,
+    #[cfg(feature = "audio")]
+    quit_tx: Option<pipewire::channel::Sender<()>>,
+}
+
+impl std::fmt::Debug for AudioMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioMonitor")
+            .field("running", &self.running.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl AudioMonitor {
+    /// Start capturing the default sink monitor.
+    ///
+    /// Returns `None` if the `audio` feature is disabled or PipeWire is unavailable.
+    pub fn start() -> Option<Self> {
+        #[cfg(feature = "audio")]
+        {
+            capture::start()
+        }
+
+        #[cfg(not(feature = "audio"))]
+        {
+            tracing::warn!(
+                "Audio-reactive shaders requested, but built without the `audio` feature"
+            );
+            None
+        }
+    }
+
+    /// Get a handle to the analysed audio frames.
+    pub fn handle(&self) -> AudioHandle {
+        self.handle.clone()
+    }
+}
+
+impl Drop for AudioMonitor {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        #[cfg(feature = "audio")]
+        if let Some(tx) = self.quit_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// Compute a log-scaled, smoothed spectrum from time-domain samples.
+///
+/// `previous` is decayed towards the new magnitudes so bars fall smoothly.
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+fn smooth_spectrum(magnitudes: &[f32], previous: &mut [f32; AUDIO_BINS]) {
+    const DECAY: f32 = 0.75;
+    // Magnitudes of full-scale sines land around 40 dB above this floor.
+    const FLOOR_DB: f32 = -60.0;
+    const RANGE_DB: f32 = 70.0;
+
+    for (bin, prev) in previous.iter_mut().enumerate() {
+        let magnitude = magnitudes.get(bin).copied().unwrap_or(0.0);
+        let db = 20.0 * magnitude.max(1e-9).log10();
+        let level = ((db - FLOOR_DB) / RANGE_DB).clamp(0.0, 1.0);
+        *prev = if level > *prev {
+            level
+        } else {
+            *prev * DECAY + level * (1.0 - DECAY)
+        };
+    }
+}
+
+#[cfg(feature = "audio")]
+mod capture {
+    use super::{AUDIO_BINS, AudioFrame, AudioHandle, AudioMonitor, smooth_spectrum};
+    use pipewire as pw;
+    use pw::{properties::properties, spa};
+    use realfft::RealFftPlanner;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const FFT_SIZE: usize = AUDIO_BINS * 2;
+    /// Analysis rate; comfortably above typical shader frame rates.
+    const ANALYSIS_INTERVAL: Duration = Duration::from_millis(16);
+
+    type SampleRing = Arc<Mutex<VecDeque<f32>>>;
+
+    pub(super) fn start() -> Option<AudioMonitor> {
+        let handle = AudioHandle::default();
+        let running = Arc::new(AtomicBool::new(true));
+        let samples: SampleRing = Arc::new(Mutex::new(VecDeque::with_capacity(FFT_SIZE)));
+        let (quit_tx, quit_rx) = pw::channel::channel::<()>();
+        let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel::<bool>(1);
+
+        let capture_samples = samples.clone();
+        std::thread::Builder::new()
+            .name("glowberry-audio".into())
+            .spawn(move || {
+                if let Err(err) = run_capture(capture_samples, quit_rx, &ready_tx) {
+                    tracing::error!(?err, "PipeWire audio capture failed");
+                    let _ = ready_tx.send(false);
+                }
+            })
+            .ok()?;
+
+        if !ready_rx.recv().unwrap_or(false) {
+            return None;
+        }
+
+        let analysis_handle = handle.clone();
+        let analysis_running = running.clone();
+        std::thread::Builder::new()
+            .name("glowberry-audio-fft".into())
+            .spawn(move || run_analysis(samples, analysis_handle, analysis_running))
+            .ok()?;
+
+        tracing::info!("Audio capture started");
+
+        Some(AudioMonitor {
+            handle,
+            running,
+            quit_tx: Some(quit_tx),
+        })
+    }
+
+    fn run_capture(
+        samples: SampleRing,
+        quit_rx: pw::channel::Receiver<()>,
+        ready_tx: &std::sync::mpsc::SyncSender<bool>,
+    ) -> Result<(), pw::Error> {
+        pw::init();
+
+        let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+        let context = pw::context::ContextRc::new(&mainloop, None)?;
+        let core = context.connect_rc(None)?;
+
+        let quit_loop = mainloop.clone();
+        let _quit = quit_rx.attach(mainloop.loop_(), move |()| quit_loop.quit());
+
+        let mut props = properties! {
+            *pw::keys::MEDIA_TYPE => "Audio",
+            *pw::keys::MEDIA_CATEGORY => "Capture",
+            *pw::keys::MEDIA_ROLE => "Music",
+        };
+        // Capture what is being played, not the microphone.
+        props.insert(*pw::keys::STREAM_CAPTURE_SINK, "true");
+
+        let stream = pw::stream::StreamBox::new(&core, "glowberry-audio", props)?;
+
+        let _listener = stream
+            .add_local_listener_with_user_data(spa::param::audio::AudioInfoRaw::default())
+            .param_changed(|_, format, id, param| {
+                let Some(param) = param else {
+                    return;
+                };
+                if id == spa::param::ParamType::Format.as_raw() {
+                    let _ = format.parse(param);
+                }
+            })
+            .process(move |stream, format| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                let datas = buffer.datas_mut();
+                let Some(data) = datas.first_mut() else {
+                    return;
+                };
+
+                let channels = format.channels().max(1) as usize;
+                let size = data.chunk().size() as usize;
+                let Some(bytes) = data.data() else {
+                    return;
+                };
+                let bytes = &bytes[..size.min(bytes.len())];
+
+                let Ok(mut ring) = samples.lock() else {
+                    return;
+                };
+                // Downmix interleaved f32 frames to mono.
+                for frame in bytes.chunks_exact(4 * channels) {
+                    let sum: f32 = frame
+                        .chunks_exact(4)
+                        .map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+                        .sum();
+                    if ring.len() == FFT_SIZE {
+                        ring.pop_front();
+                    }
+                    ring.push_back(sum / channels as f32);
+                }
+            })
+            .register()?;
+
+        let mut audio_info = spa::param::audio::AudioInfoRaw::new();
+        audio_info.set_format(spa::param::audio::AudioFormat::F32LE);
+        let obj = spa::pod::Object {
+            type_: spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
+            id: spa::param::ParamType::EnumFormat.as_raw(),
+            properties: audio_info.into(),
+        };
+        let values: Vec<u8> = spa::pod::serialize::PodSerializer::serialize(
+            std::io::Cursor::new(Vec::new()),
+            &spa::pod::Value::Object(obj),
+        )
+        .map_err(|_| pw::Error::CreationFailed)?
+        .0
+        .into_inner();
+        let mut params = [spa::pod::Pod::from_bytes(&values).ok_or(pw::Error::CreationFailed)?];
+
+        stream.connect(
+            spa::utils::Direction::Input,
+            None,
+            pw::stream::StreamFlags::AUTOCONNECT
+                | pw::stream::StreamFlags::MAP_BUFFERS
+                | pw::stream::StreamFlags::RT_PROCESS,
+            &mut params,
+        )?;
+
+        let _ = ready_tx.send(true);
+        mainloop.run();
+        tracing::debug!("Audio capture loop exited");
+
+        Ok(())
+    }
+
+    fn run_analysis(samples: SampleRing, handle: AudioHandle, running: Arc<AtomicBool>) {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(FFT_SIZE);
+        let mut input = fft.make_input_vec();
+        let mut output = fft.make_output_vec();
+        let mut magnitudes = vec![0.0f32; output.len()];
+        let mut frame = AudioFrame::default();
+
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| {
+                let t = i as f32 / (FFT_SIZE - 1) as f32;
+                0.5 - 0.5 * (std::f32::consts::TAU * t).cos()
+            })
+            .collect();
+
+        while running.load(Ordering::Relaxed) {
+            std::thread::sleep(ANALYSIS_INTERVAL);
+
+            {
+                let Ok(ring) = samples.lock() else {
+                    break;
+                };
+                let offset = FFT_SIZE - ring.len();
+                input[..offset].fill(0.0);
+                for (dst, src) in input[offset..].iter_mut().zip(ring.iter()) {
+                    *dst = *src;
+                }
+            }
+
+            for (dst, src) in frame
+                .waveform
+                .iter_mut()
+                .zip(&input[FFT_SIZE - AUDIO_BINS..])
+            {
+                *dst = *src;
+            }
+
+            for (sample, w) in input.iter_mut().zip(&window) {
+                *sample *= w;
+            }
+
+            if fft.process(&mut input, &mut output).is_err() {
+                continue;
+            }
+
+            let norm = 2.0 / FFT_SIZE as f32;
+            for (mag, c) in magnitudes.iter_mut().zip(&output) {
+                *mag = c.norm() * norm;
+            }
+            smooth_spectrum(&magnitudes, &mut frame.spectrum);
+
+            if let Ok(mut shared) = handle.frame.lock() {
+                shared.clone_from(&frame);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence_maps_to_zero_spectrum() {
+        let mut spectrum = [0.5; AUDIO_BINS];
+        for _ in 0..64 {
+            smooth_spectrum(&[0.0; AUDIO_BINS], &mut spectrum);
+        }
+        assert!(spectrum.iter().all(|v| *v < 1e-3));
+    }
+
+    #[test]
+    fn peaks_rise_immediately() {
+        let mut spectrum = [0.0; AUDIO_BINS];
+        let mut magnitudes = [0.0; AUDIO_BINS];
+        magnitudes[10] = 1.0;
+        smooth_spectrum(&magnitudes, &mut spectrum);
+        assert!(spectrum[10] > 0.5);
+        assert_eq!(spectrum[11], 0.0);
+    }
+}
@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Blurred placeholders of images seen before, shown while they decode.
+//!
+//! Each image decoded is summarized as a [BlurHash] of a few color
+//! components and cached on disk, keyed by its path, size, and modification
+//! time. When the image is shown again, its placeholder is drawn at once and
+//! the image is decoded on a worker thread, replacing the placeholder when
+//! done, so slow storage shows a blur of the image rather than the previous
+//! wallpaper or nothing at all.
+//!
+//! [BlurHash]: https://blurha.sh
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    f32::consts::PI,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Color components across and down the image.
+const COMPONENTS: (u32, u32) = (4, 3);
+
+/// Size the image is reduced to before it is summarized.
+const SAMPLE: u32 = 64;
+
+/// Width a placeholder is decoded at, before it is scaled like the image.
+const PLACEHOLDER_WIDTH: u32 = 32;
+
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Where placeholders are cached.
+fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("glowberry")
+        .join("blurhash")
+}
+
+/// Where the placeholder of the image at `path` is cached, changing with the
+/// file.
+fn cache_path(path: &Path) -> Option<PathBuf> {
+    let metadata = fs::metadata(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified().ok()?.hash(&mut hasher);
+    Some(cache_dir().join(format!("{:016x}", hasher.finish())))
+}
+
+/// Cache a placeholder of `image`, decoded from `path`, unless there is one.
+/// Images with transparency get none, as it would hide what shows through.
+pub fn remember(path: &Path, image: &DynamicImage) {
+    if image.color().has_alpha() {
+        return;
+    }
+    let Some(cached) = cache_path(path) else {
+        return;
+    };
+    if cached.exists() {
+        return;
+    }
+
+    let hash = encode(&image.thumbnail(SAMPLE, SAMPLE).to_rgb8());
+    let written = fs::create_dir_all(cache_dir()).and_then(|()| {
+        fs::write(
+            &cached,
+            format!("{} {} {hash}", image.width(), image.height()),
+        )
+    });
+    if let Err(why) = written {
+        tracing::debug!(?why, "could not cache placeholder of {}", path.display());
+    }
+}
+
+/// The cached placeholder of the image at `path`, in the image's aspect
+/// ratio but a fraction of its size.
+pub fn placeholder(path: &Path) -> Option<DynamicImage> {
+    let cached = fs::read_to_string(cache_path(path)?).ok()?;
+    let mut fields = cached.split_whitespace();
+    let width: u32 = fields.next()?.parse().ok()?;
+    let height: u32 = fields.next()?.parse().ok()?;
+    let hash = fields.next()?;
+    if width == 0 {
+        return None;
+    }
+
+    let placeholder_height =
+        (u64::from(PLACEHOLDER_WIDTH) * u64::from(height) / u64::from(width)).clamp(1, 128);
+    decode(hash, PLACEHOLDER_WIDTH, placeholder_height as u32).map(DynamicImage::ImageRgb8)
+}
+
+/// Summarize `image` as a BlurHash.
+fn encode(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let (across, down) = COMPONENTS;
+    let scale = 1.0 / (width * height).max(1) as f32;
+
+    let mut factors = Vec::with_capacity((across * down) as usize);
+    for j in 0..down {
+        for i in 0..across {
+            let normalization = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0; 3];
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let basis = normalization
+                    * (PI * i as f32 * x as f32 / width as f32).cos()
+                    * (PI * j as f32 * y as f32 / height as f32).cos();
+                for (sum, channel) in factor.iter_mut().zip(pixel.0) {
+                    *sum += basis * srgb_to_linear(channel);
+                }
+            }
+            factors.push(factor.map(|sum| sum * scale));
+        }
+    }
+
+    let mut hash = String::new();
+    push_base83(&mut hash, (across - 1) + (down - 1) * 9, 1);
+
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let largest = ac.iter().flatten().fold(0.0_f32, |max, c| max.max(c.abs()));
+    let maximum = if ac.is_empty() {
+        push_base83(&mut hash, 0, 1);
+        1.0
+    } else {
+        let quantized = ((largest * 166.0 - 0.5).floor() as i32).clamp(0, 82) as u32;
+        push_base83(&mut hash, quantized, 1);
+        (quantized + 1) as f32 / 166.0
+    };
+
+    let [r, g, b] = dc.map(linear_to_srgb);
+    push_base83(&mut hash, (r << 16) | (g << 8) | b, 4);
+    for factor in ac {
+        let [r, g, b] = factor
+            .map(|c| ((sign_pow(c / maximum, 0.5) * 9.0 + 9.5).floor() as i32).clamp(0, 18) as u32);
+        push_base83(&mut hash, r * 19 * 19 + g * 19 + b, 2);
+    }
+    hash
+}
+
+/// Draw the BlurHash `hash` at `width` by `height`.
+fn decode(hash: &str, width: u32, height: u32) -> Option<RgbImage> {
+    if !hash.is_ascii() {
+        return None;
+    }
+    let size = read_base83(hash.get(..1)?)?;
+    let (across, down) = (size % 9 + 1, size / 9 + 1);
+    if hash.len() != 4 + 2 * (across * down) as usize {
+        return None;
+    }
+    let maximum = (read_base83(&hash[1..2])? + 1) as f32 / 166.0;
+
+    let dc = read_base83(&hash[2..6])?;
+    let mut colors = vec![[dc >> 16, (dc >> 8) & 255, dc & 255].map(|c| srgb_to_linear(c as u8))];
+    for index in 1..(across * down) as usize {
+        let value = read_base83(&hash[4 + index * 2..6 + index * 2])?;
+        colors.push(
+            [value / (19 * 19), (value / 19) % 19, value % 19]
+                .map(|q| sign_pow((q as f32 - 9.0) / 9.0, 2.0) * maximum),
+        );
+    }
+
+    Some(RgbImage::from_fn(width, height, |x, y| {
+        let mut pixel = [0.0; 3];
+        for j in 0..down {
+            for i in 0..across {
+                let basis = (PI * x as f32 * i as f32 / width as f32).cos()
+                    * (PI * y as f32 * j as f32 / height as f32).cos();
+                let color = colors[(j * across + i) as usize];
+                for (sum, channel) in pixel.iter_mut().zip(color) {
+                    *sum += channel * basis;
+                }
+            }
+        }
+        Rgb(pixel.map(|c| linear_to_srgb(c) as u8))
+    }))
+}
+
+fn push_base83(hash: &mut String, value: u32, digits: u32) {
+    for digit in (0..digits).rev() {
+        hash.push(BASE83[(value / 83_u32.pow(digit) % 83) as usize] as char);
+    }
+}
+
+fn read_base83(digits: &str) -> Option<u32> {
+    digits.bytes().try_fold(0, |value, digit| {
+        let digit = BASE83.iter().position(|&c| c == digit)?;
+        Some(value * 83 + digit as u32)
+    })
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = f32::from(value) / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u32 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0 + 0.5) as u32
+}
+
+fn sign_pow(value: f32, exponent: f32) -> f32 {
+    value.abs().powf(exponent).copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_colors() {
+        let image = RgbImage::from_fn(40, 20, |x, _| {
+            if x < 20 {
+                Rgb([200, 40, 40])
+            } else {
+                Rgb([40, 40, 200])
+            }
+        });
+        let hash = encode(&image);
+        assert_eq!(hash.len(), 4 + 2 * 12);
+
+        let blurred = decode(&hash, 40, 20).unwrap();
+        let left = blurred.get_pixel(2, 10);
+        let right = blurred.get_pixel(37, 10);
+        assert!(left[0] > left[2] && right[2] > right[0]);
+
+        assert!(decode("not a hash", 4, 4).is_none());
+    }
+}
@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Schedule rules picking an entry's source by the day of the year.
+//!
+//! Rules are checked when the wallpaper is created and again just after
+//! each local midnight; the first rule applying that day supplies the
+//! source. A rule's source can itself follow the time of day, such as a
+//! day and night pair or a dynamic wallpaper.
+
+use std::time::Duration;
+
+use glowberry_config::{DateRule, Holiday, ScheduleRule, Season};
+use jiff::{Zoned, civil::Date};
+
+use crate::dynamic;
+
+/// Time until the rules are checked again when the next midnight can't be
+/// worked out.
+const RECHECK: Duration = Duration::from_secs(60 * 60);
+
+/// Index of the first of `rules` applying on `date`.
+pub fn applying(rules: &[ScheduleRule], date: Date) -> Option<usize> {
+    // The location is only looked up for seasons, which it may flip
+    let southern = rules
+        .iter()
+        .any(|rule| matches!(rule.when, DateRule::Season(_)))
+        && dynamic::location().0 < 0.0;
+    rules
+        .iter()
+        .position(|rule| applies(rule.when, date, southern))
+}
+
+/// Whether `rule` applies on `date`, south of the equator with `southern`.
+pub fn applies(rule: DateRule, date: Date, southern: bool) -> bool {
+    let day = (date.month() as u8, date.day() as u8);
+    match rule {
+        DateRule::Months(first, last) => within((first, 1), (last, 31), day),
+        DateRule::Days(first, last) => within(first, last, day),
+        DateRule::Season(season) => {
+            let (first, last) = season_months(season, southern);
+            within((first, 1), (last, 31), day)
+        }
+        DateRule::Holiday(Holiday::NewYear) => within((12, 31), (1, 1), day),
+        DateRule::Holiday(Holiday::Halloween) => day == (10, 31),
+        DateRule::Holiday(Holiday::Christmas) => within((12, 24), (12, 26), day),
+        DateRule::Holiday(Holiday::Easter) => easter(date.year()).is_some_and(|sunday| {
+            let from = sunday.yesterday().and_then(|saturday| saturday.yesterday());
+            from.is_ok_and(|friday| friday <= date)
+                && sunday.tomorrow().is_ok_and(|monday| date <= monday)
+        }),
+    }
+}
+
+/// How long after `now` the rules are due to be checked: just past the
+/// next local midnight.
+pub fn until_tomorrow(now: &Zoned) -> Duration {
+    now.date()
+        .tomorrow()
+        .and_then(|tomorrow| tomorrow.to_zoned(now.time_zone().clone()))
+        .ok()
+        .and_then(|midnight| Duration::try_from(now.duration_until(&midnight)).ok())
+        .map_or(RECHECK, |until| until + Duration::from_secs(1))
+}
+
+/// Whether `day` falls from `first` to `last`, all as `(month, day)`,
+/// wrapping around the new year when `last` comes before `first`.
+fn within(first: (u8, u8), last: (u8, u8), day: (u8, u8)) -> bool {
+    if first <= last {
+        first <= day && day <= last
+    } else {
+        first <= day || day <= last
+    }
+}
+
+/// First and last month of `season`.
+fn season_months(season: Season, southern: bool) -> (u8, u8) {
+    let (first, last) = match season {
+        Season::Spring => (3, 5),
+        Season::Summer => (6, 8),
+        Season::Autumn => (9, 11),
+        Season::Winter => (12, 2),
+    };
+    if southern {
+        ((first + 5) % 12 + 1, (last + 5) % 12 + 1)
+    } else {
+        (first, last)
+    }
+}
+
+/// Easter Sunday of `year` in the Gregorian calendar, by the anonymous
+/// Gregorian algorithm.
+fn easter(year: i16) -> Option<Date> {
+    let y = i32::from(year);
+    let a = y % 19;
+    let b = y / 100;
+    let c = y % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Date::new(year, month as i8, day as i8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i16, month: i8, day: i8) -> Date {
+        Date::new(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn matches_months_seasons_and_holidays() {
+        let winter = DateRule::Months(12, 2);
+        assert!(applies(winter, date(2025, 12, 1), false));
+        assert!(applies(winter, date(2026, 2, 28), false));
+        assert!(!applies(winter, date(2026, 3, 1), false));
+
+        let summer = DateRule::Season(Season::Summer);
+        assert!(applies(summer, date(2025, 7, 15), false));
+        assert!(!applies(summer, date(2025, 7, 15), true));
+        assert!(applies(summer, date(2026, 1, 15), true));
+
+        let holidays = DateRule::Days((12, 20), (1, 6));
+        assert!(applies(holidays, date(2025, 12, 31), false));
+        assert!(applies(holidays, date(2026, 1, 6), false));
+        assert!(!applies(holidays, date(2026, 1, 7), false));
+
+        let christmas = DateRule::Holiday(Holiday::Christmas);
+        assert!(applies(christmas, date(2025, 12, 25), false));
+        assert!(!applies(christmas, date(2025, 12, 27), false));
+
+        assert_eq!(easter(2025), Some(date(2025, 4, 20)));
+        assert_eq!(easter(2026), Some(date(2026, 4, 5)));
+        let easter = DateRule::Holiday(Holiday::Easter);
+        assert!(applies(easter, date(2026, 4, 3), false));
+        assert!(applies(easter, date(2026, 4, 6), false));
+        assert!(!applies(easter, date(2026, 4, 7), false));
+    }
+}
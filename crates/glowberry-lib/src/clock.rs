@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Time-of-day text for clock shaders.
+//!
+//! Shaders that reference `iClock` get the local time as a small texture,
+//! e.g. `14:05` or `2:05 PM`, drawn in the bitmap font of the identify
+//! overlays. The text is white and centered, with its coverage in the alpha
+//! channel, so shaders can tint, blur, or distort it. The texture is redrawn
+//! when the minute changes.
+//!
+//! 24-hour time follows the COSMIC panel clock's setting, or the locale's
+//! convention when that isn't set.
+
+use cosmic_config::ConfigGet;
+
+use crate::identify::{GLYPH_HEIGHT, GLYPH_WIDTH, draw_text};
+use crate::shader_defs::texture_upload_data;
+
+/// Longest time string, `12:59 PM`; shorter ones are centered.
+const CHARS: u32 = 8;
+/// Texture pixels per font pixel.
+const SCALE: u32 = 4;
+/// Transparent margin around the text, in font pixels.
+const PADDING: u32 = 1;
+
+/// The COSMIC panel clock's config and its 24-hour key.
+const APPLET_TIME_CONFIG: &str = "com.system76.CosmicAppletTime";
+const MILITARY_TIME: &str = "military_time";
+
+/// Regions that write the time of day with AM/PM.
+const TWELVE_HOUR_REGIONS: &[&str] = &["AU", "BD", "CA", "EG", "IN", "NZ", "PH", "PK", "SA", "US"];
+
+/// Size of the `iClock` texture, in pixels.
+pub fn texture_size() -> (u32, u32) {
+    (
+        (CHARS * (GLYPH_WIDTH + 1) - 1 + 2 * PADDING) * SCALE,
+        (GLYPH_HEIGHT + 2 * PADDING) * SCALE,
+    )
+}
+
+/// Format a time of day as shown on the clock.
+pub fn format_time(hour: i8, minute: i8, twenty_four_hour: bool) -> String {
+    if twenty_four_hour {
+        return format!("{hour:02}:{minute:02}");
+    }
+
+    let suffix = if hour < 12 { "AM" } else { "PM" };
+    let hour = (hour + 11) % 12 + 1;
+    format!("{hour}:{minute:02} {suffix}")
+}
+
+/// Whether the clock shows 24-hour time.
+pub fn uses_24_hour() -> bool {
+    cosmic_config::Config::new(APPLET_TIME_CONFIG, 1)
+        .ok()
+        .and_then(|config| config.get::<bool>(MILITARY_TIME).ok())
+        .unwrap_or_else(|| {
+            let locale = ["LC_ALL", "LC_TIME", "LANG"]
+                .into_iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|value| !value.is_empty())
+                .unwrap_or_default();
+            locale_uses_24_hour(&locale)
+        })
+}
+
+/// Whether `locale`, e.g. `en_US.UTF-8`, writes the time with 24 hours.
+fn locale_uses_24_hour(locale: &str) -> bool {
+    let name = locale.split(['.', '@']).next().unwrap_or_default();
+    let Some((language, region)) = name.split_once('_') else {
+        // `C`, `POSIX`, or no locale at all
+        return true;
+    };
+
+    // Canadian French uses 24-hour time
+    !TWELVE_HOUR_REGIONS.contains(&region) || (region == "CA" && language == "fr")
+}
+
+/// Draw `text` centered on a transparent RGBA canvas of [`texture_size`].
+pub fn draw(text: &str) -> Vec<u8> {
+    const WHITE: [u8; 4] = [0xff; 4];
+
+    let (width, height) = texture_size();
+    let mut canvas = [0xff, 0xff, 0xff, 0x00].repeat(width as usize * height as usize);
+
+    let text: String = text.chars().take(CHARS as usize).collect();
+    let chars = text.chars().count() as u32;
+    let text_width = (chars * (GLYPH_WIDTH + 1)).saturating_sub(1);
+    let left = PADDING + (CHARS * (GLYPH_WIDTH + 1) - 1 - text_width) / 2;
+    draw_text(
+        &mut canvas,
+        width,
+        (left * SCALE, PADDING * SCALE),
+        SCALE,
+        &text,
+        WHITE,
+    );
+    canvas
+}
+
+/// The `iClock` texture and the minute it shows.
+#[derive(Debug)]
+pub struct ClockTexture {
+    pub(crate) texture: wgpu::Texture,
+    pub(crate) sampler: wgpu::Sampler,
+    shown: Option<(i8, i8)>,
+}
+
+impl ClockTexture {
+    /// Create the texture; it stays blank until the first [`Self::update`].
+    pub fn new(device: &wgpu::Device) -> Self {
+        let (width, height) = texture_size();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glowberry: iClock texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("glowberry: iClock sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            sampler,
+            shown: None,
+        }
+    }
+
+    /// Redraw the text if the minute changed since the last update.
+    pub fn update(&mut self, queue: &wgpu::Queue, now: &jiff::Zoned) {
+        let minute = (now.hour(), now.minute());
+        if self.shown == Some(minute) {
+            return;
+        }
+        self.shown = Some(minute);
+
+        let text = format_time(minute.0, minute.1, uses_24_hour());
+        let (width, height) = texture_size();
+        let rgba = draw(&text);
+        let (data, bytes_per_row, rows_per_image) = texture_upload_data(&rgba, width, height);
+        queue.write_texture(
+            self.texture.as_image_copy(),
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(rows_per_image),
+            },
+            self.texture.size(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_twelve_and_twenty_four_hour_time() {
+        assert_eq!(format_time(14, 5, true), "14:05");
+        assert_eq!(format_time(7, 30, true), "07:30");
+        assert_eq!(format_time(14, 5, false), "2:05 PM");
+        assert_eq!(format_time(0, 0, false), "12:00 AM");
+        assert_eq!(format_time(12, 59, false), "12:59 PM");
+    }
+
+    #[test]
+    fn reads_the_hour_cycle_from_the_locale() {
+        assert!(!locale_uses_24_hour("en_US.UTF-8"));
+        assert!(!locale_uses_24_hour("en_CA"));
+        assert!(locale_uses_24_hour("fr_CA.UTF-8"));
+        assert!(locale_uses_24_hour("de_DE.UTF-8@euro"));
+        assert!(locale_uses_24_hour("C.UTF-8"));
+        assert!(locale_uses_24_hour(""));
+    }
+
+    #[test]
+    fn centers_the_text_on_a_transparent_canvas() {
+        let (width, height) = texture_size();
+        let canvas = draw("1:00 AM");
+        assert_eq!(canvas.len(), (width * height * 4) as usize);
+
+        let alpha = |x: u32, y: u32| canvas[((y * width + x) * 4 + 3) as usize];
+        let covered: Vec<u32> = (0..width)
+            .filter(|&x| (0..height).any(|y| alpha(x, y) > 0))
+            .collect();
+        let (first, last) = (covered[0], covered[covered.len() - 1]);
+        assert!(first.abs_diff(width - 1 - last) <= SCALE, "{first}..{last}");
+        assert_eq!(alpha(0, 0), 0);
+    }
+}
@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! HDR shader output through `wp_color_manager_v1`.
+//!
+//! Shaders that declare `// output: hdr` write linear light in sRGB
+//! primaries, where 1.0 is SDR reference white and highlights go above it.
+//! When the compositor accepts parametric image descriptions with the
+//! extended linear transfer function and an extended target volume, their
+//! layers get a half-float swapchain and an image description that maps 1.0
+//! to [`REFERENCE_WHITE`] and allows highlights up to [`PEAK_LUMINANCE`].
+//! Elsewhere they render like `output: linear` shaders, clipped to SDR.
+//!
+//! HDR output is for shaders only. Still images, PQ and HLG encoded ones
+//! included, are drawn into SDR buffers by [`crate::draw`]; uploading them
+//! in extended range is left to a request of its own.
+
+use sctk::reexports::{
+    client::{QueueHandle, WEnum, protocol::wl_surface},
+    protocols::wp::color_management::v1::client::{
+        wp_color_management_surface_v1, wp_color_manager_v1, wp_image_description_v1,
+    },
+};
+
+use crate::engine::GlowBerry;
+
+use wp_color_manager_v1::{Feature, Primaries, RenderIntent, TransferFunction};
+
+/// Luminance of an output value of 1.0, SDR reference white, in cd/m²
+/// (ITU-R BT.2408).
+pub const REFERENCE_WHITE: u32 = 203;
+/// Brightest highlight HDR shaders are mastered for, in cd/m², reached at
+/// an output value of about 4.9.
+pub const PEAK_LUMINANCE: u32 = 1000;
+/// Black level, in 0.0001 cd/m².
+const MIN_LUMINANCE: u32 = 50;
+
+/// Features the HDR image description is built with.
+const REQUIRED_FEATURES: [Feature; 4] = [
+    Feature::Parametric,
+    Feature::SetLuminances,
+    Feature::SetMasteringDisplayPrimaries,
+    Feature::ExtendedTargetVolume,
+];
+
+/// What the compositor advertised when the manager was bound.
+#[derive(Debug, Default)]
+struct Support {
+    features: Vec<Feature>,
+    transfer_functions: Vec<TransferFunction>,
+    primaries: Vec<Primaries>,
+    intents: Vec<RenderIntent>,
+}
+
+impl Support {
+    /// Whether the scRGB-like HDR image description can be created.
+    fn hdr(&self) -> bool {
+        REQUIRED_FEATURES
+            .iter()
+            .all(|feature| self.features.contains(feature))
+            && self
+                .transfer_functions
+                .contains(&TransferFunction::ExtLinear)
+            && self.primaries.contains(&Primaries::Srgb)
+            && self.intents.contains(&RenderIntent::Perceptual)
+    }
+}
+
+/// The compositor's color manager and the HDR image description made with it.
+#[derive(Debug)]
+pub struct ColorManager {
+    manager: wp_color_manager_v1::WpColorManagerV1,
+    support: Support,
+    hdr: Option<wp_image_description_v1::WpImageDescriptionV1>,
+    hdr_ready: bool,
+}
+
+/// A surface's color management object, reverting the surface to the
+/// compositor's default colors when dropped.
+#[derive(Debug)]
+pub struct SurfaceColor(wp_color_management_surface_v1::WpColorManagementSurfaceV1);
+
+impl Drop for SurfaceColor {
+    fn drop(&mut self) {
+        self.0.destroy();
+    }
+}
+
+impl ColorManager {
+    pub fn new(manager: wp_color_manager_v1::WpColorManagerV1) -> Self {
+        Self {
+            manager,
+            support: Support::default(),
+            hdr: None,
+            hdr_ready: false,
+        }
+    }
+
+    /// Record an advertised feature, or create the HDR image description
+    /// once all were sent.
+    pub fn on_event(&mut self, event: wp_color_manager_v1::Event, qh: &QueueHandle<GlowBerry>) {
+        use wp_color_manager_v1::Event;
+
+        let support = &mut self.support;
+        match event {
+            Event::SupportedIntent {
+                render_intent: WEnum::Value(intent),
+            } => support.intents.push(intent),
+            Event::SupportedFeature {
+                feature: WEnum::Value(feature),
+            } => support.features.push(feature),
+            Event::SupportedTfNamed {
+                tf: WEnum::Value(tf),
+            } => support.transfer_functions.push(tf),
+            Event::SupportedPrimariesNamed {
+                primaries: WEnum::Value(primaries),
+            } => support.primaries.push(primaries),
+            Event::Done => {
+                if self.hdr.is_some() {
+                    return;
+                }
+                if !support.hdr() {
+                    tracing::info!(?support, "Compositor has no usable HDR image descriptions");
+                    return;
+                }
+                self.hdr = Some(self.create_hdr_description(qh));
+            }
+            _ => {}
+        }
+    }
+
+    fn create_hdr_description(
+        &self,
+        qh: &QueueHandle<GlowBerry>,
+    ) -> wp_image_description_v1::WpImageDescriptionV1 {
+        let params = self.manager.create_parametric_creator(qh, ());
+        params.set_tf_named(TransferFunction::ExtLinear);
+        params.set_primaries_named(Primaries::Srgb);
+        // 1.0 is reference white; brighter values extend past the primary
+        // volume into the mastering range
+        params.set_luminances(MIN_LUMINANCE, REFERENCE_WHITE, REFERENCE_WHITE);
+        params.set_mastering_luminance(MIN_LUMINANCE, PEAK_LUMINANCE);
+        params.set_max_cll(PEAK_LUMINANCE);
+        params.create(qh, ())
+    }
+
+    /// The HDR image description became usable.
+    pub fn on_hdr_ready(&mut self) {
+        tracing::info!(
+            reference_white = REFERENCE_WHITE,
+            peak = PEAK_LUMINANCE,
+            "HDR output available for shaders"
+        );
+        self.hdr_ready = true;
+    }
+
+    /// The compositor rejected the HDR image description.
+    pub fn on_hdr_failed(&mut self, cause: WEnum<wp_image_description_v1::Cause>, msg: &str) {
+        tracing::warn!(
+            ?cause,
+            msg,
+            "HDR image description failed, shaders stay SDR"
+        );
+        if let Some(description) = self.hdr.take() {
+            description.destroy();
+        }
+    }
+
+    /// Whether HDR shaders can be given HDR surfaces.
+    pub fn hdr_ready(&self) -> bool {
+        self.hdr_ready
+    }
+
+    /// Mark `surface` as showing HDR content, until the returned object is
+    /// dropped. Returns `None` while the HDR image description isn't ready.
+    pub fn hdr_surface(
+        &self,
+        surface: &wl_surface::WlSurface,
+        qh: &QueueHandle<GlowBerry>,
+    ) -> Option<SurfaceColor> {
+        let description = self.hdr.as_ref().filter(|_| self.hdr_ready)?;
+        let color = self.manager.get_surface(surface, qh, ());
+        color.set_image_description(description, RenderIntent::Perceptual);
+        Some(SurfaceColor(color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hdr_needs_every_required_capability() {
+        let mut support = Support {
+            features: REQUIRED_FEATURES.to_vec(),
+            transfer_functions: vec![TransferFunction::St2084Pq, TransferFunction::ExtLinear],
+            primaries: vec![Primaries::Srgb, Primaries::Bt2020],
+            intents: vec![RenderIntent::Perceptual],
+        };
+        assert!(support.hdr());
+
+        support
+            .features
+            .retain(|f| *f != Feature::ExtendedTargetVolume);
+        assert!(!support.hdr());
+        support.features.push(Feature::ExtendedTargetVolume);
+
+        support
+            .transfer_functions
+            .retain(|tf| *tf != TransferFunction::ExtLinear);
+        assert!(!support.hdr());
+    }
+}
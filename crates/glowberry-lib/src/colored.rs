@@ -1,7 +1,9 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use std::f32::consts::TAU;
+
 use colorgrad::{Color, Gradient as ColorGradient};
-use glowberry_config::Gradient;
+use glowberry_config::{Gradient, Pattern, Procedural};
 use image::Rgb32FImage;
 
 /// Generate a background image from a color.
@@ -19,6 +21,23 @@ pub fn single(color: [f32; 3], width: u32, height: u32) -> Rgb32FImage {
     imgbuf
 }
 
+/// Generate the placeholder shown when a wallpaper source has no images: a
+/// magenta and black checkerboard, the familiar "missing texture" pattern,
+/// with about twelve rows of tiles on any output.
+pub fn placeholder(width: u32, height: u32) -> Rgb32FImage {
+    const MAGENTA: image::Rgb<f32> = image::Rgb([1.0, 0.0, 1.0]);
+    const BLACK: image::Rgb<f32> = image::Rgb([0.02, 0.02, 0.02]);
+
+    let tile = (height / 12).max(1);
+    Rgb32FImage::from_fn(width, height, |x, y| {
+        if (x / tile + y / tile) % 2 == 0 {
+            MAGENTA
+        } else {
+            BLACK
+        }
+    })
+}
+
 /// Generate a background image from a gradient.
 pub fn gradient(
     gradient: &Gradient,
@@ -80,9 +99,207 @@ pub fn gradient(
     Ok(imgbuf)
 }
 
+/// Smooth patterns are computed at this fraction of the output size, and
+/// scaled up.
+const SMOOTH_STEP: u32 = 4;
+
+/// Generate a background image from a procedural pattern.
+pub fn procedural(procedural: &Procedural, width: u32, height: u32) -> Rgb32FImage {
+    let palette = if procedural.palette.is_empty() {
+        default_palette(procedural.pattern)
+    } else {
+        &procedural.palette
+    };
+    let seed = procedural.seed;
+
+    let field: fn(f32, f32, u64) -> f32 = match procedural.pattern {
+        Pattern::Plasma => plasma,
+        Pattern::Clouds => clouds,
+        Pattern::Aurora => aurora,
+        Pattern::Starfield => return starfield(palette, seed, width, height),
+    };
+
+    // Coordinates are in output heights, so patterns keep their proportions
+    let (small_width, small_height) = ((width / SMOOTH_STEP).max(1), (height / SMOOTH_STEP).max(1));
+    let unit = 1.0 / small_height as f32;
+    let small = Rgb32FImage::from_fn(small_width, small_height, |x, y| {
+        let t = field((x as f32 + 0.5) * unit, (y as f32 + 0.5) * unit, seed);
+        image::Rgb(sample(palette, t))
+    });
+    image::imageops::resize(&small, width, height, image::imageops::FilterType::Triangle)
+}
+
+/// Colors of a pattern without a palette of its own.
+fn default_palette(pattern: Pattern) -> &'static [[f32; 3]] {
+    match pattern {
+        Pattern::Plasma => &[
+            [0.04, 0.01, 0.12],
+            [0.45, 0.03, 0.3],
+            [0.95, 0.3, 0.05],
+            [1.0, 0.8, 0.3],
+        ],
+        Pattern::Clouds => &[[0.04, 0.14, 0.45], [0.25, 0.45, 0.8], [0.9, 0.92, 0.95]],
+        Pattern::Aurora => &[
+            [0.002, 0.003, 0.015],
+            [0.01, 0.04, 0.06],
+            [0.05, 0.65, 0.3],
+            [0.45, 0.2, 0.75],
+        ],
+        Pattern::Starfield => &[
+            [0.0005, 0.001, 0.004],
+            [0.004, 0.008, 0.03],
+            [0.6, 0.7, 1.0],
+            [1.0, 0.95, 0.85],
+        ],
+    }
+}
+
+/// Color at `t` (0.0-1.0) of a palette spread evenly over that range.
+fn sample(palette: &[[f32; 3]], t: f32) -> [f32; 3] {
+    let Some(&last) = palette.last() else {
+        return [0.0; 3];
+    };
+    let position = t.clamp(0.0, 1.0) * (palette.len() - 1) as f32;
+    let index = position.floor() as usize;
+    let Some(&next) = palette.get(index + 1) else {
+        return last;
+    };
+    let color = palette[index];
+    let f = position - index as f32;
+    [0, 1, 2].map(|c| color[c] + (next[c] - color[c]) * f)
+}
+
+/// Interfering sines with phases and frequencies picked by the seed.
+fn plasma(x: f32, y: f32, seed: u64) -> f32 {
+    let random = |k: u64| unit(hash(seed ^ hash(k)));
+    let frequency = |k: u64| 2.0 + 4.0 * random(k);
+    let phase = |k: u64| TAU * random(k + 4);
+    let (cx, cy) = (2.0 * random(8), random(9));
+
+    let radius = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+    let v = (x * frequency(0) + phase(0)).sin()
+        + (y * frequency(1) + phase(1)).sin()
+        + ((x + y) * frequency(2) + phase(2)).sin()
+        + (radius * frequency(3) * 1.5 + phase(3)).sin();
+    v / 8.0 + 0.5
+}
+
+/// Soft-edged clouds of layered noise.
+fn clouds(x: f32, y: f32, seed: u64) -> f32 {
+    let density = fbm(x * 2.5, y * 2.5, seed, 6) * 0.5 + 0.5;
+    smoothstep(0.3, 0.85, density)
+}
+
+/// A curtain of light with a bright, wavy lower edge, fading upwards into a
+/// sky that lightens towards the horizon.
+fn aurora(x: f32, y: f32, seed: u64) -> f32 {
+    let edge = 0.38 + 0.12 * fbm(x * 0.8, 0.0, seed, 3);
+    let below = y - edge;
+    let curtain = if below > 0.0 {
+        (-below * below * 400.0).exp()
+    } else {
+        (below * 4.0).exp()
+    };
+    let rays = 0.55 + 0.45 * simplex(x * 14.0, y * 0.6, seed.wrapping_add(7));
+    let strength = 0.6 + 0.4 * fbm(x * 2.0, 1.3, seed.wrapping_add(3), 2);
+
+    let sky = 0.1 * y;
+    (sky + 0.9 * curtain * rays * strength).clamp(0.0, 1.0)
+}
+
+/// Stars scattered over a dark sky, most of them faint. The sky takes the
+/// low end of the palette, and stars its high end.
+fn starfield(palette: &[[f32; 3]], seed: u64, width: u32, height: u32) -> Rgb32FImage {
+    let mut image = Rgb32FImage::from_fn(width, height, |_, y| {
+        image::Rgb(sample(palette, 0.12 * y as f32 / height.max(1) as f32))
+    });
+
+    let count = u64::from(width) * u64::from(height) / 900;
+    for star in 0..count {
+        let random = |k: u64| unit(hash(seed ^ hash(star.wrapping_mul(4).wrapping_add(k))));
+        let (cx, cy) = (random(0) * width as f32, random(1) * height as f32);
+        let brightness = random(2).powi(3);
+        let color = sample(palette, 0.6 + 0.4 * random(3));
+        let radius = 0.5 + 1.2 * brightness;
+
+        let reach = (radius * 2.0).ceil() as i64;
+        for py in (cy as i64 - reach).max(0)..(cy as i64 + reach + 1).min(i64::from(height)) {
+            for px in (cx as i64 - reach).max(0)..(cx as i64 + reach + 1).min(i64::from(width)) {
+                let distance = (px as f32 + 0.5 - cx).powi(2) + (py as f32 + 0.5 - cy).powi(2);
+                let alpha = (0.3 + 0.7 * brightness) * (-distance / (radius * radius)).exp();
+                let pixel = image.get_pixel_mut(px as u32, py as u32);
+                for (channel, target) in pixel.0.iter_mut().zip(color) {
+                    *channel += (target - *channel) * alpha;
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Fractal noise: `octaves` of simplex noise, each at twice the frequency
+/// and half the amplitude of the previous one. Roughly in -1.0..1.0.
+fn fbm(x: f32, y: f32, seed: u64, octaves: u64) -> f32 {
+    let (mut sum, mut amplitude, mut frequency) = (0.0, 0.5, 1.0);
+    for octave in 0..octaves {
+        sum += amplitude * simplex(x * frequency, y * frequency, seed.wrapping_add(octave));
+        frequency *= 2.0;
+        amplitude *= 0.5;
+    }
+    sum / (1.0 - amplitude * 2.0)
+}
+
+/// 2D simplex noise, roughly in -1.0..1.0, with gradients picked by `seed`.
+fn simplex(x: f32, y: f32, seed: u64) -> f32 {
+    // Skew factors between the square and the triangle grid
+    const F2: f32 = 0.366_025_4;
+    const G2: f32 = 0.211_324_87;
+
+    let skew = (x + y) * F2;
+    let (i, j) = ((x + skew).floor(), (y + skew).floor());
+    let unskew = (i + j) * G2;
+    let (x0, y0) = (x - (i - unskew), y - (j - unskew));
+    let (i1, j1) = if x0 > y0 { (1.0, 0.0) } else { (0.0, 1.0) };
+
+    let corners = [
+        (x0, y0, 0.0, 0.0),
+        (x0 - i1 + G2, y0 - j1 + G2, i1, j1),
+        (x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2, 1.0, 1.0),
+    ];
+    let mut noise = 0.0;
+    for (dx, dy, di, dj) in corners {
+        let falloff = 0.5 - dx * dx - dy * dy;
+        if falloff <= 0.0 {
+            continue;
+        }
+        let corner = hash(seed ^ hash((i + di) as i64 as u64 ^ hash((j + dj) as i64 as u64)));
+        let angle = TAU * unit(corner);
+        noise += falloff.powi(4) * (angle.cos() * dx + angle.sin() * dy);
+    }
+    70.0 * noise
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// SplitMix64, so that a seed draws the same pattern on every build.
+pub(crate) fn hash(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// A hash mapped to 0.0..1.0.
+fn unit(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
 #[cfg(test)]
 mod tests {
-    use super::single;
+    use super::{Pattern, Procedural, placeholder, procedural, sample, single};
 
     #[test]
     fn single_color_buffer_matches_size() {
@@ -93,4 +310,43 @@ mod tests {
         assert_eq!(buffer.width(), width);
         assert_eq!(buffer.height(), height);
     }
+
+    #[test]
+    fn placeholder_alternates_tiles() {
+        let buffer = placeholder(240, 120);
+        let tile = 120 / 12;
+
+        assert_eq!(buffer.get_pixel(0, 0), buffer.get_pixel(tile, tile));
+        assert_ne!(buffer.get_pixel(0, 0), buffer.get_pixel(tile, 0));
+        assert_ne!(buffer.get_pixel(0, 0), buffer.get_pixel(0, tile));
+    }
+
+    #[test]
+    fn samples_palettes_evenly() {
+        let palette = [[0.0; 3], [1.0, 0.5, 0.0]];
+        assert_eq!(sample(&palette, 0.5), [0.5, 0.25, 0.0]);
+        assert_eq!(sample(&palette, 2.0), [1.0, 0.5, 0.0]);
+        assert_eq!(sample(&palette[..1], 0.7), [0.0; 3]);
+        assert_eq!(sample(&[], 0.7), [0.0; 3]);
+    }
+
+    #[test]
+    fn procedural_backgrounds_follow_their_seed() {
+        for pattern in [
+            Pattern::Plasma,
+            Pattern::Clouds,
+            Pattern::Aurora,
+            Pattern::Starfield,
+        ] {
+            let source = |seed| Procedural {
+                pattern,
+                seed,
+                palette: Vec::new(),
+            };
+            let buffer = procedural(&source(1), 96, 64);
+            assert_eq!(buffer.dimensions(), (96, 64));
+            assert_eq!(buffer, procedural(&source(1), 96, 64), "{pattern:?}");
+            assert_ne!(buffer, procedural(&source(2), 96, 64), "{pattern:?}");
+        }
+    }
 }
@@ -0,0 +1,154 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Composite wallpapers: an image with a shader blended over it.
+//!
+//! A composite layer is a shader layer running a built-in shader, see
+//! [`shader_source`]. The image is bound as the overlay's background
+//! `iTexture`, scaled like a static image, and the overlay's entry point is
+//! renamed and called from a new one that blends its output over the image.
+//! The overlay's header comes first, so `// output:` declarations still apply.
+
+use std::path::Path;
+
+use glowberry_config::{BlendMode, CompositeSource, ScalingMode, ShaderContent, ShaderSource};
+
+/// Name the overlay's entry point is renamed to.
+const OVERLAY_ENTRY: &str = "glowberry_overlay";
+
+/// The built-in shader showing `composite`, with its image scaled with
+/// `scaling`.
+pub fn shader_source(composite: &CompositeSource, scaling: &ScalingMode) -> ShaderSource {
+    let overlay = &composite.overlay;
+    let overlay_code = match &overlay.shader {
+        ShaderContent::Path(path) => std::fs::read_to_string(path).unwrap_or_else(|why| {
+            tracing::warn!(?why, path = %path.display(), "failed to read composite overlay");
+            String::new()
+        }),
+        ShaderContent::Code(code) => code.clone(),
+    };
+
+    ShaderSource {
+        shader: ShaderContent::Code(shader_code(
+            &overlay_code,
+            composite.blend,
+            composite.opacity,
+        )),
+        // Includes resolve next to the overlay's file
+        source_path: match &overlay.shader {
+            ShaderContent::Path(path) => Some(path.clone()),
+            ShaderContent::Code(_) => overlay.source_path.clone(),
+        },
+        background_image: Some(composite.image.clone()),
+        background_from_wallpaper: false,
+        background_scaling: scaling.clone(),
+        ..overlay.clone()
+    }
+}
+
+/// Path of the overlay's shader file, when it is loaded from disk.
+pub fn overlay_path(composite: &CompositeSource) -> Option<&Path> {
+    match &composite.overlay.shader {
+        ShaderContent::Path(path) => Some(path),
+        ShaderContent::Code(_) => None,
+    }
+}
+
+/// WGSL blending the output of `overlay` over `iTexture`. An overlay without
+/// an entry point draws nothing, leaving the image.
+fn shader_code(overlay: &str, blend: BlendMode, opacity: f32) -> String {
+    let (overlay, call) = match rename_entry_point(overlay) {
+        Some(overlay) => (overlay, format!("{OVERLAY_ENTRY}(fragCoord)")),
+        None => (overlay.to_owned(), String::from("vec4<f32>(0.0)")),
+    };
+    let blend = match blend {
+        BlendMode::Normal => 0,
+        BlendMode::Add => 1,
+        BlendMode::Screen => 2,
+        BlendMode::Multiply => 3,
+        BlendMode::Overlay => 4,
+    };
+    let opacity = if opacity.is_finite() {
+        opacity.clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+
+    format!(
+        r#"{overlay}
+
+// Built-in composition of composite wallpapers
+
+// 0 normal, 1 add, 2 screen, 3 multiply, 4 overlay
+const GLOWBERRY_BLEND: i32 = {blend};
+const GLOWBERRY_OPACITY: f32 = {opacity:?};
+
+fn glowberry_blend(base: vec3<f32>, layer: vec3<f32>) -> vec3<f32> {{
+    if GLOWBERRY_BLEND == 1 {{
+        return min(base + layer, vec3<f32>(1.0));
+    }} else if GLOWBERRY_BLEND == 2 {{
+        return 1.0 - (1.0 - base) * (1.0 - layer);
+    }} else if GLOWBERRY_BLEND == 3 {{
+        return base * layer;
+    }} else if GLOWBERRY_BLEND == 4 {{
+        let dark = 2.0 * base * layer;
+        let light = 1.0 - 2.0 * (1.0 - base) * (1.0 - layer);
+        return select(light, dark, base < vec3<f32>(0.5));
+    }}
+    return layer;
+}}
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {{
+    let base = textureSampleLevel(iTexture, iTextureSampler, fragCoord.xy / iResolution, 0.0).rgb;
+    let layer = {call};
+    let blended = glowberry_blend(base, layer.rgb);
+    return vec4<f32>(mix(base, blended, clamp(layer.a, 0.0, 1.0) * GLOWBERRY_OPACITY), 1.0);
+}}
+"#
+    )
+}
+
+/// Turn the overlay's `main` entry point into a plain function named
+/// [`OVERLAY_ENTRY`], dropping the attributes only entry points may have.
+fn rename_entry_point(code: &str) -> Option<String> {
+    let start = code.find("fn main(")?;
+    let body = start + code[start..].find('{')?;
+
+    let signature = code[start..body]
+        .replacen("fn main(", &format!("fn {OVERLAY_ENTRY}("), 1)
+        .replace("@builtin(position)", "")
+        .replace("@location(0)", "");
+    let head = code[..start].replace("@fragment", "");
+    Some(format!("{head}{signature}{}", &code[body..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERLAY: &str = "// output: linear
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 1.0, 0.5);
+}
+";
+
+    #[test]
+    fn wraps_the_overlay_entry_point() {
+        let code = shader_code(OVERLAY, BlendMode::Screen, 0.75);
+        assert!(code.starts_with("// output: linear"));
+        assert!(code.contains("fn glowberry_overlay( fragCoord: vec4<f32>) ->  vec4<f32> {"));
+        assert_eq!(code.matches("@fragment").count(), 1);
+        assert_eq!(code.matches("fn main(").count(), 1);
+        assert!(code.contains("const GLOWBERRY_BLEND: i32 = 2;"));
+        assert!(code.contains("const GLOWBERRY_OPACITY: f32 = 0.75;"));
+        assert!(crate::shader_defs::outputs_linear(&code));
+    }
+
+    #[test]
+    fn shows_the_image_without_an_overlay() {
+        let code = shader_code("", BlendMode::Normal, f32::NAN);
+        assert!(code.contains("let layer = vec4<f32>(0.0);"));
+        assert!(code.contains("const GLOWBERRY_OPACITY: f32 = 1.0;"));
+    }
+}
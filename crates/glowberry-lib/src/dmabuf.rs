@@ -0,0 +1,416 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! GPU buffers for static wallpapers through `zwp_linux_dmabuf_v1`.
+//!
+//! By default a static wallpaper is copied into a SHM pool, which the
+//! compositor imports again on every attach. When the compositor advertises
+//! `XRGB8888` dmabufs, the scaled image is instead written once into a GBM
+//! buffer object on the compositor's GPU and attached as a dmabuf, which the
+//! compositor can sample or scan out directly. The buffer is reattached as-is
+//! when a layer is reconfigured at the same size, and the SHM pool is never
+//! written, so its pages stay unallocated. The frames of an animation are
+//! written into the buffer the compositor released last rather than a new
+//! one. High-depth images use `XRGB2101010` buffers instead when the
+//! compositor accepts them.
+//!
+//! Allocation is only available with the `dmabuf` cargo feature; without it
+//! [`Allocator::new`] returns `None` and layers keep using SHM.
+
+use image::DynamicImage;
+use sctk::{
+    dmabuf::DmabufFeedback,
+    reexports::{
+        client::{QueueHandle, protocol::wl_buffer},
+        protocols::wp::linux_dmabuf::zv1::client::zwp_linux_dmabuf_v1,
+    },
+};
+
+use crate::{draw::Format, engine::GlowBerry};
+
+/// Error when creating a dmabuf wallpaper buffer.
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(not(feature = "dmabuf"), allow(dead_code))]
+pub enum DmabufError {
+    #[error("Failed to allocate buffer object: {0}")]
+    Allocate(#[from] std::io::Error),
+    #[error("Failed to export buffer object")]
+    Export,
+}
+
+/// Allocates wallpaper buffers on the compositor's main GPU.
+#[cfg_attr(not(feature = "dmabuf"), allow(dead_code))]
+pub struct Allocator {
+    #[cfg(feature = "dmabuf")]
+    device: gbm::Device<std::fs::File>,
+    dmabuf: zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+    /// `XRGB8888` modifiers the compositor accepts, in preference order.
+    modifiers: Vec<u64>,
+    /// `XRGB2101010` modifiers, empty if 10-bit buffers aren't accepted.
+    ten_bit_modifiers: Vec<u64>,
+}
+
+impl std::fmt::Debug for Allocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Allocator")
+            .field("modifiers", &self.modifiers)
+            .field("ten_bit_modifiers", &self.ten_bit_modifiers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A wallpaper image in a dmabuf, attached to a layer.
+///
+/// The `wl_buffer` is destroyed when this is dropped, so it has to outlive
+/// its attachment until the compositor releases it, see
+/// [`GlowBerryLayer::retire_dmabuf`](crate::engine::GlowBerryLayer::retire_dmabuf).
+pub struct Buffer {
+    pub(crate) wl_buffer: wl_buffer::WlBuffer,
+    /// Buffer size and the wallpaper image generation it shows.
+    pub(crate) key: (u32, u32, u64),
+    /// Whether the compositor released the buffer since it was last
+    /// attached, so that it may be written again.
+    pub(crate) released: bool,
+    #[cfg(feature = "dmabuf")]
+    bo: gbm::BufferObject<()>,
+    #[cfg(feature = "dmabuf")]
+    format: Format,
+}
+
+impl Buffer {
+    /// Read back the image the buffer holds, for a transition to start from,
+    /// see [`draw::read_canvas`](crate::draw::read_canvas).
+    pub(crate) fn read(&self) -> Option<DynamicImage> {
+        #[cfg(feature = "dmabuf")]
+        {
+            let (width, height, _) = self.key;
+            self.bo
+                .map(0, 0, width, height, |mapping| {
+                    crate::draw::read_canvas(
+                        mapping.buffer(),
+                        width,
+                        height,
+                        mapping.stride() as usize,
+                        self.format,
+                    )
+                })
+                .inspect_err(|why| tracing::debug!(?why, "Failed to map dmabuf for reading"))
+                .ok()
+                .flatten()
+        }
+
+        #[cfg(not(feature = "dmabuf"))]
+        {
+            None
+        }
+    }
+}
+
+impl std::fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Buffer")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.wl_buffer.destroy();
+    }
+}
+
+impl Allocator {
+    /// Open the main device of the compositor's default dmabuf `feedback`.
+    ///
+    /// Returns `None` if the `dmabuf` feature is disabled, the compositor
+    /// accepts no `XRGB8888` dmabufs, or the device can't be opened.
+    #[cfg_attr(not(feature = "dmabuf"), allow(unused_variables))]
+    pub fn new(
+        dmabuf: zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+        feedback: &DmabufFeedback,
+    ) -> Option<Self> {
+        #[cfg(feature = "dmabuf")]
+        {
+            let table: Vec<_> = feedback
+                .format_table()
+                .iter()
+                .map(|format| (format.format, format.modifier))
+                .collect();
+            let tranches: Vec<_> = feedback
+                .tranches()
+                .iter()
+                .map(|tranche| tranche.formats.as_slice())
+                .collect();
+            let modifiers = format_modifiers(Format::Xrgb8888, &table, &tranches);
+            if modifiers.is_empty() {
+                tracing::info!("Compositor accepts no XRGB8888 dmabufs, using SHM");
+                return None;
+            }
+            let ten_bit_modifiers = format_modifiers(Format::Xrgb2101010, &table, &tranches);
+
+            let device = gbm_device::open(feedback.main_device())?;
+            tracing::info!(
+                ?modifiers,
+                ?ten_bit_modifiers,
+                "Static wallpapers use dmabuf buffers"
+            );
+            Some(Self {
+                device,
+                dmabuf,
+                modifiers,
+                ten_bit_modifiers,
+            })
+        }
+
+        #[cfg(not(feature = "dmabuf"))]
+        {
+            tracing::debug!("Built without the `dmabuf` feature, static wallpapers use SHM");
+            None
+        }
+    }
+
+    /// Upload `image` into a new buffer tagged with `key`, in 10 bits per
+    /// channel if its source has them, as told by `high_depth`, and the
+    /// compositor accepts it. The
+    /// image is written into `reuse` instead when the compositor released it
+    /// and it has the image's size and format.
+    #[cfg_attr(not(feature = "dmabuf"), allow(unused_variables))]
+    pub fn buffer(
+        &self,
+        image: &DynamicImage,
+        high_depth: bool,
+        key: (u32, u32, u64),
+        reuse: Option<Buffer>,
+        qh: &QueueHandle<GlowBerry>,
+    ) -> Result<Buffer, DmabufError> {
+        #[cfg(feature = "dmabuf")]
+        {
+            let format = Format::for_depth(high_depth, !self.ten_bit_modifiers.is_empty());
+            if let Some(mut buffer) = reuse.filter(|buffer| {
+                buffer.released
+                    && buffer.format == format
+                    && (buffer.key.0, buffer.key.1) == (image.width(), image.height())
+            }) {
+                gbm_device::write(&mut buffer.bo, image, format)?;
+                buffer.key = key;
+                buffer.released = false;
+                return Ok(buffer);
+            }
+            gbm_device::buffer(self, image, format, key, qh)
+        }
+
+        #[cfg(not(feature = "dmabuf"))]
+        {
+            Err(DmabufError::Export)
+        }
+    }
+}
+
+/// Modifiers for `format` in the feedback's format `table`, following the
+/// order of its `tranches` of table indices.
+#[cfg_attr(not(feature = "dmabuf"), allow(dead_code))]
+fn format_modifiers(format: Format, table: &[(u32, u64)], tranches: &[&[u16]]) -> Vec<u64> {
+    let fourcc = format.fourcc();
+    let mut modifiers = Vec::new();
+    for &index in tranches.iter().flat_map(|tranche| tranche.iter()) {
+        if let Some(&(entry_format, modifier)) = table.get(usize::from(index))
+            && entry_format == fourcc
+            && !modifiers.contains(&modifier)
+        {
+            modifiers.push(modifier);
+        }
+    }
+    modifiers
+}
+
+/// Major and minor numbers of a Linux `dev_t`.
+#[cfg_attr(not(feature = "dmabuf"), allow(dead_code))]
+fn dev_numbers(dev: u64) -> (u64, u64) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & 0xffff_f000);
+    let minor = (dev & 0xff) | ((dev >> 12) & 0xffff_ff00);
+    (major, minor)
+}
+
+#[cfg(feature = "dmabuf")]
+mod gbm_device {
+    use super::{Allocator, Buffer, DmabufError, dev_numbers};
+    use crate::{
+        draw::{self, Format},
+        engine::GlowBerry,
+    };
+    use gbm::{BufferObjectFlags, Modifier};
+    use image::DynamicImage;
+    use sctk::{
+        dmabuf::DmaBufferData,
+        globals::GlobalData,
+        reexports::{
+            client::QueueHandle,
+            protocols::wp::linux_dmabuf::zv1::client::zwp_linux_buffer_params_v1,
+        },
+    };
+    use std::os::fd::AsFd;
+    use std::path::PathBuf;
+
+    /// `DRM_FORMAT_MOD_INVALID`: the driver picks the layout.
+    const IMPLICIT_MODIFIER: u64 = 0x00ff_ffff_ffff_ffff;
+
+    /// Open a GBM device on the render node of the GPU `dev`, which may be
+    /// its primary or render node.
+    pub(super) fn open(dev: u64) -> Option<gbm::Device<std::fs::File>> {
+        let (major, minor) = dev_numbers(dev);
+        let drm = PathBuf::from(format!("/sys/dev/char/{major}:{minor}/device/drm"));
+        let node = std::fs::read_dir(&drm)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name())
+            .find(|name| name.to_string_lossy().starts_with("renderD"))?;
+        let path = PathBuf::from("/dev/dri").join(node);
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .inspect_err(|why| tracing::warn!(?why, ?path, "Failed to open render node"))
+            .ok()?;
+        gbm::Device::new(file)
+            .inspect_err(|why| tracing::warn!(?why, ?path, "Failed to create GBM device"))
+            .ok()
+    }
+
+    pub(super) fn buffer(
+        allocator: &Allocator,
+        image: &DynamicImage,
+        format: Format,
+        key: (u32, u32, u64),
+        qh: &QueueHandle<GlowBerry>,
+    ) -> Result<Buffer, DmabufError> {
+        let (width, height) = (image.width(), image.height());
+        let flags = BufferObjectFlags::SCANOUT | BufferObjectFlags::RENDERING;
+        let (gbm_format, modifiers) = match format {
+            Format::Xrgb8888 => (gbm::Format::Xrgb8888, &allocator.modifiers),
+            Format::Xrgb2101010 => (gbm::Format::Xrgb2101010, &allocator.ten_bit_modifiers),
+            Format::Argb8888 => (gbm::Format::Argb8888, &allocator.modifiers),
+        };
+
+        let mut bo = if *modifiers == [IMPLICIT_MODIFIER] {
+            allocator
+                .device
+                .create_buffer_object::<()>(width, height, gbm_format, flags)?
+        } else {
+            allocator
+                .device
+                .create_buffer_object_with_modifiers2::<()>(
+                    width,
+                    height,
+                    gbm_format,
+                    modifiers.iter().map(|&m| Modifier::from(m)),
+                    flags,
+                )?
+        };
+
+        write(&mut bo, image, format)?;
+
+        let params = allocator.dmabuf.create_params(qh, GlobalData);
+        let modifier: u64 = bo.modifier().into();
+        for plane in 0..bo.plane_count() {
+            let fd = bo
+                .fd_for_plane(plane as i32)
+                .map_err(|_| DmabufError::Export)?;
+            params.add(
+                fd.as_fd(),
+                plane,
+                bo.offset(plane as i32),
+                bo.stride_for_plane(plane as i32),
+                (modifier >> 32) as u32,
+                modifier as u32,
+            );
+        }
+        let wl_buffer = params.create_immed(
+            width as i32,
+            height as i32,
+            format.fourcc(),
+            zwp_linux_buffer_params_v1::Flags::empty(),
+            qh,
+            DmaBufferData,
+        );
+        params.destroy();
+
+        Ok(Buffer {
+            wl_buffer,
+            key,
+            released: false,
+            bo,
+            format,
+        })
+    }
+
+    /// Write `image` into `bo` in `format`.
+    pub(super) fn write(
+        bo: &mut gbm::BufferObject<()>,
+        image: &DynamicImage,
+        format: Format,
+    ) -> Result<(), DmabufError> {
+        let (width, height) = (image.width(), image.height());
+        // Convert straight into the mapping when rows are packed
+        bo.map_mut(0, 0, width, height, |mapping| {
+            let stride = mapping.stride() as usize;
+            let row = width as usize * 4;
+            let pixels = mapping.buffer_mut();
+            if stride == row {
+                draw::write_canvas(pixels, image, format);
+            } else {
+                let mut packed = vec![0; row * height as usize];
+                draw::write_canvas(&mut packed, image, format);
+                for (dst, src) in pixels.chunks_mut(stride).zip(packed.chunks_exact(row)) {
+                    dst[..row].copy_from_slice(src);
+                }
+            }
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_xrgb8888_modifiers_in_tranche_order() {
+        const ARGB8888: u32 = u32::from_le_bytes(*b"AR24");
+        let (xrgb8888, xrgb2101010) = (Format::Xrgb8888.fourcc(), Format::Xrgb2101010.fourcc());
+        let table = [
+            (ARGB8888, 0),
+            (xrgb8888, 0),
+            (xrgb8888, 7),
+            (xrgb8888, 9),
+            (xrgb2101010, 7),
+        ];
+        let tranches: [&[u16]; 2] = [&[3, 0, 1], &[1, 2, 9]];
+
+        assert_eq!(
+            format_modifiers(Format::Xrgb8888, &table, &tranches),
+            [9, 0, 7]
+        );
+        assert!(format_modifiers(Format::Xrgb2101010, &table, &tranches).is_empty());
+
+        let tranches: [&[u16]; 1] = [&[4, 1]];
+        assert_eq!(
+            format_modifiers(Format::Xrgb2101010, &table, &tranches),
+            [7]
+        );
+    }
+
+    #[test]
+    fn splits_device_numbers() {
+        // /dev/dri/renderD128 is 226:128
+        assert_eq!(dev_numbers((226 << 8) | 128), (226, 128));
+
+        // Minor numbers past 255 continue above the major's low bits
+        let (major, minor) = (4100_u64, 300_u64);
+        let dev = (minor & 0xff)
+            | ((major & 0xfff) << 8)
+            | ((minor & !0xff) << 12)
+            | ((major & !0xfff) << 32);
+        assert_eq!(dev_numbers(dev), (major, minor));
+    }
+}
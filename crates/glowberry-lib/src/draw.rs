@@ -1,10 +1,11 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use image::{DynamicImage, GenericImageView};
+use image::DynamicImage;
 use sctk::{
     reexports::{
         client::{
-            Dispatch, QueueHandle, protocol::wl_callback, protocol::wl_shm, protocol::wl_surface,
+            Dispatch, QueueHandle, protocol::wl_buffer, protocol::wl_callback,
+            protocol::wl_output::Transform, protocol::wl_shm, protocol::wl_surface,
         },
         protocols::wp::viewporter::client::wp_viewport,
     },
@@ -12,27 +13,314 @@ use sctk::{
     shm::slot::{Buffer, CreateBufferError, SlotPool},
 };
 
+/// Pixel formats wallpaper buffers are drawn in, all 4 bytes per pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Xrgb8888,
+    /// 10 bits per channel, so gradients and high-depth images don't band.
+    Xrgb2101010,
+    /// Premultiplied alpha, for images with transparent pixels.
+    Argb8888,
+}
+
+impl Format {
+    /// The format to draw an image in: 10-bit when its source has more than
+    /// 8 bits per channel and the compositor accepts it.
+    ///
+    /// Scaled images are always 32-bit floats, so `high_depth` is told by the
+    /// source rather than by the image drawn.
+    pub fn for_depth(high_depth: bool, ten_bit: bool) -> Self {
+        if ten_bit && high_depth {
+            Self::Xrgb2101010
+        } else {
+            Self::Xrgb8888
+        }
+    }
+
+    pub fn shm(self) -> wl_shm::Format {
+        match self {
+            Self::Xrgb8888 => wl_shm::Format::Xrgb8888,
+            Self::Xrgb2101010 => wl_shm::Format::Xrgb2101010,
+            Self::Argb8888 => wl_shm::Format::Argb8888,
+        }
+    }
+
+    /// The `DRM_FORMAT_*` code of the format.
+    pub fn fourcc(self) -> u32 {
+        match self {
+            Self::Xrgb8888 => u32::from_le_bytes(*b"XR24"),
+            Self::Xrgb2101010 => u32::from_le_bytes(*b"XR30"),
+            Self::Argb8888 => u32::from_le_bytes(*b"AR24"),
+        }
+    }
+}
+
 pub fn canvas(
     pool: &mut SlotPool,
     image: &DynamicImage,
     width: i32,
     height: i32,
     stride: i32,
+    format: Format,
 ) -> Result<Buffer, CreateBufferError> {
-    let (buffer, canvas) = pool.create_buffer(width, height, stride, wl_shm::Format::Xrgb8888)?;
+    let (buffer, canvas) = pool.create_buffer(width, height, stride, format.shm())?;
 
-    xrgb888_canvas(canvas, image);
+    write_canvas(canvas, image, format);
 
     Ok(buffer)
 }
 
+/// Draws the image on a canvas in `format`.
+pub fn write_canvas(canvas: &mut [u8], image: &DynamicImage, format: Format) {
+    match format {
+        Format::Xrgb8888 => xrgb888_canvas(canvas, image),
+        Format::Xrgb2101010 => xrgb2101010_canvas(canvas, image),
+        Format::Argb8888 => match image {
+            DynamicImage::ImageRgba8(rgba) => pixels::rgba_to_argb8888(rgba.as_raw(), canvas),
+            other => pixels::rgba_to_argb8888(other.to_rgba8().as_raw(), canvas),
+        },
+    }
+}
+
+/// Whether `image` has pixels that aren't fully opaque, to be drawn in
+/// [`Format::Argb8888`] so the compositor's background shows through.
+pub fn has_transparency(image: &DynamicImage) -> bool {
+    match image {
+        DynamicImage::ImageRgba8(rgba) => rgba
+            .as_raw()
+            .chunks_exact(4)
+            .any(|pixel| pixel[3] < u8::MAX),
+        other if other.color().has_alpha() => {
+            has_transparency(&DynamicImage::ImageRgba8(other.to_rgba8()))
+        }
+        _ => false,
+    }
+}
+
+/// Whether `image` has more than 8 bits per channel, such as 16-bit PNGs
+/// and generated colors and gradients.
+pub fn is_high_depth(image: &DynamicImage) -> bool {
+    !matches!(
+        image,
+        DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_)
+    )
+}
+
+/// Copy `height` rows of `width` RGBA pixels out of a plane whose rows are
+/// `stride` bytes apart.
+#[cfg_attr(not(feature = "heif"), allow(dead_code))]
+pub(crate) fn pack_rows(plane: &[u8], stride: usize, width: u32, height: u32) -> Vec<u8> {
+    let row = width as usize * 4;
+    if stride == row {
+        return plane[..row * height as usize].to_vec();
+    }
+
+    plane
+        .chunks(stride)
+        .take(height as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect()
+}
+
+/// Edge of the square tiles compared to find damaged regions, in pixels.
+const DAMAGE_TILE: usize = 64;
+/// Damage split into more rects than this is sent as the whole buffer.
+const MAX_DAMAGE_RECTS: usize = 32;
+
+/// What a layer's last SHM buffer shows, so redraws can be skipped or
+/// limited to the tiles that changed.
+#[derive(Debug)]
+pub struct ShmFrame {
+    /// Buffer size and the wallpaper image generation it shows.
+    pub key: (u32, u32, u64),
+    /// [`tile_hashes`] of the buffer.
+    pub tiles: Vec<u64>,
+    /// The buffer, kept to read back the frame a transition starts from.
+    pub buffer: Buffer,
+    pub format: Format,
+}
+
+/// Read back the `width`x`height` image drawn on `canvas` in `format`,
+/// with rows `stride` bytes apart: 16 bits per channel from 10-bit canvases,
+/// else 8. Returns `None` if the canvas is too small.
+pub fn read_canvas(
+    canvas: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: Format,
+) -> Option<DynamicImage> {
+    let row = width as usize * 4;
+    if stride < row || canvas.len() < stride * (height as usize).saturating_sub(1) + row {
+        return None;
+    }
+    let pixels = canvas
+        .chunks(stride)
+        .take(height as usize)
+        .flat_map(|line| line[..row].chunks_exact(4));
+
+    Some(match format {
+        Format::Xrgb8888 => DynamicImage::ImageRgba8(image::RgbaImage::from_vec(
+            width,
+            height,
+            pixels.flat_map(|p| [p[2], p[1], p[0], u8::MAX]).collect(),
+        )?),
+        Format::Argb8888 => {
+            let unpremultiply = |c: u8, a: u8| match a {
+                0 => 0,
+                a => ((u16::from(c) * 255 + u16::from(a) / 2) / u16::from(a)).min(255) as u8,
+            };
+            DynamicImage::ImageRgba8(image::RgbaImage::from_vec(
+                width,
+                height,
+                pixels
+                    .flat_map(|p| {
+                        [
+                            unpremultiply(p[2], p[3]),
+                            unpremultiply(p[1], p[3]),
+                            unpremultiply(p[0], p[3]),
+                            p[3],
+                        ]
+                    })
+                    .collect(),
+            )?)
+        }
+        Format::Xrgb2101010 => {
+            let sixteen_bit = |v: u32| ((v & 1023) * 65535 / 1023) as u16;
+            DynamicImage::ImageRgba16(image::ImageBuffer::from_vec(
+                width,
+                height,
+                pixels
+                    .flat_map(|p| {
+                        let pixel = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+                        [
+                            sixteen_bit(pixel >> 20),
+                            sixteen_bit(pixel >> 10),
+                            sixteen_bit(pixel),
+                            u16::MAX,
+                        ]
+                    })
+                    .collect(),
+            )?)
+        }
+    })
+}
+
+/// Hashes of the [`DAMAGE_TILE`]-sized tiles of a packed canvas in either
+/// [`Format`], row by row.
+pub fn tile_hashes(canvas: &[u8], width: u32, height: u32) -> Vec<u64> {
+    let (width, height) = (width as usize, height as usize);
+    let tiles_x = width.div_ceil(DAMAGE_TILE);
+    let mut hashes = vec![0xcbf2_9ce4_8422_2325; tiles_x * height.div_ceil(DAMAGE_TILE)];
+
+    for (y, row) in canvas.chunks_exact(width * 4).take(height).enumerate() {
+        let tile_row = &mut hashes[y / DAMAGE_TILE * tiles_x..][..tiles_x];
+        for (hash, span) in tile_row.iter_mut().zip(row.chunks(DAMAGE_TILE * 4)) {
+            for pixel in span.chunks_exact(4) {
+                let word = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                *hash = (*hash ^ u64::from(word))
+                    .wrapping_mul(0x0000_0100_0000_01b3)
+                    .rotate_left(5);
+            }
+        }
+    }
+    hashes
+}
+
+/// Buffer damage, as `[x, y, width, height]` rects, for a canvas with `new`
+/// tile hashes replacing one with `old` hashes. Without `old` hashes of the
+/// same size, the whole buffer is damaged.
+pub fn damage(old: Option<&[u64]>, new: &[u64], width: u32, height: u32) -> Vec<[i32; 4]> {
+    let full = vec![[0, 0, width as i32, height as i32]];
+    let Some(old) = old.filter(|old| old.len() == new.len() && !new.is_empty()) else {
+        return full;
+    };
+
+    let tiles_x = (width as usize).div_ceil(DAMAGE_TILE);
+    let mut rects = Vec::new();
+    for (tile_y, (old_row, new_row)) in old.chunks(tiles_x).zip(new.chunks(tiles_x)).enumerate() {
+        let y = tile_y * DAMAGE_TILE;
+        let rect_height = DAMAGE_TILE.min(height as usize - y);
+
+        // Merge runs of changed tiles in a row into one rect
+        let mut tile_x = 0;
+        while tile_x < tiles_x {
+            if old_row[tile_x] == new_row[tile_x] {
+                tile_x += 1;
+                continue;
+            }
+            let start = tile_x;
+            while tile_x < tiles_x && old_row[tile_x] != new_row[tile_x] {
+                tile_x += 1;
+            }
+            let x = start * DAMAGE_TILE;
+            let end = (tile_x * DAMAGE_TILE).min(width as usize);
+            rects.push([x as i32, y as i32, (end - x) as i32, rect_height as i32]);
+        }
+    }
+
+    if rects.len() > MAX_DAMAGE_RECTS {
+        return full;
+    }
+    rects
+}
+
 pub fn layer_surface<T>(
     layer_surface: &LayerSurface,
     viewport: &wp_viewport::WpViewport,
     queue_handle: &QueueHandle<T>,
     buffer: &Buffer,
-    buffer_damage: (i32, i32),
+    damage: &[[i32; 4]],
+    size: (u32, u32),
+) where
+    T: Dispatch<wl_callback::WlCallback, wl_surface::WlSurface> + 'static,
+{
+    present(
+        layer_surface,
+        viewport,
+        queue_handle,
+        damage,
+        size,
+        |wl_surface| {
+            if let Err(why) = buffer.attach_to(wl_surface) {
+                tracing::error!(?why, "buffer attachment failed");
+            }
+        },
+    );
+}
+
+/// Like [`layer_surface`], for a buffer not from a SHM pool, e.g. a dmabuf.
+pub fn layer_surface_wl_buffer<T>(
+    layer_surface: &LayerSurface,
+    viewport: &wp_viewport::WpViewport,
+    queue_handle: &QueueHandle<T>,
+    buffer: &wl_buffer::WlBuffer,
+    damage: &[[i32; 4]],
+    size: (u32, u32),
+) where
+    T: Dispatch<wl_callback::WlCallback, wl_surface::WlSurface> + 'static,
+{
+    present(
+        layer_surface,
+        viewport,
+        queue_handle,
+        damage,
+        size,
+        |wl_surface| wl_surface.attach(Some(buffer), 0, 0),
+    );
+}
+
+fn present<T>(
+    layer_surface: &LayerSurface,
+    viewport: &wp_viewport::WpViewport,
+    queue_handle: &QueueHandle<T>,
+    damage: &[[i32; 4]],
     size: (u32, u32),
+    attach: impl FnOnce(&wl_surface::WlSurface),
 ) where
     T: Dispatch<wl_callback::WlCallback, wl_surface::WlSurface> + 'static,
 {
@@ -40,8 +328,9 @@ pub fn layer_surface<T>(
 
     let wl_surface = layer_surface.wl_surface();
 
-    // Damage the entire window
-    wl_surface.damage_buffer(0, 0, buffer_damage.0, buffer_damage.1);
+    for &[x, y, width, height] in damage {
+        wl_surface.damage_buffer(x, y, width, height);
+    }
 
     // Request our next frame
     layer_surface
@@ -49,26 +338,486 @@ pub fn layer_surface<T>(
         .frame(queue_handle, wl_surface.clone());
 
     // Attach and commit to present.
-    if let Err(why) = buffer.attach_to(wl_surface) {
-        tracing::error!(?why, "buffer attachment failed");
-    }
+    attach(wl_surface);
 
     viewport.set_destination(width as i32, height as i32);
 
     wl_surface.commit();
 }
 
+/// Buffer size in pixels for a surface of logical `size` at a
+/// `wp_fractional_scale` of `scale`/120, rounded half away from zero as the
+/// protocol specifies.
+pub fn physical_size(size: (u32, u32), scale: u32) -> (u32, u32) {
+    let (width, height) = size;
+    ((width * scale + 60) / 120, (height * scale + 60) / 120)
+}
+
+/// Whether `transform` turns the output by 90 or 270 degrees, swapping its
+/// width and height.
+pub fn is_rotated(transform: Transform) -> bool {
+    matches!(
+        transform,
+        Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270
+    )
+}
+
+/// Applies an output `transform` to an upright image, giving the contents of a
+/// buffer presented with `wl_surface.set_buffer_transform(transform)`, which
+/// the compositor can scan out without turning it back.
+///
+/// Returns `None` for [`Transform::Normal`], where the image is used as is.
+pub fn transformed(image: &DynamicImage, transform: Transform) -> Option<DynamicImage> {
+    // Flips are around the vertical axis and come before the counter-clockwise
+    // rotation
+    let flipped = matches!(
+        transform,
+        Transform::Flipped | Transform::Flipped90 | Transform::Flipped180 | Transform::Flipped270
+    );
+    let flipped_image = flipped.then(|| image.fliph());
+    let image = flipped_image.as_ref().unwrap_or(image);
+
+    match transform {
+        Transform::_90 | Transform::Flipped90 => Some(image.rotate270()),
+        Transform::_180 | Transform::Flipped180 => Some(image.rotate180()),
+        Transform::_270 | Transform::Flipped270 => Some(image.rotate90()),
+        _ => flipped_image,
+    }
+}
+
 /// Draws the image on an 8-bit canvas.
 pub fn xrgb888_canvas(canvas: &mut [u8], image: &DynamicImage) {
-    for (pos, (_, _, pixel)) in image.pixels().enumerate() {
-        let indice = pos * 4;
+    match image {
+        DynamicImage::ImageRgb8(rgb) => pixels::rgb_to_xrgb8888(rgb.as_raw(), canvas),
+        DynamicImage::ImageRgba8(rgba) => pixels::rgba_to_xrgb8888(rgba.as_raw(), canvas),
+        other => pixels::rgba_to_xrgb8888(other.to_rgba8().as_raw(), canvas),
+    }
+}
+
+/// Draws the image on a 10-bit canvas.
+pub fn xrgb2101010_canvas(canvas: &mut [u8], image: &DynamicImage) {
+    match image {
+        DynamicImage::ImageRgb32F(rgb) => pixels::rgb32f_to_xrgb2101010(rgb.as_raw(), canvas),
+        DynamicImage::ImageRgb16(rgb) => pixels::rgb16_to_xrgb2101010(rgb.as_raw(), canvas),
+        DynamicImage::ImageRgba16(rgba) => pixels::rgba16_to_xrgb2101010(rgba.as_raw(), canvas),
+        other => pixels::rgb32f_to_xrgb2101010(other.to_rgb32f().as_raw(), canvas),
+    }
+}
+
+/// Pixel format conversion into little-endian `XRGB8888` (bytes `B, G, R, X`)
+/// and `XRGB2101010` (`R`, `G`, and `B` in bits 29-20, 19-10, and 9-0).
+///
+/// Vectorized paths are picked at runtime (AVX2/SSSE3 on x86_64, NEON on
+/// aarch64); the scalar loops handle the remaining tail and other targets.
+pub(crate) mod pixels {
+    /// Converts packed `RGBA8` pixels to `XRGB8888`, dropping alpha.
+    pub fn rgba_to_xrgb8888(src: &[u8], dst: &mut [u8]) {
+        let pixels = (src.len() / 4).min(dst.len() / 4);
+        let (src, dst) = (&src[..pixels * 4], &mut dst[..pixels * 4]);
+
+        #[allow(unused_mut)]
+        let mut done = 0;
 
-        let [r, g, b, _] = pixel.0;
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                // SAFETY: AVX2 support was checked at runtime.
+                done = unsafe { x86::rgba_avx2(src, dst) };
+            } else if is_x86_feature_detected!("ssse3") {
+                // SAFETY: SSSE3 support was checked at runtime.
+                done = unsafe { x86::rgba_ssse3(src, dst) };
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            done = unsafe { neon::rgba(src, dst) };
+        }
+
+        rgba_scalar(&src[done * 4..], &mut dst[done * 4..]);
+    }
+
+    /// Converts packed `RGB8` pixels to `XRGB8888`.
+    pub fn rgb_to_xrgb8888(src: &[u8], dst: &mut [u8]) {
+        let pixels = (src.len() / 3).min(dst.len() / 4);
+        let (src, dst) = (&src[..pixels * 3], &mut dst[..pixels * 4]);
+
+        #[allow(unused_mut)]
+        let mut done = 0;
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("ssse3") {
+                // SAFETY: SSSE3 support was checked at runtime.
+                done = unsafe { x86::rgb_ssse3(src, dst) };
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            done = unsafe { neon::rgb(src, dst) };
+        }
+
+        rgb_scalar(&src[done * 3..], &mut dst[done * 4..]);
+    }
+
+    /// Converts packed `RGBA8` pixels to `ARGB8888` (bytes `B, G, R, A`),
+    /// premultiplying the colors by alpha.
+    pub fn rgba_to_argb8888(src: &[u8], dst: &mut [u8]) {
+        let premultiply = |c: u8, a: u8| ((u16::from(c) * u16::from(a) + 127) / 255) as u8;
+        for (s, d) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            let a = s[3];
+            d.copy_from_slice(&[
+                premultiply(s[2], a),
+                premultiply(s[1], a),
+                premultiply(s[0], a),
+                a,
+            ]);
+        }
+    }
+
+    /// Converts packed `RGB32F` pixels in `0.0..=1.0` to `XRGB2101010`.
+    pub fn rgb32f_to_xrgb2101010(src: &[f32], dst: &mut [u8]) {
+        let ten_bit = |v: f32| (v.clamp(0.0, 1.0) * 1023.0 + 0.5) as u32;
+        for (s, d) in src.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+            let pixel = (ten_bit(s[0]) << 20) | (ten_bit(s[1]) << 10) | ten_bit(s[2]);
+            d.copy_from_slice(&pixel.to_le_bytes());
+        }
+    }
+
+    /// Converts packed `RGB16` pixels to `XRGB2101010`.
+    pub fn rgb16_to_xrgb2101010(src: &[u16], dst: &mut [u8]) {
+        for (s, d) in src.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+            d.copy_from_slice(&xrgb2101010(s).to_le_bytes());
+        }
+    }
+
+    /// Converts packed `RGBA16` pixels to `XRGB2101010`, dropping alpha.
+    pub fn rgba16_to_xrgb2101010(src: &[u16], dst: &mut [u8]) {
+        for (s, d) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            d.copy_from_slice(&xrgb2101010(s).to_le_bytes());
+        }
+    }
+
+    /// The first three 16-bit channels of `pixel` packed in `XRGB2101010`.
+    fn xrgb2101010(pixel: &[u16]) -> u32 {
+        let ten_bit = |v: u16| (u32::from(v) * 1023 + 32767) / 65535;
+        (ten_bit(pixel[0]) << 20) | (ten_bit(pixel[1]) << 10) | ten_bit(pixel[2])
+    }
+
+    pub(super) fn rgba_scalar(src: &[u8], dst: &mut [u8]) {
+        for (s, d) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            d.copy_from_slice(&[s[2], s[1], s[0], 0]);
+        }
+    }
+
+    pub(super) fn rgb_scalar(src: &[u8], dst: &mut [u8]) {
+        for (s, d) in src.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+            d.copy_from_slice(&[s[2], s[1], s[0], 0]);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    mod x86 {
+        use std::arch::x86_64::*;
+
+        /// Swaps R and B and zeroes the X byte of four RGBA pixels.
+        const RGBA_MASK: [i8; 16] = [
+            2, 1, 0, -128, 6, 5, 4, -128, 10, 9, 8, -128, 14, 13, 12, -128,
+        ];
+        /// Expands four RGB pixels (12 bytes) into four BGRX pixels.
+        const RGB_MASK: [i8; 16] = [2, 1, 0, -128, 5, 4, 3, -128, 8, 7, 6, -128, 11, 10, 9, -128];
+
+        /// Returns the number of pixels converted.
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn rgba_avx2(src: &[u8], dst: &mut [u8]) -> usize {
+            let pixels = src.len() / 4 / 8 * 8;
+            unsafe {
+                let mask = _mm256_broadcastsi128_si256(_mm_loadu_si128(RGBA_MASK.as_ptr().cast()));
+                for i in (0..pixels * 4).step_by(32) {
+                    let v = _mm256_loadu_si256(src.as_ptr().add(i).cast());
+                    let v = _mm256_shuffle_epi8(v, mask);
+                    _mm256_storeu_si256(dst.as_mut_ptr().add(i).cast(), v);
+                }
+            }
+            pixels
+        }
+
+        /// Returns the number of pixels converted.
+        #[target_feature(enable = "ssse3")]
+        pub unsafe fn rgba_ssse3(src: &[u8], dst: &mut [u8]) -> usize {
+            let pixels = src.len() / 4 / 4 * 4;
+            unsafe {
+                let mask = _mm_loadu_si128(RGBA_MASK.as_ptr().cast());
+                for i in (0..pixels * 4).step_by(16) {
+                    let v = _mm_loadu_si128(src.as_ptr().add(i).cast());
+                    _mm_storeu_si128(dst.as_mut_ptr().add(i).cast(), _mm_shuffle_epi8(v, mask));
+                }
+            }
+            pixels
+        }
+
+        /// Returns the number of pixels converted.
+        #[target_feature(enable = "ssse3")]
+        pub unsafe fn rgb_ssse3(src: &[u8], dst: &mut [u8]) -> usize {
+            // Each step reads 16 bytes but consumes 12, so keep the last
+            // load in bounds by stopping one group early.
+            let groups = (src.len() / 12).saturating_sub(1);
+            unsafe {
+                let mask = _mm_loadu_si128(RGB_MASK.as_ptr().cast());
+                for g in 0..groups {
+                    let v = _mm_loadu_si128(src.as_ptr().add(g * 12).cast());
+                    _mm_storeu_si128(
+                        dst.as_mut_ptr().add(g * 16).cast(),
+                        _mm_shuffle_epi8(v, mask),
+                    );
+                }
+            }
+            groups * 4
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    mod neon {
+        use std::arch::aarch64::*;
+
+        /// Returns the number of pixels converted.
+        #[target_feature(enable = "neon")]
+        pub unsafe fn rgba(src: &[u8], dst: &mut [u8]) -> usize {
+            let pixels = src.len() / 4 / 16 * 16;
+            unsafe {
+                let zero = vdupq_n_u8(0);
+                for i in (0..pixels * 4).step_by(64) {
+                    let v = vld4q_u8(src.as_ptr().add(i));
+                    vst4q_u8(dst.as_mut_ptr().add(i), uint8x16x4_t(v.2, v.1, v.0, zero));
+                }
+            }
+            pixels
+        }
+
+        /// Returns the number of pixels converted.
+        #[target_feature(enable = "neon")]
+        pub unsafe fn rgb(src: &[u8], dst: &mut [u8]) -> usize {
+            let pixels = src.len() / 3 / 16 * 16;
+            unsafe {
+                let zero = vdupq_n_u8(0);
+                for p in (0..pixels).step_by(16) {
+                    let v = vld3q_u8(src.as_ptr().add(p * 3));
+                    vst4q_u8(
+                        dst.as_mut_ptr().add(p * 4),
+                        uint8x16x4_t(v.2, v.1, v.0, zero),
+                    );
+                }
+            }
+            pixels
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Format, Transform, damage, has_transparency, pack_rows, physical_size, pixels, read_canvas,
+        tile_hashes, transformed, write_canvas,
+    };
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    fn test_pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn rgba_conversion_matches_scalar() {
+        // Odd pixel counts exercise both the vector body and the scalar tail.
+        for pixels in [0, 1, 3, 4, 7, 8, 15, 33, 1001] {
+            let src = test_pattern(pixels * 4);
+            let mut expected = vec![0xAA; pixels * 4];
+            let mut actual = vec![0xAA; pixels * 4];
+
+            pixels::rgba_scalar(&src, &mut expected);
+            pixels::rgba_to_xrgb8888(&src, &mut actual);
+
+            assert_eq!(actual, expected, "mismatch for {pixels} pixels");
+        }
+    }
+
+    #[test]
+    fn rgb_conversion_matches_scalar() {
+        for pixels in [0, 1, 4, 5, 8, 16, 17, 33, 1001] {
+            let src = test_pattern(pixels * 3);
+            let mut expected = vec![0xAA; pixels * 4];
+            let mut actual = vec![0xAA; pixels * 4];
+
+            pixels::rgb_scalar(&src, &mut expected);
+            pixels::rgb_to_xrgb8888(&src, &mut actual);
+
+            assert_eq!(actual, expected, "mismatch for {pixels} pixels");
+        }
+    }
+
+    #[test]
+    fn xrgb_layout_is_bgrx() {
+        let mut dst = [0u8; 4];
+        pixels::rgba_to_xrgb8888(&[10, 20, 30, 255], &mut dst);
+        assert_eq!(u32::from_le_bytes(dst), (10 << 16) | (20 << 8) | 30);
+    }
+
+    #[test]
+    fn xrgb2101010_layout_packs_ten_bits() {
+        fn pixel(canvas: &[u8], i: usize) -> u32 {
+            u32::from_le_bytes(canvas[i * 4..][..4].try_into().unwrap())
+        }
+
+        let mut dst = [0u8; 8];
+        pixels::rgb32f_to_xrgb2101010(&[1.0, 0.5, 0.0, -1.0, 2.0, 0.25], &mut dst);
+        assert_eq!(pixel(&dst, 0), (1023 << 20) | (512 << 10));
+        assert_eq!(pixel(&dst, 1), (1023 << 10) | 256);
+
+        pixels::rgb16_to_xrgb2101010(&[65535, 32768, 0], &mut dst);
+        assert_eq!(pixel(&dst, 0), (1023 << 20) | (512 << 10));
+    }
+
+    #[test]
+    fn picks_ten_bits_for_high_depth_images() {
+        assert!(!is_high_depth(&DynamicImage::new_rgb8(1, 1)));
+        assert!(is_high_depth(&DynamicImage::new_rgb16(1, 1)));
+        assert!(is_high_depth(&DynamicImage::new_rgb32f(1, 1)));
+
+        assert_eq!(Format::for_depth(false, true), Format::Xrgb8888);
+        assert_eq!(Format::for_depth(true, true), Format::Xrgb2101010);
+        assert_eq!(Format::for_depth(true, false), Format::Xrgb8888);
+    }
+
+    #[test]
+    fn keeps_alpha_of_transparent_images() {
+        let mut dst = [0u8; 8];
+        pixels::rgba_to_argb8888(&[255, 128, 0, 255, 200, 100, 50, 128], &mut dst);
+        assert_eq!(dst, [0, 128, 255, 255, 25, 50, 100, 128]);
+
+        let faint = DynamicImage::from(image::RgbaImage::from_pixel(2, 2, image::Rgba([9; 4])));
+        assert!(has_transparency(&faint));
+        let mut rgba = image::RgbaImage::from_pixel(2, 2, image::Rgba([9, 9, 9, 255]));
+        assert!(!has_transparency(&DynamicImage::from(rgba.clone())));
+        rgba.put_pixel(1, 1, image::Rgba([0; 4]));
+        assert!(has_transparency(&DynamicImage::from(rgba)));
+        assert!(!has_transparency(&DynamicImage::new_rgb8(2, 2)));
+    }
+
+    #[test]
+    fn reads_back_canvases() {
+        let rgba = image::RgbaImage::from_fn(3, 2, |x, y| {
+            image::Rgba([
+                x as u8 * 80,
+                y as u8 * 200,
+                30,
+                if x == 2 { 128 } else { 255 },
+            ])
+        });
+        let image = DynamicImage::from(rgba.clone());
+
+        // Rows padded to 16 bytes, as in a GPU buffer
+        let mut canvas = vec![0u8; 3 * 4 * 2];
+        let mut padded = vec![0u8; 16 * 2];
+        for format in [Format::Xrgb8888, Format::Argb8888, Format::Xrgb2101010] {
+            write_canvas(&mut canvas, &image, format);
+            for (dst, src) in padded.chunks_mut(16).zip(canvas.chunks_exact(12)) {
+                dst[..12].copy_from_slice(src);
+            }
+            let read = read_canvas(&padded, 3, 2, 16, format).unwrap();
+            assert_eq!(read.dimensions(), (3, 2));
+            let pixel = read.to_rgba8().get_pixel(1, 1).0;
+            assert_eq!(pixel, [80, 200, 30, 255], "{format:?}");
+        }
+
+        write_canvas(&mut canvas, &image, Format::Argb8888);
+        let read = read_canvas(&canvas, 3, 2, 12, Format::Argb8888).unwrap();
+        let [r, g, b, a] = read.to_rgba8().get_pixel(2, 0).0;
+        assert!(r.abs_diff(160) <= 1 && g == 0 && b.abs_diff(30) <= 1 && a == 128);
+        write_canvas(&mut canvas, &image, Format::Xrgb2101010);
+        let read = read_canvas(&canvas, 3, 2, 12, Format::Xrgb2101010).unwrap();
+        assert!(matches!(read, DynamicImage::ImageRgba16(_)));
+        assert!(read_canvas(&canvas[..20], 3, 2, 12, Format::Xrgb8888).is_none());
+    }
+
+    #[test]
+    fn damages_only_changed_tiles() {
+        let (width, height) = (130, 70);
+        let mut canvas = vec![0u8; width * height * 4];
+        let old = tile_hashes(&canvas, width as u32, height as u32);
+        assert_eq!(old.len(), 3 * 2);
+
+        let full = vec![[0, 0, 130, 70]];
+        assert_eq!(damage(None, &old, 130, 70), full);
+        assert_eq!(damage(Some(&old), &old, 130, 70), Vec::<[i32; 4]>::new());
+
+        // One pixel in the bottom-middle tile, which is cut off at the edge
+        canvas[(65 * width + 100) * 4] = 0xff;
+        let new = tile_hashes(&canvas, width as u32, height as u32);
+        assert_eq!(damage(Some(&old), &new, 130, 70), [[64, 64, 64, 6]]);
+
+        // Neighbouring tiles merge into one rect, clipped to the width
+        canvas[(3 * width + 129) * 4] = 0xff;
+        canvas[(3 * width + 64) * 4] = 0xff;
+        let new = tile_hashes(&canvas, width as u32, height as u32);
+        assert_eq!(
+            damage(Some(&old), &new, 130, 70),
+            [[64, 0, 66, 64], [64, 64, 64, 6]]
+        );
+
+        // A different size damages everything
+        assert_eq!(damage(Some(&old[..4]), &new, 130, 70), full);
+    }
+
+    #[test]
+    fn physical_size_rounds_fractional_scales() {
+        assert_eq!(physical_size((1920, 1080), 120), (1920, 1080));
+        // 125%
+        assert_eq!(physical_size((1536, 864), 150), (1920, 1080));
+        // 150% of an odd logical size lands on a half pixel
+        assert_eq!(physical_size((1365, 767), 180), (2048, 1151));
+        assert_eq!(physical_size((1280, 720), 240), (2560, 1440));
+    }
+
+    #[test]
+    fn transforms_turn_counter_clockwise_after_flipping() {
+        // Red top-left, green top-right, in a 2x1 image
+        let mut upright = RgbImage::new(2, 1);
+        upright.put_pixel(0, 0, Rgb([255, 0, 0]));
+        upright.put_pixel(1, 0, Rgb([0, 255, 0]));
+        let upright = DynamicImage::from(upright);
+        let red = image::Rgba([255, 0, 0, 255]);
+
+        assert!(transformed(&upright, Transform::Normal).is_none());
+
+        // Turned counter-clockwise, the right end goes to the top
+        let turned = transformed(&upright, Transform::_90).unwrap();
+        assert_eq!(turned.dimensions(), (1, 2));
+        assert_eq!(turned.get_pixel(0, 1), red);
+
+        let turned = transformed(&upright, Transform::_270).unwrap();
+        assert_eq!(turned.get_pixel(0, 0), red);
+
+        let flipped = transformed(&upright, Transform::Flipped).unwrap();
+        assert_eq!(flipped.get_pixel(1, 0), red);
+
+        let flipped = transformed(&upright, Transform::Flipped90).unwrap();
+        assert_eq!(flipped.dimensions(), (1, 2));
+        assert_eq!(flipped.get_pixel(0, 0), red);
+    }
 
-        let r = u32::from(r) << 16;
-        let g = u32::from(g) << 8;
-        let b = u32::from(b);
+    #[test]
+    fn packs_padded_rows() {
+        // 2x2 pixels in rows padded to 12 bytes
+        let plane: Vec<u8> = (0..24).collect();
+        let packed = pack_rows(&plane, 12, 2, 2);
+        assert_eq!(
+            packed,
+            [(0..8).collect::<Vec<u8>>(), (12..20).collect()].concat()
+        );
 
-        canvas[indice..indice + 4].copy_from_slice(&(r | g | b).to_le_bytes());
+        let tight: Vec<u8> = (0..16).collect();
+        assert_eq!(pack_rows(&tight, 8, 2, 2), tight);
     }
 }
@@ -0,0 +1,493 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Dynamic wallpapers, which show different images through the day.
+//!
+//! macOS dynamic wallpapers are HEIC files holding several images, with XMP
+//! metadata telling when to show each: `apple_desktop:h24` by time of day,
+//! or `apple_desktop:solar` by the sun's altitude and azimuth. Either is a
+//! base64-encoded binary property list. KDE's come as folders of image
+//! files, read by the `kde_dynamic` module. The wallpaper shows the image
+//! due now and a calloop timer switches to the next one when it is due.
+//!
+//! Sun-based wallpapers use the weather location when one is configured,
+//! or else the one GeoClue reports. Without either, the longitude is
+//! estimated from the UTC offset, at 45° north. Day and night images switch
+//! at the moment the sun crosses into and out of civil twilight.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use image::DynamicImage;
+use jiff::Zoned;
+
+use crate::{geoclue, image_formats, kde_dynamic, solar, wallpaper};
+
+/// How often a sun-based wallpaper checks where the sun is.
+const SOLAR_RECHECK: Duration = Duration::from_secs(5 * 60);
+
+/// Lowest sun altitude, in degrees, still counted as day: the end of civil
+/// twilight.
+const DAY_ALTITUDE: f64 = -6.0;
+
+/// Latitude assumed for solar wallpapers without a configured location.
+const FALLBACK_LATITUDE: f64 = 45.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeEntry {
+    /// Start, as a fraction of the day since midnight
+    pub fraction: f64,
+    pub frame: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolarEntry {
+    pub altitude: f64,
+    pub azimuth: f64,
+    pub frame: usize,
+}
+
+/// When each image of a dynamic wallpaper is shown.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    /// From the start of each entry until the start of the next
+    Time(Vec<TimeEntry>),
+    /// The entry closest to the sun's position
+    Solar(Vec<SolarEntry>),
+    /// One image while the sun is up, another after dusk
+    DayNight { day: usize, night: usize },
+}
+
+impl Schedule {
+    /// Index of the image to show at `now`, seen from `location`.
+    pub fn frame_at(&self, now: &Zoned, location: (f64, f64)) -> usize {
+        match self {
+            Schedule::Time(entries) => frame_at_fraction(entries, day_fraction(now)),
+            Schedule::Solar(entries) => {
+                let (altitude, azimuth) =
+                    solar::sun_position(now.timestamp(), location.0, location.1);
+                nearest_solar(entries, altitude, azimuth)
+            }
+            Schedule::DayNight { day, night } => {
+                let (altitude, _) = solar::sun_position(now.timestamp(), location.0, location.1);
+                if altitude >= DAY_ALTITUDE {
+                    *day
+                } else {
+                    *night
+                }
+            }
+        }
+    }
+
+    /// How long after `now` a different image may be due, seen from
+    /// `location`.
+    pub fn until_next_change(&self, now: &Zoned, location: (f64, f64)) -> Duration {
+        match self {
+            Schedule::Time(entries) => {
+                let fraction = until_next_fraction(entries, day_fraction(now));
+                Duration::from_secs_f64(fraction * 86_400.0).max(Duration::from_secs(1))
+            }
+            Schedule::Solar(_) => SOLAR_RECHECK,
+            // Just past the crossing, when the other image is due
+            Schedule::DayNight { .. } => {
+                solar::next_crossing(now.timestamp(), location.0, location.1, DAY_ALTITUDE)
+                    .and_then(|crossing| {
+                        Duration::try_from(crossing.duration_since(now.timestamp())).ok()
+                    })
+                    .map_or(SOLAR_RECHECK, |until| until + Duration::from_secs(1))
+            }
+        }
+    }
+}
+
+/// Where the images of a dynamic wallpaper are.
+enum Frames {
+    /// Top-level images of a HEIC file
+    Heic(PathBuf),
+    /// Image files
+    Files(Vec<PathBuf>),
+}
+
+/// A dynamic wallpaper being shown.
+pub struct DynamicWallpaper {
+    frames: Frames,
+    schedule: Schedule,
+    location: (f64, f64),
+    shown: Option<usize>,
+}
+
+impl DynamicWallpaper {
+    /// Read the schedule of `path` if it is a dynamic HEIC wallpaper or a
+    /// KDE dynamic wallpaper.
+    pub fn open(path: &Path) -> Option<Self> {
+        let (frames, schedule) = if image_formats::is_heif(path) {
+            let schedule = parse_schedule(&image_formats::heif_xmp(path)?)?;
+            (Frames::Heic(path.to_path_buf()), schedule)
+        } else if kde_dynamic::is_dynamic(path) {
+            let (files, schedule) = kde_dynamic::open(path)?;
+            (Frames::Files(files), schedule)
+        } else {
+            return None;
+        };
+        tracing::debug!(?schedule, "dynamic wallpaper: {}", path.display());
+
+        Some(Self {
+            frames,
+            schedule,
+            location: location(),
+            shown: None,
+        })
+    }
+
+    /// Show `day` while the sun is up and `night` after dusk, switching as
+    /// the sun crosses the end of civil twilight.
+    pub fn day_night(day: PathBuf, night: PathBuf) -> Self {
+        Self {
+            frames: Frames::Files(vec![day, night]),
+            schedule: Schedule::DayNight { day: 0, night: 1 },
+            location: location(),
+            shown: None,
+        }
+    }
+
+    /// Decode the image due now, if it isn't the one shown.
+    pub fn update(&mut self) -> Option<DynamicImage> {
+        // GeoClue may have found the location since
+        self.location = location();
+        let frame = self.schedule.frame_at(&Zoned::now(), self.location);
+        if self.shown == Some(frame) {
+            return None;
+        }
+
+        let image = match &self.frames {
+            Frames::Heic(path) => image_formats::decode_heif_frame(path, frame),
+            Frames::Files(files) => files
+                .get(frame)
+                .ok_or_else(|| eyre::eyre!("dynamic wallpaper has no image {frame}"))
+                .and_then(|path| wallpaper::decode(path)),
+        };
+        match image {
+            Ok(image) => {
+                self.shown = Some(frame);
+                Some(image)
+            }
+            Err(why) => {
+                tracing::warn!(?why, frame, "could not decode dynamic wallpaper image");
+                None
+            }
+        }
+    }
+
+    /// How long until a different image may be due.
+    pub fn until_next_change(&self) -> Duration {
+        self.schedule
+            .until_next_change(&Zoned::now(), self.location)
+    }
+}
+
+/// The weather location, the one GeoClue found, or a guess from the UTC
+/// offset.
+pub(crate) fn location() -> (f64, f64) {
+    glowberry_config::context()
+        .ok()
+        .and_then(|context| context.weather_config().location())
+        .or_else(geoclue::location)
+        .unwrap_or_else(|| {
+            let offset = Zoned::now().offset().seconds();
+            (FALLBACK_LATITUDE, f64::from(offset) / 240.0)
+        })
+}
+
+/// Read the schedule out of a dynamic wallpaper's XMP metadata.
+#[cfg(feature = "heif")]
+fn parse_schedule(xmp: &str) -> Option<Schedule> {
+    use base64::Engine;
+    use plist::Value;
+
+    let property_list = |key| {
+        let encoded = xmp_value(xmp, key)?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .ok()?;
+        Value::from_reader(std::io::Cursor::new(bytes)).ok()
+    };
+    let number = |value: &Value| {
+        value
+            .as_real()
+            .or_else(|| value.as_signed_integer().map(|n| n as f64))
+    };
+    let entries = |list: &Value, key| -> Option<Vec<plist::Dictionary>> {
+        let entries = list.as_dictionary()?.get(key)?.as_array()?;
+        entries
+            .iter()
+            .map(|entry| entry.as_dictionary().cloned())
+            .collect()
+    };
+
+    if let Some(list) = property_list("apple_desktop:h24") {
+        let mut entries = entries(&list, "ti")?
+            .iter()
+            .map(|entry| {
+                Some(TimeEntry {
+                    fraction: number(entry.get("t")?)?,
+                    frame: entry.get("i")?.as_unsigned_integer()? as usize,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.fraction.total_cmp(&b.fraction));
+        return (!entries.is_empty()).then_some(Schedule::Time(entries));
+    }
+
+    let list = property_list("apple_desktop:solar")?;
+    let entries = entries(&list, "si")?
+        .iter()
+        .map(|entry| {
+            Some(SolarEntry {
+                altitude: number(entry.get("a")?)?,
+                azimuth: number(entry.get("z")?)?,
+                frame: entry.get("i")?.as_unsigned_integer()? as usize,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!entries.is_empty()).then_some(Schedule::Solar(entries))
+}
+
+#[cfg(not(feature = "heif"))]
+fn parse_schedule(_xmp: &str) -> Option<Schedule> {
+    None
+}
+
+/// The value of `key` in XMP, given either as an attribute or an element.
+#[cfg_attr(not(feature = "heif"), allow(dead_code))]
+fn xmp_value<'a>(xmp: &'a str, key: &str) -> Option<&'a str> {
+    let attribute = format!("{key}=\"");
+    if let Some(start) = xmp.find(&attribute) {
+        return xmp[start + attribute.len()..].split('"').next();
+    }
+
+    let open = format!("<{key}>");
+    let start = xmp.find(&open)? + open.len();
+    let end = start + xmp[start..].find(&format!("</{key}>"))?;
+    Some(xmp[start..end].trim())
+}
+
+/// Time since local midnight as a fraction of the day.
+fn day_fraction(now: &Zoned) -> f64 {
+    let time = now.time();
+    let seconds =
+        i32::from(time.hour()) * 3600 + i32::from(time.minute()) * 60 + i32::from(time.second());
+    (f64::from(seconds) + f64::from(time.subsec_nanosecond()) / 1e9) / 86_400.0
+}
+
+/// The last of `entries`, sorted by start, to start by `fraction`. Before
+/// the first one, yesterday's last one is still shown.
+fn frame_at_fraction(entries: &[TimeEntry], fraction: f64) -> usize {
+    entries
+        .iter()
+        .rev()
+        .find(|entry| entry.fraction <= fraction)
+        .or(entries.last())
+        .map_or(0, |entry| entry.frame)
+}
+
+/// Fraction of the day from `fraction` until the next entry starts.
+fn until_next_fraction(entries: &[TimeEntry], fraction: f64) -> f64 {
+    match entries.iter().find(|entry| entry.fraction > fraction) {
+        Some(next) => next.fraction - fraction,
+        None => entries.first().map_or(1.0, |first| first.fraction + 1.0) - fraction,
+    }
+}
+
+/// The entry whose sun position is closest to `altitude`/`azimuth`.
+fn nearest_solar(entries: &[SolarEntry], altitude: f64, azimuth: f64) -> usize {
+    let distance = |entry: &SolarEntry| {
+        let azimuth = (entry.azimuth - azimuth).rem_euclid(360.0);
+        let azimuth = azimuth.min(360.0 - azimuth);
+        (entry.altitude - altitude).hypot(azimuth)
+    };
+    entries
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .map_or(0, |entry| entry.frame)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "heif")]
+    use super::Schedule;
+    use super::{
+        SolarEntry, TimeEntry, frame_at_fraction, nearest_solar, parse_schedule,
+        until_next_fraction, xmp_value,
+    };
+
+    fn day() -> Vec<TimeEntry> {
+        [(0.25, 0), (0.5, 1), (0.8, 2)]
+            .into_iter()
+            .map(|(fraction, frame)| TimeEntry { fraction, frame })
+            .collect()
+    }
+
+    #[test]
+    fn shows_the_entry_started_last() {
+        let entries = day();
+        assert_eq!(frame_at_fraction(&entries, 0.3), 0);
+        assert_eq!(frame_at_fraction(&entries, 0.5), 1);
+        assert_eq!(frame_at_fraction(&entries, 0.9), 2);
+        // Before dawn, last night's image stays up
+        assert_eq!(frame_at_fraction(&entries, 0.1), 2);
+    }
+
+    #[test]
+    fn waits_for_the_next_entry() {
+        let entries = day();
+        assert!((until_next_fraction(&entries, 0.3) - 0.2).abs() < 1e-9);
+        assert!((until_next_fraction(&entries, 0.9) - 0.35).abs() < 1e-9);
+    }
+
+    #[test]
+    fn picks_the_closest_sun_position() {
+        let entries = vec![
+            SolarEntry {
+                altitude: 10.0,
+                azimuth: 90.0,
+                frame: 0,
+            },
+            SolarEntry {
+                altitude: 60.0,
+                azimuth: 180.0,
+                frame: 1,
+            },
+            SolarEntry {
+                altitude: 10.0,
+                azimuth: 270.0,
+                frame: 2,
+            },
+            SolarEntry {
+                altitude: -40.0,
+                azimuth: 0.0,
+                frame: 3,
+            },
+        ];
+        assert_eq!(nearest_solar(&entries, 15.0, 100.0), 0);
+        assert_eq!(nearest_solar(&entries, 15.0, 260.0), 2);
+        assert_eq!(nearest_solar(&entries, -35.0, 350.0), 3);
+    }
+
+    #[test]
+    fn reads_xmp_attributes_and_elements() {
+        let attribute = r#"<rdf:Description apple_desktop:h24="YnBsaXN0" />"#;
+        assert_eq!(xmp_value(attribute, "apple_desktop:h24"), Some("YnBsaXN0"));
+
+        let element = "<apple_desktop:solar>\n  YnBsaXN0\n</apple_desktop:solar>";
+        assert_eq!(xmp_value(element, "apple_desktop:solar"), Some("YnBsaXN0"));
+        assert_eq!(xmp_value(element, "apple_desktop:h24"), None);
+    }
+
+    /// XMP holding `entries` under `key` of a binary property list stored in
+    /// `attribute`, as macOS dynamic wallpapers do.
+    #[cfg(feature = "heif")]
+    fn xmp(attribute: &str, key: &str, entries: &[&[(&str, plist::Value)]]) -> String {
+        use base64::Engine;
+        use plist::Value;
+
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                Value::Dictionary(
+                    entry
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect(),
+                )
+            })
+            .collect();
+        let list = Value::Dictionary(
+            [(key.to_string(), Value::Array(entries))]
+                .into_iter()
+                .collect(),
+        );
+        let mut bytes = Vec::new();
+        list.to_writer_binary(&mut bytes).unwrap();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+        format!(r#"<rdf:Description {attribute}="{encoded}" />"#)
+    }
+
+    #[cfg(feature = "heif")]
+    #[test]
+    fn parses_time_schedules_in_order() {
+        use plist::Value;
+
+        let xmp = xmp(
+            "apple_desktop:h24",
+            "ti",
+            &[
+                &[("t", Value::from(0.75)), ("i", Value::from(1u64))],
+                // Whole numbers may be stored as integers
+                &[("t", Value::from(0u64)), ("i", Value::from(0u64))],
+            ],
+        );
+        assert_eq!(
+            parse_schedule(&xmp),
+            Some(Schedule::Time(vec![
+                TimeEntry {
+                    fraction: 0.0,
+                    frame: 0
+                },
+                TimeEntry {
+                    fraction: 0.75,
+                    frame: 1
+                },
+            ]))
+        );
+    }
+
+    #[cfg(feature = "heif")]
+    #[test]
+    fn parses_solar_schedules() {
+        use plist::Value;
+
+        let xmp = xmp(
+            "apple_desktop:solar",
+            "si",
+            &[&[
+                ("a", Value::from(-12.5)),
+                ("z", Value::from(270.0)),
+                ("i", Value::from(3u64)),
+            ]],
+        );
+        assert_eq!(
+            parse_schedule(&xmp),
+            Some(Schedule::Solar(vec![SolarEntry {
+                altitude: -12.5,
+                azimuth: 270.0,
+                frame: 3,
+            }]))
+        );
+    }
+
+    #[cfg(feature = "heif")]
+    #[test]
+    fn rejects_incomplete_schedules() {
+        use plist::Value;
+
+        // An entry without its image, no entries, or no schedule at all
+        let missing = xmp("apple_desktop:h24", "ti", &[&[("t", Value::from(0.5))]]);
+        assert_eq!(parse_schedule(&missing), None);
+        assert_eq!(parse_schedule(&xmp("apple_desktop:h24", "ti", &[])), None);
+        assert_eq!(parse_schedule(&xmp("apple_desktop:apr", "ap", &[])), None);
+        assert_eq!(
+            parse_schedule(r#"<rdf:Description apple_desktop:h24="not base64!" />"#),
+            None
+        );
+    }
+
+    #[cfg(not(feature = "heif"))]
+    #[test]
+    fn has_no_schedules_without_heif() {
+        assert_eq!(
+            parse_schedule(r#"<rdf:Description apple_desktop:h24="YnBsaXN0" />"#),
+            None
+        );
+    }
+}
@@ -1,38 +1,96 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use crate::{
-    fragment_canvas, gpu, img_source,
+    audio::AudioMonitor,
+    color_management::{self, ColorManager},
+    dmabuf, draw, fragment_canvas,
+    frame_watchdog::{FrameWatchdog, ShaderFrame},
+    gpu, identify,
+    idle::IdleMonitor,
+    img_source,
+    night_light::{self, NightLightMonitor},
+    now_playing::NowPlayingMonitor,
+    output_power::OutputPower,
+    pointer::{PointerEvent, PointerSeat, PointerTracker},
+    screen_share::ScreenShareMonitor,
+    session_lock::SessionLockMonitor,
+    sysstats::SystemMonitor,
+    theme_mode, timelapse,
+    toplevels::{ToplevelEvent, Toplevels},
     upower::{PowerMonitorHandle, PowerStateChanged, start_power_monitor},
+    video::VideoTexture,
     wallpaper::Wallpaper,
+    weather::{self, WeatherMonitor},
+    workspaces::Workspaces,
 };
 use cosmic_config::{CosmicConfigEntry, calloop::ConfigWatchSource};
+use cosmic_protocols::toplevel_info::v1::client::{
+    zcosmic_toplevel_handle_v1, zcosmic_toplevel_info_v1,
+};
 use eyre::Context;
 use glowberry_config::{
-    Config, Source,
-    power_saving::{OnBatteryAction, PowerSavingConfig},
+    Config, Entry, GpuBackend, Source,
+    etiquette::EtiquetteConfig,
+    night_light::{NEUTRAL_TEMPERATURE, NightLightConfig},
+    now_playing::NowPlayingConfig,
+    power_saving::{OnBatteryAction, PowerSavingConfig, ScreenCaptureAction},
     state::State,
+    weather::WeatherConfig,
 };
 use sctk::{
-    compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
-    output::{OutputHandler, OutputInfo, OutputState},
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_compositor, delegate_dmabuf, delegate_layer, delegate_output, delegate_registry,
+    delegate_shm,
+    dmabuf::{DmabufFeedback, DmabufHandler, DmabufState},
+    globals::GlobalData,
+    output::{Mode, OutputHandler, OutputInfo, OutputState},
     reexports::{
-        calloop,
+        calloop::{
+            self, RegistrationToken,
+            timer::{TimeoutAction, Timer},
+        },
         calloop_wayland_source::WaylandSource,
         client::{
-            Connection, Dispatch, Proxy, QueueHandle, Weak, delegate_noop,
+            Connection, Dispatch, Proxy, QueueHandle, Weak,
+            backend::ObjectId,
+            delegate_noop, event_created_child,
             globals::registry_queue_init,
             protocol::{
+                wl_buffer, wl_callback,
                 wl_output::{self, WlOutput},
-                wl_surface,
+                wl_pointer, wl_seat, wl_shm, wl_surface,
             },
         },
+        protocols::ext::foreign_toplevel_list::v1::client::{
+            ext_foreign_toplevel_handle_v1, ext_foreign_toplevel_list_v1,
+        },
+        protocols::ext::idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1},
+        protocols::ext::workspace::v1::client::{
+            ext_workspace_group_handle_v1, ext_workspace_handle_v1, ext_workspace_manager_v1,
+        },
         protocols::wp::{
+            color_management::v1::client::{
+                wp_color_management_surface_v1, wp_color_manager_v1,
+                wp_image_description_creator_params_v1, wp_image_description_v1,
+            },
+            cursor_shape::v1::client::{wp_cursor_shape_device_v1, wp_cursor_shape_manager_v1},
             fractional_scale::v1::client::{
                 wp_fractional_scale_manager_v1, wp_fractional_scale_v1,
             },
+            linux_dmabuf::zv1::client::{
+                zwp_linux_buffer_params_v1, zwp_linux_dmabuf_feedback_v1, zwp_linux_dmabuf_v1,
+            },
+            presentation_time::client::{wp_presentation, wp_presentation_feedback},
             viewporter::client::{wp_viewport, wp_viewporter},
         },
+        protocols_wlr::{
+            foreign_toplevel::v1::client::{
+                zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1,
+            },
+            output_power_management::v1::client::{
+                zwlr_output_power_manager_v1, zwlr_output_power_v1,
+            },
+        },
     },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
@@ -45,6 +103,9 @@ use sctk::{
     },
     shm::{Shm, ShmHandler, slot::SlotPool},
 };
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 use tracing::error;
 
 /// Access glibc malloc tunables.
@@ -73,11 +134,79 @@ mod malloc {
     }
 }
 
+/// Ask the allocator to return freed memory to the OS (no-op outside glibc).
+pub(crate) fn malloc_trim() {
+    #[cfg(target_env = "gnu")]
+    malloc::trim();
+}
+
+/// Shader texture caps from the daemon config.
+fn texture_budget(context: &glowberry_config::Context) -> fragment_canvas::TextureBudget {
+    fragment_canvas::TextureBudget {
+        max_dimension: context.shader_max_texture_size(),
+        max_bytes: u64::from(context.shader_vram_budget()) * 1024 * 1024,
+    }
+}
+
+/// Bytes of decoded frames an animated image may keep, from the daemon config.
+fn animation_cache(context: &glowberry_config::Context) -> u64 {
+    u64::from(context.animation_cache_size()) * 1024 * 1024
+}
+
+/// Disk space scaled wallpapers may be cached in, from the daemon config.
+fn scaled_cache(context: &glowberry_config::Context) -> u64 {
+    u64::from(context.scaled_cache_size()) * 1024 * 1024
+}
+
+/// Memory the wallpapers may hold, from the daemon config.
+fn memory_budget(context: &glowberry_config::Context) -> u64 {
+    u64::from(context.memory_budget()) * 1024 * 1024
+}
+
+/// Time between checks of URL wallpapers for a new image, from the daemon config.
+fn remote_refresh(context: &glowberry_config::Context) -> Duration {
+    Duration::from_secs(u64::from(context.remote_refresh_minutes()) * 60)
+}
+
+/// GPU adapter selection from the daemon config.
+fn adapter_options(backend: Option<GpuBackend>) -> gpu::AdapterOptions {
+    let mut options = glowberry_config::context()
+        .map(|context| gpu::AdapterOptions {
+            adapter: context.gpu_adapter(),
+            prefer_low_power: context.prefer_low_power(),
+            backend: context.gpu_backend(),
+        })
+        .unwrap_or_default();
+    if let Some(backend) = backend {
+        options.backend = backend;
+    }
+    options
+}
+
+/// Whether screen shares need watching: slideshows hold their image during
+/// them, or shaders may pause.
+fn watches_screen_share(etiquette: &EtiquetteConfig, power_saving: &PowerSavingConfig) -> bool {
+    etiquette.pause_rotation_while_sharing
+        || power_saving.on_screen_capture != ScreenCaptureAction::KeepAnimating
+}
+
 /// GPU state for shader-based live wallpapers.
 pub struct GpuLayerState {
     surface: wgpu::Surface<'static>,
     surface_config: wgpu::SurfaceConfiguration,
     canvas: fragment_canvas::FragmentCanvas,
+    /// Whether a frame callback queued this layer for the next batch render.
+    frame_pending: bool,
+    /// HDR image description of the surface, for `output: hdr` shaders.
+    color: Option<color_management::SurfaceColor>,
+    /// Whether the layer stopped requesting frame callbacks because its
+    /// output's display is off or a fullscreen window hides it.
+    output_paused: bool,
+    /// Whether the layer stopped requesting frame callbacks because its
+    /// canvas shows the same frame until something changes.
+    idle: bool,
+    /// Requests a frame callback again if the compositor stops sending them.
+    watchdog: FrameWatchdog<GlowBerry>,
 }
 
 // Manual Debug impl since wgpu types don't implement Debug
@@ -92,12 +221,15 @@ impl std::fmt::Debug for GpuLayerState {
 #[derive(Debug)]
 pub struct EngineConfig {
     pub enable_wayland: bool,
+    /// Graphics API for shaders, overriding the `gpu-backend` config key.
+    pub gpu_backend: Option<GpuBackend>,
 }
 
 impl Default for EngineConfig {
     fn default() -> Self {
         Self {
             enable_wayland: true,
+            gpu_backend: None,
         }
     }
 }
@@ -111,6 +243,7 @@ impl BackgroundEngine {
         if !config.enable_wayland {
             return Ok(());
         }
+        let gpu_backend = config.gpu_backend;
 
         // Prevents glibc from hoarding memory via memory fragmentation.
         #[cfg(target_env = "gnu")]
@@ -177,15 +310,165 @@ impl BackgroundEngine {
                                     changes_applied = true;
                                 }
 
+                                glowberry_config::AUDIO_REACTIVE => {
+                                    let enabled = conf_context.audio_reactive();
+                                    tracing::debug!(enabled, "audio-reactive setting changed");
+                                    state.set_audio_reactive(enabled);
+                                }
+
+                                glowberry_config::weather::WEATHER_ENABLED
+                                | glowberry_config::weather::WEATHER_LATITUDE
+                                | glowberry_config::weather::WEATHER_LONGITUDE
+                                | glowberry_config::weather::WEATHER_REFRESH_MINUTES => {
+                                    tracing::debug!(key, "weather config changed");
+                                    state.schedule_weather_restart();
+                                }
+
+                                glowberry_config::now_playing::NOW_PLAYING_ENABLED
+                                | glowberry_config::now_playing::NOW_PLAYING_BLUR
+                                | glowberry_config::now_playing::NOW_PLAYING_DIM => {
+                                    tracing::debug!(key, "album art config changed");
+                                    state.set_now_playing_config(
+                                        &conf_context.now_playing_config(),
+                                    );
+                                }
+
+                                glowberry_config::night_light::NIGHT_LIGHT_ENABLED
+                                | glowberry_config::night_light::NIGHT_LIGHT_TEMPERATURE => {
+                                    tracing::debug!(key, "night light config changed");
+                                    state.set_night_light_config(
+                                        &conf_context.night_light_config(),
+                                    );
+                                }
+
+                                glowberry_config::FALLBACK_RESOLUTION => {
+                                    state.fallback_resolution = conf_context.fallback_resolution();
+                                    tracing::debug!(
+                                        fallback = ?state.fallback_resolution,
+                                        "fallback resolution changed"
+                                    );
+                                    changes_applied = true;
+                                }
+
+                                glowberry_config::SHADER_MAX_TEXTURE_SIZE
+                                | glowberry_config::SHADER_VRAM_BUDGET => {
+                                    state.texture_budget = texture_budget(&conf_context);
+                                    tracing::debug!(
+                                        budget = ?state.texture_budget,
+                                        "shader texture budget changed"
+                                    );
+                                    for idx in 0..state.wallpapers.len() {
+                                        state.reload_shader(idx);
+                                    }
+                                }
+
+                                glowberry_config::ANIMATION_CACHE_SIZE => {
+                                    state.animation_cache = animation_cache(&conf_context);
+                                    tracing::debug!(
+                                        bytes = state.animation_cache,
+                                        "animation cache size changed"
+                                    );
+                                    // Applies to animations opened from now on
+                                    for wallpaper in &mut state.wallpapers {
+                                        wallpaper.animation_cache = state.animation_cache;
+                                    }
+                                }
+
+                                glowberry_config::SCALED_CACHE_SIZE => {
+                                    state.scaled_cache = scaled_cache(&conf_context);
+                                    tracing::debug!(
+                                        bytes = state.scaled_cache,
+                                        "scaled wallpaper cache size changed"
+                                    );
+                                    // Applies to images scaled from now on
+                                    for wallpaper in &mut state.wallpapers {
+                                        wallpaper.scaled_cache = state.scaled_cache;
+                                    }
+                                }
+
+                                glowberry_config::MEMORY_BUDGET => {
+                                    state.memory_budget = memory_budget(&conf_context);
+                                    tracing::debug!(
+                                        bytes = state.memory_budget,
+                                        "wallpaper memory budget changed"
+                                    );
+                                    state.enforce_memory_budget();
+                                }
+
+                                glowberry_config::REMOTE_REFRESH => {
+                                    state.remote_refresh = remote_refresh(&conf_context);
+                                    tracing::debug!(
+                                        refresh = ?state.remote_refresh,
+                                        "remote wallpaper refresh interval changed"
+                                    );
+                                    for wallpaper in &mut state.wallpapers {
+                                        wallpaper.set_remote_refresh(state.remote_refresh);
+                                    }
+                                }
+
+                                glowberry_config::SHADER_MIN_FRAME_RATE => {
+                                    state.shader_min_frame_rate =
+                                        conf_context.shader_min_frame_rate();
+                                    tracing::debug!(
+                                        min_frame_rate = state.shader_min_frame_rate,
+                                        "shader frame rate floor changed"
+                                    );
+                                    let min_frame_rate = state.shader_min_frame_rate;
+                                    for gpu_state in state
+                                        .wallpapers
+                                        .iter_mut()
+                                        .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+                                        .filter_map(|layer| layer.gpu_state.as_mut())
+                                    {
+                                        gpu_state.canvas.set_min_frame_rate(min_frame_rate);
+                                    }
+                                }
+
+                                glowberry_config::IDENTIFY_OUTPUTS => {
+                                    tracing::debug!("identify requested");
+                                    state.identify_outputs();
+                                }
+
+                                glowberry_config::QUEUE_EDIT => {
+                                    if let Some(edit) = conf_context.queue_edit()
+                                        && let Some(wallpaper) = state
+                                            .wallpapers
+                                            .iter_mut()
+                                            .find(|w| w.entry.output == edit.output())
+                                    {
+                                        wallpaper.edit_queue(&edit);
+                                    }
+                                }
+
+                                glowberry_config::TIMELAPSE_ENABLED
+                                | glowberry_config::TIMELAPSE_INTERVAL => {
+                                    let interval = conf_context
+                                        .timelapse_enabled()
+                                        .then(|| conf_context.timelapse_interval());
+                                    tracing::debug!(?interval, "time-lapse setting changed");
+                                    state.set_timelapse(interval);
+                                }
+
                                 // Power saving config keys
                                 glowberry_config::power_saving::ADJUST_ON_BATTERY
                                 | glowberry_config::power_saving::ON_BATTERY_ACTION
                                 | glowberry_config::power_saving::PAUSE_ON_LOW_BATTERY
                                 | glowberry_config::power_saving::LOW_BATTERY_THRESHOLD
-                                | glowberry_config::power_saving::PAUSE_ON_LID_CLOSED => {
+                                | glowberry_config::power_saving::PAUSE_ON_LID_CLOSED
+                                | glowberry_config::power_saving::RELEASE_GPU_AFTER
+                                | glowberry_config::power_saving::PAUSE_WHEN_IDLE
+                                | glowberry_config::power_saving::PAUSE_ON_FULLSCREEN
+                                | glowberry_config::power_saving::PAUSE_ON_COVERED
+                                | glowberry_config::power_saving::COVERAGE_THRESHOLD
+                                | glowberry_config::power_saving::ON_SCREEN_CAPTURE => {
                                     tracing::debug!(key, "power saving config changed");
                                     let was_paused = state.should_pause_animation();
                                     state.power_saving_config = conf_context.power_saving_config();
+                                    state.update_screen_share_monitor();
+                                    let idle_minutes = state.power_saving_config.pause_when_idle_minutes;
+                                    if let Some(idle_monitor) = &mut state.idle_monitor {
+                                        idle_monitor.set_timeout(idle_minutes, &state.qh);
+                                    }
                                     tracing::info!(config = ?state.power_saving_config, "Updated power saving config");
                                     // Force reapply frame rates with new config
                                     state.reapply_frame_rates();
@@ -195,6 +478,15 @@ impl BackgroundEngine {
                                         tracing::info!("Resuming shader animation after config change");
                                         state.request_frame_callbacks();
                                     }
+                                    // Layers behind windows may no longer be paused
+                                    for output in state.active_outputs.clone() {
+                                        state.resume_output(&output);
+                                    }
+                                }
+
+                                glowberry_config::etiquette::PAUSE_ROTATION_WHILE_SHARING => {
+                                    tracing::debug!(key, "etiquette config changed");
+                                    state.set_etiquette_config(conf_context.etiquette_config());
                                 }
 
                                 _ => {
@@ -212,8 +504,10 @@ impl BackgroundEngine {
                         if changes_applied {
                             state.apply_backgrounds();
 
-                            #[cfg(target_env = "gnu")]
-                            malloc::trim();
+                            // Evicting trims already
+                            if !state.enforce_memory_budget() {
+                                malloc_trim();
+                            }
 
                             tracing::debug!(
                                 same_on_all = state.config.same_on_all,
@@ -237,12 +531,140 @@ impl BackgroundEngine {
             }
         };
 
+        // Show the entries' dark sources while the COSMIC theme is dark
+        let mode_config = theme_mode::config();
+        let dark = mode_config.as_ref().is_some_and(theme_mode::is_dark);
+        if let Some(mode_config) = mode_config {
+            match ConfigWatchSource::new(&mode_config) {
+                Ok(source) => {
+                    event_loop
+                        .handle()
+                        .insert_source(source, |(config, keys), (), state| {
+                            if keys.iter().any(|key| key == theme_mode::IS_DARK) {
+                                state.set_dark(theme_mode::is_dark(&config));
+                            }
+                        })
+                        .expect("failed to insert theme mode watching source into event loop");
+                }
+                Err(why) => tracing::warn!(?why, "failed to watch the theme mode"),
+            }
+        }
+
         // Load power saving configuration
         let power_saving_config = glowberry_config::context()
             .map(|ctx| ctx.power_saving_config())
             .unwrap_or_default();
         tracing::info!(?power_saving_config, "Loaded power saving config");
 
+        // Start audio capture for audio-reactive shaders if enabled
+        let audio_monitor = glowberry_config::context()
+            .is_ok_and(|ctx| ctx.audio_reactive())
+            .then(AudioMonitor::start)
+            .flatten();
+
+        // Start the weather provider for weather-aware shaders if enabled
+        let weather_monitor = glowberry_config::context()
+            .map(|ctx| ctx.weather_config())
+            .ok()
+            .filter(|config| config.enabled)
+            .and_then(|config| WeatherMonitor::start(&config));
+
+        // Show the playing track's album art if enabled
+        let (now_playing_tx, now_playing_rx) = calloop::channel::channel();
+        let now_playing = glowberry_config::context()
+            .map(|ctx| ctx.now_playing_config())
+            .ok()
+            .filter(|config| config.enabled)
+            .and_then(|config| NowPlayingMonitor::start(&config, now_playing_tx.clone()));
+        event_loop
+            .handle()
+            .insert_source(now_playing_rx, |event, _, state| {
+                if let calloop::channel::Event::Msg(art) = event {
+                    state.show_now_playing(art);
+                }
+            })
+            .expect("failed to insert album art channel into event loop");
+
+        // Warm the wallpapers with the night light if enabled
+        let (night_light_tx, night_light_rx) = calloop::channel::channel();
+        let night_light = glowberry_config::context()
+            .map(|ctx| ctx.night_light_config())
+            .ok()
+            .filter(|config| config.enabled)
+            .and_then(|config| NightLightMonitor::start(&config, night_light_tx.clone()));
+        event_loop
+            .handle()
+            .insert_source(night_light_rx, |event, _, state| {
+                if let calloop::channel::Event::Msg(temperature) = event {
+                    state.set_night_light_tint(night_light::tint(temperature));
+                }
+            })
+            .expect("failed to insert night light channel into event loop");
+        if let Some(cosmic_night_light) = night_light::cosmic_config() {
+            match ConfigWatchSource::new(&cosmic_night_light) {
+                Ok(source) => {
+                    event_loop
+                        .handle()
+                        .insert_source(source, |_, (), state| {
+                            if let Some(night_light) = state.night_light.as_ref() {
+                                night_light.cosmic_changed();
+                            }
+                        })
+                        .expect("failed to insert night light watching source into event loop");
+                }
+                Err(why) => tracing::debug!(?why, "failed to watch COSMIC's night light"),
+            }
+        }
+
+        // Watch for screen sharing if rotation or animation may pause meanwhile
+        let etiquette_config = glowberry_config::context()
+            .map(|ctx| ctx.etiquette_config())
+            .unwrap_or_default();
+        let (screen_share_tx, screen_share_rx) = calloop::channel::channel();
+        let screen_share_monitor = watches_screen_share(&etiquette_config, &power_saving_config)
+            .then(|| ScreenShareMonitor::start(screen_share_tx.clone()))
+            .flatten();
+        event_loop
+            .handle()
+            .insert_source(screen_share_rx, |event, _, state| {
+                if let calloop::channel::Event::Msg(active) = event {
+                    state.on_screen_share_changed(active);
+                }
+            })
+            .expect("failed to insert screen-share channel into event loop");
+
+        let fallback_resolution = glowberry_config::context()
+            .map(|ctx| ctx.fallback_resolution())
+            .unwrap_or(glowberry_config::DEFAULT_FALLBACK_RESOLUTION);
+
+        let texture_budget = glowberry_config::context()
+            .map(|ctx| texture_budget(&ctx))
+            .unwrap_or_default();
+
+        let shader_min_frame_rate = glowberry_config::context()
+            .map(|ctx| ctx.shader_min_frame_rate())
+            .unwrap_or(glowberry_config::DEFAULT_SHADER_MIN_FRAME_RATE);
+
+        let animation_cache = glowberry_config::context().map_or(
+            u64::from(glowberry_config::DEFAULT_ANIMATION_CACHE_SIZE) * 1024 * 1024,
+            |ctx| animation_cache(&ctx),
+        );
+
+        let scaled_cache = glowberry_config::context().map_or(
+            u64::from(glowberry_config::DEFAULT_SCALED_CACHE_SIZE) * 1024 * 1024,
+            |ctx| scaled_cache(&ctx),
+        );
+
+        let memory_budget = glowberry_config::context().map_or(
+            u64::from(glowberry_config::DEFAULT_MEMORY_BUDGET) * 1024 * 1024,
+            |ctx| memory_budget(&ctx),
+        );
+
+        let remote_refresh = glowberry_config::context().map_or(
+            Duration::from_secs(u64::from(glowberry_config::DEFAULT_REMOTE_REFRESH) * 60),
+            |ctx| remote_refresh(&ctx),
+        );
+
         // Create channel for power state change notifications
         let (power_notify_tx, power_notify_rx) = calloop::channel::channel();
 
@@ -265,40 +687,48 @@ impl BackgroundEngine {
             })
             .expect("failed to insert power notification channel into event loop");
 
+        // Watch for the lock screen, which hides every shader layer
+        let (session_lock_tx, session_lock_rx) = calloop::channel::channel();
+        let session_lock = SessionLockMonitor::start(session_lock_tx);
+        if session_lock.is_none() {
+            tracing::warn!(
+                "Session lock detection unavailable, shaders keep animating while locked"
+            );
+        }
+        event_loop
+            .handle()
+            .insert_source(session_lock_rx, |event, _, state| {
+                if let calloop::channel::Event::Msg(locked) = event {
+                    state.on_session_lock_changed(locked);
+                }
+            })
+            .expect("failed to insert session lock channel into event loop");
+
         let source_tx = img_source::img_source(&event_loop.handle(), |state, source, event| {
             use notify::event::{ModifyKind, RenameMode};
 
+            // Shader file or background image changed
+            if let Some(idx) = state
+                .wallpapers
+                .iter()
+                .position(|w| w.entry.output == source && w.source().is_shader())
+            {
+                state.on_shader_file_event(idx, &event);
+                return;
+            }
+
             match event.kind {
-                // Shader file content changed — hot-reload
-                notify::EventKind::Modify(ModifyKind::Data(_)) => {
-                    for (idx, w) in state.wallpapers.iter().enumerate() {
+                notify::EventKind::Create(_)
+                | notify::EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                    for idx in 0..state.wallpapers.len() {
+                        let w = &mut state.wallpapers[idx];
                         if w.entry.output != source {
                             continue;
                         }
-                        if matches!(w.entry.source, Source::Shader(_)) {
-                            tracing::debug!(
-                                output = source,
-                                "Shader file modified, triggering hot-reload"
-                            );
-                            state.reload_shader(idx);
-                            return;
-                        }
-                    }
-                }
-
-                notify::EventKind::Create(_)
-                | notify::EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
-                    for w in state
-                        .wallpapers
-                        .iter_mut()
-                        .filter(|w| w.entry.output == source)
-                    {
-                        for p in &event.paths {
-                            if !w.image_queue.contains(p) {
-                                w.image_queue.push_front(p.into());
-                            }
+                        let was_shader = w.is_shader();
+                        if w.add_images(&event.paths) {
+                            state.on_source_switched(idx, was_shader);
                         }
-                        w.image_queue.retain(|p| !event.paths.contains(p));
                     }
                 }
                 notify::EventKind::Remove(_)
@@ -315,6 +745,8 @@ impl BackgroundEngine {
             }
         });
 
+        let compositor_state = Rc::new(CompositorState::bind(&globals, &qh).unwrap());
+
         // initial setup with all images
         let wallpapers = {
             let mut wallpapers = Vec::with_capacity(config.backgrounds.len() + 1);
@@ -326,6 +758,7 @@ impl BackgroundEngine {
                         qh.clone(),
                         event_loop.handle(),
                         source_tx.clone(),
+                        compositor_state.clone(),
                     )
                 })
             });
@@ -337,25 +770,37 @@ impl BackgroundEngine {
                 qh.clone(),
                 event_loop.handle(),
                 source_tx.clone(),
+                compositor_state.clone(),
             ));
 
+            for wallpaper in &mut wallpapers {
+                wallpaper.animation_cache = animation_cache;
+                wallpaper.scaled_cache = scaled_cache;
+                wallpaper.set_remote_refresh(remote_refresh);
+                wallpaper.set_dark(dark);
+            }
+
             wallpapers
         };
 
-        // Check if any wallpaper uses a shader source
-        let has_shader_source = config
-            .backgrounds
-            .iter()
-            .any(|bg| matches!(bg.source, glowberry_config::Source::Shader(_)))
-            || matches!(
-                config.default_background.source,
-                glowberry_config::Source::Shader(_)
-            );
+        // Check if any wallpaper uses a shader source, also as an item of a
+        // playlist or as its dark, a workspace's, or a schedule rule's
+        // source; videos are shown by one
+        let is_shader = |entry: &Entry| {
+            std::iter::once(&entry.source)
+                .chain(&entry.dark_source)
+                .chain(entry.workspaces.iter().map(|workspace| &workspace.source))
+                .chain(entry.schedule.iter().map(|rule| &rule.source))
+                .flat_map(Source::items)
+                .any(Source::is_shader)
+        };
+        let has_shader_source =
+            config.backgrounds.iter().any(is_shader) || is_shader(&config.default_background);
 
         // Lazily initialize GPU renderer only if needed
         let gpu_renderer = if has_shader_source {
             tracing::info!("Initializing GPU renderer for shader wallpapers");
-            match gpu::GpuRenderer::new() {
+            match gpu::GpuRenderer::new(&adapter_options(gpu_backend)) {
                 Ok(renderer) => Some(renderer),
                 Err(err) => {
                     tracing::error!(
@@ -372,11 +817,27 @@ impl BackgroundEngine {
         let mut bg_state = GlowBerry {
             registry_state: RegistryState::new(&globals),
             output_state: OutputState::new(&globals, &qh),
-            compositor_state: CompositorState::bind(&globals, &qh).unwrap(),
+            compositor_state,
             shm_state: Shm::bind(&globals, &qh).unwrap(),
             layer_state: LayerShell::bind(&globals, &qh).unwrap(),
             viewporter: globals.bind(&qh, 1..=1, ()).unwrap(),
             fractional_scale_manager: globals.bind(&qh, 1..=1, ()).ok(),
+            presentation: globals.bind(&qh, 1..=1, ()).ok(),
+            dmabuf_state: DmabufState::new(&globals, &qh),
+            dmabuf: cfg!(feature = "dmabuf")
+                .then(|| globals.bind(&qh, 4..=5, GlobalData).ok())
+                .flatten(),
+            dmabuf_allocator: None,
+            color_manager: globals.bind(&qh, 1..=2, ()).ok().map(ColorManager::new),
+            output_power: globals.bind(&qh, 1..=1, ()).ok().map(OutputPower::new),
+            // COSMIC's toplevel info also reports window geometry for coverage
+            toplevels: match (globals.bind(&qh, 1..=1, ()), globals.bind(&qh, 2..=2, ())) {
+                (Ok(list), Ok(info)) => Some(Toplevels::cosmic(list, info)),
+                _ => globals.bind(&qh, 2..=3, ()).ok().map(Toplevels::wlr),
+            },
+            workspaces: globals.bind(&qh, 1..=1, ()).ok().map(Workspaces::new),
+            cursor_shape: globals.bind(&qh, 1..=1, ()).ok(),
+            pointer: None,
             qh,
             source_tx,
             loop_handle: event_loop.handle(),
@@ -385,14 +846,82 @@ impl BackgroundEngine {
             config,
             active_outputs: Vec::new(),
             gpu_renderer,
+            gpu_backend,
             connection: conn_for_state,
             power_monitor,
+            session_lock,
+            idle_monitor: match (globals.bind(&qh, 1..=1, ()), globals.bind(&qh, 1..=1, ())) {
+                (Ok(notifier), Ok(seat)) => Some(IdleMonitor::new(notifier, seat)),
+                _ => None,
+            },
             power_saving_config,
             current_frame_rate_override: None,
             was_on_battery: false,
             was_animation_paused: false,
+            gpu_release_timer: None,
+            gpu_released: false,
+            gpu_lost: false,
+            frame_render_scheduled: false,
+            audio_monitor,
+            fallback_resolution,
+            texture_budget,
+            shader_min_frame_rate,
+            animation_cache,
+            scaled_cache,
+            memory_budget,
+            remote_refresh,
+            weather_monitor,
+            weather_restart: None,
+            now_playing,
+            now_playing_tx,
+            now_playing_art: None,
+            dark,
+            night_light,
+            night_light_tx,
+            night_light_tint: [1.0; 3],
+            etiquette_config,
+            screen_share_monitor,
+            screen_share_tx,
+            system_monitor: None,
+            timelapse_timer: None,
+            timelapse_save: None,
+            identify_overlays: Vec::new(),
+            identify_timer: None,
         };
 
+        let idle_minutes = bg_state.power_saving_config.pause_when_idle_minutes;
+        if let Some(idle_monitor) = &mut bg_state.idle_monitor {
+            idle_monitor.set_timeout(idle_minutes, &bg_state.qh);
+        } else if idle_minutes > 0 {
+            tracing::warn!(
+                "Compositor has no idle notifications, pausing when idle is unavailable"
+            );
+        }
+
+        if bg_state.power_saving_config.pause_on_covered
+            && !bg_state
+                .toplevels
+                .as_ref()
+                .is_some_and(Toplevels::has_geometry)
+        {
+            tracing::warn!(
+                "Compositor doesn't report window geometry, pausing when covered is unavailable"
+            );
+        }
+
+        if let Ok(ctx) = glowberry_config::context()
+            && ctx.timelapse_enabled()
+        {
+            bg_state.set_timelapse(Some(ctx.timelapse_interval()));
+        }
+
+        // The allocator is created once the compositor names its GPU
+        if bg_state.dmabuf.is_some()
+            && let Err(why) = bg_state.dmabuf_state.get_default_feedback(&bg_state.qh)
+        {
+            tracing::warn!(?why, "Failed to request dmabuf feedback");
+        }
+
         loop {
             event_loop.dispatch(None, &mut bg_state)?;
 
@@ -415,18 +944,114 @@ pub struct GlowBerryLayer {
     pub(crate) needs_redraw: bool,
     pub(crate) size: Option<(u32, u32)>,
     pub(crate) fractional_scale: Option<u32>,
+    /// How the output is rotated or flipped, applied to static wallpaper
+    /// buffers.
+    pub(crate) transform: wl_output::Transform,
+    /// Static wallpaper dmabuf currently attached, if any.
+    pub(crate) dmabuf: Option<dmabuf::Buffer>,
+    /// Dmabuf the attached one replaced while an animation plays, written
+    /// again with a later frame once the compositor released it.
+    pub(crate) dmabuf_spare: Option<dmabuf::Buffer>,
+    /// Dmabufs no longer shown, kept until the compositor releases them.
+    pub(crate) dmabuf_retired: Vec<dmabuf::Buffer>,
+    /// What the last SHM buffer attached by [`Wallpaper::draw`] shows.
+    pub(crate) shm_frame: Option<draw::ShmFrame>,
     /// GPU state for shader wallpapers (None for static wallpapers).
     pub(crate) gpu_state: Option<GpuLayerState>,
+    /// Frame a transition drawn by the CPU starts from, and the one it ends
+    /// with once drawn, see [`transition::frame`](crate::transition::frame).
+    pub(crate) shm_fade: Option<(image::DynamicImage, Option<image::DynamicImage>)>,
+    /// Whether either frame of `shm_fade` has transparent pixels, found once
+    /// the frame it ends with is drawn.
+    pub(crate) fade_transparent: bool,
+    /// Last frame of the previous shader, for the next canvas to transition
+    /// from.
+    pub(crate) gpu_fade: Option<wgpu::Texture>,
+}
+
+impl GlowBerryLayer {
+    /// Request a frame callback, tagged with the watchdog generation on
+    /// shader layers.
+    fn request_frame(&mut self, qh: &QueueHandle<GlowBerry>) {
+        let wl_surface = self.layer.wl_surface();
+        match &mut self.gpu_state {
+            Some(gpu_state) => {
+                gpu_state.idle = false;
+                wl_surface.frame(
+                    qh,
+                    ShaderFrame {
+                        surface: wl_surface.clone(),
+                        generation: gpu_state.watchdog.generation(),
+                    },
+                );
+            }
+            None => {
+                wl_surface.frame(qh, wl_surface.clone());
+            }
+        }
+    }
+
+    /// Read back the frame the layer's static wallpaper buffer shows, for a
+    /// transition to start from when the wallpaper switches.
+    pub(crate) fn shown_frame(&mut self) -> Option<image::DynamicImage> {
+        if let Some(frame) = &self.shm_frame {
+            let (width, height, _) = frame.key;
+            let canvas = self.pool.as_mut()?.raw_data_mut(&frame.buffer.slot());
+            return draw::read_canvas(canvas, width, height, width as usize * 4, frame.format);
+        }
+        self.dmabuf.as_ref()?.read()
+    }
+
+    /// Keep `buffer`, which the layer no longer shows, until the compositor
+    /// releases it: what a surface shows is undefined once a buffer it still
+    /// uses is destroyed.
+    pub(crate) fn retire_dmabuf(&mut self, buffer: Option<dmabuf::Buffer>) {
+        self.dmabuf_retired
+            .extend(buffer.filter(|buffer| !buffer.released));
+    }
+
+    /// Stop using the attached dmabuf and the spare one, before the layer
+    /// shows something else.
+    pub(crate) fn drop_dmabufs(&mut self) {
+        let (attached, spare) = (self.dmabuf.take(), self.dmabuf_spare.take());
+        self.retire_dmabuf(attached);
+        self.retire_dmabuf(spare);
+    }
 }
 
 pub struct GlowBerry {
     registry_state: RegistryState,
     output_state: OutputState,
-    compositor_state: CompositorState,
+    compositor_state: Rc<CompositorState>,
     shm_state: Shm,
     layer_state: LayerShell,
     viewporter: wp_viewporter::WpViewporter,
     fractional_scale_manager: Option<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1>,
+    presentation: Option<wp_presentation::WpPresentation>,
+    dmabuf_state: DmabufState,
+    /// `zwp_linux_dmabuf_v1` for wallpaper buffers (None without the
+    /// `dmabuf` feature or compositor support).
+    dmabuf: Option<zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1>,
+    /// `wp_color_manager_v1`, for HDR shader output (None without compositor
+    /// support).
+    color_manager: Option<ColorManager>,
+    /// `zwlr_output_power_manager_v1`, to stop rendering shaders on outputs
+    /// whose display is off (None without compositor support).
+    output_power: Option<OutputPower>,
+    /// `zcosmic_toplevel_info_v1` or `zwlr_foreign_toplevel_manager_v1`, to
+    /// stop rendering shaders on outputs showing a fullscreen window or
+    /// covered by windows (None without compositor support).
+    toplevels: Option<Toplevels>,
+    /// `ext_workspace_manager_v1`, to show the sources of entries for the
+    /// workspace active on their output (None without compositor support).
+    workspaces: Option<Workspaces>,
+    /// `wp_cursor_shape_manager_v1`, without which the pointer isn't
+    /// followed.
+    cursor_shape: Option<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>,
+    /// The seat's pointer, bound while shaders read `iPointer`.
+    pointer: Option<PointerTracker>,
+    /// Allocator for static wallpaper dmabufs, shared with every wallpaper.
+    dmabuf_allocator: Option<Rc<dmabuf::Allocator>>,
     qh: QueueHandle<GlowBerry>,
     source_tx: calloop::channel::SyncSender<(String, notify::Event)>,
     loop_handle: calloop::LoopHandle<'static, GlowBerry>,
@@ -436,10 +1061,17 @@ pub struct GlowBerry {
     active_outputs: Vec<WlOutput>,
     /// GPU renderer for shader wallpapers (lazily initialized).
     gpu_renderer: Option<gpu::GpuRenderer>,
+    /// Graphics API override from [`EngineConfig::gpu_backend`].
+    gpu_backend: Option<GpuBackend>,
     /// Wayland connection for creating GPU surfaces.
     connection: Connection,
     /// Power monitor handle for battery/lid state.
     power_monitor: Option<PowerMonitorHandle>,
+    /// logind lock state, pausing shaders while the lock screen is up.
+    session_lock: Option<SessionLockMonitor>,
+    /// Seat idle state, pausing shaders after `pause-when-idle-minutes`
+    /// without input (None without compositor support).
+    idle_monitor: Option<IdleMonitor>,
     /// Power saving configuration.
     power_saving_config: PowerSavingConfig,
     /// Currently applied frame rate override (None = using configured rates).
@@ -448,6 +1080,69 @@ pub struct GlowBerry {
     was_on_battery: bool,
     /// Whether animation was paused in the last frame (for detecting resume).
     was_animation_paused: bool,
+    /// Timer releasing GPU state once animation has stayed paused.
+    gpu_release_timer: Option<RegistrationToken>,
+    /// Whether shader layers show SHM copies of their last frame while paused.
+    gpu_released: bool,
+    /// Whether the GPU device was lost; shader layers are recreated on their
+    /// next frame callback.
+    gpu_lost: bool,
+    /// Whether an idle callback will render the layers queued by frame callbacks.
+    frame_render_scheduled: bool,
+    /// Audio capture for shaders using `iAudio` (None when disabled).
+    audio_monitor: Option<AudioMonitor>,
+    /// Resolution used for outputs that report no size or mode.
+    fallback_resolution: (u32, u32),
+    /// Texture size and memory caps for shader canvases.
+    texture_budget: fragment_canvas::TextureBudget,
+    /// Lowest frame rate slow shaders are stepped down to.
+    shader_min_frame_rate: u8,
+    /// Bytes of decoded frames an animated image may keep.
+    animation_cache: u64,
+    /// Disk space scaled wallpapers may be cached in.
+    scaled_cache: u64,
+    /// Memory the wallpapers' images, frames, and SHM pools may hold.
+    memory_budget: u64,
+    /// Time between checks of URL wallpapers for a new image.
+    remote_refresh: Duration,
+    /// Weather provider for shaders using `iWeather` (None when disabled).
+    weather_monitor: Option<WeatherMonitor>,
+    /// Timer restarting the weather provider once its config settles.
+    weather_restart: Option<RegistrationToken>,
+    /// Album art listener, while the playing track's art is shown (None
+    /// when disabled).
+    now_playing: Option<NowPlayingMonitor>,
+    /// Sender handed to new album art listeners.
+    now_playing_tx: calloop::channel::Sender<Option<PathBuf>>,
+    /// Album art shown while a track plays, also on wallpapers created meanwhile.
+    now_playing_art: Option<PathBuf>,
+    /// Whether the COSMIC theme is dark, showing the entries' dark sources,
+    /// also on wallpapers created meanwhile.
+    dark: bool,
+    /// Night light listener (None when disabled).
+    night_light: Option<NightLightMonitor>,
+    /// Sender handed to new night light listeners.
+    night_light_tx: calloop::channel::Sender<u32>,
+    /// Factors of the color channels for the night light, also applied to
+    /// wallpapers created meanwhile.
+    night_light_tint: [f32; 3],
+    /// Presentation etiquette configuration.
+    etiquette_config: EtiquetteConfig,
+    /// Screen-share detection, running while rotation or animation may pause
+    /// during shares.
+    screen_share_monitor: Option<ScreenShareMonitor>,
+    /// Sender handed to new screen-share monitors.
+    screen_share_tx: calloop::channel::Sender<bool>,
+    /// `/proc` sampler, running while a shader layer uses `iSystem`.
+    system_monitor: Option<SystemMonitor>,
+    /// Periodic time-lapse snapshot timer (None when disabled).
+    timelapse_timer: Option<RegistrationToken>,
+    /// Receives where the frames of the last capture were saved.
+    timelapse_save: Option<RegistrationToken>,
+    /// Output names currently shown by an identify request.
+    identify_overlays: Vec<identify::Overlay>,
+    /// Timer hiding the identify overlays.
+    identify_timer: Option<RegistrationToken>,
 }
 
 // Manual Debug impl since wgpu types don't implement Debug
@@ -459,15 +1154,71 @@ impl std::fmt::Debug for GlowBerry {
             .field("config", &self.config)
             .field("active_outputs", &self.active_outputs)
             .field("gpu_renderer", &self.gpu_renderer.is_some())
+            .field("dmabuf_allocator", &self.dmabuf_allocator)
+            .field("color_manager", &self.color_manager)
+            .field("output_power", &self.output_power)
+            .field("toplevels", &self.toplevels)
+            .field("workspaces", &self.workspaces)
+            .field("pointer", &self.pointer)
             .field("power_monitor", &self.power_monitor.is_some())
+            .field("session_lock", &self.session_lock)
+            .field("idle_monitor", &self.idle_monitor)
+            .field("audio_monitor", &self.audio_monitor.is_some())
+            .field("fallback_resolution", &self.fallback_resolution)
+            .field("texture_budget", &self.texture_budget)
+            .field("shader_min_frame_rate", &self.shader_min_frame_rate)
+            .field("animation_cache", &self.animation_cache)
+            .field("scaled_cache", &self.scaled_cache)
+            .field("memory_budget", &self.memory_budget)
+            .field("remote_refresh", &self.remote_refresh)
+            .field("weather_monitor", &self.weather_monitor.is_some())
+            .field("now_playing", &self.now_playing)
+            .field("night_light", &self.night_light)
+            .field("screen_share_monitor", &self.screen_share_monitor)
+            .field("system_monitor", &self.system_monitor.is_some())
+            .field("timelapse_timer", &self.timelapse_timer.is_some())
+            .field("identify_overlays", &self.identify_overlays.len())
             .finish_non_exhaustive()
     }
 }
 
 impl GlowBerry {
-    /// Check if shader animation should be paused based on current power state.
-    /// Returns true if animation should be paused.
-    fn should_pause_animation(&self) -> bool {
+    /// Check if shader animation should be paused based on the session lock
+    /// and current power state. Returns true if animation should be paused.
+    pub(crate) fn should_pause_animation(&self) -> bool {
+        // The lock screen covers every output
+        if self
+            .session_lock
+            .as_ref()
+            .is_some_and(SessionLockMonitor::is_locked)
+        {
+            tracing::debug!("Pausing animation: session is locked");
+            return true;
+        }
+
+        if self.idle_monitor.as_ref().is_some_and(IdleMonitor::is_idle) {
+            tracing::debug!("Pausing animation: no input for a while");
+            return true;
+        }
+
+        // Without a power monitor, assume AC power
+        let on_battery = self
+            .power_monitor
+            .as_ref()
+            .is_some_and(|power_monitor| power_monitor.current().on_battery);
+        if self
+            .screen_share_monitor
+            .as_ref()
+            .is_some_and(ScreenShareMonitor::is_active)
+            && self
+                .power_saving_config
+                .on_screen_capture
+                .should_pause(on_battery)
+        {
+            tracing::debug!("Pausing animation: screen is being shared");
+            return true;
+        }
+
         let Some(ref power_monitor) = self.power_monitor else {
             return false; // No power monitor, don't pause
         };
@@ -599,89 +1350,1164 @@ impl GlowBerry {
         }
     }
 
-    /// Request frame callbacks for all shader layers.
-    /// Used to resume animation after being paused.
-    fn request_frame_callbacks(&mut self) {
-        let qh = self.qh.clone();
-        for wallpaper in &mut self.wallpapers {
-            for layer in &mut wallpaper.layers {
-                if layer.gpu_state.is_some() {
-                    let wl_surface = layer.layer.wl_surface();
-                    wl_surface.frame(&qh, wl_surface.clone());
-                    layer.layer.commit();
-                }
-            }
+    /// Called when the session locks or unlocks. Shaders stop requesting
+    /// frame callbacks on their next frame while locked, and are resumed here
+    /// on unlock unless power saving still keeps them paused.
+    fn on_session_lock_changed(&mut self, locked: bool) {
+        if locked {
+            tracing::info!("Session locked, pausing shader animation");
+            return;
         }
-    }
-
-    /// Save the list of currently connected outputs to state.
-    /// This allows the settings app to know which displays are currently available.
-    fn save_connected_outputs(&self) {
-        let connected: Vec<String> = self
-            .active_outputs
-            .iter()
-            .filter_map(|o| self.output_state.info(o))
-            .filter_map(|info| info.name.clone())
-            .collect();
 
-        if let Ok(state_helper) = State::state() {
-            let mut state = State::get_entry(&state_helper).unwrap_or_default();
-            if state.connected_outputs != connected {
-                state.connected_outputs = connected;
-                if let Err(err) = state.write_entry(&state_helper) {
-                    tracing::error!("Failed to save connected outputs: {err}");
-                } else {
-                    tracing::debug!(outputs = ?state.connected_outputs, "Saved connected outputs to state");
-                }
-            }
+        tracing::info!("Session unlocked");
+        if self.was_animation_paused && !self.should_pause_animation() {
+            tracing::info!("Resuming shader animation after unlock");
+            self.was_animation_paused = false;
+            self.request_frame_callbacks();
         }
     }
 
-    fn shader_physical_size(
-        layer_size: Option<(u32, u32)>,
-        fractional_scale: Option<u32>,
-        output_mode_dims: Option<(u32, u32)>,
-    ) -> (u32, u32) {
-        if let Some((w, h)) = layer_size {
-            let scale = fractional_scale.unwrap_or(120);
-            return (w * scale / 120, h * scale / 120);
+    /// Called when the seat goes idle or input resumes. Like a lock, idling
+    /// pauses shaders on their next frame.
+    fn on_idle_changed(&mut self, idle: bool) {
+        if idle {
+            tracing::info!("No input for a while, pausing shader animation");
+            return;
         }
 
-        if let Some((w, h)) = output_mode_dims {
-            return (w, h);
+        if self.was_animation_paused && !self.should_pause_animation() {
+            tracing::info!("Resuming shader animation after input");
+            self.was_animation_paused = false;
+            self.request_frame_callbacks();
         }
-
-        let (w, h) = (1920, 1080);
-        let scale = fractional_scale.unwrap_or(120);
-        (w * scale / 120, h * scale / 120)
     }
 
-    fn shader_layer_physical_size(layer: &GlowBerryLayer) -> (u32, u32) {
-        let output_mode_dims = layer
-            .output_info
-            .modes
-            .iter()
-            .find(|m| m.current)
-            .map(|m| (m.dimensions.0 as u32, m.dimensions.1 as u32));
+    /// Follow the pointer over a wallpaper surface in the shaders showing it.
+    fn on_pointer(&mut self, event: PointerEvent) {
+        let (surface, position) = match &event {
+            PointerEvent::Moved(surface, position) => (surface, Some(*position)),
+            PointerEvent::Left(surface) => (surface, None),
+        };
 
-        Self::shader_physical_size(layer.size, layer.fractional_scale, output_mode_dims)
+        for layer in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+            .filter(|layer| layer.layer.wl_surface() == surface)
+        {
+            let (Some(gpu_state), Some((width, height))) = (&mut layer.gpu_state, layer.size)
+            else {
+                continue;
+            };
+            // Surface-local coordinates are in the layer's logical size
+            let position = position.map(|[x, y]| {
+                [
+                    (x / f64::from(width.max(1))) as f32,
+                    (y / f64::from(height.max(1))) as f32,
+                ]
+            });
+            gpu_state.canvas.set_pointer(position);
+        }
     }
 
-    fn update_shader_layer_surface(
-        gpu: &gpu::GpuRenderer,
-        qh: &QueueHandle<Self>,
-        layer: &mut GlowBerryLayer,
-    ) {
-        let (physical_w, physical_h) = Self::shader_layer_physical_size(layer);
-        let Some(gpu_state) = layer.gpu_state.as_mut() else {
-            return;
-        };
-
-        gpu_state.surface_config =
-            gpu.configure_surface(&gpu_state.surface, physical_w, physical_h);
-        gpu_state
+    /// Whether shaders on `output` should stop whatever the global pause
+    /// state: its display is off or windows hide it.
+    fn is_output_paused(&self, output: &WlOutput) -> bool {
+        if self
+            .output_power
+            .as_ref()
+            .is_some_and(|power| power.is_off(output))
+        {
+            return true;
+        }
+
+        let Some(toplevels) = &self.toplevels else {
+            return false;
+        };
+        let config = &self.power_saving_config;
+        if config.pause_on_fullscreen && toplevels.has_fullscreen(output) {
+            return true;
+        }
+
+        config.pause_on_covered
+            && self
+                .output_state
+                .info(output)
+                .and_then(|info| info.logical_size)
+                .is_some_and(|(width, height)| {
+                    toplevels.coverage(output, width, height) * 100.0
+                        >= f64::from(config.coverage_threshold)
+                })
+    }
+
+    /// Called when an output's display turns off or back on. Layers on it
+    /// stop requesting frame callbacks on their next frame while it is off.
+    fn on_output_power_changed(&mut self, output: &WlOutput, off: bool) {
+        if off {
+            tracing::info!(?output, "Display powered off, pausing its shaders");
+            return;
+        }
+
+        tracing::info!(?output, "Display powered on");
+        self.resume_output(output);
+    }
+
+    /// Whether any window is shown on `output`.
+    fn has_windows(&self, output: &WlOutput) -> bool {
+        self.toplevels
+            .as_ref()
+            .is_some_and(|toplevels| toplevels.has_windows(output))
+    }
+
+    /// Called when windows on an output change. Window effects of the layers
+    /// on that output fade in or out, and like a powered off display, a
+    /// fullscreen window or enough windows covering it pause them.
+    fn on_toplevels_changed(&mut self, output: &WlOutput) {
+        let windows = self.has_windows(output);
+        for gpu_state in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+            .filter(|layer| &layer.wl_output == output)
+            .filter_map(|layer| layer.gpu_state.as_mut())
+        {
+            gpu_state.canvas.set_windows(windows);
+        }
+        self.wake_idle_layers();
+
+        if self.is_output_paused(output) {
+            tracing::debug!(?output, "Output hidden by windows, pausing its shaders");
+            return;
+        }
+
+        self.resume_output(output);
+    }
+
+    /// Apply an event for the toplevel handle with `id`, re-evaluating the
+    /// outputs it was or is on.
+    fn on_toplevel_event(&mut self, id: ObjectId, event: Option<ToplevelEvent>) {
+        let Some(event) = event else {
+            return;
+        };
+        let changed = self
+            .toplevels
+            .as_mut()
+            .map(|toplevels| toplevels.on_event(&id, event))
+            .unwrap_or_default();
+        for output in changed {
+            self.on_toplevels_changed(&output);
+        }
+    }
+
+    /// Request a frame callback for the shader layer on `surface` if the
+    /// compositor stopped sending them while it should be animating.
+    fn check_frame_stall(&mut self, surface: &wl_surface::WlSurface) {
+        if self.gpu_lost || self.should_pause_animation() {
+            return;
+        }
+
+        let now = Instant::now();
+        for layer in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+            .filter(|layer| layer.layer.wl_surface() == surface)
+        {
+            // Paused and idle layers, and ones waiting for the batch render,
+            // have no callback outstanding
+            let Some(gpu_state) = &mut layer.gpu_state else {
+                continue;
+            };
+            if gpu_state.output_paused
+                || gpu_state.idle
+                || gpu_state.frame_pending
+                || !gpu_state.watchdog.is_stalled(now)
+            {
+                continue;
+            }
+
+            tracing::debug!(
+                output = ?layer.output_info.name,
+                "No frame callback for a while, requesting another"
+            );
+            gpu_state.watchdog.supersede();
+            layer.request_frame(&self.qh);
+            layer.layer.commit();
+        }
+    }
+
+    /// Resume the layers on `output` that stopped for a reason specific to
+    /// it, once none applies anymore. If animation is paused for another
+    /// reason, they wait to be resumed together with the others.
+    fn resume_output(&mut self, output: &WlOutput) {
+        if self.is_output_paused(output) {
+            return;
+        }
+
+        let paused = self.should_pause_animation();
+        let qh = self.qh.clone();
+        for layer in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+            .filter(|layer| &layer.wl_output == output)
+        {
+            let Some(gpu_state) = &mut layer.gpu_state else {
+                continue;
+            };
+            if !std::mem::take(&mut gpu_state.output_paused) {
+                continue;
+            }
+            if paused {
+                self.was_animation_paused = true;
+            } else {
+                tracing::info!(output = ?layer.output_info.name, "Resuming shader on output");
+                gpu_state.watchdog.reset();
+                layer.request_frame(&qh);
+                layer.layer.commit();
+            }
+        }
+    }
+
+    /// Start or stop audio capture after the audio-reactive setting changes.
+    fn set_audio_reactive(&mut self, enabled: bool) {
+        if enabled == self.audio_monitor.is_some() {
+            return;
+        }
+
+        self.audio_monitor = if enabled {
+            AudioMonitor::start()
+        } else {
+            tracing::info!("Audio capture stopped");
+            None
+        };
+    }
+
+    /// Restart the weather provider once its config has stopped changing,
+    /// so saving several keys at once restarts it only once.
+    fn schedule_weather_restart(&mut self) {
+        if let Some(token) = self.weather_restart.take() {
+            self.loop_handle.remove(token);
+        }
+        self.weather_restart = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(weather::RESTART_DELAY),
+                |_, _, state: &mut GlowBerry| {
+                    state.weather_restart = None;
+                    if let Ok(ctx) = glowberry_config::context() {
+                        state.set_weather_config(&ctx.weather_config());
+                    }
+                    TimeoutAction::Drop
+                },
+            )
+            .ok();
+    }
+
+    /// Restart or stop the weather provider after its config changes.
+    fn set_weather_config(&mut self, config: &WeatherConfig) {
+        // Drop the old provider first so only one fetch loop runs at a time
+        self.weather_monitor = None;
+        if config.enabled {
+            self.weather_monitor = WeatherMonitor::start(config);
+        } else {
+            tracing::info!("Weather provider stopped");
+        }
+    }
+
+    /// Restart or stop the album art listener after its config changes,
+    /// showing the configured wallpapers until a track is found playing.
+    fn set_now_playing_config(&mut self, config: &NowPlayingConfig) {
+        // Stop the running listener first
+        self.now_playing = None;
+        self.show_now_playing(None);
+        if config.enabled {
+            self.now_playing = NowPlayingMonitor::start(config, self.now_playing_tx.clone());
+        } else {
+            tracing::info!("Album art wallpaper stopped");
+        }
+    }
+
+    /// Restart or stop the night light listener after its config changes,
+    /// showing neutral colors until it reports.
+    fn set_night_light_config(&mut self, config: &NightLightConfig) {
+        // Stop the running listener first
+        self.night_light = None;
+        self.set_night_light_tint(night_light::tint(NEUTRAL_TEMPERATURE));
+        if config.enabled {
+            self.night_light = NightLightMonitor::start(config, self.night_light_tx.clone());
+        } else {
+            tracing::info!("Night light tint stopped");
+        }
+    }
+
+    /// Tint every wallpaper by `tint` for the night light.
+    fn set_night_light_tint(&mut self, tint: [f32; 3]) {
+        self.night_light_tint = tint;
+        for wallpaper in &mut self.wallpapers {
+            if !wallpaper.set_night_light(tint) {
+                continue;
+            }
+            if !wallpaper.is_shader() {
+                wallpaper.draw();
+                continue;
+            }
+            let Some(gpu) = self.gpu_renderer.as_ref() else {
+                continue;
+            };
+            let tint = wallpaper.color_tint();
+            for gpu_state in wallpaper
+                .layers
+                .iter_mut()
+                .filter_map(|layer| layer.gpu_state.as_mut())
+            {
+                gpu_state.canvas.set_tint(gpu.device(), tint);
+            }
+        }
+        self.wake_idle_layers();
+    }
+
+    /// Start or stop screen-share detection after the etiquette config changes.
+    fn set_etiquette_config(&mut self, config: EtiquetteConfig) {
+        self.etiquette_config = config;
+        self.update_screen_share_monitor();
+    }
+
+    /// Start or stop screen-share detection depending on whether rotation or
+    /// animation may pause during shares.
+    fn update_screen_share_monitor(&mut self) {
+        let enabled = watches_screen_share(&self.etiquette_config, &self.power_saving_config);
+        if enabled == self.screen_share_monitor.is_some() {
+            return;
+        }
+
+        self.screen_share_monitor = if enabled {
+            ScreenShareMonitor::start(self.screen_share_tx.clone())
+        } else {
+            tracing::info!("Screen-share detection stopped");
+            None
+        };
+    }
+
+    fn on_screen_share_changed(&mut self, active: bool) {
+        if self.etiquette_config.pause_rotation_while_sharing {
+            if active {
+                tracing::info!("Screen share started, pausing slideshow rotation");
+            } else {
+                tracing::info!("Screen share ended, resuming slideshow rotation");
+            }
+        }
+
+        // Shaders pause on their next frame; resume them once the share ends
+        if !active && self.was_animation_paused && !self.should_pause_animation() {
+            tracing::info!("Resuming shader animation after screen share");
+            self.was_animation_paused = false;
+            self.request_frame_callbacks();
+        }
+    }
+
+    /// Whether slideshows should hold their current image, because the
+    /// screen is being shared.
+    pub(crate) fn rotation_paused(&self) -> bool {
+        self.etiquette_config.pause_rotation_while_sharing
+            && self
+                .screen_share_monitor
+                .as_ref()
+                .is_some_and(ScreenShareMonitor::is_active)
+    }
+
+    /// Start, restart, or stop periodic time-lapse snapshots.
+    /// `interval` is in minutes; `None` disables capture.
+    fn set_timelapse(&mut self, interval: Option<u32>) {
+        if let Some(token) = self.timelapse_timer.take() {
+            self.loop_handle.remove(token);
+        }
+
+        let Some(minutes) = interval else {
+            return;
+        };
+        let period = Duration::from_secs(u64::from(minutes.max(1)) * 60);
+
+        self.timelapse_timer = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(period),
+                move |_, _, state: &mut GlowBerry| {
+                    state.capture_timelapse();
+                    TimeoutAction::ToDuration(period)
+                },
+            )
+            .ok();
+        tracing::info!(minutes, "Time-lapse capture enabled");
+    }
+
+    /// Briefly show each output's name on it, replacing any overlays still up.
+    fn identify_outputs(&mut self) {
+        if let Some(token) = self.identify_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        self.identify_overlays.clear();
+
+        for output in &self.active_outputs {
+            let Some(name) = self
+                .output_state
+                .info(output)
+                .and_then(|info| info.name.clone())
+            else {
+                continue;
+            };
+
+            let surface = self.compositor_state.create_surface(&self.qh);
+            let layer = self.layer_state.create_layer_surface(
+                &self.qh,
+                surface,
+                Layer::Overlay,
+                "glowberry-identify".into(),
+                Some(output),
+            );
+            layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+            self.identify_overlays.push(identify::Overlay::new(
+                layer,
+                name,
+                &self.compositor_state,
+            ));
+        }
+
+        self.identify_timer = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(identify::DURATION),
+                |_, _, state: &mut GlowBerry| {
+                    state.identify_overlays.clear();
+                    state.identify_timer = None;
+                    TimeoutAction::Drop
+                },
+            )
+            .ok();
+    }
+
+    /// Save a time-lapse snapshot of every output's current wallpaper.
+    /// Shader frames are rendered here; reading them back, scaling, and
+    /// saving happen on a worker thread.
+    fn capture_timelapse(&mut self) {
+        // Frames of a capture still saving are dropped with its channel
+        if let Some(token) = self.timelapse_save.take() {
+            self.loop_handle.remove(token);
+        }
+
+        let renderer = self.gpu_renderer.as_ref();
+        let mut snapshots: Vec<(String, timelapse::Render)> = Vec::new();
+        for wallpaper in &mut self.wallpapers {
+            // Shader snapshots update the canvas's uniforms, so layers are
+            // visited by index to keep `wallpaper` free for static snapshots
+            for layer_idx in 0..wallpaper.layers.len() {
+                let layer = &wallpaper.layers[layer_idx];
+                let Some(output) = layer.output_info.name.clone() else {
+                    continue;
+                };
+
+                let render = if layer.gpu_state.is_some() {
+                    let (Some(renderer), Some(gpu_state)) =
+                        (renderer, wallpaper.layers[layer_idx].gpu_state.as_mut())
+                    else {
+                        continue;
+                    };
+                    let size = timelapse::snapshot_size(
+                        gpu_state.surface_config.width,
+                        gpu_state.surface_config.height,
+                    );
+                    gpu_state.canvas.start_snapshot(renderer, size).map(
+                        |pending| -> timelapse::Render {
+                            Box::new(move || pending.wait().map(image::DynamicImage::from))
+                        },
+                    )
+                } else {
+                    let Some((width, height)) = layer.size else {
+                        continue;
+                    };
+                    let (width, height) = timelapse::snapshot_size(width, height);
+                    wallpaper.snapshot(width, height)
+                };
+
+                match render {
+                    Some(render) => snapshots.push((output, render)),
+                    None => tracing::debug!(%output, "No wallpaper to snapshot"),
+                }
+            }
+        }
+        if snapshots.is_empty() {
+            return;
+        }
+
+        let (tx, rx) = calloop::channel::channel();
+        self.timelapse_save = self
+            .loop_handle
+            .insert_source(rx, |event, _, _: &mut GlowBerry| {
+                let calloop::channel::Event::Msg((output, saved)) = event else {
+                    return;
+                };
+                match saved {
+                    Ok(path) => {
+                        tracing::debug!(%output, path = %path.display(), "Saved time-lapse frame")
+                    }
+                    Err(timelapse::TimelapseError::NothingShown) => {
+                        tracing::debug!(%output, "No wallpaper to snapshot")
+                    }
+                    Err(err) => tracing::warn!(%output, %err, "Failed to save time-lapse frame"),
+                }
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to receive time-lapse frames"))
+            .ok();
+        timelapse::spawn_save(snapshots, tx);
+    }
+
+    /// Start counting down to releasing GPU state once animation is paused.
+    fn schedule_gpu_release(&mut self) {
+        let minutes = self.power_saving_config.release_gpu_after_minutes;
+        if minutes == 0 || self.gpu_release_timer.is_some() || self.gpu_renderer.is_none() {
+            return;
+        }
+
+        let delay = Duration::from_secs(u64::from(minutes) * 60);
+        self.gpu_release_timer = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(delay),
+                |_, _, state: &mut GlowBerry| {
+                    state.gpu_release_timer = None;
+                    if state.should_pause_animation() {
+                        state.release_gpu();
+                    }
+                    TimeoutAction::Drop
+                },
+            )
+            .ok();
+    }
+
+    /// Replace every shader layer's GPU state with an SHM copy of its last
+    /// frame and drop the renderer, so a long pause (lid closed, low battery)
+    /// doesn't keep surfaces, pipelines, and textures resident.
+    fn release_gpu(&mut self) {
+        let Some(renderer) = self.gpu_renderer.as_ref() else {
+            return;
+        };
+
+        for layer in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+        {
+            let Some(mut gpu_state) = layer.gpu_state.take() else {
+                continue;
+            };
+            let (width, height) = (
+                gpu_state.surface_config.width,
+                gpu_state.surface_config.height,
+            );
+            let frame = gpu_state.canvas.snapshot(renderer, (width, height));
+            // The swapchain must be gone before an SHM buffer is attached
+            drop(gpu_state);
+
+            let Some(frame) = frame.map(image::DynamicImage::from) else {
+                continue;
+            };
+            let len = width as usize * height as usize * 4;
+            let Some(pool) = Self::sized_pool(&mut layer.pool, len, &self.shm_state) else {
+                continue;
+            };
+            layer.shm_frame = None;
+            Self::set_opaque(&self.compositor_state, layer.layer.wl_surface(), true);
+            match draw::canvas(
+                pool,
+                &frame,
+                width as i32,
+                height as i32,
+                width as i32 * 4,
+                draw::Format::Xrgb8888,
+            ) {
+                Ok(buffer) => draw::layer_surface(
+                    &layer.layer,
+                    &layer.viewport,
+                    &self.qh,
+                    &buffer,
+                    &[[0, 0, width as i32, height as i32]],
+                    layer.size.unwrap_or((width, height)),
+                ),
+                Err(why) => tracing::error!(?why, "paused frame could not be drawn"),
+            }
+        }
+
+        self.gpu_renderer = None;
+        self.gpu_released = true;
+        self.stop_unused_monitors();
+        tracing::info!("Animation paused for a while, released GPU surfaces");
+    }
+
+    /// Recreate the GPU state of shader layers after [`Self::release_gpu`],
+    /// or stop its countdown. Returns whether layers were recreated, which
+    /// requests their frame callbacks.
+    fn restore_gpu(&mut self) -> bool {
+        if let Some(token) = self.gpu_release_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        if !std::mem::take(&mut self.gpu_released) {
+            return false;
+        }
+
+        for wallpaper_idx in 0..self.wallpapers.len() {
+            let Some(shader_source) = self.wallpapers[wallpaper_idx].shader_source().cloned()
+            else {
+                continue;
+            };
+            let shader_source = self.with_wallpaper_background(shader_source);
+
+            for layer_idx in 0..self.wallpapers[wallpaper_idx].layers.len() {
+                if self.wallpapers[wallpaper_idx].layers[layer_idx]
+                    .gpu_state
+                    .is_none()
+                {
+                    self.reinit_gpu_layer(wallpaper_idx, layer_idx, &shader_source);
+                }
+            }
+        }
+        self.gpu_lost = false;
+        tracing::info!("Recreated GPU surfaces after pause");
+        true
+    }
+
+    /// Drop the renderer and every shader layer's GPU state after the device
+    /// was lost. Each layer is recreated on its next frame callback, see
+    /// [`Self::recover_gpu_layer`].
+    fn drop_lost_gpu(&mut self) {
+        // Surfaces go before the device they were created from
+        for layer in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+        {
+            // A layer queued for the batch render has no callback requested yet
+            if layer
+                .gpu_state
+                .take()
+                .is_some_and(|gpu_state| gpu_state.frame_pending)
+            {
+                let wl_surface = layer.layer.wl_surface();
+                wl_surface.frame(&self.qh, wl_surface.clone());
+                layer.layer.commit();
+            }
+        }
+        self.gpu_renderer = None;
+        self.gpu_lost = true;
+        tracing::warn!("GPU device lost, recreating shader wallpapers");
+    }
+
+    /// Recreate the shader layer on `surface` if it was dropped with a lost
+    /// device. Returns whether it was, in which case its frame callback is
+    /// already requested.
+    fn recover_gpu_layer(&mut self, surface: &wl_surface::WlSurface) -> bool {
+        let Some((wallpaper_idx, layer_idx)) =
+            self.wallpapers
+                .iter()
+                .enumerate()
+                .find_map(|(wallpaper_idx, wallpaper)| {
+                    let layer_idx = wallpaper.layers.iter().position(|layer| {
+                        layer.gpu_state.is_none() && layer.layer.wl_surface() == surface
+                    })?;
+                    Some((wallpaper_idx, layer_idx))
+                })
+        else {
+            return false;
+        };
+        let Some(shader_source) = self.wallpapers[wallpaper_idx].shader_source().cloned() else {
+            return false;
+        };
+        let shader_source = self.with_wallpaper_background(shader_source);
+        self.reinit_gpu_layer(wallpaper_idx, layer_idx, &shader_source);
+
+        self.gpu_lost = self
+            .wallpapers
+            .iter()
+            .filter(|wallpaper| wallpaper.is_shader())
+            .flat_map(|wallpaper| &wallpaper.layers)
+            .any(|layer| layer.gpu_state.is_none());
+        if !self.gpu_lost {
+            tracing::info!("Recreated GPU surfaces after device loss");
+        }
+        true
+    }
+
+    /// Initialize a shader layer whose GPU state was dropped, carrying over
+    /// the current frame rate override.
+    fn reinit_gpu_layer(
+        &mut self,
+        wallpaper_idx: usize,
+        layer_idx: usize,
+        shader_source: &glowberry_config::ShaderSource,
+    ) {
+        self.init_gpu_layer_internal(wallpaper_idx, layer_idx, shader_source);
+
+        let layer = &mut self.wallpapers[wallpaper_idx].layers[layer_idx];
+        // Left over if the canvas couldn't be created
+        layer.gpu_fade = None;
+        if let Some(gpu_state) = layer.gpu_state.as_mut() {
+            gpu_state
+                .canvas
+                .set_frame_rate_override(self.current_frame_rate_override);
+            layer.pool = None;
+            layer.shm_frame = None;
+            layer.drop_dmabufs();
+            layer.shm_fade = None;
+        }
+    }
+
+    /// Render every shader layer queued by a frame callback with one command
+    /// encoder and one queue submission, then request their next callbacks.
+    ///
+    /// With the same wallpaper on all outputs, queued outputs of one size and
+    /// format show the same frame: it is rendered once and copied to each.
+    fn render_pending_frames(&mut self) {
+        self.frame_render_scheduled = false;
+        let Some(gpu) = self.gpu_renderer.as_ref() else {
+            return;
+        };
+        let qh = &self.qh;
+        let mirror = self.config.same_on_all;
+
+        let mut encoder = gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("glowberry: frame encoder"),
+            });
+        let mut queued = Vec::new();
+        let mut frames = Vec::new();
+        let mut mirror_keys = Vec::new();
+        let mut rendered = Vec::new();
+
+        // Upload each video's latest frame once for all layers showing it
+        let videos: Vec<Option<VideoTexture>> = self
+            .wallpapers
+            .iter_mut()
+            .map(|wallpaper| {
+                let drawn = wallpaper.layers.iter().any(|layer| {
+                    layer
+                        .gpu_state
+                        .as_ref()
+                        .is_some_and(|gpu_state| gpu_state.frame_pending)
+                });
+                wallpaper
+                    .video
+                    .as_mut()
+                    .filter(|_| drawn)
+                    .and_then(|video| video.upload(gpu.device(), gpu.queue()))
+                    .cloned()
+            })
+            .collect();
+
+        for (index, layer) in self
+            .wallpapers
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(index, wallpaper)| wallpaper.layers.iter_mut().map(move |l| (index, l)))
+        {
+            let Some(gpu_state) = layer.gpu_state.as_mut() else {
+                continue;
+            };
+            if !std::mem::take(&mut gpu_state.frame_pending) {
+                continue;
+            }
+            queued.push(layer.layer.clone());
+
+            // Get current texture
+            match gpu_state.surface.get_current_texture() {
+                wgpu::CurrentSurfaceTexture::Success(surface_texture)
+                | wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
+                    // iResolution is only updated when the surface is resized
+                    tracing::trace!(
+                        output = ?layer.output_info.name,
+                        width = gpu_state.surface_config.width,
+                        height = gpu_state.surface_config.height,
+                        "Rendering shader frame"
+                    );
+
+                    if gpu_state.canvas.uses_audio()
+                        && let Some(audio) = &self.audio_monitor
+                    {
+                        gpu_state
+                            .canvas
+                            .update_audio(gpu.queue(), &audio.handle().current());
+                    }
+
+                    if gpu_state.canvas.uses_battery()
+                        && let Some(power) = &self.power_monitor
+                    {
+                        gpu_state.canvas.update_battery(&power.current());
+                    }
+
+                    if gpu_state.canvas.uses_weather()
+                        && let Some(weather) = &self.weather_monitor
+                    {
+                        gpu_state.canvas.update_weather(&weather.handle().current());
+                    }
+
+                    if gpu_state.canvas.uses_pointer() && self.pointer.is_none() {
+                        self.pointer = self.cursor_shape.as_ref().and_then(|cursor_shape| {
+                            PointerTracker::bind(&self.registry_state, &self.qh, cursor_shape)
+                        });
+                    }
+
+                    if gpu_state.canvas.uses_system_stats() {
+                        let stats = self
+                            .system_monitor
+                            .get_or_insert_with(SystemMonitor::start)
+                            .current();
+                        gpu_state.canvas.update_system_stats(&stats);
+                    }
+
+                    if let Some(video) = &videos[index] {
+                        gpu_state.canvas.update_video(gpu.device(), video);
+                    }
+
+                    // Windows differ between outputs, and so do window effects
+                    let config = &gpu_state.surface_config;
+                    mirror_keys.push(
+                        (mirror
+                            && config.usage.contains(wgpu::TextureUsages::COPY_DST)
+                            && !gpu_state.canvas.has_window_effect())
+                        .then_some((index, config.width, config.height, config.format)),
+                    );
+                    frames.push((layer.layer.clone(), surface_texture));
+                }
+                wgpu::CurrentSurfaceTexture::Timeout => {
+                    tracing::warn!("GPU surface timeout");
+                }
+                wgpu::CurrentSurfaceTexture::Lost | wgpu::CurrentSurfaceTexture::Outdated => {
+                    let width = gpu_state.surface_config.width;
+                    let height = gpu_state.surface_config.height;
+                    let hdr = gpu_state.color.is_some();
+                    gpu_state.surface_config =
+                        gpu.configure_surface(&gpu_state.surface, width, height, hdr);
+                    tracing::warn!("GPU surface lost or outdated; reconfigured surface");
+                }
+                other => {
+                    tracing::warn!(?other, "GPU surface error");
+                }
+            }
+        }
+
+        // The first layer of each group renders for all of them
+        for group in Self::mirror_groups(&mirror_keys) {
+            let leader = &frames[group[0]].0;
+            let Some(gpu_state) = self
+                .wallpapers
+                .iter_mut()
+                .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+                .find(|layer| layer.layer == *leader)
+                .and_then(|layer| layer.gpu_state.as_mut())
+            else {
+                continue;
+            };
+            let targets: Vec<_> = group.iter().map(|&i| &frames[i].1.texture).collect();
+            gpu_state.canvas.encode(gpu, &targets, &mut encoder);
+        }
+
+        if !frames.is_empty() {
+            gpu.queue().submit(std::iter::once(encoder.finish()));
+        }
+
+        for (layer, surface_texture) in frames {
+            // Present, asking for feedback to pace the next frames
+            let surface = layer.wl_surface();
+            if let Some(presentation) = &self.presentation {
+                presentation.feedback(surface, qh, surface.downgrade());
+            }
+            surface_texture.present();
+            rendered.push(layer);
+        }
+
+        for layer in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+            .filter(|layer| queued.contains(&layer.layer))
+        {
+            let Some(gpu_state) = layer.gpu_state.as_mut() else {
+                continue;
+            };
+            if rendered.contains(&layer.layer) {
+                gpu_state.canvas.submitted();
+                gpu_state.canvas.mark_frame_rendered();
+            }
+
+            // Request next frame callback to continue animation, unless the
+            // frame shown stays until something changes
+            if gpu_state.canvas.needs_frames() {
+                layer.request_frame(qh);
+                layer.layer.commit();
+            } else {
+                gpu_state.idle = true;
+            }
+        }
+    }
+
+    /// Stop sampling `/proc` once no shader layer uses `iSystem`, and release
+    /// the pointer once none uses `iPointer`; the next one to render starts
+    /// them again.
+    fn stop_unused_monitors(&mut self) {
+        let canvases = || {
+            self.wallpapers
+                .iter()
+                .flat_map(|wallpaper| &wallpaper.layers)
+                .filter_map(|layer| layer.gpu_state.as_ref())
+                .map(|gpu_state| &gpu_state.canvas)
+        };
+        let system_stats = canvases().any(fragment_canvas::FragmentCanvas::uses_system_stats);
+        let pointer = canvases().any(fragment_canvas::FragmentCanvas::uses_pointer);
+        if !system_stats && self.system_monitor.take().is_some() {
+            tracing::debug!("System stats sampling stopped");
+        }
+        if !pointer && self.pointer.take().is_some() {
+            tracing::debug!("Pointer released");
+        }
+    }
+
+    /// Render again on the idle shader layers whose canvas has a new frame to
+    /// show, e.g. once windows on their output or the tint changed.
+    fn wake_idle_layers(&mut self) {
+        let qh = self.qh.clone();
+        for layer in self
+            .wallpapers
+            .iter_mut()
+            .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+        {
+            let Some(gpu_state) = &mut layer.gpu_state else {
+                continue;
+            };
+            if !gpu_state.idle || !gpu_state.canvas.needs_frames() {
+                continue;
+            }
+            gpu_state.watchdog.reset();
+            layer.request_frame(&qh);
+            layer.layer.commit();
+        }
+    }
+
+    /// Group frames that show the same picture, by index. Frames without a
+    /// key are rendered on their own.
+    fn mirror_groups<K: PartialEq>(keys: &[Option<K>]) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (index, key) in keys.iter().enumerate() {
+            let group = key.as_ref().and_then(|key| {
+                groups
+                    .iter_mut()
+                    .find(|group| keys[group[0]].as_ref() == Some(key))
+            });
+            match group {
+                Some(group) => group.push(index),
+                None => groups.push(vec![index]),
+            }
+        }
+        groups
+    }
+
+    /// Request frame callbacks for all shader layers.
+    /// Used to resume animation after being paused.
+    fn request_frame_callbacks(&mut self) {
+        // Recreated layers request their own
+        if self.restore_gpu() {
+            return;
+        }
+
+        let qh = self.qh.clone();
+        for wallpaper in &mut self.wallpapers {
+            let is_shader = wallpaper.is_shader();
+            for layer in &mut wallpaper.layers {
+                // Layers dropped with a lost device are recreated on the callback
+                if layer.gpu_state.is_some() || (self.gpu_lost && is_shader) {
+                    if let Some(gpu_state) = &mut layer.gpu_state {
+                        gpu_state.output_paused = false;
+                        gpu_state.watchdog.reset();
+                    }
+                    layer.request_frame(&qh);
+                    layer.layer.commit();
+                }
+            }
+        }
+    }
+
+    /// Save the list of currently connected outputs to state.
+    /// This allows the settings app to know which displays are currently available.
+    fn save_connected_outputs(&self) {
+        let connected: Vec<String> = self
+            .active_outputs
+            .iter()
+            .filter_map(|o| self.output_state.info(o))
+            .filter_map(|info| info.name.clone())
+            .collect();
+
+        if let Ok(state_helper) = State::state() {
+            let mut state = State::get_entry(&state_helper).unwrap_or_default();
+            if state.connected_outputs != connected {
+                state.connected_outputs = connected;
+                if let Err(err) = state.write_entry(&state_helper) {
+                    tracing::error!("Failed to save connected outputs: {err}");
+                } else {
+                    tracing::debug!(outputs = ?state.connected_outputs, "Saved connected outputs to state");
+                }
+            }
+        }
+    }
+
+    fn shader_physical_size(
+        layer_size: Option<(u32, u32)>,
+        fractional_scale: Option<u32>,
+        output_mode_dims: Option<(u32, u32)>,
+        fallback: (u32, u32),
+    ) -> (u32, u32) {
+        if let Some(size) = layer_size {
+            return draw::physical_size(size, fractional_scale.unwrap_or(120));
+        }
+
+        if let Some((w, h)) = output_mode_dims {
+            return (w, h);
+        }
+
+        draw::physical_size(fallback, fractional_scale.unwrap_or(120))
+    }
+
+    /// [`Self::output_mode_dims`] as the output shows them, swapped when it is
+    /// rotated to portrait or back.
+    fn oriented_mode_dims(output_info: &OutputInfo) -> Option<(u32, u32)> {
+        Self::output_mode_dims(&output_info.modes).map(|(w, h)| {
+            if draw::is_rotated(output_info.transform) {
+                (h, w)
+            } else {
+                (w, h)
+            }
+        })
+    }
+
+    /// Dimensions of the output's current mode, or of its largest advertised
+    /// mode if none is marked current.
+    fn output_mode_dims(modes: &[Mode]) -> Option<(u32, u32)> {
+        modes
+            .iter()
+            .find(|m| m.current)
+            .or_else(|| {
+                modes
+                    .iter()
+                    .max_by_key(|m| i64::from(m.dimensions.0) * i64::from(m.dimensions.1))
+            })
+            .filter(|m| m.dimensions.0 > 0 && m.dimensions.1 > 0)
+            .map(|m| (m.dimensions.0 as u32, m.dimensions.1 as u32))
+    }
+
+    /// Refresh rate of the output's current mode, in mHz.
+    fn output_refresh_rate(modes: &[Mode]) -> Option<u32> {
+        modes
+            .iter()
+            .find(|m| m.current)
+            .and_then(|m| u32::try_from(m.refresh_rate).ok())
+            .filter(|&mhz| mhz > 0)
+    }
+
+    /// Warn when an output gives us nothing to size a surface from, since the
+    /// configured fallback resolution is then used blindly.
+    fn warn_fallback_resolution(output_info: &OutputInfo, fallback: (u32, u32)) {
+        tracing::warn!(
+            output = ?output_info.name,
+            make = %output_info.make,
+            model = %output_info.model,
+            modes = output_info.modes.len(),
+            fallback_width = fallback.0,
+            fallback_height = fallback.1,
+            "Output reported no size or usable mode; using fallback resolution. \
+             Set `{}` in the GlowBerry config if this output renders blurry.",
+            glowberry_config::FALLBACK_RESOLUTION,
+        );
+    }
+
+    fn shader_layer_physical_size(layer: &GlowBerryLayer, fallback: (u32, u32)) -> (u32, u32) {
+        let output_mode_dims = Self::oriented_mode_dims(&layer.output_info);
+        if layer.size.is_none() && output_mode_dims.is_none() {
+            Self::warn_fallback_resolution(&layer.output_info, fallback);
+        }
+
+        Self::shader_physical_size(
+            layer.size,
+            layer.fractional_scale,
+            output_mode_dims,
+            fallback,
+        )
+    }
+
+    /// Turn the layer on `surface` to match its output's rotation and
+    /// flipping, redrawing static wallpapers in the new orientation.
+    fn set_layer_transform(
+        &mut self,
+        surface: &wl_surface::WlSurface,
+        new_transform: wl_output::Transform,
+    ) {
+        let qh = self.qh.clone();
+        let fallback = self.fallback_resolution;
+        let gpu = self.gpu_renderer.as_ref();
+        for wallpaper in &mut self.wallpapers {
+            let is_shader = wallpaper.is_shader();
+            let Some(layer) = wallpaper
+                .layers
+                .iter_mut()
+                .find(|layer| layer.layer.wl_surface() == surface)
+            else {
+                continue;
+            };
+            if layer.transform == new_transform {
+                return;
+            }
+
+            tracing::debug!(
+                output = ?layer.output_info.name,
+                ?new_transform,
+                "Output transform changed"
+            );
+            layer.transform = new_transform;
+            layer.output_info.transform = new_transform;
+
+            if is_shader {
+                // Shaders render upright and leave turning to the compositor,
+                // but are sized from the mode when not configured yet
+                if let Some(gpu) = gpu {
+                    Self::update_shader_layer_surface(gpu, &qh, layer, fallback);
+                }
+            } else {
+                // The buffers hold the image turned the old way
+                layer.shm_frame = None;
+                layer.drop_dmabufs();
+                layer.needs_redraw = true;
+                wallpaper.draw();
+            }
+            return;
+        }
+    }
+
+    fn update_shader_layer_surface(
+        gpu: &gpu::GpuRenderer,
+        qh: &QueueHandle<Self>,
+        layer: &mut GlowBerryLayer,
+        fallback: (u32, u32),
+    ) {
+        let (physical_w, physical_h) = Self::shader_layer_physical_size(layer, fallback);
+        let Some(gpu_state) = layer.gpu_state.as_mut() else {
+            return;
+        };
+
+        let hdr = gpu_state.color.is_some();
+        gpu_state.surface_config =
+            gpu.configure_surface(&gpu_state.surface, physical_w, physical_h, hdr);
+        gpu_state
+            .canvas
+            .update_resolution(gpu, physical_w, physical_h);
+        gpu_state
             .canvas
-            .update_resolution(gpu.queue(), physical_w, physical_h);
+            .set_output_refresh(Self::output_refresh_rate(&layer.output_info.modes));
 
         // Set viewport destination to logical size so compositor scales correctly
         if let Some((logical_w, logical_h)) = layer.size {
@@ -690,11 +2516,46 @@ impl GlowBerry {
                 .set_destination(logical_w as i32, logical_h as i32);
         }
 
-        let wl_surface = layer.layer.wl_surface();
-        wl_surface.frame(qh, wl_surface.clone());
+        layer.request_frame(qh);
         layer.layer.commit();
     }
 
+    /// Free the decoded images and frames of the wallpapers drawn least
+    /// recently until all wallpapers fit the memory budget, then return the
+    /// memory to the OS. Returns whether anything was freed.
+    pub(crate) fn enforce_memory_budget(&mut self) -> bool {
+        if self.memory_budget == 0 {
+            return false;
+        }
+        let mut held: u64 = self.wallpapers.iter().map(Wallpaper::memory).sum();
+        if held <= self.memory_budget {
+            return false;
+        }
+
+        let mut order: Vec<usize> = (0..self.wallpapers.len()).collect();
+        order.sort_by_key(|&idx| self.wallpapers[idx].last_drawn);
+        let mut freed = 0;
+        for idx in order {
+            if held <= self.memory_budget {
+                break;
+            }
+            let evicted = self.wallpapers[idx].evict();
+            held = held.saturating_sub(evicted);
+            freed += evicted;
+        }
+        tracing::debug!(
+            freed,
+            held,
+            budget = self.memory_budget,
+            "evicted wallpaper images over the memory budget"
+        );
+        if freed == 0 {
+            return false;
+        }
+        malloc_trim();
+        true
+    }
+
     fn apply_backgrounds(&mut self) {
         self.wallpapers.clear();
 
@@ -703,43 +2564,88 @@ impl GlowBerry {
             self.qh.clone(),
             self.loop_handle.clone(),
             self.source_tx.clone(),
+            self.compositor_state.clone(),
         );
 
-        let mut backgrounds = self.config.backgrounds.clone();
-        backgrounds.sort_by(|a, b| a.output.cmp(&b.output));
+        let mut wallpapers: Vec<Wallpaper> = Vec::new();
 
-        'outer: for output in &self.active_outputs {
+        for output in &self.active_outputs {
             let Some(output_info) = self.output_state.info(output) else {
                 continue;
             };
 
             let o_name = output_info.name.clone().unwrap_or_default();
-            for background in &backgrounds {
-                if background.output == o_name {
-                    let mut new_wallpaper = Wallpaper::new(
+            let Some(background) = self.config.entry_for_output(&o_name) else {
+                all_wallpaper
+                    .layers
+                    .push(self.new_layer(output.clone(), output_info));
+                continue;
+            };
+
+            // Outputs matched by the same group or glob entry share a wallpaper.
+            let pos = wallpapers
+                .iter()
+                .position(|w| w.entry.output == background.output)
+                .unwrap_or_else(|| {
+                    wallpapers.push(Wallpaper::new(
                         background.clone(),
                         self.qh.clone(),
                         self.loop_handle.clone(),
                         self.source_tx.clone(),
-                    );
+                        self.compositor_state.clone(),
+                    ));
+                    wallpapers.len() - 1
+                });
 
-                    new_wallpaper
-                        .layers
-                        .push(self.new_layer(output.clone(), output_info));
-                    _ = new_wallpaper.save_state();
-                    self.wallpapers.push(new_wallpaper);
+            let layer = self.new_layer(output.clone(), output_info);
+            wallpapers[pos].layers.push(layer);
+        }
 
-                    continue 'outer;
-                }
-            }
+        wallpapers.sort_by(|a, b| a.entry.output.cmp(&b.entry.output));
+        wallpapers.push(all_wallpaper);
 
-            all_wallpaper
-                .layers
-                .push(self.new_layer(output.clone(), output_info));
+        for wallpaper in &mut wallpapers {
+            self.prepare_wallpaper(wallpaper);
         }
+        self.wallpapers = wallpapers;
+        self.stop_unused_monitors();
+        self.on_workspaces_changed();
+    }
+
+    /// Share the engine's buffers, caches, and current theme, music, and
+    /// night light with `wallpaper` once its layers are added.
+    fn prepare_wallpaper(&self, wallpaper: &mut Wallpaper) {
+        wallpaper.dmabuf = self.dmabuf_allocator.clone();
+        wallpaper.ten_bit = self
+            .shm_state
+            .formats()
+            .contains(&wl_shm::Format::Xrgb2101010);
+        wallpaper.animation_cache = self.animation_cache;
+        wallpaper.scaled_cache = self.scaled_cache;
+        wallpaper.set_remote_refresh(self.remote_refresh);
+        wallpaper.set_dark(self.dark);
+        wallpaper.set_now_playing(self.now_playing_art.clone());
+        wallpaper.set_night_light(self.night_light_tint);
+        wallpaper.filter_for_outputs();
+        _ = wallpaper.save_state();
+    }
 
-        _ = all_wallpaper.save_state();
-        self.wallpapers.push(all_wallpaper);
+    /// Wallpaper the output `name` joins when connected, given the entries
+    /// shown by `wallpapers`: the one showing the entry it matches, or a new
+    /// one of that entry when none of its outputs were connected before.
+    fn output_target<'a>(
+        config: &'a Config,
+        name: Option<&str>,
+        wallpapers: &[&str],
+    ) -> OutputTarget<'a> {
+        let entry = name.and_then(|name| config.entry_for_output(name));
+        let target = entry.map_or(glowberry_config::DEFAULT_BACKGROUND, |entry| {
+            entry.output.as_str()
+        });
+        match wallpapers.iter().position(|output| *output == target) {
+            Some(pos) => OutputTarget::Existing(pos),
+            None => OutputTarget::New(entry.unwrap_or(&config.default_background)),
+        }
     }
 
     #[must_use]
@@ -757,6 +2663,7 @@ impl GlowBerry {
         layer.set_anchor(Anchor::all());
         layer.set_exclusive_zone(-1);
         layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        Self::set_opaque(&self.compositor_state, &surface, true);
         surface.commit();
 
         let viewport = self.viewporter.get_viewport(&surface, &self.qh, ());
@@ -773,12 +2680,20 @@ impl GlowBerry {
             layer,
             viewport,
             wl_output: output,
+            transform: output_info.transform,
             output_info,
             size: None,
             fractional_scale,
             needs_redraw: false,
             pool: None,
+            dmabuf: None,
+            dmabuf_spare: None,
+            dmabuf_retired: Vec::new(),
+            shm_frame: None,
             gpu_state: None,
+            shm_fade: None,
+            fade_transparent: false,
+            gpu_fade: None,
         }
     }
 
@@ -792,37 +2707,40 @@ impl GlowBerry {
         // Ensure GPU renderer is initialized
         if self.gpu_renderer.is_none() {
             tracing::info!("Lazily initializing GPU renderer for shader wallpaper");
-            match gpu::GpuRenderer::new() {
+            match gpu::GpuRenderer::new(&adapter_options(self.gpu_backend)) {
                 Ok(renderer) => self.gpu_renderer = Some(renderer),
                 Err(err) => {
                     tracing::error!(
                         ?err,
-                        "GPU initialization failed — cannot render shader wallpaper"
+                        "GPU initialization failed — drawing the shader's background image instead"
                     );
+                    self.draw_shader_fallback(wallpaper_idx, layer_idx, &err);
                     return;
                 }
             }
         }
 
+        // Videos start playing with their first layer
+        self.wallpapers[wallpaper_idx].start_video(self.texture_budget.max_dimension);
+
         let gpu = self.gpu_renderer.as_ref().unwrap();
 
         // Get layer info needed for surface creation
         let layer = &self.wallpapers[wallpaper_idx].layers[layer_idx];
         let wl_surface = layer.layer.wl_surface().clone();
         let output_name = layer.output_info.name.clone();
+        let refresh_rate = Self::output_refresh_rate(&layer.output_info.modes);
+        let windows = self.has_windows(&layer.wl_output);
 
-        // Get native resolution from the current output mode
-        let (physical_width, physical_height) = layer
-            .output_info
-            .modes
-            .iter()
-            .find(|m| m.current)
-            .map(|m| (m.dimensions.0 as u32, m.dimensions.1 as u32))
+        // Get native resolution from the output mode
+        let (physical_width, physical_height) = Self::oriented_mode_dims(&layer.output_info)
             .unwrap_or_else(|| {
                 // Fallback to layer size with scale if no mode info
-                let (w, h) = layer.size.unwrap_or((1920, 1080));
-                let scale = layer.fractional_scale.unwrap_or(120);
-                (w * scale / 120, h * scale / 120)
+                let size = layer.size.unwrap_or_else(|| {
+                    Self::warn_fallback_resolution(&layer.output_info, self.fallback_resolution);
+                    self.fallback_resolution
+                });
+                draw::physical_size(size, layer.fractional_scale.unwrap_or(120))
             });
 
         tracing::debug!(
@@ -835,32 +2753,78 @@ impl GlowBerry {
         // Create GPU surface
         let surface = unsafe { gpu.create_surface(&self.connection, &wl_surface) };
 
+        // HDR shaders get a half-float surface tagged with the HDR image
+        // description, where the compositor supports one
+        let color = self
+            .color_manager
+            .as_ref()
+            .filter(|_| fragment_canvas::source_outputs_hdr(shader_source))
+            .and_then(|manager| manager.hdr_surface(&wl_surface, &self.qh));
+
         // Configure surface at native resolution
-        let surface_config = gpu.configure_surface(&surface, physical_width, physical_height);
+        let surface_config =
+            gpu.configure_surface(&surface, physical_width, physical_height, color.is_some());
+        let color = color.filter(|_| surface_config.format == gpu::HDR_FORMAT);
 
         // Create fragment canvas
-        match fragment_canvas::FragmentCanvas::new(gpu, shader_source, surface_config.format) {
+        match fragment_canvas::FragmentCanvas::new(
+            gpu,
+            shader_source,
+            &surface_config,
+            self.texture_budget,
+        ) {
             Ok(mut canvas) => {
-                canvas.update_resolution(gpu.queue(), physical_width, physical_height);
+                canvas.update_resolution(gpu, physical_width, physical_height);
+                canvas.set_output_refresh(refresh_rate);
+                canvas.set_min_frame_rate(self.shader_min_frame_rate);
+                canvas.set_window_effect(
+                    gpu.device(),
+                    self.wallpapers[wallpaper_idx].entry.window_effect.as_ref(),
+                    windows,
+                );
+                canvas.set_tint(gpu.device(), self.wallpapers[wallpaper_idx].color_tint());
+                if let Some(transition) = &self.wallpapers[wallpaper_idx].entry.transition
+                    && let Some(from) = self.wallpapers[wallpaper_idx].layers[layer_idx]
+                        .gpu_fade
+                        .take()
+                {
+                    canvas.start_transition(gpu.device(), from, transition);
+                }
 
                 // Render the first frame immediately to avoid showing default wallpaper
                 if let wgpu::CurrentSurfaceTexture::Success(surface_texture) =
                     surface.get_current_texture()
                 {
-                    let view = surface_texture
-                        .texture
-                        .create_view(&wgpu::TextureViewDescriptor::default());
-                    canvas.render(gpu, &view);
+                    canvas.render(gpu, &surface_texture.texture);
+                    if let Some(presentation) = &self.presentation {
+                        presentation.feedback(&wl_surface, &self.qh, wl_surface.downgrade());
+                    }
                     surface_texture.present();
                     canvas.mark_frame_rendered();
                     tracing::debug!(output = ?output_name, "Rendered initial shader frame");
                 }
 
+                // Shader output may be translucent unless the swapchain is opaque
+                Self::set_opaque(
+                    &self.compositor_state,
+                    &wl_surface,
+                    surface_config.alpha_mode == wgpu::CompositeAlphaMode::Opaque,
+                );
+
+                let watched = wl_surface.clone();
+                let watchdog = FrameWatchdog::new(&self.loop_handle, move |state: &mut Self| {
+                    state.check_frame_stall(&watched);
+                });
                 let layer = &mut self.wallpapers[wallpaper_idx].layers[layer_idx];
                 layer.gpu_state = Some(GpuLayerState {
                     surface,
                     surface_config,
                     canvas,
+                    frame_pending: false,
+                    color,
+                    output_paused: false,
+                    idle: false,
+                    watchdog,
                 });
 
                 // Set viewport destination to logical size so compositor scales correctly
@@ -871,36 +2835,406 @@ impl GlowBerry {
                 }
 
                 // Request first frame callback to continue animation
-                wl_surface.frame(&self.qh, wl_surface.clone());
+                layer.request_frame(&self.qh);
                 layer.layer.commit();
 
                 tracing::info!(
                     output = ?output_name,
                     "Initialized GPU layer for shader wallpaper"
                 );
+                self.wallpapers[wallpaper_idx]
+                    .record_known_good(&output_name.into_iter().collect::<Vec<_>>());
             }
             Err(err) => {
                 tracing::error!(
                     ?err,
                     "Failed to create fragment canvas for shader wallpaper"
                 );
+                drop(surface);
+                drop(color);
+
+                // Fall back to the last shader that worked on this output
+                let output = output_name.unwrap_or_default();
+                if let Some(known_good) = self.wallpapers[wallpaper_idx].known_good_shader(
+                    &output,
+                    shader_source,
+                    &err.to_string(),
+                ) {
+                    let known_good = self.with_wallpaper_background(known_good);
+                    self.init_gpu_layer_internal(wallpaper_idx, layer_idx, &known_good);
+                }
+            }
+        }
+    }
+
+    /// Resize a layer's SHM pool to `len` bytes, creating it on first use.
+    fn sized_pool<'a>(
+        pool: &'a mut Option<SlotPool>,
+        len: usize,
+        shm: &Shm,
+    ) -> Option<&'a mut SlotPool> {
+        if let Some(pool) = pool.as_mut() {
+            if let Err(why) = pool.resize(len) {
+                tracing::error!(?why, "failed to resize pool");
+                return None;
+            }
+        } else {
+            match SlotPool::new(len, shm) {
+                Ok(new_pool) => {
+                    pool.replace(new_pool);
+                }
+                Err(why) => {
+                    tracing::error!(?why, "failed to create pool");
+                    return None;
+                }
+            }
+        }
+        pool.as_mut()
+    }
+
+    /// Mark all of `surface` opaque, so the compositor can skip blending it
+    /// and cull what's beneath, or clear the region for buffers with alpha.
+    /// The region is clipped to the surface, so it covers every size.
+    pub(crate) fn set_opaque(
+        compositor: &CompositorState,
+        surface: &wl_surface::WlSurface,
+        opaque: bool,
+    ) {
+        if !opaque {
+            surface.set_opaque_region(None);
+            return;
+        }
+        match Region::new(compositor) {
+            Ok(region) => {
+                region.add(0, 0, i32::MAX, i32::MAX);
+                surface.set_opaque_region(Some(region.wl_region()));
+            }
+            Err(why) => tracing::warn!(?why, "failed to create opaque region"),
+        }
+    }
+
+    /// Draw a shader layer through SHM when no GPU is usable, so the output
+    /// shows the shader's background image (or the placeholder) rather than
+    /// staying on whatever was there before.
+    fn draw_shader_fallback(
+        &mut self,
+        wallpaper_idx: usize,
+        layer_idx: usize,
+        err: &gpu::GpuError,
+    ) {
+        let wallpaper = &mut self.wallpapers[wallpaper_idx];
+        let layer = &mut wallpaper.layers[layer_idx];
+        let Some((w, h)) = layer.size else {
+            return;
+        };
+
+        if Self::sized_pool(
+            &mut layer.pool,
+            w as usize * h as usize * 4,
+            &self.shm_state,
+        )
+        .is_none()
+        {
+            return;
+        }
+        layer.needs_redraw = true;
+        Self::set_opaque(&self.compositor_state, layer.layer.wl_surface(), true);
+
+        wallpaper.report_source_error(Some(format!("No usable GPU for the shader: {err}")));
+        wallpaper.draw();
+    }
+
+    /// Hot-reload a shader wallpaper whose shader file, background image, or
+    /// depth map changed on disk.
+    fn on_shader_file_event(&mut self, wallpaper_idx: usize, event: &notify::Event) {
+        use notify::EventKind;
+        use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+
+        let wallpaper = &self.wallpapers[wallpaper_idx];
+        let background = wallpaper
+            .shader_source()
+            .and_then(|s| s.background_image.clone())
+            .filter(|path| event.paths.contains(path));
+        let depth_changed = wallpaper
+            .shader_source()
+            .and_then(|s| s.depth_image.as_ref())
+            .is_some_and(|path| event.paths.contains(path));
+        // Wait for the writer to finish, or for a rename into place
+        let written = matches!(
+            event.kind,
+            EventKind::Access(AccessKind::Close(AccessMode::Write))
+                | EventKind::Modify(ModifyKind::Name(RenameMode::To))
+        );
+
+        if let Some(background) = background {
+            if written {
+                tracing::debug!(
+                    output = wallpaper.entry.output,
+                    path = %background.display(),
+                    "Shader background image modified, re-uploading"
+                );
+                self.reload_shader_background(wallpaper_idx, &background);
+            }
+        } else if depth_changed {
+            // The depth map is only read with the canvas
+            if written {
+                tracing::debug!(
+                    output = wallpaper.entry.output,
+                    "Shader depth map modified, rebuilding"
+                );
+                self.reload_shader(wallpaper_idx);
+            }
+        } else if matches!(event.kind, EventKind::Modify(ModifyKind::Data(_)))
+            && wallpaper
+                .shader_path()
+                .is_some_and(|path| event.paths.iter().any(|p| p == path))
+        {
+            tracing::debug!(
+                output = wallpaper.entry.output,
+                "Shader file modified, triggering hot-reload"
+            );
+            self.reload_shader(wallpaper_idx);
+        }
+    }
+
+    /// Replace the background texture of a shader wallpaper, keeping its
+    /// pipeline.
+    fn reload_shader_background(&mut self, wallpaper_idx: usize, path: &Path) {
+        let Some(gpu) = self.gpu_renderer.as_ref() else {
+            return;
+        };
+
+        for layer in &mut self.wallpapers[wallpaper_idx].layers {
+            let Some(gpu_state) = layer.gpu_state.as_mut() else {
+                continue;
+            };
+
+            match gpu_state.canvas.reload_background(gpu, path) {
+                Ok(()) => {
+                    tracing::info!(
+                        output = ?layer.output_info.name,
+                        "Reloaded shader background image"
+                    );
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        ?err,
+                        output = ?layer.output_info.name,
+                        "Shader background reload failed, keeping previous image"
+                    );
+                }
+            }
+        }
+        self.wake_idle_layers();
+    }
+
+    /// Point a shader that follows the static wallpaper at its current image.
+    fn with_wallpaper_background(
+        &self,
+        source: glowberry_config::ShaderSource,
+    ) -> glowberry_config::ShaderSource {
+        Self::follow_wallpaper(source, self.default_wallpaper_image())
+    }
+
+    /// Point `source` at `wallpaper`, the default wallpaper's image, if it
+    /// follows it and there is one.
+    fn follow_wallpaper(
+        mut source: glowberry_config::ShaderSource,
+        wallpaper: Option<&Path>,
+    ) -> glowberry_config::ShaderSource {
+        if source.background_from_wallpaper {
+            source.background_image = wallpaper.map(Path::to_path_buf).or(source.background_image);
+        }
+        source
+    }
+
+    /// Image currently shown by the default wallpaper, if it is a static one.
+    fn default_wallpaper_image(&self) -> Option<&Path> {
+        self.wallpapers
+            .iter()
+            .find(|w| w.entry.output == glowberry_config::DEFAULT_BACKGROUND)
+            .and_then(Wallpaper::current_image_path)
+    }
+
+    /// Keep shaders that follow the default wallpaper in sync with its
+    /// slideshow.
+    pub(crate) fn on_wallpaper_rotated(&mut self, output: &str) {
+        let followers =
+            Self::wallpaper_followers(output, self.wallpapers.iter().map(Wallpaper::shader_source));
+        if followers.is_empty() {
+            return;
+        }
+        let Some(path) = self.default_wallpaper_image().map(Path::to_path_buf) else {
+            return;
+        };
+
+        for idx in followers {
+            self.reload_shader_background(idx, &path);
+        }
+    }
+
+    /// Indices of the `shaders` that follow the default wallpaper, and so
+    /// change background when the wallpaper of `output` rotated.
+    fn wallpaper_followers<'a>(
+        output: &str,
+        shaders: impl Iterator<Item = Option<&'a glowberry_config::ShaderSource>>,
+    ) -> Vec<usize> {
+        if output != glowberry_config::DEFAULT_BACKGROUND {
+            return Vec::new();
+        }
+        shaders
+            .enumerate()
+            .filter(|(_, shader)| shader.is_some_and(|s| s.background_from_wallpaper))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Show the next item of a playlist wallpaper, moving its layers between
+    /// GPU surfaces and SHM buffers as the kind of item requires.
+    pub(crate) fn next_playlist_item(&mut self, wallpaper_idx: usize) {
+        let was_shader = self.wallpapers[wallpaper_idx].is_shader();
+        self.wallpapers[wallpaper_idx].advance_playlist();
+        self.on_source_switched(wallpaper_idx, was_shader);
+    }
+
+    /// Show the album art at `art` on every output while a track plays, or
+    /// the configured wallpapers again with `None`.
+    fn show_now_playing(&mut self, art: Option<PathBuf>) {
+        self.now_playing_art.clone_from(&art);
+        for idx in 0..self.wallpapers.len() {
+            let was_shader = self.wallpapers[idx].is_shader();
+            if self.wallpapers[idx].set_now_playing(art.clone()) {
+                self.on_source_switched(idx, was_shader);
+            }
+        }
+    }
+
+    /// Show the entries' dark sources while the COSMIC theme is `dark`, or
+    /// their sources again.
+    fn set_dark(&mut self, dark: bool) {
+        if dark == self.dark {
+            return;
+        }
+        self.dark = dark;
+        tracing::info!(dark, "theme mode changed");
+        for idx in 0..self.wallpapers.len() {
+            let was_shader = self.wallpapers[idx].is_shader();
+            if self.wallpapers[idx].set_dark(dark) {
+                self.on_source_switched(idx, was_shader);
+            }
+        }
+    }
+
+    /// Show the sources of entries for the workspaces now active on their
+    /// outputs.
+    fn on_workspaces_changed(&mut self) {
+        for idx in 0..self.wallpapers.len() {
+            // An entry on several outputs would need a source per output, so
+            // it shows its own source on all of them
+            let wallpaper = &self.wallpapers[idx];
+            let workspace = match wallpaper.layers.as_slice() {
+                [layer] => self
+                    .workspaces
+                    .as_ref()
+                    .and_then(|workspaces| workspaces.active(&layer.wl_output))
+                    .map(str::to_owned),
+                layers => {
+                    if layers.len() > 1 && !wallpaper.entry.workspaces.is_empty() {
+                        tracing::warn!(
+                            output = wallpaper.entry.output,
+                            "Entry shown on several outputs ignores its workspace sources; \
+                             give each output its own entry"
+                        );
+                    }
+                    None
+                }
+            };
+            let was_shader = self.wallpapers[idx].is_shader();
+            if self.wallpapers[idx].set_workspace(workspace) {
+                self.on_source_switched(idx, was_shader);
+            }
+        }
+    }
+
+    /// Recreate a wallpaper's layers for the source it switched to, from a
+    /// shader one with `was_shader`.
+    pub(crate) fn on_source_switched(&mut self, wallpaper_idx: usize, was_shader: bool) {
+        // Static outputs transition from the frame they show
+        self.wallpapers[wallpaper_idx].start_transition();
+        let transition = self.wallpapers[wallpaper_idx].entry.transition.is_some();
+
+        if let Some(shader_source) = self.wallpapers[wallpaper_idx].shader_source().cloned() {
+            let shader_source = self.with_wallpaper_background(shader_source);
+            for layer_idx in 0..self.wallpapers[wallpaper_idx].layers.len() {
+                let layer = &mut self.wallpapers[wallpaper_idx].layers[layer_idx];
+                // Unconfigured layers, and layers while the GPU is released,
+                // are initialized later
+                let reinit = layer.size.is_some() && !self.gpu_released;
+                // The new canvas transitions from the old one's last frame
+                if reinit
+                    && transition
+                    && let Some(gpu) = self.gpu_renderer.as_ref()
+                    && let Some(gpu_state) = layer.gpu_state.as_mut()
+                {
+                    layer.gpu_fade = Some(gpu_state.canvas.capture(gpu));
+                }
+                // The swapchain must be gone before another is created
+                layer.gpu_state = None;
+                if reinit {
+                    self.reinit_gpu_layer(wallpaper_idx, layer_idx, &shader_source);
+                }
+            }
+            self.stop_unused_monitors();
+            return;
+        }
+
+        let wallpaper = &mut self.wallpapers[wallpaper_idx];
+        if was_shader {
+            for layer in &mut wallpaper.layers {
+                layer.gpu_state = None;
+                let Some((w, h)) = layer.size else {
+                    continue;
+                };
+                if Self::sized_pool(
+                    &mut layer.pool,
+                    w as usize * h as usize * 4,
+                    &self.shm_state,
+                )
+                .is_some()
+                {
+                    Self::set_opaque(&self.compositor_state, layer.layer.wl_surface(), true);
+                }
             }
         }
+        wallpaper.draw();
+        self.stop_unused_monitors();
     }
 
     /// Hot-reload a shader by rebuilding the FragmentCanvas for all layers of a wallpaper.
     /// Keeps the existing surface and surface_config; only replaces the canvas.
     /// On failure, keeps the previous (working) canvas.
     fn reload_shader(&mut self, wallpaper_idx: usize) {
+        self.wallpapers[wallpaper_idx].reload_composite();
         let Some(gpu) = self.gpu_renderer.as_ref() else {
             return;
         };
 
-        let shader_source = match &self.wallpapers[wallpaper_idx].entry.source {
-            Source::Shader(s) => s.clone(),
+        let wallpaper = &self.wallpapers[wallpaper_idx];
+        let shader_source = match (wallpaper.source(), wallpaper.shader_source()) {
+            (Source::Shader(s), _) => self.with_wallpaper_background(s.clone()),
+            (Source::Composite(_) | Source::Parallax(_), Some(s)) => s.clone(),
             _ => return,
         };
+        let window_effect = wallpaper.entry.window_effect.clone();
+        let tint = wallpaper.color_tint();
+        let windows: Vec<WlOutput> = wallpaper
+            .layers
+            .iter()
+            .map(|layer| layer.wl_output.clone())
+            .filter(|output| self.has_windows(output))
+            .collect();
 
+        let mut reloaded = Vec::new();
         for layer_idx in 0..self.wallpapers[wallpaper_idx].layers.len() {
             let layer = &mut self.wallpapers[wallpaper_idx].layers[layer_idx];
             let Some(gpu_state) = layer.gpu_state.as_mut() else {
@@ -910,15 +3244,25 @@ impl GlowBerry {
             match fragment_canvas::FragmentCanvas::new(
                 gpu,
                 &shader_source,
-                gpu_state.surface_config.format,
+                &gpu_state.surface_config,
+                self.texture_budget,
             ) {
-                Ok(canvas) => {
+                Ok(mut canvas) => {
                     canvas.update_resolution(
-                        gpu.queue(),
+                        gpu,
                         gpu_state.surface_config.width,
                         gpu_state.surface_config.height,
                     );
+                    canvas.set_output_refresh(Self::output_refresh_rate(&layer.output_info.modes));
+                    canvas.set_min_frame_rate(self.shader_min_frame_rate);
+                    canvas.set_window_effect(
+                        gpu.device(),
+                        window_effect.as_ref(),
+                        windows.contains(&layer.wl_output),
+                    );
+                    canvas.set_tint(gpu.device(), tint);
                     gpu_state.canvas = canvas;
+                    reloaded.push(layer.output_info.name.clone().unwrap_or_default());
                     tracing::info!(
                         output = ?layer.output_info.name,
                         "Hot-reloaded shader"
@@ -933,6 +3277,48 @@ impl GlowBerry {
                 }
             }
         }
+
+        // A fixed shader replaces its last-known-good substitute
+        if !reloaded.is_empty() {
+            let wallpaper = &mut self.wallpapers[wallpaper_idx];
+            wallpaper.clear_substitute();
+            wallpaper.record_known_good(&reloaded);
+            self.refresh_hdr_layers();
+            self.wake_idle_layers();
+            self.stop_unused_monitors();
+        }
+    }
+
+    /// Recreate shader layers whose surface is HDR when their shader no
+    /// longer asks for it, or SDR when it does and HDR became available.
+    fn refresh_hdr_layers(&mut self) {
+        let hdr_ready = self
+            .color_manager
+            .as_ref()
+            .is_some_and(ColorManager::hdr_ready);
+
+        for wallpaper_idx in 0..self.wallpapers.len() {
+            let Some(shader_source) = self.wallpapers[wallpaper_idx].shader_source().cloned()
+            else {
+                continue;
+            };
+            let hdr = hdr_ready && fragment_canvas::source_outputs_hdr(&shader_source);
+            let shader_source = self.with_wallpaper_background(shader_source);
+
+            for layer_idx in 0..self.wallpapers[wallpaper_idx].layers.len() {
+                let layer = &mut self.wallpapers[wallpaper_idx].layers[layer_idx];
+                if layer
+                    .gpu_state
+                    .as_ref()
+                    .is_none_or(|gpu_state| gpu_state.color.is_some() == hdr)
+                {
+                    continue;
+                }
+                tracing::info!(output = ?layer.output_info.name, hdr, "Recreating shader surface");
+                layer.gpu_state = None;
+                self.reinit_gpu_layer(wallpaper_idx, layer_idx, &shader_source);
+            }
+        }
     }
 }
 
@@ -959,13 +3345,14 @@ impl CompositorHandler for GlowBerry {
 
             if let Some((wallpaper_idx, layer_idx, is_shader)) = target {
                 let qh = self.qh.clone();
+                let fallback = self.fallback_resolution;
                 let gpu = self.gpu_renderer.as_ref();
                 let wallpaper = &mut self.wallpapers[wallpaper_idx];
                 let layer = &mut wallpaper.layers[layer_idx];
                 layer.fractional_scale = Some(new_factor as u32 * 120);
                 if is_shader {
                     if let Some(gpu) = gpu {
-                        Self::update_shader_layer_surface(gpu, &qh, layer);
+                        Self::update_shader_layer_surface(gpu, &qh, layer, fallback);
                     }
                 } else {
                     wallpaper.draw();
@@ -987,7 +3374,31 @@ impl CompositorHandler for GlowBerry {
         // Check if animation should be paused due to power state
         let should_pause = self.should_pause_animation();
 
+        if self
+            .gpu_renderer
+            .as_ref()
+            .is_some_and(gpu::GpuRenderer::is_lost)
+        {
+            self.drop_lost_gpu();
+        }
+        if self.gpu_lost {
+            if should_pause {
+                // Dropped layers are recreated once animation resumes
+                self.was_animation_paused = true;
+            } else if self.recover_gpu_layer(surface) {
+                return;
+            }
+        }
+
+        let output_paused = self
+            .wallpapers
+            .iter()
+            .flat_map(|wallpaper| &wallpaper.layers)
+            .find(|l| l.layer.wl_surface() == surface)
+            .is_some_and(|l| self.is_output_paused(&l.wl_output));
+
         // Find the wallpaper and layer for this surface
+        let mut queued = false;
         for wallpaper in &mut self.wallpapers {
             if let Some(layer) = wallpaper
                 .layers
@@ -996,91 +3407,54 @@ impl CompositorHandler for GlowBerry {
             {
                 // Check if this is a shader wallpaper with GPU state
                 if let Some(gpu_state) = &mut layer.gpu_state {
-                    // Skip rendering if paused, but still request frame callback
-                    // so we can resume when power state changes
-                    if !should_pause {
-                        // Check if we should render this frame (frame rate limiting)
-                        if gpu_state.canvas.should_render()
-                            && let Some(gpu) = &self.gpu_renderer
-                        {
-                            // Get current texture
-                            match gpu_state.surface.get_current_texture() {
-                                wgpu::CurrentSurfaceTexture::Success(surface_texture)
-                                | wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
-                                    let view = surface_texture
-                                        .texture
-                                        .create_view(&wgpu::TextureViewDescriptor::default());
-
-                                    // Update resolution for this specific layer's surface
-                                    let width = gpu_state.surface_config.width;
-                                    let height = gpu_state.surface_config.height;
-
-                                    tracing::trace!(
-                                        output = ?layer.output_info.name,
-                                        width,
-                                        height,
-                                        "Rendering shader frame"
-                                    );
-
-                                    gpu_state
-                                        .canvas
-                                        .update_resolution(gpu.queue(), width, height);
-
-                                    // Render the shader
-                                    gpu_state.canvas.render(gpu, &view);
-
-                                    // Present
-                                    surface_texture.present();
-
-                                    gpu_state.canvas.mark_frame_rendered();
-                                }
-                                wgpu::CurrentSurfaceTexture::Timeout => {
-                                    tracing::warn!("GPU surface timeout");
-                                }
-                                wgpu::CurrentSurfaceTexture::Lost
-                                | wgpu::CurrentSurfaceTexture::Outdated => {
-                                    let width = gpu_state.surface_config.width;
-                                    let height = gpu_state.surface_config.height;
-                                    gpu_state.surface_config =
-                                        gpu.configure_surface(&gpu_state.surface, width, height);
-                                    gpu_state
-                                        .canvas
-                                        .update_resolution(gpu.queue(), width, height);
-                                    tracing::warn!(
-                                        "GPU surface lost or outdated; reconfigured surface"
-                                    );
-                                }
-                                other => {
-                                    tracing::warn!(?other, "GPU surface error");
-                                }
-                            }
-                        }
-                    }
-
-                    // Request next frame callback to continue animation
-                    // Only request if not paused - when paused, GPU goes truly idle
-                    // The on_power_state_changed handler will request frames when resuming
-                    if !should_pause {
-                        surface.frame(qh, surface.clone());
-                        layer.layer.commit();
-                    } else {
-                        // Track that we're paused so on_power_state_changed can resume us
+                    gpu_state.watchdog.reset();
+                    if output_paused {
+                        // Resumed by resume_output once the display is back on
+                        // and windows no longer hide it
+                        gpu_state.output_paused = true;
+                        tracing::debug!(output = ?layer.output_info.name, "Output hidden or off, not requesting frame callback");
+                    } else if should_pause {
+                        // Don't request a frame callback - when paused, GPU goes truly idle.
+                        // Track that we're paused so on_power_state_changed or
+                        // on_session_lock_changed can resume us
                         self.was_animation_paused = true;
                         tracing::debug!(output = ?layer.output_info.name, "Shader paused, not requesting frame callback");
+                    } else if gpu_state.canvas.should_render() && self.gpu_renderer.is_some() {
+                        // Rendered along with every other output whose callback
+                        // arrives in this dispatch, which requests the next one
+                        gpu_state.frame_pending = true;
+                        queued = true;
+                    } else if !gpu_state.canvas.needs_frames() {
+                        // Woken by wake_idle_layers once something changes
+                        gpu_state.idle = true;
+                    } else {
+                        // Request next frame callback to continue animation
+                        layer.request_frame(qh);
+                        layer.layer.commit();
                     }
                 }
                 break;
             }
         }
+
+        if queued && !std::mem::replace(&mut self.frame_render_scheduled, true) {
+            self.loop_handle
+                .insert_idle(|state| state.render_pending_frames());
+        }
+
+        if self.was_animation_paused {
+            self.schedule_gpu_release();
+        }
     }
 
     fn transform_changed(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_transform: wl_output::Transform,
+        surface: &wl_surface::WlSurface,
+        new_transform: wl_output::Transform,
     ) {
+        self.set_layer_transform(surface, new_transform);
     }
 
     fn surface_enter(
@@ -1102,6 +3476,15 @@ impl CompositorHandler for GlowBerry {
     }
 }
 
+/// Wallpaper an output joins when it is connected.
+#[derive(Debug, PartialEq)]
+enum OutputTarget<'a> {
+    /// The wallpaper at this index, showing the output's entry
+    Existing(usize),
+    /// A new wallpaper of this entry, which no connected output showed
+    New(&'a Entry),
+}
+
 impl OutputHandler for GlowBerry {
     fn output_state(&mut self) -> &mut OutputState {
         &mut self.output_state
@@ -1114,25 +3497,55 @@ impl OutputHandler for GlowBerry {
         wl_output: wl_output::WlOutput,
     ) {
         self.active_outputs.push(wl_output.clone());
+        if let Some(output_power) = &mut self.output_power {
+            output_power.add_output(&wl_output, &self.qh);
+        }
         let Some(output_info) = self.output_state.info(&wl_output) else {
             return;
         };
 
-        if let Some(pos) = self
+        let shown: Vec<&str> = self
             .wallpapers
             .iter()
-            .position(|w| match w.entry.output.as_str() {
-                "all" => !w.layers.iter().any(|l| l.wl_output == wl_output),
-                name => {
-                    Some(name) == output_info.name.as_deref()
-                        && !w.layers.iter().any(|l| l.wl_output == wl_output)
+            .map(|w| w.entry.output.as_str())
+            .collect();
+        match Self::output_target(&self.config, output_info.name.as_deref(), &shown) {
+            OutputTarget::Existing(pos) => {
+                if !self.wallpapers[pos]
+                    .layers
+                    .iter()
+                    .any(|l| l.wl_output == wl_output)
+                {
+                    let layer = self.new_layer(wl_output, output_info);
+                    self.wallpapers[pos].layers.push(layer);
+                    self.wallpapers[pos].relayout();
+                    self.wallpapers[pos].filter_for_outputs();
+                    if let Err(err) = self.wallpapers[pos].save_state() {
+                        tracing::error!("{err}");
+                    }
+                    self.on_workspaces_changed();
                 }
-            })
-        {
-            let layer = self.new_layer(wl_output, output_info);
-            self.wallpapers[pos].layers.push(layer);
-            if let Err(err) = self.wallpapers[pos].save_state() {
-                tracing::error!("{err}");
+            }
+            // The first connected output of its entry
+            OutputTarget::New(entry) => {
+                let mut wallpaper = Wallpaper::new(
+                    entry.clone(),
+                    self.qh.clone(),
+                    self.loop_handle.clone(),
+                    self.source_tx.clone(),
+                    self.compositor_state.clone(),
+                );
+                wallpaper
+                    .layers
+                    .push(self.new_layer(wl_output, output_info));
+                self.prepare_wallpaper(&mut wallpaper);
+                // Sorted by entry, with the default background last
+                let pos = self.wallpapers.partition_point(|w| {
+                    w.entry.output != glowberry_config::DEFAULT_BACKGROUND
+                        && w.entry.output < wallpaper.entry.output
+                });
+                self.wallpapers.insert(pos, wallpaper);
+                self.on_workspaces_changed();
             }
         }
 
@@ -1167,6 +3580,7 @@ impl OutputHandler for GlowBerry {
 
             if let Some((wallpaper_idx, layer_idx, is_shader)) = target {
                 let qh = self.qh.clone();
+                let fallback = self.fallback_resolution;
                 let gpu = self.gpu_renderer.as_ref();
                 let wallpaper = &mut self.wallpapers[wallpaper_idx];
                 let layer = &mut wallpaper.layers[layer_idx];
@@ -1174,13 +3588,64 @@ impl OutputHandler for GlowBerry {
                 layer.fractional_scale = Some(layer.output_info.scale_factor as u32 * 120);
                 if is_shader {
                     if let Some(gpu) = gpu {
-                        Self::update_shader_layer_surface(gpu, &qh, layer);
+                        Self::update_shader_layer_surface(gpu, &qh, layer, fallback);
                     }
                 } else {
                     wallpaper.draw();
                 }
             }
         }
+
+        // Older compositors don't send a preferred buffer transform
+        if self.compositor_state.wl_compositor().version() < 6
+            && let Some(output_info) = self.output_state.info(&output)
+        {
+            let surfaces: Vec<_> = self
+                .wallpapers
+                .iter()
+                .flat_map(|wallpaper| wallpaper.layers.iter())
+                .filter(|layer| layer.wl_output == output)
+                .map(|layer| layer.layer.wl_surface().clone())
+                .collect();
+            for surface in surfaces {
+                self.set_layer_transform(&surface, output_info.transform);
+            }
+        }
+
+        // Spanned images are sliced by the layout, which may have changed
+        if let Some(output_info) = self.output_state.info(&output) {
+            for wallpaper in &mut self.wallpapers {
+                let Some(layer) = wallpaper
+                    .layers
+                    .iter_mut()
+                    .find(|layer| layer.wl_output == output)
+                else {
+                    continue;
+                };
+                let moved = layer.output_info.logical_position != output_info.logical_position
+                    || layer.output_info.logical_size != output_info.logical_size;
+                if moved {
+                    layer.output_info.logical_position = output_info.logical_position;
+                    layer.output_info.logical_size = output_info.logical_size;
+                    wallpaper.relayout();
+                }
+            }
+        }
+
+        // Re-snap shader frame rates after a mode change
+        if let Some(output_info) = self.output_state.info(&output) {
+            let refresh_rate = Self::output_refresh_rate(&output_info.modes);
+            for layer in self
+                .wallpapers
+                .iter_mut()
+                .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+                .filter(|layer| layer.wl_output == output)
+            {
+                if let Some(gpu_state) = &mut layer.gpu_state {
+                    gpu_state.canvas.set_output_refresh(refresh_rate);
+                }
+            }
+        }
     }
 
     fn output_destroyed(
@@ -1190,6 +3655,9 @@ impl OutputHandler for GlowBerry {
         output: wl_output::WlOutput,
     ) {
         self.active_outputs.retain(|o| o != &output);
+        if let Some(output_power) = &mut self.output_power {
+            output_power.remove_output(&output);
+        }
         let Some(output_info) = self.output_state.info(&output) else {
             // Still try to save connected outputs even if we can't get info
             self.save_connected_outputs();
@@ -1210,13 +3678,10 @@ impl OutputHandler for GlowBerry {
         // Update connected outputs in state for settings app
         self.save_connected_outputs();
 
-        let Some(output_wallpaper) =
-            self.wallpapers
-                .iter_mut()
-                .find(|w| match w.entry.output.as_str() {
-                    "all" => true,
-                    name => Some(name) == output_info.name.as_deref(),
-                })
+        let Some(output_wallpaper) = self
+            .wallpapers
+            .iter_mut()
+            .find(|w| w.layers.iter().any(|l| l.wl_output == output))
         else {
             return;
         };
@@ -1230,6 +3695,8 @@ impl OutputHandler for GlowBerry {
         };
 
         output_wallpaper.layers.remove(layer_position);
+        output_wallpaper.relayout();
+        self.stop_unused_monitors();
     }
 }
 
@@ -1240,11 +3707,14 @@ impl LayerShellHandler for GlowBerry {
         _qh: &QueueHandle<Self>,
         dropped_layer: &LayerSurface,
     ) {
+        self.identify_overlays
+            .retain(|overlay| &overlay.layer != dropped_layer);
         for wallpaper in &mut self.wallpapers {
             wallpaper
                 .layers
                 .retain(|layer| &layer.layer != dropped_layer);
         }
+        self.stop_unused_monitors();
     }
 
     fn configure(
@@ -1258,6 +3728,15 @@ impl LayerShellHandler for GlowBerry {
         let span = tracing::debug_span!("<GlowBerry as LayerShellHandler>::configure");
         let _handle = span.enter();
 
+        if let Some(overlay) = self
+            .identify_overlays
+            .iter_mut()
+            .find(|overlay| &overlay.layer == layer)
+        {
+            overlay.draw(&self.shm_state);
+            return;
+        }
+
         let (w, h) = configure.new_size;
 
         // Find the wallpaper and layer index for this surface
@@ -1281,20 +3760,25 @@ impl LayerShellHandler for GlowBerry {
         let Some((wp_idx, layer_idx, is_shader, shader_source)) = found_info else {
             return;
         };
+        let shader_source = shader_source.map(|s| self.with_wallpaper_background(s));
 
         if is_shader {
             // Initialize or update GPU state for shader wallpapers
             if let Some(shader_source) = shader_source {
                 let w_layer = &mut self.wallpapers[wp_idx].layers[layer_idx];
 
-                if w_layer.gpu_state.is_none() {
+                if self.gpu_released || (self.gpu_lost && w_layer.gpu_state.is_none()) {
+                    // Recreated at the new size when animation resumes, or
+                    // on the next frame callback after a device loss
+                } else if w_layer.gpu_state.is_none() {
                     // Initialize GPU state
                     self.init_gpu_layer_internal(wp_idx, layer_idx, &shader_source);
                 } else {
                     let qh = self.qh.clone();
+                    let fallback = self.fallback_resolution;
                     if let Some(gpu) = self.gpu_renderer.as_ref() {
                         let layer = &mut self.wallpapers[wp_idx].layers[layer_idx];
-                        Self::update_shader_layer_surface(gpu, &qh, layer);
+                        Self::update_shader_layer_surface(gpu, &qh, layer, fallback);
                     }
                 }
             }
@@ -1330,7 +3814,83 @@ impl ShmHandler for GlowBerry {
     }
 }
 
+impl DmabufHandler for GlowBerry {
+    fn dmabuf_state(&mut self) -> &mut DmabufState {
+        &mut self.dmabuf_state
+    }
+
+    fn dmabuf_feedback(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _proxy: &zwp_linux_dmabuf_feedback_v1::ZwpLinuxDmabufFeedbackV1,
+        feedback: DmabufFeedback,
+    ) {
+        if self.dmabuf_allocator.is_some() {
+            return;
+        }
+        let Some(dmabuf) = self.dmabuf.clone() else {
+            return;
+        };
+        let Some(allocator) = dmabuf::Allocator::new(dmabuf, &feedback) else {
+            return;
+        };
+
+        // Move static wallpapers already on screen out of their SHM pools
+        let allocator = Rc::new(allocator);
+        self.dmabuf_allocator = Some(allocator.clone());
+        for wallpaper in &mut self.wallpapers {
+            wallpaper.dmabuf = Some(allocator.clone());
+            if wallpaper.is_shader() {
+                continue;
+            }
+            for layer in &mut wallpaper.layers {
+                layer.needs_redraw = layer.pool.is_some();
+            }
+            wallpaper.draw();
+        }
+    }
+
+    fn created(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _params: &zwp_linux_buffer_params_v1::ZwpLinuxBufferParamsV1,
+        _buffer: wl_buffer::WlBuffer,
+    ) {
+        // Buffers are created with `create_immed`
+    }
+
+    fn failed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _params: &zwp_linux_buffer_params_v1::ZwpLinuxBufferParamsV1,
+    ) {
+        tracing::warn!("Compositor rejected a dmabuf wallpaper buffer");
+    }
+
+    fn released(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        buffer: &wl_buffer::WlBuffer,
+    ) {
+        for layer in self.wallpapers.iter_mut().flat_map(|w| w.layers.iter_mut()) {
+            for dmabuf in layer.dmabuf.iter_mut().chain(layer.dmabuf_spare.iter_mut()) {
+                if dmabuf.wl_buffer == *buffer {
+                    dmabuf.released = true;
+                }
+            }
+            layer
+                .dmabuf_retired
+                .retain(|dmabuf| dmabuf.wl_buffer != *buffer);
+        }
+    }
+}
+
 delegate_compositor!(GlowBerry);
+delegate_dmabuf!(GlowBerry);
 delegate_output!(GlowBerry);
 delegate_shm!(GlowBerry);
 delegate_layer!(GlowBerry);
@@ -1338,6 +3898,72 @@ delegate_registry!(GlowBerry);
 delegate_noop!(GlowBerry: wp_viewporter::WpViewporter);
 delegate_noop!(GlowBerry: wp_viewport::WpViewport);
 delegate_noop!(GlowBerry: wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1);
+delegate_noop!(GlowBerry: ignore wp_presentation::WpPresentation);
+delegate_noop!(GlowBerry: wp_color_management_surface_v1::WpColorManagementSurfaceV1);
+delegate_noop!(GlowBerry: zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1);
+delegate_noop!(GlowBerry: ignore zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1);
+delegate_noop!(GlowBerry: ext_idle_notifier_v1::ExtIdleNotifierV1);
+delegate_noop!(GlowBerry: ignore wl_seat::WlSeat);
+delegate_noop!(GlowBerry: wp_cursor_shape_manager_v1::WpCursorShapeManagerV1);
+delegate_noop!(GlowBerry: wp_cursor_shape_device_v1::WpCursorShapeDeviceV1);
+delegate_noop!(
+    GlowBerry: wp_image_description_creator_params_v1::WpImageDescriptionCreatorParamsV1
+);
+
+impl Dispatch<wl_callback::WlCallback, ShaderFrame> for GlowBerry {
+    fn event(
+        state: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        frame: &ShaderFrame,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let wl_callback::Event::Done { callback_data } = event else {
+            return;
+        };
+        // A callback the watchdog gave up on would start a second render
+        // chain. Layers that lost their GPU state take any, to recover.
+        let current = state
+            .wallpapers
+            .iter()
+            .flat_map(|wallpaper| &wallpaper.layers)
+            .find(|layer| layer.layer.wl_surface() == &frame.surface)
+            .and_then(|layer| layer.gpu_state.as_ref())
+            .is_none_or(|gpu_state| gpu_state.watchdog.generation() == frame.generation);
+        if !current {
+            tracing::debug!("Ignoring superseded frame callback");
+            return;
+        }
+        state.frame(conn, qh, &frame.surface, callback_data);
+    }
+}
+
+impl Dispatch<wp_presentation_feedback::WpPresentationFeedback, Weak<wl_surface::WlSurface>>
+    for GlowBerry
+{
+    fn event(
+        state: &mut GlowBerry,
+        _: &wp_presentation_feedback::WpPresentationFeedback,
+        event: wp_presentation_feedback::Event,
+        surface: &Weak<wl_surface::WlSurface>,
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        // Discarded frames and sync_output carry nothing the pacer needs
+        if let wp_presentation_feedback::Event::Presented { refresh, .. } = event
+            && let Ok(surface) = surface.upgrade()
+            && let Some(gpu_state) = state
+                .wallpapers
+                .iter_mut()
+                .flat_map(|wallpaper| wallpaper.layers.iter_mut())
+                .find(|layer| layer.layer.wl_surface() == &surface)
+                .and_then(|layer| layer.gpu_state.as_mut())
+        {
+            gpu_state.canvas.on_presented(refresh);
+        }
+    }
+}
 
 impl Dispatch<wp_fractional_scale_v1::WpFractionalScaleV1, Weak<wl_surface::WlSurface>>
     for GlowBerry
@@ -1367,14 +3993,14 @@ impl Dispatch<wp_fractional_scale_v1::WpFractionalScaleV1, Weak<wl_surface::WlSu
 
                     if let Some((wallpaper_idx, layer_idx, is_shader)) = target {
                         let qh = state.qh.clone();
+                        let fallback = state.fallback_resolution;
                         let gpu = state.gpu_renderer.as_ref();
                         let wallpaper = &mut state.wallpapers[wallpaper_idx];
                         let layer = &mut wallpaper.layers[layer_idx];
                         layer.fractional_scale = Some(scale);
-                        if is_shader {
-                            if let Some(gpu) = gpu {
-                                GlowBerry::update_shader_layer_surface(gpu, &qh, layer);
-                            }
+                        // Shader layers without a GPU are drawn like static ones
+                        if is_shader && let Some(gpu) = gpu {
+                            GlowBerry::update_shader_layer_surface(gpu, &qh, layer, fallback);
                         } else {
                             wallpaper.draw();
                         }
@@ -1393,9 +4019,282 @@ impl ProvidesRegistryState for GlowBerry {
     registry_handlers![OutputState];
 }
 
+impl Dispatch<wp_color_manager_v1::WpColorManagerV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        _: &wp_color_manager_v1::WpColorManagerV1,
+        event: wp_color_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<GlowBerry>,
+    ) {
+        if let Some(color_manager) = state.color_manager.as_mut() {
+            color_manager.on_event(event, qh);
+        }
+    }
+}
+
+impl Dispatch<wp_image_description_v1::WpImageDescriptionV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        _: &wp_image_description_v1::WpImageDescriptionV1,
+        event: wp_image_description_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        let Some(color_manager) = state.color_manager.as_mut() else {
+            return;
+        };
+        match event {
+            wp_image_description_v1::Event::Ready { .. }
+            | wp_image_description_v1::Event::Ready2 { .. } => {
+                color_manager.on_hdr_ready();
+                // Shader layers created before it was ready are still SDR
+                state.refresh_hdr_layers();
+            }
+            wp_image_description_v1::Event::Failed { cause, msg } => {
+                color_manager.on_hdr_failed(cause, &msg);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwlr_output_power_v1::ZwlrOutputPowerV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        power: &zwlr_output_power_v1::ZwlrOutputPowerV1,
+        event: zwlr_output_power_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        let changed = state
+            .output_power
+            .as_mut()
+            .and_then(|output_power| output_power.on_event(power, event));
+        if let Some((output, off)) = changed {
+            state.on_output_power_changed(&output, off);
+        }
+    }
+}
+
+impl Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        _: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        let changed = state
+            .idle_monitor
+            .as_mut()
+            .and_then(|idle_monitor| idle_monitor.on_event(event));
+        if let Some(idle) = changed {
+            state.on_idle_changed(idle);
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, PointerSeat> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &PointerSeat,
+        _: &Connection,
+        qh: &QueueHandle<GlowBerry>,
+    ) {
+        if let wl_seat::Event::Capabilities { capabilities } = event
+            && let Some(pointer) = &mut state.pointer
+        {
+            pointer.set_capabilities(seat, capabilities, qh);
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        _: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        let changed = state
+            .pointer
+            .as_mut()
+            .and_then(|pointer| pointer.on_event(event));
+        if let Some(event) = changed {
+            state.on_pointer(event);
+        }
+    }
+}
+
+impl Dispatch<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        _: &zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event
+            && let Some(toplevels) = &mut state.toplevels
+        {
+            toplevels.add_wlr(toplevel);
+        }
+    }
+
+    event_created_child!(GlowBerry, zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (
+            zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+            ()
+        )
+    ]);
+}
+
+impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        handle: &zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        state.on_toplevel_event(handle.id(), ToplevelEvent::from_wlr(event));
+    }
+}
+
+impl Dispatch<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        _: &ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<GlowBerry>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event
+            && let Some(toplevels) = &mut state.toplevels
+        {
+            toplevels.add_foreign(toplevel, qh);
+        }
+    }
+
+    event_created_child!(GlowBerry, ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (
+            ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+            ()
+        )
+    ]);
+}
+
+impl Dispatch<ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        handle: &ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        state.on_toplevel_event(handle.id(), ToplevelEvent::from_foreign(event));
+    }
+}
+
+impl Dispatch<ext_workspace_manager_v1::ExtWorkspaceManagerV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        _: &ext_workspace_manager_v1::ExtWorkspaceManagerV1,
+        event: ext_workspace_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        match event {
+            ext_workspace_manager_v1::Event::WorkspaceGroup { workspace_group } => {
+                if let Some(workspaces) = &mut state.workspaces {
+                    workspaces.add_group(workspace_group);
+                }
+            }
+            ext_workspace_manager_v1::Event::Workspace { workspace } => {
+                if let Some(workspaces) = &mut state.workspaces {
+                    workspaces.add_workspace(workspace);
+                }
+            }
+            ext_workspace_manager_v1::Event::Done => state.on_workspaces_changed(),
+            _ => {}
+        }
+    }
+
+    event_created_child!(GlowBerry, ext_workspace_manager_v1::ExtWorkspaceManagerV1, [
+        ext_workspace_manager_v1::EVT_WORKSPACE_GROUP_OPCODE => (
+            ext_workspace_group_handle_v1::ExtWorkspaceGroupHandleV1,
+            ()
+        ),
+        ext_workspace_manager_v1::EVT_WORKSPACE_OPCODE => (
+            ext_workspace_handle_v1::ExtWorkspaceHandleV1,
+            ()
+        )
+    ]);
+}
+
+impl Dispatch<ext_workspace_group_handle_v1::ExtWorkspaceGroupHandleV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        handle: &ext_workspace_group_handle_v1::ExtWorkspaceGroupHandleV1,
+        event: ext_workspace_group_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        if let Some(workspaces) = &mut state.workspaces {
+            workspaces.on_group_event(handle, event);
+        }
+    }
+}
+
+impl Dispatch<ext_workspace_handle_v1::ExtWorkspaceHandleV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        handle: &ext_workspace_handle_v1::ExtWorkspaceHandleV1,
+        event: ext_workspace_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        if let Some(workspaces) = &mut state.workspaces {
+            workspaces.on_workspace_event(handle, event);
+        }
+    }
+}
+
+impl Dispatch<zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1, ()> for GlowBerry {
+    fn event(
+        state: &mut GlowBerry,
+        handle: &zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1,
+        event: zcosmic_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<GlowBerry>,
+    ) {
+        state.on_toplevel_event(handle.id(), ToplevelEvent::from_cosmic(event));
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::GlowBerry;
+    use super::{GlowBerry, OutputTarget};
+    use glowberry_config::{Color, Config, Entry, ShaderContent, ShaderSource, Source};
+    use sctk::output::Mode;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn shader_physical_size_prefers_layer_size_over_mode() {
@@ -1403,22 +4302,164 @@ mod tests {
         let scale = Some(150);
         let mode = Some((1920, 1080));
 
-        let result = GlowBerry::shader_physical_size(size, scale, mode);
+        let result = GlowBerry::shader_physical_size(size, scale, mode, (1920, 1080));
 
-        assert_eq!(result, (125, 62));
+        // 62.5 rounds up, like the compositor rounds the surface size
+        assert_eq!(result, (125, 63));
     }
 
     #[test]
     fn shader_physical_size_uses_mode_when_size_missing() {
-        let result = GlowBerry::shader_physical_size(None, Some(150), Some((1280, 720)));
+        let result =
+            GlowBerry::shader_physical_size(None, Some(150), Some((1280, 720)), (1920, 1080));
 
         assert_eq!(result, (1280, 720));
     }
 
     #[test]
     fn shader_physical_size_defaults_scale_to_120() {
-        let result = GlowBerry::shader_physical_size(Some((1200, 800)), None, Some((640, 480)));
+        let result = GlowBerry::shader_physical_size(
+            Some((1200, 800)),
+            None,
+            Some((640, 480)),
+            (1920, 1080),
+        );
 
         assert_eq!(result, (1200, 800));
     }
+
+    #[test]
+    fn shader_physical_size_uses_configured_fallback() {
+        let result = GlowBerry::shader_physical_size(None, Some(240), None, (2560, 1440));
+
+        assert_eq!(result, (5120, 2880));
+    }
+
+    #[test]
+    fn hotplugged_output_gets_its_inactive_entry() {
+        let source = || Source::Color(Color::Single([0.0; 3]));
+        let config = Config {
+            same_on_all: false,
+            backgrounds: vec![
+                Entry::new("DP-1".into(), source()),
+                Entry::new("HDMI-*".into(), source()),
+            ],
+            ..Config::default()
+        };
+        let shown = ["DP-1", glowberry_config::DEFAULT_BACKGROUND];
+
+        assert_eq!(
+            GlowBerry::output_target(&config, Some("DP-1"), &shown),
+            OutputTarget::Existing(0)
+        );
+        assert_eq!(
+            GlowBerry::output_target(&config, Some("HDMI-A-1"), &shown),
+            OutputTarget::New(&config.backgrounds[1])
+        );
+        assert_eq!(
+            GlowBerry::output_target(&config, Some("eDP-1"), &shown),
+            OutputTarget::Existing(1)
+        );
+        assert_eq!(
+            GlowBerry::output_target(&config, None, &shown[..1]),
+            OutputTarget::New(&config.default_background)
+        );
+    }
+
+    #[test]
+    fn mirror_groups_share_frames_by_key() {
+        let keys = [
+            Some((0, 1920)),
+            None,
+            Some((0, 2560)),
+            Some((0, 1920)),
+            None,
+        ];
+
+        assert_eq!(
+            GlowBerry::mirror_groups(&keys),
+            [vec![0, 3], vec![1], vec![2], vec![4]]
+        );
+    }
+
+    #[test]
+    fn output_refresh_rate_reads_the_current_mode() {
+        let mut modes = [mode((3840, 2160), false), mode((1920, 1080), true)];
+        modes[1].refresh_rate = 143_856;
+
+        assert_eq!(GlowBerry::output_refresh_rate(&modes), Some(143_856));
+        assert_eq!(GlowBerry::output_refresh_rate(&modes[..1]), None);
+    }
+
+    fn mode(dimensions: (i32, i32), current: bool) -> Mode {
+        Mode {
+            dimensions,
+            refresh_rate: 60_000,
+            current,
+            preferred: false,
+        }
+    }
+
+    #[test]
+    fn output_mode_dims_prefers_current_mode() {
+        let modes = [mode((3840, 2160), false), mode((1920, 1080), true)];
+
+        assert_eq!(GlowBerry::output_mode_dims(&modes), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn output_mode_dims_falls_back_to_largest_mode() {
+        let modes = [
+            mode((1280, 720), false),
+            mode((3840, 2160), false),
+            mode((2560, 1440), false),
+        ];
+
+        assert_eq!(GlowBerry::output_mode_dims(&modes), Some((3840, 2160)));
+        assert_eq!(GlowBerry::output_mode_dims(&[]), None);
+    }
+
+    fn shader(background_from_wallpaper: bool) -> ShaderSource {
+        ShaderSource {
+            shader: ShaderContent::Code(String::new()),
+            source_path: None,
+            params: Default::default(),
+            background_image: Some(PathBuf::from("/own.png")),
+            depth_image: None,
+            background_from_wallpaper,
+            background_scaling: Default::default(),
+            language: Default::default(),
+            frame_rate: 30,
+            time_scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn shader_follows_the_wallpaper_image() {
+        let wallpaper = Path::new("/wallpaper.png");
+
+        let following = GlowBerry::follow_wallpaper(shader(true), Some(wallpaper));
+        assert_eq!(following.background_image.as_deref(), Some(wallpaper));
+        // No static image shown: the shader keeps its own
+        let following = GlowBerry::follow_wallpaper(shader(true), None);
+        assert_eq!(
+            following.background_image.as_deref(),
+            Some(Path::new("/own.png"))
+        );
+        let own = GlowBerry::follow_wallpaper(shader(false), Some(wallpaper));
+        assert_eq!(own.background_image.as_deref(), Some(Path::new("/own.png")));
+    }
+
+    #[test]
+    fn wallpaper_rotation_reloads_following_shaders() {
+        let (following, own) = (shader(true), shader(false));
+        let shaders = || [Some(&following), None, Some(&own), Some(&following)].into_iter();
+
+        assert_eq!(
+            GlowBerry::wallpaper_followers(glowberry_config::DEFAULT_BACKGROUND, shaders()),
+            [0, 3]
+        );
+        // Only the default wallpaper is followed
+        assert!(GlowBerry::wallpaper_followers("DP-1", shaders()).is_empty());
+    }
 }
@@ -5,6 +5,8 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+use crate::image_formats;
+
 #[derive(Debug, Error)]
 pub enum CropError {
     #[error("failed to load image: {0}")]
@@ -60,7 +62,7 @@ pub fn composite_for_monitors(
     let mut image_cache: HashMap<PathBuf, DynamicImage> = HashMap::new();
     for layer in layers.iter() {
         if !image_cache.contains_key(&layer.source_path) {
-            let img = image::open(&layer.source_path)?;
+            let img = image_formats::open_upright(&layer.source_path)?;
             image_cache.insert(layer.source_path.clone(), img);
         }
     }
@@ -6,14 +6,57 @@
 //! - `iResolution` - screen dimensions
 //! - `iTime` - elapsed time for animation
 //! - Optional background texture sampling
+//! - Optional `iAudio` spectrum/waveform texture
+//! - Optional `iBattery` power state uniform
+//! - Optional `iWeather` local weather uniform
+//! - Optional `iSystem` CPU/memory/network uniform
+//! - Optional `iLocalTime` wall-clock date and time uniform
+//! - Optional `iParams` slots for automated `[PARAMS]` values
+//! - Optional `iClock` texture with the local time as text
+//! - Optional `iVideo` texture with the latest frame of a video wallpaper
+//! - Optional `iDepth` depth map, scaled like the background texture
+//! - Optional `iPointer` uniform following the pointer over the wallpaper
+//! - Optional dimming and blurring while windows are open, color tints, and
+//!   transitions from the previous source, see [`crate::post_process`]
+//!
+//! All uniforms share one buffer (see [`crate::uniforms`]) that is uploaded
+//! once per frame.
 
-use glowberry_config::{ShaderContent, ShaderLanguage, ShaderSource};
-use image::DynamicImage;
+use glowberry_config::{
+    ScalingMode, ShaderContent, ShaderLanguage, ShaderSource, Transition, WindowEffect,
+};
+use image::{DynamicImage, GenericImageView};
 use std::borrow::Cow;
-use std::time::{Duration, Instant};
+use std::path::Path;
+use std::time::Instant;
 
+use crate::audio::{AUDIO_BINS, AudioFrame};
+use crate::clock::ClockTexture;
+use crate::frame_pacing::{AdaptiveRate, FramePacer};
 use crate::gpu::GpuRenderer;
-use crate::shader_defs::{VERTEX_SHADER, WGSL_PREAMBLE, WGSL_PREAMBLE_WITH_TEXTURE};
+use crate::gpu_timer::GpuTimer;
+use crate::image_formats;
+use crate::param_automation::{self, AutomatedParam};
+use crate::post_process::PostProcess;
+use crate::scaler;
+use crate::shader_defs::{
+    VERTEX_SHADER, aligned_bytes_per_row, fragment_preamble, outputs_hdr, outputs_linear,
+    texture_upload_data, uses_audio, uses_battery, uses_clock, uses_depth, uses_local_time,
+    uses_pointer, uses_system_stats, uses_time, uses_video, uses_weather,
+};
+use crate::shader_include::{IncludeError, resolve_includes};
+use crate::sysstats::SystemStats;
+use crate::uniforms::{Uniform, UniformBlock};
+use crate::upower::PowerState;
+use crate::video::VideoTexture;
+use crate::wallpaper;
+use crate::weather::Weather;
+
+/// Size of the `iAudio` texture, counted against the [`TextureBudget`].
+const AUDIO_TEXTURE_BYTES: u64 = (AUDIO_BINS * 2 * std::mem::size_of::<f32>()) as u64;
+
+/// How quickly `iPointer` catches up with the pointer, per second.
+const POINTER_EASING: f32 = 6.0;
 
 /// Error when loading or compiling a shader.
 #[derive(Debug, thiserror::Error)]
@@ -26,37 +69,15 @@ pub enum ShaderError {
 
     #[error("Unsupported shader language: {0:?}")]
     UnsupportedLanguage(ShaderLanguage),
+
+    #[error("Failed to resolve shader include: {0}")]
+    Include(#[from] IncludeError),
 }
 
 pub fn detect_language(source: &ShaderSource) -> ShaderLanguage {
     source.language
 }
 
-fn texture_upload_data(rgba: &[u8], width: u32, height: u32) -> (Cow<'_, [u8]>, u32, u32) {
-    use crate::shader_defs::aligned_bytes_per_row;
-
-    let bytes_per_pixel = 4;
-    let unpadded_bytes_per_row = width.saturating_mul(bytes_per_pixel);
-    let bytes_per_row = aligned_bytes_per_row(width, bytes_per_pixel);
-
-    if bytes_per_row == unpadded_bytes_per_row {
-        return (Cow::Borrowed(rgba), bytes_per_row, height);
-    }
-
-    let mut padded = vec![0u8; (bytes_per_row * height) as usize];
-
-    for row in 0..height {
-        let src_offset = (row * unpadded_bytes_per_row) as usize;
-        let dst_offset = (row * bytes_per_row) as usize;
-        let src_end = src_offset + unpadded_bytes_per_row as usize;
-
-        padded[dst_offset..dst_offset + unpadded_bytes_per_row as usize]
-            .copy_from_slice(&rgba[src_offset..src_end]);
-    }
-
-    (Cow::Owned(padded), bytes_per_row, height)
-}
-
 fn build_shader_source(
     language: ShaderLanguage,
     preamble: &str,
@@ -71,33 +92,372 @@ fn build_shader_source(
     }
 }
 
+/// Pack a power state as `vec4f(level, on_battery, lid_closed, has_battery)`.
+fn battery_uniform(state: &PowerState) -> [f32; 4] {
+    let flag = |b: bool| if b { 1.0 } else { 0.0 };
+    let level = state
+        .battery_percentage
+        .map_or(1.0, |p| (p / 100.0).clamp(0.0, 1.0) as f32);
+
+    [
+        level,
+        flag(state.on_battery),
+        flag(state.lid_is_closed),
+        flag(state.battery_percentage.is_some()),
+    ]
+}
+
+/// Pack a local date and time as `vec4f(year, month, day, seconds)`.
+fn local_time_uniform(now: &jiff::Zoned) -> [f32; 4] {
+    let seconds = f32::from(now.hour()) * 3600.0
+        + f32::from(now.minute()) * 60.0
+        + f32::from(now.second())
+        + now.subsec_nanosecond() as f32 / 1e9;
+    [
+        f32::from(now.year()),
+        f32::from(now.month()),
+        f32::from(now.day()),
+        seconds,
+    ]
+}
+
+/// Move the `iPointer` value `current`, `[x, y, over]`, `dt` seconds
+/// towards the pointer at `target`, or away from the wallpaper without one.
+fn ease_pointer(current: [f32; 3], target: Option<[f32; 2]>, dt: f32) -> [f32; 3] {
+    let [x, y, over] = current;
+    let (target, target_over) = match target {
+        Some(position) => (position, 1.0),
+        None => ([x, y], 0.0),
+    };
+    let t = 1.0 - (-dt.max(0.0) * POINTER_EASING).exp();
+    [
+        x + (target[0] - x) * t,
+        y + (target[1] - y) * t,
+        over + (target_over - over) * t,
+    ]
+}
+
+/// Clamp a configured `iTime` speed factor to the supported range; invalid
+/// values run at normal speed.
+fn time_scale(configured: f32) -> f32 {
+    if configured.is_finite() {
+        configured.clamp(
+            glowberry_config::MIN_TIME_SCALE,
+            glowberry_config::MAX_TIME_SCALE,
+        )
+    } else {
+        1.0
+    }
+}
+
+/// Caps on the GPU memory a single shader canvas may allocate.
+///
+/// Oversized background images are downscaled at upload so that neither
+/// edge exceeds `max_dimension` and the canvas's textures fit in
+/// `max_bytes`; a 12000px PNG would otherwise claim hundreds of MiB of
+/// (often shared) iGPU memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureBudget {
+    /// Longest edge of an uploaded texture, in pixels.
+    pub max_dimension: u32,
+    /// Total bytes of texture memory per canvas.
+    pub max_bytes: u64,
+}
+
+impl Default for TextureBudget {
+    fn default() -> Self {
+        Self {
+            max_dimension: glowberry_config::DEFAULT_SHADER_MAX_TEXTURE_SIZE,
+            max_bytes: u64::from(glowberry_config::DEFAULT_SHADER_VRAM_BUDGET) * 1024 * 1024,
+        }
+    }
+}
+
+impl TextureBudget {
+    /// Size to upload a `width`x`height` RGBA texture at, keeping its aspect ratio.
+    pub fn fit(self, width: u32, height: u32) -> (u32, u32) {
+        let longest = width.max(height).max(1);
+        let bytes = u64::from(width) * u64::from(height) * 4;
+        let scale = (f64::from(self.max_dimension) / f64::from(longest))
+            .min((self.max_bytes as f64 / bytes.max(1) as f64).sqrt());
+        if scale >= 1.0 {
+            return (width, height);
+        }
+
+        (
+            ((f64::from(width) * scale).floor() as u32).max(1),
+            ((f64::from(height) * scale).floor() as u32).max(1),
+        )
+    }
+
+    /// Budget of each of `textures` images sharing a canvas, once `reserved`
+    /// bytes are set aside for its other textures, with edges of at most
+    /// `max_dimension` as the device allows.
+    fn share(self, reserved: u64, textures: u64, max_dimension: u32) -> Self {
+        Self {
+            max_dimension: self.max_dimension.min(max_dimension),
+            max_bytes: self.max_bytes.saturating_sub(reserved) / textures.max(1),
+        }
+    }
+}
+
+/// Size of the background texture for a `source`-sized image shown on a
+/// `target`-sized surface with `mode`, never upscaling the image. Tiled and
+/// centered images keep their own size, so they need the surface's.
+fn background_size(mode: &ScalingMode, source: (u32, u32), target: (u32, u32)) -> (u32, u32) {
+    let ratio_x = f64::from(target.0) / f64::from(source.0.max(1));
+    let ratio_y = f64::from(target.1) / f64::from(source.1.max(1));
+    let ratio = match mode {
+        ScalingMode::Tile | ScalingMode::Center(_) => return target,
+        ScalingMode::Fit(_) => ratio_x.min(ratio_y),
+        ScalingMode::Zoom | ScalingMode::Stretch | ScalingMode::Span => ratio_x.max(ratio_y),
+    };
+    if ratio <= 1.0 {
+        return target;
+    }
+
+    (
+        ((f64::from(target.0) / ratio).round() as u32).max(1),
+        ((f64::from(target.1) / ratio).round() as u32).max(1),
+    )
+}
+
+/// Whether the shader of `source` declares `// output: hdr`, checked before
+/// its canvas exists so the surface can be configured for it.
+pub fn source_outputs_hdr(source: &ShaderSource) -> bool {
+    match &source.shader {
+        ShaderContent::Path(path) => {
+            std::fs::read_to_string(path).is_ok_and(|code| outputs_hdr(&code))
+        }
+        ShaderContent::Code(code) => outputs_hdr(code),
+    }
+}
+
+/// Format a canvas renders in so that its output reaches a surface of
+/// `format` correctly encoded, and whether it has to render offscreen and
+/// copy into the surface because the surface can't be viewed in that format.
+///
+/// sRGB-encoded output goes through a non-sRGB view and is stored as-is;
+/// linear output goes through an sRGB view, which encodes it. A copy between
+/// the sRGB and non-sRGB variants of a format moves the bytes unchanged.
+fn output_format(
+    format: wgpu::TextureFormat,
+    view_formats: &[wgpu::TextureFormat],
+    usage: wgpu::TextureUsages,
+    linear: bool,
+) -> (wgpu::TextureFormat, bool) {
+    let wanted = if linear {
+        format.add_srgb_suffix()
+    } else {
+        format.remove_srgb_suffix()
+    };
+
+    if wanted == format || view_formats.contains(&wanted) {
+        (wanted, false)
+    } else if usage.contains(wgpu::TextureUsages::COPY_DST) {
+        (wanted, true)
+    } else {
+        tracing::warn!(
+            ?format,
+            linear,
+            "Surface supports neither a matching view nor copies; colors may be off"
+        );
+        (format, false)
+    }
+}
+
+/// Background image sampled through `iTexture`.
+///
+/// The decoded image is kept so that the texture can be rescaled with the
+/// wallpaper's fit mode whenever the surface size changes; sampling the raw
+/// image would stretch it on outputs with a different aspect ratio.
+struct Background {
+    image: DynamicImage,
+    scaling: ScalingMode,
+    budget: TextureBudget,
+    format: wgpu::TextureFormat,
+    texture: wgpu::Texture,
+    sampler: wgpu::Sampler,
+}
+
+impl Background {
+    fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        scaling: &ScalingMode,
+        budget: TextureBudget,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, ShaderError> {
+        let image = Self::decode(path, budget)?;
+        Ok(Self::new(device, queue, image, scaling, budget, format))
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: DynamicImage,
+        scaling: &ScalingMode,
+        budget: TextureBudget,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        // Until the surface size is known, upload the image as-is
+        let texture = FragmentCanvas::create_texture(device, queue, &image, format);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            image,
+            scaling: scaling.clone(),
+            budget,
+            format,
+            texture,
+            sampler,
+        }
+    }
+
+    /// Decode the image at `path`, downscaled to fit `budget`.
+    fn decode(path: &Path, budget: TextureBudget) -> Result<DynamicImage, ShaderError> {
+        let img = image_formats::open_upright(path)?;
+        let (width, height) = budget.fit(img.width(), img.height());
+        if (width, height) == (img.width(), img.height()) {
+            return Ok(img);
+        }
+
+        tracing::info!(
+            path = %path.display(),
+            from = ?(img.width(), img.height()),
+            to = ?(width, height),
+            "Downscaling shader background image to fit the texture budget"
+        );
+        Ok(scaler::resize(&img, width, height))
+    }
+
+    /// Replace the image with the one at `path`, scaled for a
+    /// `width`x`height` surface.
+    fn reload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        width: u32,
+        height: u32,
+    ) -> Result<(), ShaderError> {
+        self.image = Self::decode(path, self.budget)?;
+        let (width, height) = self.size_for(width, height);
+        self.upload(device, queue, width, height);
+        Ok(())
+    }
+
+    /// Rescale the texture for a `width`x`height` surface.
+    /// Returns whether the texture was replaced.
+    fn fit(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) -> bool {
+        let (width, height) = self.size_for(width, height);
+        if (width, height) == (self.texture.width(), self.texture.height()) {
+            return false;
+        }
+
+        self.upload(device, queue, width, height);
+        true
+    }
+
+    /// Texture size for a `width`x`height` surface.
+    fn size_for(&self, width: u32, height: u32) -> (u32, u32) {
+        let target = self.budget.fit(width, height);
+        background_size(&self.scaling, self.image.dimensions(), target)
+    }
+
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        let scaled = wallpaper::scale(&self.scaling, &self.image, width, height);
+        self.texture = FragmentCanvas::create_texture(device, queue, &scaled, self.format);
+    }
+}
+
 /// A GPU-rendered fragment shader canvas for live wallpapers.
 pub struct FragmentCanvas {
     // GPU resources
     pipeline: wgpu::RenderPipeline,
     bind_group: wgpu::BindGroup,
 
-    // Uniform buffers
-    resolution_buffer: wgpu::Buffer,
-    time_buffer: wgpu::Buffer,
+    // Format the pipeline renders in, and the offscreen texture used when
+    // the surface can't be viewed in that format
+    output_format: wgpu::TextureFormat,
+    copy_to_target: bool,
+    offscreen: Option<wgpu::Texture>,
+
+    // All uniforms, uploaded in a single write per frame
+    uniforms: UniformBlock,
+    /// Current `iResolution`, restored after offscreen snapshots.
+    resolution: [f32; 2],
 
     // Animation state
     start_time: Instant,
-    last_frame: Instant,
-    frame_interval: Duration,
+    /// Speed factor applied to `iTime`.
+    time_scale: f32,
+    pacer: FramePacer,
     /// The configured (original) frame rate from the shader source.
     configured_frame_rate: u8,
+    /// Temporary frame rate, e.g. while on battery.
+    frame_rate_override: Option<u8>,
+    /// Whether frames change over time. Other shaders, like the built-in one
+    /// of still images, only render again once something they show changed.
+    animated: bool,
+    /// Whether something changed since the last frame rendered.
+    changed: bool,
+
+    // GPU frame timing, when supported, and the frame rate cap it drives
+    gpu_timer: Option<GpuTimer>,
+    adaptive: AdaptiveRate,
+    /// Whether the last encoded frame is timed, pending [`Self::submitted`].
+    timing_frame: bool,
+
+    // Needed to rebuild the bind group when the background is refitted
+    bind_group_layout: wgpu::BindGroupLayout,
+
+    // Optional background texture, scaled to the surface
+    background: Option<Background>,
+
+    // Audio texture, present only if the shader references `iAudio`
+    audio_texture: Option<wgpu::Texture>,
 
-    // Optional background texture
-    _background_texture: Option<wgpu::Texture>,
+    // Time-of-day text, present only if the shader references `iClock`
+    clock: Option<ClockTexture>,
+
+    // Latest video frame, present only if the shader references `iVideo`
+    video: Option<VideoTexture>,
+
+    // Depth map, present only if the shader references `iDepth`
+    depth: Option<Background>,
+
+    // Pointer over the wallpaper, and the eased `[x, y, over]` in `iPointer`
+    pointer_target: Option<[f32; 2]>,
+    pointer: [f32; 3],
+    /// `iTime` of the last frame, before scaling, to ease `iPointer` by.
+    last_elapsed: f32,
+
+    // Uniforms bound for this shader; optional ones only if referenced
+    used: Vec<Uniform>,
+
+    // Automated `[PARAMS]` entries, animated through `iParams`
+    automated_params: Vec<AutomatedParam>,
+
+    // Post-process pass, present only with a window effect or a tint
+    post_process: Option<PostProcess>,
 }
 
 impl FragmentCanvas {
-    /// Create a new fragment canvas from a shader source.
+    /// Create a new fragment canvas from a shader source, rendering to
+    /// surfaces configured with `surface`.
+    ///
+    /// Textures are downscaled as needed to stay within `budget`.
     pub fn new(
         renderer: &GpuRenderer,
         source: &ShaderSource,
-        format: wgpu::TextureFormat,
+        surface: &wgpu::SurfaceConfiguration,
+        budget: TextureBudget,
     ) -> Result<Self, ShaderError> {
         let device = renderer.device();
         let queue = renderer.queue();
@@ -108,157 +468,255 @@ impl FragmentCanvas {
             ShaderContent::Code(code) => code.clone(),
         };
 
+        // Inlined (customized) code resolves includes next to its original file
+        let base_dir = match &source.shader {
+            ShaderContent::Path(path) => path.parent(),
+            ShaderContent::Code(_) => source.source_path.as_deref().and_then(Path::parent),
+        };
+        let shader_code = resolve_includes(&shader_code, base_dir)?;
+
+        // Automated parameters read from `iParams` instead of their constants
+        let automated_params = param_automation::parse(&shader_code);
+        let shader_code = param_automation::apply(&shader_code, &automated_params);
+
         let language = detect_language(source);
 
-        // Load optional background texture
-        let (background_texture, has_texture) = if let Some(img_path) = &source.background_image {
-            let img = image::open(img_path)?;
-            let texture = Self::create_texture(device, queue, &img);
-            (Some(texture), true)
+        // Color space of the shader's output, see `outputs_linear`; HDR
+        // output is linear too, and stored as-is on half-float surfaces
+        let linear = outputs_linear(&shader_code) || outputs_hdr(&shader_code);
+        let (output_format, copy_to_target) =
+            output_format(surface.format, &surface.view_formats, surface.usage, linear);
+        let texture_format = if linear {
+            wgpu::TextureFormat::Rgba8UnormSrgb
         } else {
-            (None, false)
+            wgpu::TextureFormat::Rgba8Unorm
         };
 
-        // Create uniform buffers
-        let resolution_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("glowberry: iResolution buffer"),
-            size: std::mem::size_of::<[f32; 2]>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        // Optional audio spectrum/waveform texture
+        let audio_texture = uses_audio(&shader_code).then(|| Self::create_audio_texture(device));
+        let reserved = if audio_texture.is_some() {
+            AUDIO_TEXTURE_BYTES
+        } else {
+            0
+        };
 
-        let time_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("glowberry: iTime buffer"),
-            size: std::mem::size_of::<f32>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        // The background and depth map split what the audio leaves of the
+        // budget
+        let has_depth = uses_depth(&shader_code);
+        let images = u64::from(source.background_image.is_some()) + u64::from(has_depth);
+        let image_budget = budget.share(reserved, images, device.limits().max_texture_dimension_2d);
 
-        // Create bind group layout
-        let bind_group_layout = if has_texture {
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("glowberry: bind group layout (with texture)"),
-                entries: &[
-                    // iResolution
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    // iTime
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    // iTexture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // iTextureSampler
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
+        // Load optional background texture, downscaled to fit the budget
+        let background = source
+            .background_image
+            .as_deref()
+            .map(|path| {
+                Background::load(
+                    device,
+                    queue,
+                    path,
+                    &source.background_scaling,
+                    image_budget,
+                    texture_format,
+                )
             })
-        } else {
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("glowberry: bind group layout"),
-                entries: &[
-                    // iResolution
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    // iTime
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
+            .transpose()?;
+        let has_texture = background.is_some();
+
+        // Optional depth map, stored as-is since it holds no colors; flat
+        // without a depth image
+        let depth = has_depth
+            .then(|| {
+                let image = match source.depth_image.as_deref() {
+                    Some(path) => Background::decode(path, image_budget)?,
+                    None => DynamicImage::ImageLuma8(image::GrayImage::from_pixel(
+                        1,
+                        1,
+                        image::Luma([128]),
+                    )),
+                };
+                Ok::<_, ShaderError>(Background::new(
+                    device,
+                    queue,
+                    image,
+                    &source.background_scaling,
+                    image_budget,
+                    wgpu::TextureFormat::Rgba8Unorm,
+                ))
             })
+            .transpose()?;
+
+        // Optional time-of-day text, drawn on the first frame
+        let clock = uses_clock(&shader_code).then(|| ClockTexture::new(device));
+
+        // Optional video frame, black until the first one is decoded
+        let video = uses_video(&shader_code).then(|| VideoTexture::new(device, queue));
+
+        // Optional uniforms share one buffer with iResolution and iTime
+        let mut used = vec![Uniform::Resolution, Uniform::Time];
+        let mut uniforms = UniformBlock::new(device);
+
+        // Power state reports "no battery" until updated
+        if uses_battery(&shader_code) {
+            used.push(Uniform::Battery);
+            uniforms.set(Uniform::Battery, &battery_uniform(&PowerState::default()));
+        }
+
+        // Weather reports the defaults until updated
+        if uses_weather(&shader_code) {
+            used.push(Uniform::Weather);
+            uniforms.set(Uniform::Weather, &Weather::default().uniform());
+        }
+
+        // System stats are idle until the sampler reports
+        if uses_system_stats(&shader_code) {
+            used.push(Uniform::System);
+            uniforms.set(Uniform::System, &SystemStats::default().uniform());
+        }
+
+        // Local time and automated parameters are refreshed on every render
+        if uses_local_time(&shader_code) {
+            used.push(Uniform::LocalTime);
+        }
+        if !automated_params.is_empty() {
+            used.push(Uniform::Params);
+        }
+
+        // The pointer starts centered, away from the wallpaper
+        if uses_pointer(&shader_code) {
+            used.push(Uniform::Pointer);
+            uniforms.set(Uniform::Pointer, &[0.5, 0.5, 0.0, 0.0]);
+        }
+
+        // Create bind group layout
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
         };
 
-        // Create bind group
-        let bind_group = if has_texture {
-            let texture = background_texture.as_ref().unwrap();
-            let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Linear,
-                ..Default::default()
+        let mut layout_entries: Vec<_> = used.iter().map(|u| uniform_entry(u.binding())).collect();
+
+        if has_texture {
+            // iTexture
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            // iTextureSampler
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
             });
+        }
 
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("glowberry: bind group (with texture)"),
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: resolution_buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: time_buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::TextureView(&texture_view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
-                    },
-                ],
-            })
-        } else {
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("glowberry: bind group"),
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: resolution_buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: time_buffer.as_entire_binding(),
-                    },
-                ],
-            })
-        };
+        if audio_texture.is_some() {
+            // iAudio
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
+
+        if clock.is_some() {
+            // iClock
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            // iClockSampler
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+
+        if video.is_some() {
+            // iVideo
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 12,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            // iVideoSampler
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 13,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+
+        if depth.is_some() {
+            // iDepth
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 14,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            // iDepthSampler
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 15,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("glowberry: bind group layout"),
+            entries: &layout_entries,
+        });
+
+        // Create bind group
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &used,
+            &uniforms,
+            background.as_ref(),
+            audio_texture.as_ref(),
+            clock.as_ref(),
+            video.as_ref(),
+            depth.as_ref(),
+        );
 
         // Create pipeline layout
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -274,13 +732,14 @@ impl FragmentCanvas {
         });
 
         // Create fragment shader module with preamble
-        let preamble = if has_texture {
-            WGSL_PREAMBLE_WITH_TEXTURE
-        } else {
-            WGSL_PREAMBLE
-        };
+        let preamble = fragment_preamble(
+            &shader_code,
+            has_texture,
+            audio_texture.is_some(),
+            !automated_params.is_empty(),
+        );
 
-        let full_shader = build_shader_source(language, preamble, &shader_code)?;
+        let full_shader = build_shader_source(language, &preamble, &shader_code)?;
 
         let fragment_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("glowberry: fragment shader"),
@@ -302,7 +761,7 @@ impl FragmentCanvas {
                 entry_point: Some("main"),
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format,
+                    format: output_format,
                     blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -319,26 +778,168 @@ impl FragmentCanvas {
 
         // Calculate frame interval
         let configured_frame_rate = source.frame_rate.clamp(1, 60);
-        let frame_interval = Duration::from_secs_f64(1.0 / f64::from(configured_frame_rate));
+
+        // Shaders reading the time or any input updated as it changes
+        let animated = uses_time(&shader_code)
+            || audio_texture.is_some()
+            || clock.is_some()
+            || video.is_some()
+            || used
+                .iter()
+                .any(|uniform| !matches!(uniform, Uniform::Resolution | Uniform::Time));
 
         Ok(Self {
             pipeline,
             bind_group,
-            resolution_buffer,
-            time_buffer,
+            output_format,
+            copy_to_target,
+            offscreen: None,
+            uniforms,
+            resolution: [0.0; 2],
             start_time: Instant::now(),
-            last_frame: Instant::now(),
-            frame_interval,
+            time_scale: time_scale(source.time_scale),
+            pacer: FramePacer::new(configured_frame_rate),
+            frame_rate_override: None,
+            animated,
+            changed: true,
+            gpu_timer: GpuTimer::new(device, queue),
+            timing_frame: false,
+            adaptive: AdaptiveRate::new(glowberry_config::DEFAULT_SHADER_MIN_FRAME_RATE),
             configured_frame_rate,
-            _background_texture: background_texture,
+            bind_group_layout,
+            background,
+            audio_texture,
+            clock,
+            video,
+            depth,
+            pointer_target: None,
+            pointer: [0.5, 0.5, 0.0],
+            last_elapsed: 0.0,
+            used,
+            automated_params,
+            post_process: None,
+        })
+    }
+
+    /// Bind the textures again after one of them was replaced.
+    fn rebind(&mut self, device: &wgpu::Device) {
+        self.changed = true;
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.used,
+            &self.uniforms,
+            self.background.as_ref(),
+            self.audio_texture.as_ref(),
+            self.clock.as_ref(),
+            self.video.as_ref(),
+            self.depth.as_ref(),
+        );
+    }
+
+    /// Bind the uniforms and textures the shader uses.
+    #[allow(clippy::too_many_arguments)]
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        used: &[Uniform],
+        uniforms: &UniformBlock,
+        background: Option<&Background>,
+        audio_texture: Option<&wgpu::Texture>,
+        clock: Option<&ClockTexture>,
+        video: Option<&VideoTexture>,
+        depth: Option<&Background>,
+    ) -> wgpu::BindGroup {
+        let background_view = background.map(|b| {
+            b.texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let audio_view =
+            audio_texture.map(|t| t.create_view(&wgpu::TextureViewDescriptor::default()));
+        let clock_view = clock.map(|c| {
+            c.texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let video_view = video.map(|v| {
+            v.texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let depth_view = depth.map(|d| {
+            d.texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+
+        let mut entries: Vec<_> = used
+            .iter()
+            .map(|&u| wgpu::BindGroupEntry {
+                binding: u.binding(),
+                resource: uniforms.binding(u),
+            })
+            .collect();
+
+        if let (Some(view), Some(background)) = (&background_view, background) {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&background.sampler),
+            });
+        }
+
+        if let Some(view) = &audio_view {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+        }
+
+        if let (Some(view), Some(clock)) = (&clock_view, clock) {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 10,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 11,
+                resource: wgpu::BindingResource::Sampler(&clock.sampler),
+            });
+        }
+
+        if let (Some(view), Some(video)) = (&video_view, video) {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 12,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::Sampler(&video.sampler),
+            });
+        }
+
+        if let (Some(view), Some(depth)) = (&depth_view, depth) {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 14,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 15,
+                resource: wgpu::BindingResource::Sampler(&depth.sampler),
+            });
+        }
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("glowberry: bind group"),
+            layout,
+            entries: &entries,
         })
     }
 
-    /// Create a GPU texture from an image.
     fn create_texture(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         image: &DynamicImage,
+        format: wgpu::TextureFormat,
     ) -> wgpu::Texture {
         let rgba = image.to_rgba8();
         let dimensions = rgba.dimensions();
@@ -355,7 +956,7 @@ impl FragmentCanvas {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
@@ -382,20 +983,301 @@ impl FragmentCanvas {
         texture
     }
 
-    /// Update the resolution uniform.
-    pub fn update_resolution(&self, queue: &wgpu::Queue, width: u32, height: u32) {
-        let data = [width as f32, height as f32];
-        queue.write_buffer(&self.resolution_buffer, 0, bytemuck::cast_slice(&data));
+    /// Create the `AUDIO_BINS`x2 audio texture, initially silent.
+    fn create_audio_texture(device: &wgpu::Device) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glowberry: iAudio texture"),
+            size: wgpu::Extent3d {
+                width: AUDIO_BINS as u32,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    /// Whether this shader samples the `iAudio` texture.
+    pub fn uses_audio(&self) -> bool {
+        self.audio_texture.is_some()
     }
 
-    /// Check if enough time has passed for the next frame.
-    pub fn should_render(&self) -> bool {
-        self.last_frame.elapsed() >= self.frame_interval
+    /// Whether the shader samples `iVideo`, i.e. shows a video wallpaper.
+    pub fn uses_video(&self) -> bool {
+        self.video.is_some()
+    }
+
+    /// Sample `shared`, the texture of a video's latest frame, through
+    /// `iVideo`, binding it again only when the video replaced it.
+    pub fn update_video(&mut self, device: &wgpu::Device, shared: &VideoTexture) {
+        let Some(video) = &mut self.video else {
+            return;
+        };
+        if video.id != shared.id {
+            *video = shared.clone();
+            self.rebind(device);
+        }
+    }
+
+    /// Upload the latest audio frame to the `iAudio` texture.
+    pub fn update_audio(&self, queue: &wgpu::Queue, frame: &AudioFrame) {
+        let Some(texture) = &self.audio_texture else {
+            return;
+        };
+
+        let mut data = Vec::with_capacity(AUDIO_BINS * 2);
+        data.extend_from_slice(&frame.spectrum);
+        data.extend_from_slice(&frame.waveform);
+
+        // AUDIO_BINS * 4 bytes is already a multiple of COPY_BYTES_PER_ROW_ALIGNMENT
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&data),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some((AUDIO_BINS * std::mem::size_of::<f32>()) as u32),
+                rows_per_image: Some(2),
+            },
+            texture.size(),
+        );
+    }
+
+    /// Whether this shader reads the `iBattery` uniform.
+    pub fn uses_battery(&self) -> bool {
+        self.used.contains(&Uniform::Battery)
+    }
+
+    /// Stage the current power state for the `iBattery` uniform.
+    pub fn update_battery(&mut self, state: &PowerState) {
+        if self.uses_battery() {
+            self.uniforms.set(Uniform::Battery, &battery_uniform(state));
+        }
+    }
+
+    /// Whether this shader reads the `iWeather` uniform.
+    pub fn uses_weather(&self) -> bool {
+        self.used.contains(&Uniform::Weather)
+    }
+
+    /// Stage the current weather for the `iWeather` uniform.
+    pub fn update_weather(&mut self, weather: &Weather) {
+        if self.uses_weather() {
+            self.uniforms.set(Uniform::Weather, &weather.uniform());
+        }
+    }
+
+    /// Whether this shader reads the `iSystem` uniform.
+    pub fn uses_system_stats(&self) -> bool {
+        self.used.contains(&Uniform::System)
+    }
+
+    /// Stage the latest system statistics for the `iSystem` uniform.
+    pub fn update_system_stats(&mut self, stats: &SystemStats) {
+        if self.uses_system_stats() {
+            self.uniforms.set(Uniform::System, &stats.uniform());
+        }
+    }
+
+    /// Update the resolution uniform and refit the background image to the
+    /// new size. Does nothing if the size is the one last set, as on
+    /// configures and scale events that don't resize the surface.
+    pub fn update_resolution(&mut self, renderer: &GpuRenderer, width: u32, height: u32) {
+        let resolution = [width as f32, height as f32];
+        if resolution == self.resolution {
+            return;
+        }
+        self.resolution = resolution;
+        self.uniforms.set(Uniform::Resolution, &self.resolution);
+        self.changed = true;
+
+        let mut refitted = false;
+        for background in [&mut self.background, &mut self.depth]
+            .into_iter()
+            .flatten()
+        {
+            refitted |= background.fit(renderer.device(), renderer.queue(), width, height);
+        }
+        if refitted {
+            self.rebind(renderer.device());
+        }
+    }
+
+    /// Replace the background image with the one at `path`, keeping the
+    /// pipeline. Does nothing if the shader has no background image.
+    pub fn reload_background(
+        &mut self,
+        renderer: &GpuRenderer,
+        path: &Path,
+    ) -> Result<(), ShaderError> {
+        let Some(background) = &mut self.background else {
+            return Ok(());
+        };
+
+        let [width, height] = self.resolution;
+        background.reload(
+            renderer.device(),
+            renderer.queue(),
+            path,
+            width as u32,
+            height as u32,
+        )?;
+        self.rebind(renderer.device());
+        Ok(())
+    }
+
+    /// Whether this shader reads the `iPointer` uniform.
+    pub fn uses_pointer(&self) -> bool {
+        self.used.contains(&Uniform::Pointer)
+    }
+
+    /// Follow the pointer at `position`, from 0.0 to 1.0 across the surface,
+    /// or `None` once it left the wallpaper. `iPointer` eases towards it over
+    /// the next frames.
+    pub fn set_pointer(&mut self, position: Option<[f32; 2]>) {
+        self.pointer_target = position;
+    }
+
+    /// Dim and blur frames as `config` asks while windows are open, starting
+    /// with the effect as it is for `windows`, or stop with `None`.
+    pub fn set_window_effect(
+        &mut self,
+        device: &wgpu::Device,
+        config: Option<&WindowEffect>,
+        windows: bool,
+    ) {
+        if config.is_none() && self.post_process.is_none() {
+            return;
+        }
+        self.post_process(device).set_window_effect(config, windows);
+        self.changed = true;
+    }
+
+    /// Whether frames are dimmed and blurred while windows are open.
+    pub fn has_window_effect(&self) -> bool {
+        self.post_process
+            .as_ref()
+            .is_some_and(PostProcess::has_window_effect)
+    }
+
+    /// Fade the window effect in or out as windows are shown on the output
+    /// or all of them are gone.
+    pub fn set_windows(&mut self, windows: bool) {
+        if let Some(post_process) = &mut self.post_process {
+            post_process.set_windows(windows);
+            self.changed = true;
+        }
+    }
+
+    /// Multiply the color channels of frames by `tint`.
+    pub fn set_tint(&mut self, device: &wgpu::Device, tint: [f32; 3]) {
+        if tint == [1.0; 3] && self.post_process.is_none() {
+            return;
+        }
+        self.post_process(device).set_tint(tint);
+        self.changed = true;
+    }
+
+    /// Render the current frame into a new texture, for the canvas that
+    /// replaces this one to transition from.
+    pub fn capture(&mut self, renderer: &GpuRenderer) -> wgpu::Texture {
+        let [width, height] = self.resolution;
+        let texture = renderer.device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("glowberry: transition texture"),
+            size: wgpu::Extent3d {
+                width: (width as u32).max(1),
+                height: (height as u32).max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.output_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        self.render(renderer, &texture);
+        texture
+    }
+
+    /// Transition from `from`, captured from the canvas this one replaces,
+    /// as `config` asks.
+    pub fn start_transition(
+        &mut self,
+        device: &wgpu::Device,
+        from: wgpu::Texture,
+        config: &Transition,
+    ) {
+        // Frames of another format hold colors encoded differently
+        if from.format() != self.output_format {
+            return;
+        }
+        self.post_process(device).start_transition(from, config);
+        self.changed = true;
+    }
+
+    fn post_process(&mut self, device: &wgpu::Device) -> &mut PostProcess {
+        let format = self.output_format;
+        self.post_process
+            .get_or_insert_with(|| PostProcess::new(device, format))
+    }
+
+    /// Called on each frame callback; returns whether to render a frame.
+    /// Every callback renders one while the window effect fades or a
+    /// transition runs, and shaders that don't animate only render once
+    /// something changed.
+    pub fn should_render(&mut self) -> bool {
+        let paced = if self.animated {
+            self.pacer.frame()
+        } else {
+            self.changed
+        };
+        paced || self.is_fading()
+    }
+
+    /// Whether the layer needs frame callbacks to render the next frames.
+    /// Without, it shows the last frame until something changes.
+    pub fn needs_frames(&self) -> bool {
+        self.animated || self.changed || self.is_fading()
+    }
+
+    /// Whether the window effect fades or a transition runs.
+    fn is_fading(&self) -> bool {
+        self.post_process
+            .as_ref()
+            .is_some_and(PostProcess::is_fading)
     }
 
     /// Mark that a frame was rendered.
     pub fn mark_frame_rendered(&mut self) {
-        self.last_frame = Instant::now();
+        self.pacer.rendered();
+        self.changed = false;
+    }
+
+    /// Pace frames to the refresh rate of the output's current mode, in mHz,
+    /// until presentation feedback reports the actual one.
+    pub fn set_output_refresh(&mut self, millihertz: Option<u32>) {
+        self.pacer.set_output_refresh(millihertz);
+        if let Some(fps) = self.pacer.effective_frame_rate() {
+            tracing::debug!(
+                configured = self.configured_frame_rate,
+                effective = fps,
+                "Snapped shader frame rate to the output refresh"
+            );
+        }
+    }
+
+    /// Record presentation feedback for the output this canvas is shown on.
+    pub fn on_presented(&mut self, refresh_ns: u32) {
+        self.pacer.presented(refresh_ns);
     }
 
     /// Get the configured (original) frame rate.
@@ -406,32 +1288,135 @@ impl FragmentCanvas {
     /// Set a temporary frame rate override.
     /// Pass `None` to restore the configured frame rate.
     pub fn set_frame_rate_override(&mut self, frame_rate: Option<u8>) {
-        let effective_rate = frame_rate
+        self.frame_rate_override = frame_rate;
+        self.pacer.set_frame_rate(self.frame_rate());
+    }
+
+    /// Set the lowest frame rate a slow shader is stepped down to.
+    pub fn set_min_frame_rate(&mut self, frame_rate: u8) {
+        self.adaptive.set_floor(frame_rate);
+        self.pacer.set_frame_rate(self.frame_rate());
+    }
+
+    /// Frame rate to render at: the configured or overridden rate, capped
+    /// while the shader is too slow for the GPU.
+    fn frame_rate(&self) -> u8 {
+        self.frame_rate_override
             .unwrap_or(self.configured_frame_rate)
-            .clamp(1, 60);
-        self.frame_interval = Duration::from_secs_f64(1.0 / f64::from(effective_rate));
+            .clamp(1, 60)
+            .min(self.adaptive.cap())
+    }
+
+    /// Feed the GPU time of the last timed frame to the adaptive frame rate.
+    fn update_adaptive_rate(&mut self, device: &wgpu::Device) {
+        let Some(gpu_time) = self.gpu_timer.as_mut().and_then(|timer| timer.read(device)) else {
+            return;
+        };
+
+        if self.adaptive.sample(gpu_time, self.frame_rate()) {
+            tracing::debug!(
+                ?gpu_time,
+                cap = self.adaptive.cap(),
+                "Adjusted shader frame rate to its GPU cost"
+            );
+            self.pacer.set_frame_rate(self.frame_rate());
+        }
     }
 
     /// Render the shader to a texture view.
-    pub fn render(&self, renderer: &GpuRenderer, view: &wgpu::TextureView) {
+    ///
+    /// `target` is either a surface texture or a texture in the canvas's own
+    /// output format.
+    pub fn render(&mut self, renderer: &GpuRenderer, target: &wgpu::Texture) {
+        let device = renderer.device();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("glowberry: render encoder"),
+        });
+        self.encode(renderer, &[target], &mut encoder);
+        renderer.queue().submit(std::iter::once(encoder.finish()));
+        self.submitted();
+    }
+
+    /// Record a frame for `targets` into `encoder`, so several canvases can be
+    /// rendered with one queue submission. Call [`Self::submitted`] once
+    /// `encoder` is submitted.
+    ///
+    /// `targets` must share one size and format. With more than one, the
+    /// shader runs once offscreen and the frame is copied into each of them,
+    /// which needs them to allow copies.
+    pub fn encode(
+        &mut self,
+        renderer: &GpuRenderer,
+        targets: &[&wgpu::Texture],
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let Some(&target) = targets.first() else {
+            return;
+        };
         let device = renderer.device();
         let queue = renderer.queue();
 
+        self.update_adaptive_rate(device);
+
         // Update time uniform
         let elapsed = self.start_time.elapsed().as_secs_f32();
-        queue.write_buffer(&self.time_buffer, 0, bytemuck::bytes_of(&elapsed));
+        self.uniforms
+            .set(Uniform::Time, &[elapsed * self.time_scale]);
 
-        // Create command encoder
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("glowberry: render encoder"),
+        // Update wall-clock time and automated parameter uniforms
+        if self.used.contains(&Uniform::LocalTime) || !self.automated_params.is_empty() {
+            let local_time = local_time_uniform(&jiff::Zoned::now());
+            self.uniforms.set(Uniform::LocalTime, &local_time);
+            let values = param_automation::values(&self.automated_params, elapsed, local_time[3]);
+            self.uniforms.set(Uniform::Params, &values);
+        }
+
+        // Ease the pointer by the time since the last frame
+        if self.uses_pointer() {
+            let dt = elapsed - self.last_elapsed;
+            self.pointer = ease_pointer(self.pointer, self.pointer_target, dt);
+            let [x, y, over] = self.pointer;
+            self.uniforms.set(Uniform::Pointer, &[x, y, over, 0.0]);
+        }
+        self.last_elapsed = elapsed;
+
+        // Redraw the clock text once the minute changes
+        if let Some(clock) = &mut self.clock {
+            clock.update(queue, &jiff::Zoned::now());
+        }
+
+        // Everything staged since the last frame goes up in one write
+        self.uniforms.upload(queue);
+
+        // Render offscreen when the target can't be viewed in the output
+        // format, or to copy the frame into several targets
+        let use_offscreen =
+            targets.len() > 1 || (self.copy_to_target && target.format() != self.output_format);
+        if use_offscreen {
+            self.prepare_offscreen(device, target.size());
+        }
+        let offscreen = self.offscreen.as_ref().filter(|_| use_offscreen);
+        let view = offscreen
+            .unwrap_or(target)
+            .create_view(&wgpu::TextureViewDescriptor {
+                format: Some(self.output_format),
+                ..Default::default()
+            });
+
+        // With a post-process pass, the shader renders into its scene
+        let scene = self.post_process.as_mut().and_then(|post_process| {
+            post_process.prepare(device, queue, self.output_format, target.size())
         });
 
+        // Time this frame unless a readback is still in flight
+        let timer = self.gpu_timer.as_mut().filter(|timer| timer.is_idle());
+
         // Begin render pass
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("glowberry: render pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view,
+                    view: scene.as_ref().unwrap_or(&view),
                     resolve_target: None,
                     depth_slice: None,
                     ops: wgpu::Operations {
@@ -440,7 +1425,7 @@ impl FragmentCanvas {
                     },
                 })],
                 depth_stencil_attachment: None,
-                timestamp_writes: None,
+                timestamp_writes: timer.as_ref().map(|timer| timer.timestamp_writes()),
                 occlusion_query_set: None,
                 multiview_mask: None,
             });
@@ -450,23 +1435,353 @@ impl FragmentCanvas {
             render_pass.draw(0..4, 0..1);
         }
 
+        if scene.is_some()
+            && let Some(post_process) = &self.post_process
+        {
+            post_process.encode(encoder, &view);
+        }
+
+        // Same bytes, reinterpreted between the sRGB and non-sRGB variants
+        if let Some(offscreen) = offscreen {
+            for target in targets {
+                encoder.copy_texture_to_texture(
+                    offscreen.as_image_copy(),
+                    target.as_image_copy(),
+                    target.size(),
+                );
+            }
+        }
+
+        if let Some(timer) = &timer {
+            timer.resolve(encoder);
+        }
+        self.timing_frame = timer.is_some();
+    }
+
+    /// Start reading back the frame's GPU time once its commands are submitted.
+    pub fn submitted(&mut self) {
+        if std::mem::take(&mut self.timing_frame)
+            && let Some(timer) = &mut self.gpu_timer
+        {
+            timer.submitted();
+        }
+    }
+
+    /// Make sure the offscreen render texture matches `size`.
+    fn prepare_offscreen(&mut self, device: &wgpu::Device, size: wgpu::Extent3d) {
+        if self.offscreen.as_ref().is_none_or(|t| t.size() != size) {
+            self.offscreen = Some(device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("glowberry: offscreen texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.output_format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }));
+        }
+    }
+
+    /// Render one frame offscreen at `size` and read it back as RGBA.
+    ///
+    /// The surface resolution is restored afterwards. Returns `None` for
+    /// output formats other than 8-bit RGBA/BGRA.
+    pub fn snapshot(
+        &mut self,
+        renderer: &GpuRenderer,
+        size: (u32, u32),
+    ) -> Option<image::RgbaImage> {
+        self.start_snapshot(renderer, size)?.wait()
+    }
+
+    /// Render one frame offscreen at `size` and start reading it back,
+    /// without waiting for the GPU. See [`Self::snapshot`].
+    pub fn start_snapshot(
+        &mut self,
+        renderer: &GpuRenderer,
+        (width, height): (u32, u32),
+    ) -> Option<PendingSnapshot> {
+        use wgpu::TextureFormat as F;
+        let format = self.output_format;
+        let swap_red_blue = match format {
+            F::Rgba8Unorm | F::Rgba8UnormSrgb => false,
+            F::Bgra8Unorm | F::Bgra8UnormSrgb => true,
+            _ => {
+                tracing::debug!(?format, "Unsupported surface format for snapshots");
+                return None;
+            }
+        };
+
+        let device = renderer.device();
+        let queue = renderer.queue();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glowberry: snapshot texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let bytes_per_row = aligned_bytes_per_row(width, 4);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("glowberry: snapshot buffer"),
+            size: u64::from(bytes_per_row) * u64::from(height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // The background stays fitted to the surface; snapshots keep its
+        // aspect, and show the wallpaper without post-processing
+        self.uniforms
+            .set(Uniform::Resolution, &[width as f32, height as f32]);
+        let post_process = self.post_process.take();
+        self.render(renderer, &texture);
+        self.post_process = post_process;
+        self.uniforms.set(Uniform::Resolution, &self.resolution);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("glowberry: snapshot encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
         queue.submit(std::iter::once(encoder.finish()));
+
+        let (tx, mapped) = std::sync::mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = tx.send(result);
+            });
+
+        Some(PendingSnapshot {
+            device: device.clone(),
+            buffer,
+            mapped,
+            width,
+            height,
+            bytes_per_row,
+            swap_red_blue,
+        })
+    }
+}
+
+/// A frame rendered offscreen by [`FragmentCanvas::start_snapshot`] whose
+/// readback may still be in flight.
+pub struct PendingSnapshot {
+    device: wgpu::Device,
+    buffer: wgpu::Buffer,
+    mapped: std::sync::mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+    swap_red_blue: bool,
+}
+
+impl PendingSnapshot {
+    /// Wait for the GPU to finish the frame and read it back as RGBA.
+    pub fn wait(self) -> Option<image::RgbaImage> {
+        let (width, height) = (self.width, self.height);
+        self.device.poll(wgpu::PollType::wait_indefinitely()).ok();
+        if let Err(err) = self.mapped.recv().ok()? {
+            tracing::warn!(?err, "Failed to map snapshot buffer");
+            return None;
+        }
+
+        let data = self.buffer.slice(..).get_mapped_range();
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in data
+            .chunks(self.bytes_per_row as usize)
+            .take(height as usize)
+        {
+            pixels.extend_from_slice(&row[..(width * 4) as usize]);
+        }
+        drop(data);
+        self.buffer.unmap();
+
+        if self.swap_red_blue {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::upower::PowerState;
+
     #[test]
-    fn pads_texture_upload_rows_when_needed() {
-        let width = 1;
-        let height = 2;
-        let rgba = vec![1u8; (width * height * 4) as usize];
+    fn packs_battery_uniform() {
+        let state = PowerState {
+            on_battery: true,
+            battery_percentage: Some(25.0),
+            lid_is_closed: false,
+        };
+        assert_eq!(super::battery_uniform(&state), [0.25, 1.0, 0.0, 1.0]);
+        assert_eq!(
+            super::battery_uniform(&PowerState::default()),
+            [1.0, 0.0, 0.0, 0.0]
+        );
+    }
 
-        let (upload_data, bytes_per_row, rows_per_image) =
-            super::texture_upload_data(&rgba, width, height);
+    #[test]
+    fn packs_local_time_uniform() {
+        let now = jiff::civil::date(2026, 10, 16)
+            .at(6, 30, 15, 500_000_000)
+            .to_zoned(jiff::tz::TimeZone::UTC)
+            .unwrap();
+        assert_eq!(
+            super::local_time_uniform(&now),
+            [2026.0, 10.0, 16.0, 23_415.5]
+        );
+    }
+
+    #[test]
+    fn clamps_time_scale() {
+        assert_eq!(super::time_scale(0.5), 0.5);
+        assert_eq!(super::time_scale(0.0), glowberry_config::MIN_TIME_SCALE);
+        assert_eq!(super::time_scale(10.0), glowberry_config::MAX_TIME_SCALE);
+        assert_eq!(super::time_scale(f32::NAN), 1.0);
+    }
+
+    #[test]
+    fn eases_the_pointer() {
+        let start = [0.5, 0.5, 0.0];
+        let [x, y, over] = super::ease_pointer(start, Some([1.0, 0.0]), 0.1);
+        assert!(x > 0.5 && x < 1.0);
+        assert!(y < 0.5 && y > 0.0);
+        assert!(over > 0.0 && over < 1.0);
+
+        // Settles on the pointer, and stays put once it leaves
+        let settled = super::ease_pointer(start, Some([1.0, 0.0]), 10.0);
+        assert!((settled[0] - 1.0).abs() < 1e-3 && (settled[2] - 1.0).abs() < 1e-3);
+        let left = super::ease_pointer(settled, None, 10.0);
+        assert_eq!(left[..2], settled[..2]);
+        assert!(left[2] < 1e-3);
+
+        assert_eq!(super::ease_pointer(start, Some([1.0, 0.0]), 0.0), start);
+    }
+
+    #[test]
+    fn fits_textures_to_the_budget() {
+        let budget = super::TextureBudget {
+            max_dimension: 4096,
+            max_bytes: 64 * 1024 * 1024,
+        };
 
-        assert_eq!(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
-        assert_eq!(rows_per_image, height);
-        assert_eq!(upload_data.len(), (bytes_per_row * height) as usize);
+        // Small images are uploaded as-is
+        assert_eq!(budget.fit(1920, 1080), (1920, 1080));
+
+        // Oversized edges are capped, keeping the aspect ratio
+        assert_eq!(budget.fit(12000, 6000), (4096, 2048));
+
+        // Memory is capped as well
+        let budget = super::TextureBudget {
+            max_bytes: 16 * 1024 * 1024,
+            ..budget
+        };
+        let (width, height) = budget.fit(4000, 4000);
+        assert!(u64::from(width) * u64::from(height) * 4 <= 16 * 1024 * 1024);
+        assert_eq!(width, height);
+    }
+
+    #[test]
+    fn background_and_depth_share_the_budget() {
+        let budget = super::TextureBudget {
+            max_dimension: 8192,
+            max_bytes: 64 * 1024 * 1024,
+        };
+        let reserved = super::AUDIO_TEXTURE_BYTES;
+        let each = budget.share(reserved, 2, 4096);
+        assert_eq!(each.max_dimension, 4096);
+
+        // A background and a depth map as large as the budget allows
+        let (width, height) = each.fit(8000, 6000);
+        let total = reserved + 2 * u64::from(width) * u64::from(height) * 4;
+        assert!(total <= budget.max_bytes);
+        // Either alone gets all the budget left
+        assert_eq!(budget.share(0, 1, 8192), budget);
+    }
+
+    #[test]
+    fn sizes_background_textures_for_the_fit_mode() {
+        use glowberry_config::ScalingMode;
+
+        // Large images are scaled to the surface
+        assert_eq!(
+            super::background_size(&ScalingMode::Zoom, (6000, 4000), (1920, 1080)),
+            (1920, 1080)
+        );
+
+        // Small images are not upscaled, but keep the surface aspect ratio
+        assert_eq!(
+            super::background_size(&ScalingMode::Zoom, (960, 960), (1920, 1080)),
+            (960, 540)
+        );
+        assert_eq!(
+            super::background_size(&ScalingMode::Fit([0.0; 3]), (960, 960), (1920, 1080)),
+            (1707, 960)
+        );
+        assert_eq!(
+            super::background_size(&ScalingMode::Fit([0.0; 3]), (480, 480), (1920, 1080)),
+            (853, 480)
+        );
+        assert_eq!(
+            super::background_size(&ScalingMode::Tile, (480, 480), (1920, 1080)),
+            (1920, 1080)
+        );
+    }
+
+    #[test]
+    fn picks_output_format_for_the_color_space() {
+        use wgpu::{TextureFormat as F, TextureUsages as U};
+
+        // A matching surface view renders directly
+        let views = [F::Bgra8Unorm];
+        assert_eq!(
+            super::output_format(F::Bgra8UnormSrgb, &views, U::RENDER_ATTACHMENT, false),
+            (F::Bgra8Unorm, false)
+        );
+        assert_eq!(
+            super::output_format(F::Bgra8UnormSrgb, &views, U::RENDER_ATTACHMENT, true),
+            (F::Bgra8UnormSrgb, false)
+        );
+
+        // Without one, render offscreen and copy
+        let usage = U::RENDER_ATTACHMENT | U::COPY_DST;
+        assert_eq!(
+            super::output_format(F::Bgra8Unorm, &[], usage, true),
+            (F::Bgra8UnormSrgb, true)
+        );
+        assert_eq!(
+            super::output_format(F::Bgra8Unorm, &[], U::RENDER_ATTACHMENT, true),
+            (F::Bgra8Unorm, false)
+        );
     }
 }
@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Shader frame pacing.
+//!
+//! Frame callbacks arrive once per display refresh while a surface animates.
+//! Once the output's refresh interval is known, a shader renders on every Nth
+//! callback, with N the smallest number of refreshes that doesn't exceed its
+//! frame rate, so 60 fps becomes 60, 30, 20, 15... fps on a 60 Hz output and
+//! 48 fps on a 144 Hz one. Frames then land on a steady vblank cadence instead
+//! of drifting against the display, the beat that makes e.g. 24 fps judder on
+//! a 60 Hz output.
+//!
+//! The refresh interval comes from the output's current mode until
+//! `wp_presentation` feedback reports the actual one. Variable-refresh outputs
+//! report a refresh of zero; they present frames whenever they arrive, so
+//! pacing falls back to wall-clock throttling at the configured rate, as it
+//! does when the refresh rate is unknown.
+//!
+//! Where the GPU supports timestamp queries, [`AdaptiveRate`] additionally
+//! caps the frame rate of shaders that take too long to render, down to a
+//! configured floor, so a heavy shader can't starve the rest of the desktop.
+
+use std::time::{Duration, Instant};
+
+/// Slack when comparing the frame interval to a multiple of the refresh
+/// interval, so that e.g. 60 fps still renders every refresh at 59.94 Hz.
+const DIVISOR_TOLERANCE: f64 = 0.05;
+
+/// Share of GPU time a shader may use before its frame rate is stepped down.
+const GPU_SHARE: f64 = 0.25;
+
+/// Timed frames averaged before the frame rate is stepped again.
+const SAMPLES_PER_STEP: u32 = 30;
+
+/// Weight of a new sample in the moving average of GPU frame times.
+const SAMPLE_WEIGHT: f64 = 0.1;
+
+/// Decides which frame callbacks a shader renders on.
+#[derive(Debug)]
+pub struct FramePacer {
+    /// Target time between frames.
+    interval: Duration,
+    /// Output refresh interval.
+    refresh: Option<Duration>,
+    /// Whether presentation feedback arrived, which overrides the output mode.
+    feedback: bool,
+    /// Frame callbacks since the last rendered frame.
+    vblanks: u32,
+    /// When the last frame was rendered, for the wall-clock fallback.
+    last_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(frame_rate: u8) -> Self {
+        Self {
+            interval: frame_interval(frame_rate),
+            refresh: None,
+            feedback: false,
+            vblanks: 0,
+            last_frame: Instant::now(),
+        }
+    }
+
+    /// Change the target frame rate.
+    pub fn set_frame_rate(&mut self, frame_rate: u8) {
+        self.interval = frame_interval(frame_rate);
+    }
+
+    /// Seed the refresh interval from the output's current mode, in mHz.
+    /// Ignored once presentation feedback has arrived.
+    pub fn set_output_refresh(&mut self, millihertz: Option<u32>) {
+        if !self.feedback {
+            self.refresh = millihertz
+                .filter(|&mhz| mhz > 0)
+                .map(|mhz| Duration::from_secs_f64(1000.0 / f64::from(mhz)));
+        }
+    }
+
+    /// Frames per second actually rendered, once the refresh interval is known.
+    pub fn effective_frame_rate(&self) -> Option<f64> {
+        let refresh = self.refresh?.as_secs_f64();
+        Some(1.0 / (refresh * f64::from(self.divisor()?)))
+    }
+
+    /// Refreshes per rendered frame, once the refresh interval is known.
+    pub fn divisor(&self) -> Option<u32> {
+        let refresh = self.refresh?.as_secs_f64();
+        let ratio = self.interval.as_secs_f64() / refresh;
+        Some(((ratio - DIVISOR_TOLERANCE).ceil() as u32).max(1))
+    }
+
+    /// Called on each frame callback; returns whether to render a frame.
+    pub fn frame(&mut self) -> bool {
+        self.vblanks = self.vblanks.saturating_add(1);
+        match self.divisor() {
+            Some(divisor) => self.vblanks >= divisor,
+            None => self.last_frame.elapsed() >= self.interval,
+        }
+    }
+
+    /// Record that a frame was rendered.
+    pub fn rendered(&mut self) {
+        self.vblanks = 0;
+        self.last_frame = Instant::now();
+    }
+
+    /// Record presentation feedback; `refresh_ns` is zero when unknown or variable.
+    pub fn presented(&mut self, refresh_ns: u32) {
+        self.feedback = true;
+        self.refresh = (refresh_ns > 0).then(|| Duration::from_nanos(refresh_ns.into()));
+    }
+}
+
+/// Caps a shader's frame rate by how long its frames take on the GPU.
+///
+/// The frame rate is stepped down by a quarter while the shader would keep
+/// the GPU busy for more than [`GPU_SHARE`] of the time, and back up once a
+/// step up would stay well below that.
+#[derive(Debug)]
+pub struct AdaptiveRate {
+    floor: u8,
+    cap: u8,
+    /// Moving average of GPU frame time, in seconds.
+    average: Option<f64>,
+    /// Timed frames since the last step.
+    samples: u32,
+}
+
+impl AdaptiveRate {
+    pub fn new(floor: u8) -> Self {
+        Self {
+            floor: floor.clamp(1, 60),
+            cap: 60,
+            average: None,
+            samples: 0,
+        }
+    }
+
+    /// Change the lowest frame rate to step down to.
+    pub fn set_floor(&mut self, floor: u8) {
+        self.floor = floor.clamp(1, 60);
+        self.cap = self.cap.max(self.floor);
+    }
+
+    /// Highest frame rate the shader may currently render at.
+    pub fn cap(&self) -> u8 {
+        self.cap
+    }
+
+    /// Record the GPU time of a frame rendered at `frame_rate`.
+    /// Returns whether the cap changed.
+    pub fn sample(&mut self, gpu_time: Duration, frame_rate: u8) -> bool {
+        let time = gpu_time.as_secs_f64();
+        let average = self
+            .average
+            .map_or(time, |average| average + (time - average) * SAMPLE_WEIGHT);
+        self.average = Some(average);
+        self.samples += 1;
+        if self.samples < SAMPLES_PER_STEP {
+            return false;
+        }
+
+        let rate = frame_rate.min(self.cap);
+        let cap = if average * f64::from(rate) > GPU_SHARE && rate > self.floor {
+            (u16::from(rate) * 3 / 4).max(u16::from(self.floor)) as u8
+        } else {
+            let up = (u16::from(self.cap) * 4 / 3 + 1).min(60) as u8;
+            if self.cap < 60 && average * f64::from(up) < GPU_SHARE / 2.0 {
+                up
+            } else {
+                self.cap
+            }
+        };
+
+        self.samples = 0;
+        let changed = cap != self.cap;
+        self.cap = cap;
+        changed
+    }
+}
+
+fn frame_interval(frame_rate: u8) -> Duration {
+    Duration::from_secs_f64(1.0 / f64::from(frame_rate.clamp(1, 60)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pacer(frame_rate: u8, refresh_hz: f64) -> FramePacer {
+        let mut pacer = FramePacer::new(frame_rate);
+        pacer.presented((1e9 / refresh_hz) as u32);
+        pacer
+    }
+
+    #[test]
+    fn divisor_never_exceeds_the_frame_rate() {
+        assert_eq!(pacer(30, 60.0).divisor(), Some(2));
+        assert_eq!(pacer(24, 60.0).divisor(), Some(3));
+        assert_eq!(pacer(60, 144.0).divisor(), Some(3));
+        assert_eq!(pacer(60, 59.94).divisor(), Some(1));
+
+        // Variable refresh falls back to wall-clock pacing
+        let mut vrr = pacer(30, 60.0);
+        vrr.presented(0);
+        assert_eq!(vrr.divisor(), None);
+    }
+
+    #[test]
+    fn presentation_feedback_overrides_the_output_mode() {
+        let mut pacer = FramePacer::new(60);
+        pacer.set_output_refresh(Some(144_000));
+        assert_eq!(pacer.divisor(), Some(3));
+        assert_eq!(pacer.effective_frame_rate().map(f64::round), Some(48.0));
+
+        // A variable-refresh output reports zero and keeps wall-clock pacing
+        pacer.presented(0);
+        pacer.set_output_refresh(Some(60_000));
+        assert_eq!(pacer.divisor(), None);
+    }
+
+    #[test]
+    fn steps_the_frame_rate_between_the_floor_and_full_rate() {
+        let mut adaptive = AdaptiveRate::new(15);
+        let run = |adaptive: &mut AdaptiveRate, gpu_ms: u64| {
+            for _ in 0..SAMPLES_PER_STEP * 20 {
+                adaptive.sample(Duration::from_millis(gpu_ms), 60);
+            }
+            adaptive.cap()
+        };
+
+        // 20 ms per frame keeps the GPU busy past its share until the floor
+        assert_eq!(run(&mut adaptive, 20), 15);
+
+        // Cheap frames climb back to full rate
+        assert_eq!(run(&mut adaptive, 1), 60);
+
+        // 8 ms per frame settles where the GPU share is respected
+        let cap = run(&mut adaptive, 8);
+        assert!(cap < 60 && f64::from(cap) * 0.008 <= GPU_SHARE, "{cap}");
+    }
+
+    #[test]
+    fn renders_on_every_nth_vblank() {
+        let mut pacer = pacer(20, 60.0);
+        let rendered: Vec<bool> = (0..9)
+            .map(|_| {
+                let render = pacer.frame();
+                if render {
+                    pacer.rendered();
+                }
+                render
+            })
+            .collect();
+        assert_eq!(
+            rendered,
+            [false, false, true, false, false, true, false, false, true]
+        );
+    }
+}
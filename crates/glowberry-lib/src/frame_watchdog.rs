@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Recovery for shader layers whose frame callbacks stop.
+//!
+//! An animating shader requests a frame callback on every frame, so it only
+//! stops on purpose: while animation is paused or its output is. Some
+//! compositors however stop sending callbacks to occluded or freshly mapped
+//! layer surfaces, or drop the one request a layer had outstanding, which
+//! freezes it for good. Each shader layer therefore gets a calloop timer that
+//! requests a new callback and commits when none arrived for
+//! [`STALL_TIMEOUT`] while the layer should be animating.
+//!
+//! Compositors that hold callbacks of hidden surfaces rather than drop them
+//! send them all once the surface shows again. Each callback is therefore
+//! tagged with the generation of the watchdog it was requested in, which a
+//! new request from the timer supersedes, and callbacks of older generations
+//! are ignored so that every layer keeps a single render chain.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use sctk::reexports::{
+    calloop::{
+        LoopHandle, RegistrationToken,
+        timer::{TimeoutAction, Timer},
+    },
+    client::protocol::wl_surface::WlSurface,
+};
+
+/// How long a layer may go without a frame callback before one is requested
+/// again. Also how often layers are checked.
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Generations of all watchdogs, so a layer's new GPU state never accepts
+/// callbacks requested for the one it replaced.
+static GENERATIONS: AtomicU64 = AtomicU64::new(0);
+
+fn next_generation() -> u64 {
+    GENERATIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// User data of a shader layer's frame callback.
+#[derive(Debug)]
+pub struct ShaderFrame {
+    pub surface: WlSurface,
+    /// Watchdog generation the callback was requested in
+    pub generation: u64,
+}
+
+/// Tracks frame callbacks of one shader layer and owns the timer checking
+/// them, which is removed when the watchdog is dropped with the layer's GPU
+/// state.
+pub struct FrameWatchdog<D: 'static> {
+    loop_handle: LoopHandle<'static, D>,
+    token: Option<RegistrationToken>,
+    last_callback: Instant,
+    generation: u64,
+}
+
+impl<D> FrameWatchdog<D> {
+    /// Start checking a layer every [`STALL_TIMEOUT`] with `check`.
+    pub fn new(
+        loop_handle: &LoopHandle<'static, D>,
+        mut check: impl FnMut(&mut D) + 'static,
+    ) -> Self {
+        let token = loop_handle
+            .insert_source(Timer::from_duration(STALL_TIMEOUT), move |_, _, state| {
+                check(state);
+                TimeoutAction::ToDuration(STALL_TIMEOUT)
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to start frame callback watchdog"))
+            .ok();
+
+        Self {
+            loop_handle: loop_handle.clone(),
+            token,
+            last_callback: Instant::now(),
+            generation: next_generation(),
+        }
+    }
+
+    /// Record that a frame callback arrived, or that the layer starts
+    /// animating again after a pause and has just requested one.
+    pub fn reset(&mut self) {
+        self.last_callback = Instant::now();
+    }
+
+    /// Give up on the callback outstanding, which may still arrive, before
+    /// requesting another: callbacks requested from now on are a new
+    /// generation.
+    pub fn supersede(&mut self) {
+        self.generation = next_generation();
+        self.reset();
+    }
+
+    /// Generation to tag callbacks requested now with.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether no callback arrived for [`STALL_TIMEOUT`] as of `now`.
+    pub fn is_stalled(&self, now: Instant) -> bool {
+        stalled(self.last_callback, now)
+    }
+}
+
+impl<D> Drop for FrameWatchdog<D> {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+}
+
+fn stalled(last_callback: Instant, now: Instant) -> bool {
+    now.saturating_duration_since(last_callback) >= STALL_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{STALL_TIMEOUT, next_generation, stalled};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn stalls_once_no_callback_arrived_for_the_timeout() {
+        let last = Instant::now();
+
+        assert!(!stalled(last, last));
+        assert!(!stalled(
+            last,
+            last + STALL_TIMEOUT - Duration::from_millis(1)
+        ));
+        assert!(stalled(last, last + STALL_TIMEOUT));
+        // A clock read before the callback isn't a stall
+        assert!(!stalled(last + Duration::from_secs(1), last));
+    }
+
+    #[test]
+    fn generations_never_repeat() {
+        let first = next_generation();
+        assert!(next_generation() > first);
+    }
+}
@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The computer's location from GeoClue, for wallpapers that follow the sun.
+//!
+//! The first time a location is needed and none is configured, a background
+//! thread asks GeoClue on the system bus for a city-level fix and keeps it
+//! for the rest of the session. Until it arrives, or where GeoClue doesn't
+//! run or the user denies access, [`location`] returns `None` and callers
+//! fall back to a guess.
+
+use std::{
+    sync::{Mutex, Once},
+    time::{Duration, Instant},
+};
+
+use zbus::{Connection, proxy, zvariant::OwnedObjectPath};
+
+/// How long GeoClue gets to find the location.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time between checks whether GeoClue found the location.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// GeoClue's accuracy level for the nearest city, all the sun needs.
+const ACCURACY_CITY: u32 = 4;
+
+static START: Once = Once::new();
+static LOCATION: Mutex<Option<(f64, f64)>> = Mutex::new(None);
+
+/// GeoClue's entry point, handing out clients.
+#[proxy(
+    interface = "org.freedesktop.GeoClue2.Manager",
+    default_service = "org.freedesktop.GeoClue2",
+    default_path = "/org/freedesktop/GeoClue2/Manager"
+)]
+trait Manager {
+    /// A new client for this connection.
+    fn get_client(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+/// A GeoClue client, locating for one application.
+#[proxy(
+    interface = "org.freedesktop.GeoClue2.Client",
+    default_service = "org.freedesktop.GeoClue2"
+)]
+trait Client {
+    fn start(&self) -> zbus::Result<()>;
+
+    fn stop(&self) -> zbus::Result<()>;
+
+    /// Desktop file ID of the application, checked against its permissions.
+    #[zbus(property)]
+    fn set_desktop_id(&self, id: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn set_requested_accuracy_level(&self, level: u32) -> zbus::Result<()>;
+
+    /// The location found, or `/` before there is one.
+    #[zbus(property)]
+    fn location(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+/// A location found by GeoClue.
+#[proxy(
+    interface = "org.freedesktop.GeoClue2.Location",
+    default_service = "org.freedesktop.GeoClue2"
+)]
+trait Location {
+    #[zbus(property)]
+    fn latitude(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn longitude(&self) -> zbus::Result<f64>;
+}
+
+/// Latitude and longitude GeoClue reported, in degrees. The first call
+/// starts asking for them.
+pub fn location() -> Option<(f64, f64)> {
+    START.call_once(|| {
+        let spawned = std::thread::Builder::new()
+            .name("glowberry-geoclue".into())
+            .spawn(run);
+        if let Err(why) = spawned {
+            tracing::warn!(?why, "failed to start locating with GeoClue");
+        }
+    });
+    *LOCATION.lock().ok()?
+}
+
+fn run() {
+    let rt = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(rt) => rt,
+        Err(why) => {
+            tracing::warn!(?why, "failed to start locating with GeoClue");
+            return;
+        }
+    };
+
+    let located = rt.block_on(start()).and_then(|(connection, client)| {
+        let deadline = Instant::now() + TIMEOUT;
+        let found = loop {
+            let path = rt.block_on(client.location())?;
+            if path.as_str() != "/" {
+                break path;
+            }
+            if Instant::now() >= deadline {
+                let _ = rt.block_on(client.stop());
+                eyre::bail!("timed out");
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        let position = rt.block_on(position(&connection, found));
+        let _ = rt.block_on(client.stop());
+        position
+    });
+
+    match located {
+        Ok((latitude, longitude)) => {
+            tracing::info!(latitude, longitude, "GeoClue found the location");
+            if let Ok(mut location) = LOCATION.lock() {
+                *location = Some((latitude, longitude));
+            }
+        }
+        Err(why) => tracing::info!(%why, "GeoClue location unavailable"),
+    }
+}
+
+/// Start a GeoClue client locating to the nearest city.
+async fn start() -> eyre::Result<(Connection, ClientProxy<'static>)> {
+    let connection = Connection::system().await?;
+    let client_path = ManagerProxy::new(&connection).await?.get_client().await?;
+    let client = ClientProxy::builder(&connection)
+        .path(client_path)?
+        .cache_properties(zbus::proxy::CacheProperties::No)
+        .build()
+        .await?;
+    client.set_desktop_id(glowberry_config::NAME).await?;
+    client.set_requested_accuracy_level(ACCURACY_CITY).await?;
+    client.start().await?;
+    Ok((connection, client))
+}
+
+/// Latitude and longitude of the location at `path`.
+async fn position(connection: &Connection, path: OwnedObjectPath) -> eyre::Result<(f64, f64)> {
+    let location = LocationProxy::builder(connection)
+        .path(path)?
+        .build()
+        .await?;
+    Ok((location.latitude().await?, location.longitude().await?))
+}
@@ -2,14 +2,20 @@
 
 //! GPU rendering support for live shader wallpapers.
 
+use glowberry_config::GpuBackend;
 use pollster::FutureExt;
 use raw_window_handle::{
     RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
 };
 use sctk::reexports::client::{Connection, Proxy};
 use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use wgpu::SurfaceTargetUnsafe;
 
+/// Swapchain format of HDR shader layers, see [`crate::color_management`].
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
 /// GPU renderer for shader-based live wallpapers.
 ///
 /// This is lazily initialized only when a shader wallpaper is configured.
@@ -19,6 +25,40 @@ pub struct GpuRenderer {
     adapter: wgpu::Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    /// Set by wgpu when the device is lost (GPU reset, driver update).
+    lost: Arc<AtomicBool>,
+}
+
+/// How [`GpuRenderer::new`] picks a GPU adapter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterOptions {
+    /// Adapter to pin to: an index into the enumerated adapters, or a
+    /// case-insensitive part of the adapter or vendor name.
+    pub adapter: Option<String>,
+    /// Prefer an integrated GPU when no adapter is pinned.
+    pub prefer_low_power: bool,
+    /// Graphics API to render with. When it has no suitable adapter, the
+    /// renderer falls back to any supported one.
+    pub backend: GpuBackend,
+}
+
+impl Default for AdapterOptions {
+    fn default() -> Self {
+        Self {
+            adapter: None,
+            prefer_low_power: true,
+            backend: GpuBackend::Auto,
+        }
+    }
+}
+
+/// wgpu backends to try for `backend`.
+fn backends(backend: GpuBackend) -> wgpu::Backends {
+    match backend {
+        GpuBackend::Auto => wgpu::Backends::VULKAN | wgpu::Backends::GL,
+        GpuBackend::Vulkan => wgpu::Backends::VULKAN,
+        GpuBackend::Gl => wgpu::Backends::GL,
+    }
 }
 
 /// Error when initializing the GPU renderer.
@@ -35,19 +75,18 @@ impl GpuRenderer {
     ///
     /// Returns an error if no GPU adapter is available or device creation fails.
     /// Callers should fall back to the SHM rendering path on failure.
-    pub fn new() -> Result<Self, GpuError> {
-        let mut instance_desc = wgpu::InstanceDescriptor::new_without_display_handle();
-        instance_desc.backends = wgpu::Backends::VULKAN | wgpu::Backends::GL;
-        let instance = wgpu::Instance::new(instance_desc);
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::LowPower,
-                force_fallback_adapter: false,
-                compatible_surface: None,
-            })
-            .block_on()
-            .map_err(GpuError::NoAdapter)?;
+    pub fn new(options: &AdapterOptions) -> Result<Self, GpuError> {
+        let (instance, adapter) = match Self::request_adapter(options, backends(options.backend)) {
+            Ok(found) => found,
+            Err(err) if options.backend != GpuBackend::Auto => {
+                tracing::warn!(
+                    backend = ?options.backend,
+                    "Configured GPU backend unavailable ({err}), falling back to any backend"
+                );
+                Self::request_adapter(options, backends(GpuBackend::Auto))?
+            }
+            Err(err) => return Err(err),
+        };
 
         tracing::info!(
             "GPU renderer using: {} ({:?})",
@@ -55,18 +94,96 @@ impl GpuRenderer {
             adapter.get_info().backend
         );
 
+        // Timestamp queries let shader canvases measure their GPU cost
         let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default())
+            .request_device(&wgpu::DeviceDescriptor {
+                required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+                ..Default::default()
+            })
             .block_on()?;
 
+        let lost = Arc::new(AtomicBool::new(false));
+        let flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            if reason != wgpu::DeviceLostReason::Destroyed {
+                tracing::error!(?reason, message, "GPU device lost");
+                flag.store(true, Ordering::Relaxed);
+            }
+        });
+
         Ok(Self {
             instance,
             adapter,
             device,
             queue,
+            lost,
         })
     }
 
+    /// Pick an adapter among `backends`, see [`AdapterOptions`].
+    fn request_adapter(
+        options: &AdapterOptions,
+        backends: wgpu::Backends,
+    ) -> Result<(wgpu::Instance, wgpu::Adapter), GpuError> {
+        let mut instance_desc = wgpu::InstanceDescriptor::new_without_display_handle();
+        instance_desc.backends = backends;
+        let instance = wgpu::Instance::new(instance_desc);
+
+        let pinned = options
+            .adapter
+            .as_deref()
+            .and_then(|selector| Self::pinned_adapter(&instance, backends, selector));
+
+        let adapter = match pinned {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: if options.prefer_low_power {
+                        wgpu::PowerPreference::LowPower
+                    } else {
+                        wgpu::PowerPreference::HighPerformance
+                    },
+                    force_fallback_adapter: false,
+                    compatible_surface: None,
+                })
+                .block_on()
+                .map_err(GpuError::NoAdapter)?,
+        };
+
+        Ok((instance, adapter))
+    }
+
+    /// Find the adapter matching `selector`, see [`AdapterOptions::adapter`].
+    fn pinned_adapter(
+        instance: &wgpu::Instance,
+        backends: wgpu::Backends,
+        selector: &str,
+    ) -> Option<wgpu::Adapter> {
+        let adapters = instance.enumerate_adapters(backends).block_on();
+        let index = adapters.iter().enumerate().position(|(index, adapter)| {
+            let info = adapter.get_info();
+            adapter_matches(selector, index, &info.name, info.vendor)
+        });
+
+        if index.is_none() {
+            let available: Vec<String> = adapters
+                .iter()
+                .enumerate()
+                .map(|(index, adapter)| {
+                    let info = adapter.get_info();
+                    format!("{index}: {} ({:?})", info.name, info.backend)
+                })
+                .collect();
+            tracing::warn!(
+                selector,
+                ?available,
+                "Configured GPU adapter not found, selecting one automatically"
+            );
+        }
+
+        adapters.into_iter().nth(index?)
+    }
+
     /// Create a wgpu surface from a Wayland surface.
     ///
     /// # Safety
@@ -98,23 +215,50 @@ impl GpuRenderer {
         }
     }
 
-    /// Configure a surface for rendering.
+    /// Configure a surface for rendering, in [`HDR_FORMAT`] for `hdr`
+    /// shaders if the surface supports it.
     pub fn configure_surface(
         &self,
         surface: &wgpu::Surface<'_>,
         width: u32,
         height: u32,
+        hdr: bool,
     ) -> wgpu::SurfaceConfiguration {
         let capabilities = surface.get_capabilities(&self.adapter);
 
-        // Prefer non-sRGB formats for better color accuracy
+        // Prefer non-sRGB formats for better color accuracy; most shaders
+        // write sRGB-encoded colors
         let format = capabilities
             .formats
             .iter()
-            .find(|f| !f.is_srgb())
+            .find(|f| **f == HDR_FORMAT && hdr)
+            .or_else(|| capabilities.formats.iter().find(|f| !f.is_srgb()))
             .copied()
             .unwrap_or(capabilities.formats[0]);
 
+        // Shaders that output linear colors opt into an sRGB view of the
+        // same surface, which encodes them, where the backend allows it
+        let other = if format.is_srgb() {
+            format.remove_srgb_suffix()
+        } else {
+            format.add_srgb_suffix()
+        };
+        let view_formats = if other != format
+            && self
+                .adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS)
+        {
+            vec![other]
+        } else {
+            Vec::new()
+        };
+
+        // Otherwise a canvas renders offscreen and copies into the surface
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | (capabilities.usages & wgpu::TextureUsages::COPY_DST);
+
         let alpha_mode = if capabilities
             .alpha_modes
             .contains(&wgpu::CompositeAlphaMode::PreMultiplied)
@@ -125,20 +269,26 @@ impl GpuRenderer {
         };
 
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format,
             width,
             height,
             present_mode: wgpu::PresentMode::AutoVsync,
             desired_maximum_frame_latency: 2,
             alpha_mode,
-            view_formats: vec![],
+            view_formats,
         };
 
         surface.configure(&self.device, &config);
         config
     }
 
+    /// Whether the device was lost; everything created from it must be
+    /// recreated with a new renderer.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+
     #[inline]
     pub fn device(&self) -> &wgpu::Device {
         &self.device
@@ -149,3 +299,46 @@ impl GpuRenderer {
         &self.queue
     }
 }
+
+/// Whether the adapter at `index` with `name` and PCI `vendor` id matches an
+/// adapter selector: its index, or a case-insensitive part of its name or its
+/// vendor's name.
+fn adapter_matches(selector: &str, index: usize, name: &str, vendor: u32) -> bool {
+    let selector = selector.trim();
+    if let Ok(wanted) = selector.parse::<usize>() {
+        return wanted == index;
+    }
+
+    let selector = selector.to_lowercase();
+    name.to_lowercase().contains(&selector) || vendor_name(vendor) == Some(selector.as_str())
+}
+
+/// Common name of a GPU vendor by PCI vendor id.
+fn vendor_name(vendor: u32) -> Option<&'static str> {
+    match vendor {
+        0x1002 => Some("amd"),
+        0x10de => Some("nvidia"),
+        0x8086 => Some("intel"),
+        0x13b5 => Some("arm"),
+        0x5143 => Some("qualcomm"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_adapters_by_index_name_and_vendor() {
+        let nvidia = ("NVIDIA GeForce RTX 3060", 0x10de);
+        let intel = ("Intel(R) UHD Graphics 620 (KBL GT2)", 0x8086);
+
+        assert!(adapter_matches("1", 1, intel.0, intel.1));
+        assert!(!adapter_matches("0", 1, intel.0, intel.1));
+        assert!(adapter_matches("rtx 30", 0, nvidia.0, nvidia.1));
+        assert!(adapter_matches(" NVIDIA ", 0, nvidia.0, nvidia.1));
+        assert!(adapter_matches("intel", 1, intel.0, intel.1));
+        assert!(!adapter_matches("amd", 0, nvidia.0, nvidia.1));
+    }
+}
@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! GPU frame timing through timestamp queries.
+//!
+//! A render pass writes timestamps at its start and end, which are resolved
+//! into a buffer and read back asynchronously. Only one readback is in flight
+//! at a time; frames rendered while it is pending are not timed, so timing
+//! never stalls rendering.
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Size of the two resolved `u64` timestamps.
+const TIMESTAMPS_SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+
+/// Measures how long a canvas's render pass takes on the GPU.
+#[derive(Debug)]
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f64,
+    /// Set by the map callback once the readback is mapped (or failed to).
+    pending: Option<Arc<OnceLock<bool>>>,
+}
+
+impl GpuTimer {
+    /// Create a timer, if the device supports timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("glowberry: frame timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("glowberry: timestamp resolve buffer"),
+            size: TIMESTAMPS_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("glowberry: timestamp readback buffer"),
+            size: TIMESTAMPS_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve,
+            readback,
+            period: f64::from(queue.get_timestamp_period()),
+            pending: None,
+        })
+    }
+
+    /// Whether the next frame can be timed.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Timestamp writes for a render pass to be timed.
+    pub fn timestamp_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    /// Copy the timed pass's timestamps into the readback buffer.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, TIMESTAMPS_SIZE);
+    }
+
+    /// Start reading back the timestamps once the frame is submitted.
+    pub fn submitted(&mut self) {
+        let mapped = Arc::new(OnceLock::new());
+        let done = mapped.clone();
+        self.readback
+            .map_async(wgpu::MapMode::Read, .., move |result| {
+                let _ = done.set(result.is_ok());
+            });
+        self.pending = Some(mapped);
+    }
+
+    /// GPU time of the last timed frame, once its readback completes.
+    pub fn read(&mut self, device: &wgpu::Device) -> Option<Duration> {
+        let pending = self.pending.as_ref()?;
+        if pending.get().is_none() {
+            let _ = device.poll(wgpu::PollType::Poll);
+        }
+        let mapped = *pending.get()?;
+        self.pending = None;
+        if !mapped {
+            return None;
+        }
+
+        let ticks = {
+            let data = self.readback.get_mapped_range(..);
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            timestamps[1].saturating_sub(timestamps[0])
+        };
+        self.readback.unmap();
+
+        Some(Duration::from_nanos((ticks as f64 * self.period) as u64))
+    }
+}
@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Output identification overlays.
+//!
+//! When the settings app bumps the `identify-outputs` config key, every
+//! output briefly shows its connector name in a small overlay surface, so
+//! users can tell which physical monitor `DP-1` is. The daemon has no text
+//! renderer, so names are drawn upper-case in a built-in 5×7 bitmap font.
+//! The overlays take no input, so clicks reach what's beneath them.
+
+use sctk::{
+    compositor::{CompositorState, Region},
+    reexports::client::protocol::wl_shm,
+    shell::{WaylandSurface, wlr_layer::LayerSurface},
+    shm::{Shm, slot::SlotPool},
+};
+use std::time::Duration;
+
+/// How long the overlays stay up.
+pub const DURATION: Duration = Duration::from_secs(3);
+
+pub(crate) const GLYPH_WIDTH: u32 = 5;
+pub(crate) const GLYPH_HEIGHT: u32 = 7;
+/// Screen pixels per font pixel.
+const SCALE: u32 = 10;
+/// Margin around the label, in font pixels.
+const PADDING: u32 = 3;
+
+const BACKGROUND: [u8; 4] = [0x20, 0x20, 0x20, 0xff];
+const FOREGROUND: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// An output's name, shown on an overlay layer surface.
+#[derive(Debug)]
+pub struct Overlay {
+    pub(crate) layer: LayerSurface,
+    label: String,
+    pool: Option<SlotPool>,
+}
+
+impl Overlay {
+    pub fn new(layer: LayerSurface, label: String, compositor: &CompositorState) -> Self {
+        let (width, height) = label_size(&label);
+        layer.set_size(width, height);
+        match Region::new(compositor) {
+            Ok(region) => layer.set_input_region(Some(region.wl_region())),
+            Err(why) => tracing::warn!(?why, "failed to create identify input region"),
+        }
+        layer.commit();
+
+        Self {
+            layer,
+            label,
+            pool: None,
+        }
+    }
+
+    /// Draw the label once the surface is configured.
+    pub fn draw(&mut self, shm: &Shm) {
+        let (width, height) = label_size(&self.label);
+        let stride = width as i32 * 4;
+
+        let pool = match self.pool.as_mut() {
+            Some(pool) => pool,
+            None => match SlotPool::new(stride as usize * height as usize, shm) {
+                Ok(pool) => self.pool.insert(pool),
+                Err(why) => {
+                    tracing::error!(?why, "failed to create identify overlay pool");
+                    return;
+                }
+            },
+        };
+
+        let (buffer, canvas) = match pool.create_buffer(
+            width as i32,
+            height as i32,
+            stride,
+            wl_shm::Format::Xrgb8888,
+        ) {
+            Ok(buffer) => buffer,
+            Err(why) => {
+                tracing::error!(?why, "failed to create identify overlay buffer");
+                return;
+            }
+        };
+        draw_label(canvas, width, &self.label);
+
+        let wl_surface = self.layer.wl_surface();
+        wl_surface.damage_buffer(0, 0, width as i32, height as i32);
+        if let Err(why) = buffer.attach_to(wl_surface) {
+            tracing::error!(?why, "buffer attachment failed");
+        }
+        wl_surface.commit();
+    }
+}
+
+/// Surface size for `label`, in pixels.
+pub fn label_size(label: &str) -> (u32, u32) {
+    let chars = label.chars().count().max(1) as u32;
+    let width = chars * (GLYPH_WIDTH + 1) - 1 + 2 * PADDING;
+    let height = GLYPH_HEIGHT + 2 * PADDING;
+    (width * SCALE, height * SCALE)
+}
+
+/// Draw `label` onto an `XRGB8888` canvas `width` pixels wide.
+pub fn draw_label(canvas: &mut [u8], width: u32, label: &str) {
+    for pixel in canvas.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&BACKGROUND);
+    }
+
+    let origin = (PADDING * SCALE, PADDING * SCALE);
+    draw_text(canvas, width, origin, SCALE, label, FOREGROUND);
+}
+
+/// Draw `text` onto a 4-byte-per-pixel canvas `width` pixels wide, in
+/// `color`, with its top-left corner at `origin` and `scale` pixels per font
+/// pixel. Glyphs are spaced one font pixel apart.
+pub(crate) fn draw_text(
+    canvas: &mut [u8],
+    width: u32,
+    origin: (u32, u32),
+    scale: u32,
+    text: &str,
+    color: [u8; 4],
+) {
+    let stride = width as usize * 4;
+    for (index, c) in text.chars().enumerate() {
+        let glyph = glyph(c);
+        let left = index as u32 * (GLYPH_WIDTH + 1);
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                let x = (origin.0 + (left + col) * scale) as usize;
+                let y = (origin.1 + row as u32 * scale) as usize;
+                for dy in 0..scale as usize {
+                    let start = (y + dy) * stride + x * 4;
+                    let Some(span) = canvas.get_mut(start..start + scale as usize * 4) else {
+                        continue;
+                    };
+                    for pixel in span.chunks_exact_mut(4) {
+                        pixel.copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a 5×7 glyph, most significant of the low five bits leftmost.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        ' ' => [0x00; 7],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_glyph_pixels_over_the_background() {
+        let (width, height) = label_size("-");
+        assert_eq!((width, height), (110, 130));
+
+        let mut canvas = vec![0; width as usize * height as usize * 4];
+        draw_label(&mut canvas, width, "-");
+        let pixel = |x: u32, y: u32| {
+            let start = (y * width + x) as usize * 4;
+            &canvas[start..start + 4]
+        };
+
+        // The dash is the glyph's middle row
+        let row = (PADDING + 3) * SCALE + SCALE / 2;
+        assert_eq!(pixel(PADDING * SCALE + 1, row), FOREGROUND);
+        assert_eq!(pixel(PADDING * SCALE + 1, PADDING * SCALE), BACKGROUND);
+        assert_eq!(pixel(0, 0), BACKGROUND);
+    }
+}
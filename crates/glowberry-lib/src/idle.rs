@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Idle detection through `ext_idle_notifier_v1`.
+//!
+//! The compositor reports when the seat has gone without input for the
+//! configured timeout and when input resumes. Idle inhibitors, such as a
+//! playing video, keep the seat from going idle.
+
+use sctk::reexports::{
+    client::{QueueHandle, protocol::wl_seat::WlSeat},
+    protocols::ext::idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1},
+};
+
+use crate::engine::GlowBerry;
+
+/// The seat's idle state, for pausing animation after a period without input.
+#[derive(Debug)]
+pub struct IdleMonitor {
+    notifier: ext_idle_notifier_v1::ExtIdleNotifierV1,
+    seat: WlSeat,
+    notification: Option<ext_idle_notification_v1::ExtIdleNotificationV1>,
+    minutes: u32,
+    idle: bool,
+}
+
+impl IdleMonitor {
+    pub fn new(notifier: ext_idle_notifier_v1::ExtIdleNotifierV1, seat: WlSeat) -> Self {
+        Self {
+            notifier,
+            seat,
+            notification: None,
+            minutes: 0,
+            idle: false,
+        }
+    }
+
+    /// Watch for `minutes` without input, replacing the previous timeout.
+    /// Zero stops watching.
+    pub fn set_timeout(&mut self, minutes: u32, qh: &QueueHandle<GlowBerry>) {
+        if minutes == self.minutes {
+            return;
+        }
+
+        if let Some(notification) = self.notification.take() {
+            notification.destroy();
+        }
+        self.minutes = minutes;
+        self.idle = false;
+
+        if minutes > 0 {
+            let timeout = minutes.saturating_mul(60_000);
+            let notification = self
+                .notifier
+                .get_idle_notification(timeout, &self.seat, qh, ());
+            self.notification = Some(notification);
+            tracing::info!(minutes, "Pausing shader animation when idle");
+        }
+    }
+
+    /// Whether the seat went without input for the configured timeout.
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Apply an idle notification event, returning the new idle state.
+    pub fn on_event(&mut self, event: ext_idle_notification_v1::Event) -> Option<bool> {
+        self.idle = match event {
+            ext_idle_notification_v1::Event::Idled => true,
+            ext_idle_notification_v1::Event::Resumed => false,
+            _ => return None,
+        };
+        Some(self.idle)
+    }
+}
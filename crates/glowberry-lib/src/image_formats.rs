@@ -0,0 +1,316 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Image formats wallpapers can be decoded from.
+//!
+//! JPEG XL is always decoded, with `jxl-oxide`. AVIF needs the `avif`
+//! feature, which decodes through dav1d, HEIF/HEIC the `heif` feature,
+//! which links libheif, and camera RAW files the `raw` feature, which
+//! develops them with imagepipe. Folders only queue files with a supported
+//! extension.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use eyre::eyre;
+use image::{DynamicImage, ImageDecoder, ImageReader, Limits, metadata::Orientation};
+
+use crate::scaler;
+
+/// File extensions, in lowercase, of the formats this build decodes.
+pub const EXTENSIONS: &[&str] = &[
+    "jpg",
+    "jpeg",
+    "png",
+    "apng",
+    "gif",
+    "webp",
+    "hdr",
+    "jxl",
+    #[cfg(feature = "avif")]
+    "avif",
+    #[cfg(feature = "heif")]
+    "heic",
+    #[cfg(feature = "heif")]
+    "heif",
+    #[cfg(feature = "raw")]
+    "cr2",
+    #[cfg(feature = "raw")]
+    "nef",
+    #[cfg(feature = "raw")]
+    "arw",
+    #[cfg(feature = "raw")]
+    "dng",
+];
+
+/// Whether `path` has the extension of a format this build decodes.
+#[must_use]
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Opens an image `image` decodes, turned upright according to its EXIF
+/// orientation, since phones store photos taken in portrait sideways.
+///
+/// # Errors
+///
+/// Fails if the file can't be read or decoded.
+pub fn open_upright(path: &Path) -> image::ImageResult<DynamicImage> {
+    open_upright_with(path, Limits::default())
+}
+
+/// Like [`open_upright`], refusing images that need more memory than
+/// `limits` allows to decode.
+///
+/// # Errors
+///
+/// Fails if the file can't be read or decoded, or exceeds `limits`.
+pub fn open_upright_with(path: &Path, limits: Limits) -> image::ImageResult<DynamicImage> {
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Least memory an image may take to decode whatever it is shown at.
+const MIN_DECODE_ALLOC: u64 = 256 << 20;
+
+/// Limits for decoding an image shown on outputs whose largest needs it to
+/// cover `bound`. Decoders of these formats can't decode at a smaller size,
+/// so an image is refused when the full decode would take more than a float
+/// RGBA image of [`scaler::OVERSIZE`] times the bound, or
+/// [`MIN_DECODE_ALLOC`] if that is more.
+#[must_use]
+pub fn decode_limits(bound: (u32, u32)) -> Limits {
+    let oversize = scaler::OVERSIZE as u64;
+    let pixels = u64::from(bound.0) * u64::from(bound.1) * oversize * oversize;
+    let mut limits = Limits::default();
+    limits.max_alloc = Some((pixels * 16).max(MIN_DECODE_ALLOC));
+    limits
+}
+
+/// Dimensions [`cached_upright_dimensions`] read, by path, with the
+/// modification time of the file they were read from.
+type Dimensions = BTreeMap<PathBuf, (SystemTime, Option<(u32, u32)>)>;
+
+static DIMENSIONS: Mutex<Dimensions> = Mutex::new(BTreeMap::new());
+
+/// [`upright_dimensions`], read once per version of the file, so slideshows
+/// filtered by the shape of their images don't read every header again when
+/// outputs change.
+pub(crate) fn cached_upright_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    let Ok(modified) = modified else {
+        return upright_dimensions(path);
+    };
+    let mut cache = DIMENSIONS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((read, dimensions)) = cache.get(path)
+        && *read == modified
+    {
+        return *dimensions;
+    }
+    let dimensions = upright_dimensions(path);
+    cache.insert(path.to_path_buf(), (modified, dimensions));
+    dimensions
+}
+
+/// Width and height of the image `image` reads at `path`, as shown upright,
+/// read from its header without decoding it.
+#[must_use]
+pub fn upright_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let mut decoder = ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (width, height) = decoder.dimensions();
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    Some(match orientation {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => (height, width),
+        _ => (width, height),
+    })
+}
+
+/// Whether `path` is a HEIF or HEIC file, which `image` can't decode.
+pub(crate) fn is_heif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("heic") || ext.eq_ignore_ascii_case("heif"))
+}
+
+/// Decodes the primary image of a HEIF or HEIC file via libheif.
+#[cfg(feature = "heif")]
+pub(crate) fn decode_heif(path: &Path) -> eyre::Result<DynamicImage> {
+    let context = heif::open(path)?;
+    let handle = context
+        .primary_image_handle()
+        .map_err(|why| eyre!("failed to read heif image header: {why}"))?;
+    heif::decode(&handle)
+}
+
+/// Decodes the `index`th top-level image of a HEIF or HEIC file, such as one
+/// of the images of a dynamic wallpaper.
+#[cfg(feature = "heif")]
+pub(crate) fn decode_heif_frame(path: &Path, index: usize) -> eyre::Result<DynamicImage> {
+    let context = heif::open(path)?;
+    let mut ids = vec![0; context.number_of_top_level_images()];
+    context.top_level_image_ids(&mut ids);
+    let id = ids
+        .get(index)
+        .ok_or_else(|| eyre!("heif image has no image {index}"))?;
+    let handle = context
+        .image_handle(*id)
+        .map_err(|why| eyre!("failed to read heif image header: {why}"))?;
+    heif::decode(&handle)
+}
+
+/// The XMP metadata of a HEIF or HEIC file that describes a macOS dynamic
+/// wallpaper, if it has any.
+#[cfg(feature = "heif")]
+pub(crate) fn heif_xmp(path: &Path) -> Option<String> {
+    let context = heif::open(path).ok()?;
+    let handle = context.primary_image_handle().ok()?;
+    handle
+        .metadata_block_ids(b"mime")
+        .into_iter()
+        .filter_map(|id| handle.metadata(id).ok())
+        .map(|data| String::from_utf8_lossy(&data).into_owned())
+        .find(|xmp| xmp.contains("apple_desktop:"))
+}
+
+#[cfg(feature = "heif")]
+mod heif {
+    use std::path::Path;
+
+    use eyre::eyre;
+    use image::DynamicImage;
+    use libheif_rs::{ColorSpace, HeifContext, ImageHandle, LibHeif, RgbChroma};
+
+    pub fn open(path: &Path) -> eyre::Result<HeifContext<'static>> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| eyre!("heif image path is not valid UTF-8"))?;
+        HeifContext::read_from_file(path)
+            .map_err(|why| eyre!("failed to read heif image file: {why}"))
+    }
+
+    pub fn decode(handle: &ImageHandle) -> eyre::Result<DynamicImage> {
+        let image = LibHeif::new()
+            .decode(handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+            .map_err(|why| eyre!("failed to decode heif image: {why}"))?;
+
+        let planes = image.planes();
+        let plane = planes
+            .interleaved
+            .ok_or_else(|| eyre!("decoded heif image has no RGBA plane"))?;
+        let pixels = crate::draw::pack_rows(plane.data, plane.stride, plane.width, plane.height);
+        image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+            .map(DynamicImage::from)
+            .ok_or_else(|| eyre!("decoded heif image has a truncated plane"))
+    }
+}
+
+#[cfg(not(feature = "heif"))]
+pub(crate) fn decode_heif(path: &Path) -> eyre::Result<DynamicImage> {
+    Err(eyre!(
+        "{} is a HEIF image, which needs glowberry built with the `heif` feature",
+        path.display()
+    ))
+}
+
+#[cfg(not(feature = "heif"))]
+pub(crate) fn decode_heif_frame(path: &Path, _index: usize) -> eyre::Result<DynamicImage> {
+    decode_heif(path)
+}
+
+#[cfg(not(feature = "heif"))]
+pub(crate) fn heif_xmp(_path: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MIN_DECODE_ALLOC, cached_upright_dimensions, decode_limits, is_heif, is_supported,
+    };
+    use image::RgbImage;
+    use std::{
+        fs::File,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn matches_extensions_in_any_case() {
+        assert!(is_supported(Path::new("/walls/forest.JPG")));
+        assert!(is_supported(Path::new("/walls/dunes.jxl")));
+        assert!(!is_supported(Path::new("/walls/notes.txt")));
+        assert!(!is_supported(Path::new("/walls/README")));
+        assert_eq!(
+            is_supported(Path::new("/walls/city.avif")),
+            cfg!(feature = "avif")
+        );
+        assert_eq!(
+            is_supported(Path::new("/walls/IMG_0001.HEIC")),
+            cfg!(feature = "heif")
+        );
+        assert_eq!(
+            is_supported(Path::new("/walls/DSC_0001.NEF")),
+            cfg!(feature = "raw")
+        );
+        assert!(is_heif(Path::new("/walls/IMG_0001.HEIC")));
+    }
+
+    #[test]
+    fn limits_decodes_to_the_bound() {
+        assert_eq!(
+            decode_limits((1920, 1080)).max_alloc,
+            Some(MIN_DECODE_ALLOC)
+        );
+        // A 7680x4320 output may take a float image twice its size each way
+        assert_eq!(
+            decode_limits((7680, 4320)).max_alloc,
+            Some(7680 * 4320 * 4 * 16)
+        );
+    }
+
+    #[test]
+    fn reads_dimensions_again_after_the_file_changes() {
+        let path =
+            std::env::temp_dir().join(format!("glowberry-dimensions-{}.png", std::process::id()));
+        let taken = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        RgbImage::new(3, 2).save(&path).unwrap();
+        let stamp = |time| {
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(time)
+        };
+        stamp(taken).unwrap();
+        assert_eq!(cached_upright_dimensions(&path), Some((3, 2)));
+
+        // Not read again while the file is unchanged
+        RgbImage::new(4, 4).save(&path).unwrap();
+        stamp(taken).unwrap();
+        assert_eq!(cached_upright_dimensions(&path), Some((3, 2)));
+        stamp(taken + Duration::from_secs(1)).unwrap();
+        assert_eq!(cached_upright_dimensions(&path), Some((4, 4)));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
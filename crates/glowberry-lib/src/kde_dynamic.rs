@@ -0,0 +1,215 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! KDE dynamic wallpapers, used unchanged from where they are installed.
+//!
+//! Two layouts are read:
+//! - Plasma wallpaper packages, with `contents/images` and optionally
+//!   `contents/images_dark`, each holding the picture at several
+//!   resolutions named `<width>x<height>.<ext>`. The largest one of each is
+//!   shown by day and after dusk respectively.
+//! - Dynamic wallpaper manifests, a `manifest.json` listing images with a
+//!   `Time` of day, a `SolarAzimuth` and `SolarElevation`, or a `TimeOfDay`
+//!   of `day` or `night`. A source can name the manifest or its folder.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::{
+    dynamic::{Schedule, SolarEntry, TimeEntry},
+    image_formats,
+};
+
+const MANIFEST: &str = "manifest.json";
+
+/// Whether `path` is a KDE wallpaper package or dynamic wallpaper, rather
+/// than a folder of images for a slideshow.
+pub(crate) fn is_dynamic(path: &Path) -> bool {
+    if path.is_dir() {
+        path.join("contents/images").is_dir() || path.join(MANIFEST).is_file()
+    } else {
+        path.extension().is_some_and(|ext| ext == "json")
+    }
+}
+
+/// Images and schedule of the KDE wallpaper at `path`.
+pub(crate) fn open(path: &Path) -> Option<(Vec<PathBuf>, Schedule)> {
+    if path.join("contents/images").is_dir() {
+        return package(path);
+    }
+
+    let manifest = if path.is_dir() {
+        path.join(MANIFEST)
+    } else {
+        path.to_path_buf()
+    };
+    let json = std::fs::read_to_string(&manifest)
+        .inspect_err(|why| tracing::warn!(?why, "could not read {}", manifest.display()))
+        .ok()?;
+    let json = serde_json::from_str(&json)
+        .inspect_err(|why| tracing::warn!(?why, "invalid manifest {}", manifest.display()))
+        .ok()?;
+
+    let (names, schedule) = parse_manifest(&json)?;
+    let dir = manifest.parent().unwrap_or(Path::new("."));
+    Some((
+        names.into_iter().map(|name| dir.join(name)).collect(),
+        schedule,
+    ))
+}
+
+/// A Plasma wallpaper package, switching to its dark images after dusk.
+fn package(path: &Path) -> Option<(Vec<PathBuf>, Schedule)> {
+    let day = largest_image(&path.join("contents/images"))?;
+    match largest_image(&path.join("contents/images_dark")) {
+        Some(night) => Some((vec![day, night], Schedule::DayNight { day: 0, night: 1 })),
+        None => Some((vec![day], Schedule::DayNight { day: 0, night: 0 })),
+    }
+}
+
+/// The image in `dir` with the largest `<width>x<height>` name, or the first
+/// one when none is named so.
+fn largest_image(dir: &Path) -> Option<PathBuf> {
+    let mut images = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| image_formats::is_supported(path))
+        .collect::<Vec<_>>();
+    images.sort();
+    images
+        .iter()
+        .max_by_key(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(resolution)
+                .map_or(0, |(width, height)| u64::from(width) * u64::from(height))
+        })
+        .cloned()
+}
+
+/// The size in a `<width>x<height>` file name.
+fn resolution(name: &str) -> Option<(u32, u32)> {
+    let (width, height) = name.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// File names and schedule of a dynamic wallpaper manifest: a list of
+/// entries, or an object with the list under `Meta`.
+fn parse_manifest(json: &Value) -> Option<(Vec<String>, Schedule)> {
+    let entries = json.as_array().or_else(|| json.get("Meta")?.as_array())?;
+    let names = entries
+        .iter()
+        .map(|entry| Some(entry.get("FileName")?.as_str()?.to_string()))
+        .collect::<Option<Vec<_>>>()?;
+    if names.is_empty() {
+        return None;
+    }
+
+    let solar = entries
+        .iter()
+        .enumerate()
+        .map(|(frame, entry)| {
+            Some(SolarEntry {
+                altitude: entry.get("SolarElevation")?.as_f64()?,
+                azimuth: entry.get("SolarAzimuth")?.as_f64()?,
+                frame,
+            })
+        })
+        .collect::<Option<Vec<_>>>();
+    if let Some(solar) = solar {
+        return Some((names, Schedule::Solar(solar)));
+    }
+
+    let timed = entries
+        .iter()
+        .enumerate()
+        .map(|(frame, entry)| {
+            Some(TimeEntry {
+                fraction: day_fraction(entry.get("Time")?.as_str()?)?,
+                frame,
+            })
+        })
+        .collect::<Option<Vec<_>>>();
+    if let Some(mut timed) = timed {
+        timed.sort_by(|a, b| a.fraction.total_cmp(&b.fraction));
+        return Some((names, Schedule::Time(timed)));
+    }
+
+    let time_of_day = |wanted| {
+        entries
+            .iter()
+            .position(|entry| entry.get("TimeOfDay").and_then(Value::as_str) == Some(wanted))
+    };
+    let day = time_of_day("day")?;
+    let night = time_of_day("night").unwrap_or(day);
+    Some((names, Schedule::DayNight { day, night }))
+}
+
+/// An `HH:MM` time as a fraction of the day.
+fn day_fraction(time: &str) -> Option<f64> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then(|| f64::from(hours * 60 + minutes) / 1440.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{day_fraction, parse_manifest, resolution};
+    use crate::dynamic::{Schedule, TimeEntry};
+    use serde_json::json;
+
+    #[test]
+    fn reads_timed_manifests() {
+        let manifest = json!([
+            { "Time": "18:30", "FileName": "dusk.png", "CrossFade": true },
+            { "Time": "6:00", "FileName": "dawn.png", "CrossFade": true },
+        ]);
+        let (names, schedule) = parse_manifest(&manifest).unwrap();
+
+        assert_eq!(names, ["dusk.png", "dawn.png"]);
+        assert_eq!(
+            schedule,
+            Schedule::Time(vec![
+                TimeEntry {
+                    fraction: 0.25,
+                    frame: 1
+                },
+                TimeEntry {
+                    fraction: day_fraction("18:30").unwrap(),
+                    frame: 0
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn prefers_sun_positions_and_falls_back_to_day_and_night() {
+        let manifest = json!({ "Meta": [
+            { "Time": "12:00", "SolarAzimuth": 180.0, "SolarElevation": 60.0, "FileName": "a.png" },
+            { "Time": "0:00", "SolarAzimuth": 0.0, "SolarElevation": -40.0, "FileName": "b.png" },
+        ]});
+        assert!(matches!(
+            parse_manifest(&manifest),
+            Some((_, Schedule::Solar(entries))) if entries.len() == 2
+        ));
+
+        let manifest = json!([
+            { "TimeOfDay": "night", "FileName": "night.png" },
+            { "TimeOfDay": "day", "FileName": "day.png" },
+        ]);
+        assert!(matches!(
+            parse_manifest(&manifest),
+            Some((_, Schedule::DayNight { day: 1, night: 0 }))
+        ));
+
+        assert!(parse_manifest(&json!([{ "FileName": "a.png" }])).is_none());
+    }
+
+    #[test]
+    fn parses_package_image_names() {
+        assert_eq!(resolution("3840x2160"), Some((3840, 2160)));
+        assert_eq!(resolution("screenshot"), None);
+        assert_eq!(day_fraction("24:00"), None);
+    }
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Ken Burns motion: a slow pan and zoom over still images.
+//!
+//! An entry with `ken_burns` shows its still images on a shader layer
+//! running a built-in shader, see [`shader_source`]. The image is bound as
+//! `iTexture`, scaled like a static image, and the view moves between two
+//! framings picked at random for each image, then back, easing at both
+//! ends. The framings always stay inside the image.
+
+use std::path::Path;
+
+use glowberry_config::{KenBurns, ScalingMode, ShaderContent, ShaderLanguage, ShaderSource};
+use rand::RngExt;
+
+/// Closest zoom allowed.
+const MAX_ZOOM: f32 = 1.5;
+
+/// Seconds a pan takes without a duration or rotation frequency.
+const DEFAULT_DURATION: u64 = 60;
+
+/// A view of the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Framing {
+    /// Point of the image at the view's center, in fractions of its size.
+    center: [f32; 2],
+    /// How far the view is zoomed in, 1.0 showing the whole image.
+    zoom: f32,
+}
+
+/// The built-in shader panning over `image`, scaled with `scaling`. A pan
+/// takes the entry's `rotation_frequency` unless `ken_burns` sets its own.
+pub fn shader_source(
+    image: &Path,
+    ken_burns: &KenBurns,
+    scaling: &ScalingMode,
+    rotation_frequency: u64,
+) -> ShaderSource {
+    let duration = match (ken_burns.duration, rotation_frequency) {
+        (0, 0) => DEFAULT_DURATION,
+        (0, rotation) => rotation,
+        (duration, _) => duration,
+    };
+    let (start, end) = framings(ken_burns.zoom, &mut rand::rng());
+
+    ShaderSource {
+        shader: ShaderContent::Code(shader_code(start, end, duration as f32)),
+        source_path: None,
+        params: Default::default(),
+        background_image: Some(image.to_path_buf()),
+        depth_image: None,
+        background_from_wallpaper: false,
+        background_scaling: scaling.clone(),
+        language: ShaderLanguage::Wgsl,
+        frame_rate: ken_burns.frame_rate,
+        time_scale: 1.0,
+    }
+}
+
+/// Two framings to pan between, in random order: one zoomed in by
+/// `max_zoom`, and a wider one, zoomed in just enough to move as well.
+fn framings(max_zoom: f32, rng: &mut impl RngExt) -> (Framing, Framing) {
+    let max_zoom = if max_zoom.is_finite() {
+        max_zoom.clamp(1.0, MAX_ZOOM)
+    } else {
+        1.2
+    };
+
+    let mut framing = |zoom: f32| {
+        // Half the view fits between the center and each edge
+        let margin = 0.5 / zoom;
+        Framing {
+            center: [
+                rng.random_range(margin..=1.0 - margin),
+                rng.random_range(margin..=1.0 - margin),
+            ],
+            zoom,
+        }
+    };
+    let close = framing(max_zoom);
+    let wide = framing(1.0 + (max_zoom - 1.0) * 0.25);
+
+    if rng.random_bool(0.5) {
+        (close, wide)
+    } else {
+        (wide, close)
+    }
+}
+
+/// WGSL moving the view of `iTexture` from `start` to `end` over `duration`
+/// seconds, and back.
+fn shader_code(start: Framing, end: Framing, duration: f32) -> String {
+    let view = |framing: Framing| {
+        let [x, y] = framing.center;
+        format!("vec3<f32>({x:?}, {y:?}, {:?})", framing.zoom)
+    };
+    let (start, end) = (view(start), view(end));
+
+    format!(
+        r#"// Built-in shader of Ken Burns motion
+
+// Center and zoom of the framings panned between
+const START: vec3<f32> = {start};
+const END: vec3<f32> = {end};
+const DURATION: f32 = {duration:?};
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {{
+    // There and back, easing at both ends
+    let phase = 1.0 - abs(fract(iTime / (2.0 * DURATION)) * 2.0 - 1.0);
+    let view = mix(START, END, smoothstep(0.0, 1.0, phase));
+    let uv = view.xy + (fragCoord.xy / iResolution - 0.5) / view.z;
+    return vec4<f32>(textureSampleLevel(iTexture, iTextureSampler, uv, 0.0).rgb, 1.0);
+}}
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framings_stay_inside_the_image() {
+        let mut rng = rand::rng();
+        for max_zoom in [1.0, 1.2, 3.0, f32::NAN] {
+            let (start, end) = framings(max_zoom, &mut rng);
+            for framing in [start, end] {
+                assert!((1.0..=MAX_ZOOM).contains(&framing.zoom));
+                let margin = 0.5 / framing.zoom;
+                for center in framing.center {
+                    assert!((margin..=1.0 - margin).contains(&center), "{framing:?}");
+                }
+            }
+        }
+
+        let close = Framing {
+            center: [0.5, 0.25],
+            zoom: 1.5,
+        };
+        let code = shader_code(close, close, 30.0);
+        assert!(code.contains("const START: vec3<f32> = vec3<f32>(0.5, 0.25, 1.5);"));
+        assert!(code.contains("const DURATION: f32 = 30.0;"));
+    }
+}
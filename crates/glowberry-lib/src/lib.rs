@@ -1,14 +1,61 @@
-pub(crate) mod colored;
+pub(crate) mod animation;
+pub(crate) mod audio;
+pub(crate) mod blurhash;
+pub(crate) mod calendar;
+pub(crate) mod clock;
+pub(crate) mod color_management;
+pub mod colored;
+pub(crate) mod composite;
+pub(crate) mod dmabuf;
 pub(crate) mod draw;
+pub(crate) mod dynamic;
 pub mod engine;
 pub mod extend_crop;
 pub(crate) mod fragment_canvas;
+pub(crate) mod frame_pacing;
+pub(crate) mod frame_watchdog;
+pub(crate) mod geoclue;
 pub(crate) mod gpu;
+pub(crate) mod gpu_timer;
+pub(crate) mod identify;
+pub(crate) mod idle;
+pub mod image_formats;
 pub(crate) mod img_source;
-pub(crate) mod scaler;
+pub(crate) mod kde_dynamic;
+pub(crate) mod ken_burns;
+pub(crate) mod listener;
+pub(crate) mod night_light;
+pub(crate) mod now_playing;
+pub mod online;
+pub(crate) mod output_power;
+pub(crate) mod parallax;
+pub mod param_automation;
+pub(crate) mod pointer;
+pub(crate) mod post_process;
+pub(crate) mod predecode;
+pub(crate) mod raw;
+pub mod remote;
+pub(crate) mod scaled_cache;
+pub mod scaler;
+pub(crate) mod screen_share;
+pub(crate) mod session_lock;
 pub mod shader_defs;
+pub mod shader_include;
+pub(crate) mod shuffle;
+pub mod soak;
+pub(crate) mod solar;
+pub(crate) mod sysstats;
+pub(crate) mod theme_mode;
+pub mod timelapse;
+pub(crate) mod toplevels;
+pub(crate) mod transition;
+pub(crate) mod uniforms;
 pub(crate) mod upower;
+pub(crate) mod video;
 pub mod wallpaper;
+pub mod wallpaper_engine;
+pub(crate) mod weather;
+pub(crate) mod workspaces;
 
 pub use engine::{BackgroundEngine, EngineConfig, GlowBerry, GlowBerryLayer};
 pub use wallpaper::Wallpaper;
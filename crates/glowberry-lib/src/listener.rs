@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Background threads following outside state for the event loop.
+//!
+//! Album art, the night light, the weather, and downloads are each followed
+//! by a task on a thread of its own, with a current-thread tokio runtime for
+//! its D-Bus signals or HTTP requests, see [`Listener::spawn`]. The task is
+//! dropped at its next await once its [`Listener`] is, and reports through
+//! [`Running::send`], which drops what a task still working when its
+//! listener was replaced reports, since the listener started in its place
+//! reports for itself.
+
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use tokio::sync::oneshot;
+
+/// A running background task, stopped when dropped.
+#[derive(Debug)]
+pub(crate) struct Listener {
+    running: Arc<AtomicBool>,
+    _stop: oneshot::Sender<()>,
+}
+
+/// What a task knows of its listener.
+#[derive(Debug, Clone)]
+pub(crate) struct Running(Arc<AtomicBool>);
+
+impl Running {
+    /// Whether the listener is still running, so that the task may report.
+    pub(crate) fn is_running(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Send `message` to the event loop, unless the listener was stopped
+    /// meanwhile. Returns whether the task should go on.
+    pub(crate) fn send<T>(&self, tx: &calloop::channel::Sender<T>, message: T) -> bool {
+        self.is_running() && tx.send(message).is_ok()
+    }
+}
+
+impl Listener {
+    /// Run `task` on a thread named `glowberry-{name}` until it returns or
+    /// the listener is dropped.
+    ///
+    /// Returns `None` if the thread or its runtime can't be started.
+    pub(crate) fn spawn<F, Fut>(name: &str, task: F) -> Option<Self>
+    where
+        F: FnOnce(Running) -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .inspect_err(|why| tracing::warn!(?why, name, "failed to start a runtime"))
+            .ok()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let (stop, stopped) = oneshot::channel::<()>();
+
+        let thread_running = Running(running.clone());
+        std::thread::Builder::new()
+            .name(format!("glowberry-{name}"))
+            .spawn(move || {
+                rt.block_on(async move {
+                    tokio::select! {
+                        () = task(thread_running) => {}
+                        _ = stopped => {}
+                    }
+                });
+            })
+            .inspect_err(|why| tracing::warn!(?why, name, "failed to start a listener"))
+            .ok()?;
+
+        Some(Self {
+            running,
+            _stop: stop,
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
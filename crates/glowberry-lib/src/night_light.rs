@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Warming the wallpaper's colors along with the desktop's night light.
+//!
+//! A listener, see [`crate::listener`], reads the night light's color
+//! temperature from COSMIC's night light config, or from GNOME's or KDE's
+//! color service on the session bus, and reads it again whenever the config
+//! or one of the services changes. Where none of them runs, the configured
+//! temperature is followed after sunset, fading in through dusk at the
+//! weather location. The event loop tints static wallpapers while drawing
+//! them, and shader layers in their post-process pass, see [`tint`].
+
+use std::{sync::Arc, time::Duration};
+
+use cosmic_config::{Config, ConfigGet};
+use futures::StreamExt;
+use glowberry_config::night_light::{MIN_TEMPERATURE, NEUTRAL_TEMPERATURE, NightLightConfig};
+use image::{DynamicImage, ImageBuffer, Pixel};
+use tokio::sync::Notify;
+use zbus::{Connection, MatchRule, MessageStream, message::Type, proxy};
+
+use crate::{
+    dynamic,
+    listener::{Listener, Running},
+    solar,
+};
+
+/// Time between steps of the scheduled night light, which moves with the
+/// sun.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sun altitudes, in degrees, between which the scheduled night light fades
+/// in: from just before sunset to the end of civil dusk.
+const DUSK: (f64, f64) = (3.0, -6.0);
+
+/// Name of COSMIC's night light config.
+const COSMIC_ID: &str = "com.system76.CosmicNightLight";
+
+/// Version of COSMIC's night light config.
+const COSMIC_VERSION: u64 = 1;
+
+/// Key holding whether COSMIC's night light is shifting colors now.
+const COSMIC_ENABLED: &str = "enabled";
+
+/// Key holding the color temperature of COSMIC's night light, in kelvin.
+const COSMIC_TEMPERATURE: &str = "temperature";
+
+/// Bus names, object paths, and interfaces of the color services.
+const SERVICES: [(&str, &str, &str); 2] = [
+    (
+        "org.gnome.SettingsDaemon.Color",
+        "/org/gnome/SettingsDaemon/Color",
+        "org.gnome.SettingsDaemon.Color",
+    ),
+    (
+        "org.kde.KWin",
+        "/org/kde/KWin/NightLight",
+        "org.kde.KWin.NightLight",
+    ),
+];
+
+/// GNOME's color service.
+#[proxy(
+    interface = "org.gnome.SettingsDaemon.Color",
+    default_service = "org.gnome.SettingsDaemon.Color",
+    default_path = "/org/gnome/SettingsDaemon/Color"
+)]
+trait GnomeColor {
+    /// Whether the night light is shifting colors now.
+    #[zbus(property)]
+    fn night_light_active(&self) -> zbus::Result<bool>;
+
+    /// Color temperature of the night light, in kelvin.
+    #[zbus(property)]
+    fn temperature(&self) -> zbus::Result<u32>;
+}
+
+/// KWin's night light.
+#[proxy(
+    interface = "org.kde.KWin.NightLight",
+    default_service = "org.kde.KWin",
+    default_path = "/org/kde/KWin/NightLight"
+)]
+trait KwinNightLight {
+    /// Whether the night light is shifting colors now.
+    #[zbus(property)]
+    fn running(&self) -> zbus::Result<bool>;
+
+    /// Color temperature applied now, in kelvin.
+    #[zbus(property, name = "currentTemperature")]
+    fn current_temperature(&self) -> zbus::Result<u32>;
+}
+
+/// COSMIC's night light config, to watch for it changing.
+pub fn cosmic_config() -> Option<Config> {
+    Config::new(COSMIC_ID, COSMIC_VERSION)
+        .inspect_err(|why| tracing::debug!(?why, "failed to open the night light config"))
+        .ok()
+}
+
+/// Running night light listener. Listening stops when this is dropped.
+#[derive(Debug)]
+pub struct NightLightMonitor {
+    cosmic_changed: Arc<Notify>,
+    _listener: Listener,
+}
+
+impl NightLightMonitor {
+    /// Start following the night light; `notify` receives its color
+    /// temperature, in kelvin, whenever it changes.
+    pub fn start(config: &NightLightConfig, notify: calloop::channel::Sender<u32>) -> Option<Self> {
+        let config = config.clone();
+        let cosmic_changed = Arc::new(Notify::new());
+        let task_cosmic_changed = cosmic_changed.clone();
+        let listener = Listener::spawn("night-light", move |running| async move {
+            run(&config, &notify, &running, &task_cosmic_changed).await;
+        })?;
+
+        tracing::info!("Night light tint started");
+        Some(Self {
+            cosmic_changed,
+            _listener: listener,
+        })
+    }
+
+    /// Read COSMIC's night light config again after it changed.
+    pub fn cosmic_changed(&self) {
+        self.cosmic_changed.notify_one();
+    }
+}
+
+async fn run(
+    config: &NightLightConfig,
+    notify: &calloop::channel::Sender<u32>,
+    running: &Running,
+    cosmic_changed: &Notify,
+) {
+    let cosmic = cosmic_config();
+    // Without a session bus, the schedule is followed
+    let connection = Connection::session()
+        .await
+        .inspect_err(|err| tracing::debug!(?err, "Session bus unavailable for night light"))
+        .ok();
+    let mut changes = match &connection {
+        Some(connection) => service_changes(connection)
+            .await
+            .inspect_err(|err| tracing::debug!(?err, "Failed to follow the color services"))
+            .ok(),
+        None => None,
+    };
+
+    // Temperature reported last
+    let mut shown = NEUTRAL_TEMPERATURE;
+    loop {
+        let mut system = cosmic.as_ref().and_then(cosmic_temperature);
+        if let (None, Some(connection)) = (system, &connection) {
+            system = service_temperature(connection).await;
+        }
+        let temperature = system.unwrap_or_else(|| scheduled_temperature(config));
+
+        if temperature != shown {
+            tracing::debug!(
+                temperature,
+                system = system.is_some(),
+                "Night light changed"
+            );
+            if !running.send(notify, temperature) {
+                break;
+            }
+            shown = temperature;
+        }
+
+        // Wait for the night light to change, or the sun to move
+        let service_changed = async {
+            match changes.as_mut() {
+                Some(changes) if changes.next().await.is_some() => {}
+                _ => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            () = service_changed => {}
+            () = cosmic_changed.notified() => {}
+            () = tokio::time::sleep(SCHEDULE_INTERVAL), if system.is_none() => {}
+        }
+    }
+}
+
+/// Signals of the color services starting, stopping, or changing their
+/// properties.
+async fn service_changes(
+    connection: &Connection,
+) -> zbus::Result<futures::stream::SelectAll<MessageStream>> {
+    let mut changes = futures::stream::SelectAll::new();
+    for (name, path, interface) in SERVICES {
+        let owner = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender("org.freedesktop.DBus")?
+            .interface("org.freedesktop.DBus")?
+            .member("NameOwnerChanged")?
+            .arg(0, name)?
+            .build();
+        let properties = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path(path)?
+            .arg(0, interface)?
+            .build();
+        changes.push(MessageStream::for_match_rule(owner, connection, None).await?);
+        changes.push(MessageStream::for_match_rule(properties, connection, None).await?);
+    }
+    Ok(changes)
+}
+
+/// Color temperature of COSMIC's night light, or `None` if COSMIC doesn't
+/// have one.
+fn cosmic_temperature(config: &Config) -> Option<u32> {
+    let enabled: bool = config.get(COSMIC_ENABLED).ok()?;
+    let temperature = if enabled {
+        config.get(COSMIC_TEMPERATURE).ok()?
+    } else {
+        NEUTRAL_TEMPERATURE
+    };
+    Some(temperature.clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE))
+}
+
+/// Color temperature of the desktop's night light, or `None` if neither
+/// GNOME's nor KDE's color service runs.
+async fn service_temperature(connection: &Connection) -> Option<u32> {
+    let gnome = async {
+        let color = GnomeColorProxy::builder(connection)
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .await?;
+        let active = color.night_light_active().await?;
+        Ok::<_, zbus::Error>(if active {
+            color.temperature().await?
+        } else {
+            NEUTRAL_TEMPERATURE
+        })
+    };
+    if let Ok(temperature) = gnome.await {
+        return Some(temperature.clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE));
+    }
+
+    let kwin = async {
+        let night_light = KwinNightLightProxy::builder(connection)
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .await?;
+        let running = night_light.running().await?;
+        Ok::<_, zbus::Error>(if running {
+            night_light.current_temperature().await?
+        } else {
+            NEUTRAL_TEMPERATURE
+        })
+    };
+    kwin.await
+        .ok()
+        .map(|temperature| temperature.clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE))
+}
+
+/// The configured temperature at night, faded in through dusk.
+fn scheduled_temperature(config: &NightLightConfig) -> u32 {
+    let (latitude, longitude) = dynamic::location();
+    let (altitude, _) = solar::sun_position(jiff::Timestamp::now(), latitude, longitude);
+    let night = ((DUSK.0 - altitude) / (DUSK.0 - DUSK.1)).clamp(0.0, 1.0);
+    let warmer = f64::from(NEUTRAL_TEMPERATURE.saturating_sub(config.temperature));
+    NEUTRAL_TEMPERATURE - (warmer * night).round() as u32
+}
+
+/// Factors of the red, green, and blue channels that warm sRGB colors to
+/// `temperature` kelvin, 6500 K leaving them unchanged.
+pub fn tint(temperature: u32) -> [f32; 3] {
+    let temperature = temperature.clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE);
+    let [red, green, blue] = blackbody(f64::from(temperature));
+    let [white_red, white_green, white_blue] = blackbody(f64::from(NEUTRAL_TEMPERATURE));
+    [
+        (red / white_red).min(1.0) as f32,
+        (green / white_green).min(1.0) as f32,
+        (blue / white_blue).min(1.0) as f32,
+    ]
+}
+
+/// Color of a black body at `kelvin`, from 0 to 255 per channel, after Tanner
+/// Helland's fit of the CIE color matching functions.
+fn blackbody(kelvin: f64) -> [f64; 3] {
+    let t = kelvin / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2)
+    };
+    let green = if t <= 66.0 {
+        99.470_802_586_1 * t.ln() - 161.119_568_166_1
+    } else {
+        288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+    };
+    [red, green, blue].map(|channel| channel.clamp(0.0, 255.0))
+}
+
+/// Multiply the color channels of `image` by `tint`, leaving alpha.
+pub fn apply(image: &mut DynamicImage, tint: [f32; 3]) {
+    fn scale<P: Pixel>(
+        buffer: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+        tint: [f32; 3],
+        f: impl Fn(P::Subpixel, f32) -> P::Subpixel,
+    ) {
+        for pixel in buffer.pixels_mut() {
+            for (value, factor) in pixel.channels_mut().iter_mut().zip(tint) {
+                *value = f(*value, factor);
+            }
+        }
+    }
+    let scale_u8 = |value: u8, factor: f32| (f32::from(value) * factor).round() as u8;
+    let scale_u16 = |value: u16, factor: f32| (f32::from(value) * factor).round() as u16;
+    let scale_f32 = |value: f32, factor: f32| value * factor;
+
+    match image {
+        DynamicImage::ImageRgb8(buffer) => scale(buffer, tint, scale_u8),
+        DynamicImage::ImageRgba8(buffer) => scale(buffer, tint, scale_u8),
+        DynamicImage::ImageRgb16(buffer) => scale(buffer, tint, scale_u16),
+        DynamicImage::ImageRgba16(buffer) => scale(buffer, tint, scale_u16),
+        DynamicImage::ImageRgb32F(buffer) => scale(buffer, tint, scale_f32),
+        DynamicImage::ImageRgba32F(buffer) => scale(buffer, tint, scale_f32),
+        _ => {
+            let mut buffer = image.to_rgba8();
+            scale(&mut buffer, tint, scale_u8);
+            *image = DynamicImage::ImageRgba8(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warms_colors_below_neutral() {
+        assert_eq!(tint(NEUTRAL_TEMPERATURE), [1.0; 3]);
+        assert_eq!(tint(9000), [1.0; 3]);
+
+        let [red, green, blue] = tint(3000);
+        assert_eq!(red, 1.0);
+        assert!(blue < green && green < 1.0, "{green} {blue}");
+        assert!(tint(2000)[2] < blue);
+
+        let mut image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([200, 200, 200, 128]),
+        ));
+        apply(&mut image, [1.0, 0.5, 0.25]);
+        assert_eq!(image.to_rgba8().get_pixel(0, 0).0, [200, 100, 50, 128]);
+    }
+}
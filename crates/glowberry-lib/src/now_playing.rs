@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Album art of the playing track as the wallpaper.
+//!
+//! A listener, see [`crate::listener`], follows the media players on the
+//! session bus over MPRIS: whenever one appears, leaves, or changes its track
+//! or playback, it asks which one is playing. The first one that is, with
+//! album art, has its art blurred and dimmed as configured and saved to the
+//! cache directory; the event loop then shows it on every output until
+//! playback stops. Art served over HTTP(S) needs the `remote` feature.
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use futures::StreamExt;
+use glowberry_config::now_playing::NowPlayingConfig;
+use image::imageops::FilterType;
+use zbus::{Connection, MatchRule, MessageStream, message::Type, proxy, zvariant::OwnedValue};
+
+use crate::{
+    listener::{Listener, Running},
+    remote,
+};
+
+/// Longest edge the art is blurred at; it is scaled to the output after.
+const ART_SIZE: u32 = 512;
+
+/// Bus name prefix of MPRIS media players.
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// Object path of MPRIS media players.
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// A media player's playback interface.
+#[proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait Player {
+    /// `Playing`, `Paused`, or `Stopped`.
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+
+    /// Metadata of the current track, with the art's URL in `mpris:artUrl`.
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+}
+
+/// Running album art listener. Listening stops when this is dropped.
+#[derive(Debug)]
+pub struct NowPlayingMonitor {
+    _listener: Listener,
+}
+
+impl NowPlayingMonitor {
+    /// Start listening for playing tracks; `notify` receives the processed
+    /// art to show, or `None` once nothing with art plays.
+    ///
+    /// Returns `None` if the listener can't be started.
+    pub fn start(
+        config: &NowPlayingConfig,
+        notify: calloop::channel::Sender<Option<PathBuf>>,
+    ) -> Option<Self> {
+        let config = config.clone();
+        let listener = Listener::spawn("now-playing", move |running| async move {
+            if let Err(err) = run(&config, &notify, &running).await {
+                tracing::warn!(
+                    ?err,
+                    "Session bus unavailable, album art wallpaper disabled"
+                );
+            }
+        })?;
+
+        tracing::info!("Album art wallpaper started");
+        Some(Self {
+            _listener: listener,
+        })
+    }
+}
+
+async fn run(
+    config: &NowPlayingConfig,
+    notify: &calloop::channel::Sender<Option<PathBuf>>,
+    running: &Running,
+) -> zbus::Result<()> {
+    let connection = Connection::session().await?;
+    // Players appearing and leaving, and changing track or playback
+    let owners = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg0ns(MPRIS_PREFIX.trim_end_matches('.'))?
+        .build();
+    let properties = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path(MPRIS_PATH)?
+        .arg(0, "org.mpris.MediaPlayer2.Player")?
+        .build();
+    let mut changes = futures::stream::select(
+        MessageStream::for_match_rule(owners, &connection, None).await?,
+        MessageStream::for_match_rule(properties, &connection, None).await?,
+    );
+
+    // Art URL shown now
+    let mut shown: Option<String> = None;
+    loop {
+        let playing = playing_art_url(&connection).await.unwrap_or_else(|err| {
+            tracing::debug!(?err, "Failed to query media players");
+            None
+        });
+
+        if playing != shown {
+            let art = playing.as_deref().and_then(|url| {
+                art(url, config)
+                    .inspect_err(|err| tracing::warn!(%err, url, "Failed to prepare album art"))
+                    .ok()
+            });
+            tracing::debug!(url = ?playing, "Playing track changed");
+            if !running.send(notify, art) {
+                break;
+            }
+            shown = playing;
+        }
+
+        if changes.next().await.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Art URL of the first player that is playing a track with art.
+async fn playing_art_url(connection: &Connection) -> zbus::Result<Option<String>> {
+    let names = zbus::fdo::DBusProxy::new(connection)
+        .await?
+        .list_names()
+        .await?;
+
+    for name in names
+        .iter()
+        .filter(|name| name.as_str().starts_with(MPRIS_PREFIX))
+    {
+        let player = PlayerProxy::builder(connection)
+            .destination(name.as_str())?
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .await?;
+        if player.playback_status().await.ok().as_deref() != Some("Playing") {
+            continue;
+        }
+        let Ok(metadata) = player.metadata().await else {
+            continue;
+        };
+        let url = metadata
+            .get("mpris:artUrl")
+            .and_then(|url| <&str>::try_from(&**url).ok())
+            .filter(|url| !url.is_empty());
+        if let Some(url) = url {
+            return Ok(Some(url.to_owned()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The art at `url` blurred and dimmed as configured, saved to the cache.
+fn art(url: &str, config: &NowPlayingConfig) -> eyre::Result<PathBuf> {
+    let source = match url.strip_prefix("file://") {
+        Some(path) => PathBuf::from(percent_decode(path)),
+        None if remote::is_url(Path::new(url)) => remote::download(url)?,
+        None => eyre::bail!("unsupported art URL"),
+    };
+
+    let mut image = image::open(&source)?;
+    if image.width().max(image.height()) > ART_SIZE {
+        image = image.resize(ART_SIZE, ART_SIZE, FilterType::Triangle);
+    }
+    if config.blur > 0.0 {
+        image = image.fast_blur(config.blur);
+    }
+    let mut rgb = image.to_rgb8();
+    let keep = 1.0 - config.dim.clamp(0.0, 1.0);
+    for channel in rgb.iter_mut() {
+        *channel = (f32::from(*channel) * keep).round() as u8;
+    }
+
+    // Named after the art and its treatment, so each track's art is a new
+    // source; older ones are removed
+    let mut hasher = DefaultHasher::new();
+    (url, config.blur.to_bits(), config.dim.to_bits()).hash(&mut hasher);
+    let dir = cache_dir();
+    let path = dir.join(format!("{:016x}.png", hasher.finish()));
+    std::fs::create_dir_all(&dir)?;
+    if let Ok(entries) = std::fs::read_dir(&dir) {
+        for entry in entries.filter_map(Result::ok) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    rgb.save(&path)?;
+    Ok(path)
+}
+
+fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("glowberry")
+        .join("now-playing")
+}
+
+/// Decode the `%XX` escapes of a `file://` URL's path.
+fn percent_decode(path: &str) -> String {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::percent_decode;
+
+    #[test]
+    fn decodes_file_urls() {
+        assert_eq!(
+            percent_decode("/home/me/Music/Caf%C3%A9%20Tacvba/cover.jpg"),
+            "/home/me/Music/Café Tacvba/cover.jpg"
+        );
+        assert_eq!(percent_decode("/tmp/100%/a%2"), "/tmp/100%/a%2");
+    }
+}
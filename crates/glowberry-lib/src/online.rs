@@ -0,0 +1,401 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Wallpapers searched on Wallhaven and Unsplash.
+//!
+//! An online source names a provider and a query. Every `refresh_minutes` a
+//! listener, see [`crate::listener`], asks the provider for random matching images, downloads
+//! one that wasn't shown recently into the remote image cache, and has the
+//! wallpaper show it. The settings app uses [`search`] and [`use_image`] to
+//! browse the same results.
+//!
+//! Searching is only available with the `remote` cargo feature.
+
+use std::path::PathBuf;
+
+use glowberry_config::{OnlineProvider, OnlineSource};
+use sctk::reexports::calloop;
+use serde_json::Value;
+
+use crate::{
+    listener::Listener,
+    remote::{self, RemoteEvent},
+};
+
+/// Width Unsplash is asked to resize images to, rather than sending the
+/// camera's original.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+const UNSPLASH_WIDTH: u32 = 3840;
+
+/// An image found on a provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnlineImage {
+    pub id: String,
+    /// Small preview of the image.
+    pub thumbnail: String,
+    /// The image at wallpaper size.
+    pub url: String,
+    /// The image's page on the provider's site.
+    pub page: String,
+    pub author: Option<String>,
+    /// Where a download has to be reported, as Unsplash asks.
+    download_location: Option<String>,
+}
+
+/// Random images matching `source`'s query.
+///
+/// # Errors
+///
+/// Fails if the provider can't be reached or rejects the request.
+pub fn search(source: &OnlineSource) -> eyre::Result<Vec<OnlineImage>> {
+    #[cfg(feature = "remote")]
+    {
+        fetch::search(&remote::fetch::agent(), source)
+    }
+
+    #[cfg(not(feature = "remote"))]
+    {
+        let _ = source;
+        Err(eyre::eyre!(
+            "searching online needs glowberry built with the `remote` feature"
+        ))
+    }
+}
+
+/// Download `image` for use as a wallpaper, reporting the download to the
+/// provider when it asks for that. Returns the downloaded copy.
+///
+/// # Errors
+///
+/// Fails if the image can't be downloaded.
+pub fn use_image(source: &OnlineSource, image: &OnlineImage) -> eyre::Result<PathBuf> {
+    let path = remote::download(&image.url)?;
+
+    #[cfg(feature = "remote")]
+    if let Err(why) = fetch::report_download(&remote::fetch::agent(), source, image) {
+        tracing::debug!(%why, "failed to report download");
+    }
+    #[cfg(not(feature = "remote"))]
+    let _ = source;
+
+    Ok(path)
+}
+
+/// Running rotation of an online source. Stops when this is dropped.
+#[derive(Debug)]
+pub(crate) struct OnlineFeed {
+    _listener: Option<Listener>,
+}
+
+impl OnlineFeed {
+    /// Show a new image from `source` every `refresh_minutes`, the first one
+    /// right away unless `showing` an earlier one.
+    pub(crate) fn start(
+        source: OnlineSource,
+        showing: bool,
+        tx: calloop::channel::Sender<RemoteEvent>,
+    ) -> Self {
+        #[cfg(feature = "remote")]
+        let listener = Listener::spawn("online", move |running| async move {
+            fetch::run(&source, showing, &running, &tx).await;
+        });
+
+        #[cfg(not(feature = "remote"))]
+        let listener = {
+            let _ = (source, showing);
+            let _ = tx.send(RemoteEvent::Failed(String::from(
+                "Online wallpapers need glowberry built with the `remote` feature",
+            )));
+            None
+        };
+
+        Self {
+            _listener: listener,
+        }
+    }
+}
+
+/// Images of a Wallhaven search response.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn parse_wallhaven(json: &Value) -> Option<Vec<OnlineImage>> {
+    json.get("data")?
+        .as_array()?
+        .iter()
+        .map(|image| {
+            let string = |value: Option<&Value>| Some(value?.as_str()?.to_string());
+            Some(OnlineImage {
+                id: string(image.get("id"))?,
+                thumbnail: string(image.get("thumbs").and_then(|thumbs| thumbs.get("small")))?,
+                url: string(image.get("path"))?,
+                page: string(image.get("url"))?,
+                author: None,
+                download_location: None,
+            })
+        })
+        .collect()
+}
+
+/// Images of an Unsplash random photos response.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn parse_unsplash(json: &Value) -> Option<Vec<OnlineImage>> {
+    json.as_array()?
+        .iter()
+        .map(|photo| {
+            let string = |value: Option<&Value>| Some(value?.as_str()?.to_string());
+            let urls = photo.get("urls")?;
+            let links = photo.get("links")?;
+            let raw = urls.get("raw")?.as_str()?;
+            let separator = if raw.contains('?') { '&' } else { '?' };
+            Some(OnlineImage {
+                id: string(photo.get("id"))?,
+                thumbnail: string(urls.get("small"))?,
+                url: format!("{raw}{separator}w={UNSPLASH_WIDTH}&fm=jpg&q=85"),
+                page: string(links.get("html"))?,
+                author: string(photo.get("user").and_then(|user| user.get("name"))),
+                download_location: string(links.get("download_location")),
+            })
+        })
+        .collect()
+}
+
+/// Ids of images shown recently, which aren't picked again.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+const RECENT_IMAGES: usize = 50;
+
+/// The first of `images` not among `recent`, or the first one when all were
+/// shown recently.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn pick<'a>(
+    images: &'a [OnlineImage],
+    recent: &std::collections::VecDeque<String>,
+) -> Option<&'a OnlineImage> {
+    images
+        .iter()
+        .find(|image| !recent.contains(&image.id))
+        .or_else(|| images.first())
+}
+
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn provider_name(provider: OnlineProvider) -> &'static str {
+    match provider {
+        OnlineProvider::Wallhaven => "Wallhaven",
+        OnlineProvider::Unsplash => "Unsplash",
+    }
+}
+
+#[cfg(feature = "remote")]
+mod fetch {
+    use super::{OnlineImage, RECENT_IMAGES, parse_unsplash, parse_wallhaven, pick, provider_name};
+    use crate::{
+        listener::Running,
+        remote::{self, RemoteEvent, fetch::RETRY_INTERVAL},
+    };
+    use glowberry_config::{OnlineProvider, OnlineSource};
+    use sctk::reexports::calloop;
+    use serde_json::Value;
+    use std::{collections::VecDeque, path::PathBuf, time::Duration};
+
+    pub(super) async fn run(
+        source: &OnlineSource,
+        showing: bool,
+        running: &Running,
+        tx: &calloop::channel::Sender<RemoteEvent>,
+    ) {
+        let agent = remote::fetch::agent();
+        let interval = Duration::from_secs(u64::from(source.refresh_minutes.max(1)) * 60);
+        let mut recent = VecDeque::new();
+        let mut wait = if showing { interval } else { Duration::ZERO };
+
+        loop {
+            tokio::time::sleep(wait).await;
+
+            let event = match next_image(&agent, source, &mut recent) {
+                Ok(path) => {
+                    wait = interval;
+                    RemoteEvent::Updated(path)
+                }
+                Err(why) => {
+                    tracing::warn!(
+                        %why,
+                        provider = provider_name(source.provider),
+                        query = source.query,
+                        "failed to fetch online wallpaper"
+                    );
+                    wait = RETRY_INTERVAL.min(interval);
+                    RemoteEvent::Failed(why.to_string())
+                }
+            };
+            if !running.send(tx, event) {
+                break;
+            }
+        }
+    }
+
+    /// Download a matching image that wasn't shown recently.
+    fn next_image(
+        agent: &ureq::Agent,
+        source: &OnlineSource,
+        recent: &mut VecDeque<String>,
+    ) -> eyre::Result<PathBuf> {
+        let images = search(agent, source)?;
+        let image = pick(&images, recent)
+            .ok_or_else(|| eyre::eyre!("no images match \"{}\"", source.query))?;
+        tracing::debug!(id = image.id, page = image.page, "showing online wallpaper");
+
+        let cached = remote::cached_path(&image.url);
+        if !cached.is_file() {
+            remote::fetch::fetch(agent, &image.url, &cached)?;
+        }
+        if let Err(why) = report_download(agent, source, image) {
+            tracing::debug!(%why, "failed to report download");
+        }
+
+        recent.push_back(image.id.clone());
+        if recent.len() > RECENT_IMAGES {
+            recent.pop_front();
+        }
+        Ok(cached)
+    }
+
+    pub(super) fn search(
+        agent: &ureq::Agent,
+        source: &OnlineSource,
+    ) -> eyre::Result<Vec<OnlineImage>> {
+        let query = source.query.trim();
+        let (request, parse): (_, fn(&Value) -> Option<Vec<OnlineImage>>) = match source.provider {
+            OnlineProvider::Wallhaven => {
+                let mut request = agent
+                    .get("https://wallhaven.cc/api/v1/search")
+                    .query("q", query)
+                    .query("purity", "100")
+                    .query("sorting", "random")
+                    .query("atleast", "1920x1080");
+                if let Some(key) = source.api_key.as_deref() {
+                    request = request.query("apikey", key);
+                }
+                (request, parse_wallhaven)
+            }
+            OnlineProvider::Unsplash => {
+                let key = source
+                    .api_key
+                    .as_deref()
+                    .ok_or_else(|| eyre::eyre!("Unsplash needs an access key"))?;
+                let mut request = agent
+                    .get("https://api.unsplash.com/photos/random")
+                    .query("orientation", "landscape")
+                    .query("count", "30")
+                    .header("Accept-Version", "v1")
+                    .header("Authorization", format!("Client-ID {key}"));
+                if !query.is_empty() {
+                    request = request.query("query", query);
+                }
+                (request, parse_unsplash)
+            }
+        };
+
+        let mut response = request.call()?;
+        let status = response.status();
+        if !status.is_success() {
+            eyre::bail!("{} answered {status}", provider_name(source.provider));
+        }
+        let json: Value = serde_json::from_slice(&response.body_mut().read_to_vec()?)?;
+        parse(&json).ok_or_else(|| {
+            eyre::eyre!(
+                "unexpected response from {}",
+                provider_name(source.provider)
+            )
+        })
+    }
+
+    /// Tell Unsplash that `image` was downloaded, as its API guidelines ask.
+    pub(super) fn report_download(
+        agent: &ureq::Agent,
+        source: &OnlineSource,
+        image: &OnlineImage,
+    ) -> eyre::Result<()> {
+        let (Some(location), Some(key)) = (&image.download_location, &source.api_key) else {
+            return Ok(());
+        };
+        agent
+            .get(location)
+            .header("Authorization", format!("Client-ID {key}"))
+            .call()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UNSPLASH_WIDTH, parse_unsplash, parse_wallhaven, pick};
+    use serde_json::json;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn reads_wallhaven_results() {
+        let response = json!({
+            "data": [{
+                "id": "94x38z",
+                "url": "https://wallhaven.cc/w/94x38z",
+                "resolution": "6742x3534",
+                "path": "https://w.wallhaven.cc/full/94/wallhaven-94x38z.jpg",
+                "thumbs": {
+                    "large": "https://th.wallhaven.cc/lg/94/94x38z.jpg",
+                    "small": "https://th.wallhaven.cc/small/94/94x38z.jpg"
+                }
+            }],
+            "meta": { "current_page": 1 }
+        });
+        let images = parse_wallhaven(&response).unwrap();
+
+        assert_eq!(images.len(), 1);
+        assert_eq!(
+            images[0].url,
+            "https://w.wallhaven.cc/full/94/wallhaven-94x38z.jpg"
+        );
+        assert_eq!(
+            images[0].thumbnail,
+            "https://th.wallhaven.cc/small/94/94x38z.jpg"
+        );
+        assert!(parse_wallhaven(&json!({ "error": "Unauthorized" })).is_none());
+    }
+
+    #[test]
+    fn reads_unsplash_results_at_wallpaper_size() {
+        let response = json!([{
+            "id": "Dwu85P9SOIk",
+            "urls": {
+                "raw": "https://images.unsplash.com/photo-1417325384643?ixid=abc",
+                "small": "https://images.unsplash.com/photo-1417325384643?ixid=abc&w=400"
+            },
+            "links": {
+                "html": "https://unsplash.com/photos/Dwu85P9SOIk",
+                "download_location": "https://api.unsplash.com/photos/Dwu85P9SOIk/download"
+            },
+            "user": { "name": "Jane Doe" }
+        }]);
+        let images = parse_unsplash(&response).unwrap();
+
+        assert_eq!(
+            images[0].url,
+            format!(
+                "https://images.unsplash.com/photo-1417325384643?ixid=abc&w={UNSPLASH_WIDTH}&fm=jpg&q=85"
+            )
+        );
+        assert_eq!(images[0].author.as_deref(), Some("Jane Doe"));
+        assert!(images[0].download_location.is_some());
+    }
+
+    #[test]
+    fn avoids_recently_shown_images() {
+        let response = json!({ "data": [
+            { "id": "a", "url": "", "path": "a.jpg", "thumbs": { "small": "" } },
+            { "id": "b", "url": "", "path": "b.jpg", "thumbs": { "small": "" } },
+        ]});
+        let images = parse_wallhaven(&response).unwrap();
+
+        let mut recent = VecDeque::from([String::from("a")]);
+        assert_eq!(pick(&images, &recent).unwrap().id, "b");
+        recent.push_back(String::from("b"));
+        assert_eq!(pick(&images, &recent).unwrap().id, "a");
+        assert!(pick(&[], &recent).is_none());
+    }
+}
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Display power state through `zwlr_output_power_manager_v1`.
+//!
+//! Compositors are free to keep sending frame callbacks to surfaces on an
+//! output whose display is off, so shader layers would keep rendering frames
+//! nobody sees. Watching each output's power mode lets those layers stop
+//! requesting callbacks until the display comes back on. Outputs whose power
+//! mode can't be watched are treated as on.
+
+use sctk::reexports::{
+    client::{QueueHandle, WEnum, protocol::wl_output::WlOutput},
+    protocols_wlr::output_power_management::v1::client::{
+        zwlr_output_power_manager_v1, zwlr_output_power_v1,
+    },
+};
+
+use crate::engine::GlowBerry;
+
+use zwlr_output_power_v1::{Mode, ZwlrOutputPowerV1};
+
+/// An output's power mode watcher.
+#[derive(Debug)]
+struct Watched {
+    output: WlOutput,
+    power: ZwlrOutputPowerV1,
+    off: bool,
+}
+
+/// Power modes of the connected outputs.
+#[derive(Debug)]
+pub struct OutputPower {
+    manager: zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+    outputs: Vec<Watched>,
+}
+
+impl OutputPower {
+    pub fn new(manager: zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1) -> Self {
+        Self {
+            manager,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Start watching a newly connected output.
+    pub fn add_output(&mut self, output: &WlOutput, qh: &QueueHandle<GlowBerry>) {
+        if self.outputs.iter().any(|w| &w.output == output) {
+            return;
+        }
+
+        self.outputs.push(Watched {
+            output: output.clone(),
+            power: self.manager.get_output_power(output, qh, ()),
+            off: false,
+        });
+    }
+
+    /// Stop watching a disconnected output.
+    pub fn remove_output(&mut self, output: &WlOutput) {
+        self.outputs.retain(|w| {
+            if &w.output == output {
+                w.power.destroy();
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Whether `output`'s display is known to be off.
+    pub fn is_off(&self, output: &WlOutput) -> bool {
+        self.outputs.iter().any(|w| &w.output == output && w.off)
+    }
+
+    /// Apply an event for `power`, returning its output and whether the
+    /// display is now off if that changed.
+    pub fn on_event(
+        &mut self,
+        power: &ZwlrOutputPowerV1,
+        event: zwlr_output_power_v1::Event,
+    ) -> Option<(WlOutput, bool)> {
+        let index = self.outputs.iter().position(|w| &w.power == power)?;
+
+        let off = match event {
+            zwlr_output_power_v1::Event::Mode { mode } => mode == WEnum::Value(Mode::Off),
+            zwlr_output_power_v1::Event::Failed => {
+                // Unsupported output or gone; assume it stays on
+                let watched = self.outputs.remove(index);
+                watched.power.destroy();
+                tracing::debug!(output = ?watched.output, "Output power mode unavailable");
+                return watched.off.then_some((watched.output, false));
+            }
+            _ => return None,
+        };
+
+        let watched = &mut self.outputs[index];
+        (std::mem::replace(&mut watched.off, off) != off).then(|| (watched.output.clone(), off))
+    }
+}
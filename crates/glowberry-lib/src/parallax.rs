@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Depth-parallax wallpapers: an image shifted by its depth map.
+//!
+//! A parallax layer is a shader layer running a built-in shader, see
+//! [`shader_source`]. The image is bound as `iTexture` and the depth map as
+//! `iDepth`, both scaled like a static image. Each pixel is shifted in the
+//! direction of a slow drift, or of the pointer while it is over the
+//! wallpaper (see `iPointer`), by how near it is, giving a 2.5D effect.
+
+use glowberry_config::{ParallaxSource, ScalingMode, ShaderContent, ShaderLanguage, ShaderSource};
+
+/// Largest shift allowed, as a fraction of the output size.
+const MAX_STRENGTH: f32 = 0.1;
+
+/// The built-in shader showing `parallax`, with its image scaled with
+/// `scaling`.
+pub fn shader_source(parallax: &ParallaxSource, scaling: &ScalingMode) -> ShaderSource {
+    ShaderSource {
+        shader: ShaderContent::Code(shader_code(parallax.strength, parallax.follow_pointer)),
+        source_path: None,
+        params: Default::default(),
+        background_image: Some(parallax.image.clone()),
+        depth_image: Some(parallax.depth.clone()),
+        background_from_wallpaper: false,
+        background_scaling: scaling.clone(),
+        language: ShaderLanguage::Wgsl,
+        frame_rate: parallax.frame_rate,
+        time_scale: 1.0,
+    }
+}
+
+/// WGSL shifting `iTexture` by `iDepth`, at most `strength` of the output.
+fn shader_code(strength: f32, follow_pointer: bool) -> String {
+    let strength = if strength.is_finite() {
+        strength.clamp(0.0, MAX_STRENGTH)
+    } else {
+        0.02
+    };
+    // Only read `iPointer` when following it, so that it isn't tracked
+    let follow = if follow_pointer {
+        "offset = mix(offset, iPointer.xy * 2.0 - 1.0, iPointer.z);"
+    } else {
+        ""
+    };
+
+    format!(
+        r#"// Built-in shader of depth-parallax wallpapers
+
+const STRENGTH: f32 = {strength:?};
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {{
+    // Slow drift, replaced by the pointer's direction from the center
+    var offset = vec2<f32>(sin(iTime * 0.21), sin(iTime * 0.13 + 1.7));
+    {follow}
+    offset = offset * STRENGTH;
+
+    // Zoom in so that shifted pixels never reach past the image's edges
+    let uv = (fragCoord.xy / iResolution - 0.5) / (1.0 + 2.0 * STRENGTH) + 0.5;
+
+    // Near pixels move with the offset and far ones against it; a few
+    // steps find the depth of the pixel that lands here
+    var shifted = uv;
+    for (var i = 0; i < 4; i++) {{
+        let depth = textureSampleLevel(iDepth, iDepthSampler, shifted, 0.0).r;
+        shifted = uv - offset * (depth - 0.5);
+    }}
+    return vec4<f32>(textureSampleLevel(iTexture, iTextureSampler, shifted, 0.0).rgb, 1.0);
+}}
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_defs::{uses_depth, uses_pointer};
+
+    #[test]
+    fn follows_the_pointer_only_when_asked() {
+        let code = shader_code(0.05, true);
+        assert!(uses_depth(&code));
+        assert!(uses_pointer(&code));
+        assert!(code.contains("const STRENGTH: f32 = 0.05;"));
+
+        let code = shader_code(1.0, false);
+        assert!(!uses_pointer(&code));
+        assert!(code.contains("const STRENGTH: f32 = 0.1;"));
+        assert!(shader_code(f32::NAN, false).contains("const STRENGTH: f32 = 0.02;"));
+    }
+}
@@ -0,0 +1,436 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Automation curves for shader parameters.
+//!
+//! A parameter in a shader's `// [PARAMS]` header may declare an automation
+//! curve next to its range, so an otherwise static value drifts slowly on its
+//! own:
+//!
+//! ```text
+//! // [PARAMS]
+//! // speed: f32 = 0.5 | min: 0.2 | max: 1.0 | lfo: 2h
+//! // hue: f32 = 0.0 | min: 0.0 | max: 1.0 | pingpong: 45m
+//! // glow: f32 = 0.5 | keyframes: 06:00=0.2, 13:00=1.0, 21:00=0.1
+//! // [/PARAMS]
+//! ```
+//!
+//! - `lfo: <period>` follows a sine wave between `min` and `max`.
+//! - `pingpong: <period>` moves linearly from `min` to `max` and back.
+//! - `keyframes: HH:MM=value, ...` interpolates by local time of day,
+//!   wrapping around midnight.
+//!
+//! Periods take an `s`, `m`, or `h` suffix (seconds without one). Automated
+//! parameters become slots of the `iParams` uniform. Their `const`
+//! declarations turn into private variables of the same name, which the
+//! `main` entry point sets from the uniform before anything else runs, so
+//! WGSL's own scoping decides what each use of the name refers to. They thus
+//! can't appear in other constant expressions. A parameter given a custom
+//! value in the settings app keeps that value instead.
+
+use std::f32::consts::TAU;
+
+/// Most parameters that can be automated in one shader (`array<vec4f, 4>`).
+pub const MAX_AUTOMATED_PARAMS: usize = 16;
+
+const SECONDS_PER_DAY: f32 = 86_400.0;
+
+/// How an automated parameter changes over time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Automation {
+    /// Sine wave between `min` and `max` with this period in seconds.
+    Lfo(f32),
+    /// Triangle wave between `min` and `max` with this period in seconds.
+    PingPong(f32),
+    /// `(seconds since midnight, value)` pairs, sorted by time.
+    Keyframes(Vec<(f32, f32)>),
+}
+
+/// A `[PARAMS]` entry with an automation curve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutomatedParam {
+    pub name: String,
+    /// Whether the shader declares the parameter as `i32`.
+    pub is_int: bool,
+    /// Value the header declares, which a custom value replaces.
+    pub default: f32,
+    pub min: f32,
+    pub max: f32,
+    pub automation: Automation,
+}
+
+impl AutomatedParam {
+    /// Value at `elapsed` seconds since the shader started and `time_of_day`
+    /// seconds since local midnight.
+    pub fn value(&self, elapsed: f32, time_of_day: f32) -> f32 {
+        let range = |t: f32| self.min + (self.max - self.min) * t;
+        match &self.automation {
+            Automation::Lfo(period) => range(0.5 - 0.5 * (TAU * elapsed / period).cos()),
+            Automation::PingPong(period) => {
+                let phase = (elapsed / period).fract();
+                range(1.0 - (2.0 * phase - 1.0).abs())
+            }
+            Automation::Keyframes(keys) => keyframe_value(keys, time_of_day),
+        }
+    }
+}
+
+/// Evaluate `params` into `iParams` slot order.
+pub fn values(
+    params: &[AutomatedParam],
+    elapsed: f32,
+    time_of_day: f32,
+) -> [f32; MAX_AUTOMATED_PARAMS] {
+    let mut values = [0.0; MAX_AUTOMATED_PARAMS];
+    for (value, param) in values.iter_mut().zip(params) {
+        *value = param.value(elapsed, time_of_day);
+    }
+    values
+}
+
+/// Parse the automated parameters from a shader's `[PARAMS]` header.
+///
+/// Parameters declared with a value other than their default, as the
+/// settings app writes custom values, and parameters beyond
+/// [`MAX_AUTOMATED_PARAMS`] keep their static value.
+pub fn parse(shader_code: &str) -> Vec<AutomatedParam> {
+    let mut params = Vec::new();
+    let mut in_params = false;
+
+    for line in shader_code.lines() {
+        match line.trim() {
+            "// [PARAMS]" => in_params = true,
+            "// [/PARAMS]" => break,
+            trimmed if in_params => {
+                if let Some(param) = trimmed.strip_prefix("// ").and_then(parse_line) {
+                    params.push(param);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    params.retain(|param| {
+        let custom = const_value(shader_code, &param.name)
+            .filter(|value| (value - param.default).abs() > 1e-5 * param.default.abs().max(1.0));
+        if let Some(value) = custom {
+            tracing::warn!(
+                name = %param.name,
+                value,
+                "Shader parameter has a custom value, not automating it"
+            );
+        }
+        custom.is_none()
+    });
+
+    if params.len() > MAX_AUTOMATED_PARAMS {
+        tracing::warn!(
+            count = params.len(),
+            "Only the first {MAX_AUTOMATED_PARAMS} automated shader parameters are animated"
+        );
+        params.truncate(MAX_AUTOMATED_PARAMS);
+    }
+    params
+}
+
+/// Replace the `const` declarations of automated parameters with private
+/// variables, set from the `iParams` uniform at the start of `main`.
+///
+/// Returns `shader_code` as it is if it has no `main` function to set them
+/// in.
+pub fn apply(shader_code: &str, params: &[AutomatedParam]) -> String {
+    if params.is_empty() {
+        return shader_code.to_owned();
+    }
+    let Some(body) = main_body(shader_code) else {
+        tracing::warn!("Shader has no `main` function, not automating its parameters");
+        return shader_code.to_owned();
+    };
+
+    let mut code = String::with_capacity(shader_code.len() + 64 * params.len());
+    let mut assignments = String::new();
+    for (index, param) in params.iter().enumerate() {
+        let slot = format!("iParams[{}].{}", index / 4, ["x", "y", "z", "w"][index % 4]);
+        let (ty, value) = if param.is_int {
+            ("i32", format!("i32(round({slot}))"))
+        } else {
+            ("f32", slot)
+        };
+        code.push_str(&format!("var<private> {}: {ty};\n", param.name));
+        assignments.push_str(&format!("\n    {} = {value};", param.name));
+    }
+
+    let mut offset = 0;
+    for line in shader_code.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        if params.iter().any(|p| declares_const(line, &p.name)) {
+            continue;
+        }
+        // The brace ends a line at the latest
+        if start < body && body <= offset {
+            let split = body - start;
+            code.push_str(&line[..split]);
+            code.push_str(&assignments);
+            code.push_str(&line[split..]);
+        } else {
+            code.push_str(line);
+        }
+    }
+    code
+}
+
+/// Byte offset just past the `{` opening the body of the `main` function.
+fn main_body(code: &str) -> Option<usize> {
+    let mut offset = 0;
+    for line in code.split_inclusive('\n') {
+        let code_part = line.split("//").next().unwrap_or_default();
+        if let Some(at) = code_part.find("fn main")
+            && code_part[at + "fn main".len()..]
+                .trim_start()
+                .starts_with('(')
+        {
+            let start = offset + at;
+            return code[start..].find('{').map(|brace| start + brace + 1);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Parse `name: type = default | min: .. | max: .. | <automation>`.
+fn parse_line(line: &str) -> Option<AutomatedParam> {
+    let mut parts = line.split('|').map(str::trim);
+    let (name_type, default) = parts.next()?.split_once('=')?;
+    let (name, ty) = name_type.split_once(':')?;
+    let default: f32 = default.trim().parse().ok()?;
+
+    let (mut min, mut max, mut automation) = (default, default, None);
+    for part in parts {
+        let Some((key, value)) = part.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "min" => min = value.parse().unwrap_or(min),
+            "max" => max = value.parse().unwrap_or(max),
+            "lfo" => automation = parse_period(value).map(Automation::Lfo),
+            "pingpong" => automation = parse_period(value).map(Automation::PingPong),
+            "keyframes" => automation = parse_keyframes(value).map(Automation::Keyframes),
+            _ => {}
+        }
+    }
+
+    Some(AutomatedParam {
+        name: name.trim().to_owned(),
+        is_int: ty.trim() == "i32",
+        default,
+        min,
+        max,
+        automation: automation?,
+    })
+}
+
+/// Parse a period such as `90`, `30s`, `45m`, or `2h` into seconds.
+fn parse_period(value: &str) -> Option<f32> {
+    let (number, scale) = match value.as_bytes().last()? {
+        b's' => (&value[..value.len() - 1], 1.0),
+        b'm' => (&value[..value.len() - 1], 60.0),
+        b'h' => (&value[..value.len() - 1], 3600.0),
+        _ => (value, 1.0),
+    };
+    let seconds = number.trim().parse::<f32>().ok()? * scale;
+    (seconds > 0.0).then_some(seconds)
+}
+
+/// Parse `HH:MM=value, ...` into sorted `(seconds since midnight, value)`.
+fn parse_keyframes(value: &str) -> Option<Vec<(f32, f32)>> {
+    let mut keys = value
+        .split(',')
+        .map(|key| {
+            let (time, value) = key.split_once('=')?;
+            let (hours, minutes) = time.trim().split_once(':')?;
+            let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+            (hours < 24 && minutes < 60).then_some(())?;
+            Some((
+                (hours * 3600 + minutes * 60) as f32,
+                value.trim().parse().ok()?,
+            ))
+        })
+        .collect::<Option<Vec<(f32, f32)>>>()?;
+
+    if keys.is_empty() {
+        return None;
+    }
+    keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Some(keys)
+}
+
+/// Linearly interpolate `keys` at `time_of_day`, wrapping around midnight.
+fn keyframe_value(keys: &[(f32, f32)], time_of_day: f32) -> f32 {
+    let Some(&(first_time, first_value)) = keys.first() else {
+        return 0.0;
+    };
+    let (last_time, last_value) = keys[keys.len() - 1];
+
+    let next = keys.iter().position(|&(time, _)| time > time_of_day);
+    let ((t0, v0), (t1, v1)) = match next {
+        Some(0) | None => {
+            // Between the last key and the first key of the next day
+            let t0 = last_time;
+            let t1 = first_time + SECONDS_PER_DAY;
+            let t = if time_of_day < first_time {
+                time_of_day + SECONDS_PER_DAY
+            } else {
+                time_of_day
+            };
+            return lerp((t0, last_value), (t1, first_value), t);
+        }
+        Some(i) => (keys[i - 1], keys[i]),
+    };
+    lerp((t0, v0), (t1, v1), time_of_day)
+}
+
+fn lerp((t0, v0): (f32, f32), (t1, v1): (f32, f32), t: f32) -> f32 {
+    if t1 <= t0 {
+        return v0;
+    }
+    v0 + (v1 - v0) * ((t - t0) / (t1 - t0)).clamp(0.0, 1.0)
+}
+
+/// Whether `line` is a `const` declaration of `name`.
+fn declares_const(line: &str, name: &str) -> bool {
+    line.trim_start()
+        .strip_prefix("const ")
+        .and_then(|rest| rest.trim_start().strip_prefix(name))
+        .is_some_and(|rest| rest.trim_start().starts_with([':', '=']))
+}
+
+/// Value of the `const` declaration of `name` in `code`, if any.
+fn const_value(code: &str, name: &str) -> Option<f32> {
+    let line = code.lines().find(|line| declares_const(line, name))?;
+    let (_, value) = line.split_once('=')?;
+    value.trim().trim_end_matches(';').trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "\
+// [PARAMS]
+// speed: f32 = 0.5 | min: 0.2 | max: 1.0 | lfo: 2h
+// steps: i32 = 4 | min: 2 | max: 8 | pingpong: 30m
+// glow: f32 = 0.5 | keyframes: 18:00=0.1, 06:00=0.3
+// scale: f32 = 1.0 | min: 0.5 | max: 2.0
+// [/PARAMS]
+
+const speed: f32 = 0.5;
+const steps: i32 = 4;
+const scale: f32 = 1.0;
+
+@fragment
+fn main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+    // speed controls the drift
+    let t = iTime * speed * scale;
+    return vec4f(vec3f(glow) * f32(steps) * t, pos.speed);
+}
+";
+
+    #[test]
+    fn parses_automation_curves() {
+        let params = parse(SHADER);
+        let names: Vec<_> = params.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["speed", "steps", "glow"]);
+
+        assert_eq!(params[0].automation, Automation::Lfo(7200.0));
+        assert!(params[1].is_int);
+        assert_eq!(params[1].automation, Automation::PingPong(1800.0));
+        assert_eq!(
+            params[2].automation,
+            Automation::Keyframes(vec![(21_600.0, 0.3), (64_800.0, 0.1)])
+        );
+    }
+
+    #[test]
+    fn evaluates_curves_within_range() {
+        let params = parse(SHADER);
+
+        // LFO starts at min and peaks half a period later
+        assert!((params[0].value(0.0, 0.0) - 0.2).abs() < 1e-6);
+        assert!((params[0].value(3600.0, 0.0) - 1.0).abs() < 1e-6);
+
+        // Ping-pong is at max halfway and back at min after a full period
+        assert!((params[1].value(900.0, 0.0) - 8.0).abs() < 1e-4);
+        assert!((params[1].value(1800.0, 0.0) - 2.0).abs() < 1e-4);
+
+        // Keyframes interpolate by time of day, wrapping past midnight
+        assert!((params[2].value(0.0, 43_200.0) - 0.2).abs() < 1e-6);
+        assert!((params[2].value(0.0, 0.0) - 0.2).abs() < 1e-6);
+        assert!((params[2].value(0.0, 64_800.0) - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sets_automated_params_from_uniform_slots() {
+        let code = apply(SHADER, &parse(SHADER));
+
+        assert!(!code.contains("const speed"));
+        assert!(!code.contains("const steps"));
+        assert!(code.contains("const scale: f32 = 1.0;"));
+        assert!(code.starts_with(
+            "var<private> speed: f32;\nvar<private> steps: i32;\nvar<private> glow: f32;\n"
+        ));
+        assert!(code.contains(
+            "-> @location(0) vec4f {\n    speed = iParams[0].x;\n    \
+             steps = i32(round(iParams[0].y));\n    glow = iParams[0].z;\n    \
+             // speed controls the drift"
+        ));
+        // Uses of the names are left to WGSL to resolve
+        assert!(code.contains("let t = iTime * speed * scale;"));
+        assert!(code.contains("pos.speed"));
+    }
+
+    #[test]
+    fn keeps_custom_values() {
+        let shader = SHADER.replace("const speed: f32 = 0.5;", "const speed: f32 = 0.800000;");
+        let names: Vec<_> = parse(&shader).into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["steps", "glow"]);
+
+        // The settings app writes defaults with six decimals
+        let shader = SHADER.replace("const speed: f32 = 0.5;", "const speed: f32 = 0.500000;");
+        assert_eq!(parse(&shader).len(), 3);
+    }
+
+    #[test]
+    fn leaves_shadowing_declarations_alone() {
+        let shader = "\
+// [PARAMS]
+// speed: f32 = 0.5 | min: 0.2 | max: 1.0 | lfo: 2h
+// [/PARAMS]
+
+const speed: f32 = 0.5;
+
+fn wave(speed: f32) -> f32 {
+    return sin(speed);
+}
+
+// fn main() is the entry point
+@fragment fn main() -> f32 {
+    let speed = speed * 2.0;
+    return wave(speed) + speed;
+}
+";
+        let code = apply(shader, &parse(shader));
+
+        assert!(code.contains("fn wave(speed: f32) -> f32 {\n    return sin(speed);"));
+        assert!(code.contains(
+            "@fragment fn main() -> f32 {\n    speed = iParams[0].x;\n    \
+             let speed = speed * 2.0;"
+        ));
+    }
+
+    #[test]
+    fn needs_a_main_function() {
+        let shader = SHADER.replace("fn main(", "fn image(");
+        assert_eq!(apply(&shader, &parse(&shader)), shader);
+    }
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Pointer tracking for shaders reading `iPointer`.
+//!
+//! The seat is bound a second time, with [`PointerSeat`], to get its pointer,
+//! while a shader layer reads `iPointer`, see [`PointerTracker::bind`].
+//! Wallpapers only see the pointer where no window covers them. A client
+//! with a pointer has to set the cursor over its surfaces, so this needs
+//! `wp_cursor_shape_manager_v1` to keep the compositor's default cursor.
+
+use sctk::reexports::{
+    client::{
+        Proxy, QueueHandle, WEnum,
+        protocol::{wl_pointer, wl_seat, wl_surface::WlSurface},
+    },
+    protocols::wp::cursor_shape::v1::client::{
+        wp_cursor_shape_device_v1::{self, WpCursorShapeDeviceV1},
+        wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+    },
+};
+use sctk::registry::RegistryState;
+
+use crate::engine::GlowBerry;
+
+/// User data of the seat bound for its pointer.
+#[derive(Debug)]
+pub struct PointerSeat;
+
+/// A change of the pointer over a wallpaper surface.
+#[derive(Debug)]
+pub enum PointerEvent {
+    /// The pointer moved to the surface-local position.
+    Moved(WlSurface, [f64; 2]),
+    /// The pointer left the surface.
+    Left(WlSurface),
+}
+
+/// The seat's pointer, while it has one. The seat and its pointer are
+/// released when dropped.
+#[derive(Debug)]
+pub struct PointerTracker {
+    cursor_shape: WpCursorShapeManagerV1,
+    seat: wl_seat::WlSeat,
+    pointer: Option<(wl_pointer::WlPointer, WpCursorShapeDeviceV1)>,
+    focus: Option<WlSurface>,
+}
+
+impl PointerTracker {
+    /// Bind the seat for its pointer, which it reports with its
+    /// capabilities.
+    pub fn bind(
+        registry: &RegistryState,
+        qh: &QueueHandle<GlowBerry>,
+        cursor_shape: &WpCursorShapeManagerV1,
+    ) -> Option<Self> {
+        let seat = registry
+            .bind_one(qh, 1..=5, PointerSeat)
+            .inspect_err(|why| tracing::warn!(?why, "Failed to bind the seat for its pointer"))
+            .ok()?;
+        Some(Self {
+            cursor_shape: cursor_shape.clone(),
+            seat,
+            pointer: None,
+            focus: None,
+        })
+    }
+
+    /// Get or release the pointer as the seat gains or loses one.
+    pub fn set_capabilities(
+        &mut self,
+        seat: &wl_seat::WlSeat,
+        capabilities: WEnum<wl_seat::Capability>,
+        qh: &QueueHandle<GlowBerry>,
+    ) {
+        let has_pointer = matches!(
+            capabilities,
+            WEnum::Value(capabilities) if capabilities.contains(wl_seat::Capability::Pointer)
+        );
+
+        match (&self.pointer, has_pointer) {
+            (None, true) => {
+                let pointer = seat.get_pointer(qh, ());
+                let device = self.cursor_shape.get_pointer(&pointer, qh, ());
+                self.pointer = Some((pointer, device));
+            }
+            (Some(_), false) => self.release_pointer(),
+            _ => {}
+        }
+    }
+
+    fn release_pointer(&mut self) {
+        if let Some((pointer, device)) = self.pointer.take() {
+            device.destroy();
+            if pointer.version() >= 3 {
+                pointer.release();
+            }
+        }
+        self.focus = None;
+    }
+
+    /// Apply a pointer event, returning how the pointer changed over which
+    /// surface.
+    pub fn on_event(&mut self, event: wl_pointer::Event) -> Option<PointerEvent> {
+        match event {
+            wl_pointer::Event::Enter {
+                serial,
+                surface,
+                surface_x,
+                surface_y,
+            } => {
+                if let Some((_, device)) = &self.pointer {
+                    device.set_shape(serial, wp_cursor_shape_device_v1::Shape::Default);
+                }
+                self.focus = Some(surface.clone());
+                Some(PointerEvent::Moved(surface, [surface_x, surface_y]))
+            }
+            wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                let surface = self.focus.clone()?;
+                Some(PointerEvent::Moved(surface, [surface_x, surface_y]))
+            }
+            wl_pointer::Event::Leave { surface, .. } => {
+                self.focus = None;
+                Some(PointerEvent::Left(surface))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for PointerTracker {
+    fn drop(&mut self) {
+        self.release_pointer();
+        if self.seat.version() >= 5 {
+            self.seat.release();
+        }
+    }
+}
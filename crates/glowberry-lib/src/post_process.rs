@@ -0,0 +1,528 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Post-processing of shader frames: dimming and blurring the wallpaper
+//! while windows are open, tinting its colors, and transitioning from the
+//! previous source.
+//!
+//! A canvas with any of them renders its frames into a scene texture first, and
+//! a post-process pass, see [`PostProcess`], draws that to the surface.
+//!
+//! An entry's `window_effect` darkens and blurs the frames by how far it has
+//! faded in. It fades in once a window is shown on the output and out once
+//! the desktop is empty again. Still images have no shader of their own;
+//! with the effect they are shown on a shader layer by a built-in shader,
+//! see [`still_image_source`].
+//!
+//! The tint multiplies each color channel, e.g. to warm the wallpaper with
+//! the night light.
+//!
+//! An entry's `transition` animates from the last frame of the canvas the
+//! source switched from, in the styles of [`crate::transition`], see
+//! [`PostProcess::start_transition`]. Still images drawn without a shader
+//! transition on the CPU instead, see [`crate::transition::blend`].
+//!
+//! The pass is skipped without a tint or transition while the window effect
+//! is fully faded out.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::Instant;
+
+use glowberry_config::{
+    ScalingMode, ShaderContent, ShaderLanguage, ShaderSource, Transition, WindowEffect,
+};
+
+use crate::shader_defs::VERTEX_SHADER;
+use crate::transition;
+
+/// Strongest dimming allowed, so the wallpaper never turns fully black.
+const MAX_DIM: f32 = 0.9;
+
+/// Widest blur radius allowed, in pixels.
+const MAX_BLUR: f32 = 64.0;
+
+/// Frame rate of still images shown with the effect. Their shader doesn't
+/// animate, so frames are only rendered while it fades or the tint changes.
+const STILL_FRAME_RATE: u8 = 1;
+
+/// Shader of the post-process pass, followed by [`transition::SHADER`]. The
+/// blur averages samples spiralling out from each pixel.
+const POST_SHADER: &str = r#"
+struct Effect {
+    // dim, blur radius in pixels, and the scene's size
+    window: vec4<f32>,
+    // factors of the red, green, and blue channels
+    tint: vec4<f32>,
+    // how far the transition from the previous frame is, and its style
+    transition: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> effect: Effect;
+@group(0) @binding(1) var scene: texture_2d<f32>;
+@group(0) @binding(2) var scene_sampler: sampler;
+@group(0) @binding(3) var previous: texture_2d<f32>;
+
+const SAMPLES: i32 = 24;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = effect.window.zw;
+    let t = effect.transition.x;
+    if t >= 1.0 {
+        return shown(fragCoord.xy / size);
+    }
+    return transitioned(fragCoord.xy, size, t, i32(effect.transition.y));
+}
+
+// The frame from before the source switched
+fn before(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(previous, scene_sampler, uv, 0.0);
+}
+
+// The scene dimmed, blurred, and tinted
+fn shown(uv: vec2<f32>) -> vec4<f32> {
+    let size = effect.window.zw;
+    let radius = effect.window.y;
+    var color = textureSampleLevel(scene, scene_sampler, uv, 0.0);
+
+    // Samples spiral out to the radius, spread evenly over the disk
+    if radius >= 0.5 {
+        for (var i = 1; i < SAMPLES; i++) {
+            let distance = radius * sqrt(f32(i) / f32(SAMPLES - 1));
+            let angle = f32(i) * GOLDEN_ANGLE;
+            let offset = vec2<f32>(cos(angle), sin(angle)) * distance / size;
+            color += textureSampleLevel(scene, scene_sampler, uv + offset, 0.0);
+        }
+        color /= f32(SAMPLES);
+    }
+
+    return vec4<f32>(color.rgb * (1.0 - effect.window.x) * effect.tint.rgb, color.a);
+}
+"#;
+
+/// The built-in shader showing the still `image`, scaled with `scaling`, so
+/// the effect or a transition can be applied over it.
+pub fn still_image_source(image: &Path, scaling: &ScalingMode) -> ShaderSource {
+    ShaderSource {
+        shader: ShaderContent::Code(String::from(
+            r#"// Built-in shader of still images with a window effect or transition
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureSampleLevel(iTexture, iTextureSampler, fragCoord.xy / iResolution, 0.0);
+}
+"#,
+        )),
+        source_path: None,
+        params: Default::default(),
+        background_image: Some(image.to_path_buf()),
+        depth_image: None,
+        background_from_wallpaper: false,
+        background_scaling: scaling.clone(),
+        language: ShaderLanguage::Wgsl,
+        frame_rate: STILL_FRAME_RATE,
+        time_scale: 1.0,
+    }
+}
+
+/// Move the effect's `amount` towards `target` by `dt` seconds of a fade
+/// taking `fade` seconds.
+fn step(amount: f32, target: f32, dt: f32, fade: f32) -> f32 {
+    if !(fade.is_finite() && fade > 0.0) {
+        return target;
+    }
+    let delta = dt.max(0.0) / fade;
+    if amount < target {
+        (amount + delta).min(target)
+    } else {
+        (amount - delta).max(target)
+    }
+}
+
+/// Transition from the last frame of the previous canvas.
+struct Switch {
+    from: wgpu::Texture,
+    config: Transition,
+    start: Instant,
+}
+
+/// Post-process pass dimming, blurring, tinting, and transitioning a
+/// canvas's frames.
+pub struct PostProcess {
+    /// The window effect, with its values clamped.
+    window_effect: Option<WindowEffect>,
+    /// Whether windows are shown on the output.
+    windows: bool,
+    /// How far the effect has faded in, from 0 to 1.
+    amount: f32,
+    /// When the amount was last stepped.
+    last_step: Instant,
+
+    /// Factors of the color channels, in the color space of the frames.
+    tint: [f32; 3],
+    /// Whether frames hold linear colors, rather than sRGB-encoded ones.
+    linear: bool,
+
+    /// Transition from the previous source, while it lasts.
+    switch: Option<Switch>,
+
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    /// Texture the canvas renders into, and the bind group sampling it.
+    scene: Option<(wgpu::Texture, wgpu::BindGroup)>,
+}
+
+impl PostProcess {
+    /// Create the pass for frames in `format`, without a window effect or
+    /// tint.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("glowberry: post-process layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("glowberry: post-process pipeline layout"),
+            bind_group_layouts: &[Some(&bind_group_layout)],
+            ..Default::default()
+        });
+        let vertex_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("glowberry: vertex shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(VERTEX_SHADER)),
+        });
+        let fragment_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("glowberry: post-process shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(format!(
+                "{POST_SHADER}{}",
+                transition::SHADER
+            ))),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("glowberry: post-process pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex_module,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment_module,
+                entry_point: Some("main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("glowberry: post-process sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("glowberry: post-process uniform"),
+            size: 48,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            window_effect: None,
+            windows: false,
+            amount: 0.0,
+            last_step: Instant::now(),
+            tint: [1.0; 3],
+            linear: format.is_srgb() || format == wgpu::TextureFormat::Rgba16Float,
+            switch: None,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            uniform,
+            scene: None,
+        }
+    }
+
+    /// Dim and blur frames as `config` asks while windows are open, starting
+    /// with the effect as it is for `windows`, or stop with `None`.
+    pub fn set_window_effect(&mut self, config: Option<&WindowEffect>, windows: bool) {
+        let clamp = |value: f32, max: f32| {
+            if value.is_finite() {
+                value.clamp(0.0, max)
+            } else {
+                0.0
+            }
+        };
+        self.window_effect = config.map(|config| WindowEffect {
+            dim: clamp(config.dim, MAX_DIM),
+            blur: clamp(config.blur, MAX_BLUR),
+            fade: config.fade,
+        });
+        self.windows = windows;
+        self.amount = self.target();
+    }
+
+    /// Whether frames are dimmed and blurred while windows are open.
+    pub fn has_window_effect(&self) -> bool {
+        self.window_effect.is_some()
+    }
+
+    /// Fade the window effect in or out as windows are shown on the output
+    /// or all of them are gone.
+    pub fn set_windows(&mut self, windows: bool) {
+        self.step();
+        self.windows = windows;
+    }
+
+    /// Whether the window effect is still fading in or out, or a
+    /// transition runs.
+    pub fn is_fading(&self) -> bool {
+        self.amount != self.target() || self.switch.is_some()
+    }
+
+    /// Transition from `from`, the last frame of the previous canvas, as
+    /// `config` asks.
+    pub fn start_transition(&mut self, from: wgpu::Texture, config: &Transition) {
+        let start = Instant::now();
+        if transition::progress(config, start) >= 1.0 {
+            return;
+        }
+        self.switch = Some(Switch {
+            from,
+            config: config.clone(),
+            start,
+        });
+        // Bound to the pass from the next frame
+        self.scene = None;
+    }
+
+    /// Multiply the color channels by `tint`, given for sRGB-encoded colors.
+    pub fn set_tint(&mut self, tint: [f32; 3]) {
+        self.tint = if self.linear {
+            tint.map(|factor| factor.powf(2.2))
+        } else {
+            tint
+        };
+    }
+
+    /// Whether the pass changes nothing and can be skipped.
+    fn is_identity(&self) -> bool {
+        self.amount <= 0.0 && self.tint == [1.0; 3] && self.switch.is_none()
+    }
+
+    fn target(&self) -> f32 {
+        if self.windows && self.window_effect.is_some() {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn step(&mut self) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_step).as_secs_f32();
+        let fade = self
+            .window_effect
+            .as_ref()
+            .map_or(0.0, |config| config.fade);
+        self.amount = step(self.amount, self.target(), dt, fade);
+        self.last_step = now;
+    }
+
+    /// Step the fade for a frame of `size`. Returns the view to render the
+    /// frame into for [`Self::encode`], or `None` while the pass changes
+    /// nothing and the frame can go to the target directly.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        size: wgpu::Extent3d,
+    ) -> Option<wgpu::TextureView> {
+        self.step();
+        let progress = self.switch.as_ref().map_or(1.0, |switch| {
+            transition::progress(&switch.config, switch.start)
+        });
+        if progress >= 1.0 && self.switch.take().is_some() {
+            self.scene = None;
+        }
+        if self.is_identity() {
+            return None;
+        }
+
+        if self.scene.as_ref().is_none_or(|(t, _)| t.size() != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("glowberry: post-process scene"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            // Without a transition, the scene stands in for the previous frame
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let previous = self
+                .switch
+                .as_ref()
+                .map_or(&texture, |switch| &switch.from)
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("glowberry: post-process bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&previous),
+                    },
+                ],
+            });
+            self.scene = Some((texture, bind_group));
+        }
+
+        // Eased at both ends of the fade
+        let eased = self.amount * self.amount * (3.0 - 2.0 * self.amount);
+        let (dim, blur) = self
+            .window_effect
+            .as_ref()
+            .map_or((0.0, 0.0), |config| (config.dim, config.blur));
+        let [red, green, blue] = self.tint;
+        let (switched, style) = self.switch.as_ref().map_or((1.0, 0), |switch| {
+            (
+                transition::ease(switch.config.easing, progress),
+                transition::style_index(switch.config.style),
+            )
+        });
+        let uniform = [
+            dim * eased,
+            blur * eased,
+            size.width as f32,
+            size.height as f32,
+            red,
+            green,
+            blue,
+            1.0,
+            switched,
+            style as f32,
+            0.0,
+            0.0,
+        ];
+        queue.write_buffer(&self.uniform, 0, bytemuck::cast_slice(&uniform));
+
+        self.scene
+            .as_ref()
+            .map(|(texture, _)| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
+    /// Draw the frame rendered into the view from [`Self::prepare`] to
+    /// `target`, dimmed, blurred, tinted, and transitioned.
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let Some((_, bind_group)) = &self.scene else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("glowberry: post-process pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                depth_slice: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_towards_the_target() {
+        assert_eq!(step(0.0, 1.0, 0.25, 0.5), 0.5);
+        assert_eq!(step(0.5, 1.0, 1.0, 0.5), 1.0);
+        assert_eq!(step(1.0, 0.0, 0.1, 1.0), 0.9);
+        assert_eq!(step(0.2, 0.0, 1.0, 0.5), 0.0);
+        assert_eq!(step(0.3, 1.0, 0.1, 0.0), 1.0);
+        assert_eq!(step(0.3, 0.0, 0.1, f32::NAN), 0.0);
+        assert_eq!(step(0.3, 1.0, -1.0, 0.5), 0.3);
+    }
+}
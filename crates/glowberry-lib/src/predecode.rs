@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Decoding and scaling images on worker threads.
+//!
+//! Large images can take long enough to decode and scale that the event
+//! loop visibly stalls, freezing every output's configure with it. Still
+//! images are instead decoded and scaled to the size of each output drawn by
+//! the CPU by a small pool of worker threads, see [`spawn`], and drawn once
+//! the results arrive. [`LEAD`] before a slideshow's rotation timer fires,
+//! the image next in the queue is decoded the same way, so it is ready on
+//! its turn.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock, mpsc},
+    time::{Duration, Instant},
+};
+
+use image::DynamicImage;
+
+use crate::{blurhash, scaled_cache::ScaledCache, scaler, wallpaper};
+
+/// How long before its turn the next image is decoded.
+pub const LEAD: Duration = Duration::from_secs(5);
+
+/// Worker threads decoding and scaling images.
+const WORKERS: usize = 2;
+
+pub(crate) type Job = Box<dyn FnOnce() + Send>;
+
+/// Queue of the worker pool, started with the first job; `None` when no
+/// worker could be started, in which case jobs run where they are
+/// submitted.
+static POOL: OnceLock<Option<mpsc::Sender<Job>>> = OnceLock::new();
+
+/// An image decoded ahead of its turn.
+pub struct Predecoded {
+    pub path: PathBuf,
+    /// The image itself, unless every frame was cached
+    pub image: Option<DynamicImage>,
+    /// The image scaled to each output's physical size
+    pub frames: Vec<((u32, u32), DynamicImage)>,
+}
+
+impl std::fmt::Debug for Predecoded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Predecoded")
+            .field("path", &self.path)
+            .field("frames", &self.frames.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Take the frame scaled to `size` out of `frames`, if there is one.
+pub fn take_frame(
+    frames: &mut Vec<((u32, u32), DynamicImage)>,
+    size: (u32, u32),
+) -> Option<DynamicImage> {
+    let index = frames.iter().position(|(scaled, _)| *scaled == size)?;
+    Some(frames.swap_remove(index).1)
+}
+
+/// Decode `path` on a worker thread, unless it is already `decoded`, and
+/// scale it to each of `sizes` with `scale`, or load the frames `cache`
+/// has; `done` receives the result. Images are decoded for `bound`, see
+/// [`wallpaper::decode_within`], and those still far larger than needed to
+/// cover it are shrunk to it right after decoding. Images that fail to
+/// decode are reported with their path.
+pub fn spawn(
+    path: PathBuf,
+    decoded: Option<DynamicImage>,
+    bound: Option<(u32, u32)>,
+    mut sizes: Vec<(u32, u32)>,
+    scale: impl Fn(&DynamicImage, u32, u32) -> DynamicImage + Send + 'static,
+    cache: ScaledCache,
+    done: calloop::channel::Sender<Result<Predecoded, (PathBuf, eyre::Report)>>,
+) {
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    submit(Box::new(move || {
+        // A decoder panicking on a malformed file takes only its image with
+        // it, reported like a failed decode
+        let failed = path.clone();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            decode(path, decoded, bound, sizes, scale, &cache)
+        }))
+        .unwrap_or_else(|_| Err((failed, eyre::eyre!("decoding the image panicked"))));
+        // The wallpaper may be gone meanwhile
+        let _ = done.send(result);
+    }));
+}
+
+/// The work of [`spawn`], on a worker thread.
+fn decode(
+    path: PathBuf,
+    decoded: Option<DynamicImage>,
+    bound: Option<(u32, u32)>,
+    sizes: Vec<(u32, u32)>,
+    scale: impl Fn(&DynamicImage, u32, u32) -> DynamicImage,
+    cache: &ScaledCache,
+) -> Result<Predecoded, (PathBuf, eyre::Report)> {
+    let start = Instant::now();
+    let mut frames = Vec::with_capacity(sizes.len());
+    let mut missing = Vec::new();
+    for size in sizes {
+        match cache.load(&path, size) {
+            Some(frame) => frames.push((size, frame)),
+            None => missing.push(size),
+        }
+    }
+
+    let image = match decoded {
+        Some(image) => image,
+        // Every frame was cached, so the image isn't needed
+        None if missing.is_empty() && !frames.is_empty() => {
+            tracing::debug!(
+                elapsed = ?start.elapsed(),
+                "loaded cached scaled images: {}",
+                path.display()
+            );
+            return Ok(Predecoded {
+                path,
+                image: None,
+                frames,
+            });
+        }
+        None => match wallpaper::decode_within(&path, bound) {
+            Ok(image) => image,
+            Err(why) => {
+                tracing::debug!(?why, "could not decode image: {}", path.display());
+                return Err((path, why));
+            }
+        },
+    };
+    let image = match bound {
+        Some(bound) => scaler::shrink_to_cover(image, bound),
+        None => image,
+    };
+    blurhash::remember(&path, &image);
+    for (width, height) in missing {
+        let frame = scale(&image, width, height);
+        cache.store(&path, &frame);
+        frames.push(((width, height), frame));
+    }
+    tracing::debug!(
+        elapsed = ?start.elapsed(),
+        "decoded image on a worker: {}",
+        path.display()
+    );
+    Ok(Predecoded {
+        path,
+        image: Some(image),
+        frames,
+    })
+}
+
+/// Run `job` on the worker pool, starting it if needed.
+pub(crate) fn submit(job: Job) {
+    let pool = POOL.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let mut started = 0;
+        for index in 0..WORKERS {
+            let rx = rx.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("glowberry-decode-{index}"))
+                .spawn(move || {
+                    loop {
+                        let job = match rx.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok(job) = job else {
+                            return;
+                        };
+                        job();
+                    }
+                });
+            match spawned {
+                Ok(_) => started += 1,
+                Err(why) => tracing::warn!(?why, "failed to start an image decoding worker"),
+            }
+        }
+        (started > 0).then_some(tx)
+    });
+
+    match pool {
+        Some(tx) => {
+            let _ = tx.send(job);
+        }
+        None => {
+            tracing::warn!("no worker to decode images on, decoding on the event loop");
+            job();
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Camera RAW photos as wallpapers.
+//!
+//! With the `raw` feature, CR2, NEF, ARW, and DNG files are decoded and
+//! developed with imagepipe: demosaicing, the camera's white balance, a base
+//! tone curve, and sRGB gamma. Developing takes seconds, so the result is
+//! kept as a PNG in the cache directory, named after the RAW file's path,
+//! size, and modification time so that an edited file is developed again,
+//! and after the size it was developed to fit in for the outputs.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use eyre::eyre;
+use image::DynamicImage;
+
+use crate::scaler;
+
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("glowberry")
+        .join("raw")
+}
+
+/// Whether `path` has the extension of a RAW format developed with the
+/// `raw` feature.
+pub(crate) fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["cr2", "nef", "arw", "dng"]
+                .iter()
+                .any(|raw| ext.eq_ignore_ascii_case(raw))
+        })
+}
+
+/// Decodes a RAW photo, from the cache when it was developed before. With a
+/// `bound`, it is developed at most [`scaler::OVERSIZE`] times that size
+/// each way, which still covers it unless the aspect ratios differ as much.
+pub(crate) fn decode_raw(path: &Path, bound: Option<(u32, u32)>) -> eyre::Result<DynamicImage> {
+    let metadata =
+        std::fs::metadata(path).map_err(|why| eyre!("failed to open raw image file: {why}"))?;
+    let max = bound.map_or((0, 0), |(width, height)| {
+        let oversize = scaler::OVERSIZE as usize;
+        (width as usize * oversize, height as usize * oversize)
+    });
+    let cached = cache_dir().join(cache_name(
+        path,
+        metadata.len(),
+        metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        max,
+    ));
+
+    if let Ok(image) = image::open(&cached) {
+        return Ok(image);
+    }
+
+    let image = develop(path, max)?;
+    if let Err(why) = std::fs::create_dir_all(cache_dir())
+        .map_err(image::ImageError::IoError)
+        .and_then(|()| image.save(&cached))
+    {
+        tracing::warn!(
+            ?why,
+            "could not cache developed raw image: {}",
+            path.display()
+        );
+    }
+    Ok(image)
+}
+
+/// Develop the RAW file at `path` to fit in `max`, `0` leaving a
+/// dimension unbounded.
+#[cfg(feature = "raw")]
+fn develop(path: &Path, (max_width, max_height): (usize, usize)) -> eyre::Result<DynamicImage> {
+    let start = std::time::Instant::now();
+    let developed = imagepipe::simple_decode_8bit(path, max_width, max_height)
+        .map_err(|why| eyre!("failed to develop raw image: {why}"))?;
+    tracing::debug!(elapsed = ?start.elapsed(), "developed raw image: {}", path.display());
+
+    image::RgbImage::from_raw(
+        developed.width as u32,
+        developed.height as u32,
+        developed.data,
+    )
+    .map(DynamicImage::from)
+    .ok_or_else(|| eyre!("developed raw image has a truncated buffer"))
+}
+
+#[cfg(not(feature = "raw"))]
+fn develop(path: &Path, _max: (usize, usize)) -> eyre::Result<DynamicImage> {
+    Err(eyre!(
+        "{} is a camera RAW image, which needs glowberry built with the `raw` feature",
+        path.display()
+    ))
+}
+
+/// File name of the copy of the RAW file at `path` developed to fit in
+/// `max`.
+fn cache_name(path: &Path, len: u64, modified: SystemTime, max: (usize, usize)) -> String {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    len.hash(&mut hasher);
+    modified.hash(&mut hasher);
+    max.hash(&mut hasher);
+    format!("{:016x}.png", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_name, is_raw};
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn develops_again_after_the_file_changes() {
+        let path = Path::new("/photos/IMG_0042.CR2");
+        let taken = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let name = cache_name(path, 25_000_000, taken, (0, 0));
+
+        assert_eq!(name, cache_name(path, 25_000_000, taken, (0, 0)));
+        assert_ne!(name, cache_name(path, 25_000_001, taken, (0, 0)));
+        assert_ne!(
+            name,
+            cache_name(path, 25_000_000, taken + Duration::from_secs(1), (0, 0))
+        );
+        assert_ne!(
+            name,
+            cache_name(Path::new("/photos/IMG_0043.CR2"), 25_000_000, taken, (0, 0))
+        );
+        // Developed smaller for a smaller output
+        assert_ne!(name, cache_name(path, 25_000_000, taken, (3840, 2160)));
+    }
+
+    #[test]
+    fn recognizes_raw_extensions() {
+        assert!(is_raw(Path::new("/photos/IMG_0042.CR2")));
+        assert!(is_raw(Path::new("/photos/DSC_0001.nef")));
+        assert!(is_raw(Path::new("/photos/pano.dng")));
+        assert!(!is_raw(Path::new("/photos/pano.jpg")));
+    }
+}
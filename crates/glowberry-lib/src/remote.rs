@@ -0,0 +1,381 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Wallpapers downloaded from HTTP(S) URLs.
+//!
+//! A `Path` source holding a URL is downloaded into the cache directory, and
+//! shown from there. A listener, see [`crate::listener`], revalidates it with the server's
+//! `ETag` every `remote-refresh-minutes`, and the wallpaper is redrawn when
+//! the server has a new image. While offline, the cached copy is shown.
+//! Once the cache takes more than [`CACHE_LIMIT`], the images used least
+//! recently are deleted.
+//!
+//! Downloading is only available with the `remote` cargo feature; without it
+//! a URL shows its cached copy, if any.
+
+use std::{
+    fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use sctk::reexports::calloop;
+
+use crate::{image_formats, listener::Listener};
+
+/// Bytes the downloaded images may take on disk.
+pub const CACHE_LIMIT: u64 = 512 * 1024 * 1024;
+
+/// Outcome of a download, sent to the event loop.
+#[derive(Debug)]
+pub(crate) enum RemoteEvent {
+    /// A new image was downloaded to the path.
+    Updated(PathBuf),
+    /// The server couldn't be reached, or refused the request.
+    Failed(String),
+}
+
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("glowberry")
+        .join("remote")
+}
+
+/// Whether a `Path` source is an HTTP(S) URL rather than a file.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("https://") || path.starts_with("http://"))
+}
+
+/// Where the image at `url` is cached. Named after a hash of the URL, keeping
+/// its extension when it is one of a supported format, since some formats are
+/// recognized by extension only.
+pub(crate) fn cached_path(url: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| image_formats::is_supported(Path::new(&format!("image.{ext}"))))
+        .unwrap_or("img");
+
+    cache_dir().join(format!("{:016x}.{extension}", hasher.finish()))
+}
+
+/// Path of a downloaded copy of the image at `url`, downloading it unless
+/// it was before.
+///
+/// # Errors
+///
+/// Fails if the image isn't cached and can't be downloaded.
+pub fn download(url: &str) -> eyre::Result<PathBuf> {
+    let cached = cached_path(url);
+    if cached.is_file() {
+        touch(&cached);
+        return Ok(cached);
+    }
+
+    #[cfg(feature = "remote")]
+    {
+        fetch::fetch(&fetch::agent(), url, &cached)?;
+        Ok(cached)
+    }
+
+    #[cfg(not(feature = "remote"))]
+    Err(eyre::eyre!(
+        "downloading images needs glowberry built with the `remote` feature"
+    ))
+}
+
+/// Mark the cached copy at `cached` as used, so it is kept over images not
+/// used for longer when pruning.
+fn touch(cached: &Path) {
+    let _ = File::options()
+        .write(true)
+        .open(cached)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+}
+
+/// Delete the least recently used images in the cache, with their `ETag`s,
+/// until it takes at most [`CACHE_LIMIT`], keeping `kept`.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn prune(kept: &Path) {
+    prune_dir(&cache_dir(), CACHE_LIMIT, kept);
+}
+
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn prune_dir(dir: &Path, limit: u64, kept: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut images: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext == "etag" || ext == "part")
+            {
+                return None;
+            }
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), path))
+        })
+        .collect();
+
+    let mut total: u64 = images.iter().map(|(_, len, _)| len).sum();
+    if total <= limit {
+        return;
+    }
+    images.sort_unstable();
+    for (_, len, path) in images {
+        if total <= limit {
+            break;
+        }
+        if path == kept {
+            continue;
+        }
+        if fs::remove_file(&path).is_ok() {
+            let _ = fs::remove_file(etag_path(&path));
+            total -= len;
+            tracing::debug!("pruned cached remote image {}", path.display());
+        }
+    }
+}
+
+/// File holding the `ETag` of the cached copy at `cached`.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn etag_path(cached: &Path) -> PathBuf {
+    cached.with_extension("etag")
+}
+
+/// Running download of a remote wallpaper. Revalidation stops when this is
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct RemoteImage {
+    refresh: Arc<AtomicU64>,
+    _listener: Option<Listener>,
+}
+
+impl RemoteImage {
+    /// Download `url` now, and again every `refresh` when it changed,
+    /// reporting each outcome to `tx`.
+    pub(crate) fn start(
+        url: String,
+        refresh: Duration,
+        tx: calloop::channel::Sender<RemoteEvent>,
+    ) -> Self {
+        let refresh = Arc::new(AtomicU64::new(refresh.as_secs()));
+
+        #[cfg(feature = "remote")]
+        let listener = {
+            let task_refresh = refresh.clone();
+            Listener::spawn("remote", move |running| async move {
+                fetch::run(&url, &task_refresh, &running, &tx).await;
+            })
+        };
+
+        #[cfg(not(feature = "remote"))]
+        let listener = {
+            let cached = cached_path(&url).is_file();
+            tracing::warn!(
+                url,
+                cached,
+                "URL wallpapers need glowberry built with the `remote` feature"
+            );
+            if !cached {
+                let _ = tx.send(RemoteEvent::Failed(String::from(
+                    "Downloading wallpapers needs glowberry built with the `remote` feature",
+                )));
+            }
+            None
+        };
+
+        Self {
+            refresh,
+            _listener: listener,
+        }
+    }
+
+    /// Change how often the image is revalidated, from the next check on.
+    pub(crate) fn set_refresh(&self, refresh: Duration) {
+        self.refresh.store(refresh.as_secs(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "remote")]
+pub(crate) mod fetch {
+    use super::{RemoteEvent, cached_path, etag_path, prune, touch};
+    use crate::listener::Running;
+    use sctk::reexports::calloop;
+    use std::{
+        path::Path,
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+
+    /// Delay before retrying after a failed download.
+    pub(crate) const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+    /// Client for downloads, which reports HTTP errors as statuses so that
+    /// `304 Not Modified` can be told apart.
+    pub(crate) fn agent() -> ureq::Agent {
+        ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(60)))
+            .user_agent(concat!("glowberry/", env!("CARGO_PKG_VERSION")))
+            .http_status_as_error(false)
+            .build()
+            .into()
+    }
+
+    pub(super) async fn run(
+        url: &str,
+        refresh: &AtomicU64,
+        running: &Running,
+        tx: &calloop::channel::Sender<RemoteEvent>,
+    ) {
+        let agent = agent();
+        let cached = cached_path(url);
+
+        loop {
+            let interval = Duration::from_secs(refresh.load(Ordering::Relaxed).max(60));
+            let wait = match fetch(&agent, url, &cached) {
+                Ok(true) => {
+                    tracing::debug!(url, "downloaded remote wallpaper");
+                    if !running.send(tx, RemoteEvent::Updated(cached.clone())) {
+                        break;
+                    }
+                    interval
+                }
+                Ok(false) => {
+                    tracing::debug!(url, "remote wallpaper unchanged");
+                    interval
+                }
+                Err(why) => {
+                    tracing::warn!(%why, url, "failed to download wallpaper, keeping cached copy");
+                    if !running.send(tx, RemoteEvent::Failed(why.to_string())) {
+                        break;
+                    }
+                    RETRY_INTERVAL.min(interval)
+                }
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Download `url` to `cached` unless the server says the cached copy is
+    /// current. Returns whether it was replaced.
+    pub(crate) fn fetch(agent: &ureq::Agent, url: &str, cached: &Path) -> eyre::Result<bool> {
+        let etag_path = etag_path(cached);
+        let mut request = agent.get(url);
+        if cached.is_file()
+            && let Ok(etag) = std::fs::read_to_string(&etag_path)
+        {
+            request = request.header("If-None-Match", etag.trim());
+        }
+
+        let mut response = request.call()?;
+        match response.status().as_u16() {
+            304 => {
+                touch(cached);
+                return Ok(false);
+            }
+            200..=299 => {}
+            status => eyre::bail!("server answered {status}"),
+        }
+
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(String::from);
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(256 * 1024 * 1024)
+            .read_to_vec()?;
+
+        if let Some(parent) = cached.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Replaced with a rename, so the image is never seen half written
+        let partial = cached.with_extension("part");
+        std::fs::write(&partial, body)?;
+        std::fs::rename(&partial, cached)?;
+        match etag {
+            Some(etag) => std::fs::write(&etag_path, etag)?,
+            None => {
+                let _ = std::fs::remove_file(&etag_path);
+            }
+        }
+        prune(cached);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cached_path, etag_path, is_url, prune_dir};
+    use std::{
+        fs::{self, File},
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn recognizes_urls() {
+        assert!(is_url(Path::new("https://example.com/sky.jpg")));
+        assert!(is_url(Path::new("http://example.com/sky.jpg")));
+        assert!(!is_url(Path::new("/home/me/Pictures/sky.jpg")));
+        assert!(!is_url(Path::new("ftp://example.com/sky.jpg")));
+    }
+
+    #[test]
+    fn caches_each_url_separately() {
+        let sky = cached_path("https://example.com/sky.jpg?w=3840");
+        assert_eq!(sky.extension().unwrap(), "jpg");
+        assert_eq!(sky, cached_path("https://example.com/sky.jpg?w=3840"));
+        assert_ne!(sky, cached_path("https://example.com/sky.jpg?w=1920"));
+        assert_eq!(
+            cached_path("https://example.com/today")
+                .extension()
+                .unwrap(),
+            "img"
+        );
+    }
+
+    #[test]
+    fn prunes_the_images_used_least_recently() {
+        let dir = std::env::temp_dir().join(format!("glowberry-remote-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for (index, name) in ["old.jpg", "kept.jpg", "new.jpg"].into_iter().enumerate() {
+            let path = dir.join(name);
+            fs::write(&path, [0; 100]).unwrap();
+            fs::write(etag_path(&path), "\"tag\"").unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(epoch + Duration::from_secs(index as u64)))
+                .unwrap();
+        }
+
+        // The image just downloaded stays even when it is older
+        prune_dir(&dir, 150, &dir.join("kept.jpg"));
+        assert!(!dir.join("old.jpg").exists());
+        assert!(!dir.join("old.etag").exists());
+        assert!(dir.join("kept.jpg").exists());
+        assert!(!dir.join("new.jpg").exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Scaled wallpapers cached on disk, so later logins and slideshow repeats
+//! skip scaling, and decoding too when every output's frame is cached.
+//!
+//! Decode workers save each frame they scale as a PNG under
+//! `$XDG_CACHE_HOME/glowberry/scaled/`, named after the image's path, size
+//! and modification time, the frame's size, and how the entry scales it.
+//! Reading a frame marks it as recently used; after saving one, the least
+//! recently used frames are deleted until the cache fits its limit.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use image::{
+    DynamicImage,
+    codecs::png::{CompressionType, FilterType, PngEncoder},
+};
+
+/// How a wallpaper caches the frames it scales.
+#[derive(Debug, Clone)]
+pub struct ScaledCache {
+    /// How the entry scales its images, as part of each frame's name
+    pub scaling: String,
+    /// Bytes the whole cache may take on disk
+    pub limit: u64,
+}
+
+fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("glowberry")
+        .join("scaled")
+}
+
+/// Where the frame of the image at `path` scaled to `size` is cached,
+/// changing with the file.
+fn cache_path(path: &Path, size: (u32, u32), scaling: &str) -> Option<PathBuf> {
+    let metadata = fs::metadata(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified().ok()?.hash(&mut hasher);
+    size.hash(&mut hasher);
+    scaling.hash(&mut hasher);
+    Some(cache_dir().join(format!("{:016x}.png", hasher.finish())))
+}
+
+impl ScaledCache {
+    /// The cached frame of the image at `path` scaled to `size`.
+    pub fn load(&self, path: &Path, size: (u32, u32)) -> Option<DynamicImage> {
+        if self.limit == 0 {
+            return None;
+        }
+        let cached = cache_path(path, size, &self.scaling)?;
+        let frame = image::open(&cached).ok()?;
+        if frame.width() != size.0 || frame.height() != size.1 {
+            return None;
+        }
+        // Kept over frames not shown for longer when pruning
+        let _ = File::options()
+            .write(true)
+            .open(&cached)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(frame)
+    }
+
+    /// Cache `frame`, the image at `path` scaled to its size, then prune the
+    /// cache to its limit.
+    pub fn store(&self, path: &Path, frame: &DynamicImage) {
+        if self.limit == 0 {
+            return;
+        }
+        let Some(cached) = cache_path(path, (frame.width(), frame.height()), &self.scaling) else {
+            return;
+        };
+        if let Err(why) = save(&cached, frame) {
+            tracing::debug!(?why, "could not cache scaled image of {}", path.display());
+            return;
+        }
+        prune(self.limit);
+    }
+}
+
+/// Save `frame` as a PNG at `cached`, 16 bits deep when it is deeper than 8,
+/// writing it aside first so readers never see part of it.
+fn save(cached: &Path, frame: &DynamicImage) -> eyre::Result<()> {
+    let color = frame.color();
+    let deep = color.bytes_per_pixel() > color.channel_count();
+    let frame = match (deep, color.has_alpha()) {
+        (false, false) => DynamicImage::ImageRgb8(frame.to_rgb8()),
+        (false, true) => DynamicImage::ImageRgba8(frame.to_rgba8()),
+        (true, false) => DynamicImage::ImageRgb16(frame.to_rgb16()),
+        (true, true) => DynamicImage::ImageRgba16(frame.to_rgba16()),
+    };
+
+    fs::create_dir_all(cache_dir())?;
+    let partial = cached.with_extension("partial");
+    let encoder = PngEncoder::new_with_quality(
+        BufWriter::new(File::create(&partial)?),
+        CompressionType::Fast,
+        FilterType::Adaptive,
+    );
+    frame.write_with_encoder(encoder)?;
+    fs::rename(&partial, cached)?;
+    Ok(())
+}
+
+/// Delete the least recently used frames until the cache takes at most
+/// `limit` bytes.
+fn prune(limit: u64) {
+    let Ok(entries) = fs::read_dir(cache_dir()) else {
+        return;
+    };
+    let mut frames: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+
+    let mut total: u64 = frames.iter().map(|(_, len, _)| len).sum();
+    if total <= limit {
+        return;
+    }
+    frames.sort_unstable();
+    for (_, len, path) in frames {
+        if total <= limit {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= len;
+            tracing::debug!("pruned cached scaled image {}", path.display());
+        }
+    }
+}
@@ -1,19 +1,70 @@
 // SPDX-License-Identifier: MPL-2.0
 
-//! Background scaling methods such as fit, stretch, and zoom.
+//! Background scaling methods such as fit, stretch, zoom, tile, and center.
+//!
+//! [`scale`] fits an image to a target size with a [`Strategy`]. The daemon
+//! uses it for static wallpapers and shader backgrounds, the settings app for
+//! its thumbnails, and other crates (lock screens, for one) can use it to
+//! match what GlowBerry draws.
+//!
+//! With the `rayon` feature enabled, resizing and the row copies that follow
+//! it are spread across all cores, which matters for 4K+ sources.
 
+use glowberry_config::ScalingMode;
 use image::imageops::FilterType;
-use image::{DynamicImage, Pixel};
+use image::{DynamicImage, Rgb32FImage};
 
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// How an image is fitted to the target size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Cover the target, cropping the overflow evenly from both sides.
+    Zoom,
+    /// Fit inside the target, filling the rest with an RGB color (0.0 to 1.0).
+    Fit([f32; 3]),
+    /// Fill the target exactly, ignoring the aspect ratio.
+    Stretch,
+    /// Repeat at its own size from the top-left corner of the target.
+    Tile,
+    /// Center at its own size, cropping the overflow evenly from both sides
+    /// and filling the rest with an RGB color (0.0 to 1.0).
+    Center([f32; 3]),
+}
+
+impl From<&ScalingMode> for Strategy {
+    fn from(mode: &ScalingMode) -> Self {
+        match mode {
+            // Spanning needs the output layout, see [`span`]
+            ScalingMode::Zoom | ScalingMode::Span => Self::Zoom,
+            ScalingMode::Fit(color) => Self::Fit(*color),
+            ScalingMode::Stretch => Self::Stretch,
+            ScalingMode::Tile => Self::Tile,
+            ScalingMode::Center(color) => Self::Center(*color),
+        }
+    }
+}
+
+/// Scale `img` to `target` (width, height) with `strategy`.
+pub fn scale(img: &DynamicImage, target: (u32, u32), strategy: Strategy) -> DynamicImage {
+    let (width, height) = target;
+    match strategy {
+        Strategy::Zoom => zoom(img, width, height),
+        Strategy::Fit(color) => fit(img, &color, width, height),
+        Strategy::Stretch => stretch(img, width, height),
+        Strategy::Tile => tile(img, width, height),
+        Strategy::Center(color) => center(img, &color, width, height),
+    }
+}
+
+/// Fit `img` inside the layer, letterboxed with `color`.
 pub fn fit(
     img: &image::DynamicImage,
     color: &[f32; 3],
     layer_width: u32,
     layer_height: u32,
 ) -> image::DynamicImage {
-    let mut filled_image =
-        image::ImageBuffer::from_pixel(layer_width, layer_height, *image::Rgb::from_slice(color));
-
     let (w, h) = (img.width(), img.height());
 
     let ratio = (layer_width as f64 / w as f64).min(layer_height as f64 / h as f64);
@@ -23,18 +74,12 @@ pub fn fit(
         (h as f64 * ratio).round() as u32,
     );
 
-    let resized_image = resize(img, new_width, new_height);
-
-    image::imageops::replace(
-        &mut filled_image,
-        &resized_image.to_rgb32f(),
-        ((layer_width - new_width) / 2).into(),
-        ((layer_height - new_height) / 2).into(),
-    );
+    let resized_image = resize(img, new_width, new_height).into_rgb32f();
 
-    DynamicImage::from(filled_image)
+    DynamicImage::from(letterbox(&resized_image, color, layer_width, layer_height))
 }
 
+/// Resize `img` to the layer size, ignoring its aspect ratio.
 pub fn stretch(
     img: &image::DynamicImage,
     layer_width: u32,
@@ -43,7 +88,49 @@ pub fn stretch(
     resize(img, layer_width, layer_height)
 }
 
+/// Repeat `img` at its own size across the layer, from its top-left corner.
+pub fn tile(img: &image::DynamicImage, layer_width: u32, layer_height: u32) -> image::DynamicImage {
+    let img = img.to_rgb32f();
+    let (w, h) = (img.width(), img.height());
+    if w == 0 || h == 0 {
+        return DynamicImage::from(Rgb32FImage::new(layer_width, layer_height));
+    }
+    DynamicImage::from(Rgb32FImage::from_fn(layer_width, layer_height, |x, y| {
+        *img.get_pixel(x % w, y % h)
+    }))
+}
+
+/// Center `img` at its own size in the layer, cropped to it and
+/// letterboxed with `color`.
+pub fn center(
+    img: &image::DynamicImage,
+    color: &[f32; 3],
+    layer_width: u32,
+    layer_height: u32,
+) -> image::DynamicImage {
+    let (w, h) = (img.width(), img.height());
+    let (crop_w, crop_h) = (w.min(layer_width), h.min(layer_height));
+    let cropped = img
+        .crop_imm((w - crop_w) / 2, (h - crop_h) / 2, crop_w, crop_h)
+        .into_rgb32f();
+
+    DynamicImage::from(letterbox(&cropped, color, layer_width, layer_height))
+}
+
+/// Resize `img` to cover the layer and crop it to the layer size.
 pub fn zoom(img: &image::DynamicImage, layer_width: u32, layer_height: u32) -> image::DynamicImage {
+    zoom_at(img, layer_width, layer_height, (0.5, 0.5))
+}
+
+/// Resize `img` to cover the layer and crop it to the layer size around
+/// `focus`, a point of the image in fractions of its size, keeping as close
+/// to the middle of the layer as the image's edges allow.
+pub fn zoom_at(
+    img: &image::DynamicImage,
+    layer_width: u32,
+    layer_height: u32,
+    focus: (f32, f32),
+) -> image::DynamicImage {
     let (w, h) = (img.width(), img.height());
 
     let ratio = (layer_width as f64 / w as f64).max(layer_height as f64 / h as f64);
@@ -55,10 +142,15 @@ pub fn zoom(img: &image::DynamicImage, layer_width: u32, layer_height: u32) -> i
 
     let mut new_image = resize(img, new_width, new_height);
 
+    let offset = |focus: f32, new: u32, layer: u32| {
+        let max = new.saturating_sub(layer);
+        let centered = f64::from(focus) * f64::from(new) - f64::from(layer) / 2.0;
+        (centered.round().max(0.0) as u32).min(max)
+    };
     image::imageops::crop(
         &mut new_image,
-        (new_width - layer_width) / 2,
-        (new_height - layer_height) / 2,
+        offset(focus.0, new_width, layer_width),
+        offset(focus.1, new_height, layer_height),
         layer_width,
         layer_height,
     )
@@ -66,7 +158,146 @@ pub fn zoom(img: &image::DynamicImage, layer_width: u32, layer_height: u32) -> i
     .into()
 }
 
-fn resize(img: &image::DynamicImage, new_width: u32, new_height: u32) -> image::DynamicImage {
+/// How many times the size it covers an image must exceed before
+/// [`shrink_to_cover`] shrinks it.
+pub(crate) const OVERSIZE: f64 = 2.0;
+
+/// Shrink `img` to the smallest size covering `target` (width, height),
+/// keeping its aspect ratio, when it is more than [`OVERSIZE`] times larger,
+/// so huge photos aren't kept at full resolution. Every strategy scales the
+/// result to `target` as it would the original.
+pub fn shrink_to_cover(img: DynamicImage, target: (u32, u32)) -> DynamicImage {
+    let (w, h) = (img.width(), img.height());
+    if w == 0 || h == 0 {
+        return img;
+    }
+    let ratio = (f64::from(target.0) / f64::from(w)).max(f64::from(target.1) / f64::from(h));
+    if ratio * OVERSIZE > 1.0 {
+        return img;
+    }
+
+    let (new_width, new_height) = (
+        (f64::from(w) * ratio).round().max(1.0) as u32,
+        (f64::from(h) * ratio).round().max(1.0) as u32,
+    );
+    resize(&img, new_width, new_height)
+}
+
+/// The region of `img` at `x`, `y` of `width`x`height`, all in fractions
+/// of its size, clamped to the image and at least a pixel in size.
+pub fn region(img: &DynamicImage, x: f32, y: f32, width: f32, height: f32) -> DynamicImage {
+    let edge = |fraction: f32, size: u32| {
+        (f64::from(fraction.clamp(0.0, 1.0)) * f64::from(size)).round() as u32
+    };
+    let left = edge(x, img.width()).min(img.width().saturating_sub(1));
+    let top = edge(y, img.height()).min(img.height().saturating_sub(1));
+    let right = edge(x + width, img.width()).max(left + 1);
+    let bottom = edge(y + height, img.height()).max(top + 1);
+    img.crop_imm(left, top, right - left, bottom - top)
+}
+
+/// Rectangle in the compositor's logical output layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    /// Smallest rectangle holding both.
+    #[must_use]
+    pub fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// The part of `img` under `output` once `img` is zoomed to cover `layout`,
+/// the bounding box of all spanned outputs, resized to `target`.
+pub fn span(img: &DynamicImage, layout: Rect, output: Rect, target: (u32, u32)) -> DynamicImage {
+    let (w, h) = (f64::from(img.width()), f64::from(img.height()));
+    let (layout_w, layout_h) = (
+        f64::from(layout.width.max(1)),
+        f64::from(layout.height.max(1)),
+    );
+    let ratio = (layout_w / w).max(layout_h / h);
+
+    // Image pixels cropped from each side to cover the layout, as in `zoom`
+    let crop_x = (w - layout_w / ratio) / 2.0;
+    let crop_y = (h - layout_h / ratio) / 2.0;
+    let x = crop_x + f64::from(output.x - layout.x) / ratio;
+    let y = crop_y + f64::from(output.y - layout.y) / ratio;
+    let slice_w = (f64::from(output.width) / ratio).round().max(1.0);
+    let slice_h = (f64::from(output.height) / ratio).round().max(1.0);
+
+    let x = (x.round().max(0.0) as u32).min(img.width().saturating_sub(1));
+    let y = (y.round().max(0.0) as u32).min(img.height().saturating_sub(1));
+    let slice = img.crop_imm(
+        x,
+        y,
+        (slice_w as u32).min(img.width() - x),
+        (slice_h as u32).min(img.height() - y),
+    );
+    resize(&slice, target.0, target.1)
+}
+
+/// Center `img` on a `width`x`height` canvas filled with `color`.
+///
+/// Each output row is written independently, so rows are filled in parallel
+/// when the `rayon` feature is enabled.
+fn letterbox(img: &Rgb32FImage, color: &[f32; 3], width: u32, height: u32) -> Rgb32FImage {
+    let (img_w, img_h) = (img.width().min(width), img.height().min(height));
+    let x_off = ((width - img_w) / 2) as usize;
+    let y_off = ((height - img_h) / 2) as usize;
+
+    let row_len = width as usize * 3;
+    let src_row_len = img.width() as usize * 3;
+    let copy_len = img_w as usize * 3;
+    let src = img.as_raw();
+
+    let mut data = vec![0f32; row_len * height as usize];
+
+    let fill_row = |(y, row): (usize, &mut [f32])| {
+        for pixel in row.chunks_exact_mut(3) {
+            pixel.copy_from_slice(color);
+        }
+
+        if y >= y_off && y < y_off + img_h as usize {
+            let src_start = (y - y_off) * src_row_len;
+            let dst_start = x_off * 3;
+            row[dst_start..dst_start + copy_len]
+                .copy_from_slice(&src[src_start..src_start + copy_len]);
+        }
+    };
+
+    #[cfg(feature = "rayon")]
+    data.par_chunks_exact_mut(row_len)
+        .enumerate()
+        .for_each(fill_row);
+
+    #[cfg(not(feature = "rayon"))]
+    data.chunks_exact_mut(row_len)
+        .enumerate()
+        .for_each(fill_row);
+
+    Rgb32FImage::from_raw(width, height, data).expect("letterbox buffer matches dimensions")
+}
+
+pub(crate) fn resize(
+    img: &image::DynamicImage,
+    new_width: u32,
+    new_height: u32,
+) -> image::DynamicImage {
+    // With the `rayon` feature, `fast_image_resize` splits the convolution
+    // passes into row bands and runs them on the global rayon pool.
     let mut resizer = fast_image_resize::Resizer::new();
     let options = fast_image_resize::ResizeOptions {
         algorithm: fast_image_resize::ResizeAlg::Convolution(
@@ -82,3 +313,120 @@ fn resize(img: &image::DynamicImage, new_width: u32, new_height: u32) -> image::
     }
     new_image
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_strategy_produces_the_target_size() {
+        let img = DynamicImage::from(Rgb32FImage::from_pixel(40, 10, image::Rgb([1.0; 3])));
+
+        for strategy in [
+            Strategy::Zoom,
+            Strategy::Fit([0.0; 3]),
+            Strategy::Stretch,
+            Strategy::Tile,
+            Strategy::Center([0.0; 3]),
+        ] {
+            let out = scale(&img, (16, 9), strategy);
+            assert_eq!((out.width(), out.height()), (16, 9), "{strategy:?}");
+        }
+        assert_eq!(Strategy::from(&ScalingMode::Zoom), Strategy::Zoom);
+    }
+
+    #[test]
+    fn tiles_and_centers_at_the_image_size() {
+        // White pixel in the middle of a black 3x3 image
+        let img = DynamicImage::from(Rgb32FImage::from_fn(3, 3, |x, y| {
+            image::Rgb([if x == 1 && y == 1 { 1.0 } else { 0.0 }; 3])
+        }));
+
+        let tiled = tile(&img, 7, 4).into_rgb32f();
+        assert_eq!(tiled.get_pixel(1, 1).0, [1.0; 3]);
+        assert_eq!(tiled.get_pixel(4, 1).0, [1.0; 3]);
+        assert_eq!(tiled.get_pixel(3, 3).0, [0.0; 3]);
+
+        let centered = center(&img, &[0.5; 3], 5, 5).into_rgb32f();
+        assert_eq!(centered.get_pixel(0, 0).0, [0.5; 3]);
+        assert_eq!(centered.get_pixel(2, 2).0, [1.0; 3]);
+        assert_eq!(centered.get_pixel(1, 1).0, [0.0; 3]);
+
+        // Larger images are cropped around their middle
+        let cropped = center(&img, &[0.5; 3], 1, 1).into_rgb32f();
+        assert_eq!(cropped.get_pixel(0, 0).0, [1.0; 3]);
+    }
+
+    #[test]
+    fn crops_regions_and_focus_points() {
+        // Black left half, white right half
+        let img = DynamicImage::from(Rgb32FImage::from_fn(20, 10, |x, _| {
+            image::Rgb([if x < 10 { 0.0 } else { 1.0 }; 3])
+        }));
+
+        let right = region(&img, 0.5, 0.0, 0.5, 1.0);
+        assert_eq!((right.width(), right.height()), (10, 10));
+        assert_eq!(right.into_rgb32f().get_pixel(0, 0).0, [1.0; 3]);
+        let clamped = region(&img, 0.9, 0.5, 2.0, f32::NAN);
+        assert_eq!((clamped.width(), clamped.height()), (2, 1));
+
+        // Zoomed to 8x4, the left or right half is kept
+        let left = zoom_at(&img, 4, 4, (0.0, 0.5)).into_rgb32f();
+        assert!(left.get_pixel(0, 2).0[0] < 1e-3);
+        let right = zoom_at(&img, 4, 4, (1.0, 0.5)).into_rgb32f();
+        assert!((right.get_pixel(3, 2).0[0] - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn shrinks_only_oversized_images() {
+        let img = DynamicImage::from(Rgb32FImage::from_pixel(400, 100, image::Rgb([1.0; 3])));
+        let shrunk = shrink_to_cover(img, (16, 9));
+        assert_eq!((shrunk.width(), shrunk.height()), (36, 9));
+
+        let kept = shrink_to_cover(shrunk, (20, 9));
+        assert_eq!((kept.width(), kept.height()), (36, 9));
+    }
+
+    #[test]
+    fn span_shows_each_output_its_slice() {
+        // Black left half, white right half
+        let img = DynamicImage::from(Rgb32FImage::from_fn(20, 10, |x, _| {
+            image::Rgb([if x < 10 { 0.0 } else { 1.0 }; 3])
+        }));
+        let left = Rect {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+        };
+        let right = Rect { x: 100, ..left };
+        let layout = left.union(right);
+        assert_eq!((layout.width, layout.height), (200, 100));
+
+        let shade = |output| {
+            let out = span(&img, layout, output, (8, 8)).into_rgb32f();
+            assert_eq!(out.dimensions(), (8, 8));
+            out.get_pixel(4, 4).0[0]
+        };
+        assert!(shade(left) < 1e-3);
+        assert!((shade(right) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fit_centers_image_on_background_color() {
+        let img = DynamicImage::from(Rgb32FImage::from_pixel(10, 10, image::Rgb([1.0; 3])));
+        let color = [0.25, 0.5, 0.75];
+
+        let out = fit(&img, &color, 30, 10).into_rgb32f();
+
+        assert_eq!(out.dimensions(), (30, 10));
+        assert_eq!(out.get_pixel(0, 5).0, color);
+        assert_eq!(out.get_pixel(29, 5).0, color);
+        assert!(
+            out.get_pixel(15, 5)
+                .0
+                .iter()
+                .all(|c| (c - 1.0).abs() < 1e-3)
+        );
+    }
+}
@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Screen-share detection.
+//!
+//! Screen-cast portal sessions publish their frames as a PipeWire video
+//! source node. A dedicated thread watches the PipeWire registry for such
+//! nodes and reports when the first one appears and the last one goes away.
+//! Cameras are video sources too, but are backed by a device, so nodes with
+//! a `device.id` are ignored.
+//!
+//! Detection is only available with the `screen-share` cargo feature; without
+//! it [`ScreenShareMonitor::start`] returns `None` and the screen is never
+//! considered shared.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Running screen-share detection. Stops when dropped.
+pub struct ScreenShareMonitor {
+    active: Arc<AtomicBool>,
+    #[cfg(feature = "screen-share")]
+    quit_tx: Option<pipewire::channel::Sender<()>>,
+}
+
+impl std::fmt::Debug for ScreenShareMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScreenShareMonitor")
+            .field("active", &self.is_active())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScreenShareMonitor {
+    /// Start watching for screen-share sessions; `notify` receives `true`
+    /// when sharing starts and `false` when it stops.
+    ///
+    /// Returns `None` if the `screen-share` feature is disabled or PipeWire
+    /// is unavailable.
+    #[cfg_attr(not(feature = "screen-share"), allow(unused_variables))]
+    pub fn start(notify: calloop::channel::Sender<bool>) -> Option<Self> {
+        #[cfg(feature = "screen-share")]
+        {
+            registry::start(notify)
+        }
+
+        #[cfg(not(feature = "screen-share"))]
+        {
+            tracing::warn!(
+                "Pausing rotation while sharing requested, but built without the `screen-share` feature"
+            );
+            None
+        }
+    }
+
+    /// Whether the screen is currently being shared.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ScreenShareMonitor {
+    fn drop(&mut self) {
+        #[cfg(feature = "screen-share")]
+        if let Some(tx) = self.quit_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// Whether a PipeWire node with `media_class` carries a screen share.
+#[cfg_attr(not(feature = "screen-share"), allow(dead_code))]
+fn is_screen_share(media_class: Option<&str>, has_device: bool) -> bool {
+    !has_device && matches!(media_class, Some("Video/Source" | "Stream/Output/Video"))
+}
+
+#[cfg(feature = "screen-share")]
+mod registry {
+    use super::{ScreenShareMonitor, is_screen_share};
+    use pipewire as pw;
+    use pw::types::ObjectType;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    pub(super) fn start(notify: calloop::channel::Sender<bool>) -> Option<ScreenShareMonitor> {
+        let active = Arc::new(AtomicBool::new(false));
+        let (quit_tx, quit_rx) = pw::channel::channel::<()>();
+        let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel::<bool>(1);
+
+        let thread_active = active.clone();
+        std::thread::Builder::new()
+            .name("glowberry-screen-share".into())
+            .spawn(move || {
+                if let Err(err) = run(thread_active, notify, quit_rx, &ready_tx) {
+                    tracing::error!(?err, "PipeWire screen-share detection failed");
+                    let _ = ready_tx.send(false);
+                }
+            })
+            .ok()?;
+
+        if !ready_rx.recv().unwrap_or(false) {
+            return None;
+        }
+
+        tracing::info!("Screen-share detection started");
+
+        Some(ScreenShareMonitor {
+            active,
+            quit_tx: Some(quit_tx),
+        })
+    }
+
+    fn run(
+        active: Arc<AtomicBool>,
+        notify: calloop::channel::Sender<bool>,
+        quit_rx: pw::channel::Receiver<()>,
+        ready_tx: &std::sync::mpsc::SyncSender<bool>,
+    ) -> Result<(), pw::Error> {
+        pw::init();
+
+        let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+        let context = pw::context::ContextRc::new(&mainloop, None)?;
+        let core = context.connect_rc(None)?;
+        let registry = core.get_registry_rc()?;
+
+        let quit_loop = mainloop.clone();
+        let _quit = quit_rx.attach(mainloop.loop_(), move |()| quit_loop.quit());
+
+        let shares = Rc::new(RefCell::new(HashSet::<u32>::new()));
+        let update = Rc::new(move |shares: &HashSet<u32>| {
+            let now = !shares.is_empty();
+            if active.swap(now, Ordering::Relaxed) != now {
+                let _ = notify.send(now);
+            }
+        });
+
+        let added = shares.clone();
+        let on_added = update.clone();
+        let removed = shares;
+        let _listener = registry
+            .add_listener_local()
+            .global(move |obj| {
+                if obj.type_ != ObjectType::Node {
+                    return;
+                }
+                let Some(props) = obj.props else {
+                    return;
+                };
+                let media_class = props.get(*pw::keys::MEDIA_CLASS);
+                if is_screen_share(media_class, props.get(*pw::keys::DEVICE_ID).is_some()) {
+                    let mut shares = added.borrow_mut();
+                    shares.insert(obj.id);
+                    on_added(&shares);
+                }
+            })
+            .global_remove(move |id| {
+                let mut shares = removed.borrow_mut();
+                if shares.remove(&id) {
+                    update(&shares);
+                }
+            })
+            .register();
+
+        let _ = ready_tx.send(true);
+        mainloop.run();
+        tracing::debug!("Screen-share detection loop exited");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_deviceless_video_sources_are_shares() {
+        assert!(is_screen_share(Some("Video/Source"), false));
+        assert!(is_screen_share(Some("Stream/Output/Video"), false));
+
+        // A webcam
+        assert!(!is_screen_share(Some("Video/Source"), true));
+        assert!(!is_screen_share(Some("Audio/Source"), false));
+        assert!(!is_screen_share(None, false));
+    }
+}
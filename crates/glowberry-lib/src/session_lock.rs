@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Session lock detection.
+//!
+//! `ext-session-lock` only tells the locking client about the lock, so the
+//! lock state comes from logind instead: lock screens set the session's
+//! `LockedHint` while they are shown. A dedicated thread watches the property
+//! on the daemon's own session and reports each change.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures::StreamExt;
+use zbus::{Connection, proxy};
+
+/// logind session D-Bus proxy, for the session the daemon runs in.
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    /// Whether a lock screen is shown for the session.
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+/// Running session lock detection.
+pub struct SessionLockMonitor {
+    locked: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for SessionLockMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionLockMonitor")
+            .field("locked", &self.is_locked())
+            .finish()
+    }
+}
+
+impl SessionLockMonitor {
+    /// Start watching the session's lock state; `notify` receives `true`
+    /// when the session locks and `false` when it unlocks.
+    ///
+    /// Returns `None` if logind or the session can't be reached.
+    pub fn start(notify: calloop::channel::Sender<bool>) -> Option<Self> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .ok()?;
+
+        let locked = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = std::sync::mpsc::sync_channel::<bool>(1);
+
+        let thread_locked = locked.clone();
+        std::thread::Builder::new()
+            .name("glowberry-session-lock".into())
+            .spawn(move || {
+                rt.block_on(async {
+                    if let Err(err) = run(thread_locked, notify, &ready_tx).await {
+                        tracing::warn!(?err, "Session lock detection failed");
+                        let _ = ready_tx.send(false);
+                    }
+                });
+            })
+            .ok()?;
+
+        if !ready_rx.recv().unwrap_or(false) {
+            return None;
+        }
+
+        tracing::info!("Session lock detection started");
+
+        Some(Self { locked })
+    }
+
+    /// Whether the session is currently locked.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+async fn run(
+    locked: Arc<AtomicBool>,
+    notify: calloop::channel::Sender<bool>,
+    ready_tx: &std::sync::mpsc::SyncSender<bool>,
+) -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+    let session = SessionProxy::new(&connection).await?;
+    let mut changes = session.receive_locked_hint_changed().await;
+
+    locked.store(session.locked_hint().await?, Ordering::Relaxed);
+    let _ = ready_tx.send(true);
+
+    while let Some(change) = changes.next().await {
+        let Ok(now) = change.get().await else {
+            continue;
+        };
+        if locked.swap(now, Ordering::Relaxed) != now {
+            tracing::debug!(locked = now, "Session lock state changed");
+            if notify.send(now).is_err() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
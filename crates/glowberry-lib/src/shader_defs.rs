@@ -6,6 +6,8 @@
 //! app preview renderer, and the shader analysis module. They must be kept in
 //! sync — which is why they live in one place.
 
+use std::borrow::Cow;
+
 /// WGSL preamble prepended to user shaders (uniforms only).
 pub const WGSL_PREAMBLE: &str = r#"
 // GlowBerry live wallpaper uniforms
@@ -22,6 +24,228 @@ pub const WGSL_PREAMBLE_WITH_TEXTURE: &str = r#"
 @group(0) @binding(3) var iTextureSampler: sampler;
 "#;
 
+/// Returns true if the shader code references the `iTime` uniform, i.e. it
+/// animates over time.
+pub fn uses_time(shader_code: &str) -> bool {
+    shader_code.contains("iTime")
+}
+
+/// Declaration appended to the preamble when a shader samples `iAudio`.
+///
+/// `iAudio` is a 512x2 texture: row 0 holds the spectrum (0.0-1.0, low to high
+/// frequencies), row 1 the latest waveform (-1.0-1.0). It is not filterable, so
+/// read it with `textureLoad(iAudio, vec2<i32>(bin, row), 0).r`.
+pub const WGSL_AUDIO_DECL: &str = r#"
+@group(0) @binding(4) var iAudio: texture_2d<f32>;
+"#;
+
+/// Returns true if the shader code references the `iAudio` texture.
+pub fn uses_audio(shader_code: &str) -> bool {
+    shader_code.contains("iAudio")
+}
+
+/// Declaration appended to the preamble when a shader reads `iBattery`.
+///
+/// `iBattery` packs the power state as
+/// `vec4f(level, on_battery, lid_closed, has_battery)`: `level` is the charge
+/// from 0.0 to 1.0 (1.0 when there is no battery), the others are 0.0 or 1.0.
+pub const WGSL_BATTERY_DECL: &str = r#"
+@group(0) @binding(5) var<uniform> iBattery: vec4f;
+"#;
+
+/// Returns true if the shader code references the `iBattery` uniform.
+pub fn uses_battery(shader_code: &str) -> bool {
+    shader_code.contains("iBattery")
+}
+
+/// Declaration appended to the preamble when a shader reads `iWeather`.
+///
+/// `iWeather` is `vec4f(temperature, cloud_cover, precipitation, is_day)`:
+/// degrees Celsius, 0.0-1.0, millimetres, and 0.0 or 1.0. Without weather data
+/// it reports a mild clear day, `vec4f(15.0, 0.0, 0.0, 1.0)`.
+pub const WGSL_WEATHER_DECL: &str = r#"
+@group(0) @binding(6) var<uniform> iWeather: vec4f;
+"#;
+
+/// Returns true if the shader code references the `iWeather` uniform.
+pub fn uses_weather(shader_code: &str) -> bool {
+    shader_code.contains("iWeather")
+}
+
+/// Declaration appended to the preamble when a shader reads `iSystem`.
+///
+/// `iSystem` is `vec4f(cpu, memory, net_rx, net_tx)`: CPU load and memory in
+/// use from 0.0 to 1.0, network throughput in MiB/s. Values are sampled every
+/// few seconds and smoothed, so they suit slow, ambient reactions.
+pub const WGSL_SYSTEM_DECL: &str = r#"
+@group(0) @binding(7) var<uniform> iSystem: vec4f;
+"#;
+
+/// Returns true if the shader code references the `iSystem` uniform.
+pub fn uses_system_stats(shader_code: &str) -> bool {
+    shader_code.contains("iSystem")
+}
+
+/// Declaration appended to the preamble when a shader reads `iLocalTime`.
+///
+/// `iLocalTime` is `vec4f(year, month, day, seconds)`: the local calendar date
+/// (month and day starting at 1) and seconds since local midnight. Use it to
+/// pick dawn, day, dusk, and night palettes by wall-clock time.
+pub const WGSL_LOCAL_TIME_DECL: &str = r#"
+@group(0) @binding(8) var<uniform> iLocalTime: vec4f;
+"#;
+
+/// Returns true if the shader code references the `iLocalTime` uniform.
+pub fn uses_local_time(shader_code: &str) -> bool {
+    shader_code.contains("iLocalTime")
+}
+
+/// Declaration appended to the preamble when a shader has automated
+/// parameters (see [`crate::param_automation`]).
+///
+/// Each automated parameter reads one component of `iParams`, in header order.
+pub const WGSL_PARAMS_DECL: &str = r#"
+@group(0) @binding(9) var<uniform> iParams: array<vec4f, 4>;
+"#;
+
+/// Declaration appended to the preamble when a shader samples `iClock`.
+///
+/// `iClock` holds the local time as white text, e.g. `14:05` or `2:05 PM`,
+/// centered with its coverage in alpha and redrawn each minute. Sample it with
+/// `textureSample(iClock, iClockSampler, uv)`; `textureDimensions(iClock)`
+/// gives its aspect ratio.
+pub const WGSL_CLOCK_DECL: &str = r#"
+@group(0) @binding(10) var iClock: texture_2d<f32>;
+@group(0) @binding(11) var iClockSampler: sampler;
+"#;
+
+/// Returns true if the shader code references the `iClock` texture.
+pub fn uses_clock(shader_code: &str) -> bool {
+    shader_code.contains("iClock")
+}
+
+/// Declaration appended to the preamble when a shader samples `iVideo`.
+///
+/// `iVideo` holds the latest frame of a video wallpaper at the video's own
+/// size; `textureDimensions(iVideo)` gives its aspect ratio. Only the built-in
+/// shader of video wallpapers (see [`crate::video`]) is fed frames.
+pub const WGSL_VIDEO_DECL: &str = r#"
+@group(0) @binding(12) var iVideo: texture_2d<f32>;
+@group(0) @binding(13) var iVideoSampler: sampler;
+"#;
+
+/// Returns true if the shader code references the `iVideo` texture.
+pub fn uses_video(shader_code: &str) -> bool {
+    shader_code.contains("iVideo")
+}
+
+/// Declaration appended to the preamble when a shader samples `iDepth`.
+///
+/// `iDepth` holds the shader's `depth_image`, scaled like the background
+/// image, with near in white and far in black; without one it is flat mid
+/// gray. Read the depth with `textureSample(iDepth, iDepthSampler, uv).r`.
+pub const WGSL_DEPTH_DECL: &str = r#"
+@group(0) @binding(14) var iDepth: texture_2d<f32>;
+@group(0) @binding(15) var iDepthSampler: sampler;
+"#;
+
+/// Returns true if the shader code references the `iDepth` texture.
+pub fn uses_depth(shader_code: &str) -> bool {
+    shader_code.contains("iDepth")
+}
+
+/// Declaration appended to the preamble when a shader reads `iPointer`.
+///
+/// `iPointer` is `vec4f(x, y, over, 0.0)`: the pointer position from 0.0 to
+/// 1.0 across the output, from the top left, and 1.0 in `over` while the
+/// pointer is over the wallpaper. Both ease towards their latest value, so
+/// the wallpaper follows the pointer smoothly; windows hide the pointer from
+/// it.
+pub const WGSL_POINTER_DECL: &str = r#"
+@group(0) @binding(16) var<uniform> iPointer: vec4f;
+"#;
+
+/// Returns true if the shader code references the `iPointer` uniform.
+pub fn uses_pointer(shader_code: &str) -> bool {
+    shader_code.contains("iPointer")
+}
+
+/// Assemble the preamble for `shader_code`: the base uniforms (plus
+/// `iTexture` with `has_texture`), then a declaration for each optional
+/// uniform the shader reads, in binding order.
+pub fn fragment_preamble(
+    shader_code: &str,
+    has_texture: bool,
+    has_audio: bool,
+    has_params: bool,
+) -> String {
+    let mut preamble = String::from(if has_texture {
+        WGSL_PREAMBLE_WITH_TEXTURE
+    } else {
+        WGSL_PREAMBLE
+    });
+    if has_audio {
+        preamble.push_str(WGSL_AUDIO_DECL);
+    }
+    if uses_battery(shader_code) {
+        preamble.push_str(WGSL_BATTERY_DECL);
+    }
+    if uses_weather(shader_code) {
+        preamble.push_str(WGSL_WEATHER_DECL);
+    }
+    if uses_system_stats(shader_code) {
+        preamble.push_str(WGSL_SYSTEM_DECL);
+    }
+    if uses_local_time(shader_code) {
+        preamble.push_str(WGSL_LOCAL_TIME_DECL);
+    }
+    if has_params {
+        preamble.push_str(WGSL_PARAMS_DECL);
+    }
+    if uses_clock(shader_code) {
+        preamble.push_str(WGSL_CLOCK_DECL);
+    }
+    if uses_video(shader_code) {
+        preamble.push_str(WGSL_VIDEO_DECL);
+    }
+    if uses_depth(shader_code) {
+        preamble.push_str(WGSL_DEPTH_DECL);
+    }
+    if uses_pointer(shader_code) {
+        preamble.push_str(WGSL_POINTER_DECL);
+    }
+    preamble
+}
+
+/// Returns true if the shader's header declares `// output: linear`.
+///
+/// By default a shader's output is taken to be sRGB-encoded, as with most
+/// Shadertoy ports, and is written to the display unchanged. Linear output is
+/// encoded to sRGB by the surface (or an offscreen copy when the surface has
+/// no sRGB view). Background textures are sampled in the same space: raw for
+/// sRGB shaders, decoded to linear for linear ones.
+pub fn outputs_linear(shader_code: &str) -> bool {
+    declares_output(shader_code, "linear")
+}
+
+/// Returns true if the shader's header declares `// output: hdr`.
+///
+/// HDR output is linear like `output: linear`, with 1.0 as SDR reference
+/// white and brighter highlights above it. It is shown in HDR where the
+/// compositor supports it, and clipped like linear output elsewhere.
+pub fn outputs_hdr(shader_code: &str) -> bool {
+    declares_output(shader_code, "hdr")
+}
+
+fn declares_output(shader_code: &str, output: &str) -> bool {
+    shader_code
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with("//"))
+        .filter_map(|line| line.trim_start_matches('/').trim().strip_prefix("output:"))
+        .any(|declared| declared.trim() == output)
+}
+
 /// Full-screen vertex shader used by both the daemon and the preview renderer.
 pub const VERTEX_SHADER: &str = r#"
 struct VertexOutput {
@@ -52,6 +276,32 @@ pub fn aligned_bytes_per_row(width: u32, bytes_per_pixel: u32) -> u32 {
     unpadded.div_ceil(alignment) * alignment
 }
 
+/// RGBA texture data laid out for `write_texture`, with rows padded to
+/// [`aligned_bytes_per_row`] when needed. Returns the data, bytes per row,
+/// and rows per image.
+pub fn texture_upload_data(rgba: &[u8], width: u32, height: u32) -> (Cow<'_, [u8]>, u32, u32) {
+    let bytes_per_pixel = 4;
+    let unpadded_bytes_per_row = width.saturating_mul(bytes_per_pixel);
+    let bytes_per_row = aligned_bytes_per_row(width, bytes_per_pixel);
+
+    if bytes_per_row == unpadded_bytes_per_row {
+        return (Cow::Borrowed(rgba), bytes_per_row, height);
+    }
+
+    let mut padded = vec![0u8; (bytes_per_row * height) as usize];
+
+    for row in 0..height {
+        let src_offset = (row * unpadded_bytes_per_row) as usize;
+        let dst_offset = (row * bytes_per_row) as usize;
+        let src_end = src_offset + unpadded_bytes_per_row as usize;
+
+        padded[dst_offset..dst_offset + unpadded_bytes_per_row as usize]
+            .copy_from_slice(&rgba[src_offset..src_end]);
+    }
+
+    (Cow::Owned(padded), bytes_per_row, height)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,6 +323,98 @@ mod tests {
         );
     }
 
+    #[test]
+    fn detects_time_usage() {
+        assert!(uses_time("let wave = sin(iTime * 2.0);"));
+        assert!(!uses_time(
+            "return textureSample(iTexture, iTextureSampler, uv);"
+        ));
+    }
+
+    #[test]
+    fn detects_audio_usage() {
+        assert!(uses_audio(
+            "let bass = textureLoad(iAudio, vec2<i32>(2, 0), 0).r;"
+        ));
+        assert!(!uses_audio("let t = iTime;"));
+    }
+
+    #[test]
+    fn detects_battery_usage() {
+        assert!(uses_battery("let dim = mix(0.5, 1.0, iBattery.x);"));
+        assert!(!uses_battery("let t = iTime;"));
+    }
+
+    #[test]
+    fn detects_weather_usage() {
+        assert!(uses_weather("let clouds = iWeather.y;"));
+        assert!(!uses_weather("let t = iTime;"));
+    }
+
+    #[test]
+    fn detects_system_stats_usage() {
+        assert!(uses_system_stats("let busy = iSystem.x;"));
+        assert!(!uses_system_stats("let t = iTime;"));
+    }
+
+    #[test]
+    fn detects_local_time_usage() {
+        assert!(uses_local_time("let hour = iLocalTime.w / 3600.0;"));
+        assert!(!uses_local_time("let t = iTime;"));
+    }
+
+    #[test]
+    fn preamble_declares_only_the_uniforms_a_shader_reads() {
+        let code = "let t = iTime * iWeather.x;";
+        let preamble = fragment_preamble(code, false, false, false);
+        assert!(preamble.starts_with(WGSL_PREAMBLE));
+        assert!(preamble.contains("iWeather"));
+        assert!(!preamble.contains("iBattery"));
+        assert!(!preamble.contains("iParams"));
+        assert!(!preamble.contains("iClock"));
+
+        assert!(!preamble.contains("iVideo"));
+
+        let code = "iBattery.x + iLocalTime.w + textureSample(iClock, iClockSampler, uv).a \
+                    + textureSample(iVideo, iVideoSampler, uv).a \
+                    + textureSample(iDepth, iDepthSampler, uv).r + iPointer.z";
+        let preamble = fragment_preamble(code, true, true, true);
+        let order = [
+            "iTexture",
+            "iAudio",
+            "iBattery",
+            "iLocalTime",
+            "iParams",
+            "iClock",
+            "iVideo",
+            "iDepth",
+            "iPointer",
+        ]
+        .map(|name| preamble.find(name).unwrap());
+        assert!(order.is_sorted());
+    }
+
+    #[test]
+    fn detects_linear_output_in_header() {
+        let header = "// [SHADER]\n// name: Glow\n// output: linear\n\n@fragment\nfn main() {}";
+        assert!(outputs_linear(header));
+        assert!(!outputs_linear(
+            "// [SHADER]\n// name: Glow\n@fragment\nfn main() {}"
+        ));
+        // Only the leading comment block counts
+        assert!(!outputs_linear("fn main() {}\n// output: linear"));
+    }
+
+    #[test]
+    fn detects_hdr_output_in_header() {
+        let header = "// [SHADER]\n// name: Glow\n// output:hdr\n\n@fragment\nfn main() {}";
+        assert!(outputs_hdr(header));
+        assert!(!outputs_linear(header));
+        assert!(!outputs_hdr(
+            "// [SHADER]\n// output: linear\n@fragment\nfn main() {}"
+        ));
+    }
+
     #[test]
     fn aligns_bytes_per_row_to_wgpu_requirement() {
         let bytes_per_pixel = 4u32;
@@ -80,4 +422,18 @@ mod tests {
         assert_eq!(aligned % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT, 0);
         assert!(aligned >= bytes_per_pixel);
     }
+
+    #[test]
+    fn pads_texture_upload_rows_when_needed() {
+        let width = 1;
+        let height = 2;
+        let rgba = vec![1u8; (width * height * 4) as usize];
+
+        let (upload_data, bytes_per_row, rows_per_image) =
+            texture_upload_data(&rgba, width, height);
+
+        assert_eq!(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        assert_eq!(rows_per_image, height);
+        assert_eq!(upload_data.len(), (bytes_per_row * height) as usize);
+    }
 }
@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `#include` support for WGSL wallpaper shaders.
+//!
+//! WGSL has no module system, so GlowBerry resolves a minimal preprocessor
+//! directive before handing the code to wgpu:
+//!
+//! ```wgsl
+//! #include "noise.wgsl"
+//! ```
+//!
+//! Paths are looked up relative to the including file's directory first, then
+//! in each system shader directory and its `include/` subdirectory. Every file
+//! is included at most once, so shared helpers can include each other freely.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Maximum nesting depth, guarding against runaway include chains.
+const MAX_DEPTH: usize = 16;
+
+/// Error when resolving `#include` directives.
+#[derive(Debug, thiserror::Error)]
+pub enum IncludeError {
+    #[error("Malformed include directive on line {line}: {directive}")]
+    Malformed { line: usize, directive: String },
+
+    #[error("Included shader not found: {0}")]
+    NotFound(String),
+
+    #[error("Failed to read included shader {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Shader includes nested deeper than {} levels", MAX_DEPTH)]
+    TooDeep,
+}
+
+/// System directories searched for shaders and shared snippets, in priority order.
+///
+/// Mirrors the XDG lookup used by the settings app:
+/// `~/.local/share/glowberry/shaders`, then `$XDG_DATA_DIRS/glowberry/shaders`.
+pub fn shader_dirs() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    if let Some(data_dir) = dirs::data_dir() {
+        paths.push(data_dir.join("glowberry").join("shaders"));
+    }
+
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+
+    for dir in data_dirs.split(':').filter(|d| !d.is_empty()) {
+        let dir = Path::new(dir).join("glowberry").join("shaders");
+        if !paths.contains(&dir) {
+            paths.push(dir);
+        }
+    }
+
+    paths
+}
+
+/// Expand all `#include` directives in `code`.
+///
+/// `base_dir` is the directory of the shader the code came from, if known.
+/// Code without any directives is returned unchanged.
+pub fn resolve_includes(code: &str, base_dir: Option<&Path>) -> Result<String, IncludeError> {
+    resolve_includes_in(code, base_dir, &shader_dirs())
+}
+
+/// Same as [`resolve_includes`], with an explicit list of search directories.
+pub fn resolve_includes_in(
+    code: &str,
+    base_dir: Option<&Path>,
+    search_dirs: &[PathBuf],
+) -> Result<String, IncludeError> {
+    if !code.contains("#include") {
+        return Ok(code.to_string());
+    }
+
+    let mut seen = HashSet::new();
+    let mut out = String::with_capacity(code.len());
+    expand(code, base_dir, search_dirs, &mut seen, &mut out, 0)?;
+    Ok(out)
+}
+
+fn expand(
+    code: &str,
+    base_dir: Option<&Path>,
+    search_dirs: &[PathBuf],
+    seen: &mut HashSet<PathBuf>,
+    out: &mut String,
+    depth: usize,
+) -> Result<(), IncludeError> {
+    if depth > MAX_DEPTH {
+        return Err(IncludeError::TooDeep);
+    }
+
+    for (idx, line) in code.lines().enumerate() {
+        let Some(name) = parse_directive(line, idx + 1)? else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+
+        let path = find_include(name, base_dir, search_dirs)
+            .ok_or_else(|| IncludeError::NotFound(name.to_string()))?;
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+
+        // Include-once semantics: silently skip files already pulled in.
+        if !seen.insert(canonical) {
+            continue;
+        }
+
+        let included = std::fs::read_to_string(&path).map_err(|source| IncludeError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+        out.push_str(&format!("// begin include: {name}\n"));
+        expand(&included, path.parent(), search_dirs, seen, out, depth + 1)?;
+        out.push_str(&format!("// end include: {name}\n"));
+    }
+
+    Ok(())
+}
+
+/// Returns the quoted path if `line` is an `#include` directive.
+fn parse_directive(line: &str, line_no: usize) -> Result<Option<&str>, IncludeError> {
+    let Some(rest) = line.trim_start().strip_prefix("#include") else {
+        return Ok(None);
+    };
+
+    let malformed = || IncludeError::Malformed {
+        line: line_no,
+        directive: line.trim().to_string(),
+    };
+
+    let rest = rest.trim();
+    let name = rest
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .or_else(|| rest.strip_prefix('<').and_then(|r| r.strip_suffix('>')))
+        .ok_or_else(malformed)?;
+
+    if name.is_empty() {
+        return Err(malformed());
+    }
+
+    Ok(Some(name))
+}
+
+fn find_include(name: &str, base_dir: Option<&Path>, search_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let name = Path::new(name);
+    if name.is_absolute() {
+        return name.is_file().then(|| name.to_path_buf());
+    }
+
+    base_dir
+        .into_iter()
+        .map(Path::to_path_buf)
+        .chain(
+            search_dirs
+                .iter()
+                .flat_map(|dir| [dir.clone(), dir.join("include")]),
+        )
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("glowberry-include-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("include")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn code_without_directives_is_unchanged() {
+        let code = "fn a() {}\nfn b() {}";
+        assert_eq!(resolve_includes_in(code, None, &[]).unwrap(), code);
+    }
+
+    #[test]
+    fn resolves_relative_and_search_dir_includes_once() {
+        let shader_dir = temp_dir("shader");
+        let system_dir = temp_dir("system");
+
+        std::fs::write(shader_dir.join("local.wgsl"), "fn local() {}").unwrap();
+        std::fs::write(
+            system_dir.join("include").join("noise.wgsl"),
+            "#include \"palette.wgsl\"\nfn noise() {}",
+        )
+        .unwrap();
+        std::fs::write(system_dir.join("palette.wgsl"), "fn palette() {}").unwrap();
+
+        let code = "#include \"local.wgsl\"\n#include <noise.wgsl>\n#include \"palette.wgsl\"\nfn main() {}";
+        let out = resolve_includes_in(code, Some(&shader_dir), &[system_dir.clone()]).unwrap();
+
+        assert!(out.contains("fn local() {}"));
+        assert!(out.contains("fn noise() {}"));
+        assert_eq!(out.matches("fn palette() {}").count(), 1);
+        assert!(!out.contains("#include"));
+
+        let _ = std::fs::remove_dir_all(shader_dir);
+        let _ = std::fs::remove_dir_all(system_dir);
+    }
+
+    #[test]
+    fn missing_include_is_an_error() {
+        let err = resolve_includes_in("#include \"nope.wgsl\"", None, &[]).unwrap_err();
+        assert!(matches!(err, IncludeError::NotFound(name) if name == "nope.wgsl"));
+    }
+
+    #[test]
+    fn malformed_directive_is_an_error() {
+        let err = resolve_includes_in("#include nope.wgsl", None, &[]).unwrap_err();
+        assert!(matches!(err, IncludeError::Malformed { line: 1, .. }));
+    }
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Shuffled slideshows that carry on where they were after a restart.
+//!
+//! Each pass through a folder's images is a permutation of them drawn from
+//! the slideshow's seed and the pass number, see [`order`], so the state
+//! only keeps those two to continue the same sequence. Passes are drawn with
+//! SplitMix64 rather than `rand`, whose algorithms may change between
+//! releases. The images shown last in one pass are kept out of the start of
+//! the next.
+
+use std::path::PathBuf;
+
+use glowberry_config::state::Shuffle;
+
+use crate::colored::hash;
+
+/// A shuffle starting its first pass from a new seed.
+pub fn new() -> Shuffle {
+    Shuffle {
+        seed: rand::random(),
+        pass: 0,
+    }
+}
+
+/// Put `images` in the order of `shuffle`'s current pass. `recent` are the
+/// images shown before it, oldest first; up to `no_repeat` of the latest
+/// are moved out of its first `no_repeat` places, as far as the other
+/// images allow.
+pub fn order(images: &mut [PathBuf], shuffle: Shuffle, recent: &[PathBuf], no_repeat: usize) {
+    // The same images give the same order however they were listed
+    images.sort();
+
+    let mut state = hash(shuffle.seed ^ hash(shuffle.pass));
+    for i in (1..images.len()).rev() {
+        state = hash(state);
+        images.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+
+    let window = no_repeat.min(images.len() / 2);
+    let recent = &recent[recent.len().saturating_sub(window)..];
+    if recent.is_empty() {
+        return;
+    }
+    let mut first = Vec::with_capacity(images.len());
+    let mut rest = Vec::new();
+    for image in images.iter_mut().map(std::mem::take) {
+        if first.len() < window && !recent.contains(&image) {
+            first.push(image);
+        } else {
+            rest.push(image);
+        }
+    }
+    for (slot, image) in images.iter_mut().zip(first.into_iter().chain(rest)) {
+        *slot = image;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shuffles_repeatably_without_early_repeats() {
+        let images: Vec<PathBuf> = (0..10).map(|i| PathBuf::from(format!("{i}.png"))).collect();
+        let shuffle = Shuffle { seed: 7, pass: 0 };
+
+        let mut first = images.clone();
+        first.reverse();
+        order(&mut first, shuffle, &[], 3);
+        let mut again = images.clone();
+        order(&mut again, shuffle, &[], 3);
+        assert_eq!(first, again);
+        assert_ne!(first, images);
+
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, images);
+
+        // The last three of the pass before stay out of the next one's start
+        for pass in 1..20 {
+            let mut next = images.clone();
+            order(&mut next, Shuffle { seed: 7, pass }, &first, 3);
+            for image in &first[7..] {
+                assert!(!next[..3].contains(image), "{pass}: {next:?}");
+            }
+            first = next;
+        }
+
+        // Two images still alternate
+        let mut pair = vec![PathBuf::from("a"), PathBuf::from("b")];
+        order(&mut pair, shuffle, &[PathBuf::from("b")], 5);
+        assert_eq!(pair[0], PathBuf::from("a"));
+    }
+}
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Slideshow memory soak test.
+//!
+//! Repeats the CPU side of a slideshow rotation (decode, scale to each output
+//! size, convert into an `XRGB8888` buffer) thousands of times, trimming
+//! malloc arenas and sampling RSS as it goes. RSS that keeps growing after
+//! warm-up points at a leak or cache in the image path, the classic failure
+//! of long-running wallpaper daemons. `glowberry soak` exposes this on the
+//! command line.
+
+use glowberry_config::ScalingMode;
+use std::path::PathBuf;
+
+use crate::{draw, wallpaper};
+
+/// Options for [`run`].
+#[derive(Debug, Clone)]
+pub struct SoakOptions {
+    /// Number of slideshow rotations to perform.
+    pub rotations: u32,
+    /// Physical output sizes each image is scaled to, as for multi-monitor setups.
+    pub sizes: Vec<(u32, u32)>,
+    /// How images are scaled to each size.
+    pub scaling_mode: ScalingMode,
+    /// Rotations to run before the RSS baseline is taken.
+    pub warmup: u32,
+    /// Allowed RSS growth over the baseline, in KiB.
+    pub max_growth_kib: u64,
+}
+
+impl Default for SoakOptions {
+    fn default() -> Self {
+        Self {
+            rotations: 5000,
+            sizes: vec![(1920, 1080), (2560, 1440)],
+            scaling_mode: ScalingMode::Zoom,
+            warmup: 100,
+            max_growth_kib: 32 * 1024,
+        }
+    }
+}
+
+/// Memory usage observed during a soak run, in KiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoakReport {
+    /// Rotations performed.
+    pub rotations: u32,
+    /// Images that failed to decode (counted per rotation).
+    pub decode_failures: u32,
+    /// RSS after warm-up.
+    pub baseline_kib: u64,
+    /// Highest RSS seen after warm-up.
+    pub peak_kib: u64,
+    /// RSS at the end of the run.
+    pub final_kib: u64,
+}
+
+/// Error when running the soak test.
+#[derive(Debug, thiserror::Error)]
+pub enum SoakError {
+    #[error("No images to rotate through")]
+    NoImages,
+
+    #[error("Failed to read process RSS from /proc/self/status")]
+    Rss,
+
+    #[error(
+        "RSS grew from {} MiB to {} MiB after {rotations} rotations",
+        baseline_kib / 1024,
+        rss_kib / 1024
+    )]
+    Leak {
+        baseline_kib: u64,
+        rss_kib: u64,
+        rotations: u32,
+    },
+}
+
+/// Rotate through `images` as a slideshow would, failing if RSS grows past
+/// `options.max_growth_kib` over the post-warm-up baseline.
+pub fn run(images: &[PathBuf], options: &SoakOptions) -> Result<SoakReport, SoakError> {
+    if images.is_empty() {
+        return Err(SoakError::NoImages);
+    }
+
+    let mut report = SoakReport {
+        rotations: 0,
+        decode_failures: 0,
+        baseline_kib: 0,
+        peak_kib: 0,
+        final_kib: 0,
+    };
+    let mut baseline = None;
+
+    for (rotation, path) in (1..=options.rotations).zip(images.iter().cycle()) {
+        match wallpaper::decode(path) {
+            Ok(image) => {
+                for &(width, height) in &options.sizes {
+                    let scaled = wallpaper::scale(&options.scaling_mode, &image, width, height);
+                    let mut canvas = vec![0u8; width as usize * height as usize * 4];
+                    draw::xrgb888_canvas(&mut canvas, &scaled);
+                }
+            }
+            Err(why) => {
+                tracing::debug!(?why, path = %path.display(), "soak: decode failed");
+                report.decode_failures += 1;
+            }
+        }
+
+        // The daemon trims after applying new backgrounds; do the same here
+        // so RSS reflects live allocations rather than arena slack.
+        crate::engine::malloc_trim();
+
+        let rss = rss_kib().ok_or(SoakError::Rss)?;
+        report.rotations = rotation;
+        report.final_kib = rss;
+
+        if rotation == options.warmup.max(1) {
+            baseline = Some(rss);
+            report.baseline_kib = rss;
+        }
+        if let Some(baseline_kib) = baseline {
+            report.peak_kib = report.peak_kib.max(rss);
+            if rss > baseline_kib + options.max_growth_kib {
+                return Err(SoakError::Leak {
+                    baseline_kib,
+                    rss_kib: rss,
+                    rotations: rotation,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Resident set size of the current process, in KiB.
+pub fn rss_kib() -> Option<u64> {
+    parse_vm_rss(&std::fs::read_to_string("/proc/self/status").ok()?)
+}
+
+/// Parse `VmRSS` from `/proc/<pid>/status`, in KiB.
+pub fn parse_vm_rss(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.split_whitespace().next()?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_vm_rss() {
+        let status = "Name:\tglowberry\nVmPeak:\t  90000 kB\nVmRSS:\t   41236 kB\nThreads:\t4\n";
+        assert_eq!(parse_vm_rss(status), Some(41236));
+        assert_eq!(parse_vm_rss("Name:\tglowberry\n"), None);
+    }
+
+    // Other tests allocating in parallel move the process's RSS; run alone
+    // with `cargo test -- --ignored slideshow_rotation`
+    #[test]
+    #[ignore = "measures process-wide RSS"]
+    fn slideshow_rotation_does_not_leak() {
+        let dir = std::env::temp_dir().join(format!("glowberry-soak-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut images = Vec::new();
+        for (i, size) in [(320, 200), (200, 320), (640, 480)].into_iter().enumerate() {
+            let path = dir.join(format!("{i}.png"));
+            image::RgbImage::from_pixel(size.0, size.1, image::Rgb([i as u8 * 80; 3]))
+                .save(&path)
+                .unwrap();
+            images.push(path);
+        }
+        images.push(dir.join("missing.png"));
+
+        let options = SoakOptions {
+            rotations: 400,
+            sizes: vec![(160, 90), (90, 160)],
+            warmup: 40,
+            ..SoakOptions::default()
+        };
+        let report = run(&images, &options).unwrap();
+        assert_eq!(report.rotations, 400);
+        assert_eq!(report.decode_failures, 100);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Position of the sun in the sky, accurate to about a degree, which is
+//! plenty for picking a wallpaper by daylight.
+
+use jiff::{SignedDuration, Timestamp};
+
+/// Step of the search for the sun crossing an altitude, small enough not to
+/// miss a sunset and sunrise between two steps.
+const CROSSING_STEP: SignedDuration = SignedDuration::from_mins(10);
+
+/// How far ahead the sun is looked for, to cover polar days and nights.
+const CROSSING_HORIZON: SignedDuration = SignedDuration::from_hours(48);
+
+/// Altitude above the horizon and azimuth clockwise from north, both in
+/// degrees, of the sun seen from `latitude`/`longitude` (degrees, east and
+/// north positive) at `time`.
+pub fn sun_position(time: Timestamp, latitude: f64, longitude: f64) -> (f64, f64) {
+    // Days since the J2000.0 epoch
+    let days = time.as_millisecond() as f64 / 86_400_000.0 - 10_957.5;
+
+    let mean_anomaly = (357.529 + 0.985_600_28 * days).to_radians();
+    let mean_longitude = 280.459 + 0.985_647_36 * days;
+    let ecliptic_longitude =
+        (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+            .to_radians();
+    let obliquity = (23.439 - 0.000_000_36 * days).to_radians();
+
+    let right_ascension =
+        (obliquity.cos() * ecliptic_longitude.sin()).atan2(ecliptic_longitude.cos());
+    let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+
+    let sidereal_hours = (18.697_374_558 + 24.065_709_824_419_08 * days).rem_euclid(24.0);
+    let hour_angle = (sidereal_hours * 15.0 + longitude).to_radians() - right_ascension;
+
+    let latitude = latitude.to_radians();
+    let altitude = (latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos())
+    .asin();
+    let azimuth = (-hour_angle.sin())
+        .atan2(declination.tan() * latitude.cos() - latitude.sin() * hour_angle.cos());
+
+    (
+        altitude.to_degrees(),
+        azimuth.to_degrees().rem_euclid(360.0),
+    )
+}
+
+/// First time after `from` that the sun, seen from `latitude`/`longitude`,
+/// rises above or sets below `altitude` degrees, to within a minute. `None`
+/// if it doesn't within two days, as in polar summer and winter.
+pub fn next_crossing(
+    from: Timestamp,
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+) -> Option<Timestamp> {
+    let above = |time| sun_position(time, latitude, longitude).0 >= altitude;
+    let start = above(from);
+
+    let mut before = from;
+    let mut after = from.checked_add(CROSSING_STEP).ok()?;
+    while above(after) == start {
+        before = after;
+        after = after.checked_add(CROSSING_STEP).ok()?;
+        if after.duration_since(from) > CROSSING_HORIZON {
+            return None;
+        }
+    }
+
+    // Narrow the step down to the minute it crosses in
+    while after.duration_since(before) > SignedDuration::from_mins(1) {
+        let middle = before.checked_add(after.duration_since(before) / 2).ok()?;
+        if above(middle) == start {
+            before = middle;
+        } else {
+            after = middle;
+        }
+    }
+    Some(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_crossing, sun_position};
+
+    #[test]
+    fn finds_the_sun_over_london() {
+        let noon: jiff::Timestamp = "2025-06-21T12:02:00Z".parse().unwrap();
+        let (altitude, azimuth) = sun_position(noon, 51.5, 0.0);
+        assert!((altitude - 61.9).abs() < 1.0, "{altitude}");
+        assert!((azimuth - 180.0).abs() < 2.0, "{azimuth}");
+
+        let evening: jiff::Timestamp = "2025-06-21T18:00:00Z".parse().unwrap();
+        let (altitude, azimuth) = sun_position(evening, 51.5, 0.0);
+        assert!((10.0..30.0).contains(&altitude), "{altitude}");
+        assert!((270.0..310.0).contains(&azimuth), "{azimuth}");
+
+        let midnight: jiff::Timestamp = "2025-12-21T00:00:00Z".parse().unwrap();
+        assert!(sun_position(midnight, 51.5, 0.0).0 < -50.0);
+    }
+
+    #[test]
+    fn finds_sunset_and_sunrise() {
+        let noon: jiff::Timestamp = "2025-06-21T12:00:00Z".parse().unwrap();
+        let sunset = next_crossing(noon, 51.5, 0.0, -0.833).unwrap();
+        let expected: jiff::Timestamp = "2025-06-21T20:21:00Z".parse().unwrap();
+        assert!(
+            sunset.duration_since(expected).abs().as_mins() < 10,
+            "{sunset}"
+        );
+
+        let sunrise = next_crossing(sunset, 51.5, 0.0, -0.833).unwrap();
+        let expected: jiff::Timestamp = "2025-06-22T03:43:00Z".parse().unwrap();
+        assert!(
+            sunrise.duration_since(expected).abs().as_mins() < 10,
+            "{sunrise}"
+        );
+
+        // The midnight sun doesn't set
+        assert_eq!(next_crossing(noon, 78.0, 15.0, -0.833), None);
+    }
+}
@@ -0,0 +1,215 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! System activity sampling for activity-reactive shader wallpapers.
+//!
+//! A background thread reads `/proc` on a slow timer and publishes CPU load,
+//! memory usage, and network throughput, which the renderer uploads to
+//! shaders as the `iSystem` uniform. Sampling only starts once a shader
+//! references `iSystem`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often `/proc` is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Weight of the newest sample in the exponential moving average.
+const SMOOTHING: f32 = 0.5;
+
+/// A snapshot of system activity.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SystemStats {
+    /// CPU load across all cores, 0.0-1.0.
+    pub cpu: f32,
+    /// Memory in use (excluding caches), 0.0-1.0.
+    pub memory: f32,
+    /// Received bytes per second, in MiB/s.
+    pub net_rx: f32,
+    /// Transmitted bytes per second, in MiB/s.
+    pub net_tx: f32,
+}
+
+impl SystemStats {
+    /// Pack as `vec4f(cpu, memory, net_rx, net_tx)`.
+    pub fn uniform(&self) -> [f32; 4] {
+        [self.cpu, self.memory, self.net_rx, self.net_tx]
+    }
+
+    /// Blend `next` into `self`, so shaders see gradual changes.
+    fn smooth(&mut self, next: &Self) {
+        let mix = |a: f32, b: f32| a + (b - a) * SMOOTHING;
+        self.cpu = mix(self.cpu, next.cpu);
+        self.memory = mix(self.memory, next.memory);
+        self.net_rx = mix(self.net_rx, next.net_rx);
+        self.net_tx = mix(self.net_tx, next.net_tx);
+    }
+}
+
+/// Running `/proc` sampler. Sampling stops when this is dropped.
+#[derive(Debug)]
+pub struct SystemMonitor {
+    stats: Arc<Mutex<SystemStats>>,
+    running: Arc<AtomicBool>,
+}
+
+impl SystemMonitor {
+    /// Start sampling system activity on a background thread.
+    pub fn start() -> Self {
+        let stats = Arc::new(Mutex::new(SystemStats::default()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_stats = stats.clone();
+        let thread_running = running.clone();
+        if let Err(err) = std::thread::Builder::new()
+            .name("glowberry-sysstats".into())
+            .spawn(move || run(&thread_stats, &thread_running))
+        {
+            tracing::error!(?err, "Failed to start system stats sampler");
+        } else {
+            tracing::info!("System stats sampler started");
+        }
+
+        Self { stats, running }
+    }
+
+    /// Get the latest smoothed statistics.
+    pub fn current(&self) -> SystemStats {
+        self.stats.lock().map(|s| *s).unwrap_or_default()
+    }
+}
+
+impl Drop for SystemMonitor {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn run(stats: &Mutex<SystemStats>, running: &AtomicBool) {
+    let read = |path| std::fs::read_to_string(path).unwrap_or_default();
+
+    let mut prev_cpu = parse_cpu_times(&read("/proc/stat"));
+    let mut prev_net = parse_net_dev(&read("/proc/net/dev"));
+    let mut prev_at = Instant::now();
+
+    while running.load(Ordering::Relaxed) {
+        std::thread::sleep(SAMPLE_INTERVAL);
+
+        let cpu = parse_cpu_times(&read("/proc/stat"));
+        let net = parse_net_dev(&read("/proc/net/dev"));
+        let now = Instant::now();
+        let secs = now.duration_since(prev_at).as_secs_f32().max(f32::EPSILON);
+
+        let cpu_load = match (prev_cpu, cpu) {
+            (Some((idle0, total0)), Some((idle1, total1))) if total1 > total0 => {
+                1.0 - (idle1.saturating_sub(idle0)) as f32 / (total1 - total0) as f32
+            }
+            _ => 0.0,
+        };
+
+        let rate = |prev: u64, next: u64| next.saturating_sub(prev) as f32 / secs / 1_048_576.0;
+        let (net_rx, net_tx) = match (prev_net, net) {
+            (Some((rx0, tx0)), Some((rx1, tx1))) => (rate(rx0, rx1), rate(tx0, tx1)),
+            _ => (0.0, 0.0),
+        };
+
+        let sample = SystemStats {
+            cpu: cpu_load.clamp(0.0, 1.0),
+            memory: parse_meminfo(&read("/proc/meminfo")).unwrap_or(0.0),
+            net_rx,
+            net_tx,
+        };
+
+        if let Ok(mut shared) = stats.lock() {
+            shared.smooth(&sample);
+        }
+
+        (prev_cpu, prev_net, prev_at) = (cpu, net, now);
+    }
+
+    tracing::debug!("System stats sampler stopped");
+}
+
+/// Parse the aggregate `cpu` line of `/proc/stat` into `(idle, total)` jiffies.
+fn parse_cpu_times(stat: &str) -> Option<(u64, u64)> {
+    let line = stat.lines().find(|l| l.starts_with("cpu "))?;
+    let fields: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+
+    // user nice system idle iowait irq softirq steal (guest time is already in user)
+    let total = fields.iter().take(8).sum();
+    let idle = fields.get(3)? + fields.get(4).copied().unwrap_or(0);
+    Some((idle, total))
+}
+
+/// Parse `/proc/meminfo` into the fraction of memory in use.
+fn parse_meminfo(meminfo: &str) -> Option<f32> {
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|l| l.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|v| v.split_whitespace().next()?.parse::<u64>().ok())
+    };
+
+    let total = field("MemTotal")?;
+    let available = field("MemAvailable")?;
+    (total > 0).then(|| 1.0 - available.min(total) as f32 / total as f32)
+}
+
+/// Parse `/proc/net/dev` into total `(rx, tx)` bytes, excluding loopback.
+fn parse_net_dev(net_dev: &str) -> Option<(u64, u64)> {
+    let mut totals = None;
+
+    for line in net_dev.lines().skip(2) {
+        let Some((iface, counters)) = line.split_once(':') else {
+            continue;
+        };
+        if iface.trim() == "lo" {
+            continue;
+        }
+
+        let counters: Vec<u64> = counters
+            .split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        if let (Some(rx), Some(tx)) = (counters.first(), counters.get(8)) {
+            let (total_rx, total_tx) = totals.get_or_insert((0, 0));
+            *total_rx += rx;
+            *total_tx += tx;
+        }
+    }
+
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aggregate_cpu_line() {
+        let stat = "cpu  100 0 50 800 50 0 0 0 0 0\ncpu0 50 0 25 400 25 0 0 0 0 0\n";
+        assert_eq!(parse_cpu_times(stat), Some((850, 1000)));
+    }
+
+    #[test]
+    fn parses_memory_in_use() {
+        let meminfo = "MemTotal:       16000000 kB\nMemFree:         1000000 kB\nMemAvailable:    4000000 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some(0.75));
+    }
+
+    #[test]
+    fn sums_network_counters_without_loopback() {
+        let net_dev = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 5000      10    0    0    0     0          0         0     5000      10    0    0    0     0       0          0
+  eth0: 1000      10    0    0    0     0          0         0     2000      10    0    0    0     0       0          0
+ wlan0: 300       10    0    0    0     0          0         0     400       10    0    0    0     0       0          0
+";
+        assert_eq!(parse_net_dev(net_dev), Some((1300, 2400)));
+    }
+}
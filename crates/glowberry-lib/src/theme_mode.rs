@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Whether the COSMIC theme is light or dark, for entries with a dark
+//! source.
+//!
+//! COSMIC keeps the mode in a cosmic-config of its own, which the engine
+//! watches next to the daemon's and reads with [`is_dark`].
+
+use cosmic_config::{Config, ConfigGet};
+
+/// Name of COSMIC's theme mode config.
+const ID: &str = "com.system76.CosmicTheme.Mode";
+
+/// Version of the theme mode config.
+const VERSION: u64 = 1;
+
+/// Key holding whether the theme is dark.
+pub const IS_DARK: &str = "is_dark";
+
+/// COSMIC's theme mode config, to watch for the mode changing.
+pub fn config() -> Option<Config> {
+    Config::new(ID, VERSION)
+        .inspect_err(|why| tracing::warn!(?why, "failed to open the theme mode config"))
+        .ok()
+}
+
+/// Whether `config` sets a dark theme. Without a mode, the entries' own
+/// sources are shown.
+pub fn is_dark(config: &Config) -> bool {
+    config.get(IS_DARK).unwrap_or(false)
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Time-lapse capture of wallpapers.
+//!
+//! When enabled, the daemon periodically saves a small snapshot of every
+//! output's wallpaper to `~/.local/share/glowberry/timelapse/<output>/`.
+//! Snapshots are read back, scaled, encoded, and written on a worker
+//! thread, see [`spawn_save`], so capturing never stalls the event loop.
+//! [`assemble`] turns a directory of snapshots into a video through the
+//! GStreamer export pipeline, see [`video::encode`], or into an animated GIF,
+//! which `glowberry timelapse` exposes on the command line.
+
+use crate::video;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{DynamicImage, Frame, RgbaImage, imageops::FilterType};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Width of saved snapshots; height follows the output's aspect ratio.
+pub const SNAPSHOT_WIDTH: u32 = 480;
+
+/// Snapshots kept per output before the oldest are deleted (a week at 5 min).
+const MAX_FRAMES: usize = 2016;
+
+/// Work rendering a snapshot of an output's wallpaper, run on the worker
+/// thread saving it; `None` when there is nothing to show yet.
+pub type Render = Box<dyn FnOnce() -> Option<DynamicImage> + Send>;
+
+/// Error when saving or assembling time-lapse frames.
+#[derive(Debug, thiserror::Error)]
+pub enum TimelapseError {
+    #[error("No data directory available for time-lapse frames")]
+    NoDataDir,
+
+    #[error("No wallpaper to snapshot")]
+    NothingShown,
+
+    #[error("No time-lapse frames found in {0}")]
+    NoFrames(PathBuf),
+
+    #[error("Unsupported time-lapse format: {0} (use .webm, .mp4, .mkv, or .gif)")]
+    UnsupportedFormat(PathBuf),
+
+    #[error("Time-lapse video error: {0}")]
+    Video(eyre::Report),
+
+    #[error("Time-lapse I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Time-lapse image error: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+/// Directory holding the snapshots of one output.
+pub fn output_dir(output: &str) -> Result<PathBuf, TimelapseError> {
+    dirs::data_dir()
+        .map(|dir| dir.join("glowberry").join("timelapse").join(output))
+        .ok_or(TimelapseError::NoDataDir)
+}
+
+/// Snapshot dimensions for an output of `width`x`height`.
+pub fn snapshot_size(width: u32, height: u32) -> (u32, u32) {
+    let snap_w = SNAPSHOT_WIDTH.min(width.max(1));
+    let snap_h = (u64::from(snap_w) * u64::from(height) / u64::from(width.max(1))).max(1);
+    (snap_w, snap_h as u32)
+}
+
+/// Save a snapshot of `output`'s wallpaper, pruning the oldest frames.
+pub fn save_snapshot(output: &str, image: &DynamicImage) -> Result<PathBuf, TimelapseError> {
+    let dir = output_dir(output)?;
+    std::fs::create_dir_all(&dir)?;
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    // Zero-padded so lexical order is chronological
+    let path = dir.join(format!("{secs:012}.png"));
+
+    let (width, height) = snapshot_size(image.width(), image.height());
+    image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_rgb8()
+        .save(&path)?;
+
+    let stale = frames(&dir)?;
+    for old in stale.iter().take(stale.len().saturating_sub(MAX_FRAMES)) {
+        let _ = std::fs::remove_file(old);
+    }
+
+    Ok(path)
+}
+
+/// Render and save each output's snapshot on a worker thread; `done`
+/// receives each output with where its snapshot was saved.
+pub fn spawn_save(
+    snapshots: Vec<(String, Render)>,
+    done: calloop::channel::Sender<(String, Result<PathBuf, TimelapseError>)>,
+) {
+    let spawned = std::thread::Builder::new()
+        .name("glowberry-timelapse".into())
+        .spawn(move || {
+            for (output, render) in snapshots {
+                let saved = render()
+                    .ok_or(TimelapseError::NothingShown)
+                    .and_then(|snapshot| save_snapshot(&output, &snapshot));
+                if done.send((output, saved)).is_err() {
+                    return;
+                }
+            }
+        });
+    if let Err(why) = spawned {
+        tracing::warn!(?why, "Failed to start saving time-lapse frames");
+    }
+}
+
+/// All snapshots in `dir`, oldest first.
+pub fn frames(dir: &Path) -> Result<Vec<PathBuf>, TimelapseError> {
+    let mut frames: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    frames.sort();
+    Ok(frames)
+}
+
+/// Assemble the snapshots in `dir` into `out` at `fps`: an animated GIF for
+/// a `.gif` path, otherwise a video encoded as its extension asks.
+///
+/// Frames are scaled to the size of the first one. Returns the frame count.
+pub fn assemble(dir: &Path, out: &Path, fps: u16) -> Result<usize, TimelapseError> {
+    if out
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"))
+    {
+        return assemble_gif(dir, out, fps);
+    }
+    if video::encoder_for(out).is_none() {
+        return Err(TimelapseError::UnsupportedFormat(out.to_path_buf()));
+    }
+
+    let (frames, width, height) = sized_frames(dir)?;
+    // Encoded as 4:2:0, which halves both dimensions
+    let (width, height) = ((width & !1).max(2), (height & !1).max(2));
+    let pixels = frames
+        .iter()
+        .filter_map(|path| load_frame(path, width, height))
+        .map(RgbaImage::into_raw);
+    video::encode(out, width, height, fps, pixels).map_err(TimelapseError::Video)
+}
+
+fn assemble_gif(dir: &Path, out: &Path, fps: u16) -> Result<usize, TimelapseError> {
+    let (frames, width, height) = sized_frames(dir)?;
+    let delay = image::Delay::from_numer_denom_ms(1000, u32::from(fps.max(1)));
+
+    let file = std::io::BufWriter::new(std::fs::File::create(out)?);
+    let mut encoder = GifEncoder::new_with_speed(file, 10);
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    let mut written = 0;
+    for rgba in frames
+        .iter()
+        .filter_map(|path| load_frame(path, width, height))
+    {
+        encoder.encode_frame(Frame::from_parts(rgba, 0, 0, delay))?;
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+/// The snapshots in `dir` with the size of the first one.
+fn sized_frames(dir: &Path) -> Result<(Vec<PathBuf>, u32, u32), TimelapseError> {
+    let frames = frames(dir)?;
+    let Some(first) = frames.first() else {
+        return Err(TimelapseError::NoFrames(dir.to_path_buf()));
+    };
+    let (width, height) = image::image_dimensions(first)?;
+    Ok((frames, width, height))
+}
+
+/// The snapshot at `path` scaled to `width`x`height`, or `None` if it can't
+/// be read.
+fn load_frame(path: &Path, width: u32, height: u32) -> Option<RgbaImage> {
+    match image::open(path) {
+        Ok(img) => Some(
+            img.resize_exact(width, height, FilterType::Triangle)
+                .into_rgba8(),
+        ),
+        Err(why) => {
+            tracing::warn!(?why, path = %path.display(), "skipping unreadable frame");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_size_keeps_aspect_ratio() {
+        assert_eq!(snapshot_size(3840, 2160), (480, 270));
+        assert_eq!(snapshot_size(1080, 1920), (480, 853));
+        assert_eq!(snapshot_size(320, 200), (320, 200));
+    }
+
+    #[test]
+    fn assembles_frames_in_order() {
+        let dir = std::env::temp_dir().join(format!("glowberry-timelapse-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (i, shade) in [0u8, 128, 255].into_iter().enumerate() {
+            image::RgbImage::from_pixel(8, 4, image::Rgb([shade; 3]))
+                .save(dir.join(format!("{i:012}.png")))
+                .unwrap();
+        }
+
+        let out = dir.join("out.gif");
+        assert_eq!(assemble(&dir, &out, 10).unwrap(), 3);
+        assert_eq!(image::image_dimensions(&out).unwrap(), (8, 4));
+
+        assert!(matches!(
+            assemble(&dir, &dir.join("out.txt"), 10),
+            Err(TimelapseError::UnsupportedFormat(_))
+        ));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
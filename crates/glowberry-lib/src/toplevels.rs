@@ -0,0 +1,414 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Windows covering the wallpaper, through COSMIC's toplevel info or
+//! `zwlr_foreign_toplevel_manager_v1`.
+//!
+//! A fullscreen window hides the wallpaper on its output entirely, and enough
+//! tiled or maximized windows can hide most of it, so shader layers there can
+//! stop rendering until the windows move away. The compositor announces every
+//! toplevel with the outputs it is on and its state, applied together on each
+//! `done` event. Only `zcosmic_toplevel_info_v1` reports where a window is on
+//! each output; with the wlr protocol just fullscreen windows are known.
+
+use cosmic_protocols::toplevel_info::v1::client::{
+    zcosmic_toplevel_handle_v1, zcosmic_toplevel_info_v1,
+};
+use sctk::reexports::{
+    client::{Proxy, QueueHandle, backend::ObjectId, protocol::wl_output::WlOutput},
+    protocols::ext::foreign_toplevel_list::v1::client::{
+        ext_foreign_toplevel_handle_v1, ext_foreign_toplevel_list_v1,
+    },
+    protocols_wlr::foreign_toplevel::v1::client::{
+        zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1,
+    },
+};
+
+use crate::engine::GlowBerry;
+
+/// A window's rectangle on an output, in the output's logical coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// A change to a toplevel, from either protocol.
+#[derive(Debug)]
+pub enum ToplevelEvent {
+    OutputEnter(WlOutput),
+    OutputLeave(WlOutput),
+    /// Whether the window is fullscreen and whether it is minimized.
+    State {
+        fullscreen: bool,
+        minimized: bool,
+    },
+    Geometry(WlOutput, Rect),
+    Done,
+    Closed,
+}
+
+impl ToplevelEvent {
+    pub fn from_wlr(event: zwlr_foreign_toplevel_handle_v1::Event) -> Option<Self> {
+        use zwlr_foreign_toplevel_handle_v1::{Event, State};
+
+        Some(match event {
+            Event::OutputEnter { output } => Self::OutputEnter(output),
+            Event::OutputLeave { output } => Self::OutputLeave(output),
+            Event::State { state } => {
+                let states = parse_states(&state);
+                Self::State {
+                    fullscreen: states.contains(&(State::Fullscreen as u32)),
+                    minimized: states.contains(&(State::Minimized as u32)),
+                }
+            }
+            Event::Done => Self::Done,
+            Event::Closed => Self::Closed,
+            _ => return None,
+        })
+    }
+
+    pub fn from_cosmic(event: zcosmic_toplevel_handle_v1::Event) -> Option<Self> {
+        use zcosmic_toplevel_handle_v1::{Event, State};
+
+        Some(match event {
+            Event::OutputEnter { output } => Self::OutputEnter(output),
+            Event::OutputLeave { output } => Self::OutputLeave(output),
+            Event::State { state } => {
+                let states = parse_states(&state);
+                Self::State {
+                    fullscreen: states.contains(&(State::Fullscreen as u32)),
+                    minimized: states.contains(&(State::Minimized as u32)),
+                }
+            }
+            Event::Geometry {
+                output,
+                x,
+                y,
+                width,
+                height,
+            } => Self::Geometry(
+                output,
+                Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+            ),
+            Event::Done => Self::Done,
+            Event::Closed => Self::Closed,
+            _ => return None,
+        })
+    }
+
+    pub fn from_foreign(event: ext_foreign_toplevel_handle_v1::Event) -> Option<Self> {
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Done => Some(Self::Done),
+            ext_foreign_toplevel_handle_v1::Event::Closed => Some(Self::Closed),
+            _ => None,
+        }
+    }
+}
+
+/// The `u32` entries of a protocol state array.
+fn parse_states(array: &[u8]) -> Vec<u32> {
+    array
+        .chunks_exact(4)
+        .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+/// What is known about a toplevel as of a `done` event.
+#[derive(Debug, Clone, Default)]
+struct Properties {
+    outputs: Vec<WlOutput>,
+    geometry: Vec<(WlOutput, Rect)>,
+    fullscreen: bool,
+    minimized: bool,
+}
+
+#[derive(Debug)]
+enum Handle {
+    Wlr(zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1),
+    Cosmic {
+        foreign: ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        cosmic: zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1,
+    },
+}
+
+impl Handle {
+    fn matches(&self, id: &ObjectId) -> bool {
+        match self {
+            Self::Wlr(handle) => &handle.id() == id,
+            Self::Cosmic { foreign, cosmic } => &foreign.id() == id || &cosmic.id() == id,
+        }
+    }
+
+    fn destroy(&self) {
+        match self {
+            Self::Wlr(handle) => handle.destroy(),
+            Self::Cosmic { foreign, cosmic } => {
+                cosmic.destroy();
+                foreign.destroy();
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Toplevel {
+    handle: Handle,
+    current: Properties,
+    pending: Properties,
+}
+
+/// The protocol toplevels are announced through.
+#[derive(Debug)]
+enum Source {
+    Wlr {
+        _manager: zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+    },
+    Cosmic {
+        _list: ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        info: zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1,
+    },
+}
+
+/// The toplevels announced by the compositor.
+#[derive(Debug)]
+pub struct Toplevels {
+    source: Source,
+    toplevels: Vec<Toplevel>,
+}
+
+impl Toplevels {
+    pub fn wlr(manager: zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1) -> Self {
+        Self {
+            source: Source::Wlr { _manager: manager },
+            toplevels: Vec::new(),
+        }
+    }
+
+    pub fn cosmic(
+        list: ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        info: zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1,
+    ) -> Self {
+        Self {
+            source: Source::Cosmic { _list: list, info },
+            toplevels: Vec::new(),
+        }
+    }
+
+    /// Whether window positions are known, so coverage can be estimated.
+    pub fn has_geometry(&self) -> bool {
+        matches!(self.source, Source::Cosmic { .. })
+    }
+
+    /// Track a toplevel announced by the wlr manager.
+    pub fn add_wlr(
+        &mut self,
+        handle: zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+    ) {
+        self.push(Handle::Wlr(handle));
+    }
+
+    /// Track a toplevel announced by the foreign toplevel list, asking COSMIC
+    /// for its state and geometry.
+    pub fn add_foreign(
+        &mut self,
+        foreign: ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        qh: &QueueHandle<GlowBerry>,
+    ) {
+        let Source::Cosmic { info, .. } = &self.source else {
+            foreign.destroy();
+            return;
+        };
+        let cosmic = info.get_cosmic_toplevel(&foreign, qh, ());
+        self.push(Handle::Cosmic { foreign, cosmic });
+    }
+
+    fn push(&mut self, handle: Handle) {
+        self.toplevels.push(Toplevel {
+            handle,
+            current: Properties::default(),
+            pending: Properties::default(),
+        });
+    }
+
+    /// Whether any window is shown on `output`.
+    pub fn has_windows(&self, output: &WlOutput) -> bool {
+        self.visible().any(|t| t.outputs.contains(output))
+    }
+
+    /// Whether a fullscreen window is shown on `output`.
+    pub fn has_fullscreen(&self, output: &WlOutput) -> bool {
+        self.visible()
+            .any(|t| t.fullscreen && t.outputs.contains(output))
+    }
+
+    /// The fraction of an output of `width` by `height` logical pixels that
+    /// windows cover, from 0 to 1.
+    pub fn coverage(&self, output: &WlOutput, width: i32, height: i32) -> f64 {
+        let rects: Vec<Rect> = self
+            .visible()
+            .flat_map(|t| &t.geometry)
+            .filter(|(o, _)| o == output)
+            .map(|(_, rect)| *rect)
+            .collect();
+        covered_fraction(&rects, width, height)
+    }
+
+    fn visible(&self) -> impl Iterator<Item = &Properties> {
+        self.toplevels
+            .iter()
+            .map(|t| &t.current)
+            .filter(|p| !p.minimized)
+    }
+
+    /// Apply an event for the handle with `id`, returning the outputs the
+    /// toplevel was or is on if its committed state changed.
+    pub fn on_event(&mut self, id: &ObjectId, event: ToplevelEvent) -> Vec<WlOutput> {
+        let Some(index) = self.toplevels.iter().position(|t| t.handle.matches(id)) else {
+            return Vec::new();
+        };
+
+        let toplevel = &mut self.toplevels[index];
+        let pending = &mut toplevel.pending;
+        let mut affected = toplevel.current.outputs.clone();
+        match event {
+            ToplevelEvent::OutputEnter(output) => {
+                if !pending.outputs.contains(&output) {
+                    pending.outputs.push(output);
+                }
+                return Vec::new();
+            }
+            ToplevelEvent::OutputLeave(output) => {
+                pending.outputs.retain(|o| o != &output);
+                pending.geometry.retain(|(o, _)| o != &output);
+                return Vec::new();
+            }
+            ToplevelEvent::State {
+                fullscreen,
+                minimized,
+            } => {
+                pending.fullscreen = fullscreen;
+                pending.minimized = minimized;
+                return Vec::new();
+            }
+            ToplevelEvent::Geometry(output, rect) => {
+                pending.geometry.retain(|(o, _)| o != &output);
+                pending.geometry.push((output, rect));
+                return Vec::new();
+            }
+            ToplevelEvent::Done => {
+                toplevel.current = pending.clone();
+                for output in &toplevel.current.outputs {
+                    if !affected.contains(output) {
+                        affected.push(output.clone());
+                    }
+                }
+            }
+            ToplevelEvent::Closed => {
+                let toplevel = self.toplevels.remove(index);
+                toplevel.handle.destroy();
+            }
+        }
+        affected
+    }
+}
+
+/// The fraction of a `width` by `height` area at the origin covered by the
+/// union of `rects`, from 0 to 1.
+fn covered_fraction(rects: &[Rect], width: i32, height: i32) -> f64 {
+    if width <= 0 || height <= 0 {
+        return 0.0;
+    }
+
+    // Clip to the output and drop what falls outside it
+    let clipped: Vec<(i64, i64, i64, i64)> = rects
+        .iter()
+        .map(|r| {
+            (
+                i64::from(r.x.clamp(0, width)),
+                i64::from(r.y.clamp(0, height)),
+                i64::from(r.x.saturating_add(r.width).clamp(0, width)),
+                i64::from(r.y.saturating_add(r.height).clamp(0, height)),
+            )
+        })
+        .filter(|(x0, y0, x1, y1)| x1 > x0 && y1 > y0)
+        .collect();
+
+    // Sweep the vertical strips between rectangle edges, summing the union
+    // of the rectangles' spans within each
+    let mut xs: Vec<i64> = clipped.iter().flat_map(|r| [r.0, r.2]).collect();
+    xs.sort_unstable();
+    xs.dedup();
+
+    let mut area = 0i64;
+    for strip in xs.windows(2) {
+        let (left, right) = (strip[0], strip[1]);
+        let mut spans: Vec<(i64, i64)> = clipped
+            .iter()
+            .filter(|r| r.0 <= left && r.2 >= right)
+            .map(|r| (r.1, r.3))
+            .collect();
+        spans.sort_unstable();
+
+        let mut covered = 0;
+        let mut end = i64::MIN;
+        for (top, bottom) in spans {
+            let top = top.max(end);
+            if bottom > top {
+                covered += bottom - top;
+            }
+            end = end.max(bottom);
+        }
+        area += covered * (right - left);
+    }
+
+    area as f64 / (i64::from(width) * i64::from(height)) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn no_windows_cover_nothing() {
+        assert_eq!(covered_fraction(&[], 1920, 1080), 0.0);
+    }
+
+    #[test]
+    fn overlapping_windows_count_once() {
+        let rects = [rect(0, 0, 960, 1080), rect(480, 0, 960, 1080)];
+        assert_eq!(covered_fraction(&rects, 1920, 1080), 0.75);
+    }
+
+    #[test]
+    fn windows_are_clipped_to_the_output() {
+        let rects = [rect(-100, -100, 2200, 1300)];
+        assert_eq!(covered_fraction(&rects, 1920, 1080), 1.0);
+        assert_eq!(
+            covered_fraction(&[rect(1920, 0, 500, 500)], 1920, 1080),
+            0.0
+        );
+    }
+
+    #[test]
+    fn tiled_windows_with_gaps() {
+        let rects = [rect(0, 0, 900, 1080), rect(1020, 0, 900, 1080)];
+        let expected = 1800.0 / 1920.0;
+        assert!((covered_fraction(&rects, 1920, 1080) - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,305 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Transitions between wallpapers: how a switch animates from the frame
+//! shown before to the new one.
+//!
+//! An entry's `transition` picks the style, easing, and duration. Static
+//! outputs blend their frames on the CPU, see [`blend`], in 16 bits per
+//! channel while either frame is drawn in 10; shader layers blend them in
+//! their post-process pass, whose shader includes [`SHADER`]. Both show the
+//! same styles at the same progress, see [`progress`].
+
+use std::time::Instant;
+
+use glowberry_config::{Easing, Transition, TransitionStyle};
+use image::{DynamicImage, ImageBuffer, Pixel};
+
+/// Largest block of a pixelate transition, in pixels, at its middle.
+const MAX_BLOCK: f32 = 48.0;
+
+/// How much larger the old frame grows by the end of a zoom transition.
+const ZOOM: f32 = 0.5;
+
+/// WGSL of the transitions, for the post-process shader. It defines
+/// `shown(uv)` and `before(uv)`, the new and old frames at `uv`.
+pub const SHADER: &str = r#"
+// Transitions of the wallpaper from the previous frame, see transition.rs
+
+const MAX_BLOCK: f32 = 48.0;
+const ZOOM: f32 = 0.5;
+
+fn transitioned(fragCoord: vec2<f32>, size: vec2<f32>, t: f32, style: i32) -> vec4<f32> {
+    let uv = fragCoord / size;
+    var color: vec4<f32>;
+    switch style {
+        // Slide: the new frame pushes the previous one out to the left
+        case 1: {
+            if uv.x < 1.0 - t {
+                color = before(vec2<f32>(uv.x + t, uv.y));
+            } else {
+                color = shown(vec2<f32>(uv.x - 1.0 + t, uv.y));
+            }
+        }
+        // Wipe: the new frame is uncovered from left to right
+        case 2: {
+            if uv.x < t {
+                color = shown(uv);
+            } else {
+                color = before(uv);
+            }
+        }
+        // Pixelate: blocks grow over the previous frame, then shrink over
+        // the new one
+        case 3: {
+            let block = max(1.0, round(MAX_BLOCK * (1.0 - abs(2.0 * t - 1.0))));
+            let snapped = (floor(fragCoord / block) + 0.5) * block / size;
+            if t < 0.5 {
+                color = before(snapped);
+            } else {
+                color = shown(snapped);
+            }
+        }
+        // Zoom: the previous frame grows and fades out over the new one
+        case 4: {
+            let zoomed = (uv - 0.5) / (1.0 + ZOOM * t) + 0.5;
+            color = mix(before(zoomed), shown(uv), t);
+        }
+        default: {
+            color = mix(before(uv), shown(uv), t);
+        }
+    }
+    return color;
+}
+"#;
+
+/// Index of `style` in [`SHADER`].
+pub fn style_index(style: TransitionStyle) -> i32 {
+    match style {
+        TransitionStyle::Crossfade => 0,
+        TransitionStyle::Slide => 1,
+        TransitionStyle::Wipe => 2,
+        TransitionStyle::Pixelate => 3,
+        TransitionStyle::Zoom => 4,
+    }
+}
+
+/// How far a transition that began at `start` is, from 0 to 1. Transitions
+/// without a valid duration are over at once.
+pub fn progress(transition: &Transition, start: Instant) -> f32 {
+    let duration = transition.duration;
+    if !(duration.is_finite() && duration > 0.0) {
+        return 1.0;
+    }
+    (start.elapsed().as_secs_f32() / duration).min(1.0)
+}
+
+/// The `progress` of a transition, from 0 to 1, along `easing`.
+pub fn ease(easing: Easing, progress: f32) -> f32 {
+    let t = if progress.is_nan() {
+        0.0
+    } else {
+        progress.clamp(0.0, 1.0)
+    };
+    match easing {
+        Easing::Linear => t,
+        Easing::EaseIn => t * t,
+        Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+        Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+    }
+}
+
+/// Channels of the frames [`blend`] mixes.
+pub trait Channel: Copy {
+    /// `from` mixed into `to` by `weight` out of 256.
+    fn mix(from: Self, to: Self, weight: u32) -> Self;
+}
+
+impl Channel for u8 {
+    fn mix(from: Self, to: Self, weight: u32) -> Self {
+        ((u32::from(from) * (256 - weight) + u32::from(to) * weight) >> 8) as u8
+    }
+}
+
+impl Channel for u16 {
+    fn mix(from: Self, to: Self, weight: u32) -> Self {
+        ((u32::from(from) * (256 - weight) + u32::from(to) * weight) >> 8) as u16
+    }
+}
+
+/// `image` as a frame to transition with: 16 bits per channel if `deep`,
+/// else 8, see [`blend_frames`].
+pub fn frame(image: &DynamicImage, deep: bool) -> DynamicImage {
+    if deep {
+        DynamicImage::ImageRgba16(image.to_rgba16())
+    } else {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    }
+}
+
+/// [`blend`] two frames made by [`frame`], or `None` if their depths differ.
+pub fn blend_frames(
+    from: &DynamicImage,
+    to: &DynamicImage,
+    style: TransitionStyle,
+    amount: f32,
+) -> Option<DynamicImage> {
+    match (from, to) {
+        (DynamicImage::ImageRgba8(from), DynamicImage::ImageRgba8(to)) => {
+            Some(DynamicImage::ImageRgba8(blend(from, to, style, amount)))
+        }
+        (DynamicImage::ImageRgba16(from), DynamicImage::ImageRgba16(to)) => {
+            Some(DynamicImage::ImageRgba16(blend(from, to, style, amount)))
+        }
+        _ => None,
+    }
+}
+
+/// Frame of a transition from `from` to `to`, frames of the same size, at
+/// `amount` along it: 0.0 shows `from`, 1.0 shows `to`.
+pub fn blend<P>(
+    from: &ImageBuffer<P, Vec<P::Subpixel>>,
+    to: &ImageBuffer<P, Vec<P::Subpixel>>,
+    style: TransitionStyle,
+    amount: f32,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel,
+    P::Subpixel: Channel,
+{
+    let amount = if amount.is_nan() {
+        0.0
+    } else {
+        amount.clamp(0.0, 1.0)
+    };
+    let (width, height) = to.dimensions();
+
+    match style {
+        TransitionStyle::Crossfade => crossfade(from, to, amount),
+        TransitionStyle::Slide => {
+            let offset = (amount * width as f32).round() as u32;
+            ImageBuffer::from_fn(width, height, |x, y| {
+                if x + offset < width {
+                    *from.get_pixel(x + offset, y)
+                } else {
+                    *to.get_pixel(x + offset - width, y)
+                }
+            })
+        }
+        TransitionStyle::Wipe => {
+            let edge = (amount * width as f32).round() as u32;
+            ImageBuffer::from_fn(width, height, |x, y| {
+                if x < edge {
+                    *to.get_pixel(x, y)
+                } else {
+                    *from.get_pixel(x, y)
+                }
+            })
+        }
+        TransitionStyle::Pixelate => {
+            let block = (MAX_BLOCK * (1.0 - (2.0 * amount - 1.0).abs()))
+                .round()
+                .max(1.0) as u32;
+            let source = if amount < 0.5 { from } else { to };
+            ImageBuffer::from_fn(width, height, |x, y| {
+                let center = |v: u32, len: u32| (v / block * block + block / 2).min(len - 1);
+                *source.get_pixel(center(x, width), center(y, height))
+            })
+        }
+        TransitionStyle::Zoom => {
+            let scale = 1.0 + ZOOM * amount;
+            let zoomed = |v: u32, len: u32| {
+                let half = len as f32 / 2.0;
+                (((v as f32 + 0.5 - half) / scale + half) as u32).min(len - 1)
+            };
+            let grown = ImageBuffer::from_fn(width, height, |x, y| {
+                *from.get_pixel(zoomed(x, width), zoomed(y, height))
+            });
+            crossfade(&grown, to, amount)
+        }
+    }
+}
+
+/// Mix `from` into `to` by `amount`, from 0.0 to 1.0.
+fn crossfade<P>(
+    from: &ImageBuffer<P, Vec<P::Subpixel>>,
+    to: &ImageBuffer<P, Vec<P::Subpixel>>,
+    amount: f32,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel,
+    P::Subpixel: Channel,
+{
+    let weight = (amount * 256.0).round() as u32;
+    let mut blended = to.clone();
+    for (value, &from) in blended.iter_mut().zip(from.iter()) {
+        *value = Channel::mix(from, *value, weight);
+    }
+    blended
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blends_frames_in_each_style() {
+        let from = image::RgbaImage::from_pixel(4, 2, image::Rgba([0, 100, 255, 255]));
+        let to = image::RgbaImage::from_pixel(4, 2, image::Rgba([200, 100, 55, 255]));
+        for style in [
+            TransitionStyle::Crossfade,
+            TransitionStyle::Slide,
+            TransitionStyle::Wipe,
+            TransitionStyle::Pixelate,
+            TransitionStyle::Zoom,
+        ] {
+            assert_eq!(blend(&from, &to, style, 0.0), from, "{style:?}");
+            assert_eq!(blend(&from, &to, style, 1.0), to, "{style:?}");
+        }
+
+        let half = blend(&from, &to, TransitionStyle::Crossfade, 0.5);
+        assert_eq!(half.get_pixel(1, 0).0, [100, 100, 155, 255]);
+        assert_eq!(
+            blend(&from, &to, TransitionStyle::Crossfade, f32::NAN),
+            from
+        );
+
+        let wiped = blend(&from, &to, TransitionStyle::Wipe, 0.5);
+        assert_eq!(wiped.get_pixel(1, 0), to.get_pixel(1, 0));
+        assert_eq!(wiped.get_pixel(2, 0), from.get_pixel(2, 0));
+    }
+
+    #[test]
+    fn blends_frames_in_their_depth() {
+        let from = image::Rgba16Image::from_pixel(2, 1, image::Rgba([0, 1000, 65535, 65535]));
+        let to = image::Rgba16Image::from_pixel(2, 1, image::Rgba([65535, 1000, 1, 65535]));
+        let half = blend(&from, &to, TransitionStyle::Crossfade, 0.5);
+        assert_eq!(half.get_pixel(0, 0).0, [32767, 1000, 32768, 65535]);
+
+        let deep = frame(&DynamicImage::ImageRgba16(from), true);
+        let shallow = frame(&DynamicImage::ImageRgba16(to), false);
+        assert!(matches!(shallow, DynamicImage::ImageRgba8(_)));
+        assert!(blend_frames(&deep, &shallow, TransitionStyle::Crossfade, 0.5).is_none());
+        assert!(matches!(
+            blend_frames(&deep, &deep, TransitionStyle::Wipe, 0.5),
+            Some(DynamicImage::ImageRgba16(_))
+        ));
+    }
+
+    #[test]
+    fn eases_progress() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(ease(easing, 0.0), 0.0);
+            assert_eq!(ease(easing, 1.0), 1.0);
+            assert_eq!(ease(easing, 2.0), 1.0);
+        }
+        assert_eq!(ease(Easing::Linear, 0.25), 0.25);
+        assert!(ease(Easing::EaseIn, 0.5) < 0.5);
+        assert!(ease(Easing::EaseOut, 0.5) > 0.5);
+        assert_eq!(ease(Easing::EaseInOut, 0.5), 0.5);
+    }
+}
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Packed uniform storage for a shader canvas.
+//!
+//! All of a canvas's uniforms live in one buffer and are uploaded with a
+//! single `write_buffer` per frame. Each uniform keeps its own binding, so
+//! shaders (and the settings preview, which shares the preamble) still see
+//! `iTime`, `iResolution`, and friends by name: the bind group binds a range
+//! of the shared buffer per uniform, at offsets aligned to the device's
+//! `min_uniform_buffer_offset_alignment`.
+
+use std::num::NonZeroU64;
+
+/// A uniform stored in the shared buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uniform {
+    /// `iResolution: vec2f`
+    Resolution,
+    /// `iTime: f32`
+    Time,
+    /// `iBattery: vec4f`
+    Battery,
+    /// `iWeather: vec4f`
+    Weather,
+    /// `iSystem: vec4f`
+    System,
+    /// `iLocalTime: vec4f`
+    LocalTime,
+    /// `iParams: array<vec4f, 4>`
+    Params,
+    /// `iPointer: vec4f`
+    Pointer,
+}
+
+impl Uniform {
+    pub const ALL: [Self; 8] = [
+        Self::Resolution,
+        Self::Time,
+        Self::Battery,
+        Self::Weather,
+        Self::System,
+        Self::LocalTime,
+        Self::Params,
+        Self::Pointer,
+    ];
+
+    /// Binding index in the shader preamble.
+    pub fn binding(self) -> u32 {
+        match self {
+            Self::Resolution => 0,
+            Self::Time => 1,
+            Self::Battery => 5,
+            Self::Weather => 6,
+            Self::System => 7,
+            Self::LocalTime => 8,
+            Self::Params => 9,
+            Self::Pointer => 16,
+        }
+    }
+
+    /// Size of the WGSL type, in bytes.
+    pub fn size(self) -> u64 {
+        match self {
+            Self::Resolution => 8,
+            Self::Time => 4,
+            Self::Battery | Self::Weather | Self::System | Self::LocalTime | Self::Pointer => 16,
+            Self::Params => 64,
+        }
+    }
+
+    fn index(self) -> u64 {
+        Self::ALL
+            .iter()
+            .position(|&u| u == self)
+            .unwrap_or_default() as u64
+    }
+}
+
+/// Distance between uniform slots for a device offset `alignment`.
+fn slot_stride(alignment: u32) -> u64 {
+    let largest = Uniform::ALL.iter().map(|u| u.size()).max().unwrap_or(16);
+    largest.next_multiple_of(u64::from(alignment.max(1)))
+}
+
+/// CPU-side copy of the uniforms plus the GPU buffer it is uploaded to.
+#[derive(Debug)]
+pub struct UniformBlock {
+    buffer: wgpu::Buffer,
+    stride: u64,
+    data: Vec<u8>,
+}
+
+impl UniformBlock {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let stride = slot_stride(device.limits().min_uniform_buffer_offset_alignment);
+        let size = stride * Uniform::ALL.len() as u64;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("glowberry: uniform buffer"),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            stride,
+            data: vec![0; size as usize],
+        }
+    }
+
+    /// Stage `values` for `uniform`; uploaded on the next [`Self::upload`].
+    pub fn set(&mut self, uniform: Uniform, values: &[f32]) {
+        let bytes: &[u8] = bytemuck::cast_slice(values);
+        let offset = (uniform.index() * self.stride) as usize;
+        let len = bytes.len().min(uniform.size() as usize);
+        self.data[offset..offset + len].copy_from_slice(&bytes[..len]);
+    }
+
+    /// Upload all staged uniforms in one write.
+    pub fn upload(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.buffer, 0, &self.data);
+    }
+
+    /// Bind group resource for `uniform`'s range of the buffer.
+    pub fn binding(&self, uniform: Uniform) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: uniform.index() * self.stride,
+            size: NonZeroU64::new(uniform.size()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_fit_the_largest_uniform_and_respect_alignment() {
+        assert_eq!(slot_stride(256), 256);
+        assert_eq!(slot_stride(32), 64);
+        assert_eq!(slot_stride(64), 64);
+    }
+
+    #[test]
+    fn bindings_match_the_preamble() {
+        let bindings: Vec<u32> = Uniform::ALL.iter().map(|u| u.binding()).collect();
+        assert_eq!(bindings, [0, 1, 5, 6, 7, 8, 9, 16]);
+    }
+}
@@ -0,0 +1,580 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Video wallpapers.
+//!
+//! A video entry plays its file in a loop through a GStreamer pipeline.
+//! `decodebin3` picks the highest-ranked decoder, so hardware decoders such as
+//! the VA-API ones are used where installed; frames are then converted to RGBA
+//! in system memory, capped to the texture budget's longest edge, and kept by
+//! an app sink that drops all but the latest one. Each frame is uploaded once,
+//! with its rows as decoded, to a texture shared by every layer showing the
+//! video, see [`VideoPlayer::upload`].
+//!
+//! Video layers are shader layers running a built-in shader, see
+//! [`shader_source`], which samples the latest frame through `iVideo` and
+//! scales it like a static image. They thus follow the same frame pacing and
+//! pause rules as shaders. While no layer asks for frames, because animation
+//! is paused or every output showing the video is, decoding pauses as well.
+//!
+//! [`encode`] runs the other way, from RGBA frames through an encoder to a
+//! video file; time-lapses are exported with it.
+//!
+//! Playback and export are only available with the `video` cargo feature;
+//! without it [`VideoPlayer::start`] returns `None` and the layers stay
+//! black, and [`encode`] fails.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use glowberry_config::{ScalingMode, ShaderContent, ShaderLanguage, ShaderSource, VideoSource};
+
+/// How long decoding continues without a layer asking for frames.
+#[cfg_attr(not(feature = "video"), allow(dead_code))]
+const IDLE_PAUSE: Duration = Duration::from_secs(1);
+
+/// A decoded video frame, RGBA rows `stride` bytes apart.
+#[derive(Debug)]
+pub struct VideoFrame {
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub data: Vec<u8>,
+    /// Increases with every frame, so layers upload each one once.
+    pub sequence: u64,
+}
+
+/// Frames handed from the streaming thread to the engine.
+///
+/// Both mutexes only hold plain values that are replaced whole, so a panic
+/// on one side while holding them leaves nothing half written, and the
+/// other side recovers them from poisoning.
+#[derive(Debug)]
+struct Shared {
+    frame: Mutex<Option<Arc<VideoFrame>>>,
+    /// When a layer last asked for a frame.
+    last_request: Mutex<Instant>,
+    /// Whether decoding was paused for lack of requests.
+    #[cfg_attr(not(feature = "video"), allow(dead_code))]
+    paused: AtomicBool,
+}
+
+/// A looping video being decoded. Stops when dropped.
+pub struct VideoPlayer {
+    shared: Arc<Shared>,
+    /// Texture showing the latest frame to every layer, once uploaded.
+    texture: Option<VideoTexture>,
+    #[cfg(feature = "video")]
+    pipeline: gstreamer::Pipeline,
+}
+
+impl std::fmt::Debug for VideoPlayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VideoPlayer").finish_non_exhaustive()
+    }
+}
+
+impl VideoPlayer {
+    /// Start playing the video at `path`, decoding frames no larger than
+    /// `max_dimension` on their longest edge.
+    ///
+    /// Returns `None` if the `video` feature is disabled or the pipeline
+    /// can't be started.
+    #[cfg_attr(not(feature = "video"), allow(unused_variables))]
+    pub fn start(path: &Path, max_dimension: u32) -> Option<Self> {
+        let shared = Arc::new(Shared {
+            frame: Mutex::new(None),
+            last_request: Mutex::new(Instant::now()),
+            paused: AtomicBool::new(false),
+        });
+
+        #[cfg(feature = "video")]
+        {
+            match playback::start(path, max_dimension, shared.clone()) {
+                Ok(pipeline) => {
+                    tracing::info!(path = %path.display(), "Playing video wallpaper");
+                    Some(Self {
+                        shared,
+                        texture: None,
+                        pipeline,
+                    })
+                }
+                Err(why) => {
+                    tracing::warn!(?why, path = %path.display(), "Failed to play video");
+                    None
+                }
+            }
+        }
+
+        #[cfg(not(feature = "video"))]
+        {
+            let _ = shared;
+            tracing::warn!("Video wallpapers need GlowBerry built with the `video` feature");
+            None
+        }
+    }
+
+    /// The latest decoded frame, resuming decoding if it was paused.
+    pub fn latest(&self) -> Option<Arc<VideoFrame>> {
+        *self
+            .shared
+            .last_request
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+
+        #[cfg(feature = "video")]
+        if self.shared.paused.swap(false, Ordering::AcqRel) {
+            use gstreamer::prelude::*;
+            if let Err(why) = self.pipeline.set_state(gstreamer::State::Playing) {
+                tracing::warn!(?why, "Failed to resume video");
+            }
+        }
+
+        self.shared
+            .frame
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Upload the latest decoded frame, unless it already is, to the
+    /// texture every layer showing the video samples.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<&VideoTexture> {
+        let frame = self.latest()?;
+        let texture = self
+            .texture
+            .get_or_insert_with(|| VideoTexture::new(device, queue));
+        texture.update(device, queue, &frame);
+        Some(texture)
+    }
+}
+
+#[cfg(feature = "video")]
+impl Drop for VideoPlayer {
+    fn drop(&mut self) {
+        use gstreamer::prelude::*;
+        let _ = self.pipeline.set_state(gstreamer::State::Null);
+    }
+}
+
+/// The built-in shader showing `video` scaled with `scaling`.
+pub fn shader_source(video: &VideoSource, scaling: &ScalingMode) -> ShaderSource {
+    ShaderSource {
+        shader: ShaderContent::Code(shader_code(scaling)),
+        source_path: None,
+        params: Default::default(),
+        background_image: None,
+        depth_image: None,
+        background_from_wallpaper: false,
+        background_scaling: ScalingMode::default(),
+        language: ShaderLanguage::Wgsl,
+        frame_rate: video.frame_rate,
+        time_scale: 1.0,
+    }
+}
+
+/// WGSL sampling `iVideo` like a static image scaled with `scaling`.
+fn shader_code(scaling: &ScalingMode) -> String {
+    let (mode, [r, g, b]) = match scaling {
+        ScalingMode::Zoom | ScalingMode::Span => (0, [0.0; 3]),
+        ScalingMode::Fit(color) => (1, *color),
+        ScalingMode::Stretch => (2, [0.0; 3]),
+        ScalingMode::Tile => (3, [0.0; 3]),
+        ScalingMode::Center(color) => (4, *color),
+    };
+
+    format!(
+        r#"// Built-in shader of video wallpapers
+
+// 0 zooms to cover the output, 1 fits the whole frame, 2 stretches it,
+// 3 tiles it and 4 centers it at its own size
+const MODE: i32 = {mode};
+const FILL: vec3<f32> = vec3<f32>({r:?}, {g:?}, {b:?});
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {{
+    let video = vec2<f32>(textureDimensions(iVideo));
+    let ratio = iResolution / video;
+    var scale = ratio;
+    if MODE == 0 {{
+        scale = vec2<f32>(max(ratio.x, ratio.y));
+    }} else if MODE == 1 {{
+        scale = vec2<f32>(min(ratio.x, ratio.y));
+    }} else if MODE >= 3 {{
+        scale = vec2<f32>(1.0);
+    }}
+
+    let shown = video * scale;
+    var uv = (fragCoord.xy - (iResolution - shown) * 0.5) / shown;
+    if MODE == 3 {{
+        uv = fract(fragCoord.xy / video);
+    }}
+    let color = textureSampleLevel(iVideo, iVideoSampler, uv, 0.0).rgb;
+    let inside = all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0));
+    return vec4<f32>(select(FILL, color, inside), 1.0);
+}}
+"#
+    )
+}
+
+/// Numbers the textures created for videos, telling layers when the one
+/// they bind was replaced.
+static TEXTURES: AtomicU64 = AtomicU64::new(0);
+
+/// The `iVideo` texture and the frame it shows. Clones share the texture.
+#[derive(Debug, Clone)]
+pub struct VideoTexture {
+    pub(crate) texture: wgpu::Texture,
+    pub(crate) sampler: wgpu::Sampler,
+    /// Distinguishes this texture from the ones it replaced.
+    pub(crate) id: u64,
+    shown: Option<u64>,
+}
+
+impl VideoTexture {
+    /// Create a black 1x1 texture, replaced by the first frame.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let texture = Self::create_texture(device, 1, 1);
+        queue.write_texture(
+            texture.as_image_copy(),
+            &[0, 0, 0, 0xff],
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4),
+                rows_per_image: Some(1),
+            },
+            texture.size(),
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("glowberry: iVideo sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            sampler,
+            id: TEXTURES.fetch_add(1, Ordering::Relaxed),
+            shown: None,
+        }
+    }
+
+    fn create_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glowberry: iVideo texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    /// Upload `frame` unless it is already shown. Returns whether the
+    /// texture was replaced because the frame size changed.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &VideoFrame,
+    ) -> bool {
+        if self.shown == Some(frame.sequence) {
+            return false;
+        }
+        self.shown = Some(frame.sequence);
+
+        let resized = (self.texture.width(), self.texture.height()) != (frame.width, frame.height);
+        if resized {
+            self.texture = Self::create_texture(device, frame.width, frame.height);
+            self.id = TEXTURES.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // Unlike buffer copies, queue writes take rows at any stride
+        queue.write_texture(
+            self.texture.as_image_copy(),
+            &frame.data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(frame.stride),
+                rows_per_image: Some(frame.height),
+            },
+            self.texture.size(),
+        );
+        resized
+    }
+}
+
+/// GStreamer elements encoding and muxing a video written to `out`, chosen
+/// by its extension: `.webm`, `.mp4`, or `.mkv`.
+pub fn encoder_for(out: &Path) -> Option<&'static str> {
+    let ext = out.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "webm" => "vp9enc deadline=1 ! webmmux",
+        "mp4" => "x264enc ! mp4mux",
+        "mkv" => "x264enc ! matroskamux",
+        _ => return None,
+    })
+}
+
+/// Encode `frames` of `width`x`height` packed RGBA pixels into the video at
+/// `out`, played at `fps`. Returns the number of frames written.
+///
+/// The codec and container follow the extension of `out`, see
+/// [`encoder_for`]. Frames are converted to 4:2:0, so `width` and `height`
+/// should be even. Fails if the `video` feature is disabled.
+#[cfg_attr(not(feature = "video"), allow(unused_variables))]
+pub fn encode(
+    out: &Path,
+    width: u32,
+    height: u32,
+    fps: u16,
+    frames: impl IntoIterator<Item = Vec<u8>>,
+) -> eyre::Result<usize> {
+    let encoder =
+        encoder_for(out).ok_or_else(|| eyre::eyre!("no video encoder for {}", out.display()))?;
+
+    #[cfg(feature = "video")]
+    {
+        export::encode(out, encoder, width, height, fps, frames)
+    }
+
+    #[cfg(not(feature = "video"))]
+    {
+        let _ = encoder;
+        Err(eyre::eyre!(
+            "Exporting videos needs GlowBerry built with the `video` feature"
+        ))
+    }
+}
+
+#[cfg(feature = "video")]
+mod playback {
+    use super::{AtomicU64, IDLE_PAUSE, Ordering, Shared, VideoFrame};
+    use gstreamer as gst;
+    use gstreamer::prelude::*;
+    use gstreamer_app as gst_app;
+    use gstreamer_video as gst_video;
+    use std::path::Path;
+    use std::sync::{Arc, PoisonError};
+
+    /// Build and start the pipeline decoding `path` into `shared`.
+    pub fn start(
+        path: &Path,
+        max_dimension: u32,
+        shared: Arc<Shared>,
+    ) -> eyre::Result<gst::Pipeline> {
+        gst::init()?;
+
+        let max = max_dimension.max(1);
+        let pipeline = gst::parse::launch(&format!(
+            "filesrc name=src ! decodebin3 ! videoconvert ! videoscale ! \
+             video/x-raw,format=RGBA,pixel-aspect-ratio=1/1,width=[1,{max}],height=[1,{max}] ! \
+             appsink name=sink max-buffers=1 drop=true sync=true"
+        ))?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| eyre::eyre!("not a pipeline"))?;
+
+        let src = pipeline
+            .by_name("src")
+            .ok_or_else(|| eyre::eyre!("no file source"))?;
+        src.set_property("location", path.to_string_lossy().as_ref());
+
+        let sink = pipeline
+            .by_name("sink")
+            .and_then(|sink| sink.downcast::<gst_app::AppSink>().ok())
+            .ok_or_else(|| eyre::eyre!("no app sink"))?;
+
+        let weak = pipeline.downgrade();
+        let sequence = AtomicU64::new(0);
+        sink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+                    let info = sample
+                        .caps()
+                        .and_then(|caps| gst_video::VideoInfo::from_caps(caps).ok())
+                        .ok_or(gst::FlowError::NotNegotiated)?;
+                    let frame = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info)
+                        .map_err(|_| gst::FlowError::Error)?;
+                    let plane = frame.plane_data(0).map_err(|_| gst::FlowError::Error)?;
+                    let stride = frame.plane_stride()[0] as u32;
+                    let data = plane[..plane.len().min((stride * info.height()) as usize)].to_vec();
+
+                    *shared.frame.lock().unwrap_or_else(PoisonError::into_inner) =
+                        Some(Arc::new(VideoFrame {
+                            width: info.width(),
+                            height: info.height(),
+                            stride,
+                            data,
+                            sequence: sequence.fetch_add(1, Ordering::Relaxed) + 1,
+                        }));
+
+                    // Nobody shows the frames; resumed by `VideoPlayer::latest`
+                    if shared
+                        .last_request
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .elapsed()
+                        >= IDLE_PAUSE
+                        && !shared.paused.swap(true, Ordering::AcqRel)
+                        && let Some(pipeline) = weak.upgrade()
+                    {
+                        tracing::debug!("No layer shows the video, pausing decoding");
+                        pipeline.call_async(|pipeline| {
+                            let _ = pipeline.set_state(gst::State::Paused);
+                        });
+                    }
+
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+
+        // Loop at the end, and log errors
+        let weak = pipeline.downgrade();
+        let bus = pipeline.bus().ok_or_else(|| eyre::eyre!("no bus"))?;
+        bus.set_sync_handler(move |_, message| {
+            match message.view() {
+                gst::MessageView::Eos(_) => {
+                    if let Some(pipeline) = weak.upgrade() {
+                        pipeline.call_async(|pipeline| {
+                            if let Err(why) = pipeline.seek_simple(
+                                gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
+                                gst::ClockTime::ZERO,
+                            ) {
+                                tracing::warn!(?why, "Failed to loop video");
+                            }
+                        });
+                    }
+                }
+                gst::MessageView::Error(error) => {
+                    tracing::warn!(
+                        error = %error.error(),
+                        debug = ?error.debug(),
+                        "Video playback failed"
+                    );
+                }
+                _ => {}
+            }
+            gst::BusSyncReply::Drop
+        });
+
+        pipeline.set_state(gst::State::Playing)?;
+        Ok(pipeline)
+    }
+}
+
+#[cfg(feature = "video")]
+mod export {
+    use gstreamer as gst;
+    use gstreamer::prelude::*;
+    use gstreamer_app as gst_app;
+    use gstreamer_video as gst_video;
+    use std::path::Path;
+
+    /// Push `frames` through `encoder` into `out`, then wait for the file
+    /// to be finished.
+    pub fn encode(
+        out: &Path,
+        encoder: &str,
+        width: u32,
+        height: u32,
+        fps: u16,
+        frames: impl IntoIterator<Item = Vec<u8>>,
+    ) -> eyre::Result<usize> {
+        gst::init()?;
+
+        // A blocking source holds frames back while the encoder catches up
+        let pipeline = gst::parse::launch(&format!(
+            "appsrc name=src format=time block=true ! videoconvert ! \
+             video/x-raw,format=I420 ! {encoder} ! filesink name=sink"
+        ))?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| eyre::eyre!("not a pipeline"))?;
+
+        let sink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| eyre::eyre!("no file sink"))?;
+        sink.set_property("location", out.to_string_lossy().as_ref());
+
+        let src = pipeline
+            .by_name("src")
+            .and_then(|src| src.downcast::<gst_app::AppSrc>().ok())
+            .ok_or_else(|| eyre::eyre!("no app source"))?;
+        let fps = fps.max(1);
+        let info = gst_video::VideoInfo::builder(gst_video::VideoFormat::Rgba, width, height)
+            .fps(gst::Fraction::new(i32::from(fps), 1))
+            .build()?;
+        src.set_caps(Some(&info.to_caps()?));
+
+        pipeline.set_state(gst::State::Playing)?;
+
+        let frame_ns = 1_000_000_000 / u64::from(fps);
+        let mut written = 0;
+        for data in frames {
+            let mut buffer = gst::Buffer::from_mut_slice(data);
+            if let Some(buffer) = buffer.get_mut() {
+                buffer.set_pts(gst::ClockTime::from_nseconds(written as u64 * frame_ns));
+                buffer.set_duration(gst::ClockTime::from_nseconds(frame_ns));
+            }
+            // Fails once the pipeline did, which the bus then reports
+            if src.push_buffer(buffer).is_err() {
+                break;
+            }
+            written += 1;
+        }
+        let _ = src.end_of_stream();
+
+        let bus = pipeline.bus().ok_or_else(|| eyre::eyre!("no bus"))?;
+        let finished = bus.timed_pop_filtered(
+            gst::ClockTime::NONE,
+            &[gst::MessageType::Eos, gst::MessageType::Error],
+        );
+        let _ = pipeline.set_state(gst::State::Null);
+
+        match finished.as_ref().map(|message| message.view()) {
+            Some(gst::MessageView::Error(error)) => {
+                Err(eyre::eyre!("{} ({:?})", error.error(), error.debug()))
+            }
+            _ => Ok(written),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_scales_like_the_entry() {
+        assert!(shader_code(&ScalingMode::Zoom).contains("const MODE: i32 = 0;"));
+        let fit = shader_code(&ScalingMode::Fit([0.5, 0.25, 1.0]));
+        assert!(fit.contains("const MODE: i32 = 1;"));
+        assert!(fit.contains("vec3<f32>(0.5, 0.25, 1.0)"));
+        assert!(shader_code(&ScalingMode::Stretch).contains("const MODE: i32 = 2;"));
+        assert!(shader_code(&ScalingMode::Tile).contains("const MODE: i32 = 3;"));
+        assert!(crate::shader_defs::uses_video(&shader_code(
+            &ScalingMode::Zoom
+        )));
+    }
+
+    #[test]
+    fn encoder_follows_the_extension() {
+        assert_eq!(
+            encoder_for(Path::new("day.webm")),
+            Some("vp9enc deadline=1 ! webmmux")
+        );
+        assert_eq!(encoder_for(Path::new("day.MP4")), Some("x264enc ! mp4mux"));
+        assert_eq!(encoder_for(Path::new("day.gif")), None);
+        assert_eq!(encoder_for(Path::new("day")), None);
+    }
+}
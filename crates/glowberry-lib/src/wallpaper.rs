@@ -1,27 +1,54 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::{colored, draw, engine::GlowBerry, engine::GlowBerryLayer, scaler};
+use crate::{
+    animation::Animation,
+    blurhash, calendar, colored, composite, dmabuf, draw,
+    dynamic::DynamicWallpaper,
+    engine::GlowBerry,
+    engine::GlowBerryLayer,
+    image_formats, kde_dynamic, ken_burns, night_light,
+    online::OnlineFeed,
+    parallax, post_process,
+    predecode::{self, Predecoded},
+    raw,
+    remote::{self, RemoteEvent, RemoteImage},
+    scaled_cache::ScaledCache,
+    scaler, shuffle, timelapse, transition,
+    video::{self, VideoPlayer},
+};
 use cosmic_config::CosmicConfigEntry;
 use eyre::eyre;
 use glowberry_config::{
-    Color, Entry, SamplingMethod, ScalingMode, ShaderContent, ShaderSource, Source, state::State,
+    Color, CompositeSource, Crop, Entry, ImageFilter, OnlineSource, ParallaxSource, QueueEdit,
+    SamplingMethod, ScalingMode, ShaderContent, ShaderSource, Source,
+    state::{Shuffle, SlideshowPosition, State},
 };
-use image::{DynamicImage, ImageReader};
+use image::DynamicImage;
 use jxl_oxide::integration::JxlDecoder;
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use rand::{rng, seq::SliceRandom};
-use sctk::reexports::{
-    calloop::{
-        self, RegistrationToken,
-        timer::{TimeoutAction, Timer},
+use sctk::{
+    compositor::CompositorState,
+    output::OutputInfo,
+    reexports::{
+        calloop::{
+            self, RegistrationToken,
+            timer::{TimeoutAction, Timer},
+        },
+        client::QueueHandle,
     },
-    client::QueueHandle,
+    shell::WaylandSurface,
 };
 use std::{
-    collections::VecDeque,
+    collections::{BTreeMap, HashMap, VecDeque},
     fs::{self, File},
-    path::PathBuf,
-    time::{Duration, Instant},
+    hash::{DefaultHasher, Hasher},
+    io::Read,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
 };
 use tracing::error;
 use walkdir::WalkDir;
@@ -32,12 +59,101 @@ pub struct Wallpaper {
     pub image_queue: VecDeque<PathBuf>,
     loop_handle: calloop::LoopHandle<'static, GlowBerry>,
     queue_handle: QueueHandle<GlowBerry>,
+    compositor: Rc<CompositorState>,
     current_source: Option<Source>,
-    // Cache of source image, if `current_source` is a `Source::Path`
+    // Cache of source image, if `current_source` is a `Source::Path`, or of
+    // a shader's background image while it is drawn without a GPU
     current_image: Option<image::DynamicImage>,
+    // Whether `current_source` is the last-known-good source, shown because
+    // the configured one can't be
+    substitute: bool,
+    // The source last recorded as known good for each output, so redrawing
+    // it doesn't go through the state file again
+    known_good: Vec<(String, Source)>,
+    // Whether the image shown was drawn but isn't kept in `current_image`,
+    // its frames having come from the cache or it having been evicted since
+    without_image: bool,
+    /// When a layer was last drawn, to evict the wallpapers drawn least
+    /// recently first.
+    pub(crate) last_drawn: Instant,
+    // Bumped whenever the shown image changes, so dmabuf buffers of the
+    // previous one aren't reattached
+    image_generation: u64,
+    // Whether the image of a generation has transparent pixels, found when
+    // its first frame is drawn
+    transparent: Option<(u64, bool)>,
+    /// Allocator for dmabuf buffers, when the compositor and build support them.
+    pub(crate) dmabuf: Option<Rc<dmabuf::Allocator>>,
+    /// Whether the compositor accepts `XRGB2101010` SHM buffers.
+    pub(crate) ten_bit: bool,
+    /// Bytes of decoded frames an animated image may keep.
+    pub(crate) animation_cache: u64,
+    /// Bytes of disk the scaled images of all wallpapers may be cached in.
+    pub(crate) scaled_cache: u64,
+    // Download of a URL source or rotation of an online one, and the
+    // channel reporting their outcomes
+    remote: Option<RemoteImage>,
+    online: Option<OnlineFeed>,
+    remote_token: Option<RegistrationToken>,
+    remote_refresh: Duration,
+    // Built-in shader of a video, composite, or parallax source, or of a
+    // still image with Ken Burns motion or a window effect, and the video's
+    // playback once a layer shows it
+    builtin_shader: Option<ShaderSource>,
+    pub(crate) video: Option<VideoPlayer>,
+    // Frames of an animated `current_image`, and the timer advancing them
+    animation: Option<Animation>,
+    animation_token: Option<RegistrationToken>,
+    // The animation's next frame scaled to each output on a worker, whether
+    // a worker is scaling one, and the channel it reports on
+    animation_frames: Vec<((u32, u32), DynamicImage)>,
+    animation_scaling: bool,
+    animation_scaled_token: Option<RegistrationToken>,
+    // Schedule of a dynamic `current_image`, and the timer switching it
+    dynamic: Option<DynamicWallpaper>,
+    dynamic_token: Option<RegistrationToken>,
+    // Start of a transition drawn by the CPU, and the timer drawing its
+    // frames
+    transition_start: Option<Instant>,
+    transition_token: Option<RegistrationToken>,
     timer_token: Option<RegistrationToken>,
-    // File watcher kept alive for source change notifications
+    // Next image of the slideshow decoded ahead of its turn, the timer
+    // starting that, and the channel the worker reports on
+    predecoded: Option<Predecoded>,
+    predecode_timer: Option<RegistrationToken>,
+    predecode_token: Option<RegistrationToken>,
+    // Image shown as its placeholder while a worker decodes it, and the
+    // channel the worker reports on
+    decoding: Option<PathBuf>,
+    decode_token: Option<RegistrationToken>,
+    // Channel a worker reports the slideshow's identical images on
+    duplicates_token: Option<RegistrationToken>,
+    // Place of a shuffled slideshow in its sequence, and the image its pass
+    // began with
+    shuffle: Option<(Shuffle, PathBuf)>,
+    // Index of the image shown in the slideshow's order
+    slideshow_cursor: usize,
+    // Item of a playlist source shown, and the image each of its slideshows
+    // showed last
+    playlist_index: usize,
+    playlist_resume: HashMap<usize, PathBuf>,
+    // Album art of the playing track, shown instead of the configured source
+    now_playing: Option<Source>,
+    // Whether the theme is dark, showing the entry's dark source if it has one
+    dark: bool,
+    // Name of the workspace active on the first output, whose source the
+    // entry shows if it has one
+    workspace: Option<String>,
+    // Index of the entry's schedule rule applying today, and the timer
+    // checking the rules again after midnight
+    scheduled: Option<usize>,
+    schedule_token: Option<RegistrationToken>,
+    // Factors of the color channels for the night light
+    night_light: [f32; 3],
+    // File watcher kept alive for source change notifications, and the
+    // channel it reports them on
     _watcher: Option<RecommendedWatcher>,
+    source_tx: calloop::channel::SyncSender<(String, notify::Event)>,
 }
 
 impl std::fmt::Debug for Wallpaper {
@@ -54,6 +170,21 @@ impl Drop for Wallpaper {
         if let Some(token) = self.timer_token.take() {
             self.loop_handle.remove(token);
         }
+        if let Some(token) = self.transition_token.take() {
+            self.loop_handle.remove(token);
+        }
+        if let Some(token) = self.schedule_token.take() {
+            self.loop_handle.remove(token);
+        }
+        if let Some(token) = self.decode_token.take() {
+            self.loop_handle.remove(token);
+        }
+        if let Some(token) = self.duplicates_token.take() {
+            self.loop_handle.remove(token);
+        }
+        self.stop_predecode();
+        self.stop_remote();
+        self.stop_animation();
     }
 }
 
@@ -63,26 +194,119 @@ impl Wallpaper {
         queue_handle: QueueHandle<GlowBerry>,
         loop_handle: calloop::LoopHandle<'static, GlowBerry>,
         source_tx: calloop::channel::SyncSender<(String, notify::Event)>,
+        compositor: Rc<CompositorState>,
     ) -> Self {
+        let scheduled = calendar::applying(&entry.schedule, jiff::Zoned::now().date());
         let mut wallpaper = Wallpaper {
             entry,
             layers: Vec::new(),
             current_source: None,
             current_image: None,
+            substitute: false,
+            known_good: Vec::new(),
+            without_image: false,
+            last_drawn: Instant::now(),
+            image_generation: 0,
+            transparent: None,
+            dmabuf: None,
+            ten_bit: false,
+            animation_cache: u64::from(glowberry_config::DEFAULT_ANIMATION_CACHE_SIZE)
+                * 1024
+                * 1024,
+            scaled_cache: u64::from(glowberry_config::DEFAULT_SCALED_CACHE_SIZE) * 1024 * 1024,
+            remote: None,
+            online: None,
+            remote_token: None,
+            remote_refresh: Duration::from_secs(
+                u64::from(glowberry_config::DEFAULT_REMOTE_REFRESH) * 60,
+            ),
+            builtin_shader: None,
+            video: None,
+            animation: None,
+            animation_token: None,
+            animation_frames: Vec::new(),
+            animation_scaling: false,
+            animation_scaled_token: None,
+            dynamic: None,
+            dynamic_token: None,
+            transition_start: None,
+            transition_token: None,
             image_queue: VecDeque::default(),
             timer_token: None,
+            predecoded: None,
+            predecode_timer: None,
+            predecode_token: None,
+            decoding: None,
+            decode_token: None,
+            duplicates_token: None,
+            shuffle: None,
+            slideshow_cursor: 0,
+            playlist_index: 0,
+            playlist_resume: HashMap::new(),
+            now_playing: None,
+            dark: false,
+            workspace: None,
+            scheduled,
+            schedule_token: None,
+            night_light: [1.0; 3],
             _watcher: None,
+            source_tx,
             loop_handle,
             queue_handle,
+            compositor,
         };
 
         wallpaper.load_images();
         wallpaper.register_timer();
-        wallpaper.watch_source(source_tx);
+        wallpaper.register_schedule();
+        wallpaper.watch_source();
         wallpaper
     }
 
+    /// The source shown now: the playing track's album art, the current
+    /// item of a playlist, or the configured source.
+    pub fn source(&self) -> &Source {
+        if let Some(art) = &self.now_playing {
+            return art;
+        }
+        let items = self.configured_source().items();
+        if items.is_empty() {
+            return self.configured_source();
+        }
+        items[self.playlist_index % items.len()]
+    }
+
+    /// The entry's source for the active workspace, the day, and the
+    /// theme: the workspace's source if it has one, the source of the
+    /// schedule rule applying today, or its dark source while the theme is
+    /// dark.
+    fn configured_source(&self) -> &Source {
+        if let Some(workspace) = self.workspace.as_deref()
+            && let Some(workspace) = self
+                .entry
+                .workspaces
+                .iter()
+                .find(|source| source.workspace == workspace)
+        {
+            return &workspace.source;
+        }
+        if let Some(rule) = self
+            .scheduled
+            .and_then(|index| self.entry.schedule.get(index))
+        {
+            return &rule.source;
+        }
+        match &self.entry.dark_source {
+            Some(dark_source) if self.dark => dark_source,
+            _ => &self.entry.source,
+        }
+    }
+
     pub fn save_state(&self) -> Result<(), cosmic_config::Error> {
+        // Album art is passing; the slideshow resumes where it was
+        if self.now_playing.is_some() {
+            return Ok(());
+        }
         let Some(cur_source) = self.current_source.clone() else {
             return Ok(());
         };
@@ -100,13 +324,225 @@ impl Wallpaper {
                 state.wallpapers.push((name, cur_source.clone()))
             }
         }
+
+        state
+            .slideshow_queues
+            .retain(|(output, _)| *output != self.entry.output);
+        if matches!(self.source(), Source::Path(_) | Source::Folders(_)) {
+            state
+                .slideshow_queues
+                .push((self.entry.output.clone(), self.upcoming_images()));
+        }
+
+        state
+            .shuffles
+            .retain(|(output, _)| *output != self.entry.output);
+        if let Some((shuffle, _)) = self.shuffle {
+            state.shuffles.push((self.entry.output.clone(), shuffle));
+        }
+
+        state
+            .slideshow_positions
+            .retain(|(output, _)| *output != self.entry.output);
+        if let Source::Path(image) = &cur_source
+            && matches!(self.source(), Source::Path(_) | Source::Folders(_))
+        {
+            state.slideshow_positions.push((
+                self.entry.output.clone(),
+                SlideshowPosition {
+                    image: image.clone(),
+                    cursor: self.slideshow_cursor,
+                },
+            ));
+        }
         state.write_entry(&state_helper)
     }
 
+    /// Images the slideshow shows next, in order. The queue's last image is
+    /// the one currently shown.
+    fn upcoming_images(&self) -> Vec<PathBuf> {
+        let upcoming = self.image_queue.len().saturating_sub(1);
+        self.image_queue.iter().take(upcoming).cloned().collect()
+    }
+
+    /// Take the slideshow's next image off the queue. A shuffled slideshow
+    /// through its pass shuffles the next one first, keeping the images
+    /// shown last out of its start.
+    fn next_image(&mut self) -> Option<PathBuf> {
+        if let SamplingMethod::Shuffle { no_repeat } = self.entry.sampling_method
+            && let Some((shuffle, first)) = &mut self.shuffle
+            && self.image_queue.len() > 1
+            && self.image_queue.front() == Some(&*first)
+        {
+            shuffle.pass += 1;
+            // The queue ends with the image shown now
+            let no_repeat = no_repeat as usize;
+            let recent: Vec<PathBuf> = self
+                .image_queue
+                .iter()
+                .skip(self.image_queue.len().saturating_sub(no_repeat))
+                .cloned()
+                .collect();
+            shuffle::order(
+                self.image_queue.make_contiguous(),
+                *shuffle,
+                &recent,
+                no_repeat,
+            );
+            if let Some(front) = self.image_queue.front() {
+                first.clone_from(front);
+            }
+            tracing::debug!(
+                output = self.entry.output,
+                pass = shuffle.pass,
+                "reshuffled slideshow"
+            );
+            self.slideshow_cursor = 0;
+        } else if !self.image_queue.is_empty() {
+            self.slideshow_cursor = (self.slideshow_cursor + 1) % self.image_queue.len();
+        }
+        self.image_queue.pop_front()
+    }
+
+    /// Apply an edit to the upcoming images and publish the new queue.
+    pub(crate) fn edit_queue(&mut self, edit: &QueueEdit) {
+        if !edit_upcoming(&mut self.image_queue, edit) {
+            tracing::warn!(?edit, "slideshow queue edit doesn't apply to the queue");
+            return;
+        }
+        tracing::info!(?edit, "edited slideshow queue");
+        if let Err(err) = self.save_state() {
+            error!("{err}");
+        }
+    }
+
+    /// Record why this wallpaper's source can't be shown, or clear it, in
+    /// the state the settings app reads.
+    pub(crate) fn report_source_error(&self, error: Option<String>) {
+        let Ok(state_helper) = State::state() else {
+            return;
+        };
+        let mut state = State::get_entry(&state_helper).unwrap_or_default();
+        let errors = state.source_errors.clone();
+        state
+            .source_errors
+            .retain(|(output, _)| *output != self.entry.output);
+        if let Some(error) = error {
+            state.source_errors.push((self.entry.output.clone(), error));
+        }
+        if state.source_errors != errors
+            && let Err(err) = state.write_entry(&state_helper)
+        {
+            error!("{err}");
+        }
+    }
+
+    /// Take the images at `paths`, just added to a watched folder, into the
+    /// slideshow the way [`Self::load_images`] lists them, then replace the
+    /// placeholder with them. Returns whether the shown source changed.
+    pub(crate) fn add_images(&mut self, paths: &[PathBuf]) -> bool {
+        let added = admitted_images(
+            paths,
+            &self.image_queue,
+            self.entry.image_filter.as_ref(),
+            &self.output_aspects(),
+        );
+        let skip_duplicates = !added.is_empty();
+        for path in added {
+            self.image_queue.push_front(path);
+        }
+        let changed = self.on_images_added();
+        if skip_duplicates {
+            self.skip_duplicates();
+        }
+        changed
+    }
+
+    /// Start showing images added to a path source that had none. Returns
+    /// whether the placeholder was replaced, for the caller to show the
+    /// image.
+    pub(crate) fn on_images_added(&mut self) -> bool {
+        if (self.current_source.is_some() && !self.substitute)
+            || !matches!(self.source(), Source::Path(_) | Source::Folders(_))
+        {
+            return false;
+        }
+        // The default folders' images leave with the substitute
+        if self.substitute {
+            let folders: Vec<PathBuf> = self
+                .source()
+                .folders()
+                .iter()
+                .filter_map(|folder| folder.canonicalize().ok())
+                .collect();
+            self.image_queue
+                .retain(|path| folders.iter().any(|folder| path.starts_with(folder)));
+        }
+        let Some(next) = self.image_queue.pop_front() else {
+            return false;
+        };
+
+        tracing::info!(
+            output = self.entry.output,
+            "images found, replacing placeholder"
+        );
+        self.current_source = Some(Source::Path(next.clone()));
+        self.substitute = false;
+        self.image_queue.push_back(next);
+        self.report_source_error(None);
+        if let Err(err) = self.save_state() {
+            error!("{err}");
+        }
+        self.clear_image();
+        self.update_still_shader();
+
+        // The rotation timer drops itself while there is nothing to rotate
+        if let Some(token) = self.timer_token.take() {
+            self.loop_handle.remove(token);
+        }
+        self.register_timer();
+        true
+    }
+
     #[allow(clippy::too_many_lines)]
     pub fn draw(&mut self) {
         let start = Instant::now();
         let mut cur_resized_img: Option<DynamicImage> = None;
+        // Outputs a new buffer was attached to
+        let mut drawn = Vec::new();
+        // Whether a layer transitions to its new frame
+        let mut fading = false;
+        // A path source without images shows the placeholder
+        let placeholder = self.current_source.is_none()
+            && matches!(
+                self.source(),
+                Source::Path(_) | Source::Folders(_) | Source::Online(_) | Source::Playlist(_)
+            );
+        // Spanned images are sliced by each output's place in the layout
+        let scaling_mode = self.scaling_mode().clone();
+        let span_layout = matches!(scaling_mode, ScalingMode::Span)
+            .then(|| span_layout(&self.layers))
+            .flatten();
+        // The image shown now and its scaled frames, if it was decoded ahead
+        // of its turn
+        let (mut ahead, mut frames) = match (&self.current_source, self.predecoded.take()) {
+            (Some(Source::Path(path)), Some(next)) if next.path == *path => {
+                self.without_image |= next.image.is_none();
+                (next.image, next.frames)
+            }
+            (_, next) => {
+                self.predecoded = next;
+                (None, Vec::new())
+            }
+        };
+        // Frames of an animation scaled on a worker
+        if frames.is_empty() {
+            frames = std::mem::take(&mut self.animation_frames);
+        }
+        // Placeholder of the image shown, once looked up, and whether the
+        // image waits for a worker to decode or scale it
+        let mut blurred: Option<Option<DynamicImage>> = None;
+        let mut waiting = false;
 
         for layer in self.layers.iter_mut().filter(|layer| layer.needs_redraw) {
             let Some(pool) = layer.pool.as_mut() else {
@@ -121,12 +557,67 @@ impl Wallpaper {
                 continue;
             };
 
-            let width = layer_width * fractional_scale / 120;
-            let height = layer_height * fractional_scale / 120;
+            // Draw at the output's physical resolution and let the viewport
+            // map it back to the logical size, so scaled outputs stay sharp
+            let (width, height) =
+                draw::physical_size((layer_width, layer_height), fractional_scale);
+
+            // Buffers hold the image turned like the output, so the
+            // compositor doesn't have to turn it back
+            let (buffer_width, buffer_height) = if draw::is_rotated(layer.transform) {
+                (height, width)
+            } else {
+                (width, height)
+            };
+            layer
+                .layer
+                .wl_surface()
+                .set_buffer_transform(layer.transform);
+
+            // Reconfigured at the same size: show the existing dmabuf again,
+            // with nothing damaged
+            let key = (buffer_width, buffer_height, self.image_generation);
+            if let Some(buffer) = layer.dmabuf.as_mut().filter(|buffer| buffer.key == key) {
+                buffer.released = false;
+                draw::layer_surface_wl_buffer(
+                    &layer.layer,
+                    &layer.viewport,
+                    &self.queue_handle,
+                    &buffer.wl_buffer,
+                    &[],
+                    (layer_width, layer_height),
+                );
+                layer.needs_redraw = false;
+                continue;
+            }
 
-            if cur_resized_img
+            // The attached SHM buffer already shows this image at this size
+            if layer
+                .shm_frame
                 .as_ref()
-                .is_none_or(|img| img.width() != width || img.height() != height)
+                .is_some_and(|frame| frame.key == key)
+            {
+                layer
+                    .viewport
+                    .set_destination(layer_width as i32, layer_height as i32);
+                layer.layer.wl_surface().commit();
+                layer.needs_redraw = false;
+                continue;
+            }
+
+            if placeholder
+                && cur_resized_img
+                    .as_ref()
+                    .is_none_or(|img| img.width() != width || img.height() != height)
+            {
+                cur_resized_img = Some(DynamicImage::from(colored::placeholder(width, height)));
+            }
+
+            let slice = span_layout.zip(logical_rect(&layer.output_info));
+            if (slice.is_some() && !placeholder)
+                || cur_resized_img
+                    .as_ref()
+                    .is_none_or(|img| img.width() != width || img.height() != height)
             {
                 let Some(source) = self.current_source.as_ref() else {
                     tracing::info!("No source for wallpaper");
@@ -136,51 +627,91 @@ impl Wallpaper {
                 cur_resized_img = match source {
                     Source::Path(path) => {
                         if self.current_image.is_none() {
-                            self.current_image = Some(match path.extension() {
-                                Some(ext) if ext == "jxl" => match decode_jpegxl(path) {
-                                    Ok(image) => image,
-                                    Err(why) => {
-                                        tracing::warn!(
-                                            ?why,
-                                            "jpegl-xl image decode failed: {}",
-                                            path.display()
-                                        );
-                                        continue;
-                                    }
-                                },
-
-                                _ => match ImageReader::open(path) {
-                                    Ok(img) => {
-                                        match img
-                                            .with_guessed_format()
-                                            .ok()
-                                            .and_then(|f| f.decode().ok())
-                                        {
-                                            Some(img) => img,
-                                            None => {
-                                                tracing::warn!(
-                                                    "could not decode image: {}",
-                                                    path.display()
-                                                );
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    Err(_) => continue,
-                                },
-                            });
+                            self.animation = Animation::open(path, self.animation_cache);
+                            self.current_image = self
+                                .animation
+                                .as_ref()
+                                .map(|animation| animation.current().image.clone());
                         }
-                        let img = self.current_image.as_ref().unwrap();
-
-                        match self.entry.scaling_mode {
-                            ScalingMode::Fit(color) => {
-                                Some(scaler::fit(img, &color, width, height))
+                        if self.current_image.is_none() {
+                            self.dynamic = DynamicWallpaper::open(path);
+                            self.current_image =
+                                self.dynamic.as_mut().and_then(DynamicWallpaper::update);
+                        }
+                        if self.current_image.is_none() {
+                            self.current_image = ahead.take();
+                        } else if ahead.take().is_some() {
+                            // Animated and dynamic images show their own frames
+                            frames.clear();
+                        }
+                        let frame = slice
+                            .is_none()
+                            .then(|| predecode::take_frame(&mut frames, (width, height)))
+                            .flatten();
+                        let still = self.animation.is_none() && self.dynamic.is_none();
+                        match (self.current_image.as_ref(), frame) {
+                            (_, Some(frame)) => Some(frame),
+                            // Spanned images are sliced here, animated and
+                            // dynamic ones show frames decoded already
+                            (Some(img), None) if slice.is_some() || !still => Some(scale_output(
+                                &self.entry,
+                                &scaling_mode,
+                                img,
+                                width,
+                                height,
+                                slice,
+                            )),
+                            // Still images are decoded and scaled on a worker,
+                            // showing their placeholder meanwhile when they
+                            // were seen before, or the previous frame
+                            (current, None) => {
+                                waiting = true;
+                                let blur = match current {
+                                    Some(_) => None,
+                                    None if self.without_image => None,
+                                    // Placeholders are a fraction of the
+                                    // image's size, too small to tile or center
+                                    None if matches!(
+                                        scaling_mode,
+                                        ScalingMode::Tile | ScalingMode::Center(_)
+                                    ) =>
+                                    {
+                                        None
+                                    }
+                                    None => blurred
+                                        .get_or_insert_with(|| blurhash::placeholder(path))
+                                        .as_ref(),
+                                };
+                                match blur {
+                                    Some(blur) => Some(scale_output(
+                                        &self.entry,
+                                        &scaling_mode,
+                                        blur,
+                                        width,
+                                        height,
+                                        slice,
+                                    )),
+                                    None => continue,
+                                }
                             }
+                        }
+                    }
 
-                            ScalingMode::Zoom => Some(scaler::zoom(img, width, height)),
-
-                            ScalingMode::Stretch => Some(scaler::stretch(img, width, height)),
+                    Source::DayNight(day_night) => {
+                        if self.current_image.is_none() {
+                            let mut dynamic = DynamicWallpaper::day_night(
+                                day_night.day.clone(),
+                                day_night.night.clone(),
+                            );
+                            self.current_image = dynamic.update();
+                            self.dynamic = Some(dynamic);
                         }
+                        Some(match self.current_image.as_ref() {
+                            Some(img) => {
+                                scale_output(&self.entry, &scaling_mode, img, width, height, slice)
+                            }
+                            None => DynamicImage::from(colored::placeholder(width, height)),
+                        })
                     }
 
                     Source::Color(Color::Single([r, g, b])) => Some(image::DynamicImage::from(
@@ -201,32 +732,199 @@ impl Wallpaper {
                         }
                     }
 
-                    // Shader sources are handled by GPU renderer, should not reach here
-                    Source::Shader(_) => {
-                        tracing::warn!("Shader source in CPU draw path - this should not happen");
-                        None
+                    Source::Procedural(procedural) => Some(DynamicImage::from(
+                        colored::procedural(procedural, width, height),
+                    )),
+
+                    // Online sources show the images they download as paths,
+                    // folders and playlists their items, and videos are only
+                    // decoded for the GPU
+                    Source::Online(_)
+                    | Source::Folders(_)
+                    | Source::Video(_)
+                    | Source::Playlist(_) => {
+                        Some(DynamicImage::from(colored::placeholder(width, height)))
+                    }
+
+                    // Shader layers only get a pool when no GPU is usable; they
+                    // show the shader's background image, or the placeholder
+                    Source::Shader(shader) => {
+                        if self.current_image.is_none()
+                            && let Some(path) = shader.background_image.as_ref()
+                        {
+                            match decode(path) {
+                                Ok(image) => self.current_image = Some(image),
+                                Err(why) => tracing::warn!(
+                                    ?why,
+                                    "could not decode shader background: {}",
+                                    path.display()
+                                ),
+                            }
+                        }
+                        Some(match self.current_image.as_ref() {
+                            Some(img) => scale(&shader.background_scaling, img, width, height),
+                            None => DynamicImage::from(colored::placeholder(width, height)),
+                        })
+                    }
+
+                    // Composites and parallax images without a GPU show
+                    // their image alone
+                    Source::Composite(CompositeSource { image: path, .. })
+                    | Source::Parallax(ParallaxSource { image: path, .. }) => {
+                        if self.current_image.is_none() {
+                            match decode(path) {
+                                Ok(image) => self.current_image = Some(image),
+                                Err(why) => tracing::warn!(
+                                    ?why,
+                                    "could not decode image: {}",
+                                    path.display()
+                                ),
+                            }
+                        }
+                        Some(match self.current_image.as_ref() {
+                            Some(img) => {
+                                scale_output(&self.entry, &scaling_mode, img, width, height, slice)
+                            }
+                            None => DynamicImage::from(colored::placeholder(width, height)),
+                        })
                     }
                 };
+
+                let tint = self.color_tint();
+                if tint != [1.0; 3]
+                    && let Some(image) = cur_resized_img.as_mut()
+                {
+                    night_light::apply(image, tint);
+                }
             }
 
             let Some(image) = cur_resized_img.as_ref() else {
                 tracing::debug!(source = ?self.entry.source, "Skipping CPU draw without image");
                 continue;
             };
-            let buffer_result =
-                draw::canvas(pool, image, width as i32, height as i32, width as i32 * 4);
+            let transformed = draw::transformed(image, layer.transform);
+            let image = transformed.as_ref().unwrap_or(image);
+
+            // Gradients and high-depth images band in 8 bits per channel
+            let high_depth = high_depth(&self.entry.source, self.current_image.as_ref());
+
+            // Images with transparent pixels keep their alpha, through SHM,
+            // and show the compositor's background where they're see-through
+            let transparent = match self.transparent {
+                Some((generation, transparent)) if generation == self.image_generation => {
+                    transparent
+                }
+                _ => {
+                    let transparent = draw::has_transparency(image);
+                    self.transparent = Some((self.image_generation, transparent));
+                    transparent
+                }
+            };
+
+            // Transitioned to by the timer, from the frame shown before, in
+            // 10 bits if either frame is drawn in them
+            if let Some((from, to)) = layer.shm_fade.as_mut().filter(|(from, _)| {
+                (from.width(), from.height()) == (image.width(), image.height())
+            }) {
+                let deep = matches!(from, DynamicImage::ImageRgba16(_))
+                    || draw::Format::for_depth(high_depth, self.ten_bit)
+                        == draw::Format::Xrgb2101010;
+                if deep && !matches!(from, DynamicImage::ImageRgba16(_)) {
+                    *from = transition::frame(from, true);
+                }
+                *to = Some(transition::frame(image, deep));
+                layer.fade_transparent = transparent || draw::has_transparency(from);
+                layer.needs_redraw = false;
+                fading = true;
+                continue;
+            }
+            layer.shm_fade = None;
+
+            if let Some(allocator) = self.dmabuf.as_ref().filter(|_| !transparent) {
+                // Animations draw into the buffer their previous frame
+                // replaced, once released
+                let spare = layer.dmabuf_spare.take_if(|spare| spare.released);
+                match allocator.buffer(image, high_depth, key, spare, &self.queue_handle) {
+                    Ok(buffer) => {
+                        GlowBerry::set_opaque(&self.compositor, layer.layer.wl_surface(), true);
+                        draw::layer_surface_wl_buffer(
+                            &layer.layer,
+                            &layer.viewport,
+                            &self.queue_handle,
+                            &buffer.wl_buffer,
+                            &[[0, 0, buffer_width as i32, buffer_height as i32]],
+                            (layer_width, layer_height),
+                        );
+                        // The previous buffer is released after the commit
+                        // above, and kept until then
+                        let replaced = layer.dmabuf.replace(buffer);
+                        if self.animation.is_some() {
+                            let spare = std::mem::replace(&mut layer.dmabuf_spare, replaced);
+                            layer.retire_dmabuf(spare);
+                        } else {
+                            layer.retire_dmabuf(replaced);
+                        }
+                        layer.shm_frame = None;
+                        layer.needs_redraw = false;
+                        drawn.push(layer.output_info.name.clone().unwrap_or_default());
+
+                        let elapsed = Instant::now().duration_since(start);
+                        tracing::debug!(?elapsed, source = ?self.entry.source, "wallpaper dmabuf draw");
+                        continue;
+                    }
+                    Err(why) => {
+                        tracing::warn!(?why, "dmabuf wallpaper buffer failed, using SHM");
+                    }
+                }
+            }
+
+            let format = if transparent {
+                draw::Format::Argb8888
+            } else {
+                draw::Format::for_depth(high_depth, self.ten_bit)
+            };
+            let buffer_result = draw::canvas(
+                pool,
+                image,
+                buffer_width as i32,
+                buffer_height as i32,
+                buffer_width as i32 * 4,
+                format,
+            );
 
             match buffer_result {
                 Ok(buffer) => {
+                    // Only damage the tiles that differ from the buffer this
+                    // one replaces, e.g. a gradient's changed band
+                    let tiles = buffer
+                        .canvas(pool)
+                        .map(|canvas| draw::tile_hashes(canvas, buffer_width, buffer_height))
+                        .unwrap_or_default();
+                    let old_tiles = layer
+                        .shm_frame
+                        .as_ref()
+                        .filter(|frame| (frame.key.0, frame.key.1) == (buffer_width, buffer_height))
+                        .map(|frame| frame.tiles.as_slice());
+                    let damage = draw::damage(old_tiles, &tiles, buffer_width, buffer_height);
+
+                    GlowBerry::set_opaque(&self.compositor, layer.layer.wl_surface(), !transparent);
                     draw::layer_surface(
                         &layer.layer,
                         &layer.viewport,
                         &self.queue_handle,
                         &buffer,
-                        (width as i32, height as i32),
+                        &damage,
                         (layer_width, layer_height),
                     );
+                    layer.drop_dmabufs();
+                    layer.shm_frame = Some(draw::ShmFrame {
+                        key,
+                        tiles,
+                        buffer,
+                        format,
+                    });
                     layer.needs_redraw = false;
+                    drawn.push(layer.output_info.name.clone().unwrap_or_default());
 
                     let elapsed = Instant::now().duration_since(start);
 
@@ -238,10 +936,82 @@ impl Wallpaper {
                 }
             }
         }
+
+        if !drawn.is_empty() {
+            self.last_drawn = Instant::now();
+        }
+
+        // Shaders, and sources shown by one, drawn without a GPU only show a
+        // stand-in, and later frames of an animation were recorded with its
+        // first
+        if !self.current_source.as_ref().is_some_and(Source::is_shader)
+            && self.animation_token.is_none()
+        {
+            self.record_known_good(&drawn);
+        }
+
+        if fading && self.transition_token.is_none() {
+            self.play_transition();
+        }
+        if self.animation.is_some() && self.animation_token.is_none() {
+            self.play_animation();
+        }
+        if self.dynamic.is_some() && self.dynamic_token.is_none() {
+            self.schedule_dynamic();
+        }
+        if waiting && let Some(Source::Path(path)) = self.current_source.clone() {
+            self.decode_shown(path);
+        }
+    }
+
+    /// Work rendering the current wallpaper at `width`x`height` without
+    /// drawing it, for a worker thread to run.
+    ///
+    /// Returns `None` for shader sources and images not yet decoded.
+    pub fn snapshot(&self, width: u32, height: u32) -> Option<timelapse::Render> {
+        match self.current_source.as_ref()? {
+            Source::Path(_) | Source::DayNight(_) => {
+                let img = self.current_image.clone()?;
+                let entry = self.entry.clone();
+                let mode = self.scaling_mode().clone();
+                Some(Box::new(move || {
+                    Some(scale_output(&entry, &mode, &img, width, height, None))
+                }))
+            }
+            &Source::Color(Color::Single(color)) => Some(Box::new(move || {
+                Some(DynamicImage::from(colored::single(color, width, height)))
+            })),
+            Source::Color(Color::Gradient(gradient)) => {
+                let gradient = gradient.clone();
+                Some(Box::new(move || {
+                    colored::gradient(&gradient, width, height)
+                        .ok()
+                        .map(DynamicImage::from)
+                }))
+            }
+            Source::Procedural(procedural) => {
+                let procedural = procedural.clone();
+                Some(Box::new(move || {
+                    Some(DynamicImage::from(colored::procedural(
+                        &procedural,
+                        width,
+                        height,
+                    )))
+                }))
+            }
+            Source::Shader(_)
+            | Source::Video(_)
+            | Source::Online(_)
+            | Source::Folders(_)
+            | Source::Playlist(_)
+            | Source::Composite(_)
+            | Source::Parallax(_) => None,
+        }
     }
 
     pub fn load_images(&mut self) {
         let mut image_queue = VecDeque::new();
+        let mut source_error = None;
         let xdg_data_dirs: Vec<String> = match std::env::var("XDG_DATA_DIRS") {
             Ok(raw_xdg_data_dirs) => raw_xdg_data_dirs
                 .split(':')
@@ -250,41 +1020,56 @@ impl Wallpaper {
             Err(_) => Vec::new(),
         };
 
-        match self.entry.source {
-            Source::Path(ref source) => {
-                tracing::debug!(?source, "loading images");
+        match self.source().clone() {
+            Source::Path(ref source) if remote::is_url(source) => {
+                let url = source.to_string_lossy().into_owned();
+                tracing::debug!(url, "loading remote image");
 
-                if let Ok(source) = source.canonicalize() {
-                    if source.is_dir() {
-                        if xdg_data_dirs
-                            .iter()
-                            .any(|xdg_data_dir| source.starts_with(xdg_data_dir))
-                        {
-                            // Store paths of wallpapers to be used for the slideshow.
-                            for img_path in WalkDir::new(source)
-                                .follow_links(true)
-                                .into_iter()
-                                .filter_map(Result::ok)
-                                .filter(|p| p.path().is_file())
-                            {
-                                image_queue.push_front(img_path.path().into());
-                            }
-                        } else if let Ok(dir) = source.read_dir() {
-                            for entry in dir.filter_map(Result::ok) {
-                                let Ok(path) = entry.path().canonicalize() else {
-                                    continue;
-                                };
+                // Shown from the cache until the download finishes
+                let cached = remote::cached_path(&url);
+                if cached.is_file() {
+                    self.current_source = Some(Source::Path(cached));
+                }
+                self.start_remote(url);
+            }
 
-                                if path.is_file() {
-                                    image_queue.push_front(path);
-                                }
-                            }
-                        }
-                    } else if source.is_file() {
-                        image_queue.push_front(source);
+            ref source @ (Source::Path(_) | Source::Folders(_)) => {
+                let folders = source.folders();
+                tracing::debug!(?folders, "loading images");
+
+                for folder in folders {
+                    list_images(folder, &xdg_data_dirs, &mut image_queue);
+                }
+                // Missing or empty folders with no wallpaper shown before to
+                // fall back to show the default folders
+                let mut defaulted = false;
+                if image_queue.is_empty()
+                    && last_known_good(&self.entry.output, self.source()).is_none()
+                {
+                    for folder in glowberry_config::default_folders() {
+                        list_images(&folder, &xdg_data_dirs, &mut image_queue);
                     }
+                    defaulted = !image_queue.is_empty();
+                }
+                let described = || {
+                    folders
+                        .iter()
+                        .map(|folder| folder.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                // Links, and folders listed twice or inside one another,
+                // lead to the same images
+                let mut images = Vec::from(image_queue);
+                images.sort();
+                images.dedup();
+                image_queue = images.into();
+                if let Some(filter) = &self.entry.image_filter {
+                    self.filter_images(&mut image_queue, filter);
                 }
 
+                let mut shuffle_start = None;
+                let mut cursor = 0;
                 if image_queue.len() > 1 {
                     let image_slice = image_queue.make_contiguous();
                     match self.entry.sampling_method {
@@ -293,145 +1078,1870 @@ impl Wallpaper {
                                 .sort_by(|a, b| a.to_string_lossy().cmp(&b.to_string_lossy()));
                         }
                         SamplingMethod::Random => image_slice.shuffle(&mut rng()),
+                        SamplingMethod::Shuffle { .. } => {
+                            let shuffle = self
+                                .shuffle
+                                .as_ref()
+                                .map(|(shuffle, _)| *shuffle)
+                                .or_else(|| saved_shuffle(&self.entry.output))
+                                .unwrap_or_else(shuffle::new);
+                            shuffle::order(image_slice, shuffle, &[], 0);
+                            shuffle_start = Some((shuffle, image_slice[0].clone()));
+                        }
                     };
 
-                    // If a wallpaper from this slideshow was previously set, resume with that wallpaper.
-                    if let Some(Source::Path(last_path)) = current_image(&self.entry.output)
-                        && image_queue.contains(&last_path)
-                    {
-                        while let Some(path) = image_queue.pop_front() {
-                            if path == last_path {
-                                image_queue.push_front(path);
-                                break;
-                            }
-
-                            image_queue.push_back(path);
-                        }
+                    // If a wallpaper from this slideshow was previously set, resume with
+                    // that wallpaper, or with the one in its place if it is gone.
+                    // Playlist items and random orders have no place to carry on from
+                    let ordered = !self.is_playlist()
+                        && !matches!(self.entry.sampling_method, SamplingMethod::Random);
+                    let resumed = resume_cursor(&image_queue, self.resume_path(), || {
+                        saved_position(&self.entry.output).filter(|_| ordered)
+                    });
+                    if let Some(cursor) = resumed {
+                        image_queue.rotate_left(cursor);
                     }
+                    cursor = resumed.unwrap_or_default();
                 }
+                self.shuffle = shuffle_start;
+                self.slideshow_cursor = cursor;
 
                 if let Some(current_image_path) = image_queue.pop_front() {
                     self.current_source = Some(Source::Path(current_image_path.clone()));
                     image_queue.push_back(current_image_path);
+                    if defaulted {
+                        tracing::warn!(
+                            output = self.entry.output,
+                            ?folders,
+                            "no images found in wallpaper source, showing the default folders"
+                        );
+                        self.substitute = true;
+                        source_error = Some(format!(
+                            "No images found in {}; showing the default wallpapers",
+                            described()
+                        ));
+                    }
+                } else {
+                    tracing::warn!(
+                        output = self.entry.output,
+                        ?folders,
+                        "no images found in wallpaper source, showing placeholder"
+                    );
+                    source_error = Some(format!("No images found in {}", described()));
+
+                    if let Some(known_good) = last_known_good(&self.entry.output, self.source()) {
+                        tracing::warn!(
+                            output = self.entry.output,
+                            ?known_good,
+                            "showing the last known good wallpaper instead"
+                        );
+                        self.current_source = Some(known_good);
+                        self.substitute = true;
+                        source_error = source_error
+                            .map(|error| format!("{error}; showing the last working wallpaper"));
+                    }
                 }
             }
 
+            Source::Online(ref online) => {
+                tracing::debug!(query = online.query, "loading online images");
+
+                // The image shown last, until a new one is downloaded
+                if let Some(Source::Path(last)) = current_image(&self.entry.output)
+                    && last.starts_with(remote::cache_dir())
+                    && last.is_file()
+                {
+                    self.current_source = Some(Source::Path(last));
+                }
+                self.start_online(online.clone(), self.current_source.is_some());
+            }
+
             Source::Color(ref c) => {
                 self.current_source = Some(Source::Color(c.clone()));
             }
 
+            Source::Procedural(ref procedural) => {
+                self.current_source = Some(Source::Procedural(procedural.clone()));
+            }
+
             Source::Shader(ref shader) => {
                 // Shader wallpapers are handled by the GPU renderer
                 // Just set the source, GPU initialization happens in GlowBerry::init_gpu_layer
                 self.current_source = Some(Source::Shader(shader.clone()));
                 tracing::info!("Shader wallpaper source configured");
             }
+
+            Source::Video(ref video) => {
+                // Shown by a built-in shader; playback starts with the first layer
+                self.current_source = Some(Source::Video(video.clone()));
+                self.builtin_shader = Some(video::shader_source(video, &self.entry.scaling_mode));
+                tracing::info!(path = %video.path.display(), "Video wallpaper source configured");
+            }
+
+            Source::Composite(ref composite) => {
+                // Shown by a built-in shader blending the overlay over the image
+                self.current_source = Some(Source::Composite(composite.clone()));
+                self.builtin_shader = Some(composite::shader_source(
+                    composite,
+                    &self.entry.scaling_mode,
+                ));
+                tracing::info!(
+                    image = %composite.image.display(),
+                    "Composite wallpaper source configured"
+                );
+            }
+
+            Source::Parallax(ref parallax) => {
+                // Shown by a built-in shader shifting the image by its depth
+                self.current_source = Some(Source::Parallax(parallax.clone()));
+                self.builtin_shader =
+                    Some(parallax::shader_source(parallax, &self.entry.scaling_mode));
+                tracing::info!(
+                    image = %parallax.image.display(),
+                    depth = %parallax.depth.display(),
+                    "Parallax wallpaper source configured"
+                );
+            }
+
+            Source::DayNight(ref day_night) => {
+                // The image due now is picked when drawing
+                self.current_source = Some(Source::DayNight(day_night.clone()));
+                tracing::info!(
+                    day = %day_night.day.display(),
+                    night = %day_night.night.display(),
+                    "Day and night wallpaper source configured"
+                );
+            }
+
+            // Only an empty playlist is shown as itself
+            Source::Playlist(_) => {
+                tracing::warn!(
+                    output = self.entry.output,
+                    "playlist has no items, showing placeholder"
+                );
+                source_error = Some(String::from("The playlist has no items"));
+            }
         };
         if let Err(err) = self.save_state() {
             error!("{err}");
         }
+        self.report_source_error(source_error);
         self.image_queue = image_queue;
+        self.skip_duplicates();
+        self.update_still_shader();
     }
 
-    /// Check if this wallpaper uses a shader source.
-    pub fn is_shader(&self) -> bool {
-        matches!(self.entry.source, Source::Shader(_))
-    }
-
-    /// Get the shader source if this is a shader wallpaper.
-    pub fn shader_source(&self) -> Option<&ShaderSource> {
-        match &self.entry.source {
-            Source::Shader(s) => Some(s),
-            _ => None,
+    /// With `skip_duplicates`, find the slideshow's images with the same
+    /// contents on a worker thread, see [`identical_groups`], then keep one
+    /// of each.
+    fn skip_duplicates(&mut self) {
+        if let Some(token) = self.duplicates_token.take() {
+            self.loop_handle.remove(token);
+        }
+        if !self.entry.skip_duplicates
+            || self.image_queue.len() < 2
+            || !matches!(self.source(), Source::Path(_) | Source::Folders(_))
+        {
+            return;
         }
-    }
-
-    fn watch_source(&mut self, tx: calloop::channel::SyncSender<(String, notify::Event)>) {
-        let path = match &self.entry.source {
-            Source::Path(path) => path.clone(),
-            Source::Shader(shader) => match &shader.shader {
-                ShaderContent::Path(path) => path.clone(),
-                ShaderContent::Code(_) => return,
-            },
-            Source::Color(_) => return,
-        };
 
+        let (tx, rx) = calloop::channel::channel();
         let output = self.entry.output.clone();
-        let mut watcher = match RecommendedWatcher::new(
-            move |res| {
-                if let Ok(e) = res {
-                    let _ = tx.send((output.clone(), e));
+        self.duplicates_token = self
+            .loop_handle
+            .insert_source(rx, move |event, _, state: &mut GlowBerry| {
+                let calloop::channel::Event::Msg(groups) = event else {
+                    return;
+                };
+                if let Some(item) = state
+                    .wallpapers
+                    .iter_mut()
+                    .find(|w| w.entry.output == output)
+                {
+                    item.drop_identical(&groups);
                 }
-            },
-            notify::Config::default(),
-        ) {
-            Ok(w) => w,
-            Err(_) => return,
-        };
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to receive duplicate images"))
+            .ok();
 
-        tracing::debug!(output = self.entry.output, path = %path.display(), "watching source");
+        let images: Vec<PathBuf> = self.image_queue.iter().cloned().collect();
+        predecode::submit(Box::new(move || {
+            let _ = tx.send(identical_groups(&images));
+        }));
+    }
 
-        if let Ok(m) = fs::metadata(&path) {
-            if m.is_dir() {
-                let _ = watcher.watch(&path, RecursiveMode::Recursive);
-            } else if m.is_file() {
-                let _ = watcher.watch(&path, RecursiveMode::NonRecursive);
-            }
+    /// Leave all but one of each of `groups` of identical images out of the
+    /// slideshow, keeping the one shown.
+    fn drop_identical(&mut self, groups: &[Vec<PathBuf>]) {
+        let shown = self.current_image_path().map(Path::to_path_buf);
+        let listed = self.image_queue.len();
+        for group in groups {
+            let Some(kept) = group
+                .iter()
+                .find(|path| Some(*path) == shown.as_ref())
+                .or(group.first())
+            else {
+                continue;
+            };
+            self.image_queue
+                .retain(|path| path == kept || !group.contains(path));
         }
+        let dropped = listed - self.image_queue.len();
+        if dropped > 0 {
+            tracing::debug!(
+                output = self.entry.output,
+                dropped,
+                "skipped duplicate images"
+            );
+        }
+    }
 
-        // Store watcher to keep it alive
-        self._watcher = Some(watcher);
+    /// Show the still image on a shader layer when the entry asks for Ken
+    /// Burns motion, panning with new random framings, or a window effect.
+    pub(crate) fn update_still_shader(&mut self) {
+        if !matches!(
+            self.source(),
+            Source::Path(_) | Source::Folders(_) | Source::Online(_)
+        ) {
+            return;
+        }
+        let entry = &self.entry;
+        self.builtin_shader = self
+            .still_shader_image()
+            .map(|image| match &entry.ken_burns {
+                Some(ken_burns) => ken_burns::shader_source(
+                    image,
+                    ken_burns,
+                    entry.scaling_mode_for(image),
+                    entry.rotation_frequency,
+                ),
+                None => post_process::still_image_source(image, entry.scaling_mode_for(image)),
+            });
     }
 
-    fn register_timer(&mut self) {
-        let rotation_freq = self.entry.rotation_frequency;
-        let output_clone = self.entry.output.clone();
-        // set timer for rotation
-        if rotation_freq > 0 {
-            self.timer_token = self
-                .loop_handle
-                .insert_source(
-                    Timer::from_duration(Duration::from_secs(rotation_freq)),
-                    move |_, _, state: &mut GlowBerry| {
-                        let span = tracing::debug_span!("Wallpaper::timer");
-                        let _handle = span.enter();
+    /// How the image shown is scaled: the entry's scaling mode, unless the
+    /// entry overrides it for the image.
+    fn scaling_mode(&self) -> &ScalingMode {
+        match &self.current_source {
+            Some(Source::Path(path)) => self.entry.scaling_mode_for(path),
+            _ => &self.entry.scaling_mode,
+        }
+    }
 
-                        let Some(item) = state
-                            .wallpapers
-                            .iter_mut()
-                            .find(|w| w.entry.output == output_clone)
-                        else {
-                            return TimeoutAction::Drop; // Drop if no item found for this timer
-                        };
+    /// Still image shown by a built-in shader, for Ken Burns motion or a
+    /// window effect. Images the GPU path can't decode, and animated GIFs,
+    /// are drawn as usual.
+    fn still_shader_image(&self) -> Option<&Path> {
+        if self.entry.ken_burns.is_none() && self.entry.window_effect.is_none() {
+            return None;
+        }
+        let path = self.current_image_path().filter(|_| !self.substitute)?;
+        image::ImageFormat::from_path(path)
+            .is_ok_and(|format| format != image::ImageFormat::Gif)
+            .then_some(path)
+    }
 
-                        if let Some(next) = item.image_queue.pop_front() {
-                            item.current_source = Some(Source::Path(next.clone()));
-                            if let Err(err) = item.save_state() {
-                                error!("{err}");
-                            }
+    /// Leave out the images `filter` skips on the outputs shown on, unless
+    /// it skips all of them.
+    fn filter_images(&self, image_queue: &mut VecDeque<PathBuf>, filter: &ImageFilter) {
+        let aspects = self.output_aspects();
+        let listed = image_queue.len();
+        let kept: VecDeque<PathBuf> = image_queue
+            .iter()
+            .filter(|path| {
+                passes_filter(
+                    image_formats::cached_upright_dimensions(path),
+                    filter,
+                    &aspects,
+                )
+            })
+            .cloned()
+            .collect();
 
-                            item.image_queue.push_back(next);
-                            item.clear_image();
-                            item.draw();
+        if kept.is_empty() && listed > 0 {
+            tracing::warn!(
+                output = self.entry.output,
+                listed,
+                "no image passes the image filter, showing them all"
+            );
+        } else if kept.len() < listed {
+            tracing::debug!(
+                output = self.entry.output,
+                skipped = listed - kept.len(),
+                "skipped images by the image filter"
+            );
+            *image_queue = kept;
+        }
+    }
 
-                            return TimeoutAction::ToDuration(Duration::from_secs(rotation_freq));
-                        }
+    /// Widths over heights of the outputs shown on.
+    fn output_aspects(&self) -> Vec<f32> {
+        self.layers
+            .iter()
+            .filter_map(|layer| layer.output_info.logical_size)
+            .filter(|(width, height)| *width > 0 && *height > 0)
+            .map(|(width, height)| width as f32 / height as f32)
+            .collect()
+    }
 
-                        TimeoutAction::Drop
-                    },
-                )
-                .ok();
+    /// Build the slideshow again for the outputs it is shown on, when the
+    /// shape of its images is filtered relative to theirs.
+    pub(crate) fn filter_for_outputs(&mut self) {
+        let by_aspect = self
+            .entry
+            .image_filter
+            .is_some_and(|filter| filter.aspect_tolerance.is_some());
+        if !by_aspect
+            || self.now_playing.is_some()
+            || !matches!(self.source(), Source::Path(_) | Source::Folders(_))
+        {
+            return;
+        }
+        let shown = self.current_source.clone();
+        self.load_images();
+        if self.current_source != shown {
+            self.clear_image();
+            self.update_still_shader();
         }
     }
 
-    fn clear_image(&mut self) {
+    /// Redraw a spanned image after its outputs were added, removed, or
+    /// moved, since each output's slice depends on all of them.
+    pub(crate) fn relayout(&mut self) {
+        if !matches!(self.scaling_mode(), ScalingMode::Span) || self.is_shader() {
+            return;
+        }
+        for layer in &mut self.layers {
+            layer.shm_frame = None;
+            layer.drop_dmabufs();
+            layer.needs_redraw = true;
+        }
+        self.draw();
+    }
+
+    /// Check if this wallpaper uses a shader source. Videos, composites,
+    /// parallax images, and still images with Ken Burns motion or a window
+    /// effect are shown by a built-in shader and count as one.
+    pub fn is_shader(&self) -> bool {
+        self.source().is_shader() || self.builtin_shader.is_some()
+    }
+
+    /// Build a composite's shader again, after its overlay file changed.
+    pub(crate) fn reload_composite(&mut self) {
+        if let Source::Composite(composite) = self.source() {
+            self.builtin_shader = Some(composite::shader_source(
+                composite,
+                &self.entry.scaling_mode,
+            ));
+        }
+    }
+
+    /// Image a slideshow resumes with: the one it showed last as an item of
+    /// the playlist, or else the one last shown on the output.
+    fn resume_path(&self) -> Option<PathBuf> {
+        if let Some(path) = self.playlist_resume.get(&self.playlist_index) {
+            return Some(path.clone());
+        }
+        if let Some(position) = saved_position(&self.entry.output) {
+            return Some(position.image);
+        }
+        match current_image(&self.entry.output)? {
+            Source::Path(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Whether the rotation timer switches between items of a playlist
+    /// rather than images of a slideshow.
+    pub(crate) fn is_playlist(&self) -> bool {
+        self.configured_source().items().len() > 1
+    }
+
+    /// Show the next item of a playlist. A slideshow moves on from the image
+    /// it showed on its last turn.
+    pub(crate) fn advance_playlist(&mut self) {
+        let len = self.configured_source().items().len();
+        if len < 2 {
+            return;
+        }
+        self.leave_source();
+
+        self.playlist_index = (self.playlist_index + 1) % len;
+        tracing::info!(
+            output = self.entry.output,
+            item = self.playlist_index,
+            "showing next playlist item"
+        );
+        let resumed = self.playlist_resume.contains_key(&self.playlist_index);
+        self.load_images();
+        if resumed
+            && self.image_queue.len() > 1
+            && let Some(next) = self.next_image()
+        {
+            self.current_source = Some(Source::Path(next.clone()));
+            self.image_queue.push_back(next);
+            if let Err(err) = self.save_state() {
+                error!("{err}");
+            }
+            self.update_still_shader();
+        }
+        self.watch_source();
+    }
+
+    /// Tint the wallpaper's colors by `tint` for the night light. Returns
+    /// whether it changed; static outputs are then redrawn on the next
+    /// [`Self::draw`].
+    pub(crate) fn set_night_light(&mut self, tint: [f32; 3]) -> bool {
+        if tint == self.night_light {
+            return false;
+        }
+        self.night_light = tint;
+        for layer in &mut self.layers {
+            layer.shm_frame = None;
+            layer.drop_dmabufs();
+            layer.needs_redraw = true;
+        }
+        true
+    }
+
+    /// Factors of the color channels shown: the entry's brightness and color
+    /// temperature, and the night light. Shader layers apply them in their
+    /// post-process pass.
+    pub(crate) fn color_tint(&self) -> [f32; 3] {
+        let brightness = if self.entry.brightness.is_finite() {
+            self.entry.brightness.clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let temperature = night_light::tint(self.entry.temperature);
+        std::array::from_fn(|channel| brightness * temperature[channel] * self.night_light[channel])
+    }
+
+    /// Show the processed album art at `art` instead of the configured
+    /// source, or the configured source again with `None`. Returns whether
+    /// the source changed.
+    pub(crate) fn set_now_playing(&mut self, art: Option<PathBuf>) -> bool {
+        let art = art.map(Source::Path);
+        if art == self.now_playing {
+            return false;
+        }
+        let resuming = self.now_playing.is_some() && art.is_none();
+        if self.now_playing.is_none() {
+            self.leave_source();
+        } else {
+            self.clear_image();
+        }
+        self.current_source = None;
+        self.now_playing = art;
+
+        tracing::info!(
+            output = self.entry.output,
+            showing = self.now_playing.is_some(),
+            "now-playing album art changed"
+        );
+        self.load_images();
+        if resuming {
+            self.playlist_resume.remove(&self.playlist_index);
+        }
+        self.watch_source();
+        true
+    }
+
+    /// Show the entry's dark source while the theme is `dark`, or its source
+    /// otherwise. Returns whether the source shown changed.
+    pub(crate) fn set_dark(&mut self, dark: bool) -> bool {
+        if dark == self.dark {
+            return false;
+        }
+        tracing::debug!(output = self.entry.output, dark, "theme mode changed");
+        self.reconfigure(|wallpaper| wallpaper.dark = dark)
+    }
+
+    /// Show the entry's source for `workspace`, now active on its first
+    /// output, if it has one. Returns whether the source shown changed.
+    pub(crate) fn set_workspace(&mut self, workspace: Option<String>) -> bool {
+        if workspace == self.workspace {
+            return false;
+        }
+        tracing::debug!(output = self.entry.output, ?workspace, "workspace changed");
+        self.reconfigure(|wallpaper| wallpaper.workspace = workspace)
+    }
+
+    /// Show the source of the entry's schedule rule applying on `date`, if
+    /// any. Returns whether the source shown changed.
+    pub(crate) fn set_date(&mut self, date: jiff::civil::Date) -> bool {
+        let scheduled = calendar::applying(&self.entry.schedule, date);
+        if scheduled == self.scheduled {
+            return false;
+        }
+        tracing::debug!(output = self.entry.output, %date, ?scheduled, "schedule rule changed");
+        self.reconfigure(|wallpaper| wallpaper.scheduled = scheduled)
+    }
+
+    /// Apply `change` to what picks the entry's source, and show the source
+    /// it picks if that is another one. Returns whether the source shown
+    /// changed.
+    fn reconfigure(&mut self, change: impl FnOnce(&mut Self)) -> bool {
+        let before = self.configured_source().clone();
+        change(self);
+        if *self.configured_source() == before {
+            return false;
+        }
+
+        // Album art stays until the track ends, then the new source shows
+        if self.now_playing.is_none() {
+            self.leave_source();
+        }
+        // Playlist items of one source aren't the other's
+        self.playlist_index = 0;
+        self.playlist_resume.clear();
+        if self.now_playing.is_some() {
+            return false;
+        }
+
+        tracing::info!(
+            output = self.entry.output,
+            "showing another configured source"
+        );
+        self.load_images();
+        self.watch_source();
+        true
+    }
+
+    /// Whether the playing track's album art is shown.
+    pub(crate) fn is_now_playing(&self) -> bool {
+        self.now_playing.is_some()
+    }
+
+    /// Stop showing the current source before another is loaded, keeping
+    /// the image a slideshow showed to resume with.
+    fn leave_source(&mut self) {
+        if matches!(self.source(), Source::Path(_) | Source::Folders(_))
+            && let Some(path) = self.current_image_path().map(Path::to_path_buf)
+        {
+            self.playlist_resume.insert(self.playlist_index, path);
+        }
+
+        self.stop_remote();
+        self.video = None;
+        self.builtin_shader = None;
+        self.current_source = None;
+        self.substitute = false;
+        self.clear_image();
+    }
+
+    /// Start playing a video source, if not playing yet, decoding frames no
+    /// larger than `max_dimension`.
+    pub(crate) fn start_video(&mut self, max_dimension: u32) {
+        let Source::Video(video) = self.source().clone() else {
+            return;
+        };
+        if self.video.is_some() {
+            return;
+        }
+
+        self.video = VideoPlayer::start(&video.path, max_dimension);
+        let error = self
+            .video
+            .is_none()
+            .then(|| format!("Could not play {}", video.path.display()));
+        self.report_source_error(error);
+    }
+
+    /// Path of the image currently shown, if this is a static image wallpaper.
+    pub fn current_image_path(&self) -> Option<&Path> {
+        match self.current_source.as_ref()? {
+            Source::Path(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Get the configured shader file path if this is a shader wallpaper
+    /// loaded from disk, or a composite whose overlay is.
+    pub fn shader_path(&self) -> Option<&Path> {
+        match self.source() {
+            Source::Shader(ShaderSource {
+                shader: ShaderContent::Path(path),
+                ..
+            }) => Some(path),
+            Source::Composite(composite) => composite::overlay_path(composite),
+            _ => None,
+        }
+    }
+
+    /// Get the shader source shown if this is a shader wallpaper, which is
+    /// the last known good one while the configured shader fails.
+    pub fn shader_source(&self) -> Option<&ShaderSource> {
+        match (self.source(), self.current_source.as_ref()) {
+            (Source::Shader(_), Some(Source::Shader(s))) => Some(s),
+            (Source::Shader(s), _) => Some(s),
+            (
+                Source::Video(_)
+                | Source::Composite(_)
+                | Source::Parallax(_)
+                | Source::Path(_)
+                | Source::Folders(_)
+                | Source::Online(_),
+                _,
+            ) => self.builtin_shader.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Last-known-good shader to show instead of `failed`, which couldn't
+    /// be compiled for `output` because of `reason`.
+    pub(crate) fn known_good_shader(
+        &mut self,
+        output: &str,
+        failed: &ShaderSource,
+        reason: &str,
+    ) -> Option<ShaderSource> {
+        if self.substitute {
+            // Layers configured before the substitution still tried the
+            // configured shader; the substitute failing as well is final
+            return self
+                .shader_source()
+                .filter(|shader| shader.shader != failed.shader)
+                .cloned();
+        }
+
+        let Some(Source::Shader(known_good)) = last_known_good(output, self.source()) else {
+            return None;
+        };
+        tracing::warn!(
+            output,
+            source_path = ?known_good.source_path,
+            "showing the last known good shader instead"
+        );
+        self.current_source = Some(Source::Shader(known_good.clone()));
+        self.substitute = true;
+        self.report_source_error(Some(format!("{reason}; showing the last working shader")));
+        Some(known_good)
+    }
+
+    /// Go back to the configured source after a last-known-good substitute.
+    pub(crate) fn clear_substitute(&mut self) {
+        if !std::mem::take(&mut self.substitute) {
+            return;
+        }
+        self.current_source = Some(self.source().clone());
+        self.report_source_error(None);
+        if let Err(err) = self.save_state() {
+            error!("{err}");
+        }
+    }
+
+    /// Remember the configured source as the last one shown successfully on
+    /// `outputs`. Shaders are stored with their code, so a later broken
+    /// edit of the file can fall back to it. Outputs already recorded with
+    /// this source are skipped.
+    pub(crate) fn record_known_good(&mut self, outputs: &[String]) {
+        if self.substitute || self.now_playing.is_some() || outputs.is_empty() {
+            return;
+        }
+        let Some(mut source) = self.current_source.clone() else {
+            return;
+        };
+        if let Source::Shader(shader) = &mut source
+            && let ShaderContent::Path(path) = &shader.shader
+            && let Ok(code) = fs::read_to_string(path)
+        {
+            shader.source_path = shader.source_path.take().or_else(|| Some(path.clone()));
+            shader.shader = ShaderContent::Code(code);
+        }
+        let outputs: Vec<&String> = outputs
+            .iter()
+            .filter(|output| {
+                !self
+                    .known_good
+                    .iter()
+                    .any(|(name, recorded)| name == *output && *recorded == source)
+            })
+            .collect();
+        if outputs.is_empty() {
+            return;
+        }
+        for output in &outputs {
+            self.known_good.retain(|(name, _)| name != *output);
+            self.known_good.push(((*output).clone(), source.clone()));
+        }
+
+        let Ok(state_helper) = State::state() else {
+            return;
+        };
+        let mut state = State::get_entry(&state_helper).unwrap_or_default();
+        let previous = state.last_known_good.clone();
+        for output in outputs {
+            state.last_known_good.retain(|(name, _)| name != output);
+            state.last_known_good.push((output.clone(), source.clone()));
+        }
+        if state.last_known_good != previous
+            && let Err(err) = state.write_entry(&state_helper)
+        {
+            error!("{err}");
+        }
+    }
+
+    fn watch_source(&mut self) {
+        self._watcher = None;
+        if self.now_playing.is_some() {
+            return;
+        }
+        let (paths, background) = match self.source() {
+            Source::Path(path) => (vec![path.clone()], None),
+            Source::Folders(folders) => (folders.clone(), None),
+            Source::Shader(shader) => (
+                match &shader.shader {
+                    ShaderContent::Path(path) => vec![path.clone()],
+                    ShaderContent::Code(_) => Vec::new(),
+                },
+                shader.background_image.clone(),
+            ),
+            Source::Composite(composite) => (
+                composite::overlay_path(composite)
+                    .map(Path::to_path_buf)
+                    .into_iter()
+                    .collect(),
+                Some(composite.image.clone()),
+            ),
+            Source::Parallax(parallax) => {
+                (vec![parallax.depth.clone()], Some(parallax.image.clone()))
+            }
+            Source::Color(_)
+            | Source::Procedural(_)
+            | Source::Video(_)
+            | Source::Online(_)
+            | Source::Playlist(_)
+            | Source::DayNight(_) => return,
+        };
+        if paths.is_empty() && background.is_none() {
+            return;
+        }
+
+        let tx = self.source_tx.clone();
+        let output = self.entry.output.clone();
+        let mut watcher = match RecommendedWatcher::new(
+            move |res| {
+                if let Ok(e) = res {
+                    let _ = tx.send((output.clone(), e));
+                }
+            },
+            notify::Config::default(),
+        ) {
+            Ok(w) => w,
+            Err(_) => return,
+        };
+
+        for path in paths {
+            tracing::debug!(output = self.entry.output, path = %path.display(), "watching source");
+
+            if let Ok(m) = fs::metadata(&path) {
+                if m.is_dir() {
+                    let _ = watcher.watch(&path, RecursiveMode::Recursive);
+                } else if m.is_file() {
+                    let _ = watcher.watch(&path, RecursiveMode::NonRecursive);
+                }
+            }
+        }
+
+        // Watch the background image's directory rather than the file, so
+        // that scripts replacing it with a rename are still noticed
+        if let Some(dir) = background.as_deref().and_then(Path::parent) {
+            tracing::debug!(
+                output = self.entry.output,
+                path = %dir.display(),
+                "watching shader background image"
+            );
+            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+
+        // Store watcher to keep it alive
+        self._watcher = Some(watcher);
+    }
+
+    fn register_timer(&mut self) {
+        let rotation_freq = self.entry.rotation_frequency;
+        let output_clone = self.entry.output.clone();
+        // set timer for rotation
+        if rotation_freq > 0 {
+            self.timer_token = self
+                .loop_handle
+                .insert_source(
+                    Timer::from_duration(Duration::from_secs(rotation_freq)),
+                    move |_, _, state: &mut GlowBerry| {
+                        let span = tracing::debug_span!("Wallpaper::timer");
+                        let _handle = span.enter();
+
+                        // Hold the current image while the screen is shared
+                        if state.rotation_paused() {
+                            return TimeoutAction::ToDuration(Duration::from_secs(rotation_freq));
+                        }
+
+                        let Some(idx) = state
+                            .wallpapers
+                            .iter()
+                            .position(|w| w.entry.output == output_clone)
+                        else {
+                            return TimeoutAction::Drop; // Drop if no item found for this timer
+                        };
+
+                        // Album art stays until playback stops
+                        if state.wallpapers[idx].is_now_playing() {
+                            return TimeoutAction::ToDuration(Duration::from_secs(rotation_freq));
+                        }
+
+                        // Playlists switch items rather than images
+                        if state.wallpapers[idx].is_playlist() {
+                            state.next_playlist_item(idx);
+                            state.on_wallpaper_rotated(&output_clone);
+                            return TimeoutAction::ToDuration(Duration::from_secs(rotation_freq));
+                        }
+
+                        let item = &mut state.wallpapers[idx];
+                        if let Some(next) = item.next_image() {
+                            let was_shader = item.is_shader();
+                            item.current_source = Some(Source::Path(next.clone()));
+                            if let Err(err) = item.save_state() {
+                                error!("{err}");
+                            }
+
+                            item.image_queue.push_back(next);
+                            item.clear_image();
+                            item.update_still_shader();
+                            item.schedule_predecode();
+                            state.on_source_switched(idx, was_shader);
+                            state.on_wallpaper_rotated(&output_clone);
+
+                            return TimeoutAction::ToDuration(Duration::from_secs(rotation_freq));
+                        }
+
+                        TimeoutAction::Drop
+                    },
+                )
+                .ok();
+            self.schedule_predecode();
+        }
+    }
+
+    /// Decode the slideshow's next image shortly before the rotation timer
+    /// next fires.
+    fn schedule_predecode(&mut self) {
+        if let Some(token) = self.predecode_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        let rotation = Duration::from_secs(self.entry.rotation_frequency);
+        if rotation.is_zero() || self.image_queue.len() < 2 || self.is_playlist() {
+            return;
+        }
+
+        let output = self.entry.output.clone();
+        self.predecode_timer = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(rotation.saturating_sub(predecode::LEAD)),
+                move |_, _, state: &mut GlowBerry| {
+                    if let Some(item) = state
+                        .wallpapers
+                        .iter_mut()
+                        .find(|w| w.entry.output == output)
+                    {
+                        item.predecode_timer = None;
+                        item.start_predecode();
+                    }
+                    TimeoutAction::Drop
+                },
+            )
+            .ok();
+    }
+
+    /// Decode the image next in the queue, and scale it to the size of each
+    /// output drawn by the CPU, on a worker thread.
+    fn start_predecode(&mut self) {
+        let Some(path) = self.image_queue.front().cloned() else {
+            return;
+        };
+        if self
+            .predecoded
+            .as_ref()
+            .is_some_and(|next| next.path == path)
+        {
+            return;
+        }
+        let Some(sizes) = self.decode_sizes(&path) else {
+            return;
+        };
+
+        self.stop_predecode();
+        let (tx, rx) = calloop::channel::channel();
+        let output = self.entry.output.clone();
+        self.predecode_token = self
+            .loop_handle
+            .insert_source(rx, move |event, _, state: &mut GlowBerry| {
+                let calloop::channel::Event::Msg(Ok(next)) = event else {
+                    return;
+                };
+                // Kept while the image is still next
+                if let Some(item) = state
+                    .wallpapers
+                    .iter_mut()
+                    .find(|w| w.entry.output == output)
+                    && item.image_queue.front() == Some(&next.path)
+                {
+                    item.predecoded = Some(next);
+                    state.enforce_memory_budget();
+                }
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to receive decoded images"))
+            .ok();
+
+        let bound = self.decode_bound(&path, &sizes);
+        let entry = self.entry.clone();
+        let mode = self.entry.scaling_mode_for(&path).clone();
+        predecode::spawn(
+            path,
+            None,
+            bound,
+            sizes,
+            move |image, width, height| scale_output(&entry, &mode, image, width, height, None),
+            self.frame_cache(&path),
+            tx,
+        );
+    }
+
+    /// Sizes to scale the image at `path` to on a worker thread: those of
+    /// the outputs drawn by the CPU, or none for spanned images, which are
+    /// sliced when drawn. `None` when shader layers decode the images for the
+    /// GPU.
+    fn decode_sizes(&self, path: &Path) -> Option<Vec<(u32, u32)>> {
+        let sizes: Vec<(u32, u32)> = self
+            .layers
+            .iter()
+            .filter(|layer| layer.pool.is_some())
+            .filter_map(|layer| Some(draw::physical_size(layer.size?, layer.fractional_scale?)))
+            .collect();
+        if sizes.is_empty() {
+            return None;
+        }
+        if matches!(self.entry.scaling_mode_for(path), ScalingMode::Span) {
+            return Some(Vec::new());
+        }
+        Some(sizes)
+    }
+
+    /// How the frames of the image at `path` scaled on worker threads are
+    /// cached.
+    fn frame_cache(&self, path: &Path) -> ScaledCache {
+        ScaledCache {
+            scaling: format!(
+                "{:?} {:?}",
+                self.entry.scaling_mode_for(path),
+                self.entry.crop
+            ),
+            limit: self.scaled_cache,
+        }
+    }
+
+    /// Size the image at `path` needs to cover to be scaled to each of
+    /// `sizes`, beyond which it is shrunk. Cropped regions, spanned images
+    /// sliced when drawn, and images tiled or centered at their own size need
+    /// the whole image.
+    fn decode_bound(&self, path: &Path, sizes: &[(u32, u32)]) -> Option<(u32, u32)> {
+        if matches!(self.entry.crop, Some(Crop::Region { .. }))
+            || matches!(
+                self.entry.scaling_mode_for(path),
+                ScalingMode::Tile | ScalingMode::Center(_)
+            )
+        {
+            return None;
+        }
+        sizes
+            .iter()
+            .copied()
+            .reduce(|(w1, h1), (w2, h2)| (w1.max(w2), h1.max(h2)))
+    }
+
+    /// Decode the image shown, unless it is already, and scale it to each
+    /// output on a worker thread, then draw it in place of its placeholder or
+    /// the previous frame.
+    fn decode_shown(&mut self, path: PathBuf) {
+        if self.decoding.as_ref() == Some(&path) {
+            return;
+        }
+        let Some(sizes) = self.decode_sizes(&path) else {
+            return;
+        };
+
+        if let Some(token) = self.decode_token.take() {
+            self.loop_handle.remove(token);
+        }
+        let (tx, rx) = calloop::channel::channel();
+        let output = self.entry.output.clone();
+        self.decode_token = self
+            .loop_handle
+            .insert_source(rx, move |event, _, state: &mut GlowBerry| {
+                let decoded = match event {
+                    calloop::channel::Event::Msg(decoded) => decoded,
+                    // The job ended without a result, so the image may be
+                    // decoded again
+                    calloop::channel::Event::Closed => {
+                        if let Some(item) = state
+                            .wallpapers
+                            .iter_mut()
+                            .find(|w| w.entry.output == output)
+                        {
+                            item.decoding = None;
+                        }
+                        return;
+                    }
+                };
+                let path = match &decoded {
+                    Ok(decoded) => &decoded.path,
+                    Err((path, _)) => path,
+                };
+                let Some(item) = state
+                    .wallpapers
+                    .iter_mut()
+                    .find(|w| w.entry.output == output)
+                    .filter(|item| item.decoding.as_ref() == Some(path))
+                else {
+                    return;
+                };
+                item.decoding = None;
+                match decoded {
+                    // Drawn while the image is still shown
+                    Ok(decoded) => {
+                        if let Some(Source::Path(path)) = &item.current_source
+                            && *path == decoded.path
+                        {
+                            item.predecoded = Some(decoded);
+                            item.clear_image();
+                            item.draw();
+                        }
+                    }
+                    Err((path, why)) => {
+                        tracing::warn!(?why, "could not decode image: {}", path.display());
+                    }
+                }
+                state.enforce_memory_budget();
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to receive decoded images"))
+            .ok();
+
+        self.decoding = Some(path.clone());
+        // An image shrunk for smaller outputs is decoded again
+        let bound = self.decode_bound(&path, &sizes);
+        let decoded = self.current_image.clone().filter(|image| {
+            bound.is_none_or(|(width, height)| image.width() >= width && image.height() >= height)
+        });
+        let entry = self.entry.clone();
+        let mode = self.entry.scaling_mode_for(&path).clone();
+        predecode::spawn(
+            path,
+            decoded,
+            bound,
+            sizes,
+            move |image, width, height| scale_output(&entry, &mode, image, width, height, None),
+            self.frame_cache(&path),
+            tx,
+        );
+    }
+
+    fn stop_predecode(&mut self) {
+        self.predecoded = None;
+        if let Some(token) = self.predecode_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        if let Some(token) = self.predecode_token.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Download a URL source, showing each new copy once it is cached.
+    fn start_remote(&mut self, url: String) {
+        let tx = self.watch_downloads();
+        self.remote = Some(RemoteImage::start(url, self.remote_refresh, tx));
+    }
+
+    /// Rotate through images of an online source, `showing` one already.
+    fn start_online(&mut self, source: OnlineSource, showing: bool) {
+        let tx = self.watch_downloads();
+        self.online = Some(OnlineFeed::start(source, showing, tx));
+    }
+
+    /// Channel on which downloads report new images to show, and failures.
+    fn watch_downloads(&mut self) -> calloop::channel::Sender<RemoteEvent> {
+        self.stop_remote();
+
+        let (tx, rx) = calloop::channel::channel();
+        let output = self.entry.output.clone();
+        self.remote_token = self
+            .loop_handle
+            .insert_source(rx, move |event, _, state: &mut GlowBerry| {
+                let calloop::channel::Event::Msg(event) = event else {
+                    return;
+                };
+                let paused = state.rotation_paused();
+                let Some(idx) = state
+                    .wallpapers
+                    .iter()
+                    .position(|w| w.entry.output == output)
+                else {
+                    return;
+                };
+                let item = &mut state.wallpapers[idx];
+
+                match event {
+                    // Online sources hold their image while the screen is shared
+                    RemoteEvent::Updated(_)
+                        if paused
+                            && item.current_source.is_some()
+                            && matches!(item.source(), Source::Online(_)) => {}
+                    RemoteEvent::Updated(path) => {
+                        tracing::info!(output, "showing downloaded wallpaper");
+                        let was_shader = item.is_shader();
+                        item.current_source = Some(Source::Path(path));
+                        if let Err(err) = item.save_state() {
+                            error!("{err}");
+                        }
+                        item.report_source_error(None);
+                        item.clear_image();
+                        item.update_still_shader();
+                        state.on_source_switched(idx, was_shader);
+                        state.on_wallpaper_rotated(&output);
+                    }
+                    // Offline with a cached copy, which stays on screen
+                    RemoteEvent::Failed(_) if item.current_source.is_some() => {}
+                    RemoteEvent::Failed(why) => {
+                        item.report_source_error(Some(format!("Download failed: {why}")));
+                    }
+                }
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to watch remote wallpaper"))
+            .ok();
+        tx
+    }
+
+    /// Change how often a URL source is checked for a new image.
+    pub(crate) fn set_remote_refresh(&mut self, refresh: Duration) {
+        self.remote_refresh = refresh;
+        if let Some(remote) = self.remote.as_ref() {
+            remote.set_refresh(refresh);
+        }
+    }
+
+    fn stop_remote(&mut self) {
+        self.remote = None;
+        self.online = None;
+        if let Some(token) = self.remote_token.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Show the frames of an animated image, each for its own delay. Holds
+    /// the frame shown while animation is paused. Frames are scaled to each
+    /// output on a worker and drawn once they arrive; a frame due while the
+    /// previous one is still being scaled is skipped.
+    fn play_animation(&mut self) {
+        let Some(animation) = self.animation.as_ref() else {
+            return;
+        };
+        let delay = animation.current().delay;
+
+        let (tx, rx) = calloop::channel::channel();
+        let output = self.entry.output.clone();
+        self.animation_scaled_token = self
+            .loop_handle
+            .insert_source(rx, move |event, _, state: &mut GlowBerry| {
+                let calloop::channel::Event::Msg((image, frames)) = event else {
+                    return;
+                };
+                let Some(item) = state
+                    .wallpapers
+                    .iter_mut()
+                    .find(|w| w.entry.output == output)
+                else {
+                    return;
+                };
+                item.animation_scaling = false;
+                if item.animation.is_none() {
+                    return;
+                }
+                item.current_image = Some(image);
+                item.animation_frames = frames;
+                item.image_generation += 1;
+                for layer in &mut item.layers {
+                    layer.needs_redraw = true;
+                }
+                item.draw();
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to receive scaled animation frames"))
+            .ok();
+
+        let output = self.entry.output.clone();
+        self.animation_token = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(delay),
+                move |_, _, state: &mut GlowBerry| {
+                    let paused = state.should_pause_animation();
+                    let Some(item) = state
+                        .wallpapers
+                        .iter_mut()
+                        .find(|w| w.entry.output == output)
+                    else {
+                        return TimeoutAction::Drop;
+                    };
+                    let Some(animation) = item.animation.as_mut() else {
+                        item.animation_token = None;
+                        return TimeoutAction::Drop;
+                    };
+
+                    if paused {
+                        return TimeoutAction::ToDuration(ANIMATION_PAUSE_POLL);
+                    }
+
+                    let frame = animation.advance().clone();
+                    if !item.animation_scaling {
+                        item.scale_animation_frame(frame.image, tx.clone());
+                    }
+
+                    TimeoutAction::ToDuration(frame.delay)
+                },
+            )
+            .inspect_err(|why| tracing::warn!(?why, "Failed to start animated wallpaper"))
+            .ok();
+    }
+
+    /// Scale a frame of the animation to each output on a worker, which
+    /// sends it to `done` with its scaled frames. Spanned animations are
+    /// sliced while drawing instead.
+    fn scale_animation_frame(
+        &mut self,
+        image: DynamicImage,
+        done: calloop::channel::Sender<(DynamicImage, Vec<((u32, u32), DynamicImage)>)>,
+    ) {
+        let Some(Source::Path(path)) = self.current_source.as_ref() else {
+            return;
+        };
+        let mut sizes = self.decode_sizes(path).unwrap_or_default();
+        sizes.sort_unstable();
+        sizes.dedup();
+        let entry = self.entry.clone();
+        let mode = self.entry.scaling_mode_for(path).clone();
+
+        self.animation_scaling = true;
+        predecode::submit(Box::new(move || {
+            // A frame that fails to scale is scaled again while drawing
+            let frames = panic::catch_unwind(AssertUnwindSafe(|| {
+                sizes
+                    .into_iter()
+                    .map(|(width, height)| {
+                        let frame = scale_output(&entry, &mode, &image, width, height, None);
+                        ((width, height), frame)
+                    })
+                    .collect()
+            }))
+            .unwrap_or_default();
+            // The wallpaper may be gone meanwhile
+            let _ = done.send((image, frames));
+        }));
+    }
+
+    /// Check the entry's schedule rules again after each midnight.
+    fn register_schedule(&mut self) {
+        if self.entry.schedule.is_empty() {
+            return;
+        }
+        let output = self.entry.output.clone();
+        self.schedule_token = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(calendar::until_tomorrow(&jiff::Zoned::now())),
+                move |_, _, state: &mut GlowBerry| {
+                    let Some(idx) = state
+                        .wallpapers
+                        .iter()
+                        .position(|w| w.entry.output == output)
+                    else {
+                        return TimeoutAction::Drop;
+                    };
+
+                    let now = jiff::Zoned::now();
+                    let was_shader = state.wallpapers[idx].is_shader();
+                    if state.wallpapers[idx].set_date(now.date()) {
+                        state.on_source_switched(idx, was_shader);
+                    }
+                    TimeoutAction::ToDuration(calendar::until_tomorrow(&now))
+                },
+            )
+            .inspect_err(|why| tracing::warn!(?why, "Failed to schedule the entry's rules"))
+            .ok();
+    }
+
+    /// Switch a dynamic wallpaper's image whenever another one is due.
+    fn schedule_dynamic(&mut self) {
+        let Some(dynamic) = self.dynamic.as_ref() else {
+            return;
+        };
+        let output = self.entry.output.clone();
+        self.dynamic_token = self
+            .loop_handle
+            .insert_source(
+                Timer::from_duration(dynamic.until_next_change()),
+                move |_, _, state: &mut GlowBerry| {
+                    let Some(item) = state
+                        .wallpapers
+                        .iter_mut()
+                        .find(|w| w.entry.output == output)
+                    else {
+                        return TimeoutAction::Drop;
+                    };
+                    let Some(dynamic) = item.dynamic.as_mut() else {
+                        item.dynamic_token = None;
+                        return TimeoutAction::Drop;
+                    };
+
+                    let next_change = dynamic.until_next_change();
+                    if let Some(image) = dynamic.update() {
+                        tracing::debug!(output, "switching dynamic wallpaper image");
+                        item.current_image = Some(image);
+                        item.image_generation += 1;
+                        for layer in &mut item.layers {
+                            layer.needs_redraw = true;
+                        }
+                        item.draw();
+                    }
+
+                    TimeoutAction::ToDuration(next_change)
+                },
+            )
+            .inspect_err(|why| tracing::warn!(?why, "Failed to schedule dynamic wallpaper"))
+            .ok();
+    }
+
+    /// Transition static outputs from the frame they show to the next one
+    /// drawn, when the entry has a transition. The frame is read back from
+    /// the buffer attached, and a transition in progress continues from the
+    /// frame it ends with.
+    pub(crate) fn start_transition(&mut self) {
+        if self.entry.transition.is_none() {
+            return;
+        }
+        for layer in &mut self.layers {
+            let from = match layer.shm_fade.take() {
+                Some((_, Some(to))) => Some(to),
+                Some((from, None)) => Some(from),
+                None => layer.shown_frame(),
+            };
+            layer.shm_fade = from.map(|from| (from, None));
+        }
+        self.transition_start = Some(Instant::now());
+    }
+
+    /// Draw the frames of a transition until it is done.
+    fn play_transition(&mut self) {
+        let output = self.entry.output.clone();
+        self.transition_token = self
+            .loop_handle
+            .insert_source(Timer::immediate(), move |_, _, state: &mut GlowBerry| {
+                let Some(item) = state
+                    .wallpapers
+                    .iter_mut()
+                    .find(|w| w.entry.output == output)
+                else {
+                    return TimeoutAction::Drop;
+                };
+                if item.draw_transition() {
+                    TimeoutAction::ToDuration(TRANSITION_FRAME)
+                } else {
+                    item.transition_token = None;
+                    TimeoutAction::Drop
+                }
+            })
+            .inspect_err(|why| tracing::warn!(?why, "Failed to start transition"))
+            .ok();
+    }
+
+    /// Draw the next frame of a transition on each layer in one. Returns
+    /// whether more follow; once it is done, the new image is drawn as usual.
+    fn draw_transition(&mut self) -> bool {
+        let (Some(config), Some(start)) = (self.entry.transition.as_ref(), self.transition_start)
+        else {
+            return self.finish_transition();
+        };
+        let progress = transition::progress(config, start);
+        if progress >= 1.0 {
+            return self.finish_transition();
+        }
+
+        let amount = transition::ease(config.easing, progress);
+        for layer in &mut self.layers {
+            let Some((from, Some(to))) = layer.shm_fade.as_ref() else {
+                continue;
+            };
+            let (Some(pool), Some(size)) = (layer.pool.as_mut(), layer.size) else {
+                continue;
+            };
+            let Some(frame) = transition::blend_frames(from, to, config.style, amount) else {
+                continue;
+            };
+            let transparent = layer.fade_transparent;
+            let format = if transparent {
+                draw::Format::Argb8888
+            } else {
+                // Blended in 16 bits when either end is drawn in 10
+                draw::Format::for_depth(draw::is_high_depth(&frame), self.ten_bit)
+            };
+            let (width, height) = (frame.width() as i32, frame.height() as i32);
+            match draw::canvas(pool, &frame, width, height, width * 4, format) {
+                Ok(buffer) => {
+                    GlowBerry::set_opaque(&self.compositor, layer.layer.wl_surface(), !transparent);
+                    draw::layer_surface(
+                        &layer.layer,
+                        &layer.viewport,
+                        &self.queue_handle,
+                        &buffer,
+                        &[[0, 0, width, height]],
+                        size,
+                    );
+                    layer.shm_frame = None;
+                    layer.drop_dmabufs();
+                }
+                Err(why) => tracing::warn!(?why, "transition frame could not be drawn"),
+            }
+        }
+        true
+    }
+
+    /// End a transition drawn by the CPU, drawing the new image as usual.
+    /// Returns `false`, for [`Self::draw_transition`].
+    fn finish_transition(&mut self) -> bool {
+        self.transition_start = None;
+        for layer in &mut self.layers {
+            if layer.shm_fade.take().is_some() {
+                layer.shm_frame = None;
+                layer.drop_dmabufs();
+                layer.needs_redraw = true;
+            }
+        }
+        self.draw();
+        false
+    }
+
+    /// Stop playing an animated image or switching a dynamic one.
+    fn stop_animation(&mut self) {
+        self.animation = None;
+        if let Some(token) = self.animation_token.take() {
+            self.loop_handle.remove(token);
+        }
+        self.animation_frames.clear();
+        self.animation_scaling = false;
+        if let Some(token) = self.animation_scaled_token.take() {
+            self.loop_handle.remove(token);
+        }
+        for layer in &mut self.layers {
+            let spare = layer.dmabuf_spare.take();
+            layer.retire_dmabuf(spare);
+        }
+        self.dynamic = None;
+        if let Some(token) = self.dynamic_token.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Bytes held by the wallpaper's decoded images, scaled frames, and SHM
+    /// pools.
+    pub(crate) fn memory(&self) -> u64 {
+        let bytes = |image: &DynamicImage| image.as_bytes().len() as u64;
+        let images = self.current_image.as_ref().map_or(0, bytes)
+            + self.predecoded.as_ref().map_or(0, |next| {
+                next.image.as_ref().map_or(0, bytes)
+                    + next
+                        .frames
+                        .iter()
+                        .map(|(_, frame)| bytes(frame))
+                        .sum::<u64>()
+            })
+            + self.animation.as_ref().map_or(0, Animation::cache_bytes);
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| {
+                layer.pool.as_ref().map_or(0, |pool| pool.len() as u64)
+                    + layer
+                        .shm_fade
+                        .as_ref()
+                        .map_or(0, |(from, to)| bytes(from) + to.as_ref().map_or(0, bytes))
+            })
+            .sum::<u64>();
+        images + layers
+    }
+
+    /// Free what can be decoded or drawn again: a still image already on
+    /// screen. The image decoded ahead of its turn is kept, as freeing it
+    /// would only waste the decode, SHM pools hold what the outputs show,
+    /// transitions only keep their frames while they run, and animations
+    /// keep to their own cache size. Returns the bytes freed.
+    pub(crate) fn evict(&mut self) -> u64 {
+        let before = self.memory();
+        if matches!(self.current_source, Some(Source::Path(_)))
+            && self.animation.is_none()
+            && self.dynamic.is_none()
+            && self.layers.iter().all(|layer| !layer.needs_redraw)
+            && self.current_image.take().is_some()
+        {
+            self.without_image = true;
+        }
+        before.saturating_sub(self.memory())
+    }
+
+    fn clear_image(&mut self) {
+        self.stop_animation();
         self.current_image = None;
+        self.without_image = false;
+        self.image_generation += 1;
         for l in &mut self.layers {
             l.needs_redraw = true;
         }
     }
 }
 
+/// How often a paused animated wallpaper checks whether it may play again.
+const ANIMATION_PAUSE_POLL: Duration = Duration::from_secs(1);
+
+/// Time between frames of a transition drawn by the CPU.
+const TRANSITION_FRAME: Duration = Duration::from_millis(40);
+
+/// Whether an image of `dimensions`, if they are known, passes `filter` on
+/// outputs of `aspects`, their widths over their heights.
+fn passes_filter(dimensions: Option<(u32, u32)>, filter: &ImageFilter, aspects: &[f32]) -> bool {
+    let Some((width, height)) = dimensions.filter(|(width, height)| *width > 0 && *height > 0)
+    else {
+        return true;
+    };
+    if let Some((min_width, min_height)) = filter.min_size
+        && (width < min_width || height < min_height)
+    {
+        return false;
+    }
+    let aspect = width as f32 / height as f32;
+    filter
+        .aspect_tolerance
+        .filter(|tolerance| tolerance.is_finite() && *tolerance >= 0.0)
+        .is_none_or(|tolerance| {
+            aspects
+                .iter()
+                .all(|output| (aspect - output).abs() <= output * tolerance)
+        })
+}
+
+/// Add the images at `source`, a folder or an image, to `queue`. Folders
+/// under the XDG data dirs' `backgrounds` are searched recursively.
+fn list_images(source: &Path, xdg_data_dirs: &[String], queue: &mut VecDeque<PathBuf>) {
+    let Ok(source) = source.canonicalize() else {
+        return;
+    };
+    // Shown as one image changing through the day
+    if kde_dynamic::is_dynamic(&source) {
+        queue.push_front(source);
+    } else if source.is_dir() {
+        if xdg_data_dirs
+            .iter()
+            .any(|xdg_data_dir| source.starts_with(xdg_data_dir))
+        {
+            // Store paths of wallpapers to be used for the slideshow.
+            for img_path in WalkDir::new(source)
+                .follow_links(true)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter_map(|p| p.path().canonicalize().ok())
+                .filter(|p| p.is_file())
+                .filter(|p| image_formats::is_supported(p))
+            {
+                queue.push_front(img_path);
+            }
+        } else if let Ok(dir) = source.read_dir() {
+            for entry in dir.filter_map(Result::ok) {
+                let Ok(path) = entry.path().canonicalize() else {
+                    continue;
+                };
+
+                if path.is_file() && image_formats::is_supported(&path) {
+                    queue.push_front(path);
+                }
+            }
+        }
+    } else if source.is_file() {
+        queue.push_front(source);
+    }
+}
+
+/// The images among `paths` a slideshow listing `queue` takes in: supported
+/// files not listed yet that pass `filter` on outputs of `aspects`.
+fn admitted_images(
+    paths: &[PathBuf],
+    queue: &VecDeque<PathBuf>,
+    filter: Option<&ImageFilter>,
+    aspects: &[f32],
+) -> Vec<PathBuf> {
+    let mut added: Vec<PathBuf> = paths
+        .iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .filter(|path| path.is_file() && image_formats::is_supported(path) && !queue.contains(path))
+        .collect();
+    added.sort();
+    added.dedup();
+    if let Some(filter) = filter {
+        added.retain(|path| {
+            passes_filter(
+                image_formats::cached_upright_dimensions(path),
+                filter,
+                aspects,
+            )
+        });
+    }
+    added
+}
+
+/// Hashes [`content_hash`] read, by path, with the length and modification
+/// time of the file they were read from.
+type ContentHashes = BTreeMap<PathBuf, (u64, SystemTime, u64)>;
+
+static CONTENT_HASHES: Mutex<ContentHashes> = Mutex::new(BTreeMap::new());
+
+/// Groups of `images` with the same contents, each in the order listed.
+/// Only files of the same size are read to compare them, and each version
+/// of a file only once.
+fn identical_groups(images: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+    let mut by_size: HashMap<u64, Vec<(&PathBuf, SystemTime)>> = HashMap::new();
+    for path in images {
+        if let Ok(metadata) = fs::metadata(path) {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            by_size
+                .entry(metadata.len())
+                .or_default()
+                .push((path, modified));
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (len, paths) in by_size.into_iter().filter(|(_, paths)| paths.len() > 1) {
+        let mut by_hash: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        for (path, modified) in paths {
+            let cached = CONTENT_HASHES
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .get(path)
+                .filter(|(read_len, read, _)| (*read_len, *read) == (len, modified))
+                .map(|(_, _, hash)| *hash);
+            let Some(hash) = cached.or_else(|| content_hash(path)) else {
+                continue;
+            };
+            CONTENT_HASHES
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(path.clone(), (len, modified, hash));
+            by_hash.entry(hash).or_default().push(path.clone());
+        }
+        groups.extend(by_hash.into_values().filter(|group| group.len() > 1));
+    }
+    groups
+}
+
+/// Hash of the contents of the file at `path`.
+fn content_hash(path: &Path) -> Option<u64> {
+    let mut file = File::open(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).ok()?;
+        if read == 0 {
+            return Some(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
+/// Apply `edit` to a slideshow queue whose last image is the current one.
+/// Returns whether the queue changed.
+fn edit_upcoming(queue: &mut VecDeque<PathBuf>, edit: &QueueEdit) -> bool {
+    let upcoming = queue.len().saturating_sub(1);
+    match edit {
+        QueueEdit::Move { from, to, .. } => {
+            if *from >= upcoming || *to >= upcoming || from == to {
+                return false;
+            }
+            if let Some(path) = queue.remove(*from) {
+                queue.insert(*to, path);
+            }
+        }
+        QueueEdit::PlayNext { path, .. } => {
+            if queue.back() == Some(path) && upcoming > 0 {
+                return false;
+            }
+            match queue
+                .iter()
+                .take(upcoming)
+                .position(|queued| queued == path)
+            {
+                Some(0) => return false,
+                Some(index) => {
+                    if let Some(path) = queue.remove(index) {
+                        queue.push_front(path);
+                    }
+                }
+                None => queue.push_front(path.clone()),
+            }
+        }
+    }
+    true
+}
+
+/// Scale `img` to `width`x`height` according to `mode`.
+pub(crate) fn scale(
+    mode: &ScalingMode,
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+) -> DynamicImage {
+    scaler::scale(img, (width, height), mode.into())
+}
+
+/// Scale the part of `img` that `entry` crops to `width`x`height` with
+/// `mode`, the image's scaling mode, or cut the output's `slice` of the
+/// layout out of it when spanned.
+fn scale_output(
+    entry: &Entry,
+    mode: &ScalingMode,
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    slice: Option<(scaler::Rect, scaler::Rect)>,
+) -> DynamicImage {
+    let region;
+    let img = match entry.crop {
+        Some(Crop::Region {
+            x,
+            y,
+            width,
+            height,
+        }) => {
+            region = scaler::region(img, x, y, width, height);
+            &region
+        }
+        _ => img,
+    };
+
+    match (slice, entry.crop, mode) {
+        (Some((layout, output)), ..) => scaler::span(img, layout, output, (width, height)),
+        (None, Some(Crop::Focus { x, y }), ScalingMode::Zoom) => {
+            scaler::zoom_at(img, width, height, (x, y))
+        }
+        (None, _, mode) => scale(mode, img, width, height),
+    }
+}
+
+/// Whether `source`, showing `current`, has more than 8 bits per channel:
+/// gradients and procedural backgrounds, and high-depth images.
+fn high_depth(source: &Source, current: Option<&DynamicImage>) -> bool {
+    match source {
+        Source::Color(Color::Gradient(_)) | Source::Procedural(_) => true,
+        Source::Color(Color::Single(_)) => false,
+        _ => current.is_some_and(draw::is_high_depth),
+    }
+}
+
+/// Where the output sits in the compositor's layout, as told by xdg-output.
+fn logical_rect(info: &OutputInfo) -> Option<scaler::Rect> {
+    let (x, y) = info.logical_position?;
+    let (width, height) = info.logical_size?;
+    Some(scaler::Rect {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+/// Bounding box of the outputs of `layers`, which a spanned image covers.
+fn span_layout(layers: &[GlowBerryLayer]) -> Option<scaler::Rect> {
+    layers
+        .iter()
+        .filter_map(|layer| logical_rect(&layer.output_info))
+        .reduce(scaler::Rect::union)
+}
+
+/// The last source that worked on `output`, an output name or entry
+/// pattern, to show instead of `configured`.
+fn last_known_good(output: &str, configured: &Source) -> Option<Source> {
+    let state = State::state().ok()?;
+    let state = State::get_entry(&state).unwrap_or_default();
+    pick_known_good(&state.last_known_good, output, configured)
+}
+
+/// First of the `known_good` sources for `output` that differs from
+/// `configured`, is of the same kind (shader or video, or static), and still
+/// exists.
+fn pick_known_good(
+    known_good: &[(String, Source)],
+    output: &str,
+    configured: &Source,
+) -> Option<Source> {
+    known_good
+        .iter()
+        .filter(|(name, _)| {
+            output == glowberry_config::DEFAULT_BACKGROUND
+                || glowberry_config::output_group::matches(output, name)
+        })
+        .map(|(_, source)| source)
+        .find(|source| {
+            *source != configured
+                && source.is_shader() == configured.is_shader()
+                && match source {
+                    Source::Path(path) => path.is_file(),
+                    _ => true,
+                }
+        })
+        .cloned()
+}
+
+/// Where the shuffled slideshow of the entry for `output` was last.
+fn saved_shuffle(output: &str) -> Option<Shuffle> {
+    let state = State::state().ok()?;
+    State::get_entry(&state)
+        .unwrap_or_default()
+        .shuffles
+        .into_iter()
+        .find(|(entry, _)| entry == output)
+        .map(|(_, shuffle)| shuffle)
+}
+
+/// Where a slideshow showing `queue` resumes: at `last`, the image it
+/// showed, or else at the place it had in its order, from `saved`, should
+/// the image be gone.
+fn resume_cursor(
+    queue: &VecDeque<PathBuf>,
+    last: Option<PathBuf>,
+    saved: impl FnOnce() -> Option<SlideshowPosition>,
+) -> Option<usize> {
+    if queue.is_empty() {
+        return None;
+    }
+    last.and_then(|last| queue.iter().position(|path| *path == last))
+        .or_else(|| Some(saved()?.cursor % queue.len()))
+}
+
+/// Where the slideshow of the entry for `output` was last.
+fn saved_position(output: &str) -> Option<SlideshowPosition> {
+    let state = State::state().ok()?;
+    State::get_entry(&state)
+        .unwrap_or_default()
+        .slideshow_positions
+        .into_iter()
+        .find(|(entry, _)| entry == output)
+        .map(|(_, position)| position)
+}
+
 fn current_image(output: &str) -> Option<Source> {
     let state = State::state().ok()?;
     let mut wallpapers = State::get_entry(&state)
@@ -442,14 +2952,43 @@ fn current_image(output: &str) -> Option<Source> {
     let wallpaper = if output == "all" {
         wallpapers.next()
     } else {
-        wallpapers.into_iter().find(|(name, _path)| name == output)
+        wallpapers
+            .into_iter()
+            .find(|(name, _path)| glowberry_config::output_group::matches(output, name))
     };
 
     wallpaper.map(|(_name, path)| path)
 }
 
+/// Decodes an image file, using `jxl-oxide` for JPEG XL, libheif for HEIF,
+/// imagepipe for camera RAW, and `image` otherwise, turned upright
+/// according to its EXIF orientation.
+pub fn decode(path: &Path) -> eyre::Result<DynamicImage> {
+    decode_within(path, None)
+}
+
+/// Like [`decode`], for outputs whose largest needs the image to cover
+/// `bound`: camera RAW is developed at about that size, and other formats,
+/// decoded at full size, are refused past
+/// [`image_formats::decode_limits`].
+pub fn decode_within(path: &Path, bound: Option<(u32, u32)>) -> eyre::Result<DynamicImage> {
+    if path.extension().is_some_and(|ext| ext == "jxl") {
+        return decode_jpegxl(path);
+    }
+    if image_formats::is_heif(path) {
+        return image_formats::decode_heif(path);
+    }
+    if raw::is_raw(path) {
+        return raw::decode_raw(path, bound);
+    }
+
+    let limits = bound.map_or_else(image::Limits::default, image_formats::decode_limits);
+    image_formats::open_upright_with(path, limits)
+        .map_err(|why| eyre!("failed to decode image: {why}"))
+}
+
 /// Decodes JPEG XL image files into `image::DynamicImage` via `jxl-oxide`.
-fn decode_jpegxl(path: &std::path::Path) -> eyre::Result<DynamicImage> {
+fn decode_jpegxl(path: &Path) -> eyre::Result<DynamicImage> {
     let file = File::open(path).map_err(|why| eyre!("failed to open jxl image file: {why}"))?;
 
     let decoder =
@@ -458,3 +2997,200 @@ fn decode_jpegxl(path: &std::path::Path) -> eyre::Result<DynamicImage> {
     image::DynamicImage::from_decoder(decoder)
         .map_err(|why| eyre!("failed to decode jxl image: {why}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(names: &[&str]) -> VecDeque<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn picks_a_known_good_source_for_the_output() {
+        let color = |value: f32| Source::Color(Color::Single([value; 3]));
+        let known_good = vec![
+            (
+                String::from("DP-1"),
+                Source::Path(PathBuf::from("/nonexistent.png")),
+            ),
+            (String::from("DP-1"), color(0.2)),
+            (String::from("HDMI-A-1"), color(0.5)),
+        ];
+
+        // Missing files and the configured source itself are skipped
+        assert_eq!(
+            pick_known_good(&known_good, "DP-1", &color(0.0)),
+            Some(color(0.2))
+        );
+        assert_eq!(pick_known_good(&known_good, "DP-1", &color(0.2)), None);
+        assert_eq!(
+            pick_known_good(&known_good, "HDMI-A-1", &color(0.0)),
+            Some(color(0.5))
+        );
+        assert_eq!(pick_known_good(&known_good, "eDP-1", &color(0.0)), None);
+        assert_eq!(
+            pick_known_good(&known_good, "all", &color(0.0)),
+            Some(color(0.2))
+        );
+    }
+
+    #[test]
+    fn edits_only_the_upcoming_images() {
+        // "d" is the current image
+        let mut images = queue(&["a", "b", "c", "d"]);
+        let output = String::from("all");
+
+        let edit = QueueEdit::Move {
+            output: output.clone(),
+            from: 2,
+            to: 0,
+        };
+        assert!(edit_upcoming(&mut images, &edit));
+        assert_eq!(images, queue(&["c", "a", "b", "d"]));
+
+        // The current image can't be moved
+        let edit = QueueEdit::Move {
+            output: output.clone(),
+            from: 3,
+            to: 0,
+        };
+        assert!(!edit_upcoming(&mut images, &edit));
+
+        let play_next = |path: &str| QueueEdit::PlayNext {
+            output: output.clone(),
+            path: PathBuf::from(path),
+        };
+        assert!(edit_upcoming(&mut images, &play_next("b")));
+        assert_eq!(images, queue(&["b", "c", "a", "d"]));
+        assert!(edit_upcoming(&mut images, &play_next("e")));
+        assert_eq!(images, queue(&["e", "b", "c", "a", "d"]));
+        assert!(!edit_upcoming(&mut images, &play_next("d")));
+    }
+
+    #[test]
+    fn filters_images_by_size_and_shape() {
+        let filter = ImageFilter {
+            min_size: Some((1920, 1080)),
+            aspect_tolerance: Some(0.15),
+        };
+        let wide = [16.0 / 9.0];
+
+        assert!(passes_filter(Some((3840, 2160)), &filter, &wide));
+        assert!(passes_filter(Some((2560, 1600)), &filter, &wide));
+        assert!(!passes_filter(Some((1280, 720)), &filter, &wide));
+        assert!(!passes_filter(Some((5120, 2160)), &filter, &wide));
+        assert!(!passes_filter(Some((2160, 3840)), &filter, &wide));
+        // Unknown sizes and outputs don't rule images out
+        assert!(passes_filter(None, &filter, &wide));
+        assert!(passes_filter(Some((2160, 3840)), &filter, &[]));
+        assert!(passes_filter(
+            Some((640, 480)),
+            &ImageFilter::default(),
+            &wide
+        ));
+    }
+
+    #[test]
+    fn drops_identical_images() {
+        let dir = std::env::temp_dir().join(format!("glowberry-duplicates-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in [("a", "one"), ("b", "two"), ("c", "one"), ("d", "uno")] {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+
+        let images: Vec<PathBuf> = ["a", "b", "c", "d"].map(|name| dir.join(name)).into();
+        assert_eq!(
+            identical_groups(&images),
+            [["a", "c"].map(|name| dir.join(name))]
+        );
+
+        // Read again once the file changes
+        fs::write(dir.join("c"), "eno").unwrap();
+        File::options()
+            .write(true)
+            .open(dir.join("c"))
+            .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(60)))
+            .unwrap();
+        assert!(identical_groups(&images).is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn admits_images_created_in_an_empty_folder() {
+        let dir = std::env::temp_dir().join(format!("glowberry-added-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+
+        fs::write(dir.join("a.png"), "one").unwrap();
+        let created = [dir.join("a.png"), dir.join("notes.txt")];
+        let mut queue = VecDeque::new();
+        let added = admitted_images(&created, &queue, None, &[]);
+        assert_eq!(added, [dir.join("a.png")]);
+
+        // Listed images are not taken in again, and copies of them are found
+        // among the new ones
+        queue.extend(added);
+        fs::write(dir.join("b.png"), "one").unwrap();
+        fs::write(dir.join("c.png"), "two").unwrap();
+        let created = [dir.join("a.png"), dir.join("b.png"), dir.join("c.png")];
+        let added = admitted_images(&created, &queue, None, &[]);
+        assert_eq!(added, [dir.join("b.png"), dir.join("c.png")]);
+        queue.extend(added);
+        assert_eq!(
+            identical_groups(queue.make_contiguous()),
+            [[dir.join("a.png"), dir.join("b.png")]]
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn depth_follows_the_source_not_the_scaled_image() {
+        let path = Source::Path(PathBuf::from("/wallpaper.png"));
+        let rgb8 = DynamicImage::new_rgb8(1, 1);
+        let rgb16 = DynamicImage::new_rgb16(1, 1);
+
+        assert!(!high_depth(&path, Some(&rgb8)));
+        assert!(high_depth(&path, Some(&rgb16)));
+        assert!(!high_depth(&path, None));
+        assert!(!high_depth(&Source::Color(Color::Single([0.5; 3])), None));
+        let procedural = glowberry_config::Procedural {
+            pattern: Default::default(),
+            seed: 0,
+            palette: Vec::new(),
+        };
+        assert!(high_depth(&Source::Procedural(procedural), None));
+    }
+
+    #[test]
+    fn resumes_with_the_last_image_or_its_place() {
+        let images = queue(&["a", "b", "c"]);
+        let saved = || {
+            Some(SlideshowPosition {
+                image: PathBuf::from("gone"),
+                cursor: 4,
+            })
+        };
+
+        assert_eq!(
+            resume_cursor(&images, Some(PathBuf::from("c")), saved),
+            Some(2)
+        );
+        // The image is gone: carry on from its place
+        assert_eq!(
+            resume_cursor(&images, Some(PathBuf::from("gone")), saved),
+            Some(1)
+        );
+        assert_eq!(resume_cursor(&images, None, saved), Some(1));
+        // Nothing saved, such as for random orders
+        assert_eq!(
+            resume_cursor(&images, Some(PathBuf::from("gone")), || None),
+            None
+        );
+        assert_eq!(resume_cursor(&VecDeque::new(), None, saved), None);
+    }
+}
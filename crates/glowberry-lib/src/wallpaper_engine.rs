@@ -0,0 +1,525 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Import of Steam Wallpaper Engine projects.
+//!
+//! A project is a folder with a `project.json` naming its type and main file.
+//! The common subset is converted:
+//! - Video projects become video sources.
+//! - Scene projects, unpacked or in a `scene.pkg`, have their image layers
+//!   flattened into one image, placed as the scene places them. Layer
+//!   textures are read when the `.tex` file embeds a PNG or JPEG, as it does
+//!   for images imported into the editor. Scenes with particle systems
+//!   become composite sources, with [`PARTICLES`] drifting over the image.
+//!
+//! Layer effects, sounds, and textures that can't be read have nothing to be
+//! converted to, so scenes with them are rejected unless the import is
+//! partial, which leaves them out, falling back to the project's preview
+//! image when no layer can be shown. Web and application projects can't be
+//! imported.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use glowberry_config::{
+    BlendMode, CompositeSource, ScalingMode, ShaderContent, ShaderLanguage, ShaderSource, Source,
+    VideoSource,
+};
+use image::{RgbaImage, imageops};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Built-in overlay standing in for a scene's particle systems: soft motes
+/// drifting up over the image.
+pub const PARTICLES: &str = r#"// [SHADER]
+// name: Wallpaper Engine particles
+//
+// [PARAMS]
+// density: i32 = 48 | min: 8 | max: 160 | step: 8 | label: Density
+// speed: f32 = 0.04 | min: 0.01 | max: 0.2 | step: 0.01 | label: Speed
+// [/PARAMS]
+
+const density: i32 = 48;
+const speed: f32 = 0.04;
+
+fn mote_hash(n: f32) -> f32 {
+    return fract(sin(n * 127.1) * 43758.5453);
+}
+
+@fragment
+fn main(@builtin(position) fragCoord: vec4<f32>) -> @location(0) vec4<f32> {
+    let scale = min(iResolution.x, iResolution.y);
+    let uv = fragCoord.xy / scale;
+    let extent = iResolution / scale;
+    var glow = 0.0;
+    for (var i = 0; i < density; i++) {
+        let n = f32(i);
+        let rate = speed * (0.5 + mote_hash(n + 3.0));
+        let x = mote_hash(n) * extent.x + 0.02 * sin(iTime * 0.7 + n);
+        let y = extent.y - fract(mote_hash(n + 7.0) + iTime * rate) * (extent.y + 0.1);
+        let size = 0.002 + 0.004 * mote_hash(n + 11.0);
+        let d = length(uv - vec2<f32>(x, y));
+        glow += smoothstep(size * 3.0, 0.0, d) * (0.4 + 0.6 * mote_hash(n + 13.0));
+    }
+    return vec4<f32>(vec3<f32>(1.0, 0.95, 0.85), clamp(glow, 0.0, 1.0));
+}
+"#;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid {0}: {1}")]
+    Json(String, serde_json::Error),
+    #[error("failed to save extracted image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("{0} projects can't be imported, only scene and video ones")]
+    Unsupported(String),
+    #[error("project has neither an image layer nor a preview that can be shown")]
+    NoImage,
+    #[error("scene has parts that can't be converted: {}", .0.join(", "))]
+    Unconvertible(Vec<String>),
+}
+
+/// A converted project.
+#[derive(Debug)]
+pub struct Import {
+    pub title: Option<String>,
+    pub source: Source,
+    /// Descriptions of the parts that were left out, by a partial import
+    pub skipped: Vec<String>,
+}
+
+/// Folder that images extracted from Wallpaper Engine projects are saved in.
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("glowberry")
+        .join("wallpaper-engine")
+}
+
+/// Convert the project in `project_dir`, saving any image that has to be
+/// extracted under `out_dir`. A `partial` import leaves out the parts of a
+/// scene that can't be converted rather than failing.
+pub fn import(project_dir: &Path, out_dir: &Path, partial: bool) -> Result<Import, ImportError> {
+    let project: Value = read_json(&project_dir.join("project.json"))?;
+    let title = project
+        .get("title")
+        .and_then(Value::as_str)
+        .map(String::from);
+    let kind = project
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or("scene")
+        .to_ascii_lowercase();
+    let file = project.get("file").and_then(Value::as_str);
+
+    let mut skipped = Vec::new();
+    let source = match kind.as_str() {
+        "video" => {
+            let file = file.ok_or_else(|| ImportError::Unsupported(kind.clone()))?;
+            Source::Video(VideoSource {
+                path: project_dir.join(file),
+                frame_rate: 30,
+            })
+        }
+        "scene" => {
+            let files = SceneFiles::open(project_dir)?;
+            let scene = match files.get(file.unwrap_or("scene.json")) {
+                Some(scene) => {
+                    let scene: Value = serde_json::from_slice(&scene)
+                        .map_err(|why| ImportError::Json(String::from("scene.json"), why))?;
+                    convert_scene(&scene, &files)
+                }
+                None => Scene::default(),
+            };
+            let mut unconvertible = scene.unconvertible;
+
+            let image = match scene.image {
+                Some(image) => {
+                    std::fs::create_dir_all(out_dir)?;
+                    let path = out_dir.join("scene.png");
+                    image.save(&path)?;
+                    path
+                }
+                None => {
+                    let preview = project
+                        .get("preview")
+                        .and_then(Value::as_str)
+                        .map(|preview| project_dir.join(preview))
+                        .filter(|preview| preview.is_file())
+                        .ok_or(ImportError::NoImage)?;
+                    unconvertible.push(String::from("scene layers, showing the preview instead"));
+                    preview
+                }
+            };
+
+            if !unconvertible.is_empty() && !partial {
+                return Err(ImportError::Unconvertible(unconvertible));
+            }
+            skipped = unconvertible;
+
+            if scene.particles {
+                Source::Composite(CompositeSource {
+                    image,
+                    overlay: particles_source(),
+                    blend: BlendMode::Screen,
+                    opacity: 1.0,
+                })
+            } else {
+                Source::Path(image)
+            }
+        }
+        _ => return Err(ImportError::Unsupported(kind.clone())),
+    };
+
+    Ok(Import {
+        title,
+        source,
+        skipped,
+    })
+}
+
+/// The [`PARTICLES`] overlay of a composite source.
+fn particles_source() -> ShaderSource {
+    ShaderSource {
+        shader: ShaderContent::Code(String::from(PARTICLES)),
+        source_path: None,
+        params: Default::default(),
+        background_image: None,
+        depth_image: None,
+        background_from_wallpaper: false,
+        background_scaling: ScalingMode::default(),
+        language: ShaderLanguage::Wgsl,
+        frame_rate: 30,
+        time_scale: 1.0,
+    }
+}
+
+/// Files of a scene, from its folder or its `scene.pkg`.
+enum SceneFiles<'a> {
+    Folder(&'a Path),
+    Package(HashMap<String, Vec<u8>>),
+}
+
+impl<'a> SceneFiles<'a> {
+    fn open(dir: &'a Path) -> Result<Self, ImportError> {
+        let package = dir.join("scene.pkg");
+        if !package.is_file() {
+            return Ok(Self::Folder(dir));
+        }
+        let bytes = std::fs::read(&package)?;
+        parse_package(&bytes).map(Self::Package).ok_or_else(|| {
+            ImportError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "scene.pkg is not a Wallpaper Engine package",
+            ))
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        match self {
+            Self::Folder(dir) => std::fs::read(dir.join(name)).ok(),
+            Self::Package(files) => files.get(name).cloned(),
+        }
+    }
+
+    fn json(&self, name: &str) -> Option<Value> {
+        serde_json::from_slice(&self.get(name)?).ok()
+    }
+}
+
+/// Entries of a `scene.pkg`: a version string, then the name, offset, and
+/// length of each file, then the files.
+fn parse_package(bytes: &[u8]) -> Option<HashMap<String, Vec<u8>>> {
+    let mut cursor = 0;
+    let u32_at = |cursor: &mut usize| {
+        let value = u32::from_le_bytes(bytes.get(*cursor..*cursor + 4)?.try_into().ok()?);
+        *cursor += 4;
+        Some(value as usize)
+    };
+    let string_at = |cursor: &mut usize| {
+        let len = u32::from_le_bytes(bytes.get(*cursor..*cursor + 4)?.try_into().ok()?) as usize;
+        let string = std::str::from_utf8(bytes.get(*cursor + 4..*cursor + 4 + len)?).ok()?;
+        *cursor += 4 + len;
+        Some(string.to_string())
+    };
+
+    if !string_at(&mut cursor)?.starts_with("PKGV") {
+        return None;
+    }
+    let count = u32_at(&mut cursor)?;
+    let mut entries = Vec::with_capacity(count.min(4096));
+    for _ in 0..count {
+        let name = string_at(&mut cursor)?;
+        let offset = u32_at(&mut cursor)?;
+        let len = u32_at(&mut cursor)?;
+        entries.push((name, offset, len));
+    }
+
+    let data = &bytes[cursor..];
+    entries
+        .into_iter()
+        .map(|(name, offset, len)| Some((name, data.get(offset..offset + len)?.to_vec())))
+        .collect()
+}
+
+/// What a scene converts to.
+#[derive(Default)]
+struct Scene {
+    /// The image layers flattened, when any could be read
+    image: Option<RgbaImage>,
+    /// Whether the scene has particle systems
+    particles: bool,
+    /// Descriptions of the parts that can't be converted
+    unconvertible: Vec<String>,
+}
+
+/// Flatten the image layers of `scene` in their order, each centered on its
+/// origin at its size and scale, on a canvas the size of the scene's
+/// projection. Scenes without one take the size of their largest layer.
+fn convert_scene(scene: &Value, files: &SceneFiles) -> Scene {
+    let mut converted = Scene::default();
+    let mut layers = Vec::new();
+
+    for object in scene
+        .get("objects")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if object.get("visible").and_then(Value::as_bool) == Some(false) {
+            continue;
+        }
+        let name = object
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or("unnamed");
+        if object.get("particle").is_some() {
+            converted.particles = true;
+            continue;
+        }
+        if object.get("sound").is_some() {
+            converted.unconvertible.push(format!("sound \"{name}\""));
+            continue;
+        }
+        let Some(model) = object.get("image").and_then(Value::as_str) else {
+            continue;
+        };
+        for effect in object
+            .get("effects")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let effect = effect
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or("effect");
+            converted
+                .unconvertible
+                .push(format!("effect \"{effect}\" on \"{name}\""));
+        }
+
+        let Some(texture) = layer_texture(files, model) else {
+            converted
+                .unconvertible
+                .push(format!("image \"{name}\", whose texture can't be read"));
+            continue;
+        };
+        let (width, height) = texture.dimensions();
+        let size = vector(object, "size").unwrap_or([width as f32, height as f32, 0.0]);
+        let scale = vector(object, "scale").unwrap_or([1.0; 3]);
+        let origin = vector(object, "origin");
+        layers.push((texture, [size[0] * scale[0], size[1] * scale[1]], origin));
+    }
+
+    let area = |[width, height]: [f32; 2]| width * height;
+    let Some(&(_, largest, _)) = layers.iter().max_by(|a, b| area(a.1).total_cmp(&area(b.1)))
+    else {
+        return converted;
+    };
+    let projection = scene
+        .get("general")
+        .and_then(|general| general.get("orthogonalprojection"));
+    let dimension = |key: &str| {
+        projection
+            .and_then(|projection| projection.get(key))
+            .and_then(Value::as_f64)
+            .map(|value| value as f32)
+    };
+    let (width, height) = (
+        dimension("width").unwrap_or(largest[0]).max(1.0),
+        dimension("height").unwrap_or(largest[1]).max(1.0),
+    );
+
+    let mut canvas = RgbaImage::new(width as u32, height as u32);
+    for (texture, [layer_width, layer_height], origin) in layers {
+        let [x, y, _] = origin.unwrap_or([width / 2.0, height / 2.0, 0.0]);
+        let (layer_width, layer_height) = (layer_width.max(1.0), layer_height.max(1.0));
+        let texture = imageops::resize(
+            &texture,
+            layer_width as u32,
+            layer_height as u32,
+            imageops::FilterType::Triangle,
+        );
+        // Scene coordinates grow upwards
+        imageops::overlay(
+            &mut canvas,
+            &texture,
+            (x - layer_width / 2.0) as i64,
+            (height - y - layer_height / 2.0) as i64,
+        );
+    }
+    converted.image = Some(canvas);
+    converted
+}
+
+/// A vector of `object`, written as a string of up to three numbers.
+fn vector(object: &Value, key: &str) -> Option<[f32; 3]> {
+    let mut vector = [0.0; 3];
+    let mut parts = object.get(key)?.as_str()?.split_whitespace();
+    for (value, part) in vector.iter_mut().zip(&mut parts) {
+        *value = part.parse().ok()?;
+    }
+    Some(vector)
+}
+
+/// The texture of an image layer: its model names a material, whose first
+/// pass names a texture under `materials/`.
+fn layer_texture(files: &SceneFiles, model: &str) -> Option<RgbaImage> {
+    let material = files.json(model)?;
+    let material = files.json(material.get("material")?.as_str()?)?;
+    let texture = material
+        .get("passes")?
+        .get(0)?
+        .get("textures")?
+        .get(0)?
+        .as_str()?;
+    let bytes = files.get(&format!("materials/{texture}.tex"))?;
+    embedded_image(&bytes).map(|image| image.to_rgba8())
+}
+
+/// The PNG or JPEG file embedded in a `.tex` texture, if it has one.
+fn embedded_image(tex: &[u8]) -> Option<image::DynamicImage> {
+    const SIGNATURES: [&[u8]; 2] = [b"\x89PNG\r\n\x1a\n", b"\xff\xd8\xff"];
+    SIGNATURES.iter().find_map(|signature| {
+        let start = tex
+            .windows(signature.len())
+            .position(|window| window == *signature)?;
+        image::load_from_memory(&tex[start..]).ok()
+    })
+}
+
+fn read_json(path: &Path) -> Result<Value, ImportError> {
+    let bytes = std::fs::read(path)?;
+    serde_json::from_slice(&bytes).map_err(|why| ImportError::Json(path.display().to_string(), why))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SceneFiles, convert_scene, embedded_image, parse_package, vector};
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use serde_json::json;
+    use std::io::Cursor;
+
+    fn string(bytes: &mut Vec<u8>, string: &str) {
+        bytes.extend((string.len() as u32).to_le_bytes());
+        bytes.extend(string.as_bytes());
+    }
+
+    #[test]
+    fn reads_scene_packages() {
+        let mut pkg = Vec::new();
+        string(&mut pkg, "PKGV0019");
+        pkg.extend(2u32.to_le_bytes());
+        string(&mut pkg, "scene.json");
+        pkg.extend(0u32.to_le_bytes());
+        pkg.extend(2u32.to_le_bytes());
+        string(&mut pkg, "materials/sky.tex");
+        pkg.extend(2u32.to_le_bytes());
+        pkg.extend(3u32.to_le_bytes());
+        pkg.extend(b"{}TEX");
+
+        let files = parse_package(&pkg).unwrap();
+        assert_eq!(files["scene.json"], b"{}");
+        assert_eq!(files["materials/sky.tex"], b"TEX");
+
+        assert!(parse_package(b"\x04\0\0\0ZIPS").is_none());
+        // An entry past the end of the file
+        pkg.truncate(pkg.len() - 1);
+        assert!(parse_package(&pkg).is_none());
+    }
+
+    #[test]
+    fn finds_images_embedded_in_textures() {
+        let mut png = Vec::new();
+        RgbaImage::new(3, 2)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let mut tex = b"TEXV0005\0TEXI0001\0\x01\x02\x03".to_vec();
+        tex.extend(&png);
+
+        let image = embedded_image(&tex).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert!(embedded_image(b"TEXV0005\0raw pixels").is_none());
+    }
+
+    /// A `.tex` texture embedding a `width` by `height` PNG of `color`.
+    fn texture(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+        let mut tex = b"TEXV0005\0TEXI0001\0".to_vec();
+        RgbaImage::from_pixel(width, height, Rgba(color))
+            .write_to(&mut Cursor::new(&mut tex), ImageFormat::Png)
+            .unwrap();
+        tex
+    }
+
+    #[test]
+    fn flattens_image_layers() {
+        let mut files = std::collections::HashMap::new();
+        for (layer, color) in [("sky", [0, 0, 255, 255]), ("sun", [255, 255, 0, 255])] {
+            files.insert(
+                format!("models/{layer}.json"),
+                json!({ "material": format!("materials/{layer}.json") })
+                    .to_string()
+                    .into_bytes(),
+            );
+            files.insert(
+                format!("materials/{layer}.json"),
+                json!({ "passes": [{ "textures": [layer] }] })
+                    .to_string()
+                    .into_bytes(),
+            );
+            files.insert(format!("materials/{layer}.tex"), texture(2, 2, color));
+        }
+        let files = SceneFiles::Package(files);
+        let scene = json!({
+            "general": { "orthogonalprojection": { "width": 8, "height": 4 } },
+            "objects": [
+                { "name": "sky", "image": "models/sky.json", "size": "8 4", "origin": "4 2 0" },
+                // A 2 by 2 sun in the top right corner
+                { "name": "sun", "image": "models/sun.json", "origin": "7 3 0" },
+                { "name": "snow", "particle": "particles/snow.json" },
+                { "name": "wind", "sound": ["sounds/wind.mp3"] },
+            ],
+        });
+
+        let scene = convert_scene(&scene, &files);
+        let image = scene.image.unwrap();
+        assert_eq!(image.dimensions(), (8, 4));
+        assert_eq!(image.get_pixel(0, 3).0, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(7, 0).0, [255, 255, 0, 255]);
+        assert_eq!(image.get_pixel(5, 2).0, [0, 0, 255, 255]);
+        assert!(scene.particles);
+        assert_eq!(scene.unconvertible, ["sound \"wind\""]);
+    }
+
+    #[test]
+    fn reads_vectors() {
+        let object = json!({ "origin": "960 540.5 0", "scale": "2 2" });
+        assert_eq!(vector(&object, "origin"), Some([960.0, 540.5, 0.0]));
+        assert_eq!(vector(&object, "scale"), Some([2.0, 2.0, 0.0]));
+        assert_eq!(vector(&object, "size"), None);
+    }
+}
@@ -0,0 +1,309 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Local weather conditions for ambient shader wallpapers.
+//!
+//! A listener, see [`crate::listener`], periodically fetches current conditions from
+//! [Open-Meteo](https://open-meteo.com) for the configured location and
+//! publishes them for the renderer, which uploads them to shaders as the
+//! `iWeather` uniform. The last response for each location is cached on
+//! disk, so shaders keep matching skies across restarts and while offline.
+//!
+//! Each [`WeatherMonitor`] publishes to its own [`WeatherHandle`], and its
+//! listener checks the monitor is still running before publishing, so a
+//! fetch still in flight when the config changes never shows the old
+//! location.
+//!
+//! Fetching is only available with the `weather` cargo feature; without it
+//! [`WeatherMonitor::start`] returns `None` and shaders see the defaults.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use glowberry_config::weather::WeatherConfig;
+
+#[cfg(feature = "weather")]
+use crate::listener::Listener;
+
+/// How long a cached response is trusted when the network is unavailable.
+#[cfg_attr(not(feature = "weather"), allow(dead_code))]
+const CACHE_MAX_AGE: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// How long the weather config must stay unchanged before the provider
+/// restarts, so editing the location doesn't start a fetch per keystroke.
+pub const RESTART_DELAY: Duration = Duration::from_secs(2);
+
+/// Current weather conditions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weather {
+    /// Air temperature at 2m, in degrees Celsius.
+    pub temperature: f32,
+    /// Total cloud cover, 0.0-1.0.
+    pub cloud_cover: f32,
+    /// Precipitation over the last interval, in millimetres.
+    pub precipitation: f32,
+    /// Whether the sun is up at the location.
+    pub is_day: bool,
+}
+
+impl Default for Weather {
+    /// A mild, clear day, used until real data is available.
+    fn default() -> Self {
+        Self {
+            temperature: 15.0,
+            cloud_cover: 0.0,
+            precipitation: 0.0,
+            is_day: true,
+        }
+    }
+}
+
+impl Weather {
+    /// Pack as `vec4f(temperature, cloud_cover, precipitation, is_day)`.
+    pub fn uniform(&self) -> [f32; 4] {
+        [
+            self.temperature,
+            self.cloud_cover,
+            self.precipitation,
+            if self.is_day { 1.0 } else { 0.0 },
+        ]
+    }
+}
+
+/// Handle to the latest known weather.
+#[derive(Clone, Debug, Default)]
+pub struct WeatherHandle {
+    weather: Arc<Mutex<Option<Weather>>>,
+}
+
+impl WeatherHandle {
+    /// Get the latest weather, or the defaults if none has been fetched yet.
+    pub fn current(&self) -> Weather {
+        self.weather
+            .lock()
+            .ok()
+            .and_then(|w| *w)
+            .unwrap_or_default()
+    }
+
+    #[cfg_attr(not(feature = "weather"), allow(dead_code))]
+    fn publish(&self, weather: Weather) {
+        if let Ok(mut shared) = self.weather.lock() {
+            *shared = Some(weather);
+        }
+    }
+}
+
+/// Running weather provider. Fetching stops when this is dropped.
+#[derive(Debug)]
+pub struct WeatherMonitor {
+    handle: WeatherHandle,
+    #[cfg(feature = "weather")]
+    _listener: Listener,
+}
+
+impl WeatherMonitor {
+    /// Start fetching weather for the configured location.
+    ///
+    /// Returns `None` if the `weather` feature is disabled or no valid
+    /// location is configured.
+    pub fn start(config: &WeatherConfig) -> Option<Self> {
+        let Some((latitude, longitude)) = config.location() else {
+            tracing::warn!("Weather enabled, but no valid latitude/longitude is configured");
+            return None;
+        };
+
+        #[cfg(feature = "weather")]
+        {
+            let handle = WeatherHandle::default();
+            let interval = Duration::from_secs(u64::from(config.refresh_minutes) * 60);
+
+            let task_handle = handle.clone();
+            let listener = Listener::spawn("weather", move |running| async move {
+                fetch::run(latitude, longitude, interval, &task_handle, &running).await;
+            })?;
+
+            tracing::info!(latitude, longitude, "Weather provider started");
+            Some(Self {
+                handle,
+                _listener: listener,
+            })
+        }
+
+        #[cfg(not(feature = "weather"))]
+        {
+            let _ = (latitude, longitude);
+            tracing::warn!("Weather uniforms requested, but built without the `weather` feature");
+            None
+        }
+    }
+
+    /// Get a handle to the latest weather.
+    pub fn handle(&self) -> WeatherHandle {
+        self.handle.clone()
+    }
+}
+
+/// Location of the cached Open-Meteo response for a location.
+#[cfg_attr(not(feature = "weather"), allow(dead_code))]
+fn cache_path(latitude: f64, longitude: f64) -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| {
+        dir.join("glowberry")
+            .join(cache_file_name(latitude, longitude))
+    })
+}
+
+/// Cache file of a location, at the precision it is fetched with.
+#[cfg_attr(not(feature = "weather"), allow(dead_code))]
+fn cache_file_name(latitude: f64, longitude: f64) -> String {
+    format!("weather-{latitude:.3}_{longitude:.3}.json")
+}
+
+/// Parse the `current` block of an Open-Meteo forecast response.
+#[cfg_attr(not(feature = "weather"), allow(dead_code))]
+fn parse_open_meteo(body: &str) -> Option<Weather> {
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    let current = json.get("current")?;
+    let number = |key: &str| current.get(key).and_then(serde_json::Value::as_f64);
+
+    Some(Weather {
+        temperature: number("temperature_2m")? as f32,
+        cloud_cover: (number("cloud_cover").unwrap_or(0.0) / 100.0).clamp(0.0, 1.0) as f32,
+        precipitation: number("precipitation").unwrap_or(0.0).max(0.0) as f32,
+        is_day: number("is_day").is_none_or(|v| v > 0.5),
+    })
+}
+
+#[cfg(feature = "weather")]
+mod fetch {
+    use super::{CACHE_MAX_AGE, Weather, WeatherHandle, cache_path, parse_open_meteo};
+    use crate::listener::Running;
+    use std::time::Duration;
+
+    /// Delay before retrying after a failed fetch.
+    const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+    pub(super) async fn run(
+        latitude: f64,
+        longitude: f64,
+        interval: Duration,
+        handle: &WeatherHandle,
+        running: &Running,
+    ) {
+        // Seed from the cache so shaders have data before the first fetch
+        if let Some(weather) = read_cache(latitude, longitude) {
+            tracing::debug!(?weather, "Loaded cached weather");
+            handle.publish(weather);
+        }
+
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(20)))
+            .user_agent(concat!("glowberry/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .into();
+
+        loop {
+            let fetched = fetch(&agent, latitude, longitude);
+            // The monitor may have been replaced during the fetch
+            if !running.is_running() {
+                break;
+            }
+
+            let wait = match fetched {
+                Ok((weather, body)) => {
+                    tracing::debug!(?weather, "Fetched weather");
+                    handle.publish(weather);
+                    write_cache(latitude, longitude, &body);
+                    interval
+                }
+                Err(err) => {
+                    tracing::warn!(%err, "Failed to fetch weather, keeping last known conditions");
+                    RETRY_INTERVAL.min(interval)
+                }
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+
+        tracing::debug!("Weather provider stopped");
+    }
+
+    fn fetch(
+        agent: &ureq::Agent,
+        latitude: f64,
+        longitude: f64,
+    ) -> eyre::Result<(Weather, String)> {
+        let url = format!(
+            "https://api.open-meteo.com/v1/forecast?latitude={latitude:.3}&longitude={longitude:.3}\
+             &current=temperature_2m,cloud_cover,precipitation,is_day"
+        );
+        let body = agent.get(&url).call()?.body_mut().read_to_string()?;
+        let weather =
+            parse_open_meteo(&body).ok_or_else(|| eyre::eyre!("unexpected Open-Meteo response"))?;
+        Ok((weather, body))
+    }
+
+    fn read_cache(latitude: f64, longitude: f64) -> Option<Weather> {
+        let path = cache_path(latitude, longitude)?;
+        let age = std::fs::metadata(&path)
+            .ok()?
+            .modified()
+            .ok()?
+            .elapsed()
+            .ok()?;
+        if age > CACHE_MAX_AGE {
+            return None;
+        }
+        parse_open_meteo(&std::fs::read_to_string(path).ok()?)
+    }
+
+    fn write_cache(latitude: f64, longitude: f64, body: &str) {
+        let Some(path) = cache_path(latitude, longitude) else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(err) = std::fs::write(&path, body) {
+            tracing::debug!(?err, "Failed to cache weather response");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_open_meteo_current_conditions() {
+        let body = r#"{
+            "latitude": 52.52,
+            "current": {
+                "time": "2026-10-16T12:00",
+                "interval": 900,
+                "temperature_2m": 11.5,
+                "cloud_cover": 75,
+                "precipitation": 0.4,
+                "is_day": 0
+            }
+        }"#;
+
+        let weather = parse_open_meteo(body).unwrap();
+        assert_eq!(weather.uniform(), [11.5, 0.75, 0.4, 0.0]);
+    }
+
+    #[test]
+    fn rejects_responses_without_current_block() {
+        assert_eq!(parse_open_meteo(r#"{"error": true}"#), None);
+        assert_eq!(parse_open_meteo("not json"), None);
+    }
+
+    #[test]
+    fn cache_is_keyed_by_location() {
+        assert_eq!(cache_file_name(52.52, 13.405), "weather-52.520_13.405.json");
+        assert_ne!(
+            cache_file_name(52.52, 13.405),
+            cache_file_name(48.857, 2.352)
+        );
+    }
+}
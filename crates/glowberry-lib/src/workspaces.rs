@@ -0,0 +1,138 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The workspace active on each output, through `ext_workspace_manager_v1`.
+//!
+//! The compositor announces groups of workspaces, each shown on some
+//! outputs, and the workspaces in them with their names and states. Changes
+//! arrive one event at a time and are complete at the manager's `done`
+//! event, when entries with workspace sources switch to the one for the
+//! workspace now active on their output.
+
+use sctk::reexports::{
+    client::{WEnum, protocol::wl_output::WlOutput},
+    protocols::ext::workspace::v1::client::{
+        ext_workspace_group_handle_v1::{self, ExtWorkspaceGroupHandleV1},
+        ext_workspace_handle_v1::{self, ExtWorkspaceHandleV1},
+        ext_workspace_manager_v1::ExtWorkspaceManagerV1,
+    },
+};
+
+#[derive(Debug)]
+struct Group {
+    handle: ExtWorkspaceGroupHandleV1,
+    outputs: Vec<WlOutput>,
+    workspaces: Vec<ExtWorkspaceHandleV1>,
+}
+
+#[derive(Debug)]
+struct Workspace {
+    handle: ExtWorkspaceHandleV1,
+    name: String,
+    active: bool,
+}
+
+/// The workspaces announced by the compositor.
+#[derive(Debug)]
+pub struct Workspaces {
+    _manager: ExtWorkspaceManagerV1,
+    groups: Vec<Group>,
+    workspaces: Vec<Workspace>,
+}
+
+impl Workspaces {
+    pub fn new(manager: ExtWorkspaceManagerV1) -> Self {
+        Self {
+            _manager: manager,
+            groups: Vec::new(),
+            workspaces: Vec::new(),
+        }
+    }
+
+    /// Track a workspace group announced by the manager.
+    pub fn add_group(&mut self, handle: ExtWorkspaceGroupHandleV1) {
+        self.groups.push(Group {
+            handle,
+            outputs: Vec::new(),
+            workspaces: Vec::new(),
+        });
+    }
+
+    /// Track a workspace announced by the manager.
+    pub fn add_workspace(&mut self, handle: ExtWorkspaceHandleV1) {
+        self.workspaces.push(Workspace {
+            handle,
+            name: String::new(),
+            active: false,
+        });
+    }
+
+    /// Apply an event for the workspace group `handle`.
+    pub fn on_group_event(
+        &mut self,
+        handle: &ExtWorkspaceGroupHandleV1,
+        event: ext_workspace_group_handle_v1::Event,
+    ) {
+        use ext_workspace_group_handle_v1::Event;
+
+        let Some(index) = self.groups.iter().position(|g| &g.handle == handle) else {
+            return;
+        };
+        let group = &mut self.groups[index];
+        match event {
+            Event::OutputEnter { output } => {
+                if !group.outputs.contains(&output) {
+                    group.outputs.push(output);
+                }
+            }
+            Event::OutputLeave { output } => group.outputs.retain(|o| o != &output),
+            Event::WorkspaceEnter { workspace } => {
+                if !group.workspaces.contains(&workspace) {
+                    group.workspaces.push(workspace);
+                }
+            }
+            Event::WorkspaceLeave { workspace } => group.workspaces.retain(|w| w != &workspace),
+            Event::Removed => {
+                let group = self.groups.remove(index);
+                group.handle.destroy();
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply an event for the workspace `handle`.
+    pub fn on_workspace_event(
+        &mut self,
+        handle: &ExtWorkspaceHandleV1,
+        event: ext_workspace_handle_v1::Event,
+    ) {
+        use ext_workspace_handle_v1::{Event, State};
+
+        let Some(index) = self.workspaces.iter().position(|w| &w.handle == handle) else {
+            return;
+        };
+        let workspace = &mut self.workspaces[index];
+        match event {
+            Event::Name { name } => workspace.name = name,
+            Event::State {
+                state: WEnum::Value(state),
+            } => workspace.active = state.contains(State::Active),
+            Event::Removed => {
+                let workspace = self.workspaces.remove(index);
+                for group in &mut self.groups {
+                    group.workspaces.retain(|w| w != &workspace.handle);
+                }
+                workspace.handle.destroy();
+            }
+            _ => {}
+        }
+    }
+
+    /// Name of the workspace active on `output`.
+    pub fn active(&self, output: &WlOutput) -> Option<&str> {
+        let group = self.groups.iter().find(|g| g.outputs.contains(output))?;
+        self.workspaces
+            .iter()
+            .find(|w| w.active && group.workspaces.contains(&w.handle))
+            .map(|w| w.name.as_str())
+    }
+}
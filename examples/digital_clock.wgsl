@@ -0,0 +1,54 @@
+// [SHADER]
+// name: Digital Clock
+// author: GlowBerry
+// license: MPL-2.0
+//
+// [PARAMS]
+// size: f32 = 0.5 | min: 0.2 | max: 0.9 | step: 0.05 | label: Size
+// glow: f32 = 0.6 | min: 0.0 | max: 1.5 | step: 0.05 | label: Glow
+// hue: f32 = 0.55 | min: 0.0 | max: 1.0 | step: 0.01 | label: Hue
+// [/PARAMS]
+
+// Default parameter values
+const size: f32 = 0.5;
+const glow: f32 = 0.6;
+const hue: f32 = 0.55;
+
+const TAU: f32 = 6.283185307;
+
+fn palette(t: f32) -> vec3<f32> {
+    return 0.5 + 0.5 * cos(TAU * (t + vec3<f32>(0.0, 0.33, 0.67)));
+}
+
+@fragment
+fn main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = pos.xy / iResolution;
+
+    // Fit the time text to `size` of the screen width, centered
+    let dims = vec2<f32>(textureDimensions(iClock));
+    let extent = dims * (iResolution.x * size / dims.x);
+    let origin = (iResolution - extent) * 0.5;
+    let text_uv = (pos.xy - origin) / extent;
+
+    // Slowly drifting backdrop
+    let drift = 0.5 + 0.5 * sin(iTime * 0.05 + uv.x * 2.0 + uv.y);
+    var color = mix(vec3<f32>(0.02, 0.02, 0.05), palette(hue + 0.5) * 0.15, drift * (1.0 - uv.y));
+
+    // Soft halo from samples in a ring around each pixel
+    let aspect = vec2<f32>(dims.y / dims.x, 1.0);
+    var halo = 0.0;
+    for (var i = 0; i < 8; i++) {
+        let angle = f32(i) * TAU / 8.0;
+        let offset = vec2<f32>(cos(angle), sin(angle)) * 0.04 * aspect;
+        halo += textureSample(iClock, iClockSampler, text_uv + offset).a;
+    }
+    halo /= 8.0;
+
+    let text = textureSample(iClock, iClockSampler, text_uv).a;
+    let tint = palette(hue);
+    let pulse = 0.85 + 0.15 * sin(iTime * 1.5);
+    color += tint * halo * glow * pulse;
+    color = mix(color, mix(vec3<f32>(1.0), tint, 0.2), text);
+
+    return vec4<f32>(color, 1.0);
+}
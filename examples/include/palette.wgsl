@@ -0,0 +1,9 @@
+// Shared palette helpers for GlowBerry shaders.
+//
+// Use from a wallpaper shader with:
+//   #include "palette.wgsl"
+
+// Cosine palette, see https://iquilezles.org/articles/palettes/
+fn cosine_palette(t: f32, a: vec3<f32>, b: vec3<f32>, c: vec3<f32>, d: vec3<f32>) -> vec3<f32> {
+    return a + b * cos(6.28318 * (c * t + d));
+}
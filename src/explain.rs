@@ -0,0 +1,100 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `glowberry explain`: report which config entry applies to each output.
+
+use cosmic_config::CosmicConfigEntry;
+use glowberry_config::{Config, ShaderContent, Source, state::State};
+
+/// Print the entry that applies to each output and why it was chosen.
+///
+/// With no outputs given, the outputs last reported as connected by the
+/// daemon are explained.
+pub fn run(outputs: Vec<String>) -> eyre::Result<()> {
+    let context = glowberry_config::context()?;
+    let config = Config::load(&context)?;
+
+    let outputs = if outputs.is_empty() {
+        let state = State::state()?;
+        State::get_entry(&state)
+            .unwrap_or_default()
+            .connected_outputs
+    } else {
+        outputs
+    };
+
+    if outputs.is_empty() {
+        eyre::bail!("no connected outputs are known; pass an output name such as `DP-1`");
+    }
+
+    for output in &outputs {
+        let (entry, reason) = config.explain_output(output);
+        println!("{output}");
+        println!("  entry:  {}", entry.output);
+        println!("  reason: {reason}");
+        println!("  source: {}", describe_source(&entry.source));
+        if let Some(dark_source) = &entry.dark_source {
+            println!("  dark:   {}", describe_source(dark_source));
+        }
+        for workspace in &entry.workspaces {
+            println!(
+                "  workspace {}: {}",
+                workspace.workspace,
+                describe_source(&workspace.source)
+            );
+        }
+        for rule in &entry.schedule {
+            println!("  {:?}: {}", rule.when, describe_source(&rule.source));
+        }
+    }
+
+    Ok(())
+}
+
+fn describe_source(source: &Source) -> String {
+    match source {
+        Source::Path(path) if path.is_dir() => format!("slideshow of {}", path.display()),
+        Source::Path(path) => format!("image {}", path.display()),
+        Source::Folders(folders) => {
+            let folders: Vec<_> = folders
+                .iter()
+                .map(|folder| folder.display().to_string())
+                .collect();
+            format!("slideshow of {}", folders.join(", "))
+        }
+        Source::Color(_) => String::from("color"),
+        Source::Procedural(procedural) => {
+            format!("{:?} pattern, seed {}", procedural.pattern, procedural.seed)
+        }
+        Source::Shader(shader) => match (&shader.shader, &shader.source_path) {
+            (ShaderContent::Path(path), _) | (ShaderContent::Code(_), Some(path)) => {
+                format!("shader {}", path.display())
+            }
+            (ShaderContent::Code(_), None) => String::from("inline shader"),
+        },
+        Source::Video(video) => format!("video {}", video.path.display()),
+        Source::Online(online) => {
+            format!("{:?} images matching \"{}\"", online.provider, online.query)
+        }
+        Source::Composite(composite) => format!(
+            "image {} under {} ({:?} blend)",
+            composite.image.display(),
+            describe_source(&Source::Shader(composite.overlay.clone())),
+            composite.blend
+        ),
+        Source::Parallax(parallax) => format!(
+            "image {} with depth map {}",
+            parallax.image.display(),
+            parallax.depth.display()
+        ),
+        Source::DayNight(day_night) => format!(
+            "day image {}, night image {}",
+            day_night.day.display(),
+            day_night.night.display()
+        ),
+        Source::Playlist(items) if items.is_empty() => String::from("empty playlist"),
+        Source::Playlist(_) => {
+            let items: Vec<_> = source.items().into_iter().map(describe_source).collect();
+            format!("playlist of {}", items.join(", "))
+        }
+    }
+}
@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `glowberry import`: use a Steam Wallpaper Engine project as a wallpaper.
+
+use glowberry_config::{Config, Entry};
+use glowberry_lib::wallpaper_engine;
+use std::path::Path;
+
+/// Convert the project in `project` and set it as the wallpaper of `output`,
+/// keeping the entry's other settings. A `partial` import leaves out what
+/// can't be converted.
+pub fn run(project: &Path, output: String, partial: bool) -> eyre::Result<()> {
+    // Paths saved in the config must not depend on the working directory
+    let project = project.canonicalize()?;
+    let name = project
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let out_dir = wallpaper_engine::data_dir().join(name);
+    let import = match wallpaper_engine::import(&project, &out_dir, partial) {
+        Err(why @ wallpaper_engine::ImportError::Unconvertible(_)) => {
+            eyre::bail!("{why}; pass --partial to import the scene without them")
+        }
+        import => import?,
+    };
+    for part in &import.skipped {
+        println!("skipped {part}");
+    }
+
+    let context = glowberry_config::context()?;
+    let mut config = Config::load(&context)?;
+    let mut entry = if output == glowberry_config::DEFAULT_BACKGROUND {
+        config.default_background.clone()
+    } else {
+        config
+            .entry(&output)
+            .cloned()
+            .unwrap_or_else(|| Entry::new(output.clone(), import.source.clone()))
+    };
+    entry.source = import.source;
+    config.set_entry(&context, entry)?;
+
+    println!(
+        "Imported {} for {output}",
+        import.title.as_deref().unwrap_or("the project")
+    );
+    Ok(())
+}
@@ -1,14 +1,67 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use clap::{CommandFactory, Parser};
+mod explain;
+mod import;
+mod queue;
+mod soak;
+mod stress;
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use glowberry_lib::engine::{BackgroundEngine, EngineConfig};
+use glowberry_lib::timelapse;
+use std::path::PathBuf;
 use tracing_subscriber::prelude::*;
 
 /// GlowBerry - Enhanced background service with live shader support
 #[derive(Parser, Debug)]
 #[command(name = "glowberry")]
 #[command(author, about, long_about = None)]
-struct Args {}
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show which config entry applies to each output, and why
+    Explain {
+        /// Output names such as `DP-1` (defaults to all connected outputs)
+        outputs: Vec<String>,
+    },
+    /// Show or reorder the upcoming images of slideshows
+    Queue {
+        #[command(subcommand)]
+        action: queue::Action,
+    },
+    /// Use a Steam Wallpaper Engine scene or video project as a wallpaper
+    Import {
+        /// The project folder, containing `project.json`
+        project: PathBuf,
+        /// Output name such as `DP-1`, or `all`
+        #[arg(short, long, default_value = "all")]
+        output: String,
+        /// Leave out the parts of a scene that can't be converted, rather
+        /// than refusing to import it
+        #[arg(long)]
+        partial: bool,
+    },
+    /// Assemble an output's time-lapse snapshots into a video
+    Timelapse {
+        /// Output name such as `DP-1`
+        output: String,
+        /// Where to write the video: `.webm`, `.mp4`, or `.mkv`, or `.gif`
+        /// for an animated GIF
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Playback speed in snapshots per second
+        #[arg(long, default_value_t = 12)]
+        fps: u16,
+    },
+    /// Fuzz output hotplug, scales, and config inside a nested sway session
+    Stress(stress::Options),
+    /// Rotate a slideshow thousands of times and fail if memory keeps growing
+    Soak(soak::Options),
+}
 
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
@@ -22,9 +75,25 @@ fn main() -> color_eyre::Result<()> {
     init_logger();
 
     let version: &'static str = glowberry_config::version_string().leak();
-    let _args = Args::command().version(version).get_matches();
+    let matches = Args::command().version(version).get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
-    BackgroundEngine::run(EngineConfig::default())?;
+    match args.command {
+        Some(Command::Explain { outputs }) => explain::run(outputs)?,
+        Some(Command::Queue { action }) => queue::run(action)?,
+        Some(Command::Import {
+            project,
+            output,
+            partial,
+        }) => import::run(&project, output, partial)?,
+        Some(Command::Timelapse { output, out, fps }) => {
+            let frames = timelapse::assemble(&timelapse::output_dir(&output)?, &out, fps)?;
+            println!("Wrote {frames} frames to {}", out.display());
+        }
+        Some(Command::Stress(options)) => stress::run(&options)?,
+        Some(Command::Soak(options)) => soak::run(&options)?,
+        None => BackgroundEngine::run(EngineConfig::default())?,
+    }
 
     Ok(())
 }
@@ -56,7 +125,8 @@ fn init_logger() {
 
 #[cfg(test)]
 mod tests {
-    use super::{BackgroundEngine, EngineConfig};
+    use super::{Args, BackgroundEngine, Command, EngineConfig};
+    use clap::Parser;
 
     #[test]
     fn main_calls_library() {
@@ -64,4 +134,76 @@ mod tests {
         let _run: fn(EngineConfig) -> eyre::Result<()> = BackgroundEngine::run;
         let _ = EngineConfig::default();
     }
+
+    #[test]
+    fn parses_explain_subcommand() {
+        let args = Args::try_parse_from(["glowberry", "explain", "DP-1", "eDP-1"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Explain { outputs }) if outputs == ["DP-1", "eDP-1"]
+        ));
+
+        let args = Args::try_parse_from(["glowberry"]).unwrap();
+        assert!(args.command.is_none());
+    }
+
+    #[test]
+    fn parses_queue_subcommand() {
+        let args = Args::try_parse_from(["glowberry", "queue", "move", "DP-1", "3", "0"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Queue {
+                action: super::queue::Action::Move { output, from: 3, to: 0 }
+            }) if output == "DP-1"
+        ));
+
+        assert!(Args::try_parse_from(["glowberry", "queue", "next", "DP-1"]).is_err());
+    }
+
+    #[test]
+    fn parses_import_subcommand() {
+        let args = Args::try_parse_from(["glowberry", "import", "/steam/431960/123"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Import { project, output, partial: false })
+                if project == std::path::Path::new("/steam/431960/123") && output == "all"
+        ));
+        let args = Args::try_parse_from(["glowberry", "import", "--partial", "/steam/431960/123"])
+            .unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Import { partial: true, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_timelapse_subcommand() {
+        let args =
+            Args::try_parse_from(["glowberry", "timelapse", "DP-1", "-o", "day.webm"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Timelapse { output, out, fps: 12 })
+                if output == "DP-1" && out == std::path::Path::new("day.webm")
+        ));
+    }
+
+    #[test]
+    fn parses_stress_subcommand() {
+        let args = Args::try_parse_from(["glowberry", "stress", "--iterations", "10"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Stress(options)) if options.iterations == 10 && options.warmup == 50
+        ));
+    }
+
+    #[test]
+    fn soak_requires_images() {
+        assert!(Args::try_parse_from(["glowberry", "soak"]).is_err());
+
+        let args = Args::try_parse_from(["glowberry", "soak", "/tmp/walls"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Soak(options)) if options.rotations == 5000 && options.max_rss_growth == 32
+        ));
+    }
 }
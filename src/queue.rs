@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `glowberry queue`: show and reorder the upcoming images of slideshows.
+
+use cosmic_config::CosmicConfigEntry;
+use glowberry_config::{Config, QueueEdit, state::State};
+use std::path::PathBuf;
+
+/// Actions of `glowberry queue`.
+#[derive(clap::Subcommand, Debug)]
+pub enum Action {
+    /// List the upcoming images of each output's slideshow
+    List {
+        /// Output names such as `DP-1` (defaults to all connected outputs)
+        outputs: Vec<String>,
+    },
+    /// Move an upcoming image to another position, 0 being the next one
+    Move {
+        /// Output name such as `DP-1`
+        output: String,
+        from: usize,
+        to: usize,
+    },
+    /// Show an image next
+    Next {
+        /// Output name such as `DP-1`
+        output: String,
+        path: PathBuf,
+    },
+}
+
+/// Print or edit slideshow queues as last published by the daemon.
+pub fn run(action: Action) -> eyre::Result<()> {
+    let context = glowberry_config::context()?;
+    let config = Config::load(&context)?;
+    let state = State::get_entry(&State::state()?).unwrap_or_default();
+
+    match action {
+        Action::List { outputs } => {
+            let outputs = if outputs.is_empty() {
+                state.connected_outputs.clone()
+            } else {
+                outputs
+            };
+            if outputs.is_empty() {
+                eyre::bail!("no connected outputs are known; pass an output name such as `DP-1`");
+            }
+
+            for output in &outputs {
+                let entry = &config.explain_output(output).0.output;
+                println!("{output} ({entry})");
+                match upcoming(&state, entry) {
+                    Some([]) => println!("  no upcoming images"),
+                    Some(images) => {
+                        for (position, path) in images.iter().enumerate() {
+                            println!("  {position}: {}", path.display());
+                        }
+                    }
+                    None => println!("  not a slideshow"),
+                }
+            }
+        }
+
+        Action::Move { output, from, to } => {
+            let entry = config.explain_output(&output).0.output.clone();
+            let Some(images) = upcoming(&state, &entry) else {
+                eyre::bail!("{output} is not showing a slideshow");
+            };
+            if from >= images.len() || to >= images.len() {
+                eyre::bail!(
+                    "{output} has {} upcoming images; positions start at 0",
+                    images.len()
+                );
+            }
+            context.request_queue_edit(QueueEdit::Move {
+                output: entry,
+                from,
+                to,
+            })?;
+        }
+
+        Action::Next { output, path } => {
+            let entry = config.explain_output(&output).0.output.clone();
+            if upcoming(&state, &entry).is_none() {
+                eyre::bail!("{output} is not showing a slideshow");
+            }
+            // The daemon queues canonical paths
+            let path = path.canonicalize()?;
+            if !path.is_file() {
+                eyre::bail!("{} is not an image file", path.display());
+            }
+            context.request_queue_edit(QueueEdit::PlayNext {
+                output: entry,
+                path,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Upcoming images of the slideshow for an entry output.
+fn upcoming<'a>(state: &'a State, entry: &str) -> Option<&'a [PathBuf]> {
+    state
+        .slideshow_queues
+        .iter()
+        .find(|(output, _)| output == entry)
+        .map(|(_, images)| images.as_slice())
+}
@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `glowberry soak`: slideshow memory leak check.
+
+use glowberry_lib::soak::{self, SoakOptions};
+use std::path::PathBuf;
+use walkdir::WalkDir;
+
+/// Options for `glowberry soak`.
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Image files or directories to rotate through
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Number of slideshow rotations
+    #[arg(long, default_value_t = 5000)]
+    pub rotations: u32,
+    /// Rotations to run before the RSS baseline is taken
+    #[arg(long, default_value_t = 100)]
+    pub warmup: u32,
+    /// Allowed RSS growth over the baseline, in MiB
+    #[arg(long, default_value_t = 32)]
+    pub max_rss_growth: u64,
+}
+
+/// Run the soak test and print the memory report.
+pub fn run(options: &Options) -> eyre::Result<()> {
+    let images: Vec<PathBuf> = options
+        .paths
+        .iter()
+        .flat_map(|path| WalkDir::new(path).follow_links(true).sort_by_file_name())
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(walkdir::DirEntry::into_path)
+        .collect();
+
+    println!(
+        "Rotating through {} images {} times",
+        images.len(),
+        options.rotations
+    );
+
+    let report = soak::run(
+        &images,
+        &SoakOptions {
+            rotations: options.rotations,
+            warmup: options.warmup,
+            max_growth_kib: options.max_rss_growth * 1024,
+            ..SoakOptions::default()
+        },
+    )?;
+
+    println!(
+        "{} rotations passed ({} decode failures): baseline {} MiB, peak {} MiB, final {} MiB",
+        report.rotations,
+        report.decode_failures,
+        report.baseline_kib / 1024,
+        report.peak_kib / 1024,
+        report.final_kib / 1024
+    );
+
+    Ok(())
+}
@@ -0,0 +1,303 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `glowberry stress`: hotplug, scale, and config fuzzing of the daemon.
+//!
+//! Meant to run against a throwaway nested wlroots compositor, for example
+//! `WLR_BACKENDS=headless WLR_LIBINPUT_NO_DEVICES=1 sway -c /dev/null`, with
+//! `WAYLAND_DISPLAY` and `SWAYSOCK` pointing at it. The daemon is spawned as a
+//! child process while outputs are created, unplugged, rescaled, and resized
+//! through `swaymsg` and config keys are flipped. The run fails if the daemon
+//! exits, reports a protocol error, or its RSS grows past the allowed bound.
+//!
+//! The run and the daemon use a temporary `XDG_CONFIG_HOME` and
+//! `XDG_STATE_HOME`, so the config keys it flips never reach the user's
+//! settings, even when the run is killed.
+
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use glowberry_lib::soak::parse_vm_rss;
+use rand::RngExt;
+use rand::seq::IndexedRandom;
+
+/// Scales applied to outputs, including fractional ones.
+const SCALES: &[f32] = &[1.0, 1.25, 1.5, 1.75, 2.0, 3.0];
+
+/// Modes applied to outputs.
+const MODES: &[(u32, u32)] = &[(640, 480), (1280, 720), (1920, 1080), (2560, 1440)];
+
+/// Options for `glowberry stress`.
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Number of random actions to perform
+    #[arg(long, default_value_t = 500)]
+    pub iterations: u32,
+    /// Milliseconds to wait after each action
+    #[arg(long, default_value_t = 100)]
+    pub delay_ms: u64,
+    /// Actions to run before the RSS baseline is taken
+    #[arg(long, default_value_t = 50)]
+    pub warmup: u32,
+    /// Allowed RSS growth over the baseline, in MiB
+    #[arg(long, default_value_t = 64)]
+    pub max_rss_growth: u64,
+}
+
+/// A single fuzzing step.
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    AddOutput,
+    RemoveOutput(String),
+    SetScale(String, f32),
+    SetMode(String, (u32, u32)),
+    ToggleSameOnAll,
+    SetFallbackResolution((u32, u32)),
+}
+
+/// Temporary config and state directories, removed when dropped.
+struct Sandbox {
+    dir: PathBuf,
+}
+
+impl Sandbox {
+    /// Point this process, and the daemon it spawns, at fresh config and
+    /// state directories.
+    fn enter() -> eyre::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("glowberry-stress-{}", std::process::id()));
+        let (config, state) = (dir.join("config"), dir.join("state"));
+        std::fs::create_dir_all(&config)?;
+        std::fs::create_dir_all(&state)?;
+        // SAFETY: set before any thread of the run is spawned
+        unsafe {
+            std::env::set_var("XDG_CONFIG_HOME", config);
+            std::env::set_var("XDG_STATE_HOME", state);
+        }
+        Ok(Self { dir })
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Spawned daemon, killed when dropped.
+struct Daemon {
+    child: Child,
+    protocol_error: Arc<AtomicBool>,
+}
+
+impl Daemon {
+    fn spawn() -> eyre::Result<Self> {
+        let mut child = Command::new(std::env::current_exe()?)
+            .env("RUST_LOG", "warn")
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Forward the daemon's log while watching for protocol errors
+        let protocol_error = Arc::new(AtomicBool::new(false));
+        if let Some(stderr) = child.stderr.take() {
+            let flag = protocol_error.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    if line.to_ascii_lowercase().contains("protocol error") {
+                        flag.store(true, Ordering::Relaxed);
+                    }
+                    eprintln!("{line}");
+                }
+            });
+        }
+
+        Ok(Self {
+            child,
+            protocol_error,
+        })
+    }
+
+    /// Fail if the daemon has exited or hit a protocol error.
+    fn check(&mut self) -> eyre::Result<()> {
+        if let Some(status) = self.child.try_wait()? {
+            eyre::bail!("daemon exited during stress test: {status}");
+        }
+        if self.protocol_error.load(Ordering::Relaxed) {
+            eyre::bail!("daemon reported a Wayland protocol error");
+        }
+        Ok(())
+    }
+
+    /// Resident set size in KiB.
+    fn rss_kib(&self) -> eyre::Result<u64> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", self.child.id()))?;
+        parse_vm_rss(&status).ok_or_else(|| eyre::eyre!("VmRSS missing from /proc status"))
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Run the stress test.
+pub fn run(options: &Options) -> eyre::Result<()> {
+    if std::env::var_os("SWAYSOCK").is_none() {
+        eyre::bail!(
+            "SWAYSOCK is not set; run inside a nested sway, e.g. \
+             `WLR_BACKENDS=headless WLR_LIBINPUT_NO_DEVICES=1 sway -c /dev/null`"
+        );
+    }
+
+    let _sandbox = Sandbox::enter()?;
+    let context = glowberry_config::context()?;
+
+    // Start with at least one output so the daemon has something to draw
+    if outputs()?.is_empty() {
+        swaymsg(&["create_output"])?;
+    }
+
+    let mut daemon = Daemon::spawn()?;
+    std::thread::sleep(Duration::from_secs(1));
+
+    let delay = Duration::from_millis(options.delay_ms);
+    let max_growth = options.max_rss_growth * 1024;
+    let mut rng = rand::rng();
+    let mut baseline = None;
+    let mut peak = 0;
+
+    for iteration in 0..options.iterations {
+        let action = random_action(&mut rng, &outputs()?);
+        tracing::debug!(iteration, ?action, "stress action");
+        apply(&context, &action)?;
+
+        std::thread::sleep(delay);
+        daemon.check()?;
+
+        let rss = daemon.rss_kib()?;
+        peak = peak.max(rss);
+        if iteration + 1 == options.warmup {
+            baseline = Some(rss);
+        }
+        if let Some(baseline) = baseline
+            && rss > baseline + max_growth
+        {
+            eyre::bail!(
+                "RSS grew from {} MiB to {} MiB after {} actions (allowed: +{} MiB)",
+                baseline / 1024,
+                rss / 1024,
+                iteration + 1,
+                options.max_rss_growth
+            );
+        }
+    }
+
+    let rss = daemon.rss_kib()?;
+    println!(
+        "{} actions passed: baseline {} MiB, peak {} MiB, final {} MiB",
+        options.iterations,
+        baseline.unwrap_or(rss) / 1024,
+        peak / 1024,
+        rss / 1024
+    );
+
+    Ok(())
+}
+
+fn random_action(rng: &mut impl rand::Rng, outputs: &[String]) -> Action {
+    let Some(output) = outputs.choose(rng).cloned() else {
+        return Action::AddOutput;
+    };
+
+    match rng.random_range(0..6) {
+        0 => Action::AddOutput,
+        // Never unplug the last output
+        1 if outputs.len() > 1 => Action::RemoveOutput(output),
+        1 | 2 => Action::SetScale(output, *SCALES.choose(rng).unwrap_or(&1.0)),
+        3 => Action::SetMode(output, *MODES.choose(rng).unwrap_or(&(1920, 1080))),
+        4 => Action::ToggleSameOnAll,
+        _ => Action::SetFallbackResolution(*MODES.choose(rng).unwrap_or(&(1920, 1080))),
+    }
+}
+
+fn apply(context: &glowberry_config::Context, action: &Action) -> eyre::Result<()> {
+    match action {
+        Action::AddOutput => swaymsg(&["create_output"]),
+        Action::RemoveOutput(output) => swaymsg(&["output", output, "unplug"]),
+        Action::SetScale(output, scale) => {
+            swaymsg(&["output", output, "scale", &scale.to_string()])
+        }
+        Action::SetMode(output, (width, height)) => {
+            swaymsg(&["output", output, "mode", &format!("{width}x{height}")])
+        }
+        Action::ToggleSameOnAll => Ok(context.set_same_on_all(!context.same_on_all())?),
+        Action::SetFallbackResolution(resolution) => {
+            Ok(context.set_fallback_resolution(*resolution)?)
+        }
+    }
+}
+
+fn swaymsg(args: &[&str]) -> eyre::Result<()> {
+    let status = Command::new("swaymsg")
+        .args(args)
+        .stdout(Stdio::null())
+        .status()?;
+    if !status.success() {
+        eyre::bail!("`swaymsg {}` failed: {status}", args.join(" "));
+    }
+    Ok(())
+}
+
+fn outputs() -> eyre::Result<Vec<String>> {
+    let output = Command::new("swaymsg")
+        .args(["-t", "get_outputs", "-r"])
+        .output()?;
+    if !output.status.success() {
+        eyre::bail!("`swaymsg -t get_outputs` failed: {}", output.status);
+    }
+    Ok(parse_output_names(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Names of the outputs in a `swaymsg -t get_outputs -r` response.
+fn parse_output_names(json: &str) -> Vec<String> {
+    serde_json::from_str::<serde_json::Value>(json)
+        .ok()
+        .as_ref()
+        .and_then(serde_json::Value::as_array)
+        .map(|outputs| {
+            outputs
+                .iter()
+                .filter_map(|o| o.get("name")?.as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sway_output_names() {
+        let json = r#"[{"name": "HEADLESS-1", "scale": 1.0}, {"name": "HEADLESS-2"}]"#;
+        assert_eq!(parse_output_names(json), ["HEADLESS-1", "HEADLESS-2"]);
+        assert!(parse_output_names("not json").is_empty());
+    }
+
+    #[test]
+    fn never_unplugs_the_last_output() {
+        let mut rng = rand::rng();
+        let outputs = ["HEADLESS-1".to_owned()];
+        for _ in 0..200 {
+            assert!(!matches!(
+                random_action(&mut rng, &outputs),
+                Action::RemoveOutput(_)
+            ));
+        }
+        assert_eq!(random_action(&mut rng, &[]), Action::AddOutput);
+    }
+}